@@ -0,0 +1,290 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! # Arena allocator for Rust SGX SDK
+//!
+//! An [`Arena`] hands out memory from chunks it gets from another allocator,
+//! [`System`] by default, by bumping a pointer, and frees it all at once when
+//! it's [`reset`] or dropped. It isn't shared between threads, so it takes no
+//! lock: request handlers allocate their scratch space from an arena, e.g.
+//! the buffers of parsing a request, and reset it at the end of each ecall:
+//!
+//! ```ignore
+//! let mut arena = Arena::with_capacity(64 * 1024);
+//! {
+//!     let mut fields = Vec::new_in(&arena);
+//!     parse(request, &mut fields)?;
+//!     respond(&fields)?;
+//! }
+//! arena.reset();
+//! ```
+//!
+//! Freeing a block only gives its memory back if it's the last one allocated,
+//! and growing one extends it in place in the same case; other blocks keep
+//! their memory until the reset.
+//!
+//! [`reset`]: Arena::reset
+
+use crate::System;
+use core::alloc::{AllocError, Allocator, Layout};
+use core::cell::Cell;
+use core::cmp;
+use core::mem;
+use core::ptr::{self, NonNull};
+
+/// The size of the first chunk of an arena, unless given.
+pub const DEFAULT_CHUNK_SIZE: usize = 4096;
+
+/// The size chunks stop doubling at; larger blocks still get a chunk of
+/// their own.
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+const CHUNK_ALIGN: usize = 16;
+
+/// The header at the start of each chunk.
+struct Chunk {
+    prev: *mut Chunk,
+    size: usize,
+}
+
+const HEADER_SIZE: usize = (mem::size_of::<Chunk>() + CHUNK_ALIGN - 1) & !(CHUNK_ALIGN - 1);
+
+/// A bump allocator freeing all its blocks at once.
+///
+/// Memory is allocated with `&Arena`, which implements [`Allocator`], so the
+/// borrow checker makes sure no block outlives a [`reset`](Arena::reset).
+pub struct Arena<A: Allocator = System> {
+    inner: A,
+    chunk: Cell<*mut Chunk>,
+    ptr: Cell<*mut u8>,
+    end: Cell<*mut u8>,
+    next_size: Cell<usize>,
+    allocated: Cell<usize>,
+}
+
+unsafe impl<A: Allocator + Send> Send for Arena<A> {}
+
+impl Arena<System> {
+    pub const fn new() -> Arena<System> {
+        Arena::with_capacity_in(DEFAULT_CHUNK_SIZE, System)
+    }
+
+    /// Creates an arena whose first chunk holds `capacity` bytes. Nothing is
+    /// allocated until the first block.
+    pub const fn with_capacity(capacity: usize) -> Arena<System> {
+        Arena::with_capacity_in(capacity, System)
+    }
+}
+
+impl Default for Arena<System> {
+    fn default() -> Self {
+        Arena::new()
+    }
+}
+
+impl<A: Allocator> Arena<A> {
+    pub const fn new_in(inner: A) -> Arena<A> {
+        Arena::with_capacity_in(DEFAULT_CHUNK_SIZE, inner)
+    }
+
+    pub const fn with_capacity_in(capacity: usize, inner: A) -> Arena<A> {
+        Arena {
+            inner,
+            chunk: Cell::new(ptr::null_mut()),
+            ptr: Cell::new(ptr::null_mut()),
+            end: Cell::new(ptr::null_mut()),
+            next_size: Cell::new(capacity),
+            allocated: Cell::new(0),
+        }
+    }
+
+    /// Returns the bytes of the chunks the arena holds.
+    pub fn allocated_bytes(&self) -> usize {
+        self.allocated.get()
+    }
+
+    /// Returns the bytes left in the current chunk.
+    pub fn remaining(&self) -> usize {
+        self.end.get() as usize - self.ptr.get() as usize
+    }
+
+    /// Frees all the blocks of the arena.
+    ///
+    /// The last chunk, the largest unless a large block got one of its own,
+    /// is kept for the next blocks; the others go back to the inner
+    /// allocator.
+    pub fn reset(&mut self) {
+        let chunk = self.chunk.get();
+        if chunk.is_null() {
+            return;
+        }
+        // SAFETY: the chunks were allocated by `grow_chunk`, and `&mut self`
+        // means no block is borrowed any more.
+        unsafe {
+            self.free_chunks((*chunk).prev);
+            (*chunk).prev = ptr::null_mut();
+            self.allocated.set((*chunk).size);
+            self.ptr.set((chunk as *mut u8).add(HEADER_SIZE));
+        }
+    }
+
+    /// Allocates `layout` in the current chunk, if it fits.
+    #[inline]
+    fn bump(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let ptr = self.ptr.get();
+        let pad = (ptr as usize).wrapping_neg() & (layout.align() - 1);
+        let needed = pad.checked_add(layout.size())?;
+        if needed > self.remaining() {
+            return None;
+        }
+        // SAFETY: the block is within the current chunk.
+        unsafe {
+            let start = ptr.add(pad);
+            self.ptr.set(start.add(layout.size()));
+            Some(NonNull::new_unchecked(start))
+        }
+    }
+
+    /// Allocates a new chunk large enough for `layout`, and `layout` in it.
+    #[cold]
+    fn grow_chunk(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        let extra = layout.align().saturating_sub(CHUNK_ALIGN);
+        let fits = HEADER_SIZE
+            .checked_add(layout.size())
+            .and_then(|size| size.checked_add(extra))
+            .ok_or(AllocError)?;
+        let size = cmp::max(self.next_size.get(), fits);
+        let chunk_layout = Layout::from_size_align(size, CHUNK_ALIGN).map_err(|_| AllocError)?;
+        let chunk = self.inner.allocate(chunk_layout)?.as_mut_ptr() as *mut Chunk;
+
+        // SAFETY: the chunk holds at least the header.
+        unsafe {
+            chunk.write(Chunk {
+                prev: self.chunk.get(),
+                size,
+            });
+            self.chunk.set(chunk);
+            self.ptr.set((chunk as *mut u8).add(HEADER_SIZE));
+            self.end.set((chunk as *mut u8).add(size));
+        }
+        self.allocated.set(self.allocated.get() + size);
+        self.next_size
+            .set(cmp::min(size.saturating_mul(2), MAX_CHUNK_SIZE).max(self.next_size.get()));
+        self.bump(layout).ok_or(AllocError)
+    }
+
+    /// Returns whether the block at `ptr` of `size` bytes is the last one.
+    #[inline]
+    fn is_last(&self, ptr: NonNull<u8>, size: usize) -> bool {
+        ptr.as_ptr().wrapping_add(size) == self.ptr.get()
+    }
+
+    unsafe fn free_chunks(&self, mut chunk: *mut Chunk) {
+        while !chunk.is_null() {
+            let Chunk { prev, size } = chunk.read();
+            let layout = Layout::from_size_align_unchecked(size, CHUNK_ALIGN);
+            self.inner
+                .deallocate(NonNull::new_unchecked(chunk as *mut u8), layout);
+            chunk = prev;
+        }
+    }
+}
+
+unsafe impl<A: Allocator> Allocator for Arena<A> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = match layout.size() {
+            // SAFETY: the alignment isn't zero.
+            0 => unsafe { NonNull::new_unchecked(layout.align() as *mut u8) },
+            _ => match self.bump(layout) {
+                Some(ptr) => ptr,
+                None => self.grow_chunk(layout)?,
+            },
+        };
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 && self.is_last(ptr, layout.size()) {
+            self.ptr.set(ptr.as_ptr());
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(
+            new_layout.size() >= old_layout.size(),
+            "`new_layout.size()` must be greater than or equal to `old_layout.size()`"
+        );
+
+        let old_size = old_layout.size();
+        let new_size = new_layout.size();
+        if old_size != 0
+            && self.is_last(ptr, old_size)
+            && ptr.as_ptr() as usize & (new_layout.align() - 1) == 0
+            && new_size - old_size <= self.remaining()
+        {
+            self.ptr.set(ptr.as_ptr().add(new_size));
+            return Ok(NonNull::slice_from_raw_parts(ptr, new_size));
+        }
+
+        let new_ptr = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_mut_ptr(), old_size);
+        self.deallocate(ptr, old_layout);
+        Ok(new_ptr)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(
+            new_layout.size() <= old_layout.size(),
+            "`new_layout.size()` must be smaller than or equal to `old_layout.size()`"
+        );
+
+        let new_size = new_layout.size();
+        if new_size == 0 {
+            self.deallocate(ptr, old_layout);
+            return self.allocate(new_layout);
+        }
+        if ptr.as_ptr() as usize & (new_layout.align() - 1) != 0 {
+            let new_ptr = self.allocate(new_layout)?;
+            ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_mut_ptr(), new_size);
+            self.deallocate(ptr, old_layout);
+            return Ok(new_ptr);
+        }
+        if self.is_last(ptr, old_layout.size()) {
+            self.ptr.set(ptr.as_ptr().add(new_size));
+        }
+        Ok(NonNull::slice_from_raw_parts(ptr, new_size))
+    }
+}
+
+impl<A: Allocator> Drop for Arena<A> {
+    fn drop(&mut self) {
+        // SAFETY: the chunks were allocated by `grow_chunk`.
+        unsafe { self.free_chunks(self.chunk.get()) }
+    }
+}
@@ -0,0 +1,679 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! # EDMM (SGX2) dynamic EPC memory for Rust SGX SDK
+//!
+//! On SGX2 platforms the enclave can add EPC pages after it was built: the
+//! pages are requested from the driver (`EAUG`) and accepted by the enclave
+//! (`EACCEPT`). The SDK does so for the reserved memory area, whose pages
+//! past `ReservedMemMinSize` are added when they're allocated and removed
+//! when they're freed.
+//!
+//! A [`DynamicHeap`] is a range of the reserved memory area which grows a
+//! step of pages at a time, up to a ceiling, so that the enclave no longer
+//! needs its worst case of EPC from the start:
+//!
+//! ```ignore
+//! let mut heap = DynamicHeap::new(HeapConfig { initial: 16, step: 64, ceiling: 64 * 1024 })?;
+//! heap.grow_to(len)?;      // allocates the pages covering `len` bytes
+//! heap.trim_to(0);         // hands them back once the work is done
+//! ```
+//!
+//! A [`DynamicAlloc`] connects one to the allocator: it serves allocations
+//! from the enclave heap, of `HeapMaxSize`, and once that's exhausted from a
+//! dynamic heap, which grows as it fills. The enclave can then be built with
+//! the heap of its usual load, and a `ReservedMemMaxSize` of its worst case:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static GLOBAL: DynamicAlloc = DynamicAlloc::new(HeapConfig { initial: 0, step: 64, ceiling: 256 * 1024 });
+//! ```
+//!
+//! On SGX1 the reserved memory area is committed when the enclave is
+//! loaded, and the dynamic heap only spares the enclave heap.
+//!
+//! With the `emm` feature, [`EdmmAlloc`] also reserves ranges of the enclave
+//! address space and commits, uncommits and protects pages in them, through
+//! the enclave memory manager of the Intel SDK 2.18 and later.
+
+use crate::rsrvmem::{RsrvMemAlloc, RsrvMemAllocErr};
+use crate::System;
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::hint;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[cfg(feature = "emm")]
+use crate::rsrvmem::ProtectAttr;
+
+/// The size of an EPC page.
+pub const PAGE_SIZE: usize = 0x1000;
+
+/// Whether the platform, the driver and the enclave support EDMM.
+#[inline]
+pub fn is_supported() -> bool {
+    sgx_trts::enclave::rsgx_is_supported_EDMM()
+}
+
+#[cfg(feature = "emm")]
+pub struct EdmmAlloc;
+
+#[cfg(feature = "emm")]
+impl EdmmAlloc {
+    /// Reserve `count` pages of address space, without committing any.
+    ///
+    /// With `on_demand`, a page is committed when it's first accessed;
+    /// otherwise accessing a page before [`commit`](EdmmAlloc::commit) faults.
+    #[inline]
+    pub unsafe fn reserve(&self, count: usize, on_demand: bool) -> Result<NonNull<u8>, EdmmErr> {
+        let flags = if on_demand {
+            platform::SGX_EMA_COMMIT_ON_DEMAND
+        } else {
+            platform::SGX_EMA_RESERVE
+        };
+        platform::alloc(count, flags)
+    }
+
+    /// Allocate `count` pages, committed at once.
+    #[inline]
+    pub unsafe fn alloc(&self, count: usize) -> Result<NonNull<u8>, EdmmErr> {
+        platform::alloc(count, platform::SGX_EMA_COMMIT_NOW)
+    }
+
+    /// Request and accept the `count` pages from `addr`, which must be in a
+    /// reserved range. The pages are zeroed and readable and writable.
+    #[inline]
+    pub unsafe fn commit(&self, addr: NonNull<u8>, count: usize) -> Result<(), EdmmErr> {
+        platform::commit(addr.as_ptr(), count)
+    }
+
+    /// Remove the `count` committed pages from `addr`, returning their EPC to
+    /// the platform. The range stays reserved.
+    #[inline]
+    pub unsafe fn uncommit(&self, addr: NonNull<u8>, count: usize) -> Result<(), EdmmErr> {
+        platform::uncommit(addr.as_ptr(), count)
+    }
+
+    /// Release the `count` pages from `addr`, committed or not.
+    #[inline]
+    pub unsafe fn dealloc(&self, addr: NonNull<u8>, count: usize) -> Result<(), EdmmErr> {
+        platform::dealloc(addr.as_ptr(), count)
+    }
+
+    /// Modify the access permissions of the `count` committed pages from
+    /// `addr`.
+    #[inline]
+    pub unsafe fn protect(
+        &self,
+        addr: NonNull<u8>,
+        count: usize,
+        prot: ProtectAttr,
+    ) -> Result<(), EdmmErr> {
+        platform::protect(addr.as_ptr(), count, prot)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EdmmErr {
+    /// The platform doesn't support EDMM.
+    Unsupported,
+    /// The sizes of a heap are inconsistent.
+    InvalidConfig,
+    /// Growing would pass the ceiling of the heap.
+    Ceiling,
+    /// The reserved memory area has no free pages at the end of the heap.
+    Exhausted,
+    /// The memory manager failed, with this errno.
+    Errno(i32),
+}
+
+impl fmt::Display for EdmmErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            EdmmErr::Unsupported => f.write_str("EDMM not supported"),
+            EdmmErr::InvalidConfig => f.write_str("invalid heap configuration"),
+            EdmmErr::Ceiling => f.write_str("heap ceiling reached"),
+            EdmmErr::Exhausted => f.write_str("reserved memory exhausted"),
+            EdmmErr::Errno(errno) => write!(f, "EDMM operation failed: errno {}", errno),
+        }
+    }
+}
+
+impl From<RsrvMemAllocErr> for EdmmErr {
+    fn from(_: RsrvMemAllocErr) -> EdmmErr {
+        EdmmErr::Exhausted
+    }
+}
+
+/// The sizes of a [`DynamicHeap`], in pages.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HeapConfig {
+    /// The pages allocated from the start; the heap always keeps at least
+    /// one.
+    pub initial: usize,
+    /// The least number of pages allocated at a time, to amortize the cost
+    /// of the `EAUG` ocall.
+    pub step: usize,
+    /// The most pages the heap ever has.
+    pub ceiling: usize,
+}
+
+impl Default for HeapConfig {
+    fn default() -> HeapConfig {
+        HeapConfig {
+            initial: 0,
+            step: 16,
+            ceiling: 16 * 1024,
+        }
+    }
+}
+
+/// A range of the reserved memory area whose pages are allocated on demand,
+/// up to a ceiling.
+///
+/// Bytes `0..committed()` from [`base`](DynamicHeap::base) are allocated,
+/// readable and writable. The heap grows at its end, so it stops growing
+/// short of its ceiling if something else of the enclave allocated the
+/// reserved memory there.
+pub struct DynamicHeap {
+    base: NonNull<u8>,
+    config: HeapConfig,
+    committed: usize,
+}
+
+impl DynamicHeap {
+    /// Allocate the initial pages of a heap of `config.ceiling` pages.
+    pub fn new(config: HeapConfig) -> Result<DynamicHeap, EdmmErr> {
+        if config.ceiling == 0 || config.initial > config.ceiling {
+            return Err(EdmmErr::InvalidConfig);
+        }
+        let committed = config.initial.max(1);
+        let base = unsafe { RsrvMemAlloc.alloc(page_count(committed)?)? };
+        Ok(DynamicHeap {
+            base,
+            config,
+            committed,
+        })
+    }
+
+    #[inline]
+    pub fn base(&self) -> NonNull<u8> {
+        self.base
+    }
+
+    /// Bytes allocated from the base.
+    #[inline]
+    pub fn committed(&self) -> usize {
+        self.committed * PAGE_SIZE
+    }
+
+    /// Bytes from the base which the heap never grows past.
+    #[inline]
+    pub fn ceiling(&self) -> usize {
+        self.config.ceiling * PAGE_SIZE
+    }
+
+    /// Allocate the pages covering the first `len` bytes, at least a step of
+    /// pages at a time.
+    ///
+    /// Fails with [`EdmmErr::Ceiling`] if `len` is past the ceiling, and
+    /// with [`EdmmErr::Exhausted`] if the pages after the heap aren't free,
+    /// leaving the heap as it was.
+    pub fn grow_to(&mut self, len: usize) -> Result<(), EdmmErr> {
+        let pages = pages(len).ok_or(EdmmErr::Ceiling)?;
+        if pages <= self.committed {
+            return Ok(());
+        }
+        if pages > self.config.ceiling {
+            return Err(EdmmErr::Ceiling);
+        }
+        let stepped = self.committed.saturating_add(self.config.step);
+        let pages = pages.max(stepped).min(self.config.ceiling);
+        let count = page_count(pages - self.committed)?;
+        unsafe {
+            let end = self.base.as_ptr().add(self.committed * PAGE_SIZE);
+            let added = RsrvMemAlloc.alloc_with_addr(NonNull::new_unchecked(end), count)?;
+            if added.as_ptr() != end {
+                let _ = RsrvMemAlloc.dealloc(added, count);
+                return Err(EdmmErr::Exhausted);
+            }
+        }
+        self.committed = pages;
+        Ok(())
+    }
+
+    /// Free the pages past the first `len` bytes, returning their EPC to
+    /// the platform. The first page is kept.
+    pub fn trim_to(&mut self, len: usize) -> Result<(), EdmmErr> {
+        let pages = match pages(len) {
+            Some(pages) if pages < self.committed => pages.max(1),
+            _ => return Ok(()),
+        };
+        if pages < self.committed {
+            unsafe {
+                let addr = NonNull::new_unchecked(self.base.as_ptr().add(pages * PAGE_SIZE));
+                RsrvMemAlloc.dealloc(addr, page_count(self.committed - pages)?)?;
+            }
+            self.committed = pages;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DynamicHeap {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = RsrvMemAlloc.dealloc(self.base, self.committed as u32);
+        }
+    }
+}
+
+/// The pages covering `len` bytes.
+#[inline]
+fn pages(len: usize) -> Option<usize> {
+    Some(len.checked_add(PAGE_SIZE - 1)? / PAGE_SIZE)
+}
+
+#[inline]
+fn page_count(pages: usize) -> Result<u32, EdmmErr> {
+    u32::try_from(pages).map_err(|_| EdmmErr::Ceiling)
+}
+
+/// The granularity of the blocks of a [`DynamicAlloc`].
+const BLOCK_ALIGN: usize = 16;
+/// The header before the data of a block: the offset of the block, and its
+/// size.
+const BLOCK_HEADER: usize = 16;
+const MIN_BLOCK: usize = 32;
+
+/// A free block of the dynamic heap, in the list of them sorted by address.
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+/// The blocks of the dynamic heap of a [`DynamicAlloc`], by offset from its
+/// base.
+struct Spill {
+    heap: Option<DynamicHeap>,
+    failed: bool,
+    /// The end of the last block; the heap past it is free.
+    top: usize,
+    free: *mut FreeBlock,
+}
+
+impl Spill {
+    /// Where the data of a block of `layout` from `start` goes, and the size
+    /// of the block.
+    #[inline]
+    fn fit(start: usize, layout: &Layout) -> Option<(usize, usize)> {
+        let align = layout.align().max(BLOCK_ALIGN);
+        let data = start.checked_add(BLOCK_HEADER + align - 1)? & !(align - 1);
+        let end = data
+            .checked_add(layout.size())?
+            .checked_add(BLOCK_ALIGN - 1)?
+            & !(BLOCK_ALIGN - 1);
+        Some((data, (end - start).max(MIN_BLOCK)))
+    }
+
+    unsafe fn offset(&self, block: *mut FreeBlock) -> usize {
+        block as usize
+            - self
+                .heap
+                .as_ref()
+                .map_or(0, |heap| heap.base.as_ptr() as usize)
+    }
+
+    unsafe fn at(&self, offset: usize) -> *mut u8 {
+        match self.heap {
+            Some(ref heap) => heap.base.as_ptr().add(offset),
+            None => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn alloc(&mut self, layout: &Layout) -> *mut u8 {
+        // First fit in the free blocks.
+        let mut link: *mut *mut FreeBlock = &mut self.free;
+        while !(*link).is_null() {
+            let block = *link;
+            let (start, size) = (self.offset(block), (*block).size);
+            if let Some((data, needed)) = Self::fit(start, layout) {
+                if needed <= size {
+                    let next = (*block).next;
+                    let used = if size - needed >= MIN_BLOCK {
+                        let rest = self.at(start + needed) as *mut FreeBlock;
+                        rest.write(FreeBlock {
+                            size: size - needed,
+                            next,
+                        });
+                        *link = rest;
+                        needed
+                    } else {
+                        *link = next;
+                        size
+                    };
+                    return self.place(start, used, data);
+                }
+            }
+            link = &mut (*block).next;
+        }
+
+        // Otherwise past the last block, growing the heap.
+        let start = self.top;
+        let (data, needed) = match Self::fit(start, layout) {
+            Some(fit) => fit,
+            None => return ptr::null_mut(),
+        };
+        let grown = match self.heap {
+            Some(ref mut heap) => heap.grow_to(start + needed).is_ok(),
+            None => false,
+        };
+        if !grown {
+            return ptr::null_mut();
+        }
+        self.top = start + needed;
+        self.place(start, needed, data)
+    }
+
+    unsafe fn place(&mut self, start: usize, size: usize, data: usize) -> *mut u8 {
+        let header = self.at(data - BLOCK_HEADER) as *mut usize;
+        header.write(start);
+        header.add(1).write(size);
+        self.at(data)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, step: usize) {
+        let header = ptr.sub(BLOCK_HEADER) as *const usize;
+        let (mut start, mut size) = (header.read(), header.add(1).read());
+
+        // The link to the first free block after the block, and the one to
+        // the free block before it.
+        let mut link: *mut *mut FreeBlock = &mut self.free;
+        let mut prev_link: *mut *mut FreeBlock = ptr::null_mut();
+        while !(*link).is_null() && self.offset(*link) < start {
+            prev_link = link;
+            link = &mut (**link).next;
+        }
+        let mut next = *link;
+        if !next.is_null() && start + size == self.offset(next) {
+            size += (*next).size;
+            next = (*next).next;
+        }
+        if !prev_link.is_null() {
+            let prev = *prev_link;
+            if self.offset(prev) + (*prev).size == start {
+                start = self.offset(prev);
+                size += (*prev).size;
+                link = prev_link;
+            }
+        }
+
+        if start + size == self.top {
+            *link = next;
+            self.top = start;
+            if let Some(ref mut heap) = self.heap {
+                if heap.committed() - start >= 2 * step * PAGE_SIZE {
+                    let _ = heap.trim_to(start);
+                }
+            }
+        } else {
+            let block = self.at(start) as *mut FreeBlock;
+            block.write(FreeBlock { size, next });
+            *link = block;
+        }
+    }
+}
+
+/// A global allocator growing into a [`DynamicHeap`] once the enclave heap
+/// is exhausted.
+///
+/// Allocations go to [`System`] first. Those it fails are served from the
+/// dynamic heap, created by the first of them, whose blocks are kept in a
+/// first-fit free list behind a spin lock: it's meant for the peaks of
+/// demand, with the enclave heap sized for the usual load.
+pub struct DynamicAlloc {
+    config: HeapConfig,
+    locked: AtomicBool,
+    base: AtomicUsize,
+    end: AtomicUsize,
+    spill: UnsafeCell<Spill>,
+}
+
+unsafe impl Sync for DynamicAlloc {}
+
+impl DynamicAlloc {
+    pub const fn new(config: HeapConfig) -> DynamicAlloc {
+        DynamicAlloc {
+            config,
+            locked: AtomicBool::new(false),
+            base: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+            spill: UnsafeCell::new(Spill {
+                heap: None,
+                failed: false,
+                top: 0,
+                free: ptr::null_mut(),
+            }),
+        }
+    }
+
+    /// Bytes of the dynamic heap allocated so far, zero until the enclave
+    /// heap was first exhausted.
+    pub fn committed(&self) -> usize {
+        self.with_spill(|spill| spill.heap.as_ref().map_or(0, DynamicHeap::committed))
+    }
+
+    fn with_spill<R, F: FnOnce(&mut Spill) -> R>(&self, f: F) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        // SAFETY: the lock is held.
+        let result = f(unsafe { &mut *self.spill.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+
+    #[inline]
+    fn is_spilled(&self, ptr: *mut u8) -> bool {
+        let addr = ptr as usize;
+        addr >= self.base.load(Ordering::Acquire) && addr < self.end.load(Ordering::Acquire)
+    }
+
+    unsafe fn spill_alloc(&self, layout: &Layout) -> *mut u8 {
+        self.with_spill(|spill| {
+            if spill.heap.is_none() {
+                if spill.failed {
+                    return ptr::null_mut();
+                }
+                match DynamicHeap::new(self.config) {
+                    Ok(heap) => {
+                        let base = heap.base.as_ptr() as usize;
+                        self.end.store(base + heap.ceiling(), Ordering::Release);
+                        self.base.store(base, Ordering::Release);
+                        spill.heap = Some(heap);
+                    }
+                    Err(_) => {
+                        spill.failed = true;
+                        return ptr::null_mut();
+                    }
+                }
+            }
+            spill.alloc(layout)
+        })
+    }
+
+    unsafe fn spill_dealloc(&self, ptr: *mut u8) {
+        self.with_spill(|spill| spill.dealloc(ptr, self.config.step.max(1)))
+    }
+}
+
+unsafe impl GlobalAlloc for DynamicAlloc {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            return ptr;
+        }
+        self.spill_alloc(&layout)
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            return ptr;
+        }
+        let ptr = self.spill_alloc(&layout);
+        if !ptr.is_null() {
+            ptr::write_bytes(ptr, 0, layout.size());
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if self.is_spilled(ptr) {
+            self.spill_dealloc(ptr);
+        } else {
+            System.dealloc(ptr, layout);
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if !self.is_spilled(ptr) {
+            let new_ptr = System.realloc(ptr, layout, new_size);
+            if !new_ptr.is_null() {
+                return new_ptr;
+            }
+        }
+        // SAFETY: the caller guarantees `new_size` makes a valid layout.
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(feature = "emm")]
+mod platform {
+    use super::{EdmmErr, PAGE_SIZE};
+    use crate::rsrvmem::ProtectAttr;
+    use core::ffi::c_void;
+    use core::ptr::{self, NonNull};
+
+    pub const SGX_EMA_RESERVE: usize = 0x1;
+    pub const SGX_EMA_COMMIT_NOW: usize = 0x2;
+    pub const SGX_EMA_COMMIT_ON_DEMAND: usize = 0x4;
+    const SGX_EMA_PROT_READ: i32 = 0x1;
+    const SGX_EMA_PROT_WRITE: i32 = 0x2;
+    const SGX_EMA_PROT_EXEC: i32 = 0x4;
+    const EINVAL: i32 = 22;
+
+    type size_t = usize;
+    type c_int = i32;
+    type sgx_enclave_fault_handler_t = *const c_void;
+
+    extern "C" {
+        fn sgx_mm_alloc(
+            addr: *mut c_void,
+            length: size_t,
+            flags: size_t,
+            handler: sgx_enclave_fault_handler_t,
+            handler_private: *mut c_void,
+            out_addr: *mut *mut c_void,
+        ) -> c_int;
+        fn sgx_mm_commit(addr: *mut c_void, length: size_t) -> c_int;
+        fn sgx_mm_uncommit(addr: *mut c_void, length: size_t) -> c_int;
+        fn sgx_mm_dealloc(addr: *mut c_void, length: size_t) -> c_int;
+        fn sgx_mm_modify_permissions(addr: *mut c_void, length: size_t, prot: c_int) -> c_int;
+    }
+
+    #[inline]
+    fn check(errno: c_int) -> Result<(), EdmmErr> {
+        if errno == 0 {
+            Ok(())
+        } else {
+            Err(EdmmErr::Errno(errno))
+        }
+    }
+
+    #[inline]
+    fn length(count: usize) -> Result<size_t, EdmmErr> {
+        match count.checked_mul(PAGE_SIZE) {
+            Some(length) if length != 0 => Ok(length),
+            _ => Err(EdmmErr::Errno(EINVAL)),
+        }
+    }
+
+    #[inline]
+    pub unsafe fn alloc(count: usize, flags: usize) -> Result<NonNull<u8>, EdmmErr> {
+        if !super::is_supported() {
+            return Err(EdmmErr::Unsupported);
+        }
+        let mut addr: *mut c_void = ptr::null_mut();
+        check(sgx_mm_alloc(
+            ptr::null_mut(),
+            length(count)?,
+            flags,
+            ptr::null(),
+            ptr::null_mut(),
+            &mut addr,
+        ))?;
+        NonNull::new(addr as *mut u8).ok_or(EdmmErr::Errno(EINVAL))
+    }
+
+    #[inline]
+    pub unsafe fn commit(addr: *mut u8, count: usize) -> Result<(), EdmmErr> {
+        check(sgx_mm_commit(addr as *mut c_void, length(count)?))
+    }
+
+    #[inline]
+    pub unsafe fn uncommit(addr: *mut u8, count: usize) -> Result<(), EdmmErr> {
+        check(sgx_mm_uncommit(addr as *mut c_void, length(count)?))
+    }
+
+    #[inline]
+    pub unsafe fn dealloc(addr: *mut u8, count: usize) -> Result<(), EdmmErr> {
+        check(sgx_mm_dealloc(addr as *mut c_void, length(count)?))
+    }
+
+    #[inline]
+    pub unsafe fn protect(addr: *mut u8, count: usize, prot: ProtectAttr) -> Result<(), EdmmErr> {
+        let prot = match prot {
+            ProtectAttr::Read => SGX_EMA_PROT_READ,
+            ProtectAttr::ReadWrite => SGX_EMA_PROT_READ | SGX_EMA_PROT_WRITE,
+            ProtectAttr::ReadExec => SGX_EMA_PROT_READ | SGX_EMA_PROT_EXEC,
+            ProtectAttr::ReadWriteExec => {
+                SGX_EMA_PROT_READ | SGX_EMA_PROT_WRITE | SGX_EMA_PROT_EXEC
+            }
+        };
+        check(sgx_mm_modify_permissions(
+            addr as *mut c_void,
+            length(count)?,
+            prot,
+        ))
+    }
+}
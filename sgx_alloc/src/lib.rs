@@ -36,9 +36,15 @@
 
 extern crate alloc;
 
+#[cfg(target_env = "sgx")]
+extern crate sgx_trts;
+
 mod system;
 pub use system::System;
 
 pub mod alignalloc;
 pub mod alignbox;
+pub mod arena;
+pub mod edmm;
 pub mod rsrvmem;
+pub mod stats;
@@ -0,0 +1,323 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! # Allocation statistics for Rust SGX SDK
+//!
+//! [`StatsAlloc`] wraps an allocator, [`System`] by default, and counts what
+//! goes through it, so that an enclave running out of EPC can tell what it
+//! holds its heap for:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static GLOBAL: StatsAlloc = StatsAlloc::new().leak_check(report_leaks);
+//!
+//! let stats = GLOBAL.alloc_stats();
+//! println!("{} bytes in use, {} at peak, {:.1}% overhead",
+//!          stats.in_use, stats.peak, 100.0 * stats.fragmentation());
+//! ```
+//!
+//! Allocations are counted in power-of-two size classes, from 16 bytes up to
+//! 1 MiB and a last class for larger ones. The fragmentation is estimated
+//! from the chunks the tlibc `malloc` rounds each allocation to: it's the
+//! share of the bytes held for live allocations which they didn't ask for.
+//! Free chunks `malloc` keeps aren't seen, so this is a lower bound.
+//!
+//! Counting costs a few relaxed atomic operations per allocation.
+
+use crate::System;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// The number of size classes: up to 16 bytes, 32, ..., 1 MiB, and larger.
+pub const SIZE_CLASSES: usize = 18;
+
+const MIN_CLASS_SHIFT: u32 = 4;
+
+/// The overhead and granularity of tlibc `malloc` chunks.
+const CHUNK_OVERHEAD: usize = 8;
+const CHUNK_ALIGN: usize = 16;
+const MIN_CHUNK: usize = 32;
+
+/// The size class of an allocation of `size` bytes.
+#[inline]
+fn class_of(size: usize) -> usize {
+    if size <= 1 << MIN_CLASS_SHIFT {
+        return 0;
+    }
+    let shift = usize::BITS - (size - 1).leading_zeros();
+    ((shift - MIN_CLASS_SHIFT) as usize).min(SIZE_CLASSES - 1)
+}
+
+/// An estimate of the bytes `malloc` holds for an allocation of `layout`.
+#[inline]
+fn held(layout: &Layout) -> usize {
+    let padded = layout
+        .size()
+        .saturating_add(CHUNK_OVERHEAD + CHUNK_ALIGN - 1)
+        & !(CHUNK_ALIGN - 1);
+    // `memalign` finds an aligned chunk within a larger one, and frees the
+    // rest; count the worst case of the padding it may keep.
+    let align = if layout.align() > CHUNK_ALIGN {
+        layout.align() - CHUNK_ALIGN
+    } else {
+        0
+    };
+    padded.max(MIN_CHUNK).saturating_add(align)
+}
+
+/// Counts of the allocations of one size class.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SizeClassStats {
+    /// The largest size in the class, `usize::MAX` for the last one.
+    pub max_size: usize,
+    /// The allocations of the class still live.
+    pub live: usize,
+    /// The allocations of the class made so far.
+    pub total: usize,
+}
+
+/// A snapshot of the counters of a [`StatsAlloc`].
+///
+/// The counters are read one at a time while other threads allocate, so a
+/// snapshot may be slightly inconsistent.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AllocStats {
+    /// Bytes of live allocations, as requested.
+    pub in_use: usize,
+    /// The most bytes ever in use at once.
+    pub peak: usize,
+    /// An estimate of the bytes `malloc` holds for the live allocations.
+    pub held: usize,
+    /// The allocations made so far, and the ones freed.
+    pub allocations: usize,
+    pub deallocations: usize,
+    /// Allocations which failed.
+    pub failures: usize,
+    pub classes: [SizeClassStats; SIZE_CLASSES],
+}
+
+impl AllocStats {
+    /// The number of live allocations.
+    #[inline]
+    pub fn live(&self) -> usize {
+        self.allocations.saturating_sub(self.deallocations)
+    }
+
+    /// The estimated share, from 0 to 1, of the held bytes which no
+    /// allocation asked for.
+    pub fn fragmentation(&self) -> f64 {
+        if self.held == 0 {
+            0.0
+        } else {
+            1.0 - self.in_use as f64 / self.held as f64
+        }
+    }
+}
+
+/// The counters of a [`StatsAlloc`].
+struct Counters {
+    in_use: AtomicUsize,
+    peak: AtomicUsize,
+    held: AtomicUsize,
+    allocations: AtomicUsize,
+    deallocations: AtomicUsize,
+    failures: AtomicUsize,
+    live: [AtomicUsize; SIZE_CLASSES],
+    total: [AtomicUsize; SIZE_CLASSES],
+    leak_report: Option<fn(&AllocStats)>,
+}
+
+impl Counters {
+    #[inline]
+    fn allocated(&self, layout: &Layout) {
+        let class = class_of(layout.size());
+        self.live[class].fetch_add(1, Ordering::Relaxed);
+        self.total[class].fetch_add(1, Ordering::Relaxed);
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        self.held.fetch_add(held(layout), Ordering::Relaxed);
+        let in_use = self.in_use.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        self.peak.fetch_max(in_use, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn freed(&self, layout: &Layout) {
+        self.live[class_of(layout.size())].fetch_sub(1, Ordering::Relaxed);
+        self.deallocations.fetch_add(1, Ordering::Relaxed);
+        self.held.fetch_sub(held(layout), Ordering::Relaxed);
+        self.in_use.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> AllocStats {
+        let mut classes = [SizeClassStats::default(); SIZE_CLASSES];
+        for (class, stats) in classes.iter_mut().enumerate() {
+            stats.max_size = if class == SIZE_CLASSES - 1 {
+                usize::MAX
+            } else {
+                1 << (class as u32 + MIN_CLASS_SHIFT)
+            };
+            stats.live = self.live[class].load(Ordering::Relaxed);
+            stats.total = self.total[class].load(Ordering::Relaxed);
+        }
+        AllocStats {
+            in_use: self.in_use.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            held: self.held.load(Ordering::Relaxed),
+            allocations: self.allocations.load(Ordering::Relaxed),
+            deallocations: self.deallocations.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            classes,
+        }
+    }
+}
+
+/// An allocator counting the allocations of another.
+pub struct StatsAlloc<A = System> {
+    inner: A,
+    counters: Counters,
+}
+
+impl StatsAlloc<System> {
+    pub const fn new() -> StatsAlloc<System> {
+        StatsAlloc::with_allocator(System)
+    }
+}
+
+impl Default for StatsAlloc<System> {
+    fn default() -> Self {
+        StatsAlloc::new()
+    }
+}
+
+impl<A> StatsAlloc<A> {
+    pub const fn with_allocator(inner: A) -> StatsAlloc<A> {
+        StatsAlloc {
+            inner,
+            counters: Counters {
+                in_use: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                held: AtomicUsize::new(0),
+                allocations: AtomicUsize::new(0),
+                deallocations: AtomicUsize::new(0),
+                failures: AtomicUsize::new(0),
+                live: [const { AtomicUsize::new(0) }; SIZE_CLASSES],
+                total: [const { AtomicUsize::new(0) }; SIZE_CLASSES],
+                leak_report: None,
+            },
+        }
+    }
+
+    /// Calls `report` with the statistics if allocations are still live when
+    /// the enclave is destroyed, once [`check_leaks_at_exit`] registered the
+    /// check.
+    ///
+    /// Allocations meant to live as long as the enclave, such as those of
+    /// lazily initialized statics, are live too: compare the classes of the
+    /// report with those of a healthy run.
+    ///
+    /// [`check_leaks_at_exit`]: StatsAlloc::check_leaks_at_exit
+    pub const fn leak_check(mut self, report: fn(&AllocStats)) -> StatsAlloc<A> {
+        self.counters.leak_report = Some(report);
+        self
+    }
+
+    /// Returns a snapshot of the counters.
+    pub fn alloc_stats(&self) -> AllocStats {
+        self.counters.snapshot()
+    }
+
+    /// Registers the leak check of this allocator to run at the exit of the
+    /// enclave, with `atexit`. Only one allocator is checked; returns false
+    /// if one already was registered, or if `atexit` failed.
+    pub fn check_leaks_at_exit(&'static self) -> bool {
+        let counters = &self.counters as *const Counters as *mut Counters;
+        if LEAK_CHECK
+            .compare_exchange(
+                ptr::null_mut(),
+                counters,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            return false;
+        }
+        unsafe { platform::atexit(run_leak_check) == 0 }
+    }
+}
+
+static LEAK_CHECK: AtomicPtr<Counters> = AtomicPtr::new(ptr::null_mut());
+
+extern "C" fn run_leak_check() {
+    // SAFETY: the counters are those of a static allocator.
+    if let Some(counters) = unsafe { LEAK_CHECK.load(Ordering::Acquire).as_ref() } {
+        let stats = counters.snapshot();
+        if let (Some(report), true) = (counters.leak_report, stats.live() != 0) {
+            report(&stats);
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for StatsAlloc<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if ptr.is_null() {
+            self.counters.failures.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.counters.allocated(&layout);
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if ptr.is_null() {
+            self.counters.failures.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.counters.allocated(&layout);
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.counters.freed(&layout);
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if new_ptr.is_null() {
+            self.counters.failures.fetch_add(1, Ordering::Relaxed);
+        } else {
+            // SAFETY: the caller guarantees `new_size` makes a valid layout.
+            let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+            self.counters.freed(&layout);
+            self.counters.allocated(&new_layout);
+        }
+        new_ptr
+    }
+}
+
+mod platform {
+    extern "C" {
+        pub fn atexit(fun: extern "C" fn()) -> i32;
+    }
+}
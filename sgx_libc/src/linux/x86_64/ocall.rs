@@ -180,6 +180,14 @@ extern "C" {
         fd: c_int,
         length: off64_t,
     ) -> sgx_status_t;
+    pub fn u_fallocate64_ocall(
+        result: *mut c_int,
+        error: *mut c_int,
+        fd: c_int,
+        mode: c_int,
+        offset: off64_t,
+        len: off64_t,
+    ) -> sgx_status_t;
     pub fn u_truncate_ocall(
         result: *mut c_int,
         error: *mut c_int,
@@ -364,6 +372,12 @@ extern "C" {
         cmd: c_int,
         arg: c_int,
     ) -> sgx_status_t;
+    pub fn u_flock_ocall(
+        result: *mut c_int,
+        errno: *mut c_int,
+        fd: c_int,
+        operation: c_int,
+    ) -> sgx_status_t;
     pub fn u_ioctl_arg0_ocall(
         result: *mut c_int,
         errno: *mut c_int,
@@ -377,7 +391,14 @@ extern "C" {
         request: c_int,
         arg: *mut c_int,
     ) -> sgx_status_t;
+    pub fn u_ficlone_ocall(
+        result: *mut c_int,
+        errno: *mut c_int,
+        fd: c_int,
+        src_fd: c_int,
+    ) -> sgx_status_t;
     pub fn u_close_ocall(result: *mut c_int, errno: *mut c_int, fd: c_int) -> sgx_status_t;
+    pub fn u_isatty_ocall(result: *mut c_int, errno: *mut c_int, fd: c_int) -> sgx_status_t;
     // time
     pub fn u_clock_gettime_ocall(
         result: *mut c_int,
@@ -1248,6 +1269,29 @@ pub unsafe fn ftruncate64(fd: c_int, length: off64_t) -> c_int {
     result
 }
 
+pub unsafe fn fallocate64(fd: c_int, mode: c_int, offset: off64_t, len: off64_t) -> c_int {
+    let mut result: c_int = 0;
+    let mut error: c_int = 0;
+    let status = u_fallocate64_ocall(
+        &mut result as *mut c_int,
+        &mut error as *mut c_int,
+        fd,
+        mode,
+        offset,
+        len,
+    );
+
+    if status == sgx_status_t::SGX_SUCCESS {
+        if result == -1 {
+            set_errno(error);
+        }
+    } else {
+        set_errno(ESGX);
+        result = -1;
+    }
+    result
+}
+
 pub unsafe fn truncate(path: *const c_char, length: off_t) -> c_int {
     let mut result: c_int = 0;
     let mut error: c_int = 0;
@@ -2308,6 +2352,22 @@ pub unsafe fn fcntl_arg0(fd: c_int, cmd: c_int) -> c_int {
     result
 }
 
+pub unsafe fn flock(fd: c_int, operation: c_int) -> c_int {
+    let mut result: c_int = 0;
+    let mut error: c_int = 0;
+    let status = u_flock_ocall(&mut result as *mut c_int, &mut error as *mut c_int, fd, operation);
+
+    if status == sgx_status_t::SGX_SUCCESS {
+        if result == -1 {
+            set_errno(error);
+        }
+    } else {
+        set_errno(ESGX);
+        result = -1;
+    }
+    result
+}
+
 pub unsafe fn fcntl_arg1(fd: c_int, cmd: c_int, arg: c_int) -> c_int {
     let mut result: c_int = 0;
     let mut error: c_int = 0;
@@ -2373,6 +2433,27 @@ pub unsafe fn ioctl_arg1(fd: c_int, request: c_int, arg: *mut c_int) -> c_int {
     result
 }
 
+pub unsafe fn ficlone(fd: c_int, src_fd: c_int) -> c_int {
+    let mut result: c_int = 0;
+    let mut error: c_int = 0;
+    let status = u_ficlone_ocall(
+        &mut result as *mut c_int,
+        &mut error as *mut c_int,
+        fd,
+        src_fd,
+    );
+
+    if status == sgx_status_t::SGX_SUCCESS {
+        if result == -1 {
+            set_errno(error);
+        }
+    } else {
+        set_errno(ESGX);
+        result = -1;
+    }
+    result
+}
+
 pub unsafe fn close(fd: c_int) -> c_int {
     let mut result: c_int = 0;
     let mut error: c_int = 0;
@@ -2389,6 +2470,22 @@ pub unsafe fn close(fd: c_int) -> c_int {
     result
 }
 
+pub unsafe fn isatty(fd: c_int) -> c_int {
+    let mut result: c_int = 0;
+    let mut error: c_int = 0;
+    let status = u_isatty_ocall(&mut result as *mut c_int, &mut error as *mut c_int, fd);
+
+    if status == sgx_status_t::SGX_SUCCESS {
+        if result == 0 {
+            set_errno(error);
+        }
+    } else {
+        set_errno(ESGX);
+        result = 0;
+    }
+    result
+}
+
 // time
 pub unsafe fn clock_gettime(clk_id: clockid_t, tp: *mut timespec) -> c_int {
     let mut result: c_int = 0;
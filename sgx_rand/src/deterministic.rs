@@ -0,0 +1,193 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! A seeded generator for reproducible tests.
+//!
+//! `DeterministicRng` expands a 256-bit seed with ChaCha20, so that tests of
+//! protocols produce the same keys, nonces and transcripts on every run.
+//! With the `test_rng` feature, [`install`] also makes it the source of the
+//! key generation and nonces of `sgx_tcrypto` and of `SgxRng`; production
+//! builds, without the feature, always read the hardware generator.
+
+use crate::{ChaChaRng, Rng, SeedableRng};
+
+/// A ChaCha20 generator seeded with 32 bytes.
+#[derive(Clone, Debug)]
+pub struct DeterministicRng {
+    rng: ChaChaRng,
+}
+
+impl DeterministicRng {
+    /// Create a generator from `seed`. The same seed always yields the same
+    /// stream.
+    pub fn new(seed: [u8; 32]) -> DeterministicRng {
+        SeedableRng::from_seed(seed)
+    }
+}
+
+impl Rng for DeterministicRng {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let word = self.rng.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+    }
+}
+
+impl SeedableRng<[u8; 32]> for DeterministicRng {
+    fn reseed(&mut self, seed: [u8; 32]) {
+        self.rng.reseed(&seed_words(&seed)[..]);
+    }
+
+    fn from_seed(seed: [u8; 32]) -> DeterministicRng {
+        DeterministicRng { rng: ChaChaRng::from_seed(&seed_words(&seed)[..]) }
+    }
+}
+
+fn seed_words(seed: &[u8; 32]) -> [u32; 8] {
+    let mut words = [0u32; 8];
+    for (word, bytes) in words.iter_mut().zip(seed.chunks(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    words
+}
+
+#[cfg(feature = "test_rng")]
+mod provider {
+    use super::DeterministicRng;
+    use crate::Rng;
+    use sgx_types::*;
+    use std::sync::SgxSpinlock;
+
+    static LOCK: SgxSpinlock = SgxSpinlock::new();
+    // The installed generator, protected by `LOCK`.
+    static mut INSTALLED: Option<DeterministicRng> = None;
+
+    fn fill(buf: &mut [u8]) -> SgxError {
+        let _guard = LOCK.lock();
+        // SAFETY: `INSTALLED` is only accessed while holding `LOCK`.
+        match unsafe { INSTALLED.as_mut() } {
+            Some(rng) => {
+                rng.fill_bytes(buf);
+                Ok(())
+            }
+            None => Err(sgx_status_t::SGX_ERROR_UNEXPECTED),
+        }
+    }
+
+    /// Makes a `DeterministicRng` seeded with `seed` the source of random
+    /// numbers of `sgx_tcrypto` and `SgxRng`, until [`uninstall`].
+    ///
+    /// The generator is global to the enclave: tests installing one must not
+    /// run concurrently with other tests drawing random numbers.
+    pub fn install(seed: [u8; 32]) {
+        {
+            let _guard = LOCK.lock();
+            // SAFETY: as in `fill`.
+            unsafe { INSTALLED = Some(DeterministicRng::new(seed)) };
+        }
+        sgx_tcrypto::rng::set_provider(Some(fill));
+    }
+
+    /// Restores the hardware generator.
+    pub fn uninstall() {
+        sgx_tcrypto::rng::set_provider(None);
+        let _guard = LOCK.lock();
+        // SAFETY: as in `fill`.
+        unsafe { INSTALLED = None };
+    }
+}
+
+#[cfg(feature = "test_rng")]
+pub use self::provider::{install, uninstall};
+
+#[cfg(test)]
+mod tests {
+    use super::DeterministicRng;
+    use crate::{Rng, SeedableRng};
+
+    fn stream(rng: &mut DeterministicRng) -> [u8; 64] {
+        let mut out = [0u8; 64];
+        rng.fill_bytes(&mut out);
+        out
+    }
+
+    #[test]
+    fn same_seed_same_stream() {
+        let mut a = DeterministicRng::new([7; 32]);
+        let mut b = DeterministicRng::new([7; 32]);
+        assert_eq!(stream(&mut a)[..], stream(&mut b)[..]);
+        assert_eq!(a.next_u64(), b.next_u64());
+        // A clone carries on from where the original is.
+        let mut c = a.clone();
+        assert_eq!(stream(&mut a)[..], stream(&mut c)[..]);
+        // Reseeding starts over the stream of the new seed.
+        a.reseed([7; 32]);
+        assert_eq!(stream(&mut a)[..], stream(&mut DeterministicRng::new([7; 32]))[..]);
+    }
+
+    #[test]
+    fn different_seeds_differ() {
+        let mut seed = [7; 32];
+        let first = stream(&mut DeterministicRng::new(seed));
+        for i in [0, 31] {
+            seed[i] ^= 1;
+            assert_ne!(first[..], stream(&mut DeterministicRng::new(seed))[..]);
+            seed[i] ^= 1;
+        }
+    }
+
+    #[test]
+    fn chacha20_stream() {
+        // The ChaCha20 keystream of the zero key and nonce, RFC 7539 A.1.
+        let mut rng = DeterministicRng::new([0; 32]);
+        let mut out = [0u8; 32];
+        rng.fill_bytes(&mut out);
+        assert_eq!(
+            out,
+            [
+                0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86,
+                0xbd, 0x28, 0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a, 0xa8, 0x36, 0xef, 0xcc,
+                0x8b, 0x77, 0x0d, 0xc7,
+            ]
+        );
+        // Lengths that aren't whole words drop the rest of the last word.
+        let mut rng = DeterministicRng::new([0; 32]);
+        let mut short = [0u8; 7];
+        rng.fill_bytes(&mut short);
+        assert_eq!(short[..], out[..7]);
+        assert_eq!(rng.next_u32(), u32::from_le_bytes([out[8], out[9], out[10], out[11]]));
+    }
+
+    #[cfg(feature = "test_rng")]
+    #[test]
+    fn installs_into_tcrypto() {
+        let mut expected = [0u8; 48];
+        DeterministicRng::new([3; 32]).fill_bytes(&mut expected);
+        super::install([3; 32]);
+        let mut drawn = [0u8; 48];
+        let result = sgx_tcrypto::rng::fill_random(&mut drawn);
+        super::uninstall();
+        assert_eq!(result, Ok(()));
+        assert_eq!(drawn[..], expected[..]);
+    }
+}
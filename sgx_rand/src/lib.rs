@@ -24,6 +24,7 @@
 
 extern crate sgx_types;
 extern crate sgx_trts;
+extern crate sgx_tcrypto;
 #[cfg(not(target_env = "sgx"))]
 #[macro_use]
 extern crate sgx_tstd as std;
@@ -41,6 +42,7 @@ pub use os::SgxRng;
 
 pub use isaac::{IsaacRng, Isaac64Rng};
 pub use chacha::ChaChaRng;
+pub use deterministic::DeterministicRng;
 
 #[cfg(target_pointer_width = "32")]
 use IsaacRng as IsaacWordRng;
@@ -53,6 +55,7 @@ use distributions::range::SampleRange;
 pub mod distributions;
 pub mod isaac;
 pub mod chacha;
+pub mod deterministic;
 pub mod reseeding;
 mod rand_impls;
 pub mod os;
@@ -58,16 +58,24 @@ fn next_u64(fill_buf: &mut dyn FnMut(&mut [u8])) -> u64 {
 mod imp {
 
     use sgx_types::*;
+    #[cfg(not(feature = "test_rng"))]
     use sgx_trts::trts::rsgx_read_rand;
     use std::io;
 
     use super::{next_u32, next_u64};
     use crate::Rng;
 
+    #[cfg(not(feature = "test_rng"))]
     fn getrandom(buf: &mut [u8]) -> SgxError {
         rsgx_read_rand(buf)
     }
 
+    // Draws from the generator a test installed, if any.
+    #[cfg(feature = "test_rng")]
+    fn getrandom(buf: &mut [u8]) -> SgxError {
+        sgx_tcrypto::rng::fill_random(buf)
+    }
+
     fn getrandom_fill_bytes(v: &mut [u8]) {
         getrandom(v).expect("unexpected getrandom error");
     }
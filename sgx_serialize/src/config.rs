@@ -0,0 +1,278 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Enclave configuration kept in a protected file.
+//!
+//! The host controls the environment and the command line of the enclave,
+//! so configuration read from them can't be trusted. A `SealedConfig` reads
+//! its value from a protected file instead, which only an enclave holding
+//! the key can have written, decodes it with `DeSerializable`, and checks it
+//! with `Validate` before using it.
+//!
+//! The enclave can't watch the file, so `reload` is called when it may have
+//! changed, e.g. from an ecall the host makes after replacing it. A value
+//! which fails to decode or validate leaves the current one in place; a new
+//! one replaces it and is passed to each subscriber.
+
+use crate::opaque::Decoder as DataDecoder;
+use crate::serialize::{DeSerializable, Serializable, SerializeHelper};
+use std::boxed::Box;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sgxfs::{KeyPolicy, SgxFile, Transaction};
+use std::string::String;
+use std::sync::Arc;
+use std::vec::Vec;
+
+/// Checks a decoded configuration, before it's used.
+///
+/// The default accepts any value which decodes.
+pub trait Validate {
+    /// Returns why the value is invalid, if it is.
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// An error loading or storing a configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The protected file couldn't be read or written, or failed to
+    /// authenticate.
+    Io(io::Error),
+    /// The contents don't decode to the type of the configuration.
+    Malformed,
+    /// The value was rejected by `Validate`.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref e) => write!(f, "configuration file error: {}", e),
+            ConfigError::Malformed => f.write_str("malformed configuration"),
+            ConfigError::Invalid(ref why) => write!(f, "invalid configuration: {}", why),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ConfigError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::Io(e)
+    }
+}
+
+/// Identifies a subscriber, to unsubscribe it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Subscription(u64);
+
+/// A configuration loaded from a protected file, and reloaded on demand.
+///
+/// The type of the configuration is bounded by `DeSerializable`, not by
+/// serde's `DeserializeOwned`. The crates of the SDK only depend on each
+/// other, by path, while serde for enclaves is the `serde-sgx` port fetched
+/// from git, which only `sgx_crypto_helper` pulls in, behind a feature. A
+/// `DeserializeOwned` bound would also need a data format such as JSON, and
+/// an enclave port of it, for a file which only the enclave writes. The
+/// derives of `sgx_serialize_derive` decode the configuration from a derive
+/// as serde's would, and `Validate` holds its schema.
+///
+/// ```ignore
+/// #[derive(Serializable, DeSerializable)]
+/// struct WalletConfig {
+///     max_signatures_per_hour: u32,
+///     allowed_chains: Vec<u64>,
+/// }
+///
+/// impl Validate for WalletConfig {
+///     fn validate(&self) -> Result<(), String> {
+///         if self.allowed_chains.is_empty() {
+///             return Err("no chain allowed".to_string());
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let mut config = SealedConfig::<WalletConfig>::load("wallet.conf", KeyPolicy::Auto)?;
+/// config.subscribe(|config| limiter.set_rate(config.max_signatures_per_hour));
+/// ```
+pub struct SealedConfig<T> {
+    path: PathBuf,
+    policy: KeyPolicy,
+    raw: Vec<u8>,
+    value: Arc<T>,
+    generation: u64,
+    next_subscription: u64,
+    subscribers: Vec<(Subscription, Box<dyn FnMut(&T)>)>,
+}
+
+impl<T: Serializable + DeSerializable + Validate> SealedConfig<T> {
+    /// Loads the configuration in the protected file at `path`, encrypted
+    /// with the key of `policy`.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        policy: KeyPolicy,
+    ) -> Result<SealedConfig<T>, ConfigError> {
+        let path = path.as_ref().to_path_buf();
+        let raw = read(&path, &policy)?;
+        let value = decode::<T>(&raw)?;
+        Ok(SealedConfig {
+            path,
+            policy,
+            raw,
+            value: Arc::new(value),
+            generation: 0,
+            next_subscription: 0,
+            subscribers: Vec::new(),
+        })
+    }
+
+    /// Writes `value` to a new protected file at `path`, replacing any
+    /// previous one atomically, and keeps it as the configuration.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        policy: KeyPolicy,
+        value: T,
+    ) -> Result<SealedConfig<T>, ConfigError> {
+        let path = path.as_ref().to_path_buf();
+        let raw = encode(&value)?;
+        write(&path, &policy, &raw)?;
+        Ok(SealedConfig {
+            path,
+            policy,
+            raw,
+            value: Arc::new(value),
+            generation: 0,
+            next_subscription: 0,
+            subscribers: Vec::new(),
+        })
+    }
+
+    /// Returns the current configuration. The value stays the same for
+    /// whoever holds it, across reloads.
+    pub fn get(&self) -> Arc<T> {
+        self.value.clone()
+    }
+
+    /// Returns how many times the configuration changed since it was loaded.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the file again, and switches to its value if it changed.
+    ///
+    /// Returns whether it changed. If the file can't be read, or its value
+    /// doesn't decode or validate, the current configuration stays.
+    pub fn reload(&mut self) -> Result<bool, ConfigError> {
+        let raw = read(&self.path, &self.policy)?;
+        if raw == self.raw {
+            return Ok(false);
+        }
+        let value = decode::<T>(&raw)?;
+        self.replace(raw, value);
+        Ok(true)
+    }
+
+    /// Writes `value` to the file and switches to it.
+    pub fn update(&mut self, value: T) -> Result<(), ConfigError> {
+        let raw = encode(&value)?;
+        write(&self.path, &self.policy, &raw)?;
+        self.replace(raw, value);
+        Ok(())
+    }
+
+    /// Calls `subscriber` with each new configuration, after it replaced the
+    /// previous one.
+    pub fn subscribe<F: FnMut(&T) + 'static>(&mut self, subscriber: F) -> Subscription {
+        let subscription = Subscription(self.next_subscription);
+        self.next_subscription += 1;
+        self.subscribers.push((subscription, Box::new(subscriber)));
+        subscription
+    }
+
+    /// Removes a subscriber, returning whether it was subscribed.
+    pub fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers.retain(|(s, _)| *s != subscription);
+        self.subscribers.len() != len
+    }
+
+    fn replace(&mut self, raw: Vec<u8>, value: T) {
+        self.raw = raw;
+        self.value = Arc::new(value);
+        self.generation += 1;
+        for (_, subscriber) in self.subscribers.iter_mut() {
+            subscriber(&self.value);
+        }
+    }
+}
+
+impl<T> fmt::Debug for SealedConfig<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SealedConfig")
+            .field("path", &self.path)
+            .field("generation", &self.generation)
+            .field("subscribers", &self.subscribers.len())
+            .finish()
+    }
+}
+
+fn read(path: &Path, policy: &KeyPolicy) -> io::Result<Vec<u8>> {
+    let mut file = SgxFile::open_with_policy(path, policy)?;
+    let mut raw = Vec::new();
+    file.read_to_end(&mut raw)?;
+    Ok(raw)
+}
+
+fn write(path: &Path, policy: &KeyPolicy, raw: &[u8]) -> io::Result<()> {
+    let mut transaction = Transaction::with_policy(path, policy)?;
+    transaction.write_all(raw)?;
+    transaction.commit()
+}
+
+fn encode<T: Serializable + Validate>(value: &T) -> Result<Vec<u8>, ConfigError> {
+    value.validate().map_err(ConfigError::Invalid)?;
+    SerializeHelper::new()
+        .encode(value)
+        .ok_or(ConfigError::Malformed)
+}
+
+/// Decodes and validates a value, which must span all of `raw`.
+fn decode<T: DeSerializable + Validate>(raw: &[u8]) -> Result<T, ConfigError> {
+    let mut decoder = DataDecoder::new(raw, 0);
+    let value = T::decode(&mut decoder).map_err(|_| ConfigError::Malformed)?;
+    if decoder.position() != raw.len() {
+        return Err(ConfigError::Malformed);
+    }
+    value.validate().map_err(ConfigError::Invalid)?;
+    Ok(value)
+}
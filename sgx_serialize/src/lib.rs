@@ -16,6 +16,10 @@
 // under the License..
 
 //! Support code for encoding and decoding types.
+//!
+//! A `SealedConfig` loads a configuration of such a type from a protected
+//! file, validates it, and reloads it when it changes. It decodes with
+//! `DeSerializable` rather than serde, for the reasons given on it.
 
 /*
 Core encoding and decoding interfaces.
@@ -33,3 +37,6 @@ pub use self::serialize::{Decoder, Encoder, DeSerializable, Serializable, Serial
 mod opaque;
 mod leb128;
 
+mod config;
+pub use self::config::{ConfigError, SealedConfig, Subscription, Validate};
+
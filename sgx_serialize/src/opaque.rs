@@ -160,9 +160,9 @@ impl<'a> Decoder<'a> {
         }
     }
 
-    // pub fn position(&self) -> usize {
-    //     self.position
-    // }
+    pub fn position(&self) -> usize {
+        self.position
+    }
 
     // pub fn advance(&mut self, bytes: usize) {
     //     self.position += bytes;
@@ -1103,6 +1103,34 @@ where
     }
 }
 
+#[cfg(all(feature = "backtrace", not(target_env = "sgx")))]
+impl Serializable for std::backtrace::OwnedFrame {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_struct("OwnedFrame", 5, |s| {
+            s.emit_struct_field("ip", 0, |s| self.ip.encode(s))?;
+            s.emit_struct_field("name", 1, |s| self.name.encode(s))?;
+            s.emit_struct_field("filename", 2, |s| self.filename.encode(s))?;
+            s.emit_struct_field("lineno", 3, |s| self.lineno.encode(s))?;
+            s.emit_struct_field("colno", 4, |s| self.colno.encode(s))
+        })
+    }
+}
+
+#[cfg(all(feature = "backtrace", not(target_env = "sgx")))]
+impl DeSerializable for std::backtrace::OwnedFrame {
+    fn decode<D: Decoder>(d: &mut D) -> Result<std::backtrace::OwnedFrame, D::Error> {
+        d.read_struct("OwnedFrame", 5, |d| {
+            Ok(std::backtrace::OwnedFrame {
+                ip: d.read_struct_field("ip", 0, DeSerializable::decode)?,
+                name: d.read_struct_field("name", 1, DeSerializable::decode)?,
+                filename: d.read_struct_field("filename", 2, DeSerializable::decode)?,
+                lineno: d.read_struct_field("lineno", 3, DeSerializable::decode)?,
+                colno: d.read_struct_field("colno", 4, DeSerializable::decode)?,
+            })
+        })
+    }
+}
+
 use std::io::Cursor;
 use std::marker::PhantomData;
 use crate::opaque::Encoder as DataEncoder;
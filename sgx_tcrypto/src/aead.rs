@@ -0,0 +1,752 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Authenticated encryption with associated data.
+//!
+//! [`Aead`] is implemented by AES-128-GCM, the SDK's own cipher, and by
+//! ciphers tolerating nonce reuse or random nonces better:
+//!
+//! * [`Aes128GcmSiv`] (RFC 8452) is nonce-misuse resistant: reusing a nonce
+//!   only reveals whether the same message was encrypted twice, where it
+//!   breaks GCM entirely.
+//! * [`XChaCha20Poly1305`] takes 192-bit nonces, which can be drawn at random
+//!   without risk of collision, e.g. by records written across enclave
+//!   restarts which can't keep a counter.
+//! * [`ChaCha20Poly1305`] (RFC 8439) is the 96-bit nonce variant.
+//!
+//! [`AeadStream`] encrypts messages too large to hold whole as a sequence of
+//! chunks, with any of these ciphers.
+//!
+//! Keys are copied into the cipher values and zeroized when they're dropped.
+
+use crate::chacha::{hchacha20, ChaCha20};
+use crate::poly1305::Poly1305;
+use crate::provider;
+use crate::secret::{Secret, SecretBytes};
+use crate::util::{ct_eq, zeroize};
+use sgx_types::*;
+
+/// The length of the tags of all the ciphers in this module.
+pub const TAG_LEN: usize = 16;
+
+/// An authentication tag.
+pub type Tag = [u8; TAG_LEN];
+
+/// An authenticated cipher with associated data.
+pub trait Aead: Sized {
+    /// The length of a key, in bytes.
+    const KEY_LEN: usize;
+    /// The length of a nonce, in bytes.
+    const NONCE_LEN: usize;
+
+    /// Creates a cipher with `key`, which must be `KEY_LEN` bytes long.
+    fn new(key: &[u8]) -> SgxResult<Self>;
+
+    /// Encrypts `src` into `dst`, authenticating `aad` along with it, and
+    /// returns the tag.
+    ///
+    /// `nonce` must be `NONCE_LEN` bytes long and `dst` at least as long as
+    /// `src`; only the first `src.len()` bytes of `dst` are written.
+    fn encrypt(&self, nonce: &[u8], aad: &[u8], src: &[u8], dst: &mut [u8]) -> SgxResult<Tag>;
+
+    /// Checks `tag` over `src` and `aad`, and decrypts `src` into `dst`.
+    ///
+    /// Fails with `SGX_ERROR_MAC_MISMATCH` if the tag doesn't match, leaving
+    /// no plaintext in `dst`.
+    fn decrypt(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        src: &[u8],
+        tag: &Tag,
+        dst: &mut [u8],
+    ) -> SgxError;
+}
+
+fn check_lengths(nonce: &[u8], nonce_len: usize, src: &[u8], dst: &[u8]) -> SgxError {
+    if nonce.len() != nonce_len || dst.len() < src.len() {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    Ok(())
+}
+
+fn copy_key<const N: usize>(key: &[u8]) -> SgxResult<[u8; N]> {
+    if key.len() != N {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    let mut copy = [0_u8; N];
+    copy.copy_from_slice(key);
+    Ok(copy)
+}
+
+/// AES-128-GCM, through the [`provider`](crate::provider), by default the
+/// SDK's `sgx_rijndael128GCM_*` functions.
+pub struct Aes128Gcm {
+    key: Secret<sgx_aes_gcm_128bit_key_t>,
+}
+
+impl Aead for Aes128Gcm {
+    const KEY_LEN: usize = SGX_AESGCM_KEY_SIZE;
+    const NONCE_LEN: usize = SGX_AESGCM_IV_SIZE;
+
+    fn new(key: &[u8]) -> SgxResult<Aes128Gcm> {
+        Ok(Aes128Gcm { key: Secret::new(copy_key(key)?) })
+    }
+
+    fn encrypt(&self, nonce: &[u8], aad: &[u8], src: &[u8], dst: &mut [u8]) -> SgxResult<Tag> {
+        check_lengths(nonce, Self::NONCE_LEN, src, dst)?;
+        let key = self.key.expose_secret();
+        let nonce = nonce.try_into().unwrap();
+        provider::current().aes128_gcm_encrypt(key, nonce, aad, src, &mut dst[..src.len()])
+    }
+
+    fn decrypt(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        src: &[u8],
+        tag: &Tag,
+        dst: &mut [u8],
+    ) -> SgxError {
+        check_lengths(nonce, Self::NONCE_LEN, src, dst)?;
+        let key = self.key.expose_secret();
+        let nonce = nonce.try_into().unwrap();
+        provider::current().aes128_gcm_decrypt(key, nonce, aad, src, tag, &mut dst[..src.len()])
+    }
+}
+
+/// Encrypts a single block with AES-128, through the provider.
+pub(crate) fn aes128_block(key: &[u8; 16], block: &[u8; 16]) -> SgxResult<[u8; 16]> {
+    let mut out = *block;
+    provider::current().aes128_encrypt_block(key, &mut out)?;
+    Ok(out)
+}
+
+/// Multiplies `x` by `y` in GF(2^128) as GCM defines it, in constant time.
+pub(crate) fn gf128_mul(x: u128, y: u128) -> u128 {
+    const R: u128 = 0xe1 << 120;
+    let mut z = 0_u128;
+    let mut v = y;
+    for i in (0..128).rev() {
+        let bit = (x >> i) & 1;
+        z ^= v & 0_u128.wrapping_sub(bit);
+        v = (v >> 1) ^ (R & 0_u128.wrapping_sub(v & 1));
+    }
+    z
+}
+
+/// POLYVAL (RFC 8452), computed through GHASH.
+///
+/// POLYVAL over `H` is GHASH over `mulX_GHASH(ByteReverse(H))` with every
+/// block byte-reversed, and so is the result.
+struct Polyval {
+    h: u128,
+    s: u128,
+}
+
+impl Polyval {
+    fn new(h: &[u8; 16]) -> Polyval {
+        // ByteReverse then a big-endian load is a little-endian load.
+        let h = u128::from_le_bytes(*h);
+        let h = (h >> 1) ^ ((0xe1 << 120) & 0_u128.wrapping_sub(h & 1));
+        Polyval { h, s: 0 }
+    }
+
+    /// Absorbs `data` zero-padded to a multiple of 16 bytes.
+    fn update_padded(&mut self, data: &[u8]) {
+        for chunk in data.chunks(16) {
+            let mut block = [0_u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.s = gf128_mul(self.s ^ u128::from_le_bytes(block), self.h);
+        }
+    }
+
+    fn finalize(self) -> [u8; 16] {
+        self.s.to_le_bytes()
+    }
+}
+
+/// AES-128-GCM-SIV (RFC 8452), a nonce-misuse resistant AEAD.
+///
+/// Messages are limited to 2^36 bytes. The cipher takes two passes over the
+/// message, the first one to derive the tag it then encrypts with.
+pub struct Aes128GcmSiv {
+    key: SecretBytes<16>,
+}
+
+impl Aes128GcmSiv {
+    /// Derives the per-nonce authentication and encryption keys.
+    fn derive_keys(&self, nonce: &[u8]) -> SgxResult<([u8; 16], [u8; 16])> {
+        let mut keys = [[0_u8; 16]; 2];
+        for (i, half) in keys.iter_mut().flat_map(|key| key.chunks_exact_mut(8)).enumerate() {
+            let mut block = [0_u8; 16];
+            block[..4].copy_from_slice(&(i as u32).to_le_bytes());
+            block[4..].copy_from_slice(nonce);
+            let mut out = aes128_block(self.key.expose_secret(), &block)?;
+            half.copy_from_slice(&out[..8]);
+            zeroize(&mut out);
+        }
+        Ok((keys[0], keys[1]))
+    }
+
+    fn tag(
+        auth_key: &[u8; 16],
+        enc_key: &[u8; 16],
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> SgxResult<Tag> {
+        let mut polyval = Polyval::new(auth_key);
+        polyval.update_padded(aad);
+        polyval.update_padded(plaintext);
+        let mut lengths = [0_u8; 16];
+        lengths[..8].copy_from_slice(&(aad.len() as u64 * 8).to_le_bytes());
+        lengths[8..].copy_from_slice(&(plaintext.len() as u64 * 8).to_le_bytes());
+        polyval.update_padded(&lengths);
+
+        let mut s = polyval.finalize();
+        for (s, n) in s.iter_mut().zip(nonce) {
+            *s ^= n;
+        }
+        s[15] &= 0x7f;
+        aes128_block(enc_key, &s)
+    }
+
+    /// XORs `src` with the keystream of the counter blocks starting at
+    /// `tag` into `dst`.
+    fn ctr(enc_key: &[u8; 16], tag: &Tag, src: &[u8], dst: &mut [u8]) -> SgxError {
+        let mut block = *tag;
+        block[15] |= 0x80;
+        let counter = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        for (i, (src, dst)) in src.chunks(16).zip(dst.chunks_mut(16)).enumerate() {
+            // The counter wraps within its first 32 bits.
+            block[..4].copy_from_slice(&counter.wrapping_add(i as u32).to_le_bytes());
+            let mut keystream = aes128_block(enc_key, &block)?;
+            for ((d, s), k) in dst.iter_mut().zip(src).zip(keystream.iter()) {
+                *d = s ^ k;
+            }
+            zeroize(&mut keystream);
+        }
+        Ok(())
+    }
+
+    fn check_len(src: &[u8], aad: &[u8]) -> SgxError {
+        if src.len() as u64 > 1 << 36 || aad.len() as u64 > 1 << 36 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(())
+    }
+}
+
+impl Aead for Aes128GcmSiv {
+    const KEY_LEN: usize = 16;
+    const NONCE_LEN: usize = 12;
+
+    fn new(key: &[u8]) -> SgxResult<Aes128GcmSiv> {
+        Ok(Aes128GcmSiv { key: Secret::new(copy_key(key)?) })
+    }
+
+    fn encrypt(&self, nonce: &[u8], aad: &[u8], src: &[u8], dst: &mut [u8]) -> SgxResult<Tag> {
+        check_lengths(nonce, Self::NONCE_LEN, src, dst)?;
+        Self::check_len(src, aad)?;
+        let (mut auth_key, mut enc_key) = self.derive_keys(nonce)?;
+        let result = Self::tag(&auth_key, &enc_key, nonce, aad, src).and_then(|tag| {
+            Self::ctr(&enc_key, &tag, src, dst)?;
+            Ok(tag)
+        });
+        zeroize(&mut auth_key);
+        zeroize(&mut enc_key);
+        result
+    }
+
+    fn decrypt(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        src: &[u8],
+        tag: &Tag,
+        dst: &mut [u8],
+    ) -> SgxError {
+        check_lengths(nonce, Self::NONCE_LEN, src, dst)?;
+        Self::check_len(src, aad)?;
+        let dst = &mut dst[..src.len()];
+        let (mut auth_key, mut enc_key) = self.derive_keys(nonce)?;
+        let result = Self::ctr(&enc_key, tag, src, dst)
+            .and_then(|_| Self::tag(&auth_key, &enc_key, nonce, aad, dst));
+        zeroize(&mut auth_key);
+        zeroize(&mut enc_key);
+        match result {
+            Ok(expected) if ct_eq(&expected, tag) => Ok(()),
+            Ok(_) => {
+                zeroize(dst);
+                Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH)
+            }
+            Err(e) => {
+                zeroize(dst);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// The ChaCha20-Poly1305 construction of RFC 8439, over a ChaCha20 subkey
+/// and nonce.
+fn chacha20poly1305_seal(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    src: &[u8],
+    dst: &mut [u8],
+) -> Tag {
+    let mut cipher = ChaCha20::new(key, nonce, 0);
+    let mut poly_key = [0_u8; 32];
+    poly_key.copy_from_slice(&cipher.next_block()[..32]);
+    cipher.apply(src, dst);
+    let tag = chacha20poly1305_tag(&poly_key, aad, &dst[..src.len()]);
+    zeroize(&mut poly_key);
+    tag
+}
+
+fn chacha20poly1305_open(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    src: &[u8],
+    tag: &Tag,
+    dst: &mut [u8],
+) -> SgxError {
+    let mut cipher = ChaCha20::new(key, nonce, 0);
+    let mut poly_key = [0_u8; 32];
+    poly_key.copy_from_slice(&cipher.next_block()[..32]);
+    let expected = chacha20poly1305_tag(&poly_key, aad, src);
+    zeroize(&mut poly_key);
+    if !ct_eq(&expected, tag) {
+        return Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH);
+    }
+    cipher.apply(src, dst);
+    Ok(())
+}
+
+fn chacha20poly1305_tag(poly_key: &[u8; 32], aad: &[u8], ciphertext: &[u8]) -> Tag {
+    let mut mac = Poly1305::new(poly_key);
+    mac.update(aad);
+    mac.pad();
+    mac.update(ciphertext);
+    mac.pad();
+    mac.update(&(aad.len() as u64).to_le_bytes());
+    mac.update(&(ciphertext.len() as u64).to_le_bytes());
+    mac.finalize()
+}
+
+/// ChaCha20 with a 32-bit block counter starting at 1 covers this much.
+const CHACHA_MAX_LEN: u64 = ((1 << 32) - 1) * 64;
+
+/// ChaCha20-Poly1305 (RFC 8439), with 96-bit nonces.
+pub struct ChaCha20Poly1305 {
+    key: SecretBytes<32>,
+}
+
+impl Aead for ChaCha20Poly1305 {
+    const KEY_LEN: usize = 32;
+    const NONCE_LEN: usize = 12;
+
+    fn new(key: &[u8]) -> SgxResult<ChaCha20Poly1305> {
+        Ok(ChaCha20Poly1305 { key: Secret::new(copy_key(key)?) })
+    }
+
+    fn encrypt(&self, nonce: &[u8], aad: &[u8], src: &[u8], dst: &mut [u8]) -> SgxResult<Tag> {
+        check_lengths(nonce, Self::NONCE_LEN, src, dst)?;
+        if src.len() as u64 > CHACHA_MAX_LEN {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let nonce = copy_key(nonce)?;
+        Ok(chacha20poly1305_seal(self.key.expose_secret(), &nonce, aad, src, dst))
+    }
+
+    fn decrypt(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        src: &[u8],
+        tag: &Tag,
+        dst: &mut [u8],
+    ) -> SgxError {
+        check_lengths(nonce, Self::NONCE_LEN, src, dst)?;
+        let nonce = copy_key(nonce)?;
+        chacha20poly1305_open(self.key.expose_secret(), &nonce, aad, src, tag, dst)
+    }
+}
+
+/// XChaCha20-Poly1305, with 192-bit nonces safe to draw at random.
+pub struct XChaCha20Poly1305 {
+    key: SecretBytes<32>,
+}
+
+impl XChaCha20Poly1305 {
+    /// Derives the ChaCha20 subkey and nonce of an extended nonce.
+    fn subkey(&self, nonce: &[u8]) -> ([u8; 32], [u8; 12]) {
+        let mut input = [0_u8; 16];
+        input.copy_from_slice(&nonce[..16]);
+        let subkey = hchacha20(self.key.expose_secret(), &input);
+        let mut chacha_nonce = [0_u8; 12];
+        chacha_nonce[4..].copy_from_slice(&nonce[16..]);
+        (subkey, chacha_nonce)
+    }
+}
+
+impl Aead for XChaCha20Poly1305 {
+    const KEY_LEN: usize = 32;
+    const NONCE_LEN: usize = 24;
+
+    fn new(key: &[u8]) -> SgxResult<XChaCha20Poly1305> {
+        Ok(XChaCha20Poly1305 { key: Secret::new(copy_key(key)?) })
+    }
+
+    fn encrypt(&self, nonce: &[u8], aad: &[u8], src: &[u8], dst: &mut [u8]) -> SgxResult<Tag> {
+        check_lengths(nonce, Self::NONCE_LEN, src, dst)?;
+        if src.len() as u64 > CHACHA_MAX_LEN {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let (mut subkey, nonce) = self.subkey(nonce);
+        let tag = chacha20poly1305_seal(&subkey, &nonce, aad, src, dst);
+        zeroize(&mut subkey);
+        Ok(tag)
+    }
+
+    fn decrypt(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        src: &[u8],
+        tag: &Tag,
+        dst: &mut [u8],
+    ) -> SgxError {
+        check_lengths(nonce, Self::NONCE_LEN, src, dst)?;
+        let (mut subkey, nonce) = self.subkey(nonce);
+        let result = chacha20poly1305_open(&subkey, &nonce, aad, src, tag, dst);
+        zeroize(&mut subkey);
+        result
+    }
+}
+
+/// The bytes a stream nonce takes past its prefix: a 32-bit chunk counter
+/// and the last chunk flag.
+const STREAM_NONCE_SUFFIX: usize = 5;
+
+/// The longest nonce prefix, that of 24-byte nonces.
+const MAX_STREAM_PREFIX: usize = 24 - STREAM_NONCE_SUFFIX;
+
+/// A message encrypted or decrypted in chunks, with the STREAM construction
+/// (Hoang, Reyhanitabar, Rogaway and Vizár, 2015).
+///
+/// Each chunk is sealed with its own tag, under a nonce made of a per-stream
+/// prefix, the big-endian index of the chunk and a flag set for the last
+/// chunk only. Chunks therefore can't be reordered, dropped or moved to
+/// another stream, and a stream cut short fails to decrypt as the last chunk
+/// is missing. Large messages go through in chunks of bounded size, without
+/// ever being held whole in the enclave.
+///
+/// A stream either encrypts or decrypts; the nonce prefix, `NONCE_LEN - 5`
+/// bytes, must never be used for two streams with the same key. With
+/// [`XChaCha20Poly1305`] it can be drawn at random.
+pub struct AeadStream<A: Aead> {
+    aead: A,
+    prefix: [u8; MAX_STREAM_PREFIX],
+    counter: u32,
+    finished: bool,
+}
+
+impl<A: Aead> AeadStream<A> {
+    /// Starts a stream with `aead` and `nonce_prefix`.
+    pub fn new(aead: A, nonce_prefix: &[u8]) -> SgxResult<AeadStream<A>> {
+        if A::NONCE_LEN > 24
+            || A::NONCE_LEN < STREAM_NONCE_SUFFIX
+            || nonce_prefix.len() != A::NONCE_LEN - STREAM_NONCE_SUFFIX
+        {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut prefix = [0_u8; MAX_STREAM_PREFIX];
+        prefix[..nonce_prefix.len()].copy_from_slice(nonce_prefix);
+        Ok(AeadStream { aead, prefix, counter: 0, finished: false })
+    }
+
+    /// Returns the length of the nonce prefix for `A`.
+    pub fn nonce_prefix_len() -> usize {
+        A::NONCE_LEN.saturating_sub(STREAM_NONCE_SUFFIX)
+    }
+
+    /// Returns the number of chunks processed so far.
+    pub fn chunks(&self) -> u32 {
+        self.counter
+    }
+
+    /// Builds the nonce of the next chunk, failing once the stream is
+    /// finished or its counter exhausted.
+    fn next_nonce(&mut self, last: bool) -> SgxResult<([u8; 24], usize)> {
+        if self.finished {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+        }
+        let prefix_len = A::NONCE_LEN - STREAM_NONCE_SUFFIX;
+        let mut nonce = [0_u8; 24];
+        nonce[..prefix_len].copy_from_slice(&self.prefix[..prefix_len]);
+        nonce[prefix_len..prefix_len + 4].copy_from_slice(&self.counter.to_be_bytes());
+        nonce[prefix_len + 4] = last as u8;
+        self.counter = match self.counter.checked_add(1) {
+            Some(counter) => counter,
+            // Only the last chunk may take the final index.
+            None if last => self.counter,
+            None => return Err(sgx_status_t::SGX_ERROR_INVALID_STATE),
+        };
+        self.finished = last;
+        Ok((nonce, A::NONCE_LEN))
+    }
+
+    /// Encrypts the next chunk, which isn't the last one.
+    pub fn encrypt_chunk(&mut self, aad: &[u8], src: &[u8], dst: &mut [u8]) -> SgxResult<Tag> {
+        let (nonce, len) = self.next_nonce(false)?;
+        self.aead.encrypt(&nonce[..len], aad, src, dst)
+    }
+
+    /// Encrypts the last chunk, finishing the stream.
+    pub fn encrypt_last(&mut self, aad: &[u8], src: &[u8], dst: &mut [u8]) -> SgxResult<Tag> {
+        let (nonce, len) = self.next_nonce(true)?;
+        self.aead.encrypt(&nonce[..len], aad, src, dst)
+    }
+
+    /// Decrypts the next chunk, which isn't the last one.
+    ///
+    /// A chunk failing to decrypt finishes the stream, so that no later
+    /// chunk is released either.
+    pub fn decrypt_chunk(
+        &mut self,
+        aad: &[u8],
+        src: &[u8],
+        tag: &Tag,
+        dst: &mut [u8],
+    ) -> SgxError {
+        let (nonce, len) = self.next_nonce(false)?;
+        let result = self.aead.decrypt(&nonce[..len], aad, src, tag, dst);
+        self.finished |= result.is_err();
+        result
+    }
+
+    /// Decrypts the last chunk, finishing the stream.
+    ///
+    /// Until this succeeds, the message may have been truncated.
+    pub fn decrypt_last(&mut self, aad: &[u8], src: &[u8], tag: &Tag, dst: &mut [u8]) -> SgxError {
+        let (nonce, len) = self.next_nonce(true)?;
+        self.aead.decrypt(&nonce[..len], aad, src, tag, dst)
+    }
+
+    /// Returns whether the last chunk has been processed, or a chunk failed
+    /// to decrypt.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+    use std::vec;
+    use std::vec::Vec;
+
+    /// The plaintext of the examples of RFC 8439 and of the XChaCha20 draft.
+    const SUNSCREEN: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+        only one tip for the future, sunscreen would be it.";
+
+    fn check<A: Aead>(key: &str, nonce: &str, aad: &str, plaintext: &[u8], ciphertext: &str, tag: &str) {
+        let (key, nonce, aad) = (hex(key), hex(nonce), hex(aad));
+        let aead = A::new(&key).unwrap();
+        let mut sealed = vec![0_u8; plaintext.len()];
+        let sealed_tag = aead.encrypt(&nonce, &aad, plaintext, &mut sealed).unwrap();
+        assert_eq!(sealed, hex(ciphertext));
+        assert_eq!(sealed_tag[..], hex(tag)[..]);
+
+        let mut opened = vec![0_u8; plaintext.len()];
+        aead.decrypt(&nonce, &aad, &sealed, &sealed_tag, &mut opened).unwrap();
+        assert_eq!(opened, plaintext);
+
+        let mut bad_tag = sealed_tag;
+        bad_tag[0] ^= 1;
+        assert_eq!(
+            aead.decrypt(&nonce, &aad, &sealed, &bad_tag, &mut opened),
+            Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH)
+        );
+        let mut bad_aad = aad.clone();
+        bad_aad.push(0);
+        assert!(aead.decrypt(&nonce, &bad_aad, &sealed, &sealed_tag, &mut opened).is_err());
+        if !sealed.is_empty() {
+            sealed[0] ^= 1;
+            assert!(aead.decrypt(&nonce, &aad, &sealed, &sealed_tag, &mut opened).is_err());
+        }
+    }
+
+    #[test]
+    fn aes128_gcm() {
+        // Test case 2 of the GCM specification.
+        check::<Aes128Gcm>(
+            "00000000000000000000000000000000",
+            "000000000000000000000000",
+            "",
+            &[0; 16],
+            "0388dace60b6a392f328c2b971b2fe78",
+            "ab6e47d42cec13bdf53a67b21257bddf",
+        );
+    }
+
+    #[test]
+    fn aes128_gcm_siv() {
+        // RFC 8452, appendix C.1.
+        check::<Aes128GcmSiv>(
+            "01000000000000000000000000000000",
+            "030000000000000000000000",
+            "",
+            &[],
+            "",
+            "dc20e2d83f25705bb49e439eca56de25",
+        );
+        check::<Aes128GcmSiv>(
+            "01000000000000000000000000000000",
+            "030000000000000000000000",
+            "",
+            &hex("0100000000000000"),
+            "b5d839330ac7b786",
+            "578782fff6013b815b287c22493a364c",
+        );
+    }
+
+    #[test]
+    fn chacha20_poly1305() {
+        // RFC 8439, section 2.8.2.
+        check::<ChaCha20Poly1305>(
+            "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f",
+            "070000004041424344454647",
+            "50515253c0c1c2c3c4c5c6c7",
+            SUNSCREEN,
+            "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d63dbea45e8ca9671282fafb69da92728b\
+             1a71de0a9e060b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+             3ff4def08e4b7a9de576d26586cec64b6116",
+            "1ae10b594f09e26a7e902ecbd0600691",
+        );
+    }
+
+    #[test]
+    fn xchacha20_poly1305() {
+        // draft-irtf-cfrg-xchacha-03, appendix A.3.1.
+        check::<XChaCha20Poly1305>(
+            "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f",
+            "404142434445464748494a4b4c4d4e4f5051525354555657",
+            "50515253c0c1c2c3c4c5c6c7",
+            SUNSCREEN,
+            "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb731c7f1b0b4aa6440bf3a82f4eda7e39\
+             ae64c6708c54c216cb96b72e1213b4522f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff9\
+             21f9664c97637da9768812f615c68b13b52e",
+            "c0875924c1c7987947deafd8780acf49",
+        );
+    }
+
+    type Sealed = Vec<(Vec<u8>, Tag)>;
+
+    /// Seals four chunks of 100 bytes with a stream, the last one flagged.
+    fn seal_stream() -> (Vec<Vec<u8>>, Sealed) {
+        let chunks: Vec<Vec<u8>> = (0..4).map(|i| vec![i as u8; 100]).collect();
+        let mut stream = AeadStream::new(XChaCha20Poly1305::new(&[7; 32]).unwrap(), &[1; 19]).unwrap();
+        let mut sealed = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut dst = vec![0_u8; chunk.len()];
+            let tag = if i == chunks.len() - 1 {
+                stream.encrypt_last(b"", chunk, &mut dst)
+            } else {
+                stream.encrypt_chunk(b"", chunk, &mut dst)
+            };
+            sealed.push((dst, tag.unwrap()));
+        }
+        assert!(stream.is_finished());
+        assert_eq!(stream.encrypt_chunk(b"", b"x", &mut [0]), Err(sgx_status_t::SGX_ERROR_INVALID_STATE));
+        (chunks, sealed)
+    }
+
+    fn open_stream() -> AeadStream<XChaCha20Poly1305> {
+        AeadStream::new(XChaCha20Poly1305::new(&[7; 32]).unwrap(), &[1; 19]).unwrap()
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        let (chunks, sealed) = seal_stream();
+        let mut stream = open_stream();
+        for (i, (chunk, tag)) in sealed.iter().enumerate() {
+            let mut dst = vec![0_u8; chunk.len()];
+            if i == sealed.len() - 1 {
+                stream.decrypt_last(b"", chunk, tag, &mut dst).unwrap();
+            } else {
+                stream.decrypt_chunk(b"", chunk, tag, &mut dst).unwrap();
+            }
+            assert_eq!(dst, chunks[i]);
+        }
+        assert!(stream.is_finished());
+        assert_eq!(stream.chunks(), 4);
+        assert!(AeadStream::new(XChaCha20Poly1305::new(&[7; 32]).unwrap(), &[1; 12]).is_err());
+    }
+
+    #[test]
+    fn stream_rejections() {
+        let (_, sealed) = seal_stream();
+        let mut dst = [0_u8; 100];
+        // Reordered chunks.
+        let mut stream = open_stream();
+        assert!(stream.decrypt_chunk(b"", &sealed[1].0, &sealed[1].1, &mut dst).is_err());
+        assert!(stream.is_finished());
+        assert_eq!(
+            stream.decrypt_chunk(b"", &sealed[0].0, &sealed[0].1, &mut dst),
+            Err(sgx_status_t::SGX_ERROR_INVALID_STATE)
+        );
+        // A dropped chunk.
+        let mut stream = open_stream();
+        stream.decrypt_chunk(b"", &sealed[0].0, &sealed[0].1, &mut dst).unwrap();
+        assert!(stream.decrypt_chunk(b"", &sealed[2].0, &sealed[2].1, &mut dst).is_err());
+        // Cut short, with a chunk passed off as the last, or the last chunk truncated.
+        let mut stream = open_stream();
+        stream.decrypt_chunk(b"", &sealed[0].0, &sealed[0].1, &mut dst).unwrap();
+        stream.decrypt_chunk(b"", &sealed[1].0, &sealed[1].1, &mut dst).unwrap();
+        assert!(stream.decrypt_last(b"", &sealed[2].0, &sealed[2].1, &mut dst).is_err());
+        let mut stream = open_stream();
+        for (chunk, tag) in &sealed[..3] {
+            stream.decrypt_chunk(b"", chunk, tag, &mut dst).unwrap();
+        }
+        assert!(stream.decrypt_last(b"", &sealed[3].0[..99], &sealed[3].1, &mut dst[..99]).is_err());
+        // A flipped tag.
+        let mut stream = open_stream();
+        let mut tag = sealed[0].1;
+        tag[15] ^= 0x80;
+        assert_eq!(
+            stream.decrypt_chunk(b"", &sealed[0].0, &tag, &mut dst),
+            Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH)
+        );
+        assert!(stream.is_finished());
+    }
+
+    #[test]
+    fn rejects_bad_lengths() {
+        assert!(ChaCha20Poly1305::new(&[0; 16]).is_err());
+        let aead = ChaCha20Poly1305::new(&[0; 32]).unwrap();
+        let mut dst = [0_u8; 4];
+        assert!(aead.encrypt(&[0; 8], &[], &[0; 4], &mut dst).is_err());
+        assert!(aead.encrypt(&[0; 12], &[], &[0; 8], &mut dst).is_err());
+    }
+}
@@ -0,0 +1,667 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! AES-XTS and AES-CTR with 128- and 256-bit keys, on the AES-NI
+//! instructions.
+//!
+//! XTS (IEEE 1619) encrypts sector-addressed storage, such as the enclave
+//! pages paged out to the host, in place under the number of each sector.
+//! It doesn't authenticate: the host can still replay an old sector or turn
+//! one into random plaintext, so paging has to MAC the sectors as well.
+//!
+//! CTR starts from a counter block of which the caller chooses how many low
+//! bits count, the others being a nonce. The caller owns that block and
+//! moves it as it likes, but a cipher refuses to wrap it around into
+//! keystream it has already produced.
+//!
+//! The Intel library only encrypts AES-128 blocks, through CTR, so the
+//! rounds run on the instructions directly, eight blocks at a time. Enclaves
+//! can't run CPUID: as for [`hash`](crate::hash), the feature mask of the
+//! trusted runtime tells whether AES-NI is there, and without it ciphers
+//! fail to be created with `SGX_ERROR_FEATURE_NOT_SUPPORTED`.
+
+use crate::provider::cpu_features;
+use crate::util::{ct_eq, zeroize};
+use sgx_types::cpu_feature::CPU_FEATURE_AES;
+use sgx_types::*;
+
+pub const AES_BLOCK_SIZE: usize = 16;
+
+/// The longest sector, the 2^20 blocks IEEE 1619 allows under one tweak.
+pub const XTS_MAX_SECTOR_SIZE: usize = AES_BLOCK_SIZE << 20;
+
+/// The blocks encrypted at once, to keep the AES units busy.
+const LANES: usize = 8;
+
+type Block = [u8; AES_BLOCK_SIZE];
+
+fn check_aes_ni() -> SgxError {
+    if cpu_features() & CPU_FEATURE_AES != 0 {
+        return Ok(());
+    }
+    Err(sgx_status_t::SGX_ERROR_FEATURE_NOT_SUPPORTED)
+}
+
+/// The round keys of a key, for encryption and for decryption.
+struct KeySchedule {
+    rounds: usize,
+    encrypt: [Block; 15],
+    decrypt: [Block; 15],
+}
+
+impl KeySchedule {
+    fn new(key: &[u8]) -> SgxResult<KeySchedule> {
+        let rounds = match key.len() {
+            16 => 10,
+            32 => 14,
+            _ => return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER),
+        };
+        check_aes_ni()?;
+        let mut schedule = KeySchedule { rounds, encrypt: [[0; 16]; 15], decrypt: [[0; 16]; 15] };
+        // Safety: the runtime reports AES-NI.
+        unsafe { ni::expand(key, &mut schedule) };
+        Ok(schedule)
+    }
+
+    fn encrypt(&self, blocks: &mut [Block]) {
+        // Safety: a schedule is only created when the runtime reports AES-NI.
+        unsafe { ni::encrypt(self, blocks) }
+    }
+
+    fn decrypt(&self, blocks: &mut [Block]) {
+        // Safety: as for encrypt().
+        unsafe { ni::decrypt(self, blocks) }
+    }
+}
+
+impl Drop for KeySchedule {
+    fn drop(&mut self) {
+        for key in self.encrypt.iter_mut().chain(self.decrypt.iter_mut()) {
+            zeroize(key);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod ni {
+    //! The key expansion and the rounds, on the AES-NI instructions.
+
+    use super::{Block, KeySchedule, LANES};
+    use core::arch::x86_64::*;
+
+    #[inline(always)]
+    unsafe fn load(bytes: &[u8]) -> __m128i {
+        debug_assert!(bytes.len() >= 16);
+        _mm_loadu_si128(bytes.as_ptr() as *const __m128i)
+    }
+
+    #[inline(always)]
+    unsafe fn store(x: __m128i, block: &mut Block) {
+        _mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, x)
+    }
+
+    #[inline(always)]
+    unsafe fn wipe(xs: &mut [__m128i]) {
+        for x in xs.iter_mut() {
+            core::ptr::write_volatile(x, _mm_setzero_si128());
+        }
+    }
+
+    /// XORs into each word of `key` the words before it.
+    #[inline(always)]
+    unsafe fn spread(key: __m128i) -> __m128i {
+        let one = _mm_slli_si128::<4>(key);
+        let two = _mm_slli_si128::<4>(one);
+        let three = _mm_slli_si128::<4>(two);
+        _mm_xor_si128(_mm_xor_si128(key, one), _mm_xor_si128(two, three))
+    }
+
+    /// Returns the round key after `prev` of an AES-128 key.
+    #[inline(always)]
+    unsafe fn next_128<const RCON: i32>(prev: __m128i) -> __m128i {
+        let t = _mm_shuffle_epi32::<0xff>(_mm_aeskeygenassist_si128::<RCON>(prev));
+        _mm_xor_si128(spread(prev), t)
+    }
+
+    /// Returns the round key after `prev2` and `prev` of an AES-256 key,
+    /// for the even round keys, with RotWord and the round constant.
+    #[inline(always)]
+    unsafe fn next_256_even<const RCON: i32>(prev2: __m128i, prev: __m128i) -> __m128i {
+        let t = _mm_shuffle_epi32::<0xff>(_mm_aeskeygenassist_si128::<RCON>(prev));
+        _mm_xor_si128(spread(prev2), t)
+    }
+
+    /// As next_256_even(), for the odd round keys, with SubWord only.
+    #[inline(always)]
+    unsafe fn next_256_odd(prev2: __m128i, prev: __m128i) -> __m128i {
+        let t = _mm_shuffle_epi32::<0xaa>(_mm_aeskeygenassist_si128::<0>(prev));
+        _mm_xor_si128(spread(prev2), t)
+    }
+
+    #[target_feature(enable = "aes")]
+    pub(super) unsafe fn expand(key: &[u8], schedule: &mut KeySchedule) {
+        let mut k = [_mm_setzero_si128(); 15];
+        k[0] = load(key);
+        if key.len() == 16 {
+            k[1] = next_128::<0x01>(k[0]);
+            k[2] = next_128::<0x02>(k[1]);
+            k[3] = next_128::<0x04>(k[2]);
+            k[4] = next_128::<0x08>(k[3]);
+            k[5] = next_128::<0x10>(k[4]);
+            k[6] = next_128::<0x20>(k[5]);
+            k[7] = next_128::<0x40>(k[6]);
+            k[8] = next_128::<0x80>(k[7]);
+            k[9] = next_128::<0x1b>(k[8]);
+            k[10] = next_128::<0x36>(k[9]);
+        } else {
+            k[1] = load(&key[16..]);
+            k[2] = next_256_even::<0x01>(k[0], k[1]);
+            k[3] = next_256_odd(k[1], k[2]);
+            k[4] = next_256_even::<0x02>(k[2], k[3]);
+            k[5] = next_256_odd(k[3], k[4]);
+            k[6] = next_256_even::<0x04>(k[4], k[5]);
+            k[7] = next_256_odd(k[5], k[6]);
+            k[8] = next_256_even::<0x08>(k[6], k[7]);
+            k[9] = next_256_odd(k[7], k[8]);
+            k[10] = next_256_even::<0x10>(k[8], k[9]);
+            k[11] = next_256_odd(k[9], k[10]);
+            k[12] = next_256_even::<0x20>(k[10], k[11]);
+            k[13] = next_256_odd(k[11], k[12]);
+            k[14] = next_256_even::<0x40>(k[12], k[13]);
+        }
+        let rounds = schedule.rounds;
+        for i in 0..=rounds {
+            store(k[i], &mut schedule.encrypt[i]);
+            // The equivalent inverse cipher runs the round keys backwards,
+            // through InvMixColumns but for the first and the last.
+            let inverse = match i {
+                0 => k[rounds],
+                i if i == rounds => k[0],
+                i => _mm_aesimc_si128(k[rounds - i]),
+            };
+            store(inverse, &mut schedule.decrypt[i]);
+        }
+        wipe(&mut k);
+    }
+
+    #[target_feature(enable = "aes")]
+    pub(super) unsafe fn encrypt(schedule: &KeySchedule, blocks: &mut [Block]) {
+        let keys = &schedule.encrypt[..=schedule.rounds];
+        let mut state = [_mm_setzero_si128(); LANES];
+        for chunk in blocks.chunks_mut(LANES) {
+            let first = load(&keys[0]);
+            for (s, block) in state.iter_mut().zip(chunk.iter()) {
+                *s = _mm_xor_si128(load(block), first);
+            }
+            for key in &keys[1..schedule.rounds] {
+                let key = load(key);
+                for s in state.iter_mut() {
+                    *s = _mm_aesenc_si128(*s, key);
+                }
+            }
+            let last = load(&keys[schedule.rounds]);
+            for (s, block) in state.iter().zip(chunk.iter_mut()) {
+                store(_mm_aesenclast_si128(*s, last), block);
+            }
+        }
+        wipe(&mut state);
+    }
+
+    #[target_feature(enable = "aes")]
+    pub(super) unsafe fn decrypt(schedule: &KeySchedule, blocks: &mut [Block]) {
+        let keys = &schedule.decrypt[..=schedule.rounds];
+        let mut state = [_mm_setzero_si128(); LANES];
+        for chunk in blocks.chunks_mut(LANES) {
+            let first = load(&keys[0]);
+            for (s, block) in state.iter_mut().zip(chunk.iter()) {
+                *s = _mm_xor_si128(load(block), first);
+            }
+            for key in &keys[1..schedule.rounds] {
+                let key = load(key);
+                for s in state.iter_mut() {
+                    *s = _mm_aesdec_si128(*s, key);
+                }
+            }
+            let last = load(&keys[schedule.rounds]);
+            for (s, block) in state.iter().zip(chunk.iter_mut()) {
+                store(_mm_aesdeclast_si128(*s, last), block);
+            }
+        }
+        wipe(&mut state);
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod ni {
+    //! No schedule is ever created without AES-NI.
+
+    use super::{Block, KeySchedule};
+
+    pub(super) unsafe fn expand(_: &[u8], _: &mut KeySchedule) {
+        unreachable!()
+    }
+
+    pub(super) unsafe fn encrypt(_: &KeySchedule, _: &mut [Block]) {
+        unreachable!()
+    }
+
+    pub(super) unsafe fn decrypt(_: &KeySchedule, _: &mut [Block]) {
+        unreachable!()
+    }
+}
+
+fn xor_block(block: &mut Block, other: &Block) {
+    for (b, o) in block.iter_mut().zip(other.iter()) {
+        *b ^= o;
+    }
+}
+
+/// Encrypts `block` with AES under a 16- or 32-byte `key`.
+pub(crate) fn encrypt_block(key: &[u8], block: &mut Block) -> SgxError {
+    KeySchedule::new(key)?.encrypt(core::slice::from_mut(block));
+    Ok(())
+}
+
+/// AES in counter mode, encrypting and decrypting alike.
+///
+/// The keystream is the encryption of successive counter blocks, big-endian
+/// as in the Intel library's `sgx_aes_ctr_encrypt`. The same counter block
+/// must never be used twice with a key.
+pub struct AesCtr {
+    schedule: KeySchedule,
+    /// The counter block of the next keystream block.
+    counter: u128,
+    /// The bits of the counter which count.
+    mask: u128,
+    /// Whether the counter wrapped after its last block.
+    exhausted: bool,
+    /// The keystream block of which the bytes from `used` are left over.
+    keystream: Block,
+    used: usize,
+}
+
+impl AesCtr {
+    /// Creates a cipher with a 16- or 32-byte `key`, starting at the counter
+    /// block `counter`, of which the low `counter_bits` bits, 1 to 128, count.
+    pub fn new(key: &[u8], counter: &Block, counter_bits: u32) -> SgxResult<AesCtr> {
+        if counter_bits == 0 || counter_bits > 128 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(AesCtr {
+            schedule: KeySchedule::new(key)?,
+            counter: u128::from_be_bytes(*counter),
+            mask: u128::MAX >> (128 - counter_bits),
+            exhausted: false,
+            keystream: [0; AES_BLOCK_SIZE],
+            used: AES_BLOCK_SIZE,
+        })
+    }
+
+    /// Returns the counter block of the next whole keystream block, so past
+    /// a block the last call only used part of.
+    pub fn counter(&self) -> Block {
+        self.counter.to_be_bytes()
+    }
+
+    /// Moves to the start of the keystream block of `counter`, e.g. the
+    /// starting block plus `n` to seek to the `n`th block of a message.
+    pub fn set_counter(&mut self, counter: &Block) {
+        self.counter = u128::from_be_bytes(*counter);
+        self.exhausted = false;
+        zeroize(&mut self.keystream);
+        self.used = AES_BLOCK_SIZE;
+    }
+
+    /// Returns the counter block of the next keystream block and moves past
+    /// it, the nonce bits staying as they are.
+    fn next_counter(&mut self) -> Block {
+        let block = self.counter.to_be_bytes();
+        let count = self.counter & self.mask;
+        self.exhausted = count == self.mask;
+        self.counter = (self.counter & !self.mask) | (count.wrapping_add(1) & self.mask);
+        block
+    }
+
+    /// XORs the next `data.len()` bytes of keystream into `data`.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_STATE`, leaving `data` as it is, if the
+    /// counter would have to wrap around.
+    pub fn apply_keystream(&mut self, data: &mut [u8]) -> SgxError {
+        let left_over = AES_BLOCK_SIZE - self.used;
+        let blocks = data.len().saturating_sub(left_over).div_ceil(AES_BLOCK_SIZE);
+        if blocks > 0
+            && (self.exhausted || blocks as u128 - 1 > self.mask - (self.counter & self.mask))
+        {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+        }
+
+        let (head, rest) = data.split_at_mut(left_over.min(data.len()));
+        for (d, k) in head.iter_mut().zip(self.keystream[self.used..].iter()) {
+            *d ^= k;
+        }
+        self.used += head.len();
+
+        let mut batch = [[0_u8; AES_BLOCK_SIZE]; LANES];
+        for chunk in rest.chunks_mut(LANES * AES_BLOCK_SIZE) {
+            let n = chunk.len().div_ceil(AES_BLOCK_SIZE);
+            for block in batch[..n].iter_mut() {
+                *block = self.next_counter();
+            }
+            self.schedule.encrypt(&mut batch[..n]);
+            for (d, k) in chunk.iter_mut().zip(batch.iter().flatten()) {
+                *d ^= k;
+            }
+            let partial = chunk.len() % AES_BLOCK_SIZE;
+            if partial != 0 {
+                self.keystream = batch[n - 1];
+                self.used = partial;
+            }
+        }
+        for block in batch.iter_mut() {
+            zeroize(block);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AesCtr {
+    fn drop(&mut self) {
+        zeroize(&mut self.keystream);
+    }
+}
+
+/// Multiplies a tweak by the primitive element of GF(2^128), in IEEE
+/// 1619's little-endian convention.
+fn double_tweak(tweak: u128) -> u128 {
+    (tweak << 1) ^ ((tweak >> 127) * 0x87)
+}
+
+/// XTS-AES, encrypting sectors in place.
+///
+/// A sector is 16 bytes to `XTS_MAX_SECTOR_SIZE`; one which isn't a whole
+/// number of blocks is encrypted with ciphertext stealing. The tweak is the
+/// sector number, little-endian as IEEE 1619 has it, so the same sector
+/// number must only ever address one sector of a key.
+pub struct AesXts {
+    data: KeySchedule,
+    tweak: KeySchedule,
+}
+
+impl AesXts {
+    /// Creates a cipher with `key`, the data key followed by the tweak key:
+    /// 32 bytes for XTS-AES-128 and 64 for XTS-AES-256. The two keys must
+    /// differ.
+    pub fn new(key: &[u8]) -> SgxResult<AesXts> {
+        if key.len() != 32 && key.len() != 64 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let (data, tweak) = key.split_at(key.len() / 2);
+        if ct_eq(data, tweak) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(AesXts { data: KeySchedule::new(data)?, tweak: KeySchedule::new(tweak)? })
+    }
+
+    /// Encrypts the sector `sector` in place.
+    pub fn encrypt_sector(&self, sector: u64, data: &mut [u8]) -> SgxError {
+        self.crypt_sector(sector, data, false)
+    }
+
+    /// Decrypts the sector `sector` in place.
+    pub fn decrypt_sector(&self, sector: u64, data: &mut [u8]) -> SgxError {
+        self.crypt_sector(sector, data, true)
+    }
+
+    fn crypt_blocks(&self, blocks: &mut [Block], decrypt: bool) {
+        match decrypt {
+            false => self.data.encrypt(blocks),
+            true => self.data.decrypt(blocks),
+        }
+    }
+
+    /// Encrypts or decrypts `block` under `tweak`.
+    fn crypt_block(&self, block: &mut [u8], tweak: u128, decrypt: bool) {
+        let tweak = tweak.to_le_bytes();
+        let mut blocks = [[0_u8; AES_BLOCK_SIZE]];
+        blocks[0].copy_from_slice(block);
+        xor_block(&mut blocks[0], &tweak);
+        self.crypt_blocks(&mut blocks, decrypt);
+        xor_block(&mut blocks[0], &tweak);
+        block.copy_from_slice(&blocks[0]);
+        zeroize(&mut blocks[0]);
+    }
+
+    fn crypt_sector(&self, sector: u64, data: &mut [u8], decrypt: bool) -> SgxError {
+        if data.len() < AES_BLOCK_SIZE || data.len() > XTS_MAX_SECTOR_SIZE {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut first = [(sector as u128).to_le_bytes()];
+        self.tweak.encrypt(&mut first);
+        let mut tweak = u128::from_le_bytes(first[0]);
+        zeroize(&mut first[0]);
+
+        // With ciphertext stealing, the last whole block and the partial one
+        // are left for the end.
+        let partial = data.len() % AES_BLOCK_SIZE;
+        let whole = match partial {
+            0 => data.len(),
+            _ => data.len() - AES_BLOCK_SIZE - partial,
+        };
+        let (body, tail) = data.split_at_mut(whole);
+        let mut batch = [[0_u8; AES_BLOCK_SIZE]; LANES];
+        let mut tweaks = [[0_u8; AES_BLOCK_SIZE]; LANES];
+        for chunk in body.chunks_mut(LANES * AES_BLOCK_SIZE) {
+            let n = chunk.len() / AES_BLOCK_SIZE;
+            for ((block, t), src) in batch.iter_mut().zip(tweaks.iter_mut()).zip(chunk.chunks(16)) {
+                *t = tweak.to_le_bytes();
+                tweak = double_tweak(tweak);
+                block.copy_from_slice(src);
+                xor_block(block, t);
+            }
+            self.crypt_blocks(&mut batch[..n], decrypt);
+            for ((block, t), dst) in batch.iter_mut().zip(tweaks.iter()).zip(chunk.chunks_mut(16)) {
+                xor_block(block, t);
+                dst.copy_from_slice(block);
+            }
+        }
+        for block in batch.iter_mut().chain(tweaks.iter_mut()) {
+            zeroize(block);
+        }
+
+        if partial != 0 {
+            let (last, stolen) = tail.split_at_mut(AES_BLOCK_SIZE);
+            let next = double_tweak(tweak);
+            // The last whole block was encrypted after the partial one was
+            // put into it, so decryption takes the tweaks the other way around.
+            let (before, after) = match decrypt {
+                false => (tweak, next),
+                true => (next, tweak),
+            };
+            self.crypt_block(last, before, decrypt);
+            let mut joined = [0_u8; AES_BLOCK_SIZE];
+            joined[..partial].copy_from_slice(stolen);
+            joined[partial..].copy_from_slice(&last[partial..]);
+            stolen.copy_from_slice(&last[..partial]);
+            self.crypt_block(&mut joined, after, decrypt);
+            last.copy_from_slice(&joined);
+            zeroize(&mut joined);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+    use std::sync::Once;
+    use std::vec::Vec;
+
+    extern "C" {
+        static mut g_cpu_feature_indicator: u64;
+    }
+
+    /// Reports AES-NI to [`cpu_features`], as the runtime does on a CPU with
+    /// it, or returns false if this CPU hasn't.
+    fn aes_ni() -> bool {
+        static REPORT: Once = Once::new();
+        if !std::is_x86_feature_detected!("aes") {
+            return false;
+        }
+        REPORT.call_once(|| unsafe { g_cpu_feature_indicator |= CPU_FEATURE_AES });
+        true
+    }
+
+    /// The ciphertexts of IEEE 1619 vectors 4 and 10, of the bytes 0 to 255
+    /// twice.
+    const VECTOR_4: &str = "\
+        27a7479befa1d476489f308cd4cfa6e2a96e4bbe3208ff25287dd3819616e89c\
+        c78cf7f5e543445f8333d8fa7f56000005279fa5d8b5e4ad40e736ddb4d35412\
+        328063fd2aab53e5ea1e0a9f332500a5df9487d07a5c92cc512c8866c7e860ce\
+        93fdf166a24912b422976146ae20ce846bb7dc9ba94a767aaef20c0d61ad0265\
+        5ea92dc4c4e41a8952c651d33174be51a10c421110e6d81588ede82103a252d8\
+        a750e8768defffed9122810aaeb99f9172af82b604dc4b8e51bcb08235a6f434\
+        1332e4ca60482a4ba1a03b3e65008fc5da76b70bf1690db4eae29c5f1badd03c\
+        5ccf2a55d705ddcd86d449511ceb7ec30bf12b1fa35b913f9f747a8afd1b130e\
+        94bff94effd01a91735ca1726acd0b197c4e5b03393697e126826fb6bbde8ecc\
+        1e08298516e2c9ed03ff3c1b7860f6de76d4cecd94c8119855ef5297ca67e9f3\
+        e7ff72b1e99785ca0a7e7720c5b36dc6d72cac9574c8cbbc2f801e23e56fd344\
+        b07f22154beba0f08ce8891e643ed995c94d9a69c9f1b5f499027a78572aeebd\
+        74d20cc39881c213ee770b1010e4bea718846977ae119f7a023ab58cca0ad752\
+        afe656bb3c17256a9f6e9bf19fdd5a38fc82bbe872c5539edb609ef4f79c203e\
+        bb140f2e583cb2ad15b4aa5b655016a8449277dbd477ef2c8d6c017db738b18d\
+        eb4a427d1923ce3ff262735779a418f20a282df920147beabe421ee5319d0568";
+
+    const VECTOR_10: &str = "\
+        1c3b3a102f770386e4836c99e370cf9bea00803f5e482357a4ae12d414a3e63b\
+        5d31e276f8fe4a8d66b317f9ac683f44680a86ac35adfc3345befecb4bb188fd\
+        5776926c49a3095eb108fd1098baec70aaa66999a72a82f27d848b21d4a741b0\
+        c5cd4d5fff9dac89aeba122961d03a757123e9870f8acf1000020887891429ca\
+        2a3e7a7d7df7b10355165c8b9a6d0a7de8b062c4500dc4cd120c0f7418dae3d0\
+        b5781c34803fa75421c790dfe1de1834f280d7667b327f6c8cd7557e12ac3a0f\
+        93ec05c52e0493ef31a12d3d9260f79a289d6a379bc70c50841473d1a8cc81ec\
+        583e9645e07b8d9670655ba5bbcfecc6dc3966380ad8fecb17b6ba02469a020a\
+        84e18e8f84252070c13e9f1f289be54fbc481457778f616015e1327a02b140f1\
+        505eb309326d68378f8374595c849d84f4c333ec4423885143cb47bd71c5edae\
+        9be69a2ffeceb1bec9de244fbe15992b11b77c040f12bd8f6a975a44a0f90c29\
+        a9abc3d4d893927284c58754cce294529f8614dcd2aba991925fedc4ae74ffac\
+        6e333b93eb4aff0479da9a410e4450e0dd7ae4c6e2910900575da401fc07059f\
+        645e8b7e9bfdef33943054ff84011493c27b3429eaedb4ed5376441a77ed4385\
+        1ad77f16f541dfd269d50d6a5f14fb0aab1cbb4c1550be97f7ab4066193c4caa\
+        773dad38014bd2092fa755c824bb5e54c4f36ffda9fcea70b9c6e693e148c151";
+
+    fn check_xts(key: &str, sector: u64, plaintext: &[u8], ciphertext: &str) {
+        let xts = AesXts::new(&hex(key)).unwrap();
+        let mut data = plaintext.to_vec();
+        xts.encrypt_sector(sector, &mut data).unwrap();
+        assert_eq!(data, hex(ciphertext), "{} bytes", plaintext.len());
+        xts.decrypt_sector(sector, &mut data).unwrap();
+        assert_eq!(data, plaintext);
+    }
+
+    #[test]
+    fn xts() {
+        if !aes_ni() {
+            return;
+        }
+        let sector: Vec<u8> = (0..=255).chain(0..=255).collect();
+        check_xts(
+            "27182818284590452353602874713526\
+             31415926535897932384626433832795",
+            0,
+            &sector,
+            VECTOR_4,
+        );
+        check_xts(
+            "2718281828459045235360287471352662497757247093699959574966967627\
+             3141592653589793238462643383279502884197169399375105820974944592",
+            0xff,
+            &sector,
+            VECTOR_10,
+        );
+        // Vectors 15 to 18 steal ciphertext for 1 to 4 bytes past a block.
+        let key = "fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0";
+        let stolen = [
+            "6c1625db4671522d3d7599601de7ca09ed",
+            "d069444b7a7e0cab09e24447d24deb1fedbf",
+            "e5df1351c0544ba1350b3363cd8ef4beedbf9d",
+            "9d84c813f719aa2c7be3f66171c7c5c2edbf9dac",
+        ];
+        for (i, ciphertext) in stolen.iter().enumerate() {
+            check_xts(key, 0x12_3456_789a, &sector[..17 + i], ciphertext);
+        }
+
+        let xts = AesXts::new(&hex(key)).unwrap();
+        assert!(xts.encrypt_sector(0, &mut [0; AES_BLOCK_SIZE - 1]).is_err());
+        assert!(AesXts::new(&[7; 32]).is_err());
+        assert!(AesXts::new(&hex(key)[..31]).is_err());
+    }
+
+    #[test]
+    fn ctr() {
+        if !aes_ni() {
+            return;
+        }
+        // NIST SP 800-38A F.5.1 and F.5.5, whose counter wraps its last
+        // byte around.
+        let counter: Block = hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").try_into().unwrap();
+        let plaintext = hex("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\
+             30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710");
+        let cases = [
+            (
+                "2b7e151628aed2a6abf7158809cf4f3c",
+                "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff\
+                 5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee",
+            ),
+            (
+                "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4",
+                "601ec313775789a5b7a7f504bbf3d228f443e3ca4d62b59aca84e990cacaf5c5\
+                 2b0930daa23de94ce87017ba2d84988ddfc9c58db67aada613c2dd08457941a6",
+            ),
+        ];
+        for (key, ciphertext) in cases {
+            for piece in [1, 15, 16, 17, 64] {
+                let mut ctr = AesCtr::new(&hex(key), &counter, 128).unwrap();
+                let mut data = plaintext.clone();
+                for part in data.chunks_mut(piece) {
+                    ctr.apply_keystream(part).unwrap();
+                }
+                assert_eq!(data, hex(ciphertext), "in pieces of {}", piece);
+            }
+            // Seeking to the third block picks up the keystream there.
+            let mut ctr = AesCtr::new(&hex(key), &counter, 128).unwrap();
+            let third = (u128::from_be_bytes(counter) + 2).to_be_bytes();
+            ctr.set_counter(&third);
+            let mut data = plaintext[32..].to_vec();
+            ctr.apply_keystream(&mut data).unwrap();
+            assert_eq!(data, hex(ciphertext)[32..]);
+            assert_eq!(ctr.counter(), (u128::from_be_bytes(counter) + 4).to_be_bytes());
+        }
+
+        // Eight counting bits run out after the block of 0xff.
+        let mut ctr = AesCtr::new(&hex(cases[0].0), &counter, 8).unwrap();
+        let mut data = plaintext[..32].to_vec();
+        assert_eq!(ctr.apply_keystream(&mut data), Err(sgx_status_t::SGX_ERROR_INVALID_STATE));
+        assert_eq!(data, plaintext[..32]);
+        ctr.apply_keystream(&mut data[..16]).unwrap();
+        assert_eq!(data[..16], hex(cases[0].1)[..16]);
+        assert_eq!(
+            ctr.apply_keystream(&mut data[16..]),
+            Err(sgx_status_t::SGX_ERROR_INVALID_STATE)
+        );
+        assert!(AesCtr::new(&hex(cases[0].0), &counter, 0).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! BLAKE2b (RFC 7693), unkeyed, which Argon2 is built on.
+
+use crate::util::zeroize;
+
+const IV: [u64; 8] = [
+    0x6a09_e667_f3bc_c908, 0xbb67_ae85_84ca_a73b, 0x3c6e_f372_fe94_f82b, 0xa54f_f53a_5f1d_36f1,
+    0x510e_527f_ade6_82d1, 0x9b05_688c_2b3e_6c1f, 0x1f83_d9ab_fb41_bd6b, 0x5be0_cd19_137e_2179,
+];
+
+const SIGMA: [[usize; 16]; 12] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+];
+
+pub(crate) struct Blake2b {
+    state: [u64; 8],
+    buf: [u8; 128],
+    buf_len: usize,
+    len: u128,
+    out_len: usize,
+}
+
+impl Blake2b {
+    /// Starts a hash with a digest of `out_len` bytes, in `1..=64`.
+    pub(crate) fn new(out_len: usize) -> Blake2b {
+        let mut state = IV;
+        state[0] ^= 0x0101_0000 ^ out_len as u64;
+        Blake2b { state, buf: [0; 128], buf_len: 0, len: 0, out_len }
+    }
+
+    fn compress(&mut self, last: bool) {
+        let mut m = [0_u64; 16];
+        for (m, chunk) in m.iter_mut().zip(self.buf.chunks_exact(8)) {
+            let mut word = [0_u8; 8];
+            word.copy_from_slice(chunk);
+            *m = u64::from_le_bytes(word);
+        }
+        let mut v = [0_u64; 16];
+        v[..8].copy_from_slice(&self.state);
+        v[8..].copy_from_slice(&IV);
+        v[12] ^= self.len as u64;
+        v[13] ^= (self.len >> 64) as u64;
+        if last {
+            v[14] = !v[14];
+        }
+        for s in SIGMA.iter() {
+            mix(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+            mix(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+            mix(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+            mix(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+            mix(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+            mix(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+            mix(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+            mix(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+        for i in 0..8 {
+            self.state[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // The last block is only compressed by `finalize`, so a full
+            // buffer waits for more input first.
+            if self.buf_len == 128 {
+                self.len += 128;
+                self.compress(false);
+                self.buf_len = 0;
+            }
+            let take = core::cmp::min(128 - self.buf_len, data.len());
+            self.buf[self.buf_len..self.buf_len + take].copy_from_slice(&data[..take]);
+            self.buf_len += take;
+            data = &data[take..];
+        }
+    }
+
+    /// Writes the digest into `out`, which is `out_len` bytes long.
+    pub(crate) fn finalize_into(mut self, out: &mut [u8]) {
+        self.len += self.buf_len as u128;
+        for byte in self.buf[self.buf_len..].iter_mut() {
+            *byte = 0;
+        }
+        self.compress(true);
+        let mut digest = [0_u8; 64];
+        for (chunk, s) in digest.chunks_exact_mut(8).zip(self.state.iter()) {
+            chunk.copy_from_slice(&s.to_le_bytes());
+        }
+        out.copy_from_slice(&digest[..self.out_len]);
+        zeroize(&mut digest);
+    }
+}
+
+impl Drop for Blake2b {
+    fn drop(&mut self) {
+        for word in self.state.iter_mut() {
+            unsafe { core::ptr::write_volatile(word, 0) };
+        }
+        zeroize(&mut self.buf);
+    }
+}
+
+fn mix(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
@@ -0,0 +1,765 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! BLAKE3, hashing four or eight chunks at once with SSE4.1 or AVX2.
+//!
+//! Enclaves can't run CPUID, so the instruction sets come from the feature
+//! mask the trusted runtime computes at initialization, which already drops
+//! AVX when the enclave's XFRM doesn't enable its state. A host lying about
+//! the CPU can only make the enclave fault, not hash differently.
+
+use crate::hash::Hash;
+use crate::provider::cpu_features;
+use crate::util::zeroize;
+use sgx_types::cpu_feature::{CPU_FEATURE_AVX2, CPU_FEATURE_SSE4_1};
+
+const IV: [u32; 8] = [
+    0x6a09_e667, 0xbb67_ae85, 0x3c6e_f372, 0xa54f_f53a, 0x510e_527f, 0x9b05_688c, 0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+/// The message words each round reads, the permutation applied repeatedly.
+const SCHEDULE: [[usize; 16]; 7] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8],
+    [3, 4, 10, 12, 13, 2, 7, 14, 6, 5, 9, 0, 11, 15, 8, 1],
+    [10, 7, 12, 9, 14, 3, 13, 15, 4, 0, 11, 2, 5, 8, 1, 6],
+    [12, 13, 9, 11, 15, 10, 14, 8, 7, 2, 5, 3, 0, 1, 6, 4],
+    [9, 14, 11, 5, 8, 12, 15, 1, 13, 3, 0, 10, 2, 6, 4, 7],
+    [11, 15, 5, 0, 1, 9, 8, 6, 14, 10, 2, 12, 3, 4, 7, 13],
+];
+
+pub const BLAKE3_KEY_SIZE: usize = 32;
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+
+const CHUNK_START: u32 = 1 << 0;
+const CHUNK_END: u32 = 1 << 1;
+const PARENT: u32 = 1 << 2;
+const ROOT: u32 = 1 << 3;
+const KEYED_HASH: u32 = 1 << 4;
+const DERIVE_KEY_CONTEXT: u32 = 1 << 5;
+const DERIVE_KEY_MATERIAL: u32 = 1 << 6;
+
+/// Returns how many chunks the CPU hashes at once.
+fn lanes() -> usize {
+    let features = cpu_features();
+    if features & CPU_FEATURE_AVX2 != 0 {
+        return 8;
+    }
+    if features & CPU_FEATURE_SSE4_1 != 0 {
+        return 4;
+    }
+    1
+}
+
+fn g(v: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(12);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(8);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(7);
+}
+
+fn compress(cv: &[u32; 8], m: &[u32; 16], counter: u64, block_len: u32, flags: u32) -> [u32; 16] {
+    let mut v = [0_u32; 16];
+    v[..8].copy_from_slice(cv);
+    v[8..12].copy_from_slice(&IV[..4]);
+    v[12] = counter as u32;
+    v[13] = (counter >> 32) as u32;
+    v[14] = block_len;
+    v[15] = flags;
+    for s in SCHEDULE.iter() {
+        g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+    for i in 0..8 {
+        v[i] ^= v[i + 8];
+        v[i + 8] ^= cv[i];
+    }
+    v
+}
+
+fn words(block: &[u8]) -> [u32; 16] {
+    let mut out = [0_u32; 16];
+    for (w, chunk) in out.iter_mut().zip(block.chunks_exact(4)) {
+        *w = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    out
+}
+
+fn first_8(v: &[u32; 16]) -> [u32; 8] {
+    let mut out = [0_u32; 8];
+    out.copy_from_slice(&v[..8]);
+    out
+}
+
+fn wipe_words(words: &mut [u32]) {
+    for word in words.iter_mut() {
+        unsafe { core::ptr::write_volatile(word, 0) };
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    //! The compression of one block of several chunks, a chunk per lane.
+
+    use super::{CHUNK_END, CHUNK_LEN, CHUNK_START, IV, SCHEDULE};
+    use core::arch::x86_64::*;
+
+    trait Lanes: Copy {
+        unsafe fn splat(x: u32) -> Self;
+        /// Loads the first word of `words` into the first lane, and so on.
+        unsafe fn load(words: &[u32; 8]) -> Self;
+        unsafe fn store(self, words: &mut [u32; 8]);
+        unsafe fn add(self, other: Self) -> Self;
+        unsafe fn xor(self, other: Self) -> Self;
+        unsafe fn ror16(self) -> Self;
+        unsafe fn ror12(self) -> Self;
+        unsafe fn ror8(self) -> Self;
+        unsafe fn ror7(self) -> Self;
+    }
+
+    impl Lanes for __m128i {
+        #[inline(always)]
+        unsafe fn splat(x: u32) -> __m128i {
+            _mm_set1_epi32(x as i32)
+        }
+
+        #[inline(always)]
+        unsafe fn load(words: &[u32; 8]) -> __m128i {
+            _mm_loadu_si128(words.as_ptr() as *const __m128i)
+        }
+
+        #[inline(always)]
+        unsafe fn store(self, words: &mut [u32; 8]) {
+            _mm_storeu_si128(words.as_mut_ptr() as *mut __m128i, self)
+        }
+
+        #[inline(always)]
+        unsafe fn add(self, other: __m128i) -> __m128i {
+            _mm_add_epi32(self, other)
+        }
+
+        #[inline(always)]
+        unsafe fn xor(self, other: __m128i) -> __m128i {
+            _mm_xor_si128(self, other)
+        }
+
+        #[inline(always)]
+        unsafe fn ror16(self) -> __m128i {
+            let mask = _mm_set_epi8(13, 12, 15, 14, 9, 8, 11, 10, 5, 4, 7, 6, 1, 0, 3, 2);
+            _mm_shuffle_epi8(self, mask)
+        }
+
+        #[inline(always)]
+        unsafe fn ror12(self) -> __m128i {
+            _mm_or_si128(_mm_srli_epi32(self, 12), _mm_slli_epi32(self, 20))
+        }
+
+        #[inline(always)]
+        unsafe fn ror8(self) -> __m128i {
+            let mask = _mm_set_epi8(12, 15, 14, 13, 8, 11, 10, 9, 4, 7, 6, 5, 0, 3, 2, 1);
+            _mm_shuffle_epi8(self, mask)
+        }
+
+        #[inline(always)]
+        unsafe fn ror7(self) -> __m128i {
+            _mm_or_si128(_mm_srli_epi32(self, 7), _mm_slli_epi32(self, 25))
+        }
+    }
+
+    impl Lanes for __m256i {
+        #[inline(always)]
+        unsafe fn splat(x: u32) -> __m256i {
+            _mm256_set1_epi32(x as i32)
+        }
+
+        #[inline(always)]
+        unsafe fn load(words: &[u32; 8]) -> __m256i {
+            _mm256_loadu_si256(words.as_ptr() as *const __m256i)
+        }
+
+        #[inline(always)]
+        unsafe fn store(self, words: &mut [u32; 8]) {
+            _mm256_storeu_si256(words.as_mut_ptr() as *mut __m256i, self)
+        }
+
+        #[inline(always)]
+        unsafe fn add(self, other: __m256i) -> __m256i {
+            _mm256_add_epi32(self, other)
+        }
+
+        #[inline(always)]
+        unsafe fn xor(self, other: __m256i) -> __m256i {
+            _mm256_xor_si256(self, other)
+        }
+
+        #[inline(always)]
+        unsafe fn ror16(self) -> __m256i {
+            let mask = _mm_set_epi8(13, 12, 15, 14, 9, 8, 11, 10, 5, 4, 7, 6, 1, 0, 3, 2);
+            _mm256_shuffle_epi8(self, _mm256_broadcastsi128_si256(mask))
+        }
+
+        #[inline(always)]
+        unsafe fn ror12(self) -> __m256i {
+            _mm256_or_si256(_mm256_srli_epi32(self, 12), _mm256_slli_epi32(self, 20))
+        }
+
+        #[inline(always)]
+        unsafe fn ror8(self) -> __m256i {
+            let mask = _mm_set_epi8(12, 15, 14, 13, 8, 11, 10, 9, 4, 7, 6, 5, 0, 3, 2, 1);
+            _mm256_shuffle_epi8(self, _mm256_broadcastsi128_si256(mask))
+        }
+
+        #[inline(always)]
+        unsafe fn ror7(self) -> __m256i {
+            _mm256_or_si256(_mm256_srli_epi32(self, 7), _mm256_slli_epi32(self, 25))
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn g<V: Lanes>(v: &mut [V; 16], a: usize, b: usize, c: usize, d: usize, x: V, y: V) {
+        v[a] = v[a].add(v[b]).add(x);
+        v[d] = v[d].xor(v[a]).ror16();
+        v[c] = v[c].add(v[d]);
+        v[b] = v[b].xor(v[c]).ror12();
+        v[a] = v[a].add(v[b]).add(y);
+        v[d] = v[d].xor(v[a]).ror8();
+        v[c] = v[c].add(v[d]);
+        v[b] = v[b].xor(v[c]).ror7();
+    }
+
+    /// Hashes `lanes` whole chunks starting at `counter` into their
+    /// chaining values.
+    #[inline(always)]
+    unsafe fn hash_chunks<V: Lanes>(
+        lanes: usize,
+        input: &[u8],
+        key: &[u32; 8],
+        counter: u64,
+        flags: u32,
+        out: &mut [[u32; 8]],
+    ) {
+        let mut lo = [0_u32; 8];
+        let mut hi = [0_u32; 8];
+        for (lane, (lo, hi)) in lo.iter_mut().zip(hi.iter_mut()).enumerate() {
+            let c = counter + lane as u64;
+            *lo = c as u32;
+            *hi = (c >> 32) as u32;
+        }
+        let (lo, hi) = (V::load(&lo), V::load(&hi));
+        let mut cv = [V::splat(0); 8];
+        for (cv, k) in cv.iter_mut().zip(key.iter()) {
+            *cv = V::splat(*k);
+        }
+
+        let mut transposed = [[0_u32; 8]; 16];
+        for block in 0..16 {
+            for (lane, chunk) in input.chunks_exact(CHUNK_LEN).take(lanes).enumerate() {
+                let block = &chunk[64 * block..64 * block + 64];
+                for (t, word) in transposed.iter_mut().zip(block.chunks_exact(4)) {
+                    t[lane] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                }
+            }
+            let mut m = [V::splat(0); 16];
+            for (m, t) in m.iter_mut().zip(transposed.iter()) {
+                *m = V::load(t);
+            }
+
+            let mut block_flags = flags;
+            if block == 0 {
+                block_flags |= CHUNK_START;
+            }
+            if block == 15 {
+                block_flags |= CHUNK_END;
+            }
+            let mut v = [V::splat(0); 16];
+            v[..8].copy_from_slice(&cv);
+            for (v, iv) in v[8..12].iter_mut().zip(IV.iter()) {
+                *v = V::splat(*iv);
+            }
+            v[12] = lo;
+            v[13] = hi;
+            v[14] = V::splat(64);
+            v[15] = V::splat(block_flags);
+            for s in SCHEDULE.iter() {
+                g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+                g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+                g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+                g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+                g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+                g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+                g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+                g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+            }
+            for i in 0..8 {
+                cv[i] = v[i].xor(v[i + 8]);
+            }
+        }
+
+        let mut words = [[0_u32; 8]; 8];
+        for (words, cv) in words.iter_mut().zip(cv.iter()) {
+            cv.store(words);
+        }
+        for (lane, out) in out.iter_mut().take(lanes).enumerate() {
+            for (o, words) in out.iter_mut().zip(words.iter()) {
+                *o = words[lane];
+            }
+        }
+        for words in transposed.iter_mut() {
+            super::wipe_words(words);
+        }
+    }
+
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn hash_chunks_sse41(
+        input: &[u8],
+        key: &[u32; 8],
+        counter: u64,
+        flags: u32,
+        out: &mut [[u32; 8]],
+    ) {
+        hash_chunks::<__m128i>(4, input, key, counter, flags, out)
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn hash_chunks_avx2(
+        input: &[u8],
+        key: &[u32; 8],
+        counter: u64,
+        flags: u32,
+        out: &mut [[u32; 8]],
+    ) {
+        hash_chunks::<__m256i>(8, input, key, counter, flags, out)
+    }
+
+    /// Hashes `lanes` whole chunks, for the `lanes` the CPU supports.
+    pub(super) fn hash_many(
+        lanes: usize,
+        input: &[u8],
+        key: &[u32; 8],
+        counter: u64,
+        flags: u32,
+        out: &mut [[u32; 8]],
+    ) {
+        assert!(input.len() >= lanes * CHUNK_LEN && out.len() >= lanes);
+        // Safety: lanes() only returns 4 and 8 when the runtime reports
+        // SSE4.1 and AVX2.
+        match lanes {
+            8 => unsafe { hash_chunks_avx2(input, key, counter, flags, out) },
+            4 => unsafe { hash_chunks_sse41(input, key, counter, flags, out) },
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The last compression of a chunk or parent, which chains or, at the
+/// root, produces output.
+struct Output {
+    cv: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    block_len: u32,
+    flags: u32,
+}
+
+impl Output {
+    fn chaining_value(&self) -> [u32; 8] {
+        first_8(&compress(&self.cv, &self.block, self.counter, self.block_len, self.flags))
+    }
+
+    fn root_bytes(&self, out: &mut [u8]) {
+        for (counter, chunk) in out.chunks_mut(2 * BLAKE3_KEY_SIZE).enumerate() {
+            let flags = self.flags | ROOT;
+            let mut words = compress(&self.cv, &self.block, counter as u64, self.block_len, flags);
+            for (bytes, word) in chunk.chunks_mut(4).zip(words.iter()) {
+                bytes.copy_from_slice(&word.to_le_bytes()[..bytes.len()]);
+            }
+            wipe_words(&mut words);
+        }
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        wipe_words(&mut self.cv);
+        wipe_words(&mut self.block);
+    }
+}
+
+fn parent_output(left: &[u32; 8], right: &[u32; 8], key: &[u32; 8], flags: u32) -> Output {
+    let mut block = [0_u32; 16];
+    block[..8].copy_from_slice(left);
+    block[8..].copy_from_slice(right);
+    Output { cv: *key, block, counter: 0, block_len: BLOCK_LEN as u32, flags: flags | PARENT }
+}
+
+#[derive(Clone)]
+struct ChunkState {
+    cv: [u32; 8],
+    counter: u64,
+    block: [u8; BLOCK_LEN],
+    block_len: usize,
+    blocks_compressed: usize,
+    flags: u32,
+}
+
+impl ChunkState {
+    fn new(key: &[u32; 8], counter: u64, flags: u32) -> ChunkState {
+        ChunkState {
+            cv: *key,
+            counter,
+            block: [0; BLOCK_LEN],
+            block_len: 0,
+            blocks_compressed: 0,
+            flags,
+        }
+    }
+
+    fn len(&self) -> usize {
+        BLOCK_LEN * self.blocks_compressed + self.block_len
+    }
+
+    fn start_flag(&self) -> u32 {
+        if self.blocks_compressed == 0 {
+            CHUNK_START
+        } else {
+            0
+        }
+    }
+
+    fn update(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            // A full block is only compressed once more input follows, as
+            // the last one is flagged.
+            if self.block_len == BLOCK_LEN {
+                let flags = self.flags | self.start_flag();
+                let m = words(&self.block);
+                self.cv = first_8(&compress(&self.cv, &m, self.counter, BLOCK_LEN as u32, flags));
+                self.blocks_compressed += 1;
+                self.block_len = 0;
+            }
+            let take = core::cmp::min(BLOCK_LEN - self.block_len, input.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&input[..take]);
+            self.block_len += take;
+            input = &input[take..];
+        }
+    }
+
+    fn output(&self) -> Output {
+        let mut block = [0_u8; BLOCK_LEN];
+        block[..self.block_len].copy_from_slice(&self.block[..self.block_len]);
+        let output = Output {
+            cv: self.cv,
+            block: words(&block),
+            counter: self.counter,
+            block_len: self.block_len as u32,
+            flags: self.flags | self.start_flag() | CHUNK_END,
+        };
+        zeroize(&mut block);
+        output
+    }
+}
+
+impl Drop for ChunkState {
+    fn drop(&mut self) {
+        wipe_words(&mut self.cv);
+        zeroize(&mut self.block);
+    }
+}
+
+/// A BLAKE3 hasher, for plain hashing, keyed hashing or key derivation.
+#[derive(Clone)]
+pub struct Blake3 {
+    key: [u32; 8],
+    chunk: ChunkState,
+    /// The chaining values of complete subtrees, enough for 2^54 chunks.
+    stack: [[u32; 8]; 54],
+    stack_len: usize,
+    flags: u32,
+}
+
+impl Blake3 {
+    fn with_key(key: &[u32; 8], flags: u32) -> Blake3 {
+        Blake3 {
+            key: *key,
+            chunk: ChunkState::new(key, 0, flags),
+            stack: [[0; 8]; 54],
+            stack_len: 0,
+            flags,
+        }
+    }
+
+    pub fn new() -> Blake3 {
+        Blake3::with_key(&IV, 0)
+    }
+
+    /// Returns a hasher computing a MAC under `key`.
+    pub fn new_keyed(key: &[u8; BLAKE3_KEY_SIZE]) -> Blake3 {
+        let mut block = [0_u8; BLOCK_LEN];
+        block[..BLAKE3_KEY_SIZE].copy_from_slice(key);
+        let mut words = words(&block);
+        let hasher = Blake3::with_key(&first_8(&words), KEYED_HASH);
+        zeroize(&mut block);
+        wipe_words(&mut words);
+        hasher
+    }
+
+    /// Returns a hasher deriving keys from the key material it is fed, for
+    /// a hardcoded, globally unique `context` string.
+    pub fn new_derive_key(context: &str) -> Blake3 {
+        let mut context_hasher = Blake3::with_key(&IV, DERIVE_KEY_CONTEXT);
+        context_hasher.update(context.as_bytes());
+        let mut key = [0_u8; BLAKE3_KEY_SIZE];
+        context_hasher.finalize_xof(&mut key);
+        let mut block = [0_u8; BLOCK_LEN];
+        block[..BLAKE3_KEY_SIZE].copy_from_slice(&key);
+        let hasher = Blake3::with_key(&first_8(&words(&block)), DERIVE_KEY_MATERIAL);
+        zeroize(&mut key);
+        zeroize(&mut block);
+        hasher
+    }
+
+    fn push_chunk(&mut self, mut cv: [u32; 8], mut total_chunks: u64) {
+        // Each trailing zero bit of the count completes a subtree.
+        while total_chunks & 1 == 0 {
+            self.stack_len -= 1;
+            cv = parent_output(&self.stack[self.stack_len], &cv, &self.key, self.flags)
+                .chaining_value();
+            total_chunks >>= 1;
+        }
+        self.stack[self.stack_len] = cv;
+        self.stack_len += 1;
+    }
+
+    pub fn update(&mut self, mut input: &[u8]) {
+        let lanes = lanes();
+        while !input.is_empty() {
+            if self.chunk.len() == CHUNK_LEN {
+                let cv = self.chunk.output().chaining_value();
+                let total = self.chunk.counter + 1;
+                self.push_chunk(cv, total);
+                self.chunk = ChunkState::new(&self.key, total, self.flags);
+            }
+            // Whole chunks go through SIMD while more input follows, so
+            // that none of them is the root.
+            #[cfg(target_arch = "x86_64")]
+            {
+                if lanes > 1 && self.chunk.len() == 0 && input.len() > lanes * CHUNK_LEN {
+                    let counter = self.chunk.counter;
+                    let mut cvs = [[0_u32; 8]; 8];
+                    simd::hash_many(lanes, input, &self.key, counter, self.flags, &mut cvs);
+                    for (i, cv) in cvs.iter().take(lanes).enumerate() {
+                        self.push_chunk(*cv, counter + i as u64 + 1);
+                    }
+                    for cv in cvs.iter_mut() {
+                        wipe_words(cv);
+                    }
+                    self.chunk = ChunkState::new(&self.key, counter + lanes as u64, self.flags);
+                    input = &input[lanes * CHUNK_LEN..];
+                    continue;
+                }
+            }
+            let take = core::cmp::min(CHUNK_LEN - self.chunk.len(), input.len());
+            self.chunk.update(&input[..take]);
+            input = &input[take..];
+        }
+    }
+
+    /// Writes `out.len()` bytes of output, any number of them.
+    pub fn finalize_xof(&self, out: &mut [u8]) {
+        let mut output = self.chunk.output();
+        for left in self.stack[..self.stack_len].iter().rev() {
+            output = parent_output(left, &output.chaining_value(), &self.key, self.flags);
+        }
+        output.root_bytes(out);
+    }
+}
+
+impl Default for Blake3 {
+    fn default() -> Blake3 {
+        Blake3::new()
+    }
+}
+
+impl Drop for Blake3 {
+    fn drop(&mut self) {
+        wipe_words(&mut self.key);
+        for cv in self.stack.iter_mut() {
+            wipe_words(cv);
+        }
+    }
+}
+
+impl Hash for Blake3 {
+    const BLOCK_LEN: usize = BLOCK_LEN;
+    const OUTPUT_LEN: usize = 32;
+
+    fn new() -> Blake3 {
+        Blake3::new()
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        Blake3::update(self, data)
+    }
+
+    fn finalize_into(self, out: &mut [u8]) {
+        self.finalize_xof(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+    use std::vec;
+    use std::vec::Vec;
+
+    /// The official vectors: input length, then the hash, keyed hash and
+    /// derived key of the first 32 bytes of output.
+    const VECTORS: [(usize, &str, &str, &str); 8] = [
+        (
+            0,
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+            "d911b37e10f46ed017e1580643eeeff4ba1aa10a2ee05e2ebf3daf30021fc4b4",
+            "2cc39783c223154fea8dfb7c1b1660f2ac2dcbd1c1de8277b0b0dd39b7e50d7d",
+        ),
+        (
+            1,
+            "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+            "62bf997180899293a0fb9570420e3a60d37cd94aff28e76ed6d0e45e030f8af1",
+            "b3e2e340a117a499c6cf2398a19ee0d29cca2bb7404c73063382693bf66cb06c",
+        ),
+        (
+            1023,
+            "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11",
+            "c7068b314f8de56168ac7b2f84c5af556b9acb2c42d05ffca3a1238c1f29fff6",
+            "74a16c1c3d44368a86e1ca6df64be6a2f64cce8f09220787450722d85725dea5",
+        ),
+        (
+            1024,
+            "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+            "2a4d9ca0d10a4de6db65de1dc78232be817d5f922475a96b9ddba7fce03502f9",
+            "7356cd7720d5b66b6d0697eb3177d9f8d73a4a5c5e968896eb6a689684302706",
+        ),
+        (
+            1025,
+            "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+            "b87b72228710d9ef62548cbaa6214e4d00c531a1b94af1de9549b2e545c2b073",
+            "effaa245f065fbf82ac186839a249707c3bddf6d3fdda22d1b95a3c970379bcb",
+        ),
+        (
+            2049,
+            "5f4d72f40d7a5f82b15ca2b2e44b1de3c2ef86c426c95c1af0b6879522563030",
+            "672a1ee5a5b893e116d36fe0301b65ff1be1d4e6578e236b2cfb8d53326ce57b",
+            "2ea477c5515cc3dd606512ee72bb3e0e758cfae7232826f35fb98ca1bcbdf273",
+        ),
+        (
+            8193,
+            "bab6c09cb8ce8cf459261398d2e7aef35700bf488116ceb94a36d0f5f1b7bc3b",
+            "b91045e85d82bf3762354cd78f5bf4e7e2926c1a22eee98a8d77fbaf0664841d",
+            "af1e0346e389b17c23200270a64aa4e1ead98c61695d917de7d5b00491c9b0f1",
+        ),
+        (
+            31744,
+            "62b6960e1a44bcc1eb1a611a8d6235b6b4b78f32e7abc4fb4c6cdcce94895c47",
+            "f1bc65fd32aae5ca2a752d547eee5de8b499e6c75f9582832873637af536aa60",
+            "39772aef80e0ebe60596361e45b061e8f417429d529171b6764468c22928e28e",
+        ),
+    ];
+
+    const KEY: &[u8; BLAKE3_KEY_SIZE] = b"whats the Elligator Sixteen gott";
+    const CONTEXT: &str = "BLAKE3 2019-12-27 16:29:52 test vectors context";
+
+    fn input(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn output(mut hasher: Blake3, data: &[u8]) -> Vec<u8> {
+        hasher.update(data);
+        let mut out = vec![0_u8; 32];
+        hasher.finalize_xof(&mut out);
+        out
+    }
+
+    #[test]
+    fn known_answers() {
+        for (len, hash, keyed, derived) in VECTORS {
+            let data = input(len);
+            assert_eq!(output(Blake3::new(), &data), hex(hash), "{}", len);
+            assert_eq!(output(Blake3::new_keyed(KEY), &data), hex(keyed), "{}", len);
+            assert_eq!(output(Blake3::new_derive_key(CONTEXT), &data), hex(derived), "{}", len);
+            // Pieces that end mid-block, at blocks and at chunks.
+            for piece in [1, 63, BLOCK_LEN, CHUNK_LEN, CHUNK_LEN + 1] {
+                let mut hasher = Blake3::new();
+                for chunk in data.chunks(piece) {
+                    hasher.update(chunk);
+                }
+                assert_eq!(output(hasher, &[]), hex(hash), "{} in pieces of {}", len, piece);
+            }
+        }
+
+        let mut out = [0_u8; 131];
+        Blake3::new().finalize_xof(&mut out);
+        assert_eq!(
+            out[..],
+            hex("af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262e00f03e7b69af26b7faaf09fcd333050338ddfe085b8cc869ca98b206c08243a26f5487789e8f660afe6c99ef9e0c52b92e7393024a80459cf91f476f9ffdbda7001c22e159b402631f277ca96f2defdf1078282314e763699a31c5363165421cce14d")[..]
+        );
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn simd_agrees() {
+        // Every path hashes the same chunks to the same chaining values as
+        // one chunk at a time does, for the flags of each mode and a counter
+        // crossing 32 bits.
+        let data = input(8 * CHUNK_LEN);
+        let keyed = Blake3::new_keyed(KEY);
+        let derived = Blake3::new_derive_key(CONTEXT);
+        let modes = [(IV, 0), (keyed.key, KEYED_HASH), (derived.key, DERIVE_KEY_MATERIAL)];
+        for (key, flags) in modes {
+            for counter in [0, u32::MAX as u64 - 2] {
+                let scalar: Vec<[u32; 8]> = (0..8)
+                    .map(|i| {
+                        let mut chunk = ChunkState::new(&key, counter + i as u64, flags);
+                        chunk.update(&data[i * CHUNK_LEN..(i + 1) * CHUNK_LEN]);
+                        chunk.output().chaining_value()
+                    })
+                    .collect();
+                let mut cvs = [[0_u32; 8]; 8];
+                if std::is_x86_feature_detected!("sse4.1") {
+                    simd::hash_many(4, &data, &key, counter, flags, &mut cvs);
+                    assert_eq!(cvs[..4], scalar[..4]);
+                }
+                if std::is_x86_feature_detected!("avx2") {
+                    simd::hash_many(8, &data, &key, counter, flags, &mut cvs);
+                    assert_eq!(cvs[..], scalar[..]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1063 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! BLS signatures over BLS12-381 in the proof-of-possession scheme, with
+//! the ciphersuite `BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_` that
+//! Ethereum validators use: public keys are points of G1 and signatures
+//! points of G2, hashed to as RFC 9380 specifies.
+//!
+//! Deriving public keys and signing are constant time in the secret key.
+//! Verification, hashing to the curve and parsing handle public values only
+//! and take shortcuts depending on them. Points are read and written in the
+//! compressed encoding, and parsing checks that they lie in the prime-order
+//! groups.
+//!
+//! Aggregating public keys for one message, as [`fast_aggregate_verify`]
+//! does, is only safe for keys which proved possession of their secret key
+//! with [`BlsSecretKey::prove_possession`]. Otherwise a rogue key can cancel
+//! the others out.
+
+use crate::ctbignum::{Monty, Uint};
+use crate::field381::{Fp, Fp12, Fp2, Fp6, Limbs};
+use crate::hmac::Hmac;
+use crate::secret::{Secret, SecretBytes};
+use crate::sha256::Sha256;
+use crate::util::{read_rand, zeroize};
+use core::fmt;
+use sgx_types::*;
+
+pub const BLS_SECRET_KEY_SIZE: usize = 32;
+pub const BLS_PUBLIC_KEY_SIZE: usize = 48;
+pub const BLS_SIGNATURE_SIZE: usize = 96;
+
+const DST_SIGNATURE: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+const DST_POSSESSION: &[u8] = b"BLS_POP_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// The order of G1 and G2.
+const R: [u64; 4] =
+    [0xffff_ffff_0000_0001, 0x53bd_a402_fffe_5bfe, 0x3339_d808_09a1_d805, 0x73ed_a753_299d_7d48];
+/// The absolute value of the curve parameter `x`, which is negative.
+const X: u64 = 0xd201_0000_0001_0000;
+
+const G1_X: Limbs = [
+    0xfb3a_f00a_db22_c6bb,
+    0x6c55_e83f_f97a_1aef,
+    0xa14e_3a3f_171b_ac58,
+    0xc368_8c4f_9774_b905,
+    0x2695_638c_4fa9_ac0f,
+    0x17f1_d3a7_3197_d794,
+];
+const G1_Y: Limbs = [
+    0x0caa_2329_46c5_e7e1,
+    0xd03c_c744_a288_8ae4,
+    0x00db_18cb_2c04_b3ed,
+    0xfcf5_e095_d5d0_0af6,
+    0xa09e_30ed_741d_8ae4,
+    0x08b3_f481_e3aa_a0f1,
+];
+
+/// The Frobenius twist `psi` multiplies `x` by `1 / (1 + i)^((p - 1) / 3)`,
+/// which is `PSI_X i`...
+const PSI_X: Limbs = [
+    0x8bfd_0000_0000_aaad,
+    0x4094_27eb_4f49_fffd,
+    0x897d_2965_0fb8_5f9b,
+    0xaa0d_857d_8975_9ad4,
+    0xec02_4086_63d4_de85,
+    0x1a01_11ea_397f_e699,
+];
+/// ...and `y` by `1 / (1 + i)^((p - 1) / 2)`.
+const PSI_Y: [Limbs; 2] = [
+    [
+        0xf1ee_7b04_121b_dea2,
+        0x3044_66cf_3e67_fa0a,
+        0xef39_6489_f61e_b45e,
+        0x1c3d_edd9_30b1_cf60,
+        0xe2e9_c448_d77a_2cd9,
+        0x1352_03e6_0180_a68e,
+    ],
+    [
+        0xc810_84fb_ede3_cc09,
+        0xee67_992f_72ec_05f4,
+        0x77f7_6e17_0092_41c5,
+        0x4839_5dab_c2d3_435e,
+        0x6831_e36d_6bd1_7ffe,
+        0x06af_0e04_37ff_400b,
+    ],
+];
+
+/// The numerators of the 3-isogeny from the curve of the simplified SWU map
+/// to that of G2, lowest degree first, from RFC 9380, appendix E.3.
+const ISO_X_NUM: [[Limbs; 2]; 4] = [
+    [
+        [
+            0x6238_aaaa_aaaa_97d6,
+            0x5c26_38e3_43d9_c71c,
+            0x88b5_8423_c50a_e15d,
+            0x32c5_2d39_fd3a_042a,
+            0xbb5b_7a9a_47d7_ed85,
+            0x05c7_5950_7e8e_333e,
+        ],
+        [
+            0x6238_aaaa_aaaa_97d6,
+            0x5c26_38e3_43d9_c71c,
+            0x88b5_8423_c50a_e15d,
+            0x32c5_2d39_fd3a_042a,
+            0xbb5b_7a9a_47d7_ed85,
+            0x05c7_5950_7e8e_333e,
+        ],
+    ],
+    [
+        [
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+        ],
+        [
+            0x26a9_ffff_ffff_c71a,
+            0x1472_aaa9_cb8d_5555,
+            0x9a20_8c6b_4f20_a418,
+            0x984f_87ad_f7ae_0c7f,
+            0x3212_6fce_d787_c88f,
+            0x1156_0bf1_7baa_99bc,
+        ],
+    ],
+    [
+        [
+            0x26a9_ffff_ffff_c71e,
+            0x1472_aaa9_cb8d_5555,
+            0x9a20_8c6b_4f20_a418,
+            0x984f_87ad_f7ae_0c7f,
+            0x3212_6fce_d787_c88f,
+            0x1156_0bf1_7baa_99bc,
+        ],
+        [
+            0x9354_ffff_ffff_e38d,
+            0x0a39_5554_e5c6_aaaa,
+            0xcd10_4635_a790_520c,
+            0xcc27_c3d6_fbd7_063f,
+            0x1909_37e7_6bc3_e447,
+            0x08ab_05f8_bdd5_4cde,
+        ],
+    ],
+    [
+        [
+            0x88e2_aaaa_aaaa_5ed1,
+            0x7098_e38d_0f67_1c71,
+            0x22d6_108f_142b_8575,
+            0xcb14_b4e7_f4e8_10aa,
+            0xed6d_ea69_1f5f_b614,
+            0x171d_6541_fa38_ccfa,
+        ],
+        [
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+        ],
+    ],
+];
+const ISO_Y_NUM: [[Limbs; 2]; 4] = [
+    [
+        [
+            0x12cf_c71c_71c6_d706,
+            0xfc8c_25eb_f8c9_2f68,
+            0xf544_39d8_7d27_e500,
+            0x0f7d_a5d4_a07f_649b,
+            0x59a4_c18b_076d_1193,
+            0x1530_477c_7ab4_113b,
+        ],
+        [
+            0x12cf_c71c_71c6_d706,
+            0xfc8c_25eb_f8c9_2f68,
+            0xf544_39d8_7d27_e500,
+            0x0f7d_a5d4_a07f_649b,
+            0x59a4_c18b_076d_1193,
+            0x1530_477c_7ab4_113b,
+        ],
+    ],
+    [
+        [
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+        ],
+        [
+            0x6238_aaaa_aaaa_97be,
+            0x5c26_38e3_43d9_c71c,
+            0x88b5_8423_c50a_e15d,
+            0x32c5_2d39_fd3a_042a,
+            0xbb5b_7a9a_47d7_ed85,
+            0x05c7_5950_7e8e_333e,
+        ],
+    ],
+    [
+        [
+            0x26a9_ffff_ffff_c71c,
+            0x1472_aaa9_cb8d_5555,
+            0x9a20_8c6b_4f20_a418,
+            0x984f_87ad_f7ae_0c7f,
+            0x3212_6fce_d787_c88f,
+            0x1156_0bf1_7baa_99bc,
+        ],
+        [
+            0x9354_ffff_ffff_e38f,
+            0x0a39_5554_e5c6_aaaa,
+            0xcd10_4635_a790_520c,
+            0xcc27_c3d6_fbd7_063f,
+            0x1909_37e7_6bc3_e447,
+            0x08ab_05f8_bdd5_4cde,
+        ],
+    ],
+    [
+        [
+            0xe1b3_71c7_1c71_8b10,
+            0x4e79_097a_56dc_4bd9,
+            0xb0e9_77c6_9aa2_7452,
+            0x761b_0f37_a1e2_6286,
+            0xfbf7_043d_e381_1ad0,
+            0x124c_9ad4_3b6c_f79b,
+        ],
+        [
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+            0x0000_0000_0000_0000,
+        ],
+    ],
+];
+
+/// The coordinates of the curves `y^2 = x^3 + b`: G1 over `Fp` and G2 over
+/// `Fp2` with `b = 4` and `b = 4 (1 + i)`.
+trait Coordinate: Copy {
+    const ZERO: Self;
+    const ONE: Self;
+    /// Returns `3 b`.
+    fn b3() -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn sub(&self, other: &Self) -> Self;
+    fn neg(&self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+    fn invert(&self) -> Self;
+    fn is_zero(&self) -> bool;
+    fn select(a: &Self, b: &Self, choice: u64) -> Self;
+}
+
+impl Coordinate for Fp {
+    const ZERO: Fp = Fp::ZERO;
+    const ONE: Fp = Fp::ONE;
+
+    fn b3() -> Fp {
+        Fp::from_u64(12)
+    }
+
+    fn add(&self, other: &Fp) -> Fp {
+        Fp::add(self, other)
+    }
+
+    fn sub(&self, other: &Fp) -> Fp {
+        Fp::sub(self, other)
+    }
+
+    fn neg(&self) -> Fp {
+        Fp::neg(self)
+    }
+
+    fn mul(&self, other: &Fp) -> Fp {
+        Fp::mul(self, other)
+    }
+
+    fn invert(&self) -> Fp {
+        Fp::invert(self)
+    }
+
+    fn is_zero(&self) -> bool {
+        Fp::is_zero(self)
+    }
+
+    fn select(a: &Fp, b: &Fp, choice: u64) -> Fp {
+        Fp::select(a, b, choice)
+    }
+}
+
+impl Coordinate for Fp2 {
+    const ZERO: Fp2 = Fp2::ZERO;
+    const ONE: Fp2 = Fp2::ONE;
+
+    fn b3() -> Fp2 {
+        Fp2::new(Fp::from_u64(12), Fp::from_u64(12))
+    }
+
+    fn add(&self, other: &Fp2) -> Fp2 {
+        Fp2::add(self, other)
+    }
+
+    fn sub(&self, other: &Fp2) -> Fp2 {
+        Fp2::sub(self, other)
+    }
+
+    fn neg(&self) -> Fp2 {
+        Fp2::neg(self)
+    }
+
+    fn mul(&self, other: &Fp2) -> Fp2 {
+        Fp2::mul(self, other)
+    }
+
+    fn invert(&self) -> Fp2 {
+        Fp2::invert(self)
+    }
+
+    fn is_zero(&self) -> bool {
+        Fp2::is_zero(self)
+    }
+
+    fn select(a: &Fp2, b: &Fp2, choice: u64) -> Fp2 {
+        Fp2::select(a, b, choice)
+    }
+}
+
+/// A point in projective coordinates, `x = X/Z`, `y = Y/Z`.
+#[derive(Clone, Copy)]
+struct Point<F> {
+    x: F,
+    y: F,
+    z: F,
+}
+
+type G1 = Point<Fp>;
+type G2 = Point<Fp2>;
+
+impl<F: Coordinate> Point<F> {
+    fn identity() -> Point<F> {
+        Point { x: F::ZERO, y: F::ONE, z: F::ZERO }
+    }
+
+    fn from_affine(x: F, y: F) -> Point<F> {
+        Point { x, y, z: F::ONE }
+    }
+
+    fn to_affine(self) -> Option<(F, F)> {
+        if self.z.is_zero() {
+            return None;
+        }
+        let zinv = self.z.invert();
+        Some((self.x.mul(&zinv), self.y.mul(&zinv)))
+    }
+
+    fn is_identity(&self) -> bool {
+        self.z.is_zero()
+    }
+
+    /// Adds with the complete formulas of Renes, Costello and Batina for
+    /// `a = 0`, which have no exceptional cases and also double.
+    fn add(&self, other: &Point<F>) -> Point<F> {
+        let b3 = F::b3();
+        let t0 = self.x.mul(&other.x);
+        let t1 = self.y.mul(&other.y);
+        let t2 = self.z.mul(&other.z);
+        let t3 = self.x.add(&self.y).mul(&other.x.add(&other.y));
+        let t3 = t3.sub(&t0.add(&t1));
+        let t4 = self.y.add(&self.z).mul(&other.y.add(&other.z));
+        let t4 = t4.sub(&t1.add(&t2));
+        let y3 = self.x.add(&self.z).mul(&other.x.add(&other.z));
+        let y3 = y3.sub(&t0.add(&t2));
+        let t0 = t0.add(&t0).add(&t0);
+        let t2 = b3.mul(&t2);
+        let z3 = t1.add(&t2);
+        let t1 = t1.sub(&t2);
+        let y3 = b3.mul(&y3);
+        let x3 = t3.mul(&t1).sub(&t4.mul(&y3));
+        let y3 = t1.mul(&z3).add(&y3.mul(&t0));
+        let z3 = z3.mul(&t4).add(&t0.mul(&t3));
+        Point { x: x3, y: y3, z: z3 }
+    }
+
+    fn double(&self) -> Point<F> {
+        self.add(self)
+    }
+
+    fn neg(&self) -> Point<F> {
+        Point { x: self.x, y: self.y.neg(), z: self.z }
+    }
+
+    fn select(a: &Point<F>, b: &Point<F>, choice: u64) -> Point<F> {
+        Point {
+            x: F::select(&a.x, &b.x, choice),
+            y: F::select(&a.y, &b.y, choice),
+            z: F::select(&a.z, &b.z, choice),
+        }
+    }
+
+    /// Computes `scalar * self` for a scalar below `r`, doubling and adding
+    /// for every bit.
+    fn mul(&self, scalar: &Uint<4>) -> Point<F> {
+        let mut acc = Point::identity();
+        for i in (0..255).rev() {
+            acc = acc.double();
+            acc = Point::select(&acc, &acc.add(self), scalar.bit(i));
+        }
+        acc
+    }
+
+    /// Computes `scalar * self` for a public scalar.
+    fn mul_vartime(&self, scalar: &[u64]) -> Point<F> {
+        let mut acc = Point::identity();
+        for i in (0..64 * scalar.len()).rev() {
+            acc = acc.double();
+            if (scalar[i / 64] >> (i % 64)) & 1 == 1 {
+                acc = acc.add(self);
+            }
+        }
+        acc
+    }
+
+    fn is_torsion_free(&self) -> bool {
+        self.mul_vartime(&R).is_identity()
+    }
+
+    fn eq(&self, other: &Point<F>) -> bool {
+        let x = self.x.mul(&other.z).sub(&other.x.mul(&self.z));
+        let y = self.y.mul(&other.z).sub(&other.y.mul(&self.z));
+        x.is_zero() && y.is_zero()
+    }
+}
+
+fn g1_generator() -> G1 {
+    Point::from_affine(Fp::from_limbs(&G1_X), Fp::from_limbs(&G1_Y))
+}
+
+fn fp2(limbs: &[Limbs; 2]) -> Fp2 {
+    Fp2::from_limbs(&limbs[0], &limbs[1])
+}
+
+/// Applies the twisted Frobenius endomorphism of G2.
+fn psi(point: &G2) -> G2 {
+    let psi_x = Fp2::new(Fp::ZERO, Fp::from_limbs(&PSI_X));
+    Point {
+        x: point.x.conjugate().mul(&psi_x),
+        y: point.y.conjugate().mul(&fp2(&PSI_Y)),
+        z: point.z.conjugate(),
+    }
+}
+
+/// Returns `x * point`, for the negative curve parameter `x`.
+fn mul_by_x<F: Coordinate>(point: &Point<F>) -> Point<F> {
+    point.mul_vartime(&[X]).neg()
+}
+
+/// Multiplies by the effective cofactor of G2, as in RFC 9380, appendix
+/// G.3: `(x^2 - x - 1) P + (x - 1) psi(P) + psi^2(2 P)`.
+fn clear_cofactor(point: &G2) -> G2 {
+    let t1 = mul_by_x(point);
+    let t2 = psi(point);
+    let t3 = psi(&psi(&point.double())).add(&t2.neg());
+    let t2 = mul_by_x(&t1.add(&t2));
+    t3.add(&t2).add(&t1.neg()).add(&point.neg())
+}
+
+/// Expands `msg` into 256 bytes with `expand_message_xmd` and SHA-256, as
+/// RFC 9380, section 5.3.1, specifies.
+fn expand_message(msg: &[u8], dst: &[u8], out: &mut [u8; 256]) {
+    let dst_len = [dst.len() as u8];
+    let mut sha = Sha256::new();
+    sha.update(&[0; 64]);
+    sha.update(msg);
+    sha.update(&[1, 0, 0]);
+    sha.update(dst);
+    sha.update(&dst_len);
+    let b0 = sha.finalize();
+    let mut prev = [0_u8; 32];
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let mut x = b0;
+        for (x, p) in x.iter_mut().zip(prev.iter()) {
+            *x ^= p;
+        }
+        let mut sha = Sha256::new();
+        sha.update(&x);
+        sha.update(&[i as u8 + 1]);
+        sha.update(dst);
+        sha.update(&dst_len);
+        prev = sha.finalize();
+        chunk.copy_from_slice(&prev);
+    }
+}
+
+/// Returns `x^3 + a x + b` on the curve of the simplified SWU map, with
+/// `a = 240 i` and `b = 1012 (1 + i)`.
+fn swu_curve(x: &Fp2, a: &Fp2, b: &Fp2) -> Fp2 {
+    x.square().mul(x).add(&a.mul(x)).add(b)
+}
+
+fn horner(coefficients: &[[Limbs; 2]], x: &Fp2) -> Fp2 {
+    coefficients.iter().rev().fold(Fp2::ZERO, |acc, k| acc.mul(x).add(&fp2(k)))
+}
+
+/// Maps a field element to G2 with the simplified SWU map and the
+/// 3-isogeny, as in RFC 9380, sections 6.6.2 and 6.6.3.
+fn map_to_curve(u: &Fp2) -> G2 {
+    let a = Fp2::new(Fp::ZERO, Fp::from_u64(240));
+    let b = Fp2::new(Fp::from_u64(1012), Fp::from_u64(1012));
+    let z = Fp2::new(Fp::from_u64(2), Fp::ONE).neg();
+
+    let zu2 = z.mul(&u.square());
+    let tv1 = zu2.square().add(&zu2);
+    let x1 = if tv1.is_zero() {
+        b.mul(&z.mul(&a).invert())
+    } else {
+        b.neg().mul(&a.invert()).mul(&tv1.invert().add(&Fp2::ONE))
+    };
+    let gx1 = swu_curve(&x1, &a, &b);
+    let (x, gx) = if gx1.is_square() {
+        (x1, gx1)
+    } else {
+        let x2 = zu2.mul(&x1);
+        (x2, swu_curve(&x2, &a, &b))
+    };
+    // gx is a square for one of x1 and x2.
+    let mut y = gx.sqrt().unwrap_or(Fp2::ZERO);
+    if y.sgn0() != u.sgn0() {
+        y = y.neg();
+    }
+
+    // x_den = x^2 + (12 - 12 i) x - 72 i and
+    // y_den = x^3 + (18 - 18 i) x^2 - 216 i x - 432 (1 + i).
+    let small = |n: u64| Fp::from_u64(n);
+    let x_den = x.square().add(&Fp2::new(small(12), small(12).neg()).mul(&x));
+    let x_den = x_den.add(&Fp2::new(Fp::ZERO, small(72).neg()));
+    let y_den = x.add(&Fp2::new(small(18), small(18).neg())).mul(&x);
+    let y_den = y_den.add(&Fp2::new(Fp::ZERO, small(216).neg())).mul(&x);
+    let y_den = y_den.add(&Fp2::new(small(432).neg(), small(432).neg()));
+    let x_map = horner(&ISO_X_NUM, &x).mul(&x_den.invert());
+    let y_map = y.mul(&horner(&ISO_Y_NUM, &x)).mul(&y_den.invert());
+    Point::from_affine(x_map, y_map)
+}
+
+/// Hashes `msg` to G2, as `hash_to_curve` of RFC 9380 does for the suite
+/// `BLS12381G2_XMD:SHA-256_SSWU_RO_`.
+fn hash_to_g2(msg: &[u8], dst: &[u8]) -> G2 {
+    let mut bytes = [0_u8; 256];
+    expand_message(msg, dst, &mut bytes);
+    let mut u = [Fp2::ZERO; 2];
+    for (u, chunk) in u.iter_mut().zip(bytes.chunks(128)) {
+        let mut c0 = [0_u8; 64];
+        let mut c1 = [0_u8; 64];
+        c0.copy_from_slice(&chunk[..64]);
+        c1.copy_from_slice(&chunk[64..]);
+        *u = Fp2::new(Fp::from_wide_be_bytes(&c0), Fp::from_wide_be_bytes(&c1));
+    }
+    clear_cofactor(&map_to_curve(&u[0]).add(&map_to_curve(&u[1])))
+}
+
+/// Returns the line through `t` with slope `lambda` evaluated at `p`,
+/// scaled by `w^3` so that it is sparse in `Fp12`.
+fn line(lambda: &Fp2, t: &(Fp2, Fp2), p: &(Fp, Fp)) -> Fp12 {
+    Fp12 {
+        c0: Fp6 { c0: lambda.mul(&t.0).sub(&t.1), c1: lambda.mul_fp(&p.0).neg(), c2: Fp2::ZERO },
+        c1: Fp6 { c0: Fp2::ZERO, c1: Fp2::new(p.1, Fp::ZERO), c2: Fp2::ZERO },
+    }
+}
+
+/// Computes the Miller loop of the optimal ate pairing, in affine
+/// coordinates since the points are public.
+fn miller_loop(p: &(Fp, Fp), q: &(Fp2, Fp2)) -> Fp12 {
+    let mut t = *q;
+    let mut f = Fp12::ONE;
+    for i in (0..63).rev() {
+        let three = Fp::from_u64(3);
+        let lambda = t.0.square().mul_fp(&three).mul(&t.1.double().invert());
+        f = f.square().mul(&line(&lambda, &t, p));
+        let x = lambda.square().sub(&t.0.double());
+        t = (x, lambda.mul(&t.0.sub(&x)).sub(&t.1));
+        if (X >> i) & 1 == 1 {
+            let lambda = q.1.sub(&t.1).mul(&q.0.sub(&t.0).invert());
+            f = f.mul(&line(&lambda, &t, p));
+            let x = lambda.square().sub(&t.0).sub(&q.0);
+            t = (x, lambda.mul(&t.0.sub(&x)).sub(&t.1));
+        }
+    }
+    // The loop ran over |x|, and x is negative.
+    f.conjugate()
+}
+
+/// Raises an element of the cyclotomic subgroup to the power `x`.
+fn cyclotomic_exp_by_x(f: &Fp12) -> Fp12 {
+    let mut acc = Fp12::ONE;
+    for i in (0..64).rev() {
+        acc = acc.square();
+        if (X >> i) & 1 == 1 {
+            acc = acc.mul(f);
+        }
+    }
+    acc.conjugate()
+}
+
+/// Raises `f` to `3 (p^12 - 1) / r`, which gives the cube of the pairing:
+/// a pairing all the same, as 3 doesn't divide `r`.
+fn final_exponentiation(f: &Fp12) -> Fp12 {
+    // The easy part, (p^6 - 1) (p^2 + 1).
+    let f = f.conjugate().mul(&f.invert());
+    let f = f.frobenius().frobenius().mul(&f);
+    // The hard part times 3, as (x - 1)^2 (x + p) (x^2 + p^2 - 1) + 3 after
+    // Hayashida, Hayasaka and Teruya.
+    let t0 = cyclotomic_exp_by_x(&f).mul(&f.conjugate());
+    let t1 = cyclotomic_exp_by_x(&t0).mul(&t0.conjugate());
+    let t2 = cyclotomic_exp_by_x(&t1).mul(&t1.frobenius());
+    let t3 = cyclotomic_exp_by_x(&cyclotomic_exp_by_x(&t2));
+    let t3 = t3.mul(&t2.frobenius().frobenius()).mul(&t2.conjugate());
+    t3.mul(&f.square()).mul(&f)
+}
+
+/// Returns whether the product of the pairings of `pairs` is 1.
+fn pairings_cancel<I: Iterator<Item = (G1, G2)>>(pairs: I) -> bool {
+    let mut f = Fp12::ONE;
+    for (p, q) in pairs {
+        if let (Some(p), Some(q)) = (p.to_affine(), q.to_affine()) {
+            f = f.mul(&miller_loop(&p, &q));
+        }
+    }
+    final_exponentiation(&f) == Fp12::ONE
+}
+
+/// Sets the flags of the compressed encoding in its first byte.
+fn set_flags(out: &mut [u8], infinity: bool, largest: bool) {
+    out[0] |= 0x80 | (infinity as u8) << 6 | (largest as u8) << 5;
+}
+
+/// Splits off the flags of a compressed encoding, returning whether the
+/// point is the identity and the sign flag, or `None` if they are invalid.
+fn take_flags(bytes: &mut [u8]) -> Option<(bool, bool)> {
+    let flags = bytes[0];
+    bytes[0] &= 0x1f;
+    if flags & 0x80 == 0 {
+        return None;
+    }
+    if flags & 0x40 != 0 {
+        // The identity has no sign, and no coordinates.
+        if flags & 0x20 != 0 || bytes.iter().any(|b| *b != 0) {
+            return None;
+        }
+        return Some((true, false));
+    }
+    Some((false, flags & 0x20 != 0))
+}
+
+fn g1_to_bytes(point: &G1) -> [u8; 48] {
+    match point.to_affine() {
+        Some((x, y)) => {
+            let mut out = x.to_be_bytes();
+            set_flags(&mut out, false, y.is_lexicographically_largest());
+            out
+        }
+        None => {
+            let mut out = [0_u8; 48];
+            set_flags(&mut out, true, false);
+            out
+        }
+    }
+}
+
+fn g1_from_bytes(bytes: &[u8; 48]) -> Option<G1> {
+    let mut x = *bytes;
+    let (infinity, largest) = take_flags(&mut x)?;
+    if infinity {
+        return Some(Point::identity());
+    }
+    let x = Fp::from_be_bytes(&x)?;
+    let y = x.square().mul(&x).add(&Fp::from_u64(4)).sqrt()?;
+    let y = if y.is_lexicographically_largest() == largest { y } else { y.neg() };
+    Some(Point::from_affine(x, y)).filter(Point::is_torsion_free)
+}
+
+/// Encodes a point of G2, with `x.c1` before `x.c0`.
+fn g2_to_bytes(point: &G2) -> [u8; 96] {
+    let mut out = [0_u8; 96];
+    match point.to_affine() {
+        Some((x, y)) => {
+            out[..48].copy_from_slice(&x.c1.to_be_bytes());
+            out[48..].copy_from_slice(&x.c0.to_be_bytes());
+            set_flags(&mut out, false, y.is_lexicographically_largest());
+        }
+        None => set_flags(&mut out, true, false),
+    }
+    out
+}
+
+fn g2_from_bytes(bytes: &[u8; 96]) -> Option<G2> {
+    let mut bytes = *bytes;
+    let (infinity, largest) = take_flags(&mut bytes)?;
+    if infinity {
+        return Some(Point::identity());
+    }
+    let mut c1 = [0_u8; 48];
+    let mut c0 = [0_u8; 48];
+    c1.copy_from_slice(&bytes[..48]);
+    c0.copy_from_slice(&bytes[48..]);
+    let x = Fp2::new(Fp::from_be_bytes(&c0)?, Fp::from_be_bytes(&c1)?);
+    let b = Fp2::new(Fp::from_u64(4), Fp::from_u64(4));
+    let y = x.square().mul(&x).add(&b).sqrt()?;
+    let y = if y.is_lexicographically_largest() == largest { y } else { y.neg() };
+    Some(Point::from_affine(x, y)).filter(Point::is_torsion_free)
+}
+
+/// A BLS secret key, a scalar in `[1, r)`.
+pub struct BlsSecretKey {
+    key: Secret<Uint<4>>,
+}
+
+impl BlsSecretKey {
+    /// Generates a key from 32 bytes of the enclave's random number
+    /// generator.
+    pub fn generate() -> SgxResult<BlsSecretKey> {
+        let mut ikm = SecretBytes::<32>::zeroed();
+        read_rand(ikm.expose_secret_mut())?;
+        BlsSecretKey::from_ikm(ikm.expose_secret(), &[])
+    }
+
+    /// Derives a key from at least 32 bytes of keying material with the
+    /// `KeyGen` of the IRTF BLS signature draft, failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` for shorter material.
+    pub fn from_ikm(ikm: &[u8], key_info: &[u8]) -> SgxResult<BlsSecretKey> {
+        if ikm.len() < 32 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let order = Monty::new(&Uint(R)).ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)?;
+        let mut salt = [0_u8; 32];
+        salt[..20].copy_from_slice(b"BLS-SIG-KEYGEN-SALT-");
+        let mut salt_len = 20;
+        loop {
+            let mut sha = Sha256::new();
+            sha.update(&salt[..salt_len]);
+            salt = sha.finalize();
+            salt_len = 32;
+
+            let mut extract = Hmac::<Sha256>::new(&salt);
+            extract.update(ikm);
+            extract.update(&[0]);
+            let mut prk = [0_u8; 32];
+            extract.finalize_into(&mut prk);
+
+            // HKDF-Expand to 48 bytes, with the info key_info || I2OSP(48, 2).
+            let expand = Hmac::<Sha256>::new(&prk);
+            let mut okm = [0_u8; 64];
+            for i in 0..2 {
+                let mut mac = expand.clone();
+                if i > 0 {
+                    mac.update(&okm[..32]);
+                }
+                mac.update(key_info);
+                mac.update(&[0, 48, i as u8 + 1]);
+                mac.finalize_into(&mut okm[32 * i..32 * (i + 1)]);
+            }
+            let mut hi = Uint::<4>::from_be_bytes(&okm[..16]).unwrap_or(Uint::ZERO);
+            let mut lo = Uint::<4>::from_be_bytes(&okm[16..48]).unwrap_or(Uint::ZERO);
+            // Encoding multiplies by 2^256, so this is hi 2^256 + lo mod r.
+            let key = order.add(&order.encode(&hi), &order.reduce(&lo));
+            zeroize(&mut prk);
+            zeroize(&mut okm);
+            hi.wipe();
+            lo.wipe();
+            if !key.is_zero() {
+                return Ok(BlsSecretKey { key: Secret::new(key) });
+            }
+        }
+    }
+
+    /// Parses a big-endian key, failing with `SGX_ERROR_INVALID_PARAMETER`
+    /// unless it is in `[1, r)`.
+    pub fn from_bytes(bytes: &[u8; BLS_SECRET_KEY_SIZE]) -> SgxResult<BlsSecretKey> {
+        match Uint::from_be_bytes(bytes) {
+            Some(key) if key.lt(&Uint(R)) && !key.is_zero() => {
+                Ok(BlsSecretKey { key: Secret::new(key) })
+            }
+            Some(mut key) => {
+                key.wipe();
+                Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+            }
+            None => Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER),
+        }
+    }
+
+    /// Returns the big-endian key, e.g. to seal it.
+    pub fn to_bytes(&self) -> SecretBytes<BLS_SECRET_KEY_SIZE> {
+        let mut out = SecretBytes::zeroed();
+        self.key.expose_secret().write_be_bytes(out.expose_secret_mut());
+        out
+    }
+
+    pub fn public_key(&self) -> BlsPublicKey {
+        BlsPublicKey { point: g1_generator().mul(self.key.expose_secret()) }
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> BlsSignature {
+        BlsSignature { point: hash_to_g2(msg, DST_SIGNATURE).mul(self.key.expose_secret()) }
+    }
+
+    /// Signs the encoded public key, proving possession of this key so that
+    /// others can safely aggregate it.
+    pub fn prove_possession(&self) -> BlsSignature {
+        let public_key = self.public_key().to_bytes();
+        let hash = hash_to_g2(&public_key, DST_POSSESSION);
+        BlsSignature { point: hash.mul(self.key.expose_secret()) }
+    }
+}
+
+impl fmt::Debug for BlsSecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlsSecretKey").field("public_key", &self.public_key()).finish()
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, name: &str, bytes: &[u8]) -> fmt::Result {
+    write!(f, "{}(", name)?;
+    for b in bytes.iter() {
+        write!(f, "{:02x}", b)?;
+    }
+    f.write_str(")")
+}
+
+/// A BLS public key, a point of G1 other than the identity.
+#[derive(Clone, Copy)]
+pub struct BlsPublicKey {
+    point: G1,
+}
+
+impl BlsPublicKey {
+    /// Parses a compressed point, failing with `SGX_ERROR_INVALID_PARAMETER`
+    /// unless it is a valid key.
+    pub fn from_bytes(bytes: &[u8; BLS_PUBLIC_KEY_SIZE]) -> SgxResult<BlsPublicKey> {
+        match g1_from_bytes(bytes) {
+            Some(point) if !point.is_identity() => Ok(BlsPublicKey { point }),
+            _ => Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER),
+        }
+    }
+
+    /// Returns the compressed point.
+    pub fn to_bytes(&self) -> [u8; BLS_PUBLIC_KEY_SIZE] {
+        g1_to_bytes(&self.point)
+    }
+
+    /// Adds up public keys, failing with `SGX_ERROR_INVALID_PARAMETER` if
+    /// there are none or they sum to the identity.
+    pub fn aggregate(keys: &[BlsPublicKey]) -> SgxResult<BlsPublicKey> {
+        let point = keys.iter().fold(Point::identity(), |acc, key| acc.add(&key.point));
+        if point.is_identity() {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(BlsPublicKey { point })
+    }
+
+    pub fn verify(&self, msg: &[u8], signature: &BlsSignature) -> bool {
+        verify_point(&self.point, &hash_to_g2(msg, DST_SIGNATURE), &signature.point)
+    }
+
+    /// Verifies a proof of possession made by
+    /// [`BlsSecretKey::prove_possession`].
+    pub fn verify_possession(&self, proof: &BlsSignature) -> bool {
+        let hash = hash_to_g2(&self.to_bytes(), DST_POSSESSION);
+        verify_point(&self.point, &hash, &proof.point)
+    }
+}
+
+/// Checks that `e(key, hash) = e(g1, signature)`.
+fn verify_point(key: &G1, hash: &G2, signature: &G2) -> bool {
+    let pairs = [(*key, *hash), (g1_generator().neg(), *signature)];
+    pairings_cancel(pairs.iter().copied())
+}
+
+impl PartialEq for BlsPublicKey {
+    fn eq(&self, other: &BlsPublicKey) -> bool {
+        self.point.eq(&other.point)
+    }
+}
+
+impl Eq for BlsPublicKey {}
+
+impl fmt::Debug for BlsPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, "BlsPublicKey", &self.to_bytes())
+    }
+}
+
+/// A BLS signature, or an aggregate of signatures, a point of G2.
+#[derive(Clone, Copy)]
+pub struct BlsSignature {
+    point: G2,
+}
+
+impl BlsSignature {
+    /// Parses a compressed point, failing with `SGX_ERROR_INVALID_PARAMETER`
+    /// unless it lies in G2.
+    pub fn from_bytes(bytes: &[u8; BLS_SIGNATURE_SIZE]) -> SgxResult<BlsSignature> {
+        g2_from_bytes(bytes)
+            .map(|point| BlsSignature { point })
+            .ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+    }
+
+    /// Returns the compressed point.
+    pub fn to_bytes(&self) -> [u8; BLS_SIGNATURE_SIZE] {
+        g2_to_bytes(&self.point)
+    }
+
+    /// Adds up signatures, failing with `SGX_ERROR_INVALID_PARAMETER` if
+    /// there are none.
+    pub fn aggregate(signatures: &[BlsSignature]) -> SgxResult<BlsSignature> {
+        if signatures.is_empty() {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let point = signatures.iter().fold(Point::identity(), |acc, sig| acc.add(&sig.point));
+        Ok(BlsSignature { point })
+    }
+}
+
+impl PartialEq for BlsSignature {
+    fn eq(&self, other: &BlsSignature) -> bool {
+        self.point.eq(&other.point)
+    }
+}
+
+impl Eq for BlsSignature {}
+
+impl fmt::Debug for BlsSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, "BlsSignature", &self.to_bytes())
+    }
+}
+
+/// Verifies an aggregate of signatures by `keys` over the same message, as
+/// attestations are; each key must have proven possession of its secret key.
+pub fn fast_aggregate_verify(keys: &[BlsPublicKey], msg: &[u8], signature: &BlsSignature) -> bool {
+    match BlsPublicKey::aggregate(keys) {
+        Ok(key) => key.verify(msg, signature),
+        Err(_) => false,
+    }
+}
+
+/// Verifies an aggregate of signatures, each by a key over its own message.
+pub fn aggregate_verify(signed: &[(BlsPublicKey, &[u8])], signature: &BlsSignature) -> bool {
+    if signed.is_empty() {
+        return false;
+    }
+    let pairs = signed.iter().map(|(key, msg)| (key.point, hash_to_g2(msg, DST_SIGNATURE)));
+    pairings_cancel(pairs.chain(core::iter::once((g1_generator().neg(), signature.point))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+    use std::vec::Vec;
+
+    /// A key derived by KeyGen from `IKM` and `KEY_INFO`, its public key,
+    /// its signature of `MESSAGE` and its proof of possession.
+    const IKM: &str = "07070707070707070707070707070707070707070707070707070707070707070707070707070707";
+    const KEY_INFO: &[u8] = b"info";
+    const MESSAGE: &[u8] = b"abc";
+    const SECRET_KEY: &str = "21c096ab5fb5470a638fd14192b3c46a571a2ee0602428f22681b70609e4d926";
+    const PUBLIC_KEY: &str =
+        "98c7e0dee1d99ac51913b4dd0cb4258a23f061e095fbbcb3e5ed9901bddbe690deef10e726335ae4f947ccd04e44868f";
+    const SIGNATURE: &str = "a27b61fb81ae7bf19e5fad061055951f04ad986cb850b77cbb1cf6def0faad4dc7eb88207d9a6d817f017a0419f23042\
+        1410a953298ae0fa13f672aa8c9b77c8028c8c92d9e3c1bd86ecac5d8abedcef7be2ab791d277244aa85c3f655e6d08a";
+    const PROOF: &str = "b600ce4a81617daf8d8267ba8d65d45d65cb0facaebb35d50abc89324a07c0a7b8ec636ed4850ebe08af90a2e53e5b89\
+        17ea9908f0e44a68a7b3915ce342e583e7da1eb2ccd0359570982efb3186bde136af00c65738e61d71d8a5b601ed65fd";
+
+    fn signature(bytes: &str) -> BlsSignature {
+        BlsSignature::from_bytes(&hex(bytes).try_into().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn known_answer() {
+        let key = BlsSecretKey::from_ikm(&hex(IKM), KEY_INFO).unwrap();
+        assert_eq!(key.to_bytes().expose_secret()[..], hex(SECRET_KEY)[..]);
+        let public = key.public_key();
+        assert_eq!(public.to_bytes()[..], hex(PUBLIC_KEY)[..]);
+        assert_eq!(key.sign(MESSAGE).to_bytes()[..], hex(SIGNATURE)[..]);
+        assert_eq!(key.prove_possession().to_bytes()[..], hex(PROOF)[..]);
+
+        let public = BlsPublicKey::from_bytes(&public.to_bytes()).unwrap();
+        assert!(public.verify(MESSAGE, &signature(SIGNATURE)));
+        assert!(!public.verify(b"abd", &signature(SIGNATURE)));
+        assert!(public.verify_possession(&signature(PROOF)));
+        assert!(!public.verify_possession(&signature(SIGNATURE)));
+
+        // The public key of 1 is the generator of G1.
+        let mut one = [0_u8; BLS_SECRET_KEY_SIZE];
+        one[31] = 1;
+        assert_eq!(
+            BlsSecretKey::from_bytes(&one).unwrap().public_key().to_bytes()[..],
+            hex("97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb")[..]
+        );
+    }
+
+    #[test]
+    fn aggregates() {
+        let keys: Vec<BlsSecretKey> =
+            (1..4_u8).map(|i| BlsSecretKey::from_ikm(&[i; 32], &[]).unwrap()).collect();
+        let public: Vec<BlsPublicKey> = keys.iter().map(BlsSecretKey::public_key).collect();
+        let same: Vec<BlsSignature> = keys.iter().map(|key| key.sign(MESSAGE)).collect();
+        let aggregate = BlsSignature::aggregate(&same).unwrap();
+        assert!(fast_aggregate_verify(&public, MESSAGE, &aggregate));
+        assert!(!fast_aggregate_verify(&public[..2], MESSAGE, &aggregate));
+        assert!(!fast_aggregate_verify(&[], MESSAGE, &aggregate));
+
+        let messages: [&[u8]; 3] = [b"a", b"b", b"c"];
+        let distinct: Vec<BlsSignature> = keys.iter().zip(messages).map(|(key, msg)| key.sign(msg)).collect();
+        let aggregate = BlsSignature::aggregate(&distinct).unwrap();
+        let signed: Vec<(BlsPublicKey, &[u8])> = public.iter().copied().zip(messages).collect();
+        assert!(aggregate_verify(&signed, &aggregate));
+        assert!(!aggregate_verify(&signed[..2], &aggregate));
+    }
+
+    #[test]
+    fn rejects_invalid_encodings() {
+        assert!(BlsSecretKey::from_bytes(&[0; BLS_SECRET_KEY_SIZE]).is_err());
+        assert!(BlsSecretKey::from_bytes(&[0xff; BLS_SECRET_KEY_SIZE]).is_err());
+
+        // The identity isn't a valid public key.
+        let mut identity = [0_u8; BLS_PUBLIC_KEY_SIZE];
+        identity[0] = 0xc0;
+        assert!(BlsPublicKey::from_bytes(&identity).is_err());
+        // Nor is an uncompressed encoding.
+        let mut uncompressed = hex(PUBLIC_KEY);
+        uncompressed[0] &= 0x7f;
+        assert!(BlsPublicKey::from_bytes(&uncompressed.try_into().unwrap()).is_err());
+        // Points of the curve with small x are outside the subgroup.
+        for x in 1..50 {
+            let mut point = [0_u8; BLS_PUBLIC_KEY_SIZE];
+            point[0] = 0x80;
+            point[BLS_PUBLIC_KEY_SIZE - 1] = x;
+            assert!(BlsPublicKey::from_bytes(&point).is_err());
+        }
+        // A bit flip leaves either no point or some other signature.
+        let public = BlsPublicKey::from_bytes(&hex(PUBLIC_KEY).try_into().unwrap()).unwrap();
+        let mut bad = hex(SIGNATURE);
+        bad[BLS_SIGNATURE_SIZE - 1] ^= 1;
+        if let Ok(bad) = BlsSignature::from_bytes(&bad.try_into().unwrap()) {
+            assert!(!public.verify(MESSAGE, &bad));
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! The ChaCha20 stream cipher and the HChaCha20 subkey derivation (RFC 8439,
+//! draft-irtf-cfrg-xchacha).
+
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+#[inline(always)]
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// Runs the 20 rounds over `state`.
+fn rounds(state: &mut [u32; 16]) {
+    for _ in 0..10 {
+        quarter_round(state, 0, 4, 8, 12);
+        quarter_round(state, 1, 5, 9, 13);
+        quarter_round(state, 2, 6, 10, 14);
+        quarter_round(state, 3, 7, 11, 15);
+        quarter_round(state, 0, 5, 10, 15);
+        quarter_round(state, 1, 6, 11, 12);
+        quarter_round(state, 2, 7, 8, 13);
+        quarter_round(state, 3, 4, 9, 14);
+    }
+}
+
+fn load_words<const N: usize>(bytes: &[u8]) -> [u32; N] {
+    let mut words = [0_u32; N];
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    words
+}
+
+fn initial_state(key: &[u8; 32], input: &[u8; 16]) -> [u32; 16] {
+    let mut state = [0_u32; 16];
+    state[..4].copy_from_slice(&CONSTANTS);
+    state[4..12].copy_from_slice(&load_words::<8>(key));
+    state[12..].copy_from_slice(&load_words::<4>(input));
+    state
+}
+
+/// ChaCha20 with a 96-bit nonce and a 32-bit block counter.
+pub(crate) struct ChaCha20 {
+    state: [u32; 16],
+}
+
+impl ChaCha20 {
+    pub(crate) fn new(key: &[u8; 32], nonce: &[u8; 12], counter: u32) -> ChaCha20 {
+        let mut input = [0_u8; 16];
+        input[..4].copy_from_slice(&counter.to_le_bytes());
+        input[4..].copy_from_slice(nonce);
+        ChaCha20 { state: initial_state(key, &input) }
+    }
+
+    /// Returns the keystream block at the current counter, and moves to the
+    /// next block.
+    pub(crate) fn next_block(&mut self) -> [u8; 64] {
+        let mut working = self.state;
+        rounds(&mut working);
+        let mut block = [0_u8; 64];
+        for (i, chunk) in block.chunks_exact_mut(4).enumerate() {
+            chunk.copy_from_slice(&working[i].wrapping_add(self.state[i]).to_le_bytes());
+        }
+        wipe(&mut working);
+        self.state[12] = self.state[12].wrapping_add(1);
+        block
+    }
+
+    /// XORs `src` with the keystream into `dst`, which is as long as `src`.
+    pub(crate) fn apply(&mut self, src: &[u8], dst: &mut [u8]) {
+        for (src, dst) in src.chunks(64).zip(dst.chunks_mut(64)) {
+            let block = self.next_block();
+            for ((d, s), k) in dst.iter_mut().zip(src).zip(block.iter()) {
+                *d = s ^ k;
+            }
+        }
+    }
+}
+
+impl Drop for ChaCha20 {
+    fn drop(&mut self) {
+        wipe(&mut self.state);
+    }
+}
+
+fn wipe(words: &mut [u32]) {
+    for word in words.iter_mut() {
+        unsafe { core::ptr::write_volatile(word, 0) };
+    }
+}
+
+/// Derives a subkey from `key` and the first 16 bytes of an extended nonce.
+pub(crate) fn hchacha20(key: &[u8; 32], input: &[u8; 16]) -> [u8; 32] {
+    let mut state = initial_state(key, input);
+    rounds(&mut state);
+    let mut subkey = [0_u8; 32];
+    for (i, &word) in state[..4].iter().chain(&state[12..]).enumerate() {
+        subkey[4 * i..4 * i + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    wipe(&mut state);
+    subkey
+}
@@ -0,0 +1,693 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Fixed-width unsigned integers and Montgomery arithmetic, for protocols
+//! needing moduli larger than the curves' with secret operands.
+//!
+//! An integer is a [`Uint`] of `L` 64-bit limbs, and [`Monty`] computes
+//! modulo an odd integer of the same width, in the Montgomery domain.
+//!
+//! # Constant time
+//!
+//! Unless named `_vartime`, operations take time depending only on the
+//! widths involved and on explicit bit counts such as the exponent length
+//! of [`Monty::pow`], not on the values: they don't branch on them or index
+//! memory with them, comparisons return their result without an early exit,
+//! and `==` is [`Uint::ct_eq`]. This holds for secret moduli too, such as
+//! the primes of an RSA key, once [`Monty::new`] has checked that the
+//! modulus is odd.
+//!
+//! The exceptions are:
+//!
+//! - functions named `_vartime`, for public values only;
+//! - [`Uint::from_be_bytes`], whose time depends on the length of its
+//!   input, and whose result tells whether the value fits;
+//! - [`Uint::random_below`], which samples until a value is in range, so
+//!   that its time depends on the bound and on the rejected samples only;
+//! - [`is_probable_prime`] and [`random_prime`], which sieve candidates by
+//!   small primes in variable time and stop at the first witness, as prime
+//!   generation must.
+//!
+//! Integers are `Copy`, so values can't zeroize themselves on drop; call
+//! [`Uint::wipe`] and [`Monty::wipe`] on secrets instead.
+
+use crate::util::read_rand;
+use sgx_types::*;
+
+/// An integer of `L` 64-bit limbs, least significant first.
+#[derive(Clone, Copy)]
+pub struct Uint<const L: usize>(pub [u64; L]);
+
+/// Returns all ones if `choice` is 1 and zero if it is 0.
+fn mask(choice: u64) -> u64 {
+    0_u64.wrapping_sub(choice)
+}
+
+impl<const L: usize> Uint<L> {
+    pub const ZERO: Uint<L> = Uint([0; L]);
+
+    pub fn from_u64(value: u64) -> Uint<L> {
+        let mut out = Uint::ZERO;
+        out.0[0] = value;
+        out
+    }
+
+    /// Parses a big-endian integer, failing if it doesn't fit.
+    pub fn from_be_bytes(bytes: &[u8]) -> Option<Uint<L>> {
+        let mut out = Uint::ZERO;
+        let mut overflow = 0_u8;
+        for (i, byte) in bytes.iter().rev().enumerate() {
+            if i >= 8 * L {
+                overflow |= *byte;
+                continue;
+            }
+            out.0[i / 8] |= (*byte as u64) << (8 * (i % 8));
+        }
+        if overflow != 0 {
+            out.wipe();
+            return None;
+        }
+        Some(out)
+    }
+
+    /// Writes the low `out.len()` bytes, big-endian.
+    pub fn write_be_bytes(&self, out: &mut [u8]) {
+        let len = out.len();
+        for (i, byte) in out.iter_mut().enumerate() {
+            let pos = len - 1 - i;
+            *byte = if pos < 8 * L { (self.0[pos / 8] >> (8 * (pos % 8))) as u8 } else { 0 };
+        }
+    }
+
+    /// Zero-extends or truncates to `M` limbs.
+    pub fn resize<const M: usize>(&self) -> Uint<M> {
+        let mut out = Uint::<M>::ZERO;
+        for (o, l) in out.0.iter_mut().zip(self.0.iter()) {
+            *o = *l;
+        }
+        out
+    }
+
+    /// Adds `other` in place, returning the carry.
+    pub fn adc(&mut self, other: &Uint<L>) -> u64 {
+        let mut carry = 0_u64;
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            let (s, c1) = a.overflowing_add(*b);
+            let (s, c2) = s.overflowing_add(carry);
+            *a = s;
+            carry = (c1 | c2) as u64;
+        }
+        carry
+    }
+
+    /// Subtracts `other` in place, returning the borrow.
+    pub fn sbb(&mut self, other: &Uint<L>) -> u64 {
+        let mut borrow = 0_u64;
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            let (d, b1) = a.overflowing_sub(*b);
+            let (d, b2) = d.overflowing_sub(borrow);
+            *a = d;
+            borrow = (b1 | b2) as u64;
+        }
+        borrow
+    }
+
+    pub fn wrapping_add(&self, other: &Uint<L>) -> Uint<L> {
+        let mut out = *self;
+        out.adc(other);
+        out
+    }
+
+    pub fn wrapping_sub(&self, other: &Uint<L>) -> Uint<L> {
+        let mut out = *self;
+        out.sbb(other);
+        out
+    }
+
+    pub fn lt(&self, other: &Uint<L>) -> bool {
+        let mut tmp = *self;
+        tmp.sbb(other) == 1
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().fold(0, |acc, l| acc | l) == 0
+    }
+
+    /// Compares in constant time, as `==` does.
+    pub fn ct_eq(&self, other: &Uint<L>) -> bool {
+        self.wrapping_sub(other).is_zero()
+    }
+
+    pub fn is_odd(&self) -> bool {
+        self.0[0] & 1 == 1
+    }
+
+    pub fn bit(&self, i: usize) -> u64 {
+        (self.0[i / 64] >> (i % 64)) & 1
+    }
+
+    /// Returns the position of the highest set bit plus one.
+    pub fn bits_vartime(&self) -> usize {
+        for i in (0..L).rev() {
+            if self.0[i] != 0 {
+                return 64 * i + 64 - self.0[i].leading_zeros() as usize;
+            }
+        }
+        0
+    }
+
+    /// Returns `b` if `choice` is 1 and `a` if it is 0; other values of
+    /// `choice` mix `a` and `b`.
+    pub fn select(a: &Uint<L>, b: &Uint<L>, choice: u64) -> Uint<L> {
+        let m = mask(choice);
+        let mut out = *a;
+        for (o, b) in out.0.iter_mut().zip(b.0.iter()) {
+            *o ^= m & (*o ^ b);
+        }
+        out
+    }
+
+    pub fn shr1(&self) -> Uint<L> {
+        let mut out = Uint::ZERO;
+        for i in 0..L {
+            let high = if i + 1 < L { self.0[i + 1] << 63 } else { 0 };
+            out.0[i] = (self.0[i] >> 1) | high;
+        }
+        out
+    }
+
+    /// Returns the low `L` limbs of `self * other`.
+    pub fn wrapping_mul(&self, other: &Uint<L>) -> Uint<L> {
+        let mut out = Uint::ZERO;
+        for i in 0..L {
+            let mut carry = 0_u128;
+            for j in 0..L - i {
+                let t = out.0[i + j] as u128 + self.0[i] as u128 * other.0[j] as u128 + carry;
+                out.0[i + j] = t as u64;
+                carry = t >> 64;
+            }
+        }
+        out
+    }
+
+    /// Returns the remainder of the division by a small divisor, in time
+    /// depending on the value on some CPUs.
+    pub fn rem_u32_vartime(&self, d: u32) -> u32 {
+        let mut rem = 0_u128;
+        for limb in self.0.iter().rev() {
+            rem = ((rem << 64) | *limb as u128) % d as u128;
+        }
+        rem as u32
+    }
+
+    /// Returns the inverse of an odd `self` modulo `2^(64 L)`, by Newton's
+    /// iteration.
+    pub fn inv_2exp(&self) -> Uint<L> {
+        let two = Uint::from_u64(2);
+        let mut x = Uint::from_u64(1);
+        // Each step doubles the number of correct low bits.
+        let mut bits = 1;
+        while bits < 64 * L {
+            x = x.wrapping_mul(&two.wrapping_sub(&self.wrapping_mul(&x)));
+            bits *= 2;
+        }
+        x
+    }
+
+    /// Returns `self / d` for a `d` known to divide `self` exactly.
+    pub fn div_exact(&self, d: &Uint<L>) -> Uint<L> {
+        self.wrapping_mul(&d.inv_2exp())
+    }
+
+    /// Returns a random integer of up to `bits` bits.
+    pub fn random_bits(bits: usize) -> SgxResult<Uint<L>> {
+        let mut bytes = [0_u8; 8];
+        let mut out = Uint::<L>::ZERO;
+        for (i, limb) in out.0.iter_mut().enumerate() {
+            if 64 * i >= bits {
+                break;
+            }
+            read_rand(&mut bytes)?;
+            *limb = u64::from_le_bytes(bytes);
+            if bits < 64 * (i + 1) {
+                *limb &= (1 << (bits - 64 * i)) - 1;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns a uniformly random integer in `[0, bound)`, for `bound > 0`.
+    pub fn random_below(bound: &Uint<L>) -> SgxResult<Uint<L>> {
+        let bits = bound.bits_vartime();
+        loop {
+            let out = Uint::random_bits(bits)?;
+            if out.lt(bound) {
+                return Ok(out);
+            }
+        }
+    }
+
+    /// Returns `self^-1 mod m` for an odd `m`, or `None` if they aren't
+    /// coprime, with the binary extended GCD run for a fixed number of
+    /// steps.
+    pub fn inv_mod_odd(&self, m: &Uint<L>) -> Option<Uint<L>> {
+        let mut a = *self;
+        let mut b = *m;
+        let mut u = Uint::from_u64(1);
+        let mut v = Uint::ZERO;
+        // a = u * x and b = v * x (mod m) throughout.
+        for _ in 0..2 * 64 * L {
+            let odd = a.0[0] & 1;
+            let swap = odd & a.lt(&b) as u64;
+            let (na, nb) = (Uint::select(&a, &b, swap), Uint::select(&b, &a, swap));
+            let (nu, nv) = (Uint::select(&u, &v, swap), Uint::select(&v, &u, swap));
+            a = Uint::select(&na, &na.wrapping_sub(&nb), odd);
+            b = nb;
+            v = nv;
+            let mut diff = nu;
+            let borrow = diff.sbb(&nv);
+            let mut fixed = diff;
+            fixed.adc(&Uint::select(&Uint::ZERO, m, borrow));
+            u = Uint::select(&nu, &fixed, odd);
+
+            a = a.shr1();
+            // Halve u modulo m, adding m first if u is odd.
+            let mut sum = u;
+            let carry = sum.adc(&Uint::select(&Uint::ZERO, m, u.0[0] & 1));
+            u = sum.shr1();
+            u.0[L - 1] |= carry << 63;
+        }
+        let one = Uint::from_u64(1);
+        if b == one {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    /// Overwrites the limbs with zeroes, in a way the compiler won't remove.
+    pub fn wipe(&mut self) {
+        for limb in self.0.iter_mut() {
+            unsafe { core::ptr::write_volatile(limb, 0) };
+        }
+    }
+}
+
+impl<const L: usize> PartialEq for Uint<L> {
+    fn eq(&self, other: &Uint<L>) -> bool {
+        self.ct_eq(other)
+    }
+}
+
+impl<const L: usize> Eq for Uint<L> {}
+
+/// Arithmetic modulo an odd `m`, in the Montgomery domain.
+#[derive(Clone, Copy)]
+pub struct Monty<const L: usize> {
+    m: Uint<L>,
+    /// `-m^-1 mod 2^64`.
+    inv: u64,
+    /// `2^(128 L) mod m`.
+    r2: Uint<L>,
+    /// `2^(64 L) mod m`, the representation of 1.
+    one: Uint<L>,
+}
+
+impl<const L: usize> Monty<L> {
+    /// Precomputes the constants for `m`, or returns `None` unless it is odd
+    /// and above 1.
+    pub fn new(m: &Uint<L>) -> Option<Monty<L>> {
+        if !m.is_odd() || *m == Uint::from_u64(1) {
+            return None;
+        }
+        let mut inv = 1_u64;
+        for _ in 0..6 {
+            inv = inv.wrapping_mul(2_u64.wrapping_sub(m.0[0].wrapping_mul(inv)));
+        }
+        let inv = inv.wrapping_neg();
+
+        let mut monty = Monty { m: *m, inv, r2: Uint::ZERO, one: Uint::ZERO };
+        let mut x = Uint::from_u64(1);
+        for i in 0..2 * 64 * L {
+            if i == 64 * L {
+                monty.one = x;
+            }
+            x = monty.add(&x, &x);
+        }
+        monty.r2 = x;
+        Some(monty)
+    }
+
+    /// Returns the arithmetic modulo a constant `m`, from `inv`, `r2` and
+    /// `one` as [`Monty::new`] would compute them.
+    pub(crate) const fn with_constants(
+        m: Uint<L>,
+        inv: u64,
+        r2: Uint<L>,
+        one: Uint<L>,
+    ) -> Monty<L> {
+        Monty { m, inv, r2, one }
+    }
+
+    pub fn modulus(&self) -> &Uint<L> {
+        &self.m
+    }
+
+    /// Reduces `carry * 2^(64 L) + a`, known to be below `2m`.
+    fn reduce_once(&self, a: &Uint<L>, carry: u64) -> Uint<L> {
+        let mut d = *a;
+        let borrow = d.sbb(&self.m);
+        Uint::select(a, &d, carry | (borrow ^ 1))
+    }
+
+    /// Adds residues, which needn't be in the Montgomery domain.
+    pub fn add(&self, a: &Uint<L>, b: &Uint<L>) -> Uint<L> {
+        let mut s = *a;
+        let carry = s.adc(b);
+        self.reduce_once(&s, carry)
+    }
+
+    /// Subtracts residues below `m`.
+    pub fn sub(&self, a: &Uint<L>, b: &Uint<L>) -> Uint<L> {
+        let mut d = *a;
+        let borrow = d.sbb(b);
+        d.adc(&Uint::select(&Uint::ZERO, &self.m, borrow));
+        d
+    }
+
+    /// Returns `a * b / 2^(64 L) mod m`, for `a < 2^(64 L)` and `b < m`.
+    pub fn mul(&self, a: &Uint<L>, b: &Uint<L>) -> Uint<L> {
+        let mut t = [0_u64; L];
+        let mut top = 0_u64;
+        for bi in b.0.iter() {
+            let mut c = 0_u128;
+            for (tj, aj) in t.iter_mut().zip(a.0.iter()) {
+                let s = *tj as u128 + *aj as u128 * *bi as u128 + c;
+                *tj = s as u64;
+                c = s >> 64;
+            }
+            let s = top as u128 + c;
+            top = s as u64;
+            let over = (s >> 64) as u64;
+
+            let q = t[0].wrapping_mul(self.inv);
+            let mut c = (t[0] as u128 + q as u128 * self.m.0[0] as u128) >> 64;
+            for j in 1..L {
+                let s = t[j] as u128 + q as u128 * self.m.0[j] as u128 + c;
+                t[j - 1] = s as u64;
+                c = s >> 64;
+            }
+            let s = top as u128 + c;
+            t[L - 1] = s as u64;
+            top = over + (s >> 64) as u64;
+        }
+        self.reduce_once(&Uint(t), top)
+    }
+
+    pub fn square(&self, a: &Uint<L>) -> Uint<L> {
+        self.mul(a, a)
+    }
+
+    /// Converts into the Montgomery domain, reducing any value.
+    pub fn encode(&self, a: &Uint<L>) -> Uint<L> {
+        self.mul(a, &self.r2)
+    }
+
+    pub fn decode(&self, a: &Uint<L>) -> Uint<L> {
+        self.mul(a, &Uint::from_u64(1))
+    }
+
+    /// Reduces any value modulo `m`.
+    pub fn reduce(&self, a: &Uint<L>) -> Uint<L> {
+        self.decode(&self.encode(a))
+    }
+
+    /// Returns `a * b mod m` of values outside the Montgomery domain.
+    pub fn mul_mod(&self, a: &Uint<L>, b: &Uint<L>) -> Uint<L> {
+        self.mul(&self.reduce(a), &self.encode(b))
+    }
+
+    /// Overwrites the modulus and its constants, for secret moduli.
+    pub fn wipe(&mut self) {
+        self.m.wipe();
+        self.r2.wipe();
+        self.one.wipe();
+    }
+
+    /// Returns `base^exp mod m`, using the lowest `exp_bits` bits of `exp`,
+    /// in time independent of both.
+    pub fn pow<const E: usize>(
+        &self,
+        base: &Uint<L>,
+        exp: &Uint<E>,
+        exp_bits: usize,
+    ) -> Uint<L> {
+        let base = self.encode(base);
+        let mut acc = self.one;
+        for i in (0..exp_bits).rev() {
+            acc = self.square(&acc);
+            let product = self.mul(&acc, &base);
+            acc = Uint::select(&acc, &product, exp.bit(i));
+        }
+        self.decode(&acc)
+    }
+
+    /// Returns `base^exp mod m`, in time depending on `exp` but not `base`.
+    pub fn pow_vartime<const E: usize>(&self, base: &Uint<L>, exp: &Uint<E>) -> Uint<L> {
+        let base = self.encode(base);
+        let mut acc = self.one;
+        for i in (0..exp.bits_vartime()).rev() {
+            acc = self.square(&acc);
+            if exp.bit(i) == 1 {
+                acc = self.mul(&acc, &base);
+            }
+        }
+        self.decode(&acc)
+    }
+}
+
+/// Calls `f` with the odd primes below `limit`, at most 2^16, until it
+/// returns false.
+pub(crate) fn for_each_small_prime<F: FnMut(u32) -> bool>(limit: u32, mut f: F) {
+    // Bit i stands for 2i + 1.
+    let mut composite = [0_u64; 512];
+    let limit = core::cmp::min(limit, 1 << 16);
+    let mut n = 3;
+    while n < limit {
+        let i = (n / 2) as usize;
+        if (composite[i / 64] >> (i % 64)) & 1 == 0 {
+            if !f(n) {
+                return;
+            }
+            let mut multiple = n * n;
+            while multiple < limit {
+                let j = (multiple / 2) as usize;
+                composite[j / 64] |= 1 << (j % 64);
+                multiple += 2 * n;
+            }
+        }
+        n += 2;
+    }
+}
+
+/// Returns whether an odd `n` is probably prime, by Miller-Rabin with
+/// `rounds` random bases.
+pub fn is_probable_prime<const L: usize>(n: &Uint<L>, rounds: usize) -> SgxResult<bool> {
+    let monty = match Monty::new(n) {
+        Some(monty) => monty,
+        None => return Ok(false),
+    };
+    let one = Uint::from_u64(1);
+    let minus_one = n.wrapping_sub(&one);
+    let mut d = minus_one;
+    let mut s = 0;
+    while !d.is_odd() {
+        d = d.shr1();
+        s += 1;
+    }
+    let bound = n.wrapping_sub(&Uint::from_u64(3));
+    for _ in 0..rounds {
+        let base = Uint::random_below(&bound)?.wrapping_add(&Uint::from_u64(2));
+        let mut x = monty.pow(&base, &d, 64 * L);
+        if x == one || x == minus_one {
+            continue;
+        }
+        let mut witness = true;
+        for _ in 1..s {
+            x = monty.mul_mod(&x, &x);
+            if x == minus_one {
+                witness = false;
+                break;
+            }
+        }
+        if witness {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Generates a random prime of exactly `64 L` bits, with its top two bits
+/// set so that products of two such primes have exactly twice the bits.
+pub fn random_prime<const L: usize>() -> SgxResult<Uint<L>> {
+    loop {
+        let mut candidate = Uint::<L>::random_bits(64 * L)?;
+        candidate.0[L - 1] |= 0xc000_0000_0000_0000;
+        candidate.0[0] |= 1;
+        let mut sieved = true;
+        for_each_small_prime(2048, |p| {
+            sieved = candidate.rem_u32_vartime(p) != 0;
+            sieved
+        });
+        if sieved && is_probable_prime(&candidate, 40)? {
+            return Ok(candidate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2^128 - 159, a prime filling both limbs.
+    const P128: u128 = u128::MAX - 158;
+
+    fn uint(x: u128) -> Uint<2> {
+        Uint([x as u64, (x >> 64) as u64])
+    }
+
+    fn int(x: &Uint<2>) -> u128 {
+        x.0[0] as u128 | (x.0[1] as u128) << 64
+    }
+
+    /// Values around the limb boundaries and the modulus.
+    fn edges() -> [u128; 12] {
+        let limb = u64::MAX as u128;
+        [
+            0,
+            1,
+            2,
+            limb - 1,
+            limb,
+            limb + 1,
+            limb + 2,
+            1 << 127,
+            P128 - 1,
+            P128,
+            u128::MAX - 1,
+            u128::MAX,
+        ]
+    }
+
+    fn add_mod(a: u128, b: u128, m: u128) -> u128 {
+        let (s, carry) = a.overflowing_add(b);
+        if carry || s >= m {
+            s.wrapping_sub(m)
+        } else {
+            s
+        }
+    }
+
+    fn mul_mod(a: u128, b: u128, m: u128) -> u128 {
+        let a = a % m;
+        let mut r = 0;
+        for i in (0..128).rev() {
+            r = add_mod(r, r, m);
+            if (b >> i) & 1 == 1 {
+                r = add_mod(r, a, m);
+            }
+        }
+        r
+    }
+
+    #[test]
+    fn limb_arithmetic() {
+        for a in edges() {
+            let x = uint(a);
+            assert_eq!(int(&x.shr1()), a >> 1);
+            assert_eq!(x.bits_vartime(), 128 - a.leading_zeros() as usize);
+            assert_eq!(int(&Uint::from_be_bytes(&a.to_be_bytes()).unwrap()), a);
+            let mut bytes = [0_u8; 17];
+            x.write_be_bytes(&mut bytes);
+            assert_eq!(bytes[1..], a.to_be_bytes());
+            assert_eq!(int(&Uint::from_be_bytes(&bytes).unwrap()), a);
+            assert_eq!(int(&x.resize::<3>().resize()), a);
+            assert_eq!(x.resize::<1>().0[0], a as u64);
+            for b in edges() {
+                let y = uint(b);
+                let mut sum = x;
+                assert_eq!(sum.adc(&y) == 1, a.overflowing_add(b).1);
+                assert_eq!(int(&sum), a.wrapping_add(b));
+                let mut diff = x;
+                assert_eq!(diff.sbb(&y) == 1, a < b);
+                assert_eq!(int(&diff), a.wrapping_sub(b));
+                assert_eq!(int(&x.wrapping_mul(&y)), a.wrapping_mul(b));
+                assert_eq!(x.lt(&y), a < b);
+                assert_eq!(x == y, a == b);
+                assert_eq!(int(&Uint::select(&x, &y, 1)), b);
+                assert_eq!(int(&Uint::select(&x, &y, 0)), a);
+            }
+        }
+        assert!(Uint::<2>::from_be_bytes(&[1; 17]).is_none());
+        assert!(Uint::<2>::from_be_bytes(&[]).unwrap().is_zero());
+        assert_eq!(uint(u128::MAX).rem_u32_vartime(65521), (u128::MAX % 65521) as u32);
+        assert_eq!(int(&uint(3).wrapping_mul(&uint(3).inv_2exp())), 1);
+        assert_eq!(int(&uint(21 << 70).div_exact(&uint(7))), 3 << 70);
+    }
+
+    #[test]
+    fn montgomery_reduction() {
+        let m = uint(P128);
+        let monty = Monty::new(&m).unwrap();
+        assert!(*monty.modulus() == m);
+        for a in edges() {
+            let x = uint(a);
+            assert_eq!(int(&monty.reduce(&x)), a % P128);
+            assert_eq!(int(&monty.decode(&monty.encode(&x))), a % P128);
+            for b in edges() {
+                let y = uint(b);
+                assert_eq!(int(&monty.mul_mod(&x, &y)), mul_mod(a, b, P128), "{:x} {:x}", a, b);
+                let (a, b) = (a % P128, b % P128);
+                let (x, y) = (uint(a), uint(b));
+                assert_eq!(int(&monty.add(&x, &y)), add_mod(a, b, P128));
+                assert_eq!(int(&monty.sub(&x, &y)), add_mod(a, P128 - b, P128));
+            }
+        }
+
+        let base = uint((u64::MAX as u128) << 32 | 12345);
+        let exp = uint(P128 - 2);
+        let inverse = monty.pow(&base, &exp, 128);
+        assert!(monty.pow_vartime(&base, &exp) == inverse);
+        assert!(base.inv_mod_odd(&m).unwrap() == inverse);
+        assert_eq!(int(&monty.mul_mod(&base, &inverse)), 1);
+        assert!(monty.pow(&base, &uint(0), 128) == uint(1));
+        assert!(is_probable_prime(&m, 20).unwrap());
+
+        // A single limb, and moduli that aren't odd, prime or above 1.
+        let p64 = Uint::<1>::from_u64(u64::MAX - 58);
+        let monty = Monty::new(&p64).unwrap();
+        let x = Uint::from_u64(u64::MAX);
+        assert_eq!(
+            monty.mul_mod(&x, &x).0[0] as u128,
+            (u64::MAX as u128).pow(2) % p64.0[0] as u128
+        );
+        assert!(Monty::new(&Uint::<2>::from_u64(1)).is_none());
+        assert!(Monty::new(&uint(1 << 64)).is_none());
+        assert!(Uint::from_u64(5).inv_mod_odd(&Uint::<1>::from_u64(15)).is_none());
+        assert!(!is_probable_prime(&Uint::<1>::from_u64(561), 20).unwrap());
+    }
+}
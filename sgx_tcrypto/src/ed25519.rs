@@ -0,0 +1,594 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Ed25519 signatures (RFC 8032).
+//!
+//! Signing is constant time in the private key. Private keys are zeroized
+//! on drop and can't be printed, like the ECDSA keys elsewhere in this
+//! crate.
+
+use crate::field25519::Fe;
+use crate::msm::{straus, wnaf, Group, Table, DIGITS};
+use crate::secret::{Secret, SecretBytes};
+use crate::sha512::Sha512;
+use crate::util::{ct_eq, read_rand, zeroize};
+use core::fmt;
+use sgx_types::*;
+
+/// The length of Ed25519 seeds and public keys, in bytes.
+pub const ED25519_KEY_SIZE: usize = 32;
+/// The length of Ed25519 signatures, in bytes.
+pub const ED25519_SIGNATURE_SIZE: usize = 64;
+
+/// `2 * d`, where `d = -121665 / 121666` defines the curve.
+const D2: Fe =
+    Fe([0x69b9426b2f159, 0x35050762add7a, 0x3cf44c0038052, 0x6738cc7407977, 0x2406d9dc56dff]);
+const D: Fe =
+    Fe([0x34dca135978a3, 0x1a8283b156ebd, 0x5e7a26001c029, 0x739c663a03cbb, 0x52036cee2b6ff]);
+const SQRT_M1: Fe =
+    Fe([0x61b274a0ea0b0, 0xd5a5fc8f189d, 0x7ef5e9cbd0c60, 0x78595a6804c9e, 0x2b8324804fc1d]);
+
+const BASE_POINT: Point = Point {
+    x: Fe([0x62d608f25d51a, 0x412a4b4f6592a, 0x75b7171a4b31d, 0x1ff60527118fe, 0x216936d3cd6e5]),
+    y: Fe([0x6666666666658, 0x4cccccccccccc, 0x1999999999999, 0x3333333333333, 0x6666666666666]),
+    z: Fe::ONE,
+    t: Fe([0x68ab3a5b7dda3, 0xeea2a5eadbb, 0x2af8df483c27e, 0x332b375274732, 0x67875f0fd78b7]),
+};
+
+/// The order of the base point, little-endian.
+const L: [u64; 4] = [0x5812_631a_5cf5_d3ed, 0x14de_f9de_a2f7_9cd6, 0, 0x1000_0000_0000_0000];
+
+/// A point in extended coordinates, `x = X/Z`, `y = Y/Z`, `x * y = T/Z`.
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Point = Point { x: Fe::ZERO, y: Fe::ONE, z: Fe::ONE, t: Fe::ZERO };
+
+    /// Adds with the unified formulas, which also double.
+    fn add(&self, other: &Point) -> Point {
+        let a = self.y.sub(&self.x).mul(&other.y.sub(&other.x));
+        let b = self.y.add(&self.x).mul(&other.y.add(&other.x));
+        let c = self.t.mul(&D2).mul(&other.t);
+        let d = self.z.add(&self.z).mul(&other.z);
+        let e = b.sub(&a);
+        let f = d.sub(&c);
+        let g = d.add(&c);
+        let h = b.add(&a);
+        Point { x: e.mul(&f), y: g.mul(&h), z: f.mul(&g), t: e.mul(&h) }
+    }
+
+    /// Doubles with the dedicated formulas, cheaper than adding.
+    fn double(&self) -> Point {
+        let a = self.x.square();
+        let b = self.y.square();
+        let c = self.z.square().add(&self.z.square());
+        let e = self.x.add(&self.y).square().sub(&a).sub(&b);
+        let g = b.sub(&a);
+        let f = g.sub(&c);
+        let h = a.add(&b).neg();
+        Point { x: e.mul(&f), y: g.mul(&h), z: f.mul(&g), t: e.mul(&h) }
+    }
+
+    fn neg(&self) -> Point {
+        Point { x: self.x.neg(), y: self.y, z: self.z, t: self.t.neg() }
+    }
+
+    fn is_identity(&self) -> bool {
+        self.x.is_zero() && self.y.ct_eq(&self.z)
+    }
+
+    fn cmov(&mut self, other: &Point, choice: u64) {
+        self.x.cmov(&other.x, choice);
+        self.y.cmov(&other.y, choice);
+        self.z.cmov(&other.z, choice);
+        self.t.cmov(&other.t, choice);
+    }
+
+    /// Computes `scalar * self`, doubling and adding for every bit.
+    fn mul(&self, scalar: &[u8; 32]) -> Point {
+        let mut acc = Point::IDENTITY;
+        for i in (0..256).rev() {
+            acc = acc.add(&acc);
+            let sum = acc.add(self);
+            acc.cmov(&sum, ((scalar[i / 8] >> (i % 8)) & 1) as u64);
+        }
+        acc
+    }
+
+    fn compress(&self) -> [u8; 32] {
+        let zinv = self.z.invert();
+        let x = self.x.mul(&zinv);
+        let mut bytes = self.y.mul(&zinv).to_bytes();
+        bytes[31] |= (x.is_negative() as u8) << 7;
+        bytes
+    }
+
+    /// Decodes a point, rejecting non-canonical encodings of `y`.
+    fn decompress(bytes: &[u8; 32]) -> Option<Point> {
+        let y = Fe::from_bytes(bytes);
+        let mut canonical = *bytes;
+        canonical[31] &= 0x7f;
+        if y.to_bytes() != canonical {
+            return None;
+        }
+        let sign = bytes[31] >> 7 == 1;
+
+        // x^2 = (y^2 - 1) / (d y^2 + 1)
+        let yy = y.square();
+        let u = yy.sub(&Fe::ONE);
+        let v = yy.mul(&D).add(&Fe::ONE);
+        let v3 = v.square().mul(&v);
+        let mut x = u.mul(&v3).mul(&u.mul(&v3).mul(&v3).mul(&v).pow_p58());
+        let vxx = v.mul(&x.square());
+        if !vxx.ct_eq(&u) {
+            if !vxx.ct_eq(&u.neg()) {
+                return None;
+            }
+            x = x.mul(&SQRT_M1);
+        }
+        if x.is_zero() && sign {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+        Some(Point { x, y, z: Fe::ONE, t: x.mul(&y) })
+    }
+}
+
+impl Group for Point {
+    fn identity() -> Point {
+        Point::IDENTITY
+    }
+
+    fn add(&self, other: &Point) -> Point {
+        Point::add(self, other)
+    }
+
+    fn double(&self) -> Point {
+        Point::double(self)
+    }
+
+    fn neg(&self) -> Point {
+        Point::neg(self)
+    }
+}
+
+/// Reduces a 512-bit little-endian value modulo `L`, in constant time.
+fn reduce(wide: &[u8; 64]) -> [u8; 32] {
+    let mut r = [0_u64; 4];
+    for i in (0..512).rev() {
+        // r < L, so 2r + 1 < 2L and one conditional subtraction does.
+        r[3] = (r[3] << 1) | (r[2] >> 63);
+        r[2] = (r[2] << 1) | (r[1] >> 63);
+        r[1] = (r[1] << 1) | (r[0] >> 63);
+        r[0] = (r[0] << 1) | ((wide[i / 8] >> (i % 8)) & 1) as u64;
+
+        let mut diff = [0_u64; 4];
+        let mut borrow = 0_u64;
+        for j in 0..4 {
+            let (d, b1) = r[j].overflowing_sub(L[j]);
+            let (d, b2) = d.overflowing_sub(borrow);
+            diff[j] = d;
+            borrow = (b1 | b2) as u64;
+        }
+        let keep = 0_u64.wrapping_sub(borrow);
+        for j in 0..4 {
+            r[j] = (r[j] & keep) | (diff[j] & !keep);
+        }
+    }
+    let mut out = [0_u8; 32];
+    for (j, limb) in r.iter().enumerate() {
+        out[j * 8..j * 8 + 8].copy_from_slice(&limb.to_le_bytes());
+    }
+    zeroize_words(&mut r);
+    out
+}
+
+/// Returns `(a * b + c) mod L`.
+fn mul_add(a: &[u8; 32], b: &[u8; 32], c: &[u8; 32]) -> [u8; 32] {
+    let load = |s: &[u8; 32], j: usize| {
+        let mut word = [0_u8; 8];
+        word.copy_from_slice(&s[j * 8..j * 8 + 8]);
+        u64::from_le_bytes(word)
+    };
+    let mut wide = [0_u64; 8];
+    for i in 0..4 {
+        let mut carry = 0_u128;
+        for j in 0..4 {
+            let t = load(a, i) as u128 * load(b, j) as u128 + wide[i + j] as u128 + carry;
+            wide[i + j] = t as u64;
+            carry = t >> 64;
+        }
+        wide[i + 4] = carry as u64;
+    }
+    let mut carry = 0_u128;
+    for (j, word) in wide.iter_mut().enumerate() {
+        let t = *word as u128 + if j < 4 { load(c, j) as u128 } else { 0 } + carry;
+        *word = t as u64;
+        carry = t >> 64;
+    }
+    let mut bytes = [0_u8; 64];
+    for (j, word) in wide.iter().enumerate() {
+        bytes[j * 8..j * 8 + 8].copy_from_slice(&word.to_le_bytes());
+    }
+    let out = reduce(&bytes);
+    zeroize_words(&mut wide);
+    zeroize(&mut bytes);
+    out
+}
+
+fn zeroize_words(words: &mut [u64]) {
+    for word in words.iter_mut() {
+        unsafe { core::ptr::write_volatile(word, 0) };
+    }
+}
+
+/// Returns whether a little-endian scalar is below `L`.
+fn is_canonical(scalar: &[u8; 32]) -> bool {
+    for i in (0..4).rev() {
+        let mut word = [0_u8; 8];
+        word.copy_from_slice(&scalar[i * 8..i * 8 + 8]);
+        let word = u64::from_le_bytes(word);
+        if word != L[i] {
+            return word < L[i];
+        }
+    }
+    false
+}
+
+fn challenge(r: &[u8], public: &[u8; 32], message: &[u8]) -> [u8; 32] {
+    reduce(&Sha512::digest(&[r, public, message]))
+}
+
+fn to_words(scalar: &[u8; 32]) -> [u64; 4] {
+    let mut words = [0_u64; 4];
+    for (i, word) in words.iter_mut().enumerate() {
+        let mut le = [0_u8; 8];
+        le.copy_from_slice(&scalar[i * 8..i * 8 + 8]);
+        *word = u64::from_le_bytes(le);
+    }
+    words
+}
+
+/// An Ed25519 private key, kept as its seed along with the expanded halves.
+pub struct Ed25519PrivateKey {
+    seed: SecretBytes<32>,
+    scalar: SecretBytes<32>,
+    prefix: SecretBytes<32>,
+    public: Ed25519PublicKey,
+}
+
+impl Ed25519PrivateKey {
+    /// Generates a key with the enclave's random number generator.
+    pub fn generate() -> SgxResult<Ed25519PrivateKey> {
+        let mut seed = SecretBytes::<32>::zeroed();
+        read_rand(seed.expose_secret_mut())?;
+        Ok(Ed25519PrivateKey::from_seed(seed.expose_secret()))
+    }
+
+    /// Creates the key derived from a 32-byte seed, as RFC 8032 defines
+    /// private keys.
+    pub fn from_seed(seed: &[u8; 32]) -> Ed25519PrivateKey {
+        let hash = Secret::new(Sha512::digest(&[seed]));
+        let mut scalar = SecretBytes::<32>::zeroed();
+        let mut prefix = SecretBytes::<32>::zeroed();
+        scalar.expose_secret_mut().copy_from_slice(&hash.expose_secret()[..32]);
+        prefix.expose_secret_mut().copy_from_slice(&hash.expose_secret()[32..]);
+        let s = scalar.expose_secret_mut();
+        s[0] &= 248;
+        s[31] &= 127;
+        s[31] |= 64;
+        let public = Ed25519PublicKey(BASE_POINT.mul(scalar.expose_secret()).compress());
+        Ed25519PrivateKey { seed: Secret::new(*seed), scalar, prefix, public }
+    }
+
+    /// Returns the seed, e.g. to seal it.
+    pub fn seed(&self) -> SecretBytes<32> {
+        self.seed.clone()
+    }
+
+    pub fn public_key(&self) -> Ed25519PublicKey {
+        self.public
+    }
+
+    /// Signs `message`. Signatures are deterministic.
+    pub fn sign(&self, message: &[u8]) -> Ed25519Signature {
+        let nonce = Secret::new(reduce(&Sha512::digest(&[self.prefix.expose_secret(), message])));
+        let r = BASE_POINT.mul(nonce.expose_secret()).compress();
+        let k = challenge(&r, &self.public.0, message);
+        let s = mul_add(&k, self.scalar.expose_secret(), nonce.expose_secret());
+
+        let mut signature = [0_u8; 64];
+        signature[..32].copy_from_slice(&r);
+        signature[32..].copy_from_slice(&s);
+        Ed25519Signature(signature)
+    }
+}
+
+impl fmt::Debug for Ed25519PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ed25519PrivateKey").field("public_key", &self.public).finish()
+    }
+}
+
+/// An Ed25519 public key, the encoding of a curve point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Ed25519PublicKey([u8; 32]);
+
+impl Ed25519PublicKey {
+    /// Parses a public key, failing with `SGX_ERROR_INVALID_PARAMETER`
+    /// unless it encodes a point of the curve.
+    pub fn from_bytes(bytes: &[u8; 32]) -> SgxResult<Ed25519PublicKey> {
+        match Point::decompress(bytes) {
+            Some(_) => Ok(Ed25519PublicKey(*bytes)),
+            None => Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Verifies `signature` over `message`, with the cofactorless equation
+    /// `[S]B = R + [k]A` and rejecting non-canonical `S`.
+    pub fn verify(&self, message: &[u8], signature: &Ed25519Signature) -> bool {
+        let point = match Point::decompress(&self.0) {
+            Some(point) => point,
+            None => return false,
+        };
+        let mut r = [0_u8; 32];
+        let mut s = [0_u8; 32];
+        r.copy_from_slice(&signature.0[..32]);
+        s.copy_from_slice(&signature.0[32..]);
+        if !is_canonical(&s) {
+            return false;
+        }
+        let k = challenge(&r, &self.0, message);
+        let check = BASE_POINT.mul(&s).add(&point.neg().mul(&k));
+        ct_eq(&check.compress(), &r)
+    }
+}
+
+/// The number of signatures combined in one multiplication, which bounds
+/// the stack taken by the tables of their points to about 20 KB.
+const BATCH_CHUNK: usize = 8;
+
+/// Verifies a batch of signatures, returning whether all of them are
+/// valid; [`Ed25519PublicKey::verify`] then tells which ones aren't.
+///
+/// A random linear combination of the verification equations is checked
+/// with a single multi-scalar multiplication for every eight signatures,
+/// and a public key repeated in those is decompressed once. The equation
+/// is the cofactored `[8][S]B = [8]R + [8][k]A`: it holds for everything
+/// the single verification accepts, and also for signatures with
+/// small-order components, which honest signers never produce. `false` is
+/// returned as well if the random number generator fails.
+pub fn verify_batch(items: &[(&Ed25519PublicKey, &[u8], &Ed25519Signature)]) -> bool {
+    let base = Table::new(&BASE_POINT.neg());
+    items.chunks(BATCH_CHUNK).all(|chunk| verify_chunk(&base, chunk))
+}
+
+fn verify_chunk(
+    base: &Table<Point>,
+    chunk: &[(&Ed25519PublicKey, &[u8], &Ed25519Signature)],
+) -> bool {
+    let n = chunk.len();
+    let mut weights = [0_u8; 16 * BATCH_CHUNK];
+    if read_rand(&mut weights[..16 * n]).is_err() {
+        return false;
+    }
+
+    // The terms of R_i come first, then those of the distinct keys.
+    let mut tables = [Table::new(&Point::IDENTITY); 2 * BATCH_CHUNK];
+    let mut scalars = [[0_u8; 32]; 2 * BATCH_CHUNK];
+    let mut keys = [[0_u8; 32]; BATCH_CHUNK];
+    let mut key_count = 0;
+    let mut base_scalar = [0_u8; 32];
+    for (i, (public, message, signature)) in chunk.iter().enumerate() {
+        let mut r = [0_u8; 32];
+        let mut s = [0_u8; 32];
+        r.copy_from_slice(&signature.0[..32]);
+        s.copy_from_slice(&signature.0[32..]);
+        if !is_canonical(&s) {
+            return false;
+        }
+        let point = match Point::decompress(&r) {
+            Some(point) => point,
+            None => return false,
+        };
+        let key = match keys[..key_count].iter().position(|key| *key == public.0) {
+            Some(key) => key,
+            None => {
+                let point = match Point::decompress(&public.0) {
+                    Some(point) => point,
+                    None => return false,
+                };
+                keys[key_count] = public.0;
+                tables[n + key_count] = Table::new(&point);
+                key_count += 1;
+                key_count - 1
+            }
+        };
+
+        let mut z = [0_u8; 32];
+        z[..16].copy_from_slice(&weights[16 * i..16 * i + 16]);
+        let k = challenge(&r, &public.0, message);
+        tables[i] = Table::new(&point);
+        scalars[i] = z;
+        scalars[n + key] = mul_add(&z, &k, &scalars[n + key]);
+        base_scalar = mul_add(&z, &s, &base_scalar);
+    }
+
+    let count = n + key_count;
+    let mut digits = [[0_i8; DIGITS]; 2 * BATCH_CHUNK + 1];
+    for (digits, scalar) in digits.iter_mut().zip(scalars[..count].iter()) {
+        *digits = wnaf(&to_words(scalar));
+    }
+    digits[count] = wnaf(&to_words(&base_scalar));
+    let mut terms = [(base, &digits[count]); 2 * BATCH_CHUNK + 1];
+    for (i, term) in terms[..count].iter_mut().enumerate() {
+        *term = (&tables[i], &digits[i]);
+    }
+    straus(&terms[..=count]).double().double().double().is_identity()
+}
+
+/// An Ed25519 signature, `R || S`.
+#[derive(Clone, Copy)]
+pub struct Ed25519Signature(pub [u8; 64]);
+
+impl Ed25519Signature {
+    pub fn to_bytes(&self) -> [u8; 64] {
+        self.0
+    }
+}
+
+impl fmt::Debug for Ed25519Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Ed25519Signature(")?;
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl PartialEq for Ed25519Signature {
+    fn eq(&self, other: &Ed25519Signature) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for Ed25519Signature {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+    use std::vec;
+    use std::vec::Vec;
+
+    /// Tests 1 to 3 of RFC 8032, section 7.1: seed, public key, message and
+    /// signature.
+    const VECTORS: [(&str, &str, &str, &str); 3] = [
+        (
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            "",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46b\
+             d25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            "72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c\
+             387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            "af82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc659\
+             4a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    #[test]
+    fn rfc8032() {
+        for (seed, public, message, signature) in VECTORS {
+            let key = Ed25519PrivateKey::from_seed(&hex(seed).try_into().unwrap());
+            assert_eq!(key.public_key().to_bytes()[..], hex(public)[..]);
+            let message = hex(message);
+            let sig = key.sign(&message);
+            assert_eq!(sig.to_bytes()[..], hex(signature)[..]);
+            let public = Ed25519PublicKey::from_bytes(&hex(public).try_into().unwrap()).unwrap();
+            assert!(public.verify(&message, &sig));
+        }
+    }
+
+    #[test]
+    fn rejects_forgeries() {
+        let (seed, public, _, signature) = VECTORS[1];
+        let key = Ed25519PrivateKey::from_seed(&hex(seed).try_into().unwrap());
+        let public = Ed25519PublicKey::from_bytes(&hex(public).try_into().unwrap()).unwrap();
+        let sig = Ed25519Signature(hex(signature).try_into().unwrap());
+        assert!(!public.verify(b"s", &sig));
+        for i in [0, 31, 32, 63] {
+            let mut bad = sig;
+            bad.0[i] ^= 1;
+            assert!(!public.verify(b"r", &bad));
+        }
+        // S must be reduced: adding the group order to it is a malleation.
+        let mut s = [0_u8; 32];
+        s.copy_from_slice(&sig.0[32..]);
+        let l = hex("edd3f55c1a631258d69cf7a2def9de1400000000000000000000000000000010");
+        let mut carry = 0_u16;
+        for (s, l) in s.iter_mut().zip(l) {
+            let sum = *s as u16 + l as u16 + carry;
+            *s = sum as u8;
+            carry = sum >> 8;
+        }
+        let mut malleated = sig;
+        malleated.0[32..].copy_from_slice(&s);
+        assert!(!public.verify(b"r", &malleated));
+        assert!(!key.public_key().verify(b"r", &Ed25519Signature([0; 64])));
+    }
+
+    #[test]
+    fn verify_batch() {
+        // More signatures than one multiplication combines, with repeated
+        // keys.
+        let keys: Vec<Ed25519PrivateKey> =
+            (0..3).map(|i| Ed25519PrivateKey::from_seed(&[i; 32])).collect();
+        let publics: Vec<Ed25519PublicKey> = keys.iter().map(|key| key.public_key()).collect();
+        let messages: Vec<Vec<u8>> =
+            (0..2 * BATCH_CHUNK as u8 + 3).map(|i| vec![i; i as usize]).collect();
+        let signatures: Vec<Ed25519Signature> =
+            messages.iter().enumerate().map(|(i, m)| keys[i % 3].sign(m)).collect();
+        let items: Vec<_> = messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (&publics[i % 3], &m[..], &signatures[i]))
+            .collect();
+        assert!(super::verify_batch(&items));
+        assert!(super::verify_batch(&items[..1]));
+        assert!(super::verify_batch(&[]));
+
+        for bad in [0, BATCH_CHUNK + 1, items.len() - 1] {
+            for byte in [3, 40] {
+                let mut forged = *items[bad].2;
+                forged.0[byte] ^= 1;
+                let mut batch = items.clone();
+                batch[bad].2 = &forged;
+                assert!(!super::verify_batch(&batch), "{} {}", bad, byte);
+            }
+            let mut batch = items.clone();
+            batch[bad].0 = &publics[(bad + 1) % 3];
+            assert!(!super::verify_batch(&batch));
+            let mut batch = items.clone();
+            batch[bad].1 = b"another message";
+            assert!(!super::verify_batch(&batch));
+        }
+    }
+}
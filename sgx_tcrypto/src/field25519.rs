@@ -0,0 +1,261 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Arithmetic modulo 2^255 - 19, in constant time, over five 51-bit limbs.
+
+const MASK51: u64 = (1 << 51) - 1;
+
+#[derive(Clone, Copy)]
+pub(crate) struct Fe(pub(crate) [u64; 5]);
+
+impl Fe {
+    pub(crate) const ZERO: Fe = Fe([0; 5]);
+    pub(crate) const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    /// Loads a little-endian element, ignoring the top bit.
+    pub(crate) fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let load = |i: usize| {
+            let mut word = [0_u8; 8];
+            word.copy_from_slice(&bytes[i..i + 8]);
+            u64::from_le_bytes(word)
+        };
+        Fe([
+            load(0) & MASK51,
+            (load(6) >> 3) & MASK51,
+            (load(12) >> 6) & MASK51,
+            (load(19) >> 1) & MASK51,
+            (load(24) >> 12) & MASK51,
+        ])
+    }
+
+    /// Returns the canonical little-endian encoding.
+    pub(crate) fn to_bytes(self) -> [u8; 32] {
+        let mut l = Fe::carry(self.0);
+        // Add 19 to find out whether the value is at least p, then subtract
+        // p by adding 19 and dropping 2^255.
+        let mut q = (l[0] + 19) >> 51;
+        q = (l[1] + q) >> 51;
+        q = (l[2] + q) >> 51;
+        q = (l[3] + q) >> 51;
+        q = (l[4] + q) >> 51;
+        l[0] += 19 * q;
+        l[1] += l[0] >> 51;
+        l[0] &= MASK51;
+        l[2] += l[1] >> 51;
+        l[1] &= MASK51;
+        l[3] += l[2] >> 51;
+        l[2] &= MASK51;
+        l[4] += l[3] >> 51;
+        l[3] &= MASK51;
+        l[4] &= MASK51;
+
+        let mut bytes = [0_u8; 32];
+        let mut acc: u128 = 0;
+        let mut acc_bits = 0;
+        let mut pos = 0;
+        for limb in l.iter() {
+            acc |= (*limb as u128) << acc_bits;
+            acc_bits += 51;
+            while acc_bits >= 8 && pos < 32 {
+                bytes[pos] = acc as u8;
+                acc >>= 8;
+                acc_bits -= 8;
+                pos += 1;
+            }
+        }
+        if pos < 32 {
+            bytes[pos] = acc as u8;
+        }
+        bytes
+    }
+
+    fn carry(mut l: [u64; 5]) -> [u64; 5] {
+        let c = l[0] >> 51;
+        l[0] &= MASK51;
+        l[1] += c;
+        let c = l[1] >> 51;
+        l[1] &= MASK51;
+        l[2] += c;
+        let c = l[2] >> 51;
+        l[2] &= MASK51;
+        l[3] += c;
+        let c = l[3] >> 51;
+        l[3] &= MASK51;
+        l[4] += c;
+        let c = l[4] >> 51;
+        l[4] &= MASK51;
+        l[0] += c * 19;
+        l
+    }
+
+    pub(crate) fn add(&self, other: &Fe) -> Fe {
+        let mut l = [0_u64; 5];
+        for (i, l) in l.iter_mut().enumerate() {
+            *l = self.0[i] + other.0[i];
+        }
+        Fe(Fe::carry(l))
+    }
+
+    pub(crate) fn sub(&self, other: &Fe) -> Fe {
+        // Add 16p so that limbs don't underflow.
+        const P16: [u64; 5] = [
+            36_028_797_018_963_664,
+            36_028_797_018_963_952,
+            36_028_797_018_963_952,
+            36_028_797_018_963_952,
+            36_028_797_018_963_952,
+        ];
+        let mut l = [0_u64; 5];
+        for (i, l) in l.iter_mut().enumerate() {
+            *l = (self.0[i] + P16[i]) - other.0[i];
+        }
+        Fe(Fe::carry(l))
+    }
+
+    pub(crate) fn neg(&self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+
+    pub(crate) fn mul(&self, other: &Fe) -> Fe {
+        let m = |a: u64, b: u64| a as u128 * b as u128;
+        let a = &self.0;
+        let b = &other.0;
+        let b1 = b[1] * 19;
+        let b2 = b[2] * 19;
+        let b3 = b[3] * 19;
+        let b4 = b[4] * 19;
+
+        let r0 = m(a[0], b[0]) + m(a[1], b4) + m(a[2], b3) + m(a[3], b2) + m(a[4], b1);
+        let mut r1 = m(a[0], b[1]) + m(a[1], b[0]) + m(a[2], b4) + m(a[3], b3) + m(a[4], b2);
+        let mut r2 = m(a[0], b[2]) + m(a[1], b[1]) + m(a[2], b[0]) + m(a[3], b4) + m(a[4], b3);
+        let mut r3 = m(a[0], b[3]) + m(a[1], b[2]) + m(a[2], b[1]) + m(a[3], b[0]) + m(a[4], b4);
+        let mut r4 = m(a[0], b[4]) + m(a[1], b[3]) + m(a[2], b[2]) + m(a[3], b[1]) + m(a[4], b[0]);
+
+        r1 += r0 >> 51;
+        let l0 = r0 as u64 & MASK51;
+        r2 += r1 >> 51;
+        let l1 = r1 as u64 & MASK51;
+        r3 += r2 >> 51;
+        let l2 = r2 as u64 & MASK51;
+        r4 += r3 >> 51;
+        let l3 = r3 as u64 & MASK51;
+        let c = (r4 >> 51) as u64;
+        let l4 = r4 as u64 & MASK51;
+
+        let l0 = l0 + c * 19;
+        Fe([l0 & MASK51, l1 + (l0 >> 51), l2, l3, l4])
+    }
+
+    pub(crate) fn square(&self) -> Fe {
+        self.mul(self)
+    }
+
+    /// Squares `k` times.
+    pub(crate) fn pow2k(&self, k: u32) -> Fe {
+        let mut x = *self;
+        for _ in 0..k {
+            x = x.square();
+        }
+        x
+    }
+
+    /// Multiplies by a small constant.
+    pub(crate) fn mul_small(&self, k: u32) -> Fe {
+        let mut l = [0_u128; 5];
+        for (i, l) in l.iter_mut().enumerate() {
+            *l = self.0[i] as u128 * k as u128;
+        }
+        let mut out = [0_u64; 5];
+        let mut carry = 0_u128;
+        for i in 0..5 {
+            let v = l[i] + carry;
+            out[i] = v as u64 & MASK51;
+            carry = v >> 51;
+        }
+        out[0] += carry as u64 * 19;
+        Fe(Fe::carry(out))
+    }
+
+    /// Returns `(self^(2^250 - 1), self^11)`, from which the inverse and
+    /// square roots are computed.
+    fn pow22501(&self) -> (Fe, Fe) {
+        let t0 = self.square();
+        let t1 = t0.square().square();
+        let t2 = self.mul(&t1);
+        let t3 = t0.mul(&t2);
+        let t4 = t3.square();
+        let t5 = t2.mul(&t4);
+        let t7 = t5.pow2k(5).mul(&t5);
+        let t9 = t7.pow2k(10).mul(&t7);
+        let t11 = t9.pow2k(20).mul(&t9);
+        let t13 = t11.pow2k(10).mul(&t7);
+        let t15 = t13.pow2k(50).mul(&t13);
+        let t17 = t15.pow2k(100).mul(&t15);
+        let t19 = t17.pow2k(50).mul(&t13);
+        (t19, t3)
+    }
+
+    /// Returns the inverse, or 0 for 0.
+    pub(crate) fn invert(&self) -> Fe {
+        let (t19, t3) = self.pow22501();
+        t19.pow2k(5).mul(&t3)
+    }
+
+    /// Returns `self^((p - 5) / 8)`.
+    pub(crate) fn pow_p58(&self) -> Fe {
+        let (t19, _) = self.pow22501();
+        t19.pow2k(2).mul(self)
+    }
+
+    pub(crate) fn ct_eq(&self, other: &Fe) -> bool {
+        crate::util::ct_eq(&self.to_bytes(), &other.to_bytes())
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.ct_eq(&Fe::ZERO)
+    }
+
+    /// Returns whether the canonical encoding is odd, the "negative" sign
+    /// of RFC 8032.
+    pub(crate) fn is_negative(&self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    /// Swaps `a` and `b` if `choice` is 1, in constant time.
+    pub(crate) fn cswap(a: &mut Fe, b: &mut Fe, choice: u64) {
+        let mask = 0_u64.wrapping_sub(choice);
+        for i in 0..5 {
+            let t = mask & (a.0[i] ^ b.0[i]);
+            a.0[i] ^= t;
+            b.0[i] ^= t;
+        }
+    }
+
+    /// Replaces `self` with `other` if `choice` is 1, in constant time.
+    pub(crate) fn cmov(&mut self, other: &Fe, choice: u64) {
+        let mask = 0_u64.wrapping_sub(choice);
+        for i in 0..5 {
+            self.0[i] ^= mask & (self.0[i] ^ other.0[i]);
+        }
+    }
+
+    pub(crate) fn wipe(&mut self) {
+        for limb in self.0.iter_mut() {
+            unsafe { core::ptr::write_volatile(limb, 0) };
+        }
+    }
+}
@@ -0,0 +1,205 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Arithmetic modulo 256-bit primes, in the Montgomery domain, shared by
+//! the short Weierstrass curves.
+
+pub(crate) type Words = [u64; 4];
+
+/// A 256-bit odd modulus, with the constants of Montgomery multiplication.
+pub(crate) struct Modulus {
+    pub(crate) m: Words,
+    /// `-m^-1 mod 2^64`.
+    pub(crate) inv: u64,
+    /// `2^512 mod m`.
+    pub(crate) r2: Words,
+}
+
+pub(crate) fn from_be_bytes(bytes: &[u8; 32]) -> Words {
+    let mut words = [0_u64; 4];
+    for (i, word) in words.iter_mut().enumerate() {
+        let mut be = [0_u8; 8];
+        be.copy_from_slice(&bytes[24 - 8 * i..32 - 8 * i]);
+        *word = u64::from_be_bytes(be);
+    }
+    words
+}
+
+pub(crate) fn to_be_bytes(words: &Words) -> [u8; 32] {
+    let mut bytes = [0_u8; 32];
+    for (i, word) in words.iter().enumerate() {
+        bytes[24 - 8 * i..32 - 8 * i].copy_from_slice(&word.to_be_bytes());
+    }
+    bytes
+}
+
+/// Returns `a - b` and the borrow out.
+pub(crate) fn sub_words(a: &Words, b: &Words) -> (Words, u64) {
+    let mut out = [0_u64; 4];
+    let mut borrow = 0_u64;
+    for i in 0..4 {
+        let (d, b1) = a[i].overflowing_sub(b[i]);
+        let (d, b2) = d.overflowing_sub(borrow);
+        out[i] = d;
+        borrow = (b1 | b2) as u64;
+    }
+    (out, borrow)
+}
+
+/// Returns `b` if `choice` is 1 and `a` if it is 0, in constant time.
+pub(crate) fn select(a: &Words, b: &Words, choice: u64) -> Words {
+    let mask = 0_u64.wrapping_sub(choice);
+    let mut out = [0_u64; 4];
+    for i in 0..4 {
+        out[i] = a[i] ^ (mask & (a[i] ^ b[i]));
+    }
+    out
+}
+
+pub(crate) fn is_zero(a: &Words) -> bool {
+    a.iter().fold(0, |acc, w| acc | w) == 0
+}
+
+pub(crate) fn wipe(words: &mut Words) {
+    for word in words.iter_mut() {
+        unsafe { core::ptr::write_volatile(word, 0) };
+    }
+}
+
+/// Arithmetic on Montgomery representations, in constant time.
+impl Modulus {
+    /// Reduces `carry * 2^256 + a`, known to be below `2m`.
+    pub(crate) fn reduce_once(&self, a: &Words, carry: u64) -> Words {
+        let (d, borrow) = sub_words(a, &self.m);
+        select(a, &d, carry | (borrow ^ 1))
+    }
+
+    pub(crate) fn add(&self, a: &Words, b: &Words) -> Words {
+        let mut out = [0_u64; 4];
+        let mut carry = 0_u64;
+        for i in 0..4 {
+            let (s, c1) = a[i].overflowing_add(b[i]);
+            let (s, c2) = s.overflowing_add(carry);
+            out[i] = s;
+            carry = (c1 | c2) as u64;
+        }
+        self.reduce_once(&out, carry)
+    }
+
+    pub(crate) fn sub(&self, a: &Words, b: &Words) -> Words {
+        let (d, borrow) = sub_words(a, b);
+        let mask = 0_u64.wrapping_sub(borrow);
+        let mut out = [0_u64; 4];
+        let mut carry = 0_u64;
+        for i in 0..4 {
+            let (s, c1) = d[i].overflowing_add(self.m[i] & mask);
+            let (s, c2) = s.overflowing_add(carry);
+            out[i] = s;
+            carry = (c1 | c2) as u64;
+        }
+        out
+    }
+
+    pub(crate) fn neg(&self, a: &Words) -> Words {
+        self.sub(&[0; 4], a)
+    }
+
+    /// Returns `a * b / 2^256 mod m`, for `a < 2^256` and `b < m`.
+    pub(crate) fn mul(&self, a: &Words, b: &Words) -> Words {
+        let mut t = [0_u64; 6];
+        for bi in b.iter() {
+            let mut c = 0_u128;
+            for j in 0..4 {
+                let s = t[j] as u128 + a[j] as u128 * *bi as u128 + c;
+                t[j] = s as u64;
+                c = s >> 64;
+            }
+            let s = t[4] as u128 + c;
+            t[4] = s as u64;
+            t[5] = (s >> 64) as u64;
+
+            let q = t[0].wrapping_mul(self.inv);
+            let mut c = (t[0] as u128 + q as u128 * self.m[0] as u128) >> 64;
+            for j in 1..4 {
+                let s = t[j] as u128 + q as u128 * self.m[j] as u128 + c;
+                t[j - 1] = s as u64;
+                c = s >> 64;
+            }
+            let s = t[4] as u128 + c;
+            t[3] = s as u64;
+            t[4] = t[5] + (s >> 64) as u64;
+        }
+        self.reduce_once(&[t[0], t[1], t[2], t[3]], t[4])
+    }
+
+    pub(crate) fn square(&self, a: &Words) -> Words {
+        self.mul(a, a)
+    }
+
+    /// Converts into the Montgomery domain, reducing any 256-bit value.
+    pub(crate) fn encode(&self, a: &Words) -> Words {
+        self.mul(a, &self.r2)
+    }
+
+    pub(crate) fn decode(&self, a: &Words) -> Words {
+        self.mul(a, &[1, 0, 0, 0])
+    }
+
+    pub(crate) fn one(&self) -> Words {
+        self.encode(&[1, 0, 0, 0])
+    }
+
+    /// Raises to a public exponent.
+    pub(crate) fn pow(&self, a: &Words, exp: &Words) -> Words {
+        let mut acc = self.one();
+        for i in (0..256).rev() {
+            acc = self.square(&acc);
+            if (exp[i / 64] >> (i % 64)) & 1 == 1 {
+                acc = self.mul(&acc, a);
+            }
+        }
+        acc
+    }
+
+    /// Returns the inverse of a non-zero element, by Fermat's little theorem.
+    pub(crate) fn invert(&self, a: &Words) -> Words {
+        let (exp, _) = sub_words(&self.m, &[2, 0, 0, 0]);
+        self.pow(a, &exp)
+    }
+
+    /// Inverts non-zero elements with a single inversion, by Montgomery's
+    /// trick; `prefix` is scratch space as long as `values`.
+    pub(crate) fn invert_all(&self, values: &mut [Words], prefix: &mut [Words]) {
+        let prefix = &mut prefix[..values.len()];
+        let mut product = self.one();
+        for (value, prefix) in values.iter().zip(prefix.iter_mut()) {
+            *prefix = product;
+            product = self.mul(&product, value);
+        }
+        let mut inverse = self.invert(&product);
+        for (value, prefix) in values.iter_mut().zip(prefix.iter()).rev() {
+            let next = self.mul(&inverse, value);
+            *value = self.mul(prefix, &inverse);
+            inverse = next;
+        }
+    }
+
+    /// Returns whether `a < m`.
+    pub(crate) fn contains(&self, a: &Words) -> bool {
+        sub_words(a, &self.m).1 == 1
+    }
+}
@@ -0,0 +1,472 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! The fields of BLS12-381: the base field `Fp`, and the tower
+//! `Fp2 = Fp[i] / (i^2 + 1)`, `Fp6 = Fp2[v] / (v^3 - (1 + i))` and
+//! `Fp12 = Fp6[w] / (w^2 - v)` in which pairings take their values.
+//!
+//! Elements stay in the Montgomery domain. Arithmetic is constant time;
+//! exponents, where functions take them, are public constants.
+
+use crate::ctbignum::{Monty, Uint};
+
+/// An integer below `p`, as little-endian limbs.
+pub(crate) type Limbs = [u64; 6];
+
+const M: Limbs = [
+    0xb9fe_ffff_ffff_aaab,
+    0x1eab_fffe_b153_ffff,
+    0x6730_d2a0_f6b0_f624,
+    0x6477_4b84_f385_12bf,
+    0x4b1b_a7b6_434b_acd7,
+    0x1a01_11ea_397f_e69a,
+];
+const INV: u64 = 0x89f3_fffc_fffc_fffd;
+/// `2^768 mod p`.
+const R2: Limbs = [
+    0xf4df_1f34_1c34_1746,
+    0x0a76_e6a6_09d1_04f1,
+    0x8de5_476c_4c95_b6d5,
+    0x67eb_88a9_939d_83c0,
+    0x9a79_3e85_b519_952d,
+    0x1198_8fe5_92ca_e3aa,
+];
+/// `2^384 mod p`, the Montgomery form of 1.
+const ONE: Limbs = [
+    0x7609_0000_0002_fffd,
+    0xebf4_000b_c40c_0002,
+    0x5f48_9857_53c7_58ba,
+    0x77ce_5853_7052_5745,
+    0x5c07_1a97_a256_ec6d,
+    0x15f6_5ec3_fa80_e493,
+];
+const P_MINUS_2: Limbs = [
+    0xb9fe_ffff_ffff_aaa9,
+    0x1eab_fffe_b153_ffff,
+    0x6730_d2a0_f6b0_f624,
+    0x6477_4b84_f385_12bf,
+    0x4b1b_a7b6_434b_acd7,
+    0x1a01_11ea_397f_e69a,
+];
+/// `(p + 1) / 4`: as `p = 3 mod 4`, squares have the root `a^((p + 1) / 4)`.
+const SQRT_EXP: Limbs = [
+    0xee7f_bfff_ffff_eaab,
+    0x07aa_ffff_ac54_ffff,
+    0xd9cc_34a8_3dac_3d89,
+    0xd91d_d2e1_3ce1_44af,
+    0x92c6_e9ed_90d2_eb35,
+    0x0680_447a_8e5f_f9a6,
+];
+/// `(p - 1) / 2`, the exponent of the Legendre symbol.
+const HALF: Limbs = [
+    0xdcff_7fff_ffff_d555,
+    0x0f55_ffff_58a9_ffff,
+    0xb398_6950_7b58_7b12,
+    0xb23b_a5c2_79c2_895f,
+    0x258d_d3db_21a5_d66b,
+    0x0d00_88f5_1cbf_f34d,
+];
+/// `(p - 3) / 4`, for square roots in `Fp2`.
+const FP2_SQRT_EXP: Limbs = [
+    0xee7f_bfff_ffff_eaaa,
+    0x07aa_ffff_ac54_ffff,
+    0xd9cc_34a8_3dac_3d89,
+    0xd91d_d2e1_3ce1_44af,
+    0x92c6_e9ed_90d2_eb35,
+    0x0680_447a_8e5f_f9a6,
+];
+
+const P: Monty<6> = Monty::with_constants(Uint(M), INV, Uint(R2), Uint(ONE));
+
+/// An element of the base field.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fp(Uint<6>);
+
+impl Fp {
+    pub(crate) const ZERO: Fp = Fp(Uint([0; 6]));
+    pub(crate) const ONE: Fp = Fp(Uint(ONE));
+
+    pub(crate) fn from_limbs(limbs: &Limbs) -> Fp {
+        Fp(P.encode(&Uint(*limbs)))
+    }
+
+    pub(crate) fn from_u64(value: u64) -> Fp {
+        Fp(P.encode(&Uint::from_u64(value)))
+    }
+
+    /// Parses a big-endian element, failing unless it is below `p`.
+    pub(crate) fn from_be_bytes(bytes: &[u8; 48]) -> Option<Fp> {
+        let x = Uint::<6>::from_be_bytes(bytes)?;
+        if x.lt(&Uint(M)) {
+            Some(Fp(P.encode(&x)))
+        } else {
+            None
+        }
+    }
+
+    /// Reduces a 512-bit big-endian value, as hashing to the field does.
+    pub(crate) fn from_wide_be_bytes(bytes: &[u8; 64]) -> Fp {
+        let hi = Uint::<6>::from_be_bytes(&bytes[..16]).unwrap_or(Uint::ZERO);
+        let lo = Uint::<6>::from_be_bytes(&bytes[16..]).unwrap_or(Uint::ZERO);
+        // Encoding multiplies by 2^384, so encoding `hi` twice gives the
+        // Montgomery form of `hi * 2^384`.
+        Fp(P.add(&P.encode(&P.encode(&hi)), &P.encode(&lo)))
+    }
+
+    pub(crate) fn to_be_bytes(self) -> [u8; 48] {
+        let mut out = [0_u8; 48];
+        P.decode(&self.0).write_be_bytes(&mut out);
+        out
+    }
+
+    pub(crate) fn add(&self, other: &Fp) -> Fp {
+        Fp(P.add(&self.0, &other.0))
+    }
+
+    pub(crate) fn sub(&self, other: &Fp) -> Fp {
+        Fp(P.sub(&self.0, &other.0))
+    }
+
+    pub(crate) fn neg(&self) -> Fp {
+        Fp::ZERO.sub(self)
+    }
+
+    pub(crate) fn double(&self) -> Fp {
+        self.add(self)
+    }
+
+    pub(crate) fn mul(&self, other: &Fp) -> Fp {
+        Fp(P.mul(&self.0, &other.0))
+    }
+
+    pub(crate) fn square(&self) -> Fp {
+        Fp(P.square(&self.0))
+    }
+
+    fn pow(&self, exp: &Limbs) -> Fp {
+        Fp(P.encode(&P.pow(&P.decode(&self.0), &Uint(*exp), 381)))
+    }
+
+    /// Returns the inverse, or zero for zero.
+    pub(crate) fn invert(&self) -> Fp {
+        self.pow(&P_MINUS_2)
+    }
+
+    pub(crate) fn sqrt(&self) -> Option<Fp> {
+        let root = self.pow(&SQRT_EXP);
+        if root.square() == *self {
+            Some(root)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn is_square(&self) -> bool {
+        let symbol = self.pow(&HALF);
+        symbol == Fp::ONE || self.is_zero()
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub(crate) fn select(a: &Fp, b: &Fp, choice: u64) -> Fp {
+        Fp(Uint::select(&a.0, &b.0, choice))
+    }
+
+    /// Returns the parity of the integer below `p`.
+    pub(crate) fn is_odd(&self) -> bool {
+        P.decode(&self.0).is_odd()
+    }
+
+    /// Returns whether the element is above `(p - 1) / 2`, the larger of
+    /// itself and its negation, which point encodings flag.
+    pub(crate) fn is_lexicographically_largest(&self) -> bool {
+        Uint(HALF).lt(&P.decode(&self.0))
+    }
+}
+
+/// An element `c0 + c1 i` of `Fp2`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fp2 {
+    pub(crate) c0: Fp,
+    pub(crate) c1: Fp,
+}
+
+impl Fp2 {
+    pub(crate) const ZERO: Fp2 = Fp2 { c0: Fp::ZERO, c1: Fp::ZERO };
+    pub(crate) const ONE: Fp2 = Fp2 { c0: Fp::ONE, c1: Fp::ZERO };
+
+    pub(crate) fn new(c0: Fp, c1: Fp) -> Fp2 {
+        Fp2 { c0, c1 }
+    }
+
+    pub(crate) fn from_limbs(c0: &Limbs, c1: &Limbs) -> Fp2 {
+        Fp2 { c0: Fp::from_limbs(c0), c1: Fp::from_limbs(c1) }
+    }
+
+    pub(crate) fn add(&self, other: &Fp2) -> Fp2 {
+        Fp2 { c0: self.c0.add(&other.c0), c1: self.c1.add(&other.c1) }
+    }
+
+    pub(crate) fn sub(&self, other: &Fp2) -> Fp2 {
+        Fp2 { c0: self.c0.sub(&other.c0), c1: self.c1.sub(&other.c1) }
+    }
+
+    pub(crate) fn neg(&self) -> Fp2 {
+        Fp2 { c0: self.c0.neg(), c1: self.c1.neg() }
+    }
+
+    pub(crate) fn double(&self) -> Fp2 {
+        self.add(self)
+    }
+
+    pub(crate) fn mul(&self, other: &Fp2) -> Fp2 {
+        let t0 = self.c0.mul(&other.c0);
+        let t1 = self.c1.mul(&other.c1);
+        let t2 = self.c0.add(&self.c1).mul(&other.c0.add(&other.c1));
+        Fp2 { c0: t0.sub(&t1), c1: t2.sub(&t0).sub(&t1) }
+    }
+
+    pub(crate) fn square(&self) -> Fp2 {
+        let t = self.c0.mul(&self.c1);
+        Fp2 { c0: self.c0.add(&self.c1).mul(&self.c0.sub(&self.c1)), c1: t.double() }
+    }
+
+    pub(crate) fn mul_fp(&self, other: &Fp) -> Fp2 {
+        Fp2 { c0: self.c0.mul(other), c1: self.c1.mul(other) }
+    }
+
+    /// Multiplies by `1 + i`, the non-residue defining `Fp6`.
+    pub(crate) fn mul_by_nonresidue(&self) -> Fp2 {
+        Fp2 { c0: self.c0.sub(&self.c1), c1: self.c0.add(&self.c1) }
+    }
+
+    /// Returns `c0 - c1 i`, which is also the Frobenius map `x^p`.
+    pub(crate) fn conjugate(&self) -> Fp2 {
+        Fp2 { c0: self.c0, c1: self.c1.neg() }
+    }
+
+    fn norm(&self) -> Fp {
+        self.c0.square().add(&self.c1.square())
+    }
+
+    /// Returns the inverse, or zero for zero.
+    pub(crate) fn invert(&self) -> Fp2 {
+        let t = self.norm().invert();
+        Fp2 { c0: self.c0.mul(&t), c1: self.c1.neg().mul(&t) }
+    }
+
+    fn pow(&self, exp: &Limbs) -> Fp2 {
+        let mut acc = Fp2::ONE;
+        for i in (0..381).rev() {
+            acc = acc.square();
+            if (exp[i / 64] >> (i % 64)) & 1 == 1 {
+                acc = acc.mul(self);
+            }
+        }
+        acc
+    }
+
+    pub(crate) fn is_square(&self) -> bool {
+        self.norm().is_square()
+    }
+
+    /// Returns a square root, by algorithm 9 of Adj and
+    /// Rodríguez-Henríquez for `p = 3 mod 4`.
+    pub(crate) fn sqrt(&self) -> Option<Fp2> {
+        let a1 = self.pow(&FP2_SQRT_EXP);
+        let alpha = a1.square().mul(self);
+        let x0 = a1.mul(self);
+        let minus_one = Fp2::ONE.neg();
+        let root = if alpha == minus_one {
+            Fp2 { c0: x0.c1.neg(), c1: x0.c0 }
+        } else {
+            alpha.add(&Fp2::ONE).pow(&HALF).mul(&x0)
+        };
+        if root.square() == *self {
+            Some(root)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.c0.is_zero() && self.c1.is_zero()
+    }
+
+    pub(crate) fn select(a: &Fp2, b: &Fp2, choice: u64) -> Fp2 {
+        Fp2 { c0: Fp::select(&a.c0, &b.c0, choice), c1: Fp::select(&a.c1, &b.c1, choice) }
+    }
+
+    /// Returns the sign of RFC 9380, section 4.1.
+    pub(crate) fn sgn0(&self) -> bool {
+        self.c0.is_odd() || (self.c0.is_zero() && self.c1.is_odd())
+    }
+
+    pub(crate) fn is_lexicographically_largest(&self) -> bool {
+        if self.c1.is_zero() {
+            self.c0.is_lexicographically_largest()
+        } else {
+            self.c1.is_lexicographically_largest()
+        }
+    }
+}
+
+/// `(1 + i)^((p - 1) / 6)`, the power of `w` by which the Frobenius map
+/// multiplies `w`.
+const GAMMA_1: [Limbs; 2] = [
+    [
+        0x8d07_75ed_9223_5fb8,
+        0xf67e_a53d_63e7_813d,
+        0x7b24_43d7_84ba_b9c4,
+        0x0fd6_03fd_3cbd_5f4f,
+        0xc231_beb4_202c_0d1f,
+        0x1904_d3bf_02bb_0667,
+    ],
+    [
+        0x2cf7_8a12_6ddc_4af3,
+        0x282d_5ac1_4d6c_7ec2,
+        0xec0c_8ec9_71f6_3c5f,
+        0x54a1_4787_b6c7_b36f,
+        0x88e9_e902_231f_9fb8,
+        0x00fc_3e2b_36c4_e032,
+    ],
+];
+/// `(1 + i)^((p - 1) / 3)`, for `v`, which is purely imaginary.
+const GAMMA_2: Limbs = [
+    0x8bfd_0000_0000_aaac,
+    0x4094_27eb_4f49_fffd,
+    0x897d_2965_0fb8_5f9b,
+    0xaa0d_857d_8975_9ad4,
+    0xec02_4086_63d4_de85,
+    0x1a01_11ea_397f_e699,
+];
+/// `(1 + i)^(2 (p - 1) / 3)`, for `v^2`, which is real.
+const GAMMA_4: Limbs = [
+    0x8bfd_0000_0000_aaad,
+    0x4094_27eb_4f49_fffd,
+    0x897d_2965_0fb8_5f9b,
+    0xaa0d_857d_8975_9ad4,
+    0xec02_4086_63d4_de85,
+    0x1a01_11ea_397f_e699,
+];
+
+/// An element `c0 + c1 v + c2 v^2` of `Fp6`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fp6 {
+    pub(crate) c0: Fp2,
+    pub(crate) c1: Fp2,
+    pub(crate) c2: Fp2,
+}
+
+impl Fp6 {
+    pub(crate) const ZERO: Fp6 = Fp6 { c0: Fp2::ZERO, c1: Fp2::ZERO, c2: Fp2::ZERO };
+    pub(crate) const ONE: Fp6 = Fp6 { c0: Fp2::ONE, c1: Fp2::ZERO, c2: Fp2::ZERO };
+
+    pub(crate) fn add(&self, other: &Fp6) -> Fp6 {
+        Fp6 { c0: self.c0.add(&other.c0), c1: self.c1.add(&other.c1), c2: self.c2.add(&other.c2) }
+    }
+
+    pub(crate) fn sub(&self, other: &Fp6) -> Fp6 {
+        Fp6 { c0: self.c0.sub(&other.c0), c1: self.c1.sub(&other.c1), c2: self.c2.sub(&other.c2) }
+    }
+
+    pub(crate) fn neg(&self) -> Fp6 {
+        Fp6 { c0: self.c0.neg(), c1: self.c1.neg(), c2: self.c2.neg() }
+    }
+
+    pub(crate) fn mul(&self, other: &Fp6) -> Fp6 {
+        let t0 = self.c0.mul(&other.c0);
+        let t1 = self.c1.mul(&other.c1);
+        let t2 = self.c2.mul(&other.c2);
+        let s12 = self.c1.add(&self.c2).mul(&other.c1.add(&other.c2)).sub(&t1).sub(&t2);
+        let s01 = self.c0.add(&self.c1).mul(&other.c0.add(&other.c1)).sub(&t0).sub(&t1);
+        let s02 = self.c0.add(&self.c2).mul(&other.c0.add(&other.c2)).sub(&t0).sub(&t2);
+        Fp6 {
+            c0: t0.add(&s12.mul_by_nonresidue()),
+            c1: s01.add(&t2.mul_by_nonresidue()),
+            c2: s02.add(&t1),
+        }
+    }
+
+    /// Multiplies by `v`, whose cube is `1 + i`.
+    pub(crate) fn mul_by_v(&self) -> Fp6 {
+        Fp6 { c0: self.c2.mul_by_nonresidue(), c1: self.c0, c2: self.c1 }
+    }
+
+    fn mul_fp2(&self, other: &Fp2) -> Fp6 {
+        Fp6 { c0: self.c0.mul(other), c1: self.c1.mul(other), c2: self.c2.mul(other) }
+    }
+
+    pub(crate) fn invert(&self) -> Fp6 {
+        let a = self.c0.square().sub(&self.c1.mul(&self.c2).mul_by_nonresidue());
+        let b = self.c2.square().mul_by_nonresidue().sub(&self.c0.mul(&self.c1));
+        let c = self.c1.square().sub(&self.c0.mul(&self.c2));
+        let f = self.c1.mul(&c).add(&self.c2.mul(&b)).mul_by_nonresidue().add(&self.c0.mul(&a));
+        Fp6 { c0: a, c1: b, c2: c }.mul_fp2(&f.invert())
+    }
+
+    pub(crate) fn frobenius(&self) -> Fp6 {
+        let gamma_2 = Fp2::new(Fp::ZERO, Fp::from_limbs(&GAMMA_2));
+        let gamma_4 = Fp::from_limbs(&GAMMA_4);
+        Fp6 {
+            c0: self.c0.conjugate(),
+            c1: self.c1.conjugate().mul(&gamma_2),
+            c2: self.c2.conjugate().mul_fp(&gamma_4),
+        }
+    }
+}
+
+/// An element `c0 + c1 w` of `Fp12`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fp12 {
+    pub(crate) c0: Fp6,
+    pub(crate) c1: Fp6,
+}
+
+impl Fp12 {
+    pub(crate) const ONE: Fp12 = Fp12 { c0: Fp6::ONE, c1: Fp6::ZERO };
+
+    pub(crate) fn mul(&self, other: &Fp12) -> Fp12 {
+        let t0 = self.c0.mul(&other.c0);
+        let t1 = self.c1.mul(&other.c1);
+        let t2 = self.c0.add(&self.c1).mul(&other.c0.add(&other.c1));
+        Fp12 { c0: t0.add(&t1.mul_by_v()), c1: t2.sub(&t0).sub(&t1) }
+    }
+
+    pub(crate) fn square(&self) -> Fp12 {
+        self.mul(self)
+    }
+
+    /// Returns `c0 - c1 w`, which inverts the elements of the cyclotomic
+    /// subgroup left by the easy part of the final exponentiation.
+    pub(crate) fn conjugate(&self) -> Fp12 {
+        Fp12 { c0: self.c0, c1: self.c1.neg() }
+    }
+
+    pub(crate) fn invert(&self) -> Fp12 {
+        let t = self.c0.mul(&self.c0).sub(&self.c1.mul(&self.c1).mul_by_v()).invert();
+        Fp12 { c0: self.c0.mul(&t), c1: self.c1.neg().mul(&t) }
+    }
+
+    /// Returns `x^p`.
+    pub(crate) fn frobenius(&self) -> Fp12 {
+        let gamma_1 = Fp2::from_limbs(&GAMMA_1[0], &GAMMA_1[1]);
+        Fp12 { c0: self.c0.frobenius(), c1: self.c1.frobenius().mul_fp2(&gamma_1) }
+    }
+}
@@ -0,0 +1,395 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Format-preserving encryption with FF1 (NIST SP 800-38G) over AES-128,
+//! to tokenize values such as card numbers: a ciphertext has the length and
+//! the alphabet of its plaintext.
+//!
+//! Values are strings of numerals in a radix from 2 to 2^16, of 2 to
+//! `FF1_MAX_LEN` numerals, with at least a million possible values, as the
+//! standard requires since its 2019 revision. Encryption is deterministic,
+//! so a tweak, e.g. the bank identification number or the name of the
+//! field, should separate the domains which mustn't share tokens.
+
+use crate::aead::aes128_block;
+use crate::secret::{Secret, SecretBytes, Zeroize};
+use crate::util::zeroize;
+use sgx_types::*;
+
+pub const FF1_KEY_SIZE: usize = 16;
+
+/// The longest string of numerals, which bounds the big numbers of the
+/// rounds to 1024 bits.
+pub const FF1_MAX_LEN: usize = 128;
+
+/// Decimal digits, the alphabet of card and account numbers.
+pub const DECIMAL: &[u8] = b"0123456789";
+/// Digits and lowercase letters, in the order of the radix-36 numerals of
+/// the standard's examples.
+pub const ALPHANUMERIC: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+const ROUNDS: u8 = 10;
+const LIMBS: usize = 34;
+
+/// A non-negative integer of `LIMBS` little-endian 32-bit limbs.
+type Num = [u32; LIMBS];
+
+/// Sets `x` to `x * m + a`.
+fn mul_add(x: &mut Num, m: u32, a: u32) {
+    let mut carry = a as u64;
+    for limb in x.iter_mut() {
+        let t = *limb as u64 * m as u64 + carry;
+        *limb = t as u32;
+        carry = t >> 32;
+    }
+}
+
+/// Divides `x` by `d` in place, returning the remainder.
+fn div_rem(x: &mut Num, d: u32) -> u32 {
+    let mut rem = 0_u64;
+    for limb in x.iter_mut().rev() {
+        let t = rem << 32 | *limb as u64;
+        *limb = (t / d as u64) as u32;
+        rem = t % d as u64;
+    }
+    rem as u32
+}
+
+/// Writes the low `out.len()` bytes of `x`, big-endian.
+fn write_be(x: &Num, out: &mut [u8]) {
+    for (i, byte) in out.iter_mut().rev().enumerate() {
+        *byte = (x[i / 4] >> (8 * (i % 4))) as u8;
+    }
+}
+
+fn read_be(bytes: &[u8]) -> Num {
+    let mut x = [0_u32; LIMBS];
+    for (i, byte) in bytes.iter().rev().enumerate() {
+        x[i / 4] |= (*byte as u32) << (8 * (i % 4));
+    }
+    x
+}
+
+/// CBC-MAC with a zero IV, the PRF of FF1, over input whose length is a
+/// multiple of the block size.
+struct CbcMac<'a> {
+    key: &'a [u8; 16],
+    state: [u8; 16],
+    len: usize,
+}
+
+impl CbcMac<'_> {
+    fn update(&mut self, data: &[u8]) -> SgxError {
+        for byte in data.iter() {
+            self.state[self.len] ^= byte;
+            self.len += 1;
+            if self.len == 16 {
+                self.state = aes128_block(self.key, &self.state)?;
+                self.len = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CbcMac<'_> {
+    fn drop(&mut self) {
+        zeroize(&mut self.state);
+    }
+}
+
+/// An FF1 key, for numerals of one radix.
+pub struct Ff1 {
+    key: SecretBytes<FF1_KEY_SIZE>,
+    radix: u32,
+}
+
+impl Ff1 {
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` unless `radix` is in
+    /// `[2, 2^16]`.
+    pub fn new(key: &[u8; FF1_KEY_SIZE], radix: u32) -> SgxResult<Ff1> {
+        if !(2..=1 << 16).contains(&radix) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(Ff1 { key: Secret::new(*key), radix })
+    }
+
+    pub fn radix(&self) -> u32 {
+        self.radix
+    }
+
+    /// Encrypts a string of numerals in place, failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` if its length is out of bounds or a
+    /// numeral isn't below the radix.
+    pub fn encrypt(&self, tweak: &[u8], numerals: &mut [u16]) -> SgxError {
+        self.crypt(tweak, numerals, true)
+    }
+
+    /// Decrypts a string of numerals in place, with the same checks as
+    /// [`encrypt`](Ff1::encrypt).
+    pub fn decrypt(&self, tweak: &[u8], numerals: &mut [u16]) -> SgxError {
+        self.crypt(tweak, numerals, false)
+    }
+
+    /// Encrypts text in place, each byte a character of `alphabet`, which
+    /// orders the numerals of the radix. Fails with
+    /// `SGX_ERROR_INVALID_PARAMETER` if the alphabet isn't as long as the
+    /// radix or repeats a character, or the text has a character out of it.
+    pub fn encrypt_text(&self, tweak: &[u8], alphabet: &[u8], text: &mut [u8]) -> SgxError {
+        self.crypt_text(tweak, alphabet, text, true)
+    }
+
+    /// Decrypts text in place, with the same checks as
+    /// [`encrypt_text`](Ff1::encrypt_text).
+    pub fn decrypt_text(&self, tweak: &[u8], alphabet: &[u8], text: &mut [u8]) -> SgxError {
+        self.crypt_text(tweak, alphabet, text, false)
+    }
+
+    fn crypt_text(
+        &self,
+        tweak: &[u8],
+        alphabet: &[u8],
+        text: &mut [u8],
+        encrypt: bool,
+    ) -> SgxError {
+        let mut index = [None; 256];
+        for (i, c) in alphabet.iter().enumerate() {
+            if index[*c as usize].is_some() {
+                return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+            }
+            index[*c as usize] = Some(i as u16);
+        }
+        if alphabet.len() != self.radix as usize || text.len() > FF1_MAX_LEN {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut numerals = [0_u16; FF1_MAX_LEN];
+        let digits = &mut numerals[..text.len()];
+        for (n, c) in digits.iter_mut().zip(text.iter()) {
+            *n = index[*c as usize].ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+        }
+        let result = self.crypt(tweak, digits, encrypt);
+        if result.is_ok() {
+            for (c, n) in text.iter_mut().zip(digits.iter()) {
+                *c = alphabet[*n as usize];
+            }
+        }
+        numerals.zeroize();
+        result
+    }
+
+    fn check(&self, tweak: &[u8], numerals: &[u16]) -> SgxError {
+        let n = numerals.len();
+        if !(2..=FF1_MAX_LEN).contains(&n) || tweak.len() > u32::MAX as usize {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut domain = 1_u64;
+        for _ in 0..n {
+            domain = domain.saturating_mul(self.radix as u64);
+        }
+        if domain < 1_000_000 || numerals.iter().any(|&x| x as u32 >= self.radix) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(())
+    }
+
+    fn crypt(&self, tweak: &[u8], numerals: &mut [u16], encrypt: bool) -> SgxError {
+        self.check(tweak, numerals)?;
+        let n = numerals.len();
+        let (u, v) = (n / 2, n - n / 2);
+
+        // b is the byte length of radix^v - 1, and d that of the PRF output
+        // kept every round.
+        let mut max = [0_u32; LIMBS];
+        for _ in 0..v {
+            mul_add(&mut max, self.radix, self.radix - 1);
+        }
+        let b = ceil_div(bit_len(&max), 8);
+        let d = 4 * ceil_div(b, 4) + 4;
+
+        let mut p = [1, 2, 1, 0, 0, 0, 10, u as u8, 0, 0, 0, 0, 0, 0, 0, 0];
+        p[3..6].copy_from_slice(&self.radix.to_be_bytes()[1..]);
+        p[8..12].copy_from_slice(&(n as u32).to_be_bytes());
+        p[12..].copy_from_slice(&(tweak.len() as u32).to_be_bytes());
+
+        let mut a = [0_u16; FF1_MAX_LEN];
+        let mut c = [0_u16; FF1_MAX_LEN];
+        a[..u].copy_from_slice(&numerals[..u]);
+        c[..v].copy_from_slice(&numerals[u..]);
+        let (mut a_len, mut c_len) = (u, v);
+        let mut result = Ok(());
+        for step in 0..ROUNDS {
+            // Encryption adds to A a function of B; decryption runs the
+            // rounds backwards, subtracting from B a function of A.
+            let i = if encrypt { step } else { ROUNDS - 1 - step };
+            let m = if i % 2 == 0 { u } else { v };
+            let (source, target) = if encrypt {
+                (&c[..c_len], &mut a[..a_len])
+            } else {
+                (&a[..a_len], &mut c[..c_len])
+            };
+            let mut y = [0_u16; FF1_MAX_LEN];
+            if let Err(e) = self.round(&p, tweak, i, source, b, d, &mut y[..m]) {
+                result = Err(e);
+                break;
+            }
+            // Adds or subtracts numeral by numeral, modulo radix^m as the
+            // last carry is dropped.
+            let mut carry = 0_u32;
+            for (t, y) in target.iter_mut().rev().zip(y.iter()) {
+                let (x, y) = (*t as u32, *y as u32);
+                if encrypt {
+                    let sum = x + y + carry;
+                    carry = (sum >= self.radix) as u32;
+                    *t = (sum - carry * self.radix) as u16;
+                } else {
+                    let sub = y + carry;
+                    carry = (x < sub) as u32;
+                    *t = (x + carry * self.radix - sub) as u16;
+                }
+            }
+            y.zeroize();
+            core::mem::swap(&mut a, &mut c);
+            core::mem::swap(&mut a_len, &mut c_len);
+        }
+        if result.is_ok() {
+            numerals[..u].copy_from_slice(&a[..u]);
+            numerals[u..].copy_from_slice(&c[..v]);
+        }
+        a.zeroize();
+        c.zeroize();
+        result
+    }
+
+    /// Computes the low `y.len()` numerals of the round function of FF1,
+    /// from the half `source` of the state.
+    fn round(
+        &self,
+        p: &[u8; 16],
+        tweak: &[u8],
+        i: u8,
+        source: &[u16],
+        b: usize,
+        d: usize,
+        y: &mut [u16],
+    ) -> SgxError {
+        let mut num = [0_u32; LIMBS];
+        for &x in source.iter() {
+            mul_add(&mut num, self.radix, x as u32);
+        }
+        let mut num_bytes = [0_u8; 4 * LIMBS];
+        write_be(&num, &mut num_bytes[..b]);
+
+        let key = self.key.expose_secret();
+        let mut mac = CbcMac { key, state: [0; 16], len: 0 };
+        let pad = (16 - (tweak.len() + b + 1) % 16) % 16;
+        let mut result = mac.update(p);
+        for part in [tweak, &[0; 16][..pad], &[i], &num_bytes[..b]] {
+            result = result.and_then(|()| mac.update(part));
+        }
+
+        // S is R followed by encryptions of R xored with 1, 2, ...
+        let mut s = [0_u8; 16 * (4 * LIMBS / 16 + 1)];
+        if result.is_ok() {
+            s[..16].copy_from_slice(&mac.state);
+            for j in 1..ceil_div(d, 16) {
+                let mut block = mac.state;
+                for (x, k) in block[8..].iter_mut().zip((j as u64).to_be_bytes().iter()) {
+                    *x ^= k;
+                }
+                match aes128_block(key, &block) {
+                    Ok(out) => s[16 * j..16 * j + 16].copy_from_slice(&out),
+                    Err(e) => result = Err(e),
+                }
+                zeroize(&mut block);
+            }
+        }
+        let mut value = read_be(&s[..d]);
+        for x in y.iter_mut() {
+            *x = div_rem(&mut value, self.radix) as u16;
+        }
+        num.zeroize();
+        value.zeroize();
+        zeroize(&mut num_bytes);
+        zeroize(&mut s);
+        result
+    }
+}
+
+fn ceil_div(a: usize, b: usize) -> usize {
+    let q = a / b;
+    if q * b < a {
+        q + 1
+    } else {
+        q
+    }
+}
+
+fn bit_len(x: &Num) -> usize {
+    match x.iter().rposition(|&limb| limb != 0) {
+        Some(top) => 32 * top + 32 - x[top].leading_zeros() as usize,
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+
+    const KEY: [u8; FF1_KEY_SIZE] = *b"\x2b\x7e\x15\x16\x28\xae\xd2\xa6\xab\xf7\x15\x88\x09\xcf\x4f\x3c";
+
+    #[test]
+    fn nist_samples() {
+        // Samples 1 to 3 of the NIST FF1 examples, with AES-128.
+        let ff1 = Ff1::new(&KEY, 10).unwrap();
+        let mut numerals = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        ff1.encrypt(&[], &mut numerals).unwrap();
+        assert_eq!(numerals, [2, 4, 3, 3, 4, 7, 7, 4, 8, 4]);
+        ff1.decrypt(&[], &mut numerals).unwrap();
+        assert_eq!(numerals, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        let tweak = hex("39383736353433323130");
+        let mut text = *b"0123456789";
+        ff1.encrypt_text(&tweak, DECIMAL, &mut text).unwrap();
+        assert_eq!(&text, b"6124200773");
+        ff1.decrypt_text(&tweak, DECIMAL, &mut text).unwrap();
+        assert_eq!(&text, b"0123456789");
+
+        let ff1 = Ff1::new(&KEY, 36).unwrap();
+        let tweak = hex("3737373770717273373737");
+        let mut text = *b"0123456789abcdefghi";
+        ff1.encrypt_text(&tweak, ALPHANUMERIC, &mut text).unwrap();
+        assert_eq!(&text, b"a9tv40mll9kdu509eum");
+        ff1.decrypt_text(&tweak, ALPHANUMERIC, &mut text).unwrap();
+        assert_eq!(&text, b"0123456789abcdefghi");
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert!(Ff1::new(&KEY, 1).is_err());
+        assert!(Ff1::new(&KEY, 65537).is_err());
+        let ff1 = Ff1::new(&KEY, 10).unwrap();
+        // Too short for the domain to hold a million values.
+        assert!(ff1.encrypt(&[], &mut [1, 2, 3, 4, 5]).is_err());
+        assert!(ff1.encrypt(&[], &mut [1, 2, 3, 4, 5, 10]).is_err());
+        assert!(ff1.encrypt(&[], &mut [0; FF1_MAX_LEN + 1]).is_err());
+        assert!(ff1.encrypt_text(&[], DECIMAL, &mut { *b"12345x" }).is_err());
+        assert!(ff1.encrypt_text(&[], ALPHANUMERIC, &mut { *b"123456" }).is_err());
+        // Alphabets can't repeat symbols.
+        assert!(ff1.encrypt_text(&[], b"0123456780", &mut { *b"123456" }).is_err());
+    }
+}
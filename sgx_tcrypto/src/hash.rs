@@ -0,0 +1,55 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Hash functions implemented in Rust, behind one [`Hash`] trait.
+//!
+//! Unlike the handles of the Intel library, these states can be cloned,
+//! which HMAC and the key derivation functions rely on, and they need no
+//! allocation. SHA-256, SHA-384 and SHA-512 follow FIPS 180-4, SHA3-256 and
+//! SHA3-512 FIPS 202, and [`Keccak256`] is the original Keccak padding
+//! Ethereum hashes with. [`Blake3`] hashes long inputs several chunks at a
+//! time with SSE4.1 or AVX2 when the trusted runtime reports them. FIPS
+//! 202's SHAKE128 and SHAKE256 are extendable-output functions rather than
+//! hashes, outside the trait.
+//!
+//! States are zeroized on drop, as they may hold keys being MACed.
+
+pub use crate::blake3::Blake3;
+pub use crate::sha256::Sha256;
+pub use crate::sha3::{Keccak256, Sha3_256, Sha3_512, Shake128, Shake256, ShakeReader};
+pub use crate::sha512::{Sha384, Sha512};
+
+/// An incremental hash function.
+pub trait Hash: Clone {
+    /// The length of the blocks, or of the rate for sponges, in bytes.
+    const BLOCK_LEN: usize;
+    /// The length of the digest, in bytes.
+    const OUTPUT_LEN: usize;
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    /// Writes the first `out.len()` bytes of the digest, at most
+    /// `OUTPUT_LEN`.
+    fn finalize_into(self, out: &mut [u8]);
+
+    /// Writes the first `out.len()` bytes of the digest of `data`.
+    fn digest_into(data: &[u8], out: &mut [u8]) {
+        let mut hasher = Self::new();
+        hasher.update(data);
+        hasher.finalize_into(out);
+    }
+}
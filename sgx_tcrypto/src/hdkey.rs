@@ -0,0 +1,403 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Hierarchical deterministic keys: BIP32 for secp256k1 and SLIP-0010 for
+//! Ed25519.
+//!
+//! An [`HdWallet`] holds the master seed, typically unsealed from storage,
+//! and hands out signing handles addressed by a [`DerivationPath`]. The
+//! handles sign and report their public key, but neither they nor the
+//! wallet give out derived private keys or chain codes.
+
+use crate::ed25519::{Ed25519PrivateKey, Ed25519PublicKey, Ed25519Signature};
+use crate::secp256k1::{Secp256k1PrivateKey, Secp256k1PublicKey, Secp256k1RecoverableSignature};
+use crate::hmac::hmac_sha512;
+use crate::secret::SecretBytes;
+use crate::util::{read_rand, zeroize};
+use core::fmt;
+use core::str::FromStr;
+use sgx_types::*;
+
+/// The bit set in the indices of hardened children.
+pub const HARDENED: u32 = 0x8000_0000;
+/// The deepest path a [`DerivationPath`] holds.
+pub const MAX_DEPTH: usize = 16;
+
+const MIN_SEED_LEN: usize = 16;
+const MAX_SEED_LEN: usize = 64;
+
+/// A path of child indices from the master key, such as `m/44'/60'/0'/0/0`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DerivationPath {
+    indices: [u32; MAX_DEPTH],
+    len: usize,
+}
+
+impl DerivationPath {
+    /// Returns the path of the master key itself.
+    pub fn master() -> DerivationPath {
+        DerivationPath { indices: [0; MAX_DEPTH], len: 0 }
+    }
+
+    /// Returns the BIP44 path `m/44'/coin'/account'/change/index`.
+    pub fn bip44(coin: u32, account: u32, change: u32, index: u32) -> SgxResult<DerivationPath> {
+        DerivationPath::master()
+            .child(44 | HARDENED)?
+            .child(coin | HARDENED)?
+            .child(account | HARDENED)?
+            .child(change)?
+            .child(index)
+    }
+
+    /// Returns the path of the child `index` of this path, failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` beyond [`MAX_DEPTH`].
+    pub fn child(&self, index: u32) -> SgxResult<DerivationPath> {
+        if self.len == MAX_DEPTH {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut path = *self;
+        path.indices[path.len] = index;
+        path.len += 1;
+        Ok(path)
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices[..self.len]
+    }
+
+    pub fn is_fully_hardened(&self) -> bool {
+        self.indices().iter().all(|index| index & HARDENED != 0)
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = sgx_status_t;
+
+    /// Parses `m` followed by `/`-separated indices, hardened ones marked by
+    /// a trailing `'` or `h`.
+    fn from_str(s: &str) -> SgxResult<DerivationPath> {
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        let mut parts = s.split('/');
+        if parts.next() != Some("m") {
+            return Err(invalid);
+        }
+        let mut path = DerivationPath::master();
+        for part in parts {
+            let (digits, hardened) = match part.strip_suffix(|c| c == '\'' || c == 'h') {
+                Some(digits) => (digits, HARDENED),
+                None => (part, 0),
+            };
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid);
+            }
+            let index: u32 = digits.parse().map_err(|_| invalid)?;
+            if index & HARDENED != 0 {
+                return Err(invalid);
+            }
+            path = path.child(index | hardened)?;
+        }
+        Ok(path)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("m")?;
+        for index in self.indices() {
+            if index & HARDENED != 0 {
+                write!(f, "/{}'", index & !HARDENED)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DerivationPath({})", self)
+    }
+}
+
+/// A private key with its chain code.
+struct ExtendedKey {
+    key: SecretBytes<32>,
+    chain_code: SecretBytes<32>,
+}
+
+impl ExtendedKey {
+    fn from_hmac(mut mac: [u8; 64]) -> ExtendedKey {
+        let mut ext = ExtendedKey { key: SecretBytes::zeroed(), chain_code: SecretBytes::zeroed() };
+        ext.key.expose_secret_mut().copy_from_slice(&mac[..32]);
+        ext.chain_code.expose_secret_mut().copy_from_slice(&mac[32..]);
+        zeroize(&mut mac);
+        ext
+    }
+}
+
+/// A master seed, from which keys are derived.
+pub struct HdWallet {
+    seed: SecretBytes<MAX_SEED_LEN>,
+    len: usize,
+}
+
+impl HdWallet {
+    /// Generates a 256-bit seed with the enclave's random number generator.
+    pub fn generate() -> SgxResult<HdWallet> {
+        let mut wallet = HdWallet { seed: SecretBytes::zeroed(), len: 32 };
+        read_rand(&mut wallet.seed.expose_secret_mut()[..32])?;
+        Ok(wallet)
+    }
+
+    /// Creates a wallet from a seed of 16 to 64 bytes, such as a BIP39 seed.
+    pub fn from_seed(seed: &[u8]) -> SgxResult<HdWallet> {
+        if seed.len() < MIN_SEED_LEN || seed.len() > MAX_SEED_LEN {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut wallet = HdWallet { seed: SecretBytes::zeroed(), len: seed.len() };
+        wallet.seed.expose_secret_mut()[..seed.len()].copy_from_slice(seed);
+        Ok(wallet)
+    }
+
+    /// Returns the seed, to seal it. It must not leave the enclave otherwise.
+    pub fn seed(&self) -> &[u8] {
+        &self.seed.expose_secret()[..self.len]
+    }
+
+    /// Derives the secp256k1 key at `path` with BIP32.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` in the negligible case that
+    /// the path crosses an invalid key, which BIP32 leaves to the caller to
+    /// skip.
+    pub fn secp256k1(&self, path: &DerivationPath) -> SgxResult<Secp256k1Signer> {
+        let mut ext = ExtendedKey::from_hmac(hmac_sha512(b"Bitcoin seed", &[self.seed()]));
+        let mut key = Secp256k1PrivateKey::from_bytes(ext.key.expose_secret())?;
+        for index in path.indices() {
+            let index_bytes = index.to_be_bytes();
+            let mac = if index & HARDENED != 0 {
+                let private = key.to_bytes();
+                let chain_code = ext.chain_code.expose_secret();
+                hmac_sha512(chain_code, &[&[0], private.expose_secret(), &index_bytes])
+            } else {
+                let public = key.public_key().to_compressed();
+                hmac_sha512(ext.chain_code.expose_secret(), &[&public, &index_bytes])
+            };
+            ext = ExtendedKey::from_hmac(mac);
+            key = key.add_tweak(ext.key.expose_secret())?;
+        }
+        Ok(Secp256k1Signer { key, path: *path })
+    }
+
+    /// Derives the Ed25519 key at `path` with SLIP-0010, failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` unless every index is hardened.
+    pub fn ed25519(&self, path: &DerivationPath) -> SgxResult<Ed25519Signer> {
+        if !path.is_fully_hardened() {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut ext = ExtendedKey::from_hmac(hmac_sha512(b"ed25519 seed", &[self.seed()]));
+        for index in path.indices() {
+            let (key, chain_code) = (ext.key.expose_secret(), ext.chain_code.expose_secret());
+            let mac = hmac_sha512(chain_code, &[&[0], key, &index.to_be_bytes()]);
+            ext = ExtendedKey::from_hmac(mac);
+        }
+        let key = Ed25519PrivateKey::from_seed(ext.key.expose_secret());
+        Ok(Ed25519Signer { key, path: *path })
+    }
+}
+
+impl fmt::Debug for HdWallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HdWallet").finish_non_exhaustive()
+    }
+}
+
+/// A handle signing with the secp256k1 key derived at a path.
+pub struct Secp256k1Signer {
+    key: Secp256k1PrivateKey,
+    path: DerivationPath,
+}
+
+impl Secp256k1Signer {
+    pub fn path(&self) -> &DerivationPath {
+        &self.path
+    }
+
+    pub fn public_key(&self) -> Secp256k1PublicKey {
+        self.key.public_key()
+    }
+
+    /// Signs a 32-byte message hash, as
+    /// [`Secp256k1PrivateKey::sign_prehash`] does.
+    pub fn sign_prehash(&self, hash: &[u8; 32]) -> SgxResult<Secp256k1RecoverableSignature> {
+        self.key.sign_prehash(hash)
+    }
+}
+
+impl fmt::Debug for Secp256k1Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secp256k1Signer")
+            .field("path", &self.path)
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+/// A handle signing with the Ed25519 key derived at a path.
+pub struct Ed25519Signer {
+    key: Ed25519PrivateKey,
+    path: DerivationPath,
+}
+
+impl Ed25519Signer {
+    pub fn path(&self) -> &DerivationPath {
+        &self.path
+    }
+
+    pub fn public_key(&self) -> Ed25519PublicKey {
+        self.key.public_key()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Ed25519Signature {
+        self.key.sign(message)
+    }
+}
+
+impl fmt::Debug for Ed25519Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ed25519Signer")
+            .field("path", &self.path)
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+    use std::format;
+    use std::string::ToString;
+
+    fn key32(s: &str) -> [u8; 32] {
+        hex(s).try_into().unwrap()
+    }
+
+    #[test]
+    fn bip32_vector1() {
+        let wallet = HdWallet::from_seed(&hex("000102030405060708090a0b0c0d0e0f")).unwrap();
+        let levels = [
+            ("m", "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"),
+            ("m/0'", "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea"),
+            ("m/0'/1", "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368"),
+            ("m/0'/1/2'", "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca"),
+            ("m/0'/1/2'/2", "0f479245fb19a38a1954c5c7c0ebab2f9bdfd96a17563ef28a6a4b1a2a764ef4"),
+            (
+                "m/0'/1/2'/2/1000000000",
+                "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8",
+            ),
+        ];
+        for (path, key) in levels {
+            let signer = wallet.secp256k1(&path.parse().unwrap()).unwrap();
+            assert_eq!(signer.key.to_bytes().expose_secret(), &key32(key), "{}", path);
+            assert_eq!(signer.path().to_string(), path);
+        }
+    }
+
+    #[test]
+    fn slip10_ed25519_vector1() {
+        let wallet = HdWallet::from_seed(&hex("000102030405060708090a0b0c0d0e0f")).unwrap();
+        let levels = [
+            ("m", "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"),
+            ("m/0'", "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3"),
+            ("m/0'/1'", "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2"),
+            ("m/0'/1'/2'", "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9"),
+            ("m/0'/1'/2'/2'", "30d1dc7e5fc04c31219ab25a27ae00b50f6fd66622f6e9c913253d6511d1e662"),
+            (
+                "m/0'/1'/2'/2'/1000000000'",
+                "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793",
+            ),
+        ];
+        for (path, key) in levels {
+            let signer = wallet.ed25519(&path.parse().unwrap()).unwrap();
+            assert_eq!(
+                signer.public_key(),
+                Ed25519PrivateKey::from_seed(&key32(key)).public_key(),
+                "{}",
+                path
+            );
+        }
+        assert_eq!(
+            wallet.ed25519(&"m/0'/1".parse().unwrap()).unwrap_err(),
+            sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let path: DerivationPath = "m/0h/2147483647'/2147483647".parse().unwrap();
+        assert_eq!(path.indices(), [HARDENED, u32::MAX, HARDENED - 1]);
+        assert_eq!(path.to_string(), "m/0'/2147483647'/2147483647");
+        assert!(!path.is_fully_hardened());
+        assert!(DerivationPath::master().is_fully_hardened());
+        assert_eq!("m".parse::<DerivationPath>().unwrap(), DerivationPath::master());
+        assert_eq!(DerivationPath::bip44(60, 0, 0, 5).unwrap().to_string(), "m/44'/60'/0'/0/5");
+        for bad in [
+            "",
+            "M",
+            "m/",
+            "m//1",
+            "m/x",
+            "m/-1",
+            "m/+1",
+            "m/1''",
+            "m/2147483648",
+            "m/4294967296",
+            "n/1",
+        ] {
+            assert!(bad.parse::<DerivationPath>().is_err(), "{}", bad);
+        }
+
+        let deepest = format!("m{}", "/1".repeat(MAX_DEPTH));
+        let path: DerivationPath = deepest.parse().unwrap();
+        assert_eq!(path.child(1).unwrap_err(), sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        assert!(format!("{}/1", deepest).parse::<DerivationPath>().is_err());
+
+        assert!(HdWallet::from_seed(&[0; MIN_SEED_LEN - 1]).is_err());
+        assert!(HdWallet::from_seed(&[0; MAX_SEED_LEN + 1]).is_err());
+        assert_eq!(HdWallet::from_seed(&[9; MAX_SEED_LEN]).unwrap().seed(), [9; MAX_SEED_LEN]);
+    }
+
+    #[test]
+    fn invalid_child() {
+        // A child key is invalid when the tweak isn't below the order or the
+        // sum is zero; derivation then fails rather than wrapping around.
+        let order = key32("fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141");
+        let key = Secp256k1PrivateKey::from_bytes(&key32(
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+        ))
+        .unwrap();
+        assert_eq!(key.add_tweak(&order).unwrap_err(), sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        assert_eq!(
+            key.add_tweak(&[0xff; 32]).unwrap_err(),
+            sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+        );
+        let negated = key32("170cd18dc2130bfae5105371d36c3639089aabae977af021ab3da57507f2d60c");
+        assert_eq!(key.add_tweak(&negated).unwrap_err(), sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        assert!(key.add_tweak(&[0; 32]).is_ok());
+    }
+}
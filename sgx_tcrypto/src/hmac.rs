@@ -0,0 +1,200 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! HMAC (RFC 2104) over the software hash functions.
+
+use crate::hash::{Hash, Sha256, Sha384, Sha512};
+use crate::util::{ct_eq, zeroize};
+use sgx_types::*;
+
+/// The largest block and output of the hash functions, the block being the
+/// rate of SHA3-256.
+const MAX_BLOCK_LEN: usize = 136;
+pub(crate) const MAX_OUTPUT_LEN: usize = 64;
+
+/// An HMAC state, fed incrementally; keyed once, it can be cloned for every
+/// message, or to fork a MAC computed over a common prefix.
+#[derive(Clone)]
+pub struct Hmac<D: Hash> {
+    inner: D,
+    outer: D,
+}
+
+impl<D: Hash> Hmac<D> {
+    pub fn new(key: &[u8]) -> Hmac<D> {
+        let mut block = [0_u8; MAX_BLOCK_LEN];
+        if key.len() > D::BLOCK_LEN {
+            let mut hasher = D::new();
+            hasher.update(key);
+            hasher.finalize_into(&mut block[..D::OUTPUT_LEN]);
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = D::new();
+        let mut outer = D::new();
+        let mut pad = [0_u8; MAX_BLOCK_LEN];
+        for (p, k) in pad.iter_mut().zip(block.iter()) {
+            *p = k ^ 0x36;
+        }
+        inner.update(&pad[..D::BLOCK_LEN]);
+        for (p, k) in pad.iter_mut().zip(block.iter()) {
+            *p = k ^ 0x5c;
+        }
+        outer.update(&pad[..D::BLOCK_LEN]);
+        zeroize(&mut block);
+        zeroize(&mut pad);
+        Hmac { inner, outer }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    /// Writes the first `out.len()` bytes of the MAC, at most `OUTPUT_LEN`.
+    pub fn finalize_into(self, out: &mut [u8]) {
+        let mut digest = [0_u8; MAX_OUTPUT_LEN];
+        let Hmac { inner, mut outer } = self;
+        inner.finalize_into(&mut digest[..D::OUTPUT_LEN]);
+        outer.update(&digest[..D::OUTPUT_LEN]);
+        outer.finalize_into(out);
+        zeroize(&mut digest);
+    }
+
+    /// Checks the MAC against `tag`, which may be truncated, in constant
+    /// time, failing with `SGX_ERROR_MAC_MISMATCH`.
+    pub fn verify(self, tag: &[u8]) -> SgxError {
+        if tag.is_empty() || tag.len() > D::OUTPUT_LEN {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut mac = [0_u8; MAX_OUTPUT_LEN];
+        self.finalize_into(&mut mac[..tag.len()]);
+        let equal = ct_eq(&mac[..tag.len()], tag);
+        zeroize(&mut mac);
+        if equal {
+            Ok(())
+        } else {
+            Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH)
+        }
+    }
+}
+
+pub type HmacSha256 = Hmac<Sha256>;
+pub type HmacSha384 = Hmac<Sha384>;
+pub type HmacSha512 = Hmac<Sha512>;
+
+impl Hmac<Sha256> {
+    pub fn finalize(self) -> [u8; 32] {
+        let mut out = [0_u8; 32];
+        self.finalize_into(&mut out);
+        out
+    }
+}
+
+impl Hmac<Sha384> {
+    pub fn finalize(self) -> [u8; 48] {
+        let mut out = [0_u8; 48];
+        self.finalize_into(&mut out);
+        out
+    }
+}
+
+impl Hmac<Sha512> {
+    pub fn finalize(self) -> [u8; 64] {
+        let mut out = [0_u8; 64];
+        self.finalize_into(&mut out);
+        out
+    }
+}
+
+/// Computes HMAC-SHA512 of the concatenation of `parts`.
+pub(crate) fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new(key);
+    for part in parts {
+        mac.update(part);
+    }
+    let mut out = [0_u8; 64];
+    mac.finalize_into(&mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+
+    #[test]
+    fn rfc4231() {
+        let large_key = [0xaa_u8; 131];
+        let cases: [(&[u8], &[u8], &str); 6] = [
+            (&[0x0b; 20], b"Hi There", "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"),
+            (b"Jefe", b"what do ya want for nothing?", "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"),
+            (&[0xaa; 20], &[0xdd; 50], "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe"),
+            (
+                &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25],
+                &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                &large_key,
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                &large_key,
+                b"This is a test using a larger than block-size key and a larger than block-size data. \
+                  The key needs to be hashed before being used by the HMAC algorithm.",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+        for (key, data, mac) in cases {
+            let mut hmac = HmacSha256::new(key);
+            hmac.update(data);
+            hmac.clone().verify(&hex(mac)).unwrap();
+            assert_eq!(hmac.finalize()[..], hex(mac)[..]);
+            // Fed in pieces, from a state forked after the first one.
+            let (head, tail) = data.split_at(data.len() / 2);
+            let mut hmac = HmacSha256::new(key);
+            hmac.update(head);
+            let mut fork = hmac.clone();
+            fork.update(tail);
+            assert_eq!(fork.finalize()[..], hex(mac)[..]);
+        }
+
+        // Test case 5 checks a tag truncated to 128 bits.
+        let mut hmac = HmacSha256::new(&[0x0c; 20]);
+        hmac.update(b"Test With Truncation");
+        hmac.verify(&hex("a3b6167473100ee06e0c796c2955552b")).unwrap();
+    }
+
+    #[test]
+    fn rejects_wrong_tags() {
+        let mut hmac = HmacSha256::new(b"Jefe");
+        hmac.update(b"what do ya want for nothing?");
+        let mut tag = hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        hmac.clone().verify(&tag).unwrap();
+        for i in [0, 31] {
+            tag[i] ^= 1;
+            assert_eq!(hmac.clone().verify(&tag), Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH));
+            tag[i] ^= 1;
+        }
+        hmac.clone().verify(&tag[..31]).unwrap();
+        assert_eq!(hmac.clone().verify(&[]), Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER));
+        tag.push(0);
+        assert_eq!(hmac.verify(&tag), Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER));
+    }
+}
@@ -0,0 +1,673 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Key derivation functions: HKDF (RFC 5869), PBKDF2 (RFC 8018), scrypt
+//! (RFC 7914) and Argon2id (RFC 9106).
+//!
+//! HKDF derives keys from keys. The others stretch passphrases, and scrypt
+//! and Argon2id are memory hard: the crate doesn't allocate, so they run in
+//! memory the caller provides, sized by their parameters. That memory is in
+//! the EPC, so their cost should stay well within it; paging enclave memory
+//! out is both slow and visible to the host.
+
+use crate::blake2b::Blake2b;
+use crate::hash::Hash;
+use crate::hmac::{Hmac, MAX_OUTPUT_LEN};
+use crate::sha256::Sha256;
+use crate::sha512::Sha512;
+use crate::util::zeroize;
+use sgx_types::*;
+
+/// Derives `okm.len()` bytes with HKDF-SHA256, at most 8160.
+pub fn hkdf_sha256(salt: &[u8], ikm: &[u8], info: &[u8], okm: &mut [u8]) -> SgxError {
+    hkdf::<Sha256>(salt, ikm, info, okm)
+}
+
+/// Derives `okm.len()` bytes with HKDF-SHA512, at most 16320.
+pub fn hkdf_sha512(salt: &[u8], ikm: &[u8], info: &[u8], okm: &mut [u8]) -> SgxError {
+    hkdf::<Sha512>(salt, ikm, info, okm)
+}
+
+fn hkdf<D: Hash>(salt: &[u8], ikm: &[u8], info: &[u8], okm: &mut [u8]) -> SgxError {
+    if okm.len() > 255 * D::OUTPUT_LEN {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    // An empty salt keys HMAC the same as the zeroes RFC 5869 substitutes.
+    let mut prk = [0_u8; MAX_OUTPUT_LEN];
+    let mut extract = Hmac::<D>::new(salt);
+    extract.update(ikm);
+    extract.finalize_into(&mut prk[..D::OUTPUT_LEN]);
+
+    let key = Hmac::<D>::new(&prk[..D::OUTPUT_LEN]);
+    let mut t = [0_u8; MAX_OUTPUT_LEN];
+    for (i, chunk) in okm.chunks_mut(D::OUTPUT_LEN).enumerate() {
+        let mut mac = key.clone();
+        if i > 0 {
+            mac.update(&t[..D::OUTPUT_LEN]);
+        }
+        mac.update(info);
+        mac.update(&[i as u8 + 1]);
+        mac.finalize_into(&mut t[..D::OUTPUT_LEN]);
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+    zeroize(&mut prk);
+    zeroize(&mut t);
+    Ok(())
+}
+
+/// Derives `out.len()` bytes with PBKDF2-HMAC-SHA256.
+pub fn pbkdf2_hmac_sha256(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    out: &mut [u8],
+) -> SgxError {
+    pbkdf2::<Sha256>(password, salt, iterations, out)
+}
+
+/// Derives `out.len()` bytes with PBKDF2-HMAC-SHA512.
+pub fn pbkdf2_hmac_sha512(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    out: &mut [u8],
+) -> SgxError {
+    pbkdf2::<Sha512>(password, salt, iterations, out)
+}
+
+fn pbkdf2<D: Hash>(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) -> SgxError {
+    if iterations == 0 || out.is_empty() {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    let key = Hmac::<D>::new(password);
+    let mut u = [0_u8; MAX_OUTPUT_LEN];
+    let mut t = [0_u8; MAX_OUTPUT_LEN];
+    for (i, chunk) in out.chunks_mut(D::OUTPUT_LEN).enumerate() {
+        let mut mac = key.clone();
+        mac.update(salt);
+        mac.update(&(i as u32 + 1).to_be_bytes());
+        mac.finalize_into(&mut u[..D::OUTPUT_LEN]);
+        t = u;
+        for _ in 1..iterations {
+            let mut mac = key.clone();
+            mac.update(&u[..D::OUTPUT_LEN]);
+            mac.finalize_into(&mut u[..D::OUTPUT_LEN]);
+            for (t, u) in t.iter_mut().zip(u.iter()) {
+                *t ^= u;
+            }
+        }
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+    zeroize(&mut u);
+    zeroize(&mut t);
+    Ok(())
+}
+
+/// The cost parameters of scrypt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScryptParams {
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+impl ScryptParams {
+    /// Creates parameters with `N = 2^log_n`, block size `r` and
+    /// parallelism `p`, failing with `SGX_ERROR_INVALID_PARAMETER` if they
+    /// are out of the bounds of RFC 7914 or their memory can't be
+    /// addressed.
+    pub fn new(log_n: u8, r: u32, p: u32) -> SgxResult<ScryptParams> {
+        let invalid = Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        if log_n == 0 || log_n >= 32 || r == 0 || p == 0 || (r as u64) * (p as u64) >= 1 << 30 {
+            return invalid;
+        }
+        let words = (32 * r as u128) * ((1_u128 << log_n) + p as u128 + 2);
+        if words * 4 > usize::MAX as u128 {
+            return invalid;
+        }
+        Ok(ScryptParams { log_n, r, p })
+    }
+
+    /// Returns the length of the scratch memory, in 32-bit words; it is
+    /// `128 * r * (N + p + 2)` bytes.
+    pub fn scratch_len(&self) -> usize {
+        32 * self.r as usize * ((1_usize << self.log_n) + self.p as usize + 2)
+    }
+}
+
+/// Derives `out.len()` bytes with scrypt, in `scratch`, which must hold at
+/// least [`ScryptParams::scratch_len`] words and is zeroed afterwards.
+pub fn scrypt(
+    password: &[u8],
+    salt: &[u8],
+    params: &ScryptParams,
+    scratch: &mut [u32],
+    out: &mut [u8],
+) -> SgxError {
+    if scratch.len() < params.scratch_len() || out.is_empty() {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    let n = 1_usize << params.log_n;
+    let block_words = 32 * params.r as usize;
+    let scratch = &mut scratch[..params.scratch_len()];
+    let (b, rest) = scratch.split_at_mut(block_words * params.p as usize);
+    let (v, rest) = rest.split_at_mut(block_words * n);
+    let (x, y) = rest.split_at_mut(block_words);
+
+    let key = Hmac::<Sha256>::new(password);
+    let mut t = [0_u8; 32];
+    for (i, words) in b.chunks_mut(8).enumerate() {
+        let mut mac = key.clone();
+        mac.update(salt);
+        mac.update(&(i as u32 + 1).to_be_bytes());
+        mac.finalize_into(&mut t);
+        for (word, bytes) in words.iter_mut().zip(t.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+
+    for block in b.chunks_mut(block_words) {
+        ro_mix(block, v, x, y, n);
+    }
+
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let mut mac = key.clone();
+        for words in b.chunks(8) {
+            for (bytes, word) in t.chunks_exact_mut(4).zip(words.iter()) {
+                bytes.copy_from_slice(&word.to_le_bytes());
+            }
+            mac.update(&t);
+        }
+        mac.update(&(i as u32 + 1).to_be_bytes());
+        mac.finalize_into(&mut t);
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+    zeroize(&mut t);
+    for word in scratch.iter_mut() {
+        unsafe { core::ptr::write_volatile(word, 0) };
+    }
+    Ok(())
+}
+
+fn ro_mix(b: &mut [u32], v: &mut [u32], x: &mut [u32], y: &mut [u32], n: usize) {
+    let len = b.len();
+    x.copy_from_slice(b);
+    for i in 0..n {
+        v[i * len..(i + 1) * len].copy_from_slice(x);
+        block_mix(x, y);
+        x.copy_from_slice(y);
+    }
+    for _ in 0..n {
+        let j = x[len - 16] as usize & (n - 1);
+        for (x, v) in x.iter_mut().zip(v[j * len..(j + 1) * len].iter()) {
+            *x ^= v;
+        }
+        block_mix(x, y);
+        x.copy_from_slice(y);
+    }
+    b.copy_from_slice(x);
+}
+
+fn block_mix(input: &[u32], output: &mut [u32]) {
+    let r = input.len() / 32;
+    let mut x = [0_u32; 16];
+    x.copy_from_slice(&input[input.len() - 16..]);
+    for (i, block) in input.chunks_exact(16).enumerate() {
+        for (x, b) in x.iter_mut().zip(block.iter()) {
+            *x ^= b;
+        }
+        salsa20_8(&mut x);
+        let dst = if i % 2 == 0 { i / 2 } else { r + i / 2 };
+        output[dst * 16..dst * 16 + 16].copy_from_slice(&x);
+    }
+}
+
+fn salsa20_8(b: &mut [u32; 16]) {
+    fn quarter(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+        x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+        x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+        x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+    }
+    let mut x = *b;
+    for _ in 0..4 {
+        quarter(&mut x, 0, 4, 8, 12);
+        quarter(&mut x, 5, 9, 13, 1);
+        quarter(&mut x, 10, 14, 2, 6);
+        quarter(&mut x, 15, 3, 7, 11);
+        quarter(&mut x, 0, 1, 2, 3);
+        quarter(&mut x, 5, 6, 7, 4);
+        quarter(&mut x, 10, 11, 8, 9);
+        quarter(&mut x, 15, 12, 13, 14);
+    }
+    for (b, x) in b.iter_mut().zip(x.iter()) {
+        *b = b.wrapping_add(*x);
+    }
+}
+
+/// The number of 64-bit words in a block of Argon2 memory.
+pub const ARGON2_BLOCK_WORDS: usize = 128;
+
+/// A 1 KiB block of Argon2 memory.
+pub type Argon2Block = [u64; ARGON2_BLOCK_WORDS];
+
+const ARGON2_VERSION: u32 = 0x13;
+const ARGON2ID: u32 = 2;
+const SYNC_POINTS: usize = 4;
+
+/// The cost parameters of Argon2id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Argon2Params {
+    memory_kib: u32,
+    iterations: u32,
+    lanes: u32,
+}
+
+impl Argon2Params {
+    /// 19 MiB, two passes and one lane, the lightest setting RFC 9106 and
+    /// OWASP accept for passphrases, which leaves most of the EPC free.
+    pub const ENCLAVE_DEFAULT: Argon2Params =
+        Argon2Params { memory_kib: 19 * 1024, iterations: 2, lanes: 1 };
+
+    /// Creates parameters using `memory_kib` KiB over `iterations` passes
+    /// and `lanes` lanes, failing with `SGX_ERROR_INVALID_PARAMETER` if
+    /// they are out of the bounds of RFC 9106.
+    pub fn new(memory_kib: u32, iterations: u32, lanes: u32) -> SgxResult<Argon2Params> {
+        if iterations == 0 || lanes == 0 || lanes >= 1 << 24 || memory_kib / 8 < lanes {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(Argon2Params { memory_kib, iterations, lanes })
+    }
+
+    /// Returns the number of blocks of memory used, `memory_kib` rounded
+    /// down to a multiple of `4 * lanes`.
+    pub fn blocks(&self) -> usize {
+        let group = 4 * self.lanes as usize;
+        self.memory_kib as usize / group * group
+    }
+}
+
+/// Derives `out.len()` bytes, at least 4, with Argon2id, in `memory`,
+/// which must hold at least [`Argon2Params::blocks`] blocks and is zeroed
+/// afterwards. The salt must be at least 8 bytes long.
+pub fn argon2id(
+    password: &[u8],
+    salt: &[u8],
+    params: &Argon2Params,
+    memory: &mut [Argon2Block],
+    out: &mut [u8],
+) -> SgxError {
+    let blocks = params.blocks();
+    if memory.len() < blocks || salt.len() < 8 || out.len() < 4 || out.len() > u32::MAX as usize {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    let memory = &mut memory[..blocks];
+    let lanes = params.lanes as usize;
+    let lane_len = blocks / lanes;
+    let segment_len = lane_len / SYNC_POINTS;
+
+    let mut h0 = [0_u8; 64];
+    let mut hasher = Blake2b::new(64);
+    for value in [params.lanes, out.len() as u32, params.memory_kib, params.iterations]
+        .iter()
+        .chain([ARGON2_VERSION, ARGON2ID].iter())
+    {
+        hasher.update(&value.to_le_bytes());
+    }
+    for input in [password, salt, &[], &[]].iter() {
+        hasher.update(&(input.len() as u32).to_le_bytes());
+        hasher.update(input);
+    }
+    hasher.finalize_into(&mut h0);
+
+    let mut bytes = [0_u8; 1024];
+    for lane in 0..lanes {
+        for column in 0..2_u32 {
+            hash_long(&[&h0, &column.to_le_bytes(), &(lane as u32).to_le_bytes()], &mut bytes);
+            load_block(&mut memory[lane * lane_len + column as usize], &bytes);
+        }
+    }
+
+    let mut address = [0_u64; ARGON2_BLOCK_WORDS];
+    let mut input = [0_u64; ARGON2_BLOCK_WORDS];
+    for pass in 0..params.iterations as usize {
+        for slice in 0..SYNC_POINTS {
+            for lane in 0..lanes {
+                // The first half of the first pass indexes independently of
+                // the data, against side channels; the rest depends on it,
+                // against tradeoff attacks.
+                let independent = pass == 0 && slice < 2;
+                if independent {
+                    input = [0; ARGON2_BLOCK_WORDS];
+                    input[0] = pass as u64;
+                    input[1] = lane as u64;
+                    input[2] = slice as u64;
+                    input[3] = blocks as u64;
+                    input[4] = params.iterations as u64;
+                    input[5] = ARGON2ID as u64;
+                }
+                let start = if pass == 0 && slice == 0 {
+                    next_addresses(&mut address, &mut input);
+                    2
+                } else {
+                    0
+                };
+                for index in start..segment_len {
+                    let current = lane * lane_len + slice * segment_len + index;
+                    // The first block of a lane follows on from its last.
+                    let previous = if slice == 0 && index == 0 {
+                        current + lane_len - 1
+                    } else {
+                        current - 1
+                    };
+                    let pseudo_rand = if independent {
+                        if index % ARGON2_BLOCK_WORDS == 0 {
+                            next_addresses(&mut address, &mut input);
+                        }
+                        address[index % ARGON2_BLOCK_WORDS]
+                    } else {
+                        memory[previous][0]
+                    };
+                    let ref_lane = if pass == 0 && slice == 0 {
+                        lane
+                    } else {
+                        (pseudo_rand >> 32) as usize % lanes
+                    };
+                    let position = Position { pass, slice, index, segment_len, lane_len };
+                    let ref_index =
+                        position.reference(pseudo_rand & 0xffff_ffff, ref_lane == lane);
+                    let reference = memory[ref_lane * lane_len + ref_index];
+                    let prev = memory[previous];
+                    fill_block(&prev, &reference, &mut memory[current], pass != 0);
+                }
+            }
+        }
+    }
+
+    let mut last = memory[lane_len - 1];
+    for lane in 1..lanes {
+        for (word, other) in last.iter_mut().zip(memory[lane * lane_len + lane_len - 1].iter()) {
+            *word ^= other;
+        }
+    }
+    for (chunk, word) in bytes.chunks_exact_mut(8).zip(last.iter()) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    hash_long(&[&bytes], out);
+
+    zeroize(&mut h0);
+    zeroize(&mut bytes);
+    wipe_block(&mut last);
+    wipe_block(&mut address);
+    for block in memory.iter_mut() {
+        wipe_block(block);
+    }
+    Ok(())
+}
+
+struct Position {
+    pass: usize,
+    slice: usize,
+    index: usize,
+    segment_len: usize,
+    lane_len: usize,
+}
+
+impl Position {
+    /// Maps a pseudo-random value onto the index of a block within the
+    /// reference lane, as section 3.4.2 of RFC 9106 does.
+    fn reference(&self, j1: u64, same_lane: bool) -> usize {
+        let finished = if self.pass == 0 {
+            self.slice * self.segment_len
+        } else {
+            self.lane_len - self.segment_len
+        };
+        let area = if same_lane {
+            finished + self.index - 1
+        } else if self.index == 0 {
+            finished - 1
+        } else {
+            finished
+        };
+        let relative = (j1 * j1) >> 32;
+        let relative = area - 1 - ((area as u64 * relative) >> 32) as usize;
+        let start = if self.pass != 0 && self.slice != SYNC_POINTS - 1 {
+            (self.slice + 1) * self.segment_len
+        } else {
+            0
+        };
+        (start + relative) % self.lane_len
+    }
+}
+
+/// The variable-length hash `H'` of RFC 9106.
+fn hash_long(parts: &[&[u8]], out: &mut [u8]) {
+    let len = (out.len() as u32).to_le_bytes();
+    if out.len() <= 64 {
+        let mut hasher = Blake2b::new(out.len());
+        hasher.update(&len);
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize_into(out);
+        return;
+    }
+
+    let mut v = [0_u8; 64];
+    let mut hasher = Blake2b::new(64);
+    hasher.update(&len);
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize_into(&mut v);
+    out[..32].copy_from_slice(&v[..32]);
+    let mut pos = 32;
+    while out.len() - pos > 64 {
+        let mut hasher = Blake2b::new(64);
+        hasher.update(&v);
+        hasher.finalize_into(&mut v);
+        out[pos..pos + 32].copy_from_slice(&v[..32]);
+        pos += 32;
+    }
+    let mut hasher = Blake2b::new(out.len() - pos);
+    hasher.update(&v);
+    hasher.finalize_into(&mut out[pos..]);
+    zeroize(&mut v);
+}
+
+fn load_block(block: &mut Argon2Block, bytes: &[u8; 1024]) {
+    for (word, chunk) in block.iter_mut().zip(bytes.chunks_exact(8)) {
+        let mut le = [0_u8; 8];
+        le.copy_from_slice(chunk);
+        *word = u64::from_le_bytes(le);
+    }
+}
+
+fn wipe_block(block: &mut Argon2Block) {
+    for word in block.iter_mut() {
+        unsafe { core::ptr::write_volatile(word, 0) };
+    }
+}
+
+fn next_addresses(address: &mut Argon2Block, input: &mut Argon2Block) {
+    let zero = [0_u64; ARGON2_BLOCK_WORDS];
+    input[6] += 1;
+    let mut tmp = [0_u64; ARGON2_BLOCK_WORDS];
+    fill_block(&zero, input, &mut tmp, false);
+    fill_block(&zero, &tmp, address, false);
+}
+
+/// Computes the compression `G(prev, reference)` into `next`, XORing it
+/// with the old contents of `next` after the first pass.
+fn fill_block(prev: &Argon2Block, reference: &Argon2Block, next: &mut Argon2Block, xor: bool) {
+    let mut r = [0_u64; ARGON2_BLOCK_WORDS];
+    for (r, (a, b)) in r.iter_mut().zip(prev.iter().zip(reference.iter())) {
+        *r = a ^ b;
+    }
+    let mut tmp = r;
+    if xor {
+        for (t, n) in tmp.iter_mut().zip(next.iter()) {
+            *t ^= n;
+        }
+    }
+    for row in 0..8 {
+        let mut idx = [0_usize; 16];
+        for (k, idx) in idx.iter_mut().enumerate() {
+            *idx = 16 * row + k;
+        }
+        permute(&mut r, &idx);
+    }
+    for column in 0..8 {
+        let mut idx = [0_usize; 16];
+        for (k, idx) in idx.iter_mut().enumerate() {
+            *idx = 16 * (k / 2) + 2 * column + k % 2;
+        }
+        permute(&mut r, &idx);
+    }
+    for (n, (t, r)) in next.iter_mut().zip(tmp.iter().zip(r.iter())) {
+        *n = t ^ r;
+    }
+}
+
+/// The BLAKE2b round, with multiplications, over the words at `idx`.
+fn permute(r: &mut Argon2Block, idx: &[usize; 16]) {
+    fn blamka(a: u64, b: u64) -> u64 {
+        let product = (a & 0xffff_ffff).wrapping_mul(b & 0xffff_ffff);
+        a.wrapping_add(b).wrapping_add(product.wrapping_mul(2))
+    }
+    fn gb(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize) {
+        v[a] = blamka(v[a], v[b]);
+        v[d] = (v[d] ^ v[a]).rotate_right(32);
+        v[c] = blamka(v[c], v[d]);
+        v[b] = (v[b] ^ v[c]).rotate_right(24);
+        v[a] = blamka(v[a], v[b]);
+        v[d] = (v[d] ^ v[a]).rotate_right(16);
+        v[c] = blamka(v[c], v[d]);
+        v[b] = (v[b] ^ v[c]).rotate_right(63);
+    }
+    let mut v = [0_u64; 16];
+    for (v, &i) in v.iter_mut().zip(idx.iter()) {
+        *v = r[i];
+    }
+    gb(&mut v, 0, 4, 8, 12);
+    gb(&mut v, 1, 5, 9, 13);
+    gb(&mut v, 2, 6, 10, 14);
+    gb(&mut v, 3, 7, 11, 15);
+    gb(&mut v, 0, 5, 10, 15);
+    gb(&mut v, 1, 6, 11, 12);
+    gb(&mut v, 2, 7, 8, 13);
+    gb(&mut v, 3, 4, 9, 14);
+    for (v, &i) in v.iter().zip(idx.iter()) {
+        r[i] = *v;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+    use std::vec;
+
+    #[test]
+    fn hkdf() {
+        // RFC 5869, test cases 1 and 3.
+        let mut okm = [0_u8; 42];
+        hkdf_sha256(&hex("000102030405060708090a0b0c"), &[0x0b; 22], &hex("f0f1f2f3f4f5f6f7f8f9"), &mut okm)
+            .unwrap();
+        assert_eq!(
+            okm[..],
+            hex("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865")[..]
+        );
+        hkdf_sha256(&[], &[0x0b; 22], &[], &mut okm).unwrap();
+        assert_eq!(
+            okm[..],
+            hex("8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8")[..]
+        );
+        let mut okm = [0_u8; 42];
+        hkdf_sha512(&hex("73616c7479"), b"ikm-bytes", b"info", &mut okm).unwrap();
+        assert_eq!(
+            okm[..],
+            hex("2856b1dab0010dc4061bec9c11e442c8744eb84c00176719eb15b6e2ec2d3a071a119f558eae07f5a594")[..]
+        );
+        assert!(hkdf_sha256(&[], &[0x0b; 22], &[], &mut [0; 255 * 32 + 1]).is_err());
+    }
+
+    #[test]
+    fn pbkdf2() {
+        let mut out = [0_u8; 32];
+        pbkdf2_hmac_sha256(b"password", b"salt", 1, &mut out).unwrap();
+        assert_eq!(out[..], hex("120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b")[..]);
+        pbkdf2_hmac_sha256(b"password", b"salt", 4096, &mut out).unwrap();
+        assert_eq!(out[..], hex("c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a")[..]);
+        let mut out = [0_u8; 77];
+        pbkdf2_hmac_sha512(b"pw", b"salt", 2, &mut out).unwrap();
+        assert_eq!(
+            out[..],
+            hex("8816cc3aaf71373639c3415049b2b6572f8d2bad8d7641ff6402939df50db47aefea61bf81b34afa696ccc5d3fe6e5\
+                 5f98827522a407dc5acbcaa0a6023649a6392a0e58baab4fe4d6eb622c83")[..]
+        );
+        assert!(pbkdf2_hmac_sha256(b"password", b"salt", 0, &mut [0; 32]).is_err());
+    }
+
+    #[test]
+    fn scrypt_rfc7914() {
+        // Section 12, the first two vectors.
+        let vectors = [
+            (
+                &b""[..],
+                &b""[..],
+                4,
+                1,
+                1,
+                "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442\
+                 fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906",
+            ),
+            (
+                &b"password"[..],
+                &b"NaCl"[..],
+                10,
+                8,
+                16,
+                "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162\
+                 2eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640",
+            ),
+        ];
+        for (password, salt, log_n, r, p, want) in vectors {
+            let params = ScryptParams::new(log_n, r, p).unwrap();
+            let mut scratch = vec![0_u32; params.scratch_len()];
+            let mut out = [0_u8; 64];
+            scrypt(password, salt, &params, &mut scratch, &mut out).unwrap();
+            assert_eq!(out[..], hex(want)[..]);
+            assert!(scratch.iter().all(|&word| word == 0));
+        }
+        assert!(ScryptParams::new(0, 1, 1).is_err());
+        let params = ScryptParams::new(4, 1, 1).unwrap();
+        let mut short = vec![0_u32; params.scratch_len() - 1];
+        assert!(scrypt(b"", b"", &params, &mut short, &mut [0; 64]).is_err());
+    }
+
+    #[test]
+    fn argon2id_known_answer() {
+        let params = Argon2Params::new(32, 3, 4).unwrap();
+        let mut memory = vec![[0_u64; ARGON2_BLOCK_WORDS]; params.blocks()];
+        let mut out = [0_u8; 32];
+        argon2id(b"password", b"somesalt", &params, &mut memory, &mut out).unwrap();
+        assert_eq!(out[..], hex("bb0cc80a3e671149526915418c6eefe761bb19d5d2d567a017703e0cea6ab05c")[..]);
+        assert!(argon2id(b"password", b"short", &params, &mut memory, &mut out).is_err());
+        assert!(argon2id(b"password", b"somesalt", &params, &mut memory[1..], &mut out).is_err());
+    }
+}
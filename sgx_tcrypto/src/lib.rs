@@ -20,6 +20,35 @@
 //! The Intel(R) Software Guard Extensions SDK includes a trusted cryptography library named sgx_tcrypto.
 //! It includes the cryptographic functions used by other trusted libraries included in the SDK
 //!
+//! The [`aead`] module adds authenticated ciphers the Intel library lacks, implemented in Rust
+//! on top of its AES primitives where they need AES. The [`ed25519`] and [`x25519`] modules
+//! do the same for Curve25519 signatures and key agreement, and [`secp256k1`] for the
+//! ECDSA curve of Bitcoin and Ethereum; [`hdkey`] derives wallet keys for both curves. The
+//! [`kdf`] module derives keys from keys and passphrases, and [`tss`] signs with secp256k1
+//! keys split between two parties. The [`bls12_381`] module signs and aggregates as Ethereum
+//! validators do, and [`rsa`] adds PSS signatures and OAEP encryption with larger keys.
+//! They share the constant-time integers of [`ctbignum`], which protocols built on this crate
+//! can use for their own arithmetic. Against quantum computers, [`mlkem`] encapsulates keys
+//! with ML-KEM-768, alone or in a hybrid with X25519, and [`mldsa`] signs with ML-DSA-65.
+//!
+//! Ed25519 and secp256k1 signatures, and with [`p256`] the ECDSA signatures of the Intel
+//! library, can also be verified in batches. Secrets like wallet seeds are split into shares
+//! with [`shamir`], verifiably if they are P-256 scalars, and [`fpe`] encrypts values such as
+//! card numbers into tokens of the same format.
+//!
+//! The [`aes`] module adds AES-XTS, for sector-addressed storage, and AES-CTR with counters
+//! the caller manages, with 128- and 256-bit keys on AES-NI.
+//!
+//! The [`hash`] module gathers the hash functions implemented in Rust, SHA-2, SHA-3, Keccak
+//! and BLAKE3, behind one trait, and [`mac`] the MACs over them and AES; their states are
+//! values which can be cloned, unlike the handles of the Intel library.
+//!
+//! The primitives these modules take from the Intel library, random numbers and AES, go through
+//! a [`provider::CryptoProvider`], which an enclave can replace as it initializes.
+//!
+//! Keys are held, and exported, as [`secret::Secret`] values, which are zeroized when they're
+//! dropped.
+//!
 
 #![no_std]
 #![cfg_attr(target_env = "sgx", feature(rustc_private))]
@@ -28,6 +57,43 @@
 #![allow(clippy::too_many_arguments)]
 
 extern crate sgx_types;
+#[cfg(test)]
+extern crate std;
 
+pub mod aead;
+pub mod aes;
+mod blake2b;
+mod blake3;
+pub mod bls12_381;
+mod chacha;
 mod crypto;
+pub mod ctbignum;
+pub mod ed25519;
+mod field25519;
+mod field256;
+mod field381;
+pub mod fpe;
+pub mod hash;
+pub mod hdkey;
+mod hmac;
+pub mod kdf;
+pub mod mac;
+pub mod mldsa;
+pub mod mlkem;
+mod msm;
+pub mod p256;
+mod paillier;
+mod poly1305;
+pub mod provider;
+pub mod rng;
+pub mod rsa;
+pub mod secp256k1;
+pub mod secret;
+pub mod shamir;
+mod sha256;
+mod sha3;
+mod sha512;
+pub mod tss;
+mod util;
+pub mod x25519;
 pub use self::crypto::*;
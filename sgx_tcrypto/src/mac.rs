@@ -0,0 +1,184 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Incremental message authentication codes: HMAC over any [`Hash`] and
+//! AES-128-CMAC (RFC 4493).
+//!
+//! Unlike [`SgxHmacHandle`] and [`SgxCmacHandle`], these states are plain
+//! values: they can be cloned to fork a MAC over a common prefix, and fed a
+//! large sealed file chunk by chunk as it is read. Keys and states are
+//! zeroized on drop, and `verify` compares tags in constant time.
+//!
+//! [`Hash`]: crate::hash::Hash
+//! [`SgxHmacHandle`]: crate::SgxHmacHandle
+//! [`SgxCmacHandle`]: crate::SgxCmacHandle
+
+use crate::aead::aes128_block;
+use crate::util::{ct_eq, zeroize};
+use sgx_types::*;
+
+pub use crate::hmac::{Hmac, HmacSha256, HmacSha384, HmacSha512};
+
+pub const CMAC_KEY_SIZE: usize = 16;
+pub const CMAC_TAG_SIZE: usize = 16;
+
+/// Doubles in GF(2^128), as CMAC derives its subkeys.
+fn dbl(block: &[u8; 16]) -> [u8; 16] {
+    let x = u128::from_be_bytes(*block);
+    let carry = 0_u128.wrapping_sub(x >> 127);
+    ((x << 1) ^ (carry & 0x87)).to_be_bytes()
+}
+
+/// An AES-128-CMAC state, fed incrementally.
+#[derive(Clone)]
+pub struct Aes128Cmac {
+    key: [u8; 16],
+    k1: [u8; 16],
+    k2: [u8; 16],
+    state: [u8; 16],
+    /// The last block, encrypted only once more data follows, since the
+    /// final block is masked with a subkey.
+    buf: [u8; 16],
+    buf_len: usize,
+}
+
+impl Aes128Cmac {
+    pub fn new(key: &[u8; CMAC_KEY_SIZE]) -> SgxResult<Aes128Cmac> {
+        let mut l = aes128_block(key, &[0; 16])?;
+        let k1 = dbl(&l);
+        let k2 = dbl(&k1);
+        zeroize(&mut l);
+        Ok(Aes128Cmac { key: *key, k1, k2, state: [0; 16], buf: [0; 16], buf_len: 0 })
+    }
+
+    fn absorb(&mut self, block: &[u8; 16]) -> SgxError {
+        for (s, b) in self.state.iter_mut().zip(block.iter()) {
+            *s ^= b;
+        }
+        self.state = aes128_block(&self.key, &self.state)?;
+        Ok(())
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) -> SgxError {
+        while !data.is_empty() {
+            if self.buf_len == 16 {
+                let block = self.buf;
+                self.absorb(&block)?;
+                self.buf_len = 0;
+            }
+            let take = core::cmp::min(16 - self.buf_len, data.len());
+            self.buf[self.buf_len..self.buf_len + take].copy_from_slice(&data[..take]);
+            self.buf_len += take;
+            data = &data[take..];
+        }
+        Ok(())
+    }
+
+    pub fn finalize(mut self) -> SgxResult<[u8; CMAC_TAG_SIZE]> {
+        let mut last = [0_u8; 16];
+        last[..self.buf_len].copy_from_slice(&self.buf[..self.buf_len]);
+        let subkey = if self.buf_len == 16 {
+            self.k1
+        } else {
+            last[self.buf_len] = 0x80;
+            self.k2
+        };
+        for (l, k) in last.iter_mut().zip(subkey.iter()) {
+            *l ^= k;
+        }
+        let result = self.absorb(&last);
+        zeroize(&mut last);
+        result.map(|()| self.state)
+    }
+
+    /// Checks the MAC against `tag` in constant time, failing with
+    /// `SGX_ERROR_MAC_MISMATCH`.
+    pub fn verify(self, tag: &[u8; CMAC_TAG_SIZE]) -> SgxError {
+        let mut mac = self.finalize()?;
+        let equal = ct_eq(&mac, tag);
+        zeroize(&mut mac);
+        if equal {
+            Ok(())
+        } else {
+            Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH)
+        }
+    }
+}
+
+impl Drop for Aes128Cmac {
+    fn drop(&mut self) {
+        zeroize(&mut self.key);
+        zeroize(&mut self.k1);
+        zeroize(&mut self.k2);
+        zeroize(&mut self.state);
+        zeroize(&mut self.buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+
+    const KEY: [u8; 16] = [
+        0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f,
+        0x3c,
+    ];
+
+    fn tag(s: &str) -> [u8; CMAC_TAG_SIZE] {
+        hex(s).try_into().unwrap()
+    }
+
+    #[test]
+    fn rfc4493() {
+        let message = hex("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\
+             30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710");
+        let cases = [
+            (0, "bb1d6929e95937287fa37d129b756746"),
+            (16, "070a16b46b4d4144f79bdd9dd04a287c"),
+            (40, "dfa66747de9ae63030ca32611497c827"),
+            (64, "51f0bebf7e3b9d92fc49741779363cfe"),
+        ];
+        for (len, mac) in cases {
+            for piece in [1, 5, 16, 64] {
+                let mut cmac = Aes128Cmac::new(&KEY).unwrap();
+                for part in message[..len].chunks(piece) {
+                    cmac.update(part).unwrap();
+                }
+                cmac.clone().verify(&tag(mac)).unwrap();
+                assert_eq!(cmac.finalize().unwrap(), tag(mac), "{} in pieces of {}", len, piece);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_tags() {
+        let mut cmac = Aes128Cmac::new(&KEY).unwrap();
+        cmac.update(&hex("6bc1bee22e409f96e93d7e117393172a")).unwrap();
+        let mut mac = tag("070a16b46b4d4144f79bdd9dd04a287c");
+        for i in [0, 15] {
+            mac[i] ^= 0x80;
+            assert_eq!(cmac.clone().verify(&mac), Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH));
+            mac[i] ^= 0x80;
+        }
+        // The tag of a message depends on where it ends.
+        let mut longer = cmac.clone();
+        longer.update(&[0]).unwrap();
+        assert_eq!(longer.verify(&mac), Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH));
+        cmac.verify(&mac).unwrap();
+    }
+}
@@ -0,0 +1,818 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! ML-DSA-65 (FIPS 204), the lattice signatures which stay unforgeable
+//! against a quantum computer.
+//!
+//! Keys are generated inside the enclave, from a 32-byte seed which is all
+//! that needs sealing, or exported in the 4032-byte encoding of FIPS 204,
+//! which [`MlDsa65PrivateKey::from_bytes`] checks against itself when it's
+//! unsealed. Signatures are hedged: the enclave's random numbers go into
+//! every signature along with the key, so neither a weak generator nor
+//! faults during signing repeat the secret `y` of a signature.
+//!
+//! The arithmetic is implemented in Rust, in time which doesn't depend on
+//! secrets but for the rejection sampling FIPS 204 specifies, which leaks
+//! the number of candidates drawn and nothing of the one kept. The matrix
+//! `Â` is sampled as it's used rather than held, to spare the 30 KiB it would
+//! take on the enclave's stack.
+
+use crate::hash::{Shake128, Shake256};
+use crate::secret::{SecretBytes, Zeroize};
+use crate::util::{ct_eq, read_rand, zeroize};
+use core::fmt;
+use sgx_types::*;
+
+const N: usize = 256;
+const Q: u32 = 8_380_417;
+const K: usize = 6;
+const L: usize = 5;
+const D: usize = 13;
+const ETA: u32 = 4;
+const TAU: usize = 49;
+const BETA: u32 = 196;
+const GAMMA1: u32 = 1 << 19;
+const GAMMA2: u32 = (Q - 1) / 32;
+const OMEGA: usize = 55;
+/// The length of the commitment hash `c̃`, `λ / 4` bytes.
+const C_TILDE_SIZE: usize = 48;
+
+/// The offsets of `tr`, `s1`, `s2` and `t0` in an encoded private key.
+const TR_OFFSET: usize = 64;
+const S1_OFFSET: usize = 128;
+const S2_OFFSET: usize = S1_OFFSET + 128 * L;
+const T0_OFFSET: usize = S2_OFFSET + 128 * K;
+/// The offset of the hints in a signature.
+const HINTS_OFFSET: usize = C_TILDE_SIZE + 640 * L;
+
+pub const ML_DSA_65_PUBLIC_KEY_SIZE: usize = 32 + 320 * K;
+pub const ML_DSA_65_PRIVATE_KEY_SIZE: usize = T0_OFFSET + 416 * K;
+pub const ML_DSA_65_SIGNATURE_SIZE: usize = HINTS_OFFSET + OMEGA + K;
+/// The length of the seed `ξ` a private key is derived from.
+pub const ML_DSA_SEED_SIZE: usize = 32;
+/// The length of the longest context string.
+pub const ML_DSA_MAX_CONTEXT_SIZE: usize = 255;
+
+/// A polynomial of `Z_q[X]/(X^256 + 1)`, or an NTT, with coefficients
+/// below `q`.
+type Poly = [u32; N];
+type Hints = [[bool; N]; K];
+
+/// Returns `n mod q` for `n < 2^48`, by a multiplication which, unlike a
+/// division, takes the same time for every `n`.
+fn reduce(n: u64) -> u32 {
+    let quotient = ((n as u128 * 281_750_089_695_397) >> 71) as u64;
+    (n - quotient * Q as u64) as u32
+}
+
+const fn bit_rev8(i: usize) -> usize {
+    let mut r = 0;
+    let mut b = 0;
+    while b < 8 {
+        r |= ((i >> b) & 1) << (7 - b);
+        b += 1;
+    }
+    r
+}
+
+const fn pow1753(e: usize) -> u32 {
+    let mut r = 1_u64;
+    let mut i = 0;
+    while i < e {
+        r = r * 1753 % Q as u64;
+        i += 1;
+    }
+    r as u32
+}
+
+/// The powers `1753^BitRev8(i)` of the NTT.
+const ZETAS: [u32; N] = {
+    let mut z = [0_u32; N];
+    let mut i = 0;
+    while i < N {
+        z[i] = pow1753(bit_rev8(i));
+        i += 1;
+    }
+    z
+};
+
+fn add(a: &Poly, b: &Poly) -> Poly {
+    let mut c = [0_u32; N];
+    for i in 0..N {
+        c[i] = reduce((a[i] + b[i]) as u64);
+    }
+    c
+}
+
+fn sub(a: &Poly, b: &Poly) -> Poly {
+    let mut c = [0_u32; N];
+    for i in 0..N {
+        c[i] = reduce((a[i] + Q - b[i]) as u64);
+    }
+    c
+}
+
+/// Algorithm 41 of FIPS 204.
+fn ntt(w: &mut Poly) {
+    let mut m = 0;
+    let mut len = 128;
+    while len >= 1 {
+        for start in (0..N).step_by(2 * len) {
+            m += 1;
+            let zeta = ZETAS[m] as u64;
+            for j in start..start + len {
+                let t = reduce(zeta * w[j + len] as u64);
+                w[j + len] = reduce((w[j] + Q - t) as u64);
+                w[j] = reduce((w[j] + t) as u64);
+            }
+        }
+        len /= 2;
+    }
+}
+
+/// Algorithm 42 of FIPS 204.
+fn inverse_ntt(w: &mut Poly) {
+    let mut m = N;
+    let mut len = 1;
+    while len < N {
+        for start in (0..N).step_by(2 * len) {
+            m -= 1;
+            let zeta = (Q - ZETAS[m]) as u64;
+            for j in start..start + len {
+                let t = w[j];
+                w[j] = reduce((t + w[j + len]) as u64);
+                w[j + len] = reduce(zeta * (t + Q - w[j + len]) as u64);
+            }
+        }
+        len *= 2;
+    }
+    for c in w.iter_mut() {
+        // 8347681 is 256^-1 mod q.
+        *c = reduce(*c as u64 * 8_347_681);
+    }
+}
+
+/// The product of two NTTs, coefficient by coefficient.
+fn multiply_ntts(a: &Poly, b: &Poly) -> Poly {
+    let mut c = [0_u32; N];
+    for i in 0..N {
+        c[i] = reduce(a[i] as u64 * b[i] as u64);
+    }
+    c
+}
+
+/// Algorithm 30 of FIPS 204, the NTT `Â[r][s]` drawn uniformly from SHAKE128
+/// of `rho` and the indices `s` and `r`.
+fn rej_ntt_poly(rho: &[u8; 32], s: u8, r: u8) -> Poly {
+    let mut xof = Shake128::new();
+    xof.update(rho);
+    xof.update(&[s, r]);
+    let mut reader = xof.finalize_xof();
+    let mut a = [0_u32; N];
+    let mut n = 0;
+    let mut bytes = [0_u8; 3];
+    while n < N {
+        reader.read(&mut bytes);
+        let z = bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32 & 0x7f) << 16;
+        if z < Q {
+            a[n] = z;
+            n += 1;
+        }
+    }
+    a
+}
+
+/// Returns the product `Â ∘ v` of the matrix of `rho` and the NTTs `v`.
+fn multiply_matrix(rho: &[u8; 32], v: &[Poly; L]) -> [Poly; K] {
+    let mut w = [[0_u32; N]; K];
+    for (r, w) in w.iter_mut().enumerate() {
+        for (s, v) in v.iter().enumerate() {
+            *w = add(w, &multiply_ntts(&rej_ntt_poly(rho, s as u8, r as u8), v));
+        }
+    }
+    w
+}
+
+/// Algorithm 31 of FIPS 204 for `η = 4`, the polynomial of coefficients
+/// between `-η` and `η` drawn from SHAKE256 of `rho` and the index `r`.
+fn rej_bounded_poly(rho: &[u8; 64], r: u16) -> Poly {
+    let mut xof = Shake256::new();
+    xof.update(rho);
+    xof.update(&r.to_le_bytes());
+    let mut reader = xof.finalize_xof();
+    let mut a = [0_u32; N];
+    let mut n = 0;
+    let mut byte = [0_u8; 1];
+    while n < N {
+        reader.read(&mut byte);
+        for b in [byte[0] & 0x0f, byte[0] >> 4] {
+            if b < 9 && n < N {
+                a[n] = reduce((Q + ETA - b as u32) as u64);
+                n += 1;
+            }
+        }
+    }
+    zeroize(&mut byte);
+    a
+}
+
+/// Algorithm 34 of FIPS 204, the mask `y` of the attempt `kappa / L`.
+fn expand_mask(rho: &[u8; 64], kappa: u16) -> [Poly; L] {
+    let mut y = [[0_u32; N]; L];
+    let mut bytes = [0_u8; 640];
+    for (r, y) in y.iter_mut().enumerate() {
+        let mut xof = Shake256::new();
+        xof.update(rho);
+        xof.update(&kappa.wrapping_add(r as u16).to_le_bytes());
+        xof.finalize_xof().read(&mut bytes);
+        *y = unpack_centered(&bytes, GAMMA1, 20);
+    }
+    zeroize(&mut bytes);
+    y
+}
+
+/// Algorithm 29 of FIPS 204, the challenge of `τ` coefficients `±1` drawn
+/// from SHAKE256 of the commitment hash.
+fn sample_in_ball(c_tilde: &[u8]) -> Poly {
+    let mut xof = Shake256::new();
+    xof.update(c_tilde);
+    let mut reader = xof.finalize_xof();
+    let mut signs = [0_u8; 8];
+    reader.read(&mut signs);
+    let signs = u64::from_le_bytes(signs);
+    let mut c = [0_u32; N];
+    let mut j = [0_u8; 1];
+    for i in N - TAU..N {
+        loop {
+            reader.read(&mut j);
+            if j[0] as usize <= i {
+                break;
+            }
+        }
+        let j = j[0] as usize;
+        c[i] = c[j];
+        c[j] = match (signs >> (i + TAU - N)) & 1 {
+            0 => 1,
+            _ => Q - 1,
+        };
+    }
+    c
+}
+
+/// Algorithm 35 of FIPS 204, splitting `r` into `r1 2^d + r0` with `r0`
+/// modulo `q` between `-2^(d-1)` and `2^(d-1)`.
+fn power2round(r: u32) -> (u32, u32) {
+    let low = (r & ((1 << D) - 1)) as i32;
+    let r0 = low - (((1 << (D - 1)) - low) >> 31 & (1 << D));
+    (((r as i32 - r0) >> D) as u32, (r0 + Q as i32) as u32 % Q)
+}
+
+/// Algorithm 36 of FIPS 204 for `γ2 = (q - 1) / 32`, splitting `r` into its
+/// high bits `r1` and its centered low bits `r0` without branching.
+fn decompose(r: u32) -> (u32, i32) {
+    let r1 = (((r + 127) >> 7) * 1025 + (1 << 21)) >> 22 & 15;
+    let r0 = r as i32 - (r1 * 2 * GAMMA2) as i32;
+    (r1, r0 - (((Q as i32 - 1) / 2 - r0) >> 31 & Q as i32))
+}
+
+/// Algorithm 40 of FIPS 204.
+fn use_hint(hint: bool, r: u32) -> u32 {
+    let (r1, r0) = decompose(r);
+    match (hint, r0 > 0) {
+        (false, _) => r1,
+        (true, true) => (r1 + 1) & 15,
+        (true, false) => (r1 + 15) & 15,
+    }
+}
+
+/// Returns whether a coefficient of `f` is at least `bound` away from zero.
+/// Stopping at the first one only tells which candidate was rejected.
+fn exceeds(f: &Poly, bound: u32) -> bool {
+    f.iter().any(|&c| c >= bound && c <= Q - bound)
+}
+
+/// Algorithm 16 of FIPS 204, into the `32 bits` bytes of `out`.
+fn bit_pack(f: &Poly, bits: usize, out: &mut [u8]) {
+    let mut acc = 0_u64;
+    let mut filled = 0;
+    let mut pos = 0;
+    for &c in f.iter() {
+        acc |= (c as u64) << filled;
+        filled += bits;
+        while filled >= 8 {
+            out[pos] = acc as u8;
+            pos += 1;
+            acc >>= 8;
+            filled -= 8;
+        }
+    }
+}
+
+/// Algorithm 18 of FIPS 204, from the `32 bits` bytes of `bytes`.
+fn bit_unpack(bytes: &[u8], bits: usize) -> Poly {
+    let mut f = [0_u32; N];
+    let mut acc = 0_u64;
+    let mut filled = 0;
+    let mut bytes = bytes.iter();
+    for c in f.iter_mut() {
+        while filled < bits {
+            acc |= (*bytes.next().unwrap() as u64) << filled;
+            filled += 8;
+        }
+        *c = (acc & ((1 << bits) - 1)) as u32;
+        acc >>= bits;
+        filled -= bits;
+    }
+    f
+}
+
+/// Algorithm 17 of FIPS 204, packing the coefficients `b - c`.
+fn pack_centered(f: &Poly, b: u32, bits: usize, out: &mut [u8]) {
+    let mut v = [0_u32; N];
+    for (v, &c) in v.iter_mut().zip(f.iter()) {
+        *v = reduce((b + Q - c) as u64);
+    }
+    bit_pack(&v, bits, out);
+    v.zeroize();
+}
+
+/// Algorithm 19 of FIPS 204, the inverse of [`pack_centered`].
+fn unpack_centered(bytes: &[u8], b: u32, bits: usize) -> Poly {
+    let mut f = bit_unpack(bytes, bits);
+    for c in f.iter_mut() {
+        *c = reduce((b + Q - *c) as u64);
+    }
+    f
+}
+
+/// Algorithm 20 of FIPS 204, into the `ω + k` bytes of `out`.
+fn pack_hints(hints: &Hints, out: &mut [u8]) {
+    let mut index = 0;
+    for (i, hints) in hints.iter().enumerate() {
+        for (j, _) in hints.iter().enumerate().filter(|(_, &hint)| hint) {
+            out[index] = j as u8;
+            index += 1;
+        }
+        out[OMEGA + i] = index as u8;
+    }
+}
+
+/// Algorithm 21 of FIPS 204, returning `None` unless the hints are encoded
+/// the one way FIPS 204 allows, which keeps signatures from being malleable.
+fn unpack_hints(bytes: &[u8]) -> Option<Hints> {
+    let mut hints = [[false; N]; K];
+    let mut index = 0;
+    for (i, hints) in hints.iter_mut().enumerate() {
+        let end = bytes[OMEGA + i] as usize;
+        if end < index || end > OMEGA {
+            return None;
+        }
+        let first = index;
+        while index < end {
+            if index > first && bytes[index - 1] >= bytes[index] {
+                return None;
+            }
+            hints[bytes[index] as usize] = true;
+            index += 1;
+        }
+    }
+    if bytes[index..OMEGA].iter().any(|&b| b != 0) {
+        return None;
+    }
+    Some(hints)
+}
+
+/// `H`, SHAKE256 of the concatenation of `parts`, into `out`.
+fn h(parts: &[&[u8]], out: &mut [u8]) {
+    let mut xof = Shake256::new();
+    for part in parts {
+        xof.update(part);
+    }
+    xof.finalize_xof().read(out);
+}
+
+/// Returns `μ`, the hash of `tr` and of the message `M'` which ML-DSA signs
+/// for `message` in `context`.
+fn message_representative(tr: &[u8], context: &[u8], message: &[u8]) -> [u8; 64] {
+    let mut mu = [0_u8; 64];
+    h(&[tr, &[0, context.len() as u8], context, message], &mut mu);
+    mu
+}
+
+/// Returns the encoding of `w1`, 4 bits for each of its coefficients.
+fn encode_w1(w1: &[Poly; K]) -> [u8; 128 * K] {
+    let mut bytes = [0_u8; 128 * K];
+    for (w1, out) in w1.iter().zip(bytes.chunks_mut(128)) {
+        bit_pack(w1, 4, out);
+    }
+    bytes
+}
+
+/// The second half of Algorithm 6 of FIPS 204, encoding the private key of
+/// `rho`, `key`, `s1` and `s2` as Algorithm 24 does, along with its public
+/// key.
+fn encode_keys(
+    rho: &[u8; 32],
+    key: &[u8],
+    s1: &[Poly; L],
+    s2: &[Poly; K],
+) -> (SecretBytes<ML_DSA_65_PRIVATE_KEY_SIZE>, MlDsa65PublicKey) {
+    let mut s1_hat = *s1;
+    for poly in s1_hat.iter_mut() {
+        ntt(poly);
+    }
+    let mut t = multiply_matrix(rho, &s1_hat);
+    let mut bytes = SecretBytes::<ML_DSA_65_PRIVATE_KEY_SIZE>::zeroed();
+    let sk = bytes.expose_secret_mut();
+    let mut pk = [0_u8; ML_DSA_65_PUBLIC_KEY_SIZE];
+    pk[..32].copy_from_slice(rho);
+    let mut t1 = [0_u32; N];
+    let mut t0 = [0_u32; N];
+    for (i, (t, s2)) in t.iter_mut().zip(s2.iter()).enumerate() {
+        inverse_ntt(t);
+        *t = add(t, s2);
+        for (j, &c) in t.iter().enumerate() {
+            (t1[j], t0[j]) = power2round(c);
+        }
+        bit_pack(&t1, 10, &mut pk[32 + 320 * i..][..320]);
+        pack_centered(&t0, 1 << (D - 1), D, &mut sk[T0_OFFSET + 416 * i..][..416]);
+    }
+
+    sk[..32].copy_from_slice(rho);
+    sk[32..TR_OFFSET].copy_from_slice(key);
+    h(&[&pk], &mut sk[TR_OFFSET..S1_OFFSET]);
+    for (poly, out) in s1.iter().chain(s2.iter()).zip(sk[S1_OFFSET..T0_OFFSET].chunks_mut(128)) {
+        pack_centered(poly, ETA, 4, out);
+    }
+    s1_hat.zeroize();
+    t.zeroize();
+    t0.zeroize();
+    (bytes, MlDsa65PublicKey(pk))
+}
+
+/// An ML-DSA-65 private key.
+pub struct MlDsa65PrivateKey {
+    /// The key as FIPS 204 encodes it: `rho`, the signing seed `K`, the hash
+    /// `tr` of the public key, `s1`, `s2` and `t0`.
+    bytes: SecretBytes<ML_DSA_65_PRIVATE_KEY_SIZE>,
+    public: MlDsa65PublicKey,
+}
+
+impl MlDsa65PrivateKey {
+    /// Generates a key with the enclave's random number generator.
+    pub fn generate() -> SgxResult<MlDsa65PrivateKey> {
+        let mut seed = SecretBytes::<ML_DSA_SEED_SIZE>::zeroed();
+        read_rand(seed.expose_secret_mut())?;
+        Ok(MlDsa65PrivateKey::from_seed(seed.expose_secret()))
+    }
+
+    /// Derives the key of the seed `ξ`, as `ML-DSA.KeyGen_internal` does.
+    /// Sealing the seed rather than the key takes 32 bytes instead of 4032.
+    pub fn from_seed(seed: &[u8; ML_DSA_SEED_SIZE]) -> MlDsa65PrivateKey {
+        let mut expanded = [0_u8; 128];
+        h(&[seed, &[K as u8, L as u8]], &mut expanded);
+        let mut rho = [0_u8; 32];
+        rho.copy_from_slice(&expanded[..32]);
+        let mut rho_prime = [0_u8; 64];
+        rho_prime.copy_from_slice(&expanded[32..96]);
+        let mut s1 = [[0_u32; N]; L];
+        for (r, poly) in s1.iter_mut().enumerate() {
+            *poly = rej_bounded_poly(&rho_prime, r as u16);
+        }
+        let mut s2 = [[0_u32; N]; K];
+        for (r, poly) in s2.iter_mut().enumerate() {
+            *poly = rej_bounded_poly(&rho_prime, (L + r) as u16);
+        }
+        let (bytes, public) = encode_keys(&rho, &expanded[96..], &s1, &s2);
+        zeroize(&mut expanded);
+        zeroize(&mut rho_prime);
+        s1.zeroize();
+        s2.zeroize();
+        MlDsa65PrivateKey { bytes, public }
+    }
+
+    /// Creates a key from its encoding, failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` unless the coefficients of `s1` and `s2`
+    /// are in range and `t0` and `tr` are those they give.
+    pub fn from_bytes(bytes: &[u8; ML_DSA_65_PRIVATE_KEY_SIZE]) -> SgxResult<MlDsa65PrivateKey> {
+        let packed = &bytes[S1_OFFSET..T0_OFFSET];
+        if packed.iter().any(|&b| b & 0x0f > 2 * ETA as u8 || b >> 4 > 2 * ETA as u8) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut rho = [0_u8; 32];
+        rho.copy_from_slice(&bytes[..32]);
+        let (mut s1, mut s2, mut t0) = decode_vectors(bytes);
+        let (encoded, public) = encode_keys(&rho, &bytes[32..TR_OFFSET], &s1, &s2);
+        s1.zeroize();
+        s2.zeroize();
+        t0.zeroize();
+        if !ct_eq(encoded.expose_secret(), bytes) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(MlDsa65PrivateKey { bytes: encoded, public })
+    }
+
+    /// Returns the encoding of the key, e.g. to seal it.
+    pub fn to_bytes(&self) -> SecretBytes<ML_DSA_65_PRIVATE_KEY_SIZE> {
+        self.bytes.clone()
+    }
+
+    pub fn public_key(&self) -> MlDsa65PublicKey {
+        self.public.clone()
+    }
+
+    /// Signs `message` in `context`, a string of at most 255 bytes which
+    /// keeps signatures made for one purpose from passing for another, as
+    /// `ML-DSA.Sign` does.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if `context` is too long.
+    pub fn sign(&self, message: &[u8], context: &[u8]) -> SgxResult<MlDsa65Signature> {
+        let mut rnd = SecretBytes::<32>::zeroed();
+        read_rand(rnd.expose_secret_mut())?;
+        self.sign_internal(message, context, rnd.expose_secret())
+    }
+
+    /// Signs `message` in `context` without random numbers, as the
+    /// deterministic variant of FIPS 204 does, to check test vectors.
+    /// Signatures should otherwise be hedged with [`sign`].
+    ///
+    /// [`sign`]: MlDsa65PrivateKey::sign
+    pub fn sign_deterministic(
+        &self,
+        message: &[u8],
+        context: &[u8],
+    ) -> SgxResult<MlDsa65Signature> {
+        self.sign_internal(message, context, &[0; 32])
+    }
+
+    /// Algorithm 7 of FIPS 204, for the message `M'` of `message` in
+    /// `context`.
+    fn sign_internal(
+        &self,
+        message: &[u8],
+        context: &[u8],
+        rnd: &[u8; 32],
+    ) -> SgxResult<MlDsa65Signature> {
+        if context.len() > ML_DSA_MAX_CONTEXT_SIZE {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let sk = self.bytes.expose_secret();
+        let mut rho = [0_u8; 32];
+        rho.copy_from_slice(&sk[..32]);
+        let (mut s1, mut s2, mut t0) = decode_vectors(sk);
+        for poly in s1.iter_mut().chain(s2.iter_mut()).chain(t0.iter_mut()) {
+            ntt(poly);
+        }
+        let mu = message_representative(&sk[TR_OFFSET..S1_OFFSET], context, message);
+        let mut rho_prime = [0_u8; 64];
+        h(&[&sk[32..TR_OFFSET], rnd, &mu], &mut rho_prime);
+
+        // The mask `y` becomes the response `z`, the commitment `w` becomes
+        // `r = w - c s2`, and `product` holds the products of the challenge
+        // with `s2` and `t0`.
+        let mut y;
+        let mut w;
+        let mut product = [0_u32; N];
+        let mut hints = [[false; N]; K];
+        let mut kappa = 0_u16;
+        let signature = loop {
+            y = expand_mask(&rho_prime, kappa);
+            kappa = kappa.wrapping_add(L as u16);
+            for poly in y.iter_mut() {
+                ntt(poly);
+            }
+            w = multiply_matrix(&rho, &y);
+            let mut w1 = [[0_u32; N]; K];
+            for (w, w1) in w.iter_mut().zip(w1.iter_mut()) {
+                inverse_ntt(w);
+                for (&c, w1) in w.iter().zip(w1.iter_mut()) {
+                    *w1 = decompose(c).0;
+                }
+            }
+            let mut c_tilde = [0_u8; C_TILDE_SIZE];
+            h(&[&mu, &encode_w1(&w1)], &mut c_tilde);
+            let mut c = sample_in_ball(&c_tilde);
+            ntt(&mut c);
+
+            let mut rejected = false;
+            for (y, s1) in y.iter_mut().zip(s1.iter()) {
+                *y = add(y, &multiply_ntts(&c, s1));
+                inverse_ntt(y);
+                rejected |= exceeds(y, GAMMA1 - BETA);
+            }
+            if rejected {
+                continue;
+            }
+            let mut count = 0;
+            for i in 0..K {
+                product = multiply_ntts(&c, &s2[i]);
+                inverse_ntt(&mut product);
+                w[i] = sub(&w[i], &product);
+                if w[i].iter().any(|&c| decompose(c).1.unsigned_abs() >= GAMMA2 - BETA) {
+                    rejected = true;
+                    break;
+                }
+                product = multiply_ntts(&c, &t0[i]);
+                inverse_ntt(&mut product);
+                if exceeds(&product, GAMMA2) {
+                    rejected = true;
+                    break;
+                }
+                // MakeHint(-c t0, w - c s2 + c t0), Algorithm 39.
+                for j in 0..N {
+                    let high = decompose(w[i][j]).0;
+                    hints[i][j] = decompose(reduce((w[i][j] + product[j]) as u64)).0 != high;
+                    count += hints[i][j] as usize;
+                }
+            }
+            if rejected || count > OMEGA {
+                continue;
+            }
+
+            let mut signature = [0_u8; ML_DSA_65_SIGNATURE_SIZE];
+            signature[..C_TILDE_SIZE].copy_from_slice(&c_tilde);
+            let packed = signature[C_TILDE_SIZE..HINTS_OFFSET].chunks_mut(640);
+            for (z, out) in y.iter().zip(packed) {
+                pack_centered(z, GAMMA1, 20, out);
+            }
+            pack_hints(&hints, &mut signature[HINTS_OFFSET..]);
+            break MlDsa65Signature(signature);
+        };
+
+        y.zeroize();
+        w.zeroize();
+        product.zeroize();
+        s1.zeroize();
+        s2.zeroize();
+        t0.zeroize();
+        zeroize(&mut rho_prime);
+        Ok(signature)
+    }
+}
+
+/// Decodes `s1`, `s2` and `t0` from an encoded private key, as Algorithm 25
+/// of FIPS 204 does.
+fn decode_vectors(sk: &[u8]) -> ([Poly; L], [Poly; K], [Poly; K]) {
+    let mut s1 = [[0_u32; N]; L];
+    let mut s2 = [[0_u32; N]; K];
+    let mut t0 = [[0_u32; N]; K];
+    let packed = sk[S1_OFFSET..T0_OFFSET].chunks(128);
+    for (poly, bytes) in s1.iter_mut().chain(s2.iter_mut()).zip(packed) {
+        *poly = unpack_centered(bytes, ETA, 4);
+    }
+    for (poly, bytes) in t0.iter_mut().zip(sk[T0_OFFSET..].chunks(416)) {
+        *poly = unpack_centered(bytes, 1 << (D - 1), D);
+    }
+    (s1, s2, t0)
+}
+
+impl fmt::Debug for MlDsa65PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MlDsa65PrivateKey").field("public_key", &self.public).finish()
+    }
+}
+
+/// An ML-DSA-65 public key, `rho` and the high bits `t1`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MlDsa65PublicKey(pub [u8; ML_DSA_65_PUBLIC_KEY_SIZE]);
+
+impl MlDsa65PublicKey {
+    /// Verifies a signature of `message` in `context`, as Algorithm 8 of
+    /// FIPS 204 does.
+    pub fn verify(&self, message: &[u8], context: &[u8], signature: &MlDsa65Signature) -> bool {
+        if context.len() > ML_DSA_MAX_CONTEXT_SIZE {
+            return false;
+        }
+        let signature = &signature.0;
+        let c_tilde = &signature[..C_TILDE_SIZE];
+        let hints = match unpack_hints(&signature[HINTS_OFFSET..]) {
+            Some(hints) => hints,
+            None => return false,
+        };
+        let mut z = [[0_u32; N]; L];
+        let packed = signature[C_TILDE_SIZE..HINTS_OFFSET].chunks(640);
+        for (z, bytes) in z.iter_mut().zip(packed) {
+            *z = unpack_centered(bytes, GAMMA1, 20);
+            if exceeds(z, GAMMA1 - BETA) {
+                return false;
+            }
+            ntt(z);
+        }
+
+        let mut rho = [0_u8; 32];
+        rho.copy_from_slice(&self.0[..32]);
+        let mut tr = [0_u8; 64];
+        h(&[&self.0], &mut tr);
+        let mu = message_representative(&tr, context, message);
+        let mut c = sample_in_ball(c_tilde);
+        ntt(&mut c);
+
+        let mut w = multiply_matrix(&rho, &z);
+        let mut w1 = [[0_u32; N]; K];
+        for (i, (w, w1)) in w.iter_mut().zip(w1.iter_mut()).enumerate() {
+            let mut t1 = bit_unpack(&self.0[32 + 320 * i..][..320], 10);
+            for c in t1.iter_mut() {
+                *c <<= D;
+            }
+            ntt(&mut t1);
+            *w = sub(w, &multiply_ntts(&c, &t1));
+            inverse_ntt(w);
+            for ((&c, w1), &hint) in w.iter().zip(w1.iter_mut()).zip(hints[i].iter()) {
+                *w1 = use_hint(hint, c);
+            }
+        }
+        let mut again = [0_u8; C_TILDE_SIZE];
+        h(&[&mu, &encode_w1(&w1)], &mut again);
+        again[..] == *c_tilde
+    }
+}
+
+impl fmt::Debug for MlDsa65PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MlDsa65PublicKey({:02x?}..)", &self.0[..8])
+    }
+}
+
+/// An ML-DSA-65 signature, the commitment hash `c̃`, the response `z` and
+/// the hints.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MlDsa65Signature(pub [u8; ML_DSA_65_SIGNATURE_SIZE]);
+
+impl fmt::Debug for MlDsa65Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MlDsa65Signature({:02x?}..)", &self.0[..8])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{Hash, Sha3_256};
+    use crate::util::hex;
+
+    fn sha3_256(data: &[u8]) -> [u8; 32] {
+        let mut digest = [0_u8; 32];
+        Sha3_256::digest_into(data, &mut digest);
+        digest
+    }
+
+    fn key() -> MlDsa65PrivateKey {
+        let mut seed = [0_u8; ML_DSA_SEED_SIZE];
+        seed.iter_mut().enumerate().for_each(|(i, byte)| *byte = i as u8);
+        MlDsa65PrivateKey::from_seed(&seed)
+    }
+
+    #[test]
+    fn known_answer() {
+        // The key of the seed 00 01 .. 1f and its deterministic signature,
+        // pinned by their SHA3-256 digests; OpenSSL derives the same public
+        // key and accepts the signature.
+        let key = key();
+        let public = key.public_key();
+        assert_eq!(
+            sha3_256(&public.0)[..],
+            hex("1800725067e388d837d911fe4f66101cc1961b1bb755030dc574272cfb00013f")[..]
+        );
+        let signature = key.sign_deterministic(b"message", b"context").unwrap();
+        assert_eq!(
+            sha3_256(&signature.0)[..],
+            hex("6d110387073718ebe3abfa4830bcd277d130a38e9a331aea1fe6964799e66cff")[..]
+        );
+        assert!(public.verify(b"message", b"context", &signature));
+
+        let hedged = key.sign(b"message", b"context").unwrap();
+        assert!(public.verify(b"message", b"context", &hedged));
+        let again = MlDsa65PrivateKey::from_bytes(key.to_bytes().expose_secret()).unwrap();
+        assert!(again.public_key() == public);
+    }
+
+    #[test]
+    fn rejects_forgeries() {
+        let key = key();
+        let public = key.public_key();
+        let signature = key.sign_deterministic(b"message", b"context").unwrap();
+        assert!(!public.verify(b"massage", b"context", &signature));
+        assert!(!public.verify(b"message", b"", &signature));
+        for i in [0, 100, ML_DSA_65_SIGNATURE_SIZE - 1] {
+            let mut bad = signature.clone();
+            bad.0[i] ^= 1;
+            assert!(!public.verify(b"message", b"context", &bad));
+        }
+        assert!(key.sign(b"message", &[0; ML_DSA_MAX_CONTEXT_SIZE + 1]).is_err());
+        assert!(!public.verify(b"message", &[0; ML_DSA_MAX_CONTEXT_SIZE + 1], &signature));
+    }
+}
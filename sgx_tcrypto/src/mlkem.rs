@@ -0,0 +1,822 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! ML-KEM-768 (FIPS 203), and the hybrid of X25519 and ML-KEM-768 with which
+//! TLS 1.3 agrees keys as the `X25519MLKEM768` group.
+//!
+//! A key encapsulated with ML-KEM stays secret against a quantum computer
+//! built after it was recorded, as long as the module lattice problems it
+//! rests on stay hard; the hybrid stays secret as long as either X25519 or
+//! ML-KEM isn't broken. Sealed data and RA-TLS channels should therefore use
+//! the hybrid.
+//!
+//! The arithmetic and the sampling are implemented in Rust, in time which
+//! doesn't depend on secrets: reductions modulo `q` multiply by a constant
+//! rather than divide, and decapsulation recomputes the ciphertext and
+//! selects the shared secret without branching. Encapsulation keys taken
+//! from outside are checked as FIPS 203 requires, and so are the hashes in
+//! decapsulation keys.
+
+use crate::hash::{Hash, Sha3_256, Sha3_512, Shake128, Shake256};
+use crate::secret::{Secret, SecretBytes, Zeroize};
+use crate::util::{ct_eq, read_rand, zeroize};
+use crate::x25519::{X25519PrivateKey, X25519PublicKey, X25519_KEY_SIZE};
+use core::fmt;
+use sgx_types::*;
+
+const N: usize = 256;
+const Q: u32 = 3329;
+const K: usize = 3;
+const DU: usize = 10;
+const DV: usize = 4;
+
+/// The length of an encoded vector of `K` polynomials.
+const VECTOR_SIZE: usize = 384 * K;
+/// The length of the compressed vector `u` of a ciphertext.
+const U_SIZE: usize = 32 * DU * K;
+
+pub const ML_KEM_768_ENCAPSULATION_KEY_SIZE: usize = VECTOR_SIZE + 32;
+pub const ML_KEM_768_DECAPSULATION_KEY_SIZE: usize = 2 * VECTOR_SIZE + 96;
+pub const ML_KEM_768_CIPHERTEXT_SIZE: usize = U_SIZE + 32 * DV;
+pub const ML_KEM_SHARED_SECRET_SIZE: usize = 32;
+/// The length of the seed `d || z` a decapsulation key is derived from.
+pub const ML_KEM_SEED_SIZE: usize = 64;
+
+pub const X25519_ML_KEM_768_PUBLIC_KEY_SIZE: usize =
+    ML_KEM_768_ENCAPSULATION_KEY_SIZE + X25519_KEY_SIZE;
+pub const X25519_ML_KEM_768_CIPHERTEXT_SIZE: usize = ML_KEM_768_CIPHERTEXT_SIZE + X25519_KEY_SIZE;
+pub const X25519_ML_KEM_768_SHARED_SECRET_SIZE: usize =
+    ML_KEM_SHARED_SECRET_SIZE + X25519_KEY_SIZE;
+
+/// A polynomial of `Z_q[X]/(X^256 + 1)`, or an NTT, with coefficients
+/// below `q`.
+type Poly = [u16; N];
+type Vector = [Poly; K];
+
+/// Returns `n / q` for `n < 2^24`, by a multiplication which, unlike a
+/// division, takes the same time for every `n`.
+fn div_q(n: u32) -> u32 {
+    ((n as u64 * 20_642_679) >> 36) as u32
+}
+
+/// Returns `n mod q` for `n < 2^24`.
+fn reduce(n: u32) -> u16 {
+    (n - div_q(n) * Q) as u16
+}
+
+const fn bit_rev7(i: usize) -> usize {
+    let mut r = 0;
+    let mut b = 0;
+    while b < 7 {
+        r |= ((i >> b) & 1) << (6 - b);
+        b += 1;
+    }
+    r
+}
+
+const fn pow17(e: usize) -> u16 {
+    let mut r = 1_u32;
+    let mut i = 0;
+    while i < e {
+        r = r * 17 % Q;
+        i += 1;
+    }
+    r as u16
+}
+
+/// The powers `17^BitRev7(i)` of the NTT.
+const ZETAS: [u16; 128] = {
+    let mut z = [0_u16; 128];
+    let mut i = 0;
+    while i < 128 {
+        z[i] = pow17(bit_rev7(i));
+        i += 1;
+    }
+    z
+};
+
+/// The powers `17^(2 BitRev7(i) + 1)` of the products of NTTs.
+const GAMMAS: [u16; 128] = {
+    let mut g = [0_u16; 128];
+    let mut i = 0;
+    while i < 128 {
+        g[i] = pow17(2 * bit_rev7(i) + 1);
+        i += 1;
+    }
+    g
+};
+
+fn add(a: &Poly, b: &Poly) -> Poly {
+    let mut c = [0_u16; N];
+    for i in 0..N {
+        c[i] = reduce(a[i] as u32 + b[i] as u32);
+    }
+    c
+}
+
+fn sub(a: &Poly, b: &Poly) -> Poly {
+    let mut c = [0_u16; N];
+    for i in 0..N {
+        c[i] = reduce(a[i] as u32 + Q - b[i] as u32);
+    }
+    c
+}
+
+/// Algorithm 9 of FIPS 203.
+fn ntt(f: &mut Poly) {
+    let mut i = 1;
+    let mut len = 128;
+    while len >= 2 {
+        for start in (0..N).step_by(2 * len) {
+            let zeta = ZETAS[i] as u32;
+            i += 1;
+            for j in start..start + len {
+                let t = reduce(zeta * f[j + len] as u32) as u32;
+                f[j + len] = reduce(f[j] as u32 + Q - t);
+                f[j] = reduce(f[j] as u32 + t);
+            }
+        }
+        len /= 2;
+    }
+}
+
+/// Algorithm 10 of FIPS 203.
+fn inverse_ntt(f: &mut Poly) {
+    let mut i = 127;
+    let mut len = 2;
+    while len <= 128 {
+        for start in (0..N).step_by(2 * len) {
+            let zeta = ZETAS[i] as u32;
+            i -= 1;
+            for j in start..start + len {
+                let t = f[j] as u32;
+                f[j] = reduce(t + f[j + len] as u32);
+                f[j + len] = reduce(zeta * (f[j + len] as u32 + Q - t));
+            }
+        }
+        len *= 2;
+    }
+    for c in f.iter_mut() {
+        // 3303 is 128^-1 mod q.
+        *c = reduce(*c as u32 * 3303);
+    }
+}
+
+/// Algorithms 11 and 12 of FIPS 203, the product of two NTTs.
+fn multiply_ntts(a: &Poly, b: &Poly) -> Poly {
+    let mut c = [0_u16; N];
+    for i in 0..N / 2 {
+        let (a0, a1) = (a[2 * i] as u32, a[2 * i + 1] as u32);
+        let (b0, b1) = (b[2 * i] as u32, b[2 * i + 1] as u32);
+        let a1b1 = reduce(a1 * b1) as u32;
+        c[2 * i] = reduce(reduce(a0 * b0) as u32 + reduce(a1b1 * GAMMAS[i] as u32) as u32);
+        c[2 * i + 1] = reduce(reduce(a0 * b1) as u32 + reduce(a1 * b0) as u32);
+    }
+    c
+}
+
+/// Returns the sum of the products of the NTTs of `a` and `b`.
+fn dot(a: &Vector, b: &Vector) -> Poly {
+    let mut c = [0_u16; N];
+    for (a, b) in a.iter().zip(b.iter()) {
+        c = add(&c, &multiply_ntts(a, b));
+    }
+    c
+}
+
+/// Algorithm 7 of FIPS 203, the NTT drawn uniformly from SHAKE128 of `rho`
+/// and the indices `j` and `i`.
+fn sample_ntt(rho: &[u8; 32], j: u8, i: u8) -> Poly {
+    let mut xof = Shake128::new();
+    xof.update(rho);
+    xof.update(&[j, i]);
+    let mut reader = xof.finalize_xof();
+    let mut a = [0_u16; N];
+    let mut n = 0;
+    let mut bytes = [0_u8; 3];
+    // The matrix is public, so rejection sampling may take variable time.
+    while n < N {
+        reader.read(&mut bytes);
+        let d1 = bytes[0] as u16 | ((bytes[1] as u16 & 0x0f) << 8);
+        let d2 = (bytes[1] as u16 >> 4) | ((bytes[2] as u16) << 4);
+        for d in [d1, d2] {
+            if (d as u32) < Q && n < N {
+                a[n] = d;
+                n += 1;
+            }
+        }
+    }
+    a
+}
+
+/// Returns the matrix `Â` of `rho`, or its transpose.
+fn matrix(rho: &[u8; 32], transpose: bool) -> [Vector; K] {
+    let mut a = [[[0_u16; N]; K]; K];
+    for (i, row) in a.iter_mut().enumerate() {
+        for (j, entry) in row.iter_mut().enumerate() {
+            *entry = match transpose {
+                false => sample_ntt(rho, j as u8, i as u8),
+                true => sample_ntt(rho, i as u8, j as u8),
+            };
+        }
+    }
+    a
+}
+
+/// Algorithm 8 of FIPS 203 for `η = 2`, on `PRF(s, b)`.
+fn sample_cbd(s: &[u8; 32], b: u8) -> Poly {
+    let mut bytes = [0_u8; 128];
+    let mut prf = Shake256::new();
+    prf.update(s);
+    prf.update(&[b]);
+    prf.finalize_xof().read(&mut bytes);
+    let mut f = [0_u16; N];
+    for (i, c) in f.iter_mut().enumerate() {
+        let bits = (bytes[i / 2] >> (4 * (i % 2))) as u32;
+        let x = (bits & 1) + ((bits >> 1) & 1);
+        let y = ((bits >> 2) & 1) + ((bits >> 3) & 1);
+        *c = reduce(x + Q - y);
+    }
+    zeroize(&mut bytes);
+    f
+}
+
+/// Samples a vector from `PRF(s, b)`, `PRF(s, b + 1)` and `PRF(s, b + 2)`.
+fn sample_vector(s: &[u8; 32], b: u8) -> Vector {
+    let mut v = [[0_u16; N]; K];
+    for (i, poly) in v.iter_mut().enumerate() {
+        *poly = sample_cbd(s, b + i as u8);
+    }
+    v
+}
+
+/// Algorithm 5 of FIPS 203, into the `32 d` bytes of `out`.
+fn byte_encode(f: &Poly, d: usize, out: &mut [u8]) {
+    let mut acc = 0_u32;
+    let mut bits = 0;
+    let mut pos = 0;
+    for &c in f.iter() {
+        acc |= (c as u32) << bits;
+        bits += d;
+        while bits >= 8 {
+            out[pos] = acc as u8;
+            pos += 1;
+            acc >>= 8;
+            bits -= 8;
+        }
+    }
+}
+
+/// Algorithm 6 of FIPS 203, from the `32 d` bytes of `bytes`. The 12-bit
+/// coefficients are reduced modulo `q`.
+fn byte_decode(bytes: &[u8], d: usize) -> Poly {
+    let mut f = [0_u16; N];
+    let mut acc = 0_u32;
+    let mut bits = 0;
+    let mut bytes = bytes.iter();
+    for c in f.iter_mut() {
+        while bits < d {
+            acc |= (*bytes.next().unwrap() as u32) << bits;
+            bits += 8;
+        }
+        *c = (acc & ((1 << d) - 1)) as u16;
+        acc >>= d;
+        bits -= d;
+        if d == 12 {
+            *c = reduce(*c as u32);
+        }
+    }
+    f
+}
+
+fn encode_vector(v: &Vector, out: &mut [u8]) {
+    for (poly, out) in v.iter().zip(out.chunks_mut(384)) {
+        byte_encode(poly, 12, out);
+    }
+}
+
+fn decode_vector(bytes: &[u8]) -> Vector {
+    let mut v = [[0_u16; N]; K];
+    for (poly, bytes) in v.iter_mut().zip(bytes.chunks(384)) {
+        *poly = byte_decode(bytes, 12);
+    }
+    v
+}
+
+/// `Compress_d` of every coefficient, rounding `2^d x / q`.
+fn compress(f: &mut Poly, d: usize) {
+    for c in f.iter_mut() {
+        *c = (div_q(((*c as u32) << d) + Q / 2) & ((1 << d) - 1)) as u16;
+    }
+}
+
+/// `Decompress_d` of every coefficient, rounding `q y / 2^d`.
+fn decompress(f: &mut Poly, d: usize) {
+    for c in f.iter_mut() {
+        *c = ((*c as u32 * Q + (1 << (d - 1))) >> d) as u16;
+    }
+}
+
+/// `G`, SHA3-512, in halves.
+fn g(parts: &[&[u8]]) -> ([u8; 32], [u8; 32]) {
+    let mut hasher = Sha3_512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut out = [0_u8; 64];
+    hasher.finalize_into(&mut out);
+    let mut a = [0_u8; 32];
+    let mut b = [0_u8; 32];
+    a.copy_from_slice(&out[..32]);
+    b.copy_from_slice(&out[32..]);
+    zeroize(&mut out);
+    (a, b)
+}
+
+/// `H`, SHA3-256.
+fn h(data: &[u8]) -> [u8; 32] {
+    let mut out = [0_u8; 32];
+    Sha3_256::digest_into(data, &mut out);
+    out
+}
+
+/// Algorithm 13 of FIPS 203, writing the encryption key into `ek` and the
+/// decryption key into `dk`.
+fn pke_key_gen(d: &[u8; 32], ek: &mut [u8], dk: &mut [u8]) {
+    let (rho, mut sigma) = g(&[d, &[K as u8]]);
+    let a = matrix(&rho, false);
+    let mut s = sample_vector(&sigma, 0);
+    let mut e = sample_vector(&sigma, K as u8);
+    zeroize(&mut sigma);
+    for poly in s.iter_mut().chain(e.iter_mut()) {
+        ntt(poly);
+    }
+    let mut t = [[0_u16; N]; K];
+    for ((t, row), e) in t.iter_mut().zip(a.iter()).zip(e.iter()) {
+        *t = add(&dot(row, &s), e);
+    }
+    encode_vector(&t, &mut ek[..VECTOR_SIZE]);
+    ek[VECTOR_SIZE..].copy_from_slice(&rho);
+    encode_vector(&s, dk);
+    s.zeroize();
+    e.zeroize();
+}
+
+/// Algorithm 14 of FIPS 203, encrypting `m` with the randomness `r`.
+fn pke_encrypt(ek: &[u8], m: &[u8; 32], r: &[u8; 32]) -> [u8; ML_KEM_768_CIPHERTEXT_SIZE] {
+    let t = decode_vector(&ek[..VECTOR_SIZE]);
+    let mut rho = [0_u8; 32];
+    rho.copy_from_slice(&ek[VECTOR_SIZE..]);
+    let a_t = matrix(&rho, true);
+    let mut y = sample_vector(r, 0);
+    let mut e1 = sample_vector(r, K as u8);
+    let mut e2 = sample_cbd(r, 2 * K as u8);
+    for poly in y.iter_mut() {
+        ntt(poly);
+    }
+
+    let mut c = [0_u8; ML_KEM_768_CIPHERTEXT_SIZE];
+    for ((row, e1), out) in a_t.iter().zip(e1.iter()).zip(c[..U_SIZE].chunks_mut(32 * DU)) {
+        let mut u = dot(row, &y);
+        inverse_ntt(&mut u);
+        u = add(&u, e1);
+        compress(&mut u, DU);
+        byte_encode(&u, DU, out);
+        u.zeroize();
+    }
+    let mut mu = byte_decode(m, 1);
+    decompress(&mut mu, 1);
+    let mut v = dot(&t, &y);
+    inverse_ntt(&mut v);
+    v = add(&add(&v, &e2), &mu);
+    compress(&mut v, DV);
+    byte_encode(&v, DV, &mut c[U_SIZE..]);
+
+    for poly in [&mut e2, &mut mu, &mut v] {
+        poly.zeroize();
+    }
+    y.zeroize();
+    e1.zeroize();
+    c
+}
+
+/// Algorithm 15 of FIPS 203.
+fn pke_decrypt(dk: &[u8], c: &[u8; ML_KEM_768_CIPHERTEXT_SIZE]) -> [u8; 32] {
+    let mut u = [[0_u16; N]; K];
+    for (u, bytes) in u.iter_mut().zip(c[..U_SIZE].chunks(32 * DU)) {
+        *u = byte_decode(bytes, DU);
+        decompress(u, DU);
+        ntt(u);
+    }
+    let mut v = byte_decode(&c[U_SIZE..], DV);
+    decompress(&mut v, DV);
+    let mut s = decode_vector(dk);
+    let mut su = dot(&s, &u);
+    inverse_ntt(&mut su);
+    let mut w = sub(&v, &su);
+    compress(&mut w, 1);
+    let mut m = [0_u8; 32];
+    byte_encode(&w, 1, &mut m);
+    for poly in [&mut v, &mut su, &mut w] {
+        poly.zeroize();
+    }
+    s.zeroize();
+    m
+}
+
+/// An ML-KEM-768 decapsulation key.
+pub struct MlKem768DecapsulationKey {
+    /// The key as FIPS 203 encodes it: the decryption key, the encapsulation
+    /// key, its hash and the implicit rejection value `z`.
+    bytes: SecretBytes<ML_KEM_768_DECAPSULATION_KEY_SIZE>,
+}
+
+impl MlKem768DecapsulationKey {
+    /// Generates a key with the enclave's random number generator.
+    pub fn generate() -> SgxResult<MlKem768DecapsulationKey> {
+        let mut seed = SecretBytes::<ML_KEM_SEED_SIZE>::zeroed();
+        read_rand(seed.expose_secret_mut())?;
+        Ok(MlKem768DecapsulationKey::from_seed(seed.expose_secret()))
+    }
+
+    /// Derives the key of the 64-byte seed `d || z`, as `ML-KEM.KeyGen_internal`
+    /// does. Sealing the seed rather than the key takes 64 bytes instead of
+    /// 2400.
+    pub fn from_seed(seed: &[u8; ML_KEM_SEED_SIZE]) -> MlKem768DecapsulationKey {
+        let mut bytes = SecretBytes::<ML_KEM_768_DECAPSULATION_KEY_SIZE>::zeroed();
+        let dk = bytes.expose_secret_mut();
+        let mut d = [0_u8; 32];
+        d.copy_from_slice(&seed[..32]);
+        let (dk_pke, rest) = dk.split_at_mut(VECTOR_SIZE);
+        let (ek, rest) = rest.split_at_mut(ML_KEM_768_ENCAPSULATION_KEY_SIZE);
+        pke_key_gen(&d, ek, dk_pke);
+        let hash = h(ek);
+        rest[..32].copy_from_slice(&hash);
+        rest[32..].copy_from_slice(&seed[32..]);
+        zeroize(&mut d);
+        MlKem768DecapsulationKey { bytes }
+    }
+
+    /// Creates a key from its encoding, failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` if the hash of its encapsulation key
+    /// doesn't match.
+    pub fn from_bytes(
+        bytes: &[u8; ML_KEM_768_DECAPSULATION_KEY_SIZE],
+    ) -> SgxResult<MlKem768DecapsulationKey> {
+        let key = MlKem768DecapsulationKey { bytes: Secret::new(*bytes) };
+        let hash = &key.bytes.expose_secret()[2 * VECTOR_SIZE + 32..][..32];
+        if h(key.encapsulation_key_bytes()) != hash {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(key)
+    }
+
+    /// Returns the encoding of the key, e.g. to seal it.
+    pub fn to_bytes(&self) -> SecretBytes<ML_KEM_768_DECAPSULATION_KEY_SIZE> {
+        self.bytes.clone()
+    }
+
+    fn encapsulation_key_bytes(&self) -> &[u8] {
+        &self.bytes.expose_secret()[VECTOR_SIZE..2 * VECTOR_SIZE + 32]
+    }
+
+    /// Returns the encapsulation key.
+    pub fn encapsulation_key(&self) -> MlKem768EncapsulationKey {
+        let mut ek = [0_u8; ML_KEM_768_ENCAPSULATION_KEY_SIZE];
+        ek.copy_from_slice(self.encapsulation_key_bytes());
+        MlKem768EncapsulationKey(ek)
+    }
+
+    /// Returns the secret encapsulated in `ciphertext`, as Algorithm 18 of
+    /// FIPS 203 does.
+    ///
+    /// A ciphertext which wasn't made with the encapsulation key gives a
+    /// pseudorandom secret rather than an error, which the peers then find
+    /// out as their keys don't match.
+    pub fn decapsulate(&self, ciphertext: &MlKem768Ciphertext) -> MlKemSharedSecret {
+        let dk = self.bytes.expose_secret();
+        let dk_pke = &dk[..VECTOR_SIZE];
+        let ek = self.encapsulation_key_bytes();
+        let hash = &dk[2 * VECTOR_SIZE + 32..][..32];
+        let z = &dk[2 * VECTOR_SIZE + 64..];
+
+        let mut m = pke_decrypt(dk_pke, &ciphertext.0);
+        let (mut key, mut r) = g(&[&m, hash]);
+        let mut rejected = [0_u8; 32];
+        let mut j = Shake256::new();
+        j.update(z);
+        j.update(&ciphertext.0);
+        j.finalize_xof().read(&mut rejected);
+        let again = pke_encrypt(ek, &m, &r);
+        let mask = (ct_eq(&again, &ciphertext.0) as u8).wrapping_neg();
+        for (k, r) in key.iter_mut().zip(rejected.iter()) {
+            *k = (*k & mask) | (r & !mask);
+        }
+        zeroize(&mut m);
+        zeroize(&mut r);
+        zeroize(&mut rejected);
+        MlKemSharedSecret(Secret::new(key))
+    }
+}
+
+impl fmt::Debug for MlKem768DecapsulationKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MlKem768DecapsulationKey(..)")
+    }
+}
+
+/// An ML-KEM-768 encapsulation key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MlKem768EncapsulationKey([u8; ML_KEM_768_ENCAPSULATION_KEY_SIZE]);
+
+impl MlKem768EncapsulationKey {
+    /// Creates a key from its encoding, failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` unless its coefficients are all
+    /// below `q`.
+    pub fn from_bytes(
+        bytes: &[u8; ML_KEM_768_ENCAPSULATION_KEY_SIZE],
+    ) -> SgxResult<MlKem768EncapsulationKey> {
+        let mut encoded = [0_u8; VECTOR_SIZE];
+        encode_vector(&decode_vector(&bytes[..VECTOR_SIZE]), &mut encoded);
+        if encoded[..] != bytes[..VECTOR_SIZE] {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(MlKem768EncapsulationKey(*bytes))
+    }
+
+    /// Returns the encoding of the key.
+    pub fn to_bytes(&self) -> [u8; ML_KEM_768_ENCAPSULATION_KEY_SIZE] {
+        self.0
+    }
+
+    /// Encapsulates a fresh secret, returning the ciphertext for the owner of
+    /// the decapsulation key and the secret.
+    pub fn encapsulate(&self) -> SgxResult<(MlKem768Ciphertext, MlKemSharedSecret)> {
+        let mut m = SecretBytes::<32>::zeroed();
+        read_rand(m.expose_secret_mut())?;
+        Ok(self.encapsulate_deterministic(m.expose_secret()))
+    }
+
+    /// Encapsulates the secret of the randomness `m`, as
+    /// `ML-KEM.Encaps_internal` does, to check test vectors. `m` must be
+    /// random and used only once.
+    pub fn encapsulate_deterministic(
+        &self,
+        m: &[u8; 32],
+    ) -> (MlKem768Ciphertext, MlKemSharedSecret) {
+        let (key, mut r) = g(&[m, &h(&self.0)]);
+        let ciphertext = MlKem768Ciphertext(pke_encrypt(&self.0, m, &r));
+        zeroize(&mut r);
+        (ciphertext, MlKemSharedSecret(Secret::new(key)))
+    }
+}
+
+impl fmt::Debug for MlKem768EncapsulationKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MlKem768EncapsulationKey({:02x?}..)", &self.0[..8])
+    }
+}
+
+/// An ML-KEM-768 ciphertext.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MlKem768Ciphertext(pub [u8; ML_KEM_768_CIPHERTEXT_SIZE]);
+
+impl fmt::Debug for MlKem768Ciphertext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MlKem768Ciphertext({:02x?}..)", &self.0[..8])
+    }
+}
+
+/// A secret shared through ML-KEM.
+pub struct MlKemSharedSecret(SecretBytes<ML_KEM_SHARED_SECRET_SIZE>);
+
+impl MlKemSharedSecret {
+    /// Returns the secret, to use as a key or feed to a key derivation
+    /// function.
+    pub fn as_bytes(&self) -> &[u8; ML_KEM_SHARED_SECRET_SIZE] {
+        self.0.expose_secret()
+    }
+}
+
+impl fmt::Debug for MlKemSharedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MlKemSharedSecret(..)")
+    }
+}
+
+/// The private key of the X25519MLKEM768 hybrid, an ML-KEM-768
+/// decapsulation key and an X25519 key.
+///
+/// Its owner sends the public key, the other peer encapsulates to it, and
+/// both get the secret `ML-KEM secret || X25519 secret`, laid out as in TLS
+/// (draft-ietf-tls-ecdhe-mlkem). Outside of TLS, the secret should go
+/// through a key derivation function along with both the public key and the
+/// ciphertext, which TLS does through its transcript.
+#[derive(Debug)]
+pub struct X25519MlKem768PrivateKey {
+    ml_kem: MlKem768DecapsulationKey,
+    x25519: X25519PrivateKey,
+}
+
+impl X25519MlKem768PrivateKey {
+    /// Generates a key with the enclave's random number generator.
+    pub fn generate() -> SgxResult<X25519MlKem768PrivateKey> {
+        Ok(X25519MlKem768PrivateKey {
+            ml_kem: MlKem768DecapsulationKey::generate()?,
+            x25519: X25519PrivateKey::generate()?,
+        })
+    }
+
+    /// Creates a key from its two halves, e.g. unsealed.
+    pub fn from_keys(
+        ml_kem: MlKem768DecapsulationKey,
+        x25519: X25519PrivateKey,
+    ) -> X25519MlKem768PrivateKey {
+        X25519MlKem768PrivateKey { ml_kem, x25519 }
+    }
+
+    pub fn ml_kem(&self) -> &MlKem768DecapsulationKey {
+        &self.ml_kem
+    }
+
+    pub fn x25519(&self) -> &X25519PrivateKey {
+        &self.x25519
+    }
+
+    /// Returns the public key, the encapsulation key followed by the X25519
+    /// public key.
+    pub fn public_key(&self) -> X25519MlKem768PublicKey {
+        let mut bytes = [0_u8; X25519_ML_KEM_768_PUBLIC_KEY_SIZE];
+        bytes[..ML_KEM_768_ENCAPSULATION_KEY_SIZE]
+            .copy_from_slice(&self.ml_kem.encapsulation_key().to_bytes());
+        bytes[ML_KEM_768_ENCAPSULATION_KEY_SIZE..]
+            .copy_from_slice(&self.x25519.public_key().to_bytes());
+        X25519MlKem768PublicKey(bytes)
+    }
+
+    /// Returns the secret encapsulated in `ciphertext`.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if the X25519 half is a
+    /// low-order point.
+    pub fn decapsulate(
+        &self,
+        ciphertext: &X25519MlKem768Ciphertext,
+    ) -> SgxResult<X25519MlKem768SharedSecret> {
+        let mut ml_kem = [0_u8; ML_KEM_768_CIPHERTEXT_SIZE];
+        ml_kem.copy_from_slice(&ciphertext.0[..ML_KEM_768_CIPHERTEXT_SIZE]);
+        let mut peer = [0_u8; X25519_KEY_SIZE];
+        peer.copy_from_slice(&ciphertext.0[ML_KEM_768_CIPHERTEXT_SIZE..]);
+        let x25519 = self.x25519.diffie_hellman(&X25519PublicKey(peer))?;
+        let ml_kem = self.ml_kem.decapsulate(&MlKem768Ciphertext(ml_kem));
+        Ok(X25519MlKem768SharedSecret::join(&ml_kem, x25519.as_bytes()))
+    }
+}
+
+/// The public key of the X25519MLKEM768 hybrid, the key share of a TLS
+/// client.
+#[derive(Clone, PartialEq, Eq)]
+pub struct X25519MlKem768PublicKey(pub [u8; X25519_ML_KEM_768_PUBLIC_KEY_SIZE]);
+
+impl X25519MlKem768PublicKey {
+    /// Encapsulates a fresh secret, returning the ciphertext for the owner of
+    /// the private key, the key share of a TLS server, and the secret.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if the encapsulation key is
+    /// invalid or the X25519 key a low-order point.
+    pub fn encapsulate(
+        &self,
+    ) -> SgxResult<(X25519MlKem768Ciphertext, X25519MlKem768SharedSecret)> {
+        let mut ek = [0_u8; ML_KEM_768_ENCAPSULATION_KEY_SIZE];
+        ek.copy_from_slice(&self.0[..ML_KEM_768_ENCAPSULATION_KEY_SIZE]);
+        let mut peer = [0_u8; X25519_KEY_SIZE];
+        peer.copy_from_slice(&self.0[ML_KEM_768_ENCAPSULATION_KEY_SIZE..]);
+        let ek = MlKem768EncapsulationKey::from_bytes(&ek)?;
+
+        let ephemeral = X25519PrivateKey::generate()?;
+        let x25519 = ephemeral.diffie_hellman(&X25519PublicKey(peer))?;
+        let (ml_kem_ciphertext, ml_kem) = ek.encapsulate()?;
+        let mut ciphertext = [0_u8; X25519_ML_KEM_768_CIPHERTEXT_SIZE];
+        ciphertext[..ML_KEM_768_CIPHERTEXT_SIZE].copy_from_slice(&ml_kem_ciphertext.0);
+        ciphertext[ML_KEM_768_CIPHERTEXT_SIZE..]
+            .copy_from_slice(&ephemeral.public_key().to_bytes());
+        let secret = X25519MlKem768SharedSecret::join(&ml_kem, x25519.as_bytes());
+        Ok((X25519MlKem768Ciphertext(ciphertext), secret))
+    }
+}
+
+impl fmt::Debug for X25519MlKem768PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "X25519MlKem768PublicKey({:02x?}..)", &self.0[..8])
+    }
+}
+
+/// The ciphertext of the X25519MLKEM768 hybrid, the ML-KEM ciphertext
+/// followed by an ephemeral X25519 public key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct X25519MlKem768Ciphertext(pub [u8; X25519_ML_KEM_768_CIPHERTEXT_SIZE]);
+
+impl fmt::Debug for X25519MlKem768Ciphertext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "X25519MlKem768Ciphertext({:02x?}..)", &self.0[..8])
+    }
+}
+
+/// A secret shared through the X25519MLKEM768 hybrid.
+pub struct X25519MlKem768SharedSecret(SecretBytes<X25519_ML_KEM_768_SHARED_SECRET_SIZE>);
+
+impl X25519MlKem768SharedSecret {
+    fn join(ml_kem: &MlKemSharedSecret, x25519: &[u8; 32]) -> X25519MlKem768SharedSecret {
+        let mut secret = SecretBytes::<X25519_ML_KEM_768_SHARED_SECRET_SIZE>::zeroed();
+        secret.expose_secret_mut()[..ML_KEM_SHARED_SECRET_SIZE].copy_from_slice(ml_kem.as_bytes());
+        secret.expose_secret_mut()[ML_KEM_SHARED_SECRET_SIZE..].copy_from_slice(x25519);
+        X25519MlKem768SharedSecret(secret)
+    }
+
+    /// Returns the secret, to feed to a key derivation function.
+    pub fn as_bytes(&self) -> &[u8; X25519_ML_KEM_768_SHARED_SECRET_SIZE] {
+        self.0.expose_secret()
+    }
+}
+
+impl fmt::Debug for X25519MlKem768SharedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("X25519MlKem768SharedSecret(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{Hash, Sha3_256};
+    use crate::util::hex;
+
+    fn sha3_256(data: &[u8]) -> [u8; 32] {
+        let mut digest = [0_u8; 32];
+        Sha3_256::digest_into(data, &mut digest);
+        digest
+    }
+
+    fn key() -> MlKem768DecapsulationKey {
+        let mut seed = [0_u8; ML_KEM_SEED_SIZE];
+        seed.iter_mut().enumerate().for_each(|(i, byte)| *byte = i as u8);
+        MlKem768DecapsulationKey::from_seed(&seed)
+    }
+
+    #[test]
+    fn known_answer() {
+        // The key of the seed 00 01 .. 3f and the encapsulation of m = 42 ..
+        // 42 to it, pinned by their SHA3-256 digests and checked against
+        // OpenSSL, which also rejects the ciphertext with its first bit
+        // flipped to the same secret.
+        let key = key();
+        let ek = key.encapsulation_key();
+        assert_eq!(
+            sha3_256(&ek.to_bytes())[..],
+            hex("a24e16d8f8f9383a95b77050f4d9fd2f5733eec1d63ef3c23ebf9918173669a7")[..]
+        );
+        let (ciphertext, secret) = ek.encapsulate_deterministic(&[0x42; 32]);
+        assert_eq!(
+            sha3_256(&ciphertext.0)[..],
+            hex("e9a0824664dba3f8f3c86ecb43a0c889030947ff01d276d04d46c204b62fc221")[..]
+        );
+        let expected = hex("b83e7f23b33f909715c7a50b0d4b1f6684d53e1f4b9056f803b29f058ccb5566");
+        assert_eq!(secret.as_bytes()[..], expected[..]);
+        assert_eq!(key.decapsulate(&ciphertext).as_bytes()[..], expected[..]);
+
+        let mut bad = ciphertext.clone();
+        bad.0[0] ^= 1;
+        assert_eq!(
+            key.decapsulate(&bad).as_bytes()[..],
+            hex("3816af13752429d4e8b800fd2c691b3254d09ed953cf287c99453d3d8057b41e")[..]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let key = key();
+        let mut ek = key.encapsulation_key().to_bytes();
+        // A coefficient of q = 3329, encoded little-endian in 12 bits.
+        ek[0] = 0x01;
+        ek[1] = (ek[1] & 0xf0) | 0x0d;
+        assert!(MlKem768EncapsulationKey::from_bytes(&ek).is_err());
+
+        let mut dk = *key.to_bytes().expose_secret();
+        assert!(MlKem768DecapsulationKey::from_bytes(&dk).is_ok());
+        // The copy of the encapsulation key no longer matches its hash.
+        dk[2 * VECTOR_SIZE] ^= 1;
+        assert!(MlKem768DecapsulationKey::from_bytes(&dk).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Variable-time multi-scalar multiplication, with Straus' method over
+//! width-5 non-adjacent forms, for batch verification.
+//!
+//! Running time depends on the scalars and points, so these functions only
+//! ever see public values: signatures, public keys and message hashes.
+
+/// The group operations the multiplication needs.
+pub(crate) trait Group: Copy {
+    fn identity() -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn double(&self) -> Self;
+    fn neg(&self) -> Self;
+}
+
+/// The number of digits of the non-adjacent form of a 256-bit scalar.
+pub(crate) const DIGITS: usize = 257;
+
+/// The odd multiples `P, 3P, ..., 15P` of a point.
+#[derive(Clone, Copy)]
+pub(crate) struct Table<G>([G; 8]);
+
+impl<G: Group> Table<G> {
+    pub(crate) fn new(point: &G) -> Table<G> {
+        let double = point.double();
+        let mut table = [*point; 8];
+        for i in 1..8 {
+            table[i] = table[i - 1].add(&double);
+        }
+        Table(table)
+    }
+
+    /// Returns `digit * P`, for an odd digit in `(-16, 16)`.
+    fn get(&self, digit: i8) -> G {
+        if digit > 0 {
+            self.0[(digit / 2) as usize]
+        } else {
+            self.0[(-digit / 2) as usize].neg()
+        }
+    }
+}
+
+/// Returns the width-5 non-adjacent form of a little-endian scalar: digits
+/// which are zero or odd in `(-16, 16)`, with at least four zeros after
+/// every non-zero digit.
+pub(crate) fn wnaf(scalar: &[u64; 4]) -> [i8; DIGITS] {
+    let mut k = [scalar[0], scalar[1], scalar[2], scalar[3], 0];
+    let mut digits = [0_i8; DIGITS];
+    for digit in digits.iter_mut() {
+        if k.iter().all(|&w| w == 0) {
+            break;
+        }
+        if k[0] & 1 == 1 {
+            let mut d = (k[0] & 31) as i8;
+            if d >= 16 {
+                d -= 32;
+            }
+            *digit = d;
+            // Subtracting the digit clears the low five bits.
+            if d > 0 {
+                k[0] -= d as u64;
+            } else {
+                let (sum, mut carry) = k[0].overflowing_add(-d as u64);
+                k[0] = sum;
+                for word in k[1..].iter_mut() {
+                    if !carry {
+                        break;
+                    }
+                    let (sum, c) = word.overflowing_add(1);
+                    *word = sum;
+                    carry = c;
+                }
+            }
+        }
+        for i in 0..4 {
+            k[i] = (k[i] >> 1) | (k[i + 1] << 63);
+        }
+        k[4] >>= 1;
+    }
+    digits
+}
+
+/// Computes `sum(s_i * P_i)` from the tables of the points and the
+/// non-adjacent forms of the scalars, sharing the doublings.
+pub(crate) fn straus<G: Group>(terms: &[(&Table<G>, &[i8; DIGITS])]) -> G {
+    let top = terms.iter().filter_map(|(_, digits)| digits.iter().rposition(|&d| d != 0)).max();
+    let mut acc = G::identity();
+    let top = match top {
+        Some(top) => top,
+        None => return acc,
+    };
+    for i in (0..=top).rev() {
+        acc = acc.double();
+        for (table, digits) in terms.iter() {
+            if digits[i] != 0 {
+                acc = acc.add(&table.get(digits[i]));
+            }
+        }
+    }
+    acc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// The integers modulo 2^61 - 1 under addition, in which `s * P` is a
+    /// product to check against.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Zp(u64);
+
+    const P: u64 = (1 << 61) - 1;
+
+    impl Group for Zp {
+        fn identity() -> Zp {
+            Zp(0)
+        }
+
+        fn add(&self, other: &Zp) -> Zp {
+            Zp((self.0 + other.0) % P)
+        }
+
+        fn double(&self) -> Zp {
+            self.add(self)
+        }
+
+        fn neg(&self) -> Zp {
+            Zp((P - self.0) % P)
+        }
+    }
+
+    fn naive(scalar: &[u64; 4], point: Zp) -> Zp {
+        let s = scalar.iter().rev().fold(0_u128, |acc, &w| ((acc << 64) | w as u128) % P as u128);
+        Zp((s * point.0 as u128 % P as u128) as u64)
+    }
+
+    const SCALARS: [[u64; 4]; 6] = [
+        [0; 4],
+        [1, 0, 0, 0],
+        [u64::MAX; 4],
+        [0x8000_0000_0000_0000, 0, 0, 0xffff_ffff_ffff_fff0],
+        [
+            0x0123_4567_89ab_cdef,
+            0xfedc_ba98_7654_3210,
+            0x1f1f_1f1f_1f1f_1f1f,
+            0x7777_0000_7777_0000,
+        ],
+        [31, 15, 16, 17],
+    ];
+
+    #[test]
+    fn wnaf_digits() {
+        for scalar in SCALARS {
+            let digits = wnaf(&scalar);
+            let mut last = None;
+            for (i, &d) in digits.iter().enumerate().filter(|(_, &d)| d != 0) {
+                assert!(d % 2 != 0 && d > -16 && d < 16);
+                if let Some(last) = last {
+                    assert!(i - last >= 5, "{:?}", scalar);
+                }
+                last = Some(i);
+            }
+            // The digits add up to the scalar, here modulo the toy order.
+            let one = Table::new(&Zp(1));
+            assert_eq!(straus(&[(&one, &digits)]), naive(&scalar, Zp(1)));
+        }
+    }
+
+    #[test]
+    fn matches_naive_multiplication() {
+        let points = [Zp(1), Zp(2), Zp(P - 1), Zp(0x0123_4567_89ab_cdef), Zp(0), Zp(1 << 60)];
+        let tables: Vec<Table<Zp>> = points.iter().map(Table::new).collect();
+        let digits: Vec<[i8; DIGITS]> = SCALARS.iter().map(wnaf).collect();
+        for n in 0..=points.len() {
+            let terms: Vec<_> = tables[..n].iter().zip(digits.iter()).collect();
+            let expected = points[..n]
+                .iter()
+                .zip(SCALARS.iter())
+                .fold(Zp::identity(), |acc, (p, s)| acc.add(&naive(s, *p)));
+            assert_eq!(straus(&terms), expected, "{} terms", n);
+        }
+    }
+}
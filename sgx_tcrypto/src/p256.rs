@@ -0,0 +1,389 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Batch verification of ECDSA signatures over NIST P-256, implemented in
+//! Rust; signing, and verifying a single signature, are left to the Intel
+//! library's [`SgxEccHandle`]. The curve arithmetic also serves protocols,
+//! like the verifiable sharing of [`shamir`](crate::shamir).
+//!
+//! Keys and signatures are the Intel library's types, and messages are
+//! hashed with SHA-256, as [`SgxEccHandle::ecdsa_verify_slice`] does.
+//!
+//! [`SgxEccHandle`]: crate::SgxEccHandle
+//! [`SgxEccHandle::ecdsa_verify_slice`]: crate::SgxEccHandle::ecdsa_verify_slice
+
+use crate::field256::{
+    from_be_bytes, is_zero, select, sub_words, to_be_bytes, wipe, Modulus, Words,
+};
+use crate::msm::{straus, wnaf, Group, Table};
+use crate::sha256::Sha256;
+use crate::util::{read_rand, zeroize};
+use sgx_types::*;
+
+const P: Modulus = Modulus {
+    m: [0xffff_ffff_ffff_ffff, 0x0000_0000_ffff_ffff, 0, 0xffff_ffff_0000_0001],
+    inv: 1,
+    r2: [3, 0xffff_fffb_ffff_ffff, 0xffff_ffff_ffff_fffe, 0x0000_0004_ffff_fffd],
+};
+
+const N: Modulus = Modulus {
+    m: [0xf3b9_cac2_fc63_2551, 0xbce6_faad_a717_9e84, 0xffff_ffff_ffff_ffff, 0xffff_ffff_0000_0000],
+    inv: 0xccd1_c8aa_ee00_bc4f,
+    r2: [
+        0x8324_4c95_be79_eea2,
+        0x4699_799c_49bd_6fa6,
+        0x2845_b239_2b6b_ec59,
+        0x66e1_2d94_f3d9_5620,
+    ],
+};
+
+const B: Words =
+    [0x3bce_3c3e_27d2_604b, 0x651d_06b0_cc53_b0f6, 0xb3eb_bd55_7698_86bc, 0x5ac6_35d8_aa3a_93e7];
+const GX: Words =
+    [0xf4a1_3945_d898_c296, 0x7703_7d81_2deb_33a0, 0xf8bc_e6e5_63a4_40f2, 0x6b17_d1f2_e12c_4247];
+const GY: Words =
+    [0xcbb6_4068_37bf_51f5, 0x2bce_3357_6b31_5ece, 0x8ee7_eb4a_7c0f_9e16, 0x4fe3_42e2_fe1a_7f9b];
+
+/// A point in projective coordinates, `x = X/Z`, `y = Y/Z`, with the
+/// coordinates in the Montgomery domain.
+#[derive(Clone, Copy)]
+pub(crate) struct Point {
+    x: Words,
+    y: Words,
+    z: Words,
+}
+
+impl Point {
+    pub(crate) fn generator() -> Point {
+        Point { x: P.encode(&GX), y: P.encode(&GY), z: P.one() }
+    }
+
+    /// Adds with the complete formulas of Renes, Costello and Batina for
+    /// `a = -3`.
+    pub(crate) fn add(&self, other: &Point) -> Point {
+        let b = P.encode(&B);
+        let t0 = P.mul(&self.x, &other.x);
+        let t1 = P.mul(&self.y, &other.y);
+        let t2 = P.mul(&self.z, &other.z);
+        let t3 = P.mul(&P.add(&self.x, &self.y), &P.add(&other.x, &other.y));
+        let t3 = P.sub(&t3, &P.add(&t0, &t1));
+        let t4 = P.mul(&P.add(&self.y, &self.z), &P.add(&other.y, &other.z));
+        let t4 = P.sub(&t4, &P.add(&t1, &t2));
+        let x3 = P.mul(&P.add(&self.x, &self.z), &P.add(&other.x, &other.z));
+        let y3 = P.sub(&x3, &P.add(&t0, &t2));
+        let x3 = P.sub(&y3, &P.mul(&b, &t2));
+        let x3 = P.add(&P.add(&x3, &x3), &x3);
+        let z3 = P.sub(&t1, &x3);
+        let x3 = P.add(&t1, &x3);
+        let y3 = P.mul(&b, &y3);
+        let t2 = P.add(&P.add(&t2, &t2), &t2);
+        let y3 = P.sub(&P.sub(&y3, &t2), &t0);
+        let y3 = P.add(&P.add(&y3, &y3), &y3);
+        let t0 = P.sub(&P.add(&P.add(&t0, &t0), &t0), &t2);
+        let t1 = P.mul(&t4, &y3);
+        let t2 = P.mul(&t0, &y3);
+        let y3 = P.add(&P.mul(&x3, &z3), &t2);
+        let x3 = P.sub(&P.mul(&t3, &x3), &t1);
+        let z3 = P.add(&P.mul(&t4, &z3), &P.mul(&t3, &t0));
+        Point { x: x3, y: y3, z: z3 }
+    }
+
+    /// Doubles with the dedicated formulas of the same paper.
+    fn double(&self) -> Point {
+        let b = P.encode(&B);
+        let t0 = P.square(&self.x);
+        let t1 = P.square(&self.y);
+        let t2 = P.square(&self.z);
+        let t3 = P.mul(&self.x, &self.y);
+        let t3 = P.add(&t3, &t3);
+        let z3 = P.mul(&self.x, &self.z);
+        let z3 = P.add(&z3, &z3);
+        let y3 = P.sub(&P.mul(&b, &t2), &z3);
+        let y3 = P.add(&P.add(&y3, &y3), &y3);
+        let x3 = P.sub(&t1, &y3);
+        let y3 = P.mul(&x3, &P.add(&t1, &y3));
+        let x3 = P.mul(&x3, &t3);
+        let t2 = P.add(&P.add(&t2, &t2), &t2);
+        let z3 = P.sub(&P.sub(&P.mul(&b, &z3), &t2), &t0);
+        let z3 = P.add(&P.add(&z3, &z3), &z3);
+        let t0 = P.sub(&P.add(&P.add(&t0, &t0), &t0), &t2);
+        let y3 = P.add(&y3, &P.mul(&t0, &z3));
+        let t0 = P.mul(&self.y, &self.z);
+        let t0 = P.add(&t0, &t0);
+        let x3 = P.sub(&x3, &P.mul(&t0, &z3));
+        let z3 = P.mul(&t0, &t1);
+        let z3 = P.add(&z3, &z3);
+        Point { x: x3, y: y3, z: P.add(&z3, &z3) }
+    }
+
+    fn cmov(&mut self, other: &Point, choice: u64) {
+        self.x = select(&self.x, &other.x, choice);
+        self.y = select(&self.y, &other.y, choice);
+        self.z = select(&self.z, &other.z, choice);
+    }
+
+    /// Computes `scalar * self`, doubling and adding for every bit, in
+    /// constant time.
+    pub(crate) fn mul(&self, scalar: &Words) -> Point {
+        let mut acc = Point::identity();
+        for i in (0..256).rev() {
+            acc = acc.double();
+            let sum = acc.add(self);
+            acc.cmov(&sum, (scalar[i / 64] >> (i % 64)) & 1);
+        }
+        acc
+    }
+
+    /// Computes `scalar * self` in variable time, for public scalars.
+    pub(crate) fn mul_vartime(&self, scalar: &Words) -> Point {
+        straus(&[(&Table::new(self), &wnaf(scalar))])
+    }
+
+    pub(crate) fn same(&self, other: &Point) -> bool {
+        P.mul(&self.x, &other.z) == P.mul(&other.x, &self.z)
+            && P.mul(&self.y, &other.z) == P.mul(&other.y, &self.z)
+    }
+
+    /// Returns the point as a public key of the Intel library, or `None`
+    /// for the identity.
+    pub(crate) fn to_public(self) -> Option<sgx_ec256_public_t> {
+        if is_zero(&self.z) {
+            return None;
+        }
+        let zinv = P.invert(&self.z);
+        Some(sgx_ec256_public_t {
+            gx: to_le_bytes(&P.decode(&P.mul(&self.x, &zinv))),
+            gy: to_le_bytes(&P.decode(&P.mul(&self.y, &zinv))),
+        })
+    }
+
+    pub(crate) fn from_public(public: &sgx_ec256_public_t) -> Option<Point> {
+        Point::from_affine(&from_le_bytes(&public.gx), &from_le_bytes(&public.gy))
+    }
+
+    /// Returns the point with affine coordinates `(x, y)`, if it is on the
+    /// curve.
+    fn from_affine(x: &Words, y: &Words) -> Option<Point> {
+        if !P.contains(x) || !P.contains(y) {
+            return None;
+        }
+        let (x, y) = (P.encode(x), P.encode(y));
+        let three_x = P.add(&P.add(&x, &x), &x);
+        let rhs = P.add(&P.sub(&P.mul(&P.square(&x), &x), &three_x), &P.encode(&B));
+        if P.square(&y) != rhs {
+            return None;
+        }
+        Some(Point { x, y, z: P.one() })
+    }
+
+    /// Returns whether the affine `x` of the point is `r` modulo `n`, for
+    /// `r` in `[1, n)`, without inverting `Z`.
+    fn x_matches(&self, r: &Words) -> bool {
+        if is_zero(&self.z) {
+            return false;
+        }
+        if P.mul(&P.encode(r), &self.z) == self.x {
+            return true;
+        }
+        let (bound, _) = sub_words(&P.m, &N.m);
+        sub_words(r, &bound).1 == 1 && P.mul(&P.encode(&P.add(r, &N.m)), &self.z) == self.x
+    }
+}
+
+impl Group for Point {
+    fn identity() -> Point {
+        Point { x: [0; 4], y: P.one(), z: [0; 4] }
+    }
+
+    fn add(&self, other: &Point) -> Point {
+        Point::add(self, other)
+    }
+
+    fn double(&self) -> Point {
+        Point::double(self)
+    }
+
+    fn neg(&self) -> Point {
+        Point { x: self.x, y: P.neg(&self.y), z: self.z }
+    }
+}
+
+fn from_le_bytes(bytes: &[u8; 32]) -> Words {
+    let mut words = [0_u64; 4];
+    for (i, word) in words.iter_mut().enumerate() {
+        let mut le = [0_u8; 8];
+        le.copy_from_slice(&bytes[i * 8..i * 8 + 8]);
+        *word = u64::from_le_bytes(le);
+    }
+    words
+}
+
+fn to_le_bytes(words: &Words) -> [u8; 32] {
+    let mut bytes = [0_u8; 32];
+    for (i, word) in words.iter().enumerate() {
+        bytes[i * 8..i * 8 + 8].copy_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+/// Joins the little-endian 32-bit words of the Intel signature type.
+fn from_le_words(words: &[u32; 8]) -> Words {
+    let mut out = [0_u64; 4];
+    for (i, word) in out.iter_mut().enumerate() {
+        *word = words[2 * i] as u64 | (words[2 * i + 1] as u64) << 32;
+    }
+    out
+}
+
+/// Hashes a message to a scalar; the hash is below `2n`, so one
+/// subtraction reduces it.
+fn hash_to_scalar(message: &[u8]) -> Words {
+    let mut sha = Sha256::new();
+    sha.update(message);
+    let hash = sha.finalize();
+    let mut z = [0_u64; 4];
+    for (i, word) in z.iter_mut().enumerate() {
+        let mut be = [0_u8; 8];
+        be.copy_from_slice(&hash[24 - 8 * i..32 - 8 * i]);
+        *word = u64::from_be_bytes(be);
+    }
+    let (d, borrow) = sub_words(&z, &N.m);
+    if borrow == 1 {
+        z
+    } else {
+        d
+    }
+}
+
+/// A scalar modulo the group order `n`, for the protocols built on the
+/// curve. Arithmetic is constant time.
+#[derive(Clone, Copy)]
+pub(crate) struct Scalar(pub(crate) Words);
+
+impl Scalar {
+    /// Returns a uniformly random non-zero scalar.
+    pub(crate) fn random() -> SgxResult<Scalar> {
+        let mut bytes = [0_u8; 32];
+        loop {
+            read_rand(&mut bytes)?;
+            let scalar = Scalar::from_bytes(&bytes);
+            if let Some(scalar) = scalar.filter(|s| !s.is_zero()) {
+                zeroize(&mut bytes);
+                return Ok(scalar);
+            }
+        }
+    }
+
+    /// Parses a big-endian scalar, failing if it isn't below `n`.
+    pub(crate) fn from_bytes(bytes: &[u8; 32]) -> Option<Scalar> {
+        let words = from_be_bytes(bytes);
+        if N.contains(&words) {
+            Some(Scalar(words))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn to_bytes(self) -> [u8; 32] {
+        to_be_bytes(&self.0)
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        is_zero(&self.0)
+    }
+
+    pub(crate) fn add(&self, other: &Scalar) -> Scalar {
+        Scalar(N.add(&self.0, &other.0))
+    }
+
+    pub(crate) fn sub(&self, other: &Scalar) -> Scalar {
+        Scalar(N.sub(&self.0, &other.0))
+    }
+
+    pub(crate) fn mul(&self, other: &Scalar) -> Scalar {
+        Scalar(N.decode(&N.mul(&N.encode(&self.0), &N.encode(&other.0))))
+    }
+
+    /// Returns the inverse of a non-zero scalar.
+    pub(crate) fn invert(&self) -> Scalar {
+        Scalar(N.decode(&N.invert(&N.encode(&self.0))))
+    }
+
+    pub(crate) fn wipe(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+/// The number of signatures whose `s` are inverted together.
+const BATCH_CHUNK: usize = 16;
+
+/// Verifies a batch of signatures, returning whether all of them are valid;
+/// [`SgxEccHandle::ecdsa_verify_slice`] then tells which ones aren't.
+///
+/// As for [`secp256k1::verify_batch`], the batch shares the inversions of
+/// `s`, and the tables of the generator and of repeated public keys, and
+/// checks each signature with one variable-time multiplication.
+///
+/// [`SgxEccHandle::ecdsa_verify_slice`]: crate::SgxEccHandle::ecdsa_verify_slice
+/// [`secp256k1::verify_batch`]: crate::secp256k1::verify_batch
+pub fn verify_batch(items: &[(&sgx_ec256_public_t, &[u8], &sgx_ec256_signature_t)]) -> bool {
+    let generator = Table::new(&Point::generator());
+    items.chunks(BATCH_CHUNK).all(|chunk| verify_chunk(&generator, chunk))
+}
+
+fn verify_chunk(
+    generator: &Table<Point>,
+    chunk: &[(&sgx_ec256_public_t, &[u8], &sgx_ec256_signature_t)],
+) -> bool {
+    let mut inverses = [[0_u64; 4]; BATCH_CHUNK];
+    for (i, (_, _, signature)) in chunk.iter().enumerate() {
+        let (r, s) = (from_le_words(&signature.x), from_le_words(&signature.y));
+        if is_zero(&r) || is_zero(&s) || !N.contains(&r) || !N.contains(&s) {
+            return false;
+        }
+        inverses[i] = N.encode(&s);
+    }
+    N.invert_all(&mut inverses[..chunk.len()], &mut [[0; 4]; BATCH_CHUNK]);
+
+    let mut keys = [chunk[0].0; BATCH_CHUNK];
+    let mut tables = [Table::new(&Point::identity()); BATCH_CHUNK];
+    let mut key_count = 0;
+    for (i, (public, message, signature)) in chunk.iter().enumerate() {
+        let same = |key: &&sgx_ec256_public_t| key.gx == public.gx && key.gy == public.gy;
+        let key = match keys[..key_count].iter().position(same) {
+            Some(key) => key,
+            None => {
+                let q = match Point::from_public(public) {
+                    Some(q) => q,
+                    None => return false,
+                };
+                keys[key_count] = public;
+                tables[key_count] = Table::new(&q);
+                key_count += 1;
+                key_count - 1
+            }
+        };
+        let r = from_le_words(&signature.x);
+        let u1 = N.decode(&N.mul(&N.encode(&hash_to_scalar(message)), &inverses[i]));
+        let u2 = N.decode(&N.mul(&N.encode(&r), &inverses[i]));
+        let point = straus(&[(generator, &wnaf(&u1)), (&tables[key], &wnaf(&u2))]);
+        if !point.x_matches(&r) {
+            return false;
+        }
+    }
+    true
+}
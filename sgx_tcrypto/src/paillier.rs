@@ -0,0 +1,177 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! The Paillier cryptosystem over a 2048-bit modulus, with `g = n + 1`.
+
+use crate::ctbignum::{random_prime, Monty, Uint};
+use sgx_types::*;
+
+pub(crate) const MODULUS_BITS: usize = 2048;
+pub(crate) const MODULUS_SIZE: usize = MODULUS_BITS / 8;
+pub(crate) const CIPHERTEXT_SIZE: usize = 2 * MODULUS_SIZE;
+
+/// Plaintexts and randomness, modulo `n`.
+pub(crate) type Nat = Uint<32>;
+/// Ciphertexts, modulo `n^2`.
+pub(crate) type Nat2 = Uint<64>;
+/// The prime factors of `n`.
+pub(crate) type Prime = Uint<16>;
+
+#[derive(Clone, Copy)]
+pub(crate) struct PublicKey {
+    n: Nat,
+    n2: Nat2,
+    mod_n: Monty<32>,
+    mod_n2: Monty<64>,
+}
+
+impl PublicKey {
+    /// Checks that `n` is odd and exactly `MODULUS_BITS` long; the rest of
+    /// its validity needs a proof.
+    pub(crate) fn new(n: &Nat) -> Option<PublicKey> {
+        if n.bits_vartime() != MODULUS_BITS {
+            return None;
+        }
+        let n2 = n.resize::<64>().wrapping_mul(&n.resize());
+        Some(PublicKey { n: *n, n2, mod_n: Monty::new(n)?, mod_n2: Monty::new(&n2)? })
+    }
+
+    pub(crate) fn n(&self) -> &Nat {
+        &self.n
+    }
+
+    pub(crate) fn mod_n(&self) -> &Monty<32> {
+        &self.mod_n
+    }
+
+    /// Returns whether `c` can be a ciphertext, in `[1, n^2)`.
+    pub(crate) fn is_ciphertext(&self, c: &Nat2) -> bool {
+        !c.is_zero() && c.lt(&self.n2)
+    }
+
+    /// Returns a random element of `[1, n)`, a unit unless it reveals a
+    /// factor of `n`, which happens with negligible probability.
+    pub(crate) fn random_unit(&self) -> SgxResult<Nat> {
+        loop {
+            let r = Nat::random_below(&self.n)?;
+            if !r.is_zero() {
+                return Ok(r);
+            }
+        }
+    }
+
+    /// Encrypts `m < n` with the randomness `r`, as `(1 + m n) r^n mod n^2`.
+    pub(crate) fn encrypt_with(&self, m: &Nat, r: &Nat) -> Nat2 {
+        let mut gm = m.resize::<64>().wrapping_mul(&self.n.resize());
+        gm.adc(&Uint::from_u64(1));
+        // The exponent n is public, so only its bits steer the timing.
+        let rn = self.mod_n2.pow_vartime(&r.resize(), &self.n);
+        self.mod_n2.mul_mod(&gm, &rn)
+    }
+
+    /// Encrypts `m < n`, returning the ciphertext and its randomness.
+    pub(crate) fn encrypt(&self, m: &Nat) -> SgxResult<(Nat2, Nat)> {
+        let r = self.random_unit()?;
+        Ok((self.encrypt_with(m, &r), r))
+    }
+
+    /// Returns a ciphertext of the sum of the plaintexts.
+    pub(crate) fn add(&self, a: &Nat2, b: &Nat2) -> Nat2 {
+        self.mod_n2.mul_mod(a, b)
+    }
+
+    /// Returns a ciphertext of the plaintext times `k`, using the lowest
+    /// `bits` bits of `k`, in time independent of `k`.
+    pub(crate) fn scale<const E: usize>(&self, c: &Nat2, k: &Uint<E>, bits: usize) -> Nat2 {
+        self.mod_n2.pow(c, k, bits)
+    }
+}
+
+pub(crate) struct SecretKey {
+    p: Prime,
+    q: Prime,
+    public: PublicKey,
+    phi: Nat,
+    /// `phi^-1 mod n`.
+    mu: Nat,
+    /// `n^-1 mod phi`, taking `n`-th roots modulo `n`.
+    root_exp: Nat,
+}
+
+impl SecretKey {
+    pub(crate) fn generate() -> SgxResult<SecretKey> {
+        loop {
+            let p = random_prime::<16>()?;
+            let q = random_prime::<16>()?;
+            if let Some(key) = SecretKey::from_primes(&p, &q) {
+                return Ok(key);
+            }
+        }
+    }
+
+    /// Recreates the key from its primes, failing unless `n = p q` is a
+    /// valid modulus with `gcd(n, phi(n)) = 1`.
+    pub(crate) fn from_primes(p: &Prime, q: &Prime) -> Option<SecretKey> {
+        if p == q {
+            return None;
+        }
+        let n = p.resize::<32>().wrapping_mul(&q.resize());
+        let public = PublicKey::new(&n)?;
+        let one = Nat::from_u64(1);
+        let phi = n.wrapping_sub(&p.resize()).wrapping_sub(&q.resize()).wrapping_add(&one);
+        let mu = phi.inv_mod_odd(&n)?;
+
+        // mu phi = 1 + k n, so n (phi - k) = 1 (mod phi).
+        let mut product = mu.resize::<64>().wrapping_mul(&phi.resize());
+        product.sbb(&Uint::from_u64(1));
+        let k = product.div_exact(&n.resize()).resize::<32>();
+        let root_exp = phi.wrapping_sub(&k);
+        Some(SecretKey { p: *p, q: *q, public, phi, mu, root_exp })
+    }
+
+    pub(crate) fn public(&self) -> &PublicKey {
+        &self.public
+    }
+
+    pub(crate) fn primes(&self) -> (&Prime, &Prime) {
+        (&self.p, &self.q)
+    }
+
+    /// Decrypts a ciphertext, as `L(c^phi mod n^2) mu mod n` with
+    /// `L(u) = (u - 1) / n`.
+    pub(crate) fn decrypt(&self, c: &Nat2) -> Nat {
+        let mut u = self.public.mod_n2.pow(c, &self.phi, MODULUS_BITS);
+        u.sbb(&Uint::from_u64(1));
+        let l = u.div_exact(&self.public.n.resize()).resize::<32>();
+        self.public.mod_n.mul_mod(&l, &self.mu)
+    }
+
+    /// Returns the `n`-th root of `x` modulo `n`.
+    pub(crate) fn nth_root(&self, x: &Nat) -> Nat {
+        self.public.mod_n.pow(x, &self.root_exp, MODULUS_BITS)
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.p.wipe();
+        self.q.wipe();
+        self.phi.wipe();
+        self.mu.wipe();
+        self.root_exp.wipe();
+    }
+}
@@ -0,0 +1,197 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! The Poly1305 one-time authenticator (RFC 8439), with 26-bit limbs.
+
+use crate::util::zeroize;
+
+pub(crate) struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+    buf: [u8; 16],
+    buf_len: usize,
+}
+
+impl Poly1305 {
+    pub(crate) fn new(key: &[u8; 32]) -> Poly1305 {
+        let le = |i: usize| u32::from_le_bytes([key[i], key[i + 1], key[i + 2], key[i + 3]]);
+        // r is clamped as the RFC requires.
+        let r = [
+            le(0) & 0x03ff_ffff,
+            (le(3) >> 2) & 0x03ff_ff03,
+            (le(6) >> 4) & 0x03ff_c0ff,
+            (le(9) >> 6) & 0x03f0_3fff,
+            (le(12) >> 8) & 0x000f_ffff,
+        ];
+        let pad = [le(16), le(20), le(24), le(28)];
+        Poly1305 { r, h: [0; 5], pad, buf: [0; 16], buf_len: 0 }
+    }
+
+    /// Processes a 16-byte block, with `hibit` set for full blocks.
+    fn block(&mut self, m: &[u8; 16], hibit: u32) {
+        let [r0, r1, r2, r3, r4] = self.r;
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+        let le = |i: usize| u32::from_le_bytes([m[i], m[i + 1], m[i + 2], m[i + 3]]);
+
+        let mut h0 = self.h[0] + (le(0) & 0x03ff_ffff);
+        let mut h1 = self.h[1] + ((le(3) >> 2) & 0x03ff_ffff);
+        let mut h2 = self.h[2] + ((le(6) >> 4) & 0x03ff_ffff);
+        let mut h3 = self.h[3] + ((le(9) >> 6) & 0x03ff_ffff);
+        let mut h4 = self.h[4] + ((le(12) >> 8) | hibit);
+
+        let m = |a: u32, b: u32| a as u64 * b as u64;
+        let d0 = m(h0, r0) + m(h1, s4) + m(h2, s3) + m(h3, s2) + m(h4, s1);
+        let mut d1 = m(h0, r1) + m(h1, r0) + m(h2, s4) + m(h3, s3) + m(h4, s2);
+        let mut d2 = m(h0, r2) + m(h1, r1) + m(h2, r0) + m(h3, s4) + m(h4, s3);
+        let mut d3 = m(h0, r3) + m(h1, r2) + m(h2, r1) + m(h3, r0) + m(h4, s4);
+        let mut d4 = m(h0, r4) + m(h1, r3) + m(h2, r2) + m(h3, r1) + m(h4, r0);
+
+        let mut c = (d0 >> 26) as u32;
+        h0 = d0 as u32 & 0x03ff_ffff;
+        d1 += c as u64;
+        c = (d1 >> 26) as u32;
+        h1 = d1 as u32 & 0x03ff_ffff;
+        d2 += c as u64;
+        c = (d2 >> 26) as u32;
+        h2 = d2 as u32 & 0x03ff_ffff;
+        d3 += c as u64;
+        c = (d3 >> 26) as u32;
+        h3 = d3 as u32 & 0x03ff_ffff;
+        d4 += c as u64;
+        c = (d4 >> 26) as u32;
+        h4 = d4 as u32 & 0x03ff_ffff;
+        h0 += c * 5;
+        c = h0 >> 26;
+        h0 &= 0x03ff_ffff;
+        h1 += c;
+
+        self.h = [h0, h1, h2, h3, h4];
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        if self.buf_len > 0 {
+            let take = core::cmp::min(16 - self.buf_len, data.len());
+            self.buf[self.buf_len..self.buf_len + take].copy_from_slice(&data[..take]);
+            self.buf_len += take;
+            data = &data[take..];
+            if self.buf_len < 16 {
+                return;
+            }
+            let block = self.buf;
+            self.block(&block, 1 << 24);
+            self.buf_len = 0;
+        }
+        let mut blocks = data.chunks_exact(16);
+        for block in &mut blocks {
+            let mut m = [0_u8; 16];
+            m.copy_from_slice(block);
+            self.block(&m, 1 << 24);
+        }
+        let rest = blocks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.buf_len = rest.len();
+    }
+
+    /// Pads the data processed so far with zeroes to a multiple of 16
+    /// bytes, as the AEAD construction requires.
+    pub(crate) fn pad(&mut self) {
+        if self.buf_len > 0 {
+            let zeroes = [0_u8; 16];
+            let len = 16 - self.buf_len;
+            self.update(&zeroes[..len]);
+        }
+    }
+
+    pub(crate) fn finalize(mut self) -> [u8; 16] {
+        if self.buf_len > 0 {
+            let mut m = [0_u8; 16];
+            m[..self.buf_len].copy_from_slice(&self.buf[..self.buf_len]);
+            m[self.buf_len] = 1;
+            self.block(&m, 0);
+        }
+
+        let [mut h0, mut h1, mut h2, mut h3, mut h4] = self.h;
+        let mut c = h1 >> 26;
+        h1 &= 0x03ff_ffff;
+        h2 += c;
+        c = h2 >> 26;
+        h2 &= 0x03ff_ffff;
+        h3 += c;
+        c = h3 >> 26;
+        h3 &= 0x03ff_ffff;
+        h4 += c;
+        c = h4 >> 26;
+        h4 &= 0x03ff_ffff;
+        h0 += c * 5;
+        c = h0 >> 26;
+        h0 &= 0x03ff_ffff;
+        h1 += c;
+
+        // Compute h - p, and select it if it doesn't underflow.
+        let mut g0 = h0.wrapping_add(5);
+        c = g0 >> 26;
+        g0 &= 0x03ff_ffff;
+        let mut g1 = h1.wrapping_add(c);
+        c = g1 >> 26;
+        g1 &= 0x03ff_ffff;
+        let mut g2 = h2.wrapping_add(c);
+        c = g2 >> 26;
+        g2 &= 0x03ff_ffff;
+        let mut g3 = h3.wrapping_add(c);
+        c = g3 >> 26;
+        g3 &= 0x03ff_ffff;
+        let g4 = h4.wrapping_add(c).wrapping_sub(1 << 26);
+
+        let mask = (g4 >> 31).wrapping_sub(1);
+        h0 = (h0 & !mask) | (g0 & mask);
+        h1 = (h1 & !mask) | (g1 & mask);
+        h2 = (h2 & !mask) | (g2 & mask);
+        h3 = (h3 & !mask) | (g3 & mask);
+        h4 = (h4 & !mask) | (g4 & mask);
+
+        let h0 = h0 | (h1 << 26);
+        let h1 = (h1 >> 6) | (h2 << 20);
+        let h2 = (h2 >> 12) | (h3 << 14);
+        let h3 = (h3 >> 18) | (h4 << 8);
+
+        let mut f = h0 as u64 + self.pad[0] as u64;
+        let t0 = f as u32;
+        f = h1 as u64 + self.pad[1] as u64 + (f >> 32);
+        let t1 = f as u32;
+        f = h2 as u64 + self.pad[2] as u64 + (f >> 32);
+        let t2 = f as u32;
+        f = h3 as u64 + self.pad[3] as u64 + (f >> 32);
+        let t3 = f as u32;
+
+        let mut tag = [0_u8; 16];
+        tag[..4].copy_from_slice(&t0.to_le_bytes());
+        tag[4..8].copy_from_slice(&t1.to_le_bytes());
+        tag[8..12].copy_from_slice(&t2.to_le_bytes());
+        tag[12..].copy_from_slice(&t3.to_le_bytes());
+        tag
+    }
+}
+
+impl Drop for Poly1305 {
+    fn drop(&mut self) {
+        for word in self.r.iter_mut().chain(self.h.iter_mut()).chain(self.pad.iter_mut()) {
+            unsafe { core::ptr::write_volatile(word, 0) };
+        }
+        zeroize(&mut self.buf);
+    }
+}
@@ -0,0 +1,437 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! A [`CryptoProvider`] trait over the primitives the software implementations
+//! of this crate take from the Intel library, so that an enclave can choose the
+//! implementation they run on, e.g. one validated under a customer's mandate.
+//!
+//! [`IntelProvider`], the default, calls the Intel library, and
+//! [`RustProvider`] the Rust implementations of this crate, on AES-NI and
+//! RDRAND. Other backends implement the trait in their own crates. An enclave
+//! installs its provider with [`install`] while it initializes, naming it in
+//! its code or picking it from its configuration, before any cryptography
+//! runs; the choice is then fixed for the life of the enclave, so that no key
+//! is handled by two implementations.
+//!
+//! The provider supplies the random numbers, the AES-128 blocks under
+//! GCM-SIV, CMAC and FF1, and AES-128-GCM. The `rsgx_*` functions and
+//! [`SgxEccHandle`](crate::SgxEccHandle) remain the interface of the Intel
+//! library and keep calling it, and signatures and key agreement aren't part
+//! of the trait yet. With the `test_rng` feature, a test random number
+//! provider still takes precedence.
+
+use crate::aead::{gf128_mul, Tag};
+use crate::aes::{encrypt_block, AesCtr};
+use crate::crypto::{
+    rsgx_aes_ctr_encrypt, rsgx_rijndael128GCM_decrypt, rsgx_rijndael128GCM_encrypt,
+    rsgx_sha256_slice,
+};
+use crate::sha256::Sha256;
+use crate::util::{ct_eq, zeroize};
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicU8, Ordering};
+use sgx_types::*;
+
+/// An implementation of the primitives the crate builds on.
+///
+/// Failures are reported with the status codes of the Intel library.
+pub trait CryptoProvider: Sync {
+    /// Names the implementation, for logs and attestation policies.
+    fn name(&self) -> &'static str;
+
+    /// Fills `buf` with random bytes fit for keys.
+    fn fill_random(&self, buf: &mut [u8]) -> SgxError;
+
+    /// Encrypts `block` in place with AES-128 under `key`.
+    fn aes128_encrypt_block(&self, key: &[u8; 16], block: &mut [u8; 16]) -> SgxError;
+
+    /// Encrypts `src` into `dst`, which is as long, with AES-128-GCM under
+    /// `key` and `nonce`, authenticating `aad` along with it, and returns the
+    /// tag.
+    fn aes128_gcm_encrypt(
+        &self,
+        key: &[u8; 16],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        src: &[u8],
+        dst: &mut [u8],
+    ) -> SgxResult<Tag>;
+
+    /// Checks `tag` over `src` and `aad`, and decrypts `src` into `dst`,
+    /// which is as long.
+    ///
+    /// Fails with `SGX_ERROR_MAC_MISMATCH` if the tag doesn't match, leaving
+    /// no plaintext in `dst`.
+    fn aes128_gcm_decrypt(
+        &self,
+        key: &[u8; 16],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        src: &[u8],
+        tag: &Tag,
+        dst: &mut [u8],
+    ) -> SgxError;
+
+    /// Returns the SHA-256 digest of `data`.
+    fn sha256(&self, data: &[u8]) -> SgxResult<[u8; 32]>;
+}
+
+/// The Intel library, whose AES and random numbers go through IPP and
+/// `sgx_read_rand`.
+pub struct IntelProvider;
+
+impl CryptoProvider for IntelProvider {
+    fn name(&self) -> &'static str {
+        "intel-sgx-tcrypto"
+    }
+
+    fn fill_random(&self, buf: &mut [u8]) -> SgxError {
+        match unsafe { sgx_read_rand(buf.as_mut_ptr(), buf.len()) } {
+            sgx_status_t::SGX_SUCCESS => Ok(()),
+            ret => Err(ret),
+        }
+    }
+
+    fn aes128_encrypt_block(&self, key: &[u8; 16], block: &mut [u8; 16]) -> SgxError {
+        // A single block of CTR keystream starting at `block` is its
+        // encryption.
+        let mut ctr = *block;
+        rsgx_aes_ctr_encrypt(key, &[0_u8; 16], &mut ctr, 128, block)
+    }
+
+    fn aes128_gcm_encrypt(
+        &self,
+        key: &[u8; 16],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        src: &[u8],
+        dst: &mut [u8],
+    ) -> SgxResult<Tag> {
+        let mut tag = Tag::default();
+        rsgx_rijndael128GCM_encrypt(key, src, nonce, aad, dst, &mut tag)?;
+        Ok(tag)
+    }
+
+    fn aes128_gcm_decrypt(
+        &self,
+        key: &[u8; 16],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        src: &[u8],
+        tag: &Tag,
+        dst: &mut [u8],
+    ) -> SgxError {
+        rsgx_rijndael128GCM_decrypt(key, src, nonce, aad, tag, dst)
+    }
+
+    fn sha256(&self, data: &[u8]) -> SgxResult<[u8; 32]> {
+        rsgx_sha256_slice(data)
+    }
+}
+
+/// The Rust implementations of this crate, with AES on AES-NI through
+/// [`aes`](crate::aes) and random numbers read from RDRAND, both of which
+/// the trusted runtime must report.
+pub struct RustProvider;
+
+/// Reads `buf.len()` bytes from RDRAND.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "rdrand")]
+unsafe fn rdrand(buf: &mut [u8]) -> SgxError {
+    use core::arch::x86_64::_rdrand64_step;
+
+    for chunk in buf.chunks_mut(8) {
+        let mut word = 0_u64;
+        // As Intel advises, ten failures in a row mean a broken generator
+        // rather than a busy one.
+        let mut tries = 0;
+        while _rdrand64_step(&mut word) != 1 {
+            tries += 1;
+            if tries == 10 {
+                return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+            }
+        }
+        chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+        core::ptr::write_volatile(&mut word, 0);
+    }
+    Ok(())
+}
+
+/// Computes GHASH over `H`, of `aad` and `ciphertext` and their lengths.
+fn ghash(h: u128, aad: &[u8], ciphertext: &[u8]) -> u128 {
+    let mut y = 0_u128;
+    for data in [aad, ciphertext] {
+        for chunk in data.chunks(16) {
+            let mut block = [0_u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            y = gf128_mul(y ^ u128::from_be_bytes(block), h);
+        }
+    }
+    let lengths = ((aad.len() as u128 * 8) << 64) | (ciphertext.len() as u128 * 8);
+    gf128_mul(y ^ lengths, h)
+}
+
+impl RustProvider {
+    /// Returns the tag of `ciphertext`, and a cipher at its first block.
+    fn gcm(
+        key: &[u8; 16],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> SgxResult<(Tag, AesCtr)> {
+        let mut h = [0_u8; 16];
+        encrypt_block(key, &mut h)?;
+        let mut j0 = [0_u8; 16];
+        j0[..12].copy_from_slice(nonce);
+        j0[15] = 1;
+        let mut tag = j0;
+        encrypt_block(key, &mut tag)?;
+        let s = ghash(u128::from_be_bytes(h), aad, ciphertext);
+        let tag = (u128::from_be_bytes(tag) ^ s).to_be_bytes();
+        zeroize(&mut h);
+        j0[15] = 2;
+        Ok((tag, AesCtr::new(key, &j0, 32)?))
+    }
+}
+
+impl CryptoProvider for RustProvider {
+    fn name(&self) -> &'static str {
+        "rust-sgx-tcrypto"
+    }
+
+    fn fill_random(&self, buf: &mut [u8]) -> SgxError {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if cpu_features() & cpu_feature::CPU_FEATURE_RDRND != 0 {
+                // Safety: the runtime reports RDRAND.
+                return unsafe { rdrand(buf) };
+            }
+        }
+        Err(sgx_status_t::SGX_ERROR_FEATURE_NOT_SUPPORTED)
+    }
+
+    fn aes128_encrypt_block(&self, key: &[u8; 16], block: &mut [u8; 16]) -> SgxError {
+        encrypt_block(key, block)
+    }
+
+    fn aes128_gcm_encrypt(
+        &self,
+        key: &[u8; 16],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        src: &[u8],
+        dst: &mut [u8],
+    ) -> SgxResult<Tag> {
+        if dst.len() != src.len() {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let (_, mut ctr) = RustProvider::gcm(key, nonce, aad, &[])?;
+        dst.copy_from_slice(src);
+        ctr.apply_keystream(dst).map_err(|_| sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+        let (tag, _) = RustProvider::gcm(key, nonce, aad, dst)?;
+        Ok(tag)
+    }
+
+    fn aes128_gcm_decrypt(
+        &self,
+        key: &[u8; 16],
+        nonce: &[u8; 12],
+        aad: &[u8],
+        src: &[u8],
+        tag: &Tag,
+        dst: &mut [u8],
+    ) -> SgxError {
+        if dst.len() != src.len() {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let (expected, mut ctr) = RustProvider::gcm(key, nonce, aad, src)?;
+        if !ct_eq(&expected, tag) {
+            return Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH);
+        }
+        dst.copy_from_slice(src);
+        ctr.apply_keystream(dst).map_err(|_| sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+    }
+
+    fn sha256(&self, data: &[u8]) -> SgxResult<[u8; 32]> {
+        let mut hasher = Sha256::new();
+        hasher.update(data);
+        Ok(hasher.finalize())
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+extern "C" {
+    static g_cpu_feature_indicator: u64;
+}
+
+/// Returns the `cpu_feature` bits of the CPU, as detected by the runtime
+/// when the enclave was loaded; none outside x86-64.
+pub(crate) fn cpu_features() -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // Safety: the runtime sets the indicator before any enclave code runs.
+        unsafe { g_cpu_feature_indicator }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    0
+}
+
+const UNSET: u8 = 0;
+const INSTALLING: u8 = 1;
+const INSTALLED: u8 = 2;
+/// The default provider has been used, and can't be replaced anymore.
+const DEFAULT: u8 = 3;
+
+/// The provider of the enclave, installed once.
+struct Registry {
+    state: AtomicU8,
+    slot: UnsafeCell<Option<&'static dyn CryptoProvider>>,
+}
+
+// SAFETY: the slot is only written once, by the thread which moved `state`
+// from UNSET to INSTALLING, and only read once `state` is INSTALLED.
+unsafe impl Sync for Registry {}
+
+impl Registry {
+    const fn new() -> Registry {
+        Registry { state: AtomicU8::new(UNSET), slot: UnsafeCell::new(None) }
+    }
+
+    fn install(&self, provider: &'static dyn CryptoProvider) -> SgxError {
+        let claimed =
+            self.state.compare_exchange(UNSET, INSTALLING, Ordering::AcqRel, Ordering::Acquire);
+        if claimed.is_err() {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+        }
+        // SAFETY: see `Registry`.
+        unsafe { *self.slot.get() = Some(provider) };
+        self.state.store(INSTALLED, Ordering::Release);
+        Ok(())
+    }
+
+    fn current(&self) -> &'static dyn CryptoProvider {
+        loop {
+            match self.state.compare_exchange(UNSET, DEFAULT, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) | Err(DEFAULT) => return &IntelProvider,
+                // SAFETY: see `Registry`.
+                Err(INSTALLED) => return unsafe { (*self.slot.get()).unwrap() },
+                Err(_) => core::hint::spin_loop(),
+            }
+        }
+    }
+}
+
+static REGISTRY: Registry = Registry::new();
+
+/// Installs `provider` for the whole enclave.
+///
+/// Fails with `SGX_ERROR_INVALID_STATE` if a provider is already installed,
+/// or if the default one is already in use.
+pub fn install(provider: &'static dyn CryptoProvider) -> SgxError {
+    REGISTRY.install(provider)
+}
+
+/// Returns the installed provider or, fixing the choice if none is
+/// installed yet, the [`IntelProvider`].
+pub fn current() -> &'static dyn CryptoProvider {
+    REGISTRY.current()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicUsize;
+
+    /// Delegates to the [`RustProvider`], counting the digests.
+    struct Recorder(AtomicUsize);
+
+    impl CryptoProvider for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn fill_random(&self, buf: &mut [u8]) -> SgxError {
+            RustProvider.fill_random(buf)
+        }
+
+        fn aes128_encrypt_block(&self, key: &[u8; 16], block: &mut [u8; 16]) -> SgxError {
+            RustProvider.aes128_encrypt_block(key, block)
+        }
+
+        fn aes128_gcm_encrypt(
+            &self,
+            key: &[u8; 16],
+            nonce: &[u8; 12],
+            aad: &[u8],
+            src: &[u8],
+            dst: &mut [u8],
+        ) -> SgxResult<Tag> {
+            RustProvider.aes128_gcm_encrypt(key, nonce, aad, src, dst)
+        }
+
+        fn aes128_gcm_decrypt(
+            &self,
+            key: &[u8; 16],
+            nonce: &[u8; 12],
+            aad: &[u8],
+            src: &[u8],
+            tag: &Tag,
+            dst: &mut [u8],
+        ) -> SgxError {
+            RustProvider.aes128_gcm_decrypt(key, nonce, aad, src, tag, dst)
+        }
+
+        fn sha256(&self, data: &[u8]) -> SgxResult<[u8; 32]> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            RustProvider.sha256(data)
+        }
+    }
+
+    // The tests use registries of their own, as the enclave's is shared by
+    // every test drawing random numbers.
+
+    #[test]
+    fn install_switches_backend() {
+        static RECORDER: Recorder = Recorder(AtomicUsize::new(0));
+        let registry = Registry::new();
+        registry.install(&RECORDER).unwrap();
+        let provider = registry.current();
+        assert_eq!(provider.name(), "recorder");
+        let digest = provider.sha256(b"abc").unwrap();
+        assert_eq!(digest, RustProvider.sha256(b"abc").unwrap());
+        assert_eq!(RECORDER.0.load(Ordering::SeqCst), 1);
+        assert_eq!(registry.current().name(), "recorder");
+    }
+
+    #[test]
+    fn installs_once() {
+        static FIRST: Recorder = Recorder(AtomicUsize::new(0));
+        let registry = Registry::new();
+        registry.install(&FIRST).unwrap();
+        assert_eq!(registry.install(&RustProvider), Err(sgx_status_t::SGX_ERROR_INVALID_STATE));
+        assert_eq!(registry.current().name(), "recorder");
+    }
+
+    #[test]
+    fn default_is_fixed_once_used() {
+        static LATE: Recorder = Recorder(AtomicUsize::new(0));
+        let registry = Registry::new();
+        assert_eq!(registry.current().name(), IntelProvider.name());
+        assert_eq!(registry.install(&LATE), Err(sgx_status_t::SGX_ERROR_INVALID_STATE));
+        assert_eq!(registry.current().name(), IntelProvider.name());
+        assert_eq!(LATE.0.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,63 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! The random numbers of the software implementations.
+//!
+//! Keys and nonces are drawn from the crypto [`provider`](crate::provider),
+//! by default `sgx_read_rand`, which reads RDRAND, as [`fill_random`] does
+//! for other crates. With the `test_rng` feature, tests can install another
+//! generator through [`set_provider`], e.g. a seeded one so that a protocol
+//! run produces the same transcript every time. Without the feature nothing
+//! but the crypto provider generates, so the feature must only be enabled
+//! for test builds.
+//!
+//! The functions of the Intel library, such as `rsgx_ecc256_create_key_pair`,
+//! draw their own random numbers and ignore both.
+
+use sgx_types::*;
+
+/// Fills a buffer with random bytes.
+pub type RandProvider = fn(&mut [u8]) -> SgxError;
+
+/// Fills `buf` with random bytes, from the crypto provider unless a test
+/// provider is installed.
+pub fn fill_random(buf: &mut [u8]) -> SgxError {
+    crate::util::read_rand(buf)
+}
+
+#[cfg(feature = "test_rng")]
+static PROVIDER: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+/// Installs `provider` in place of the crypto provider's generator, or
+/// restores that generator, and returns the provider which was installed.
+///
+/// The provider is global to the enclave, so tests installing one mustn't
+/// run concurrently with other tests drawing random numbers.
+#[cfg(feature = "test_rng")]
+pub fn set_provider(provider: Option<RandProvider>) -> Option<RandProvider> {
+    let new = provider.map_or(0, |f| f as usize);
+    let old = PROVIDER.swap(new, core::sync::atomic::Ordering::SeqCst);
+    // SAFETY: the only non-zero values stored are `RandProvider`s.
+    (old != 0).then(|| unsafe { core::mem::transmute::<usize, RandProvider>(old) })
+}
+
+#[cfg(feature = "test_rng")]
+pub(crate) fn provider() -> Option<RandProvider> {
+    let p = PROVIDER.load(core::sync::atomic::Ordering::SeqCst);
+    // SAFETY: as in `set_provider`.
+    (p != 0).then(|| unsafe { core::mem::transmute::<usize, RandProvider>(p) })
+}
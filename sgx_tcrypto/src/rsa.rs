@@ -0,0 +1,677 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! RSASSA-PSS signatures and RSAES-OAEP encryption (RFC 8017) with SHA-256
+//! or SHA-384, over 3072- and 4096-bit keys generated in the enclave.
+//! PKCS #1 v1.5 signatures of other parties, such as certificate
+//! authorities, can be verified too, with 2048-bit keys as well.
+//!
+//! The functions of the Intel library only sign with PKCS #1 v1.5 padding
+//! and fix the key size; these pad in Rust on top of the crate's big
+//! integers. Private key operations use the Chinese remainder theorem in
+//! time independent of the key and the input, check signatures before
+//! returning them, and decrypt without telling padding errors apart. Key
+//! generation and import reduce the primes by small numbers in variable
+//! time, as prime generation must.
+
+use crate::ctbignum::{random_prime, Monty, Uint};
+use crate::hash::Hash;
+use crate::sha256::Sha256;
+use crate::sha512::Sha384;
+use crate::util::{ct_eq, read_rand, zeroize};
+use core::fmt;
+use sgx_types::*;
+
+/// The public exponent of generated keys.
+pub const RSA_PUBLIC_EXPONENT: u32 = 65537;
+
+/// The largest modulus, in bytes, sizing the buffers below.
+const MAX_SIZE: usize = 512;
+/// The largest output of the hash functions.
+const MAX_HASH_LEN: usize = 48;
+
+pub type Rsa2048PublicKey = RsaPublicKey<32>;
+pub type Rsa3072PublicKey = RsaPublicKey<48>;
+pub type Rsa3072PrivateKey = RsaPrivateKey<48, 24>;
+pub type Rsa4096PublicKey = RsaPublicKey<64>;
+pub type Rsa4096PrivateKey = RsaPrivateKey<64, 32>;
+
+/// The hash function of a signature or encryption, also used by MGF1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RsaHash {
+    Sha256,
+    Sha384,
+}
+
+impl RsaHash {
+    /// The DER of a PKCS #1 v1.5 `DigestInfo` up to the digest.
+    fn digest_info(self) -> &'static [u8] {
+        match self {
+            RsaHash::Sha256 => &[
+                0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+                0x01, 0x05, 0x00, 0x04, 0x20,
+            ],
+            RsaHash::Sha384 => &[
+                0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+                0x02, 0x05, 0x00, 0x04, 0x30,
+            ],
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            RsaHash::Sha256 => Sha256::OUTPUT_LEN,
+            RsaHash::Sha384 => Sha384::OUTPUT_LEN,
+        }
+    }
+
+    /// Writes the first `out.len()` bytes of the hash of the concatenation
+    /// of `parts`.
+    fn digest(self, parts: &[&[u8]], out: &mut [u8]) {
+        match self {
+            RsaHash::Sha256 => digest::<Sha256>(parts, out),
+            RsaHash::Sha384 => digest::<Sha384>(parts, out),
+        }
+    }
+
+    /// XORs `out` with MGF1 of `seed`.
+    fn mask(self, seed: &[u8], out: &mut [u8]) {
+        let len = self.len();
+        let mut block = [0_u8; MAX_HASH_LEN];
+        for (i, chunk) in out.chunks_mut(len).enumerate() {
+            self.digest(&[seed, &(i as u32).to_be_bytes()], &mut block[..len]);
+            for (o, b) in chunk.iter_mut().zip(block.iter()) {
+                *o ^= b;
+            }
+        }
+        zeroize(&mut block);
+    }
+}
+
+fn digest<D: Hash>(parts: &[&[u8]], out: &mut [u8]) {
+    let mut hasher = D::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize_into(out);
+}
+
+/// Returns 1 if `x` is zero, and 0 otherwise, without branching.
+fn is_zero(x: u8) -> u8 {
+    ((x as u32).wrapping_sub(1) >> 31) as u8
+}
+
+/// Returns `a^-1 mod m` for a public `m`, or `None` if they aren't coprime.
+fn inv_mod_u64(a: u64, m: u64) -> Option<u64> {
+    let (mut r0, mut r1) = (m as i128, a as i128);
+    let (mut t0, mut t1) = (0_i128, 1_i128);
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (t0, t1) = (t1, t0 - q * t1);
+    }
+    if r0 != 1 {
+        return None;
+    }
+    Some(t0.rem_euclid(m as i128) as u64)
+}
+
+/// An RSA public key with a modulus of exactly `64 L` bits.
+#[derive(Clone, Copy)]
+pub struct RsaPublicKey<const L: usize> {
+    n: Uint<L>,
+    e: u32,
+    mod_n: Monty<L>,
+}
+
+impl<const L: usize> RsaPublicKey<L> {
+    /// The length of the modulus, signatures and ciphertexts, in bytes.
+    pub const SIZE: usize = 8 * L;
+
+    /// Builds a key from its big-endian modulus of `SIZE` bytes and its
+    /// exponent, failing with `SGX_ERROR_INVALID_PARAMETER` unless the
+    /// modulus has its top bit set and both are odd, the exponent above 1.
+    pub fn new(modulus: &[u8], exponent: u32) -> SgxResult<RsaPublicKey<L>> {
+        if 8 * L > MAX_SIZE || modulus.len() != Self::SIZE || exponent < 3 || exponent & 1 == 0 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let n = Uint::from_be_bytes(modulus).ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+        if n.bits_vartime() != 64 * L {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mod_n = Monty::new(&n).ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+        Ok(RsaPublicKey { n, e: exponent, mod_n })
+    }
+
+    /// Writes the big-endian modulus into `out`, of `SIZE` bytes.
+    pub fn write_modulus(&self, out: &mut [u8]) -> SgxError {
+        if out.len() != Self::SIZE {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        self.n.write_be_bytes(out);
+        Ok(())
+    }
+
+    pub fn exponent(&self) -> u32 {
+        self.e
+    }
+
+    /// Returns `x^e mod n`.
+    fn apply(&self, x: &Uint<L>) -> Uint<L> {
+        self.mod_n.pow_vartime(x, &Uint::<1>::from_u64(self.e as u64))
+    }
+
+    /// Parses a signature or ciphertext, which must be below `n`.
+    fn parse(&self, bytes: &[u8]) -> Option<Uint<L>> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        Uint::from_be_bytes(bytes).filter(|x| x.lt(&self.n))
+    }
+
+    /// Verifies an RSASSA-PSS signature of `message` with MGF1 over the same
+    /// hash and a salt as long as the hash.
+    pub fn verify_pss(&self, hash: RsaHash, message: &[u8], signature: &[u8]) -> bool {
+        let s = match self.parse(signature) {
+            Some(s) => s,
+            None => return false,
+        };
+        let mut em = [0_u8; MAX_SIZE];
+        let em = &mut em[..Self::SIZE];
+        self.apply(&s).write_be_bytes(em);
+
+        // The encoded message has one bit less than the modulus.
+        let h_len = hash.len();
+        let db_len = Self::SIZE - h_len - 1;
+        if em[Self::SIZE - 1] != 0xbc || em[0] & 0x80 != 0 {
+            return false;
+        }
+        let (db, rest) = em.split_at_mut(db_len);
+        let h = &rest[..h_len];
+        hash.mask(h, db);
+        db[0] &= 0x7f;
+        let salt_start = db_len - h_len;
+        if db[..salt_start - 1].iter().any(|b| *b != 0) || db[salt_start - 1] != 1 {
+            return false;
+        }
+
+        let mut m_hash = [0_u8; MAX_HASH_LEN];
+        hash.digest(&[message], &mut m_hash[..h_len]);
+        let mut expected = [0_u8; MAX_HASH_LEN];
+        hash.digest(&[&[0; 8], &m_hash[..h_len], &db[salt_start..]], &mut expected[..h_len]);
+        ct_eq(&expected[..h_len], h)
+    }
+
+    /// Verifies an RSASSA-PKCS1-v1_5 signature of `message`.
+    pub fn verify_pkcs1v15(&self, hash: RsaHash, message: &[u8], signature: &[u8]) -> bool {
+        let s = match self.parse(signature) {
+            Some(s) => s,
+            None => return false,
+        };
+        let mut em = [0_u8; MAX_SIZE];
+        let em = &mut em[..Self::SIZE];
+        self.apply(&s).write_be_bytes(em);
+
+        // 00 01 FF .. FF 00 DigestInfo, the signature being public.
+        let info = hash.digest_info();
+        let t_len = info.len() + hash.len();
+        let ps_end = Self::SIZE - t_len - 1;
+        if em[0] != 0 || em[1] != 1 || em[2..ps_end].iter().any(|b| *b != 0xff) || em[ps_end] != 0 {
+            return false;
+        }
+        let (prefix, digest) = em[ps_end + 1..].split_at(info.len());
+        let mut expected = [0_u8; MAX_HASH_LEN];
+        hash.digest(&[message], &mut expected[..hash.len()]);
+        prefix == info && ct_eq(&expected[..hash.len()], digest)
+    }
+
+    /// Encrypts `message` with RSAES-OAEP and MGF1 over the same hash,
+    /// writing `SIZE` bytes into `ciphertext`.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if `message` is longer than
+    /// `SIZE - 2 * hash length - 2` bytes.
+    pub fn encrypt_oaep(
+        &self,
+        hash: RsaHash,
+        label: &[u8],
+        message: &[u8],
+        ciphertext: &mut [u8],
+    ) -> SgxError {
+        let h_len = hash.len();
+        if ciphertext.len() != Self::SIZE || message.len() > Self::SIZE - 2 * h_len - 2 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut em = [0_u8; MAX_SIZE];
+        let em = &mut em[..Self::SIZE];
+        let (seed, db) = em[1..].split_at_mut(h_len);
+        hash.digest(&[label], &mut db[..h_len]);
+        let m_start = db.len() - message.len();
+        db[m_start - 1] = 1;
+        db[m_start..].copy_from_slice(message);
+        read_rand(seed)?;
+        hash.mask(seed, db);
+        hash.mask(db, seed);
+
+        // em starts with a zero byte, so it is below n.
+        let m = Uint::<L>::from_be_bytes(em).ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)?;
+        zeroize(em);
+        self.apply(&m).write_be_bytes(ciphertext);
+        Ok(())
+    }
+}
+
+impl<const L: usize> PartialEq for RsaPublicKey<L> {
+    fn eq(&self, other: &RsaPublicKey<L>) -> bool {
+        self.n == other.n && self.e == other.e
+    }
+}
+
+impl<const L: usize> Eq for RsaPublicKey<L> {}
+
+impl<const L: usize> fmt::Debug for RsaPublicKey<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut n = [0_u8; MAX_SIZE];
+        self.n.write_be_bytes(&mut n[..Self::SIZE]);
+        write!(f, "RsaPublicKey {{ e: {}, n: ", self.e)?;
+        for b in n[..Self::SIZE].iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        f.write_str(" }")
+    }
+}
+
+/// An RSA private key with a modulus of `64 L` bits, the product of two
+/// primes of `64 H` bits each, so `L = 2 H`.
+pub struct RsaPrivateKey<const L: usize, const H: usize> {
+    public: RsaPublicKey<L>,
+    p: Uint<H>,
+    q: Uint<H>,
+    mod_p: Monty<H>,
+    mod_q: Monty<H>,
+    /// `e^-1 mod p - 1` and `e^-1 mod q - 1`.
+    dp: Uint<H>,
+    dq: Uint<H>,
+    /// `q^-1 mod p`.
+    q_inv: Uint<H>,
+}
+
+/// Returns `e^-1 mod p - 1`, as `(1 + (p - 1) y) / e` for the `y` which
+/// makes it an integer.
+fn crt_exponent<const H: usize, const L: usize>(p: &Uint<H>, e: u32) -> Option<Uint<H>> {
+    let p_1 = p.wrapping_sub(&Uint::from_u64(1));
+    let r = p_1.rem_u32_vartime(e);
+    let y = inv_mod_u64((e - r) as u64 % e as u64, e as u64)?;
+    let mut x = p_1.resize::<L>().wrapping_mul(&Uint::from_u64(y));
+    x.adc(&Uint::from_u64(1));
+    let mut x = x.div_exact(&Uint::from_u64(e as u64));
+    let dp = x.resize();
+    x.wipe();
+    Some(dp)
+}
+
+impl<const L: usize, const H: usize> RsaPrivateKey<L, H> {
+    /// The length of [`RsaPrivateKey::to_bytes`], in bytes.
+    pub const ENCODED_SIZE: usize = 8 * L + 4;
+
+    /// Generates a key with the exponent [`RSA_PUBLIC_EXPONENT`].
+    pub fn generate() -> SgxResult<RsaPrivateKey<L, H>> {
+        if 2 * H != L {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        loop {
+            let mut p = random_prime::<H>()?;
+            let mut q = random_prime::<H>()?;
+            let key = RsaPrivateKey::from_primes(&p, &q, RSA_PUBLIC_EXPONENT);
+            p.wipe();
+            q.wipe();
+            // Primes with p - 1 or q - 1 divisible by e are skipped.
+            if let Ok(key) = key {
+                return Ok(key);
+            }
+        }
+    }
+
+    fn from_primes(p: &Uint<H>, q: &Uint<H>, e: u32) -> SgxResult<RsaPrivateKey<L, H>> {
+        if 2 * H != L || p == q {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut modulus = [0_u8; MAX_SIZE];
+        let modulus = &mut modulus[..8 * L];
+        p.resize::<L>().wrapping_mul(&q.resize()).write_be_bytes(modulus);
+        let public = RsaPublicKey::new(modulus, e)?;
+        let mod_p = Monty::new(p).ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+        let mod_q = Monty::new(q).ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+        let mut key = RsaPrivateKey {
+            public,
+            p: *p,
+            q: *q,
+            mod_p,
+            mod_q,
+            dp: Uint::ZERO,
+            dq: Uint::ZERO,
+            q_inv: Uint::ZERO,
+        };
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        key.dp = crt_exponent::<H, L>(p, e).ok_or(invalid)?;
+        key.dq = crt_exponent::<H, L>(q, e).ok_or(invalid)?;
+        key.q_inv = mod_p.reduce(q).inv_mod_odd(p).ok_or(invalid)?;
+
+        // Catches factors which aren't prime, for which the CRT exponents
+        // don't invert e.
+        let x = Uint::random_below(&key.public.n)?;
+        if key.public.apply(&key.apply(&x)) != x {
+            return Err(invalid);
+        }
+        Ok(key)
+    }
+
+    /// Parses a key written by [`RsaPrivateKey::to_bytes`], failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` unless it is consistent.
+    pub fn from_bytes(bytes: &[u8]) -> SgxResult<RsaPrivateKey<L, H>> {
+        if bytes.len() != Self::ENCODED_SIZE {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        let mut p = Uint::from_be_bytes(&bytes[..4 * L]).ok_or(invalid)?;
+        let mut q = Uint::from_be_bytes(&bytes[4 * L..8 * L]).ok_or(invalid)?;
+        let mut e = [0_u8; 4];
+        e.copy_from_slice(&bytes[8 * L..]);
+        let key = RsaPrivateKey::from_primes(&p, &q, u32::from_be_bytes(e));
+        p.wipe();
+        q.wipe();
+        key
+    }
+
+    /// Writes the big-endian primes and the exponent, `ENCODED_SIZE` bytes,
+    /// e.g. to seal them.
+    pub fn to_bytes(&self, out: &mut [u8]) -> SgxError {
+        if out.len() != Self::ENCODED_SIZE {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        self.p.write_be_bytes(&mut out[..4 * L]);
+        self.q.write_be_bytes(&mut out[4 * L..8 * L]);
+        out[8 * L..].copy_from_slice(&self.public.e.to_be_bytes());
+        Ok(())
+    }
+
+    pub fn public_key(&self) -> RsaPublicKey<L> {
+        self.public
+    }
+
+    /// Returns `x^d mod n`, for `x < n`.
+    fn apply(&self, x: &Uint<L>) -> Uint<L> {
+        let mut lo = Uint::<H>::ZERO;
+        let mut hi = Uint::<H>::ZERO;
+        lo.0.copy_from_slice(&x.0[..H]);
+        hi.0.copy_from_slice(&x.0[H..]);
+        // x mod p is hi 2^(64 H) + lo, and encoding multiplies by 2^(64 H).
+        let xp = self.mod_p.add(&self.mod_p.encode(&hi), &self.mod_p.reduce(&lo));
+        let xq = self.mod_q.add(&self.mod_q.encode(&hi), &self.mod_q.reduce(&lo));
+        let mut m1 = self.mod_p.pow(&xp, &self.dp, 64 * H);
+        let mut m2 = self.mod_q.pow(&xq, &self.dq, 64 * H);
+        let mut h = self.mod_p.sub(&m1, &self.mod_p.reduce(&m2));
+        h = self.mod_p.mul_mod(&h, &self.q_inv);
+        let m = m2.resize::<L>().wrapping_add(&h.resize::<L>().wrapping_mul(&self.q.resize()));
+        for secret in [&mut lo, &mut hi, &mut m1, &mut m2, &mut h] {
+            secret.wipe();
+        }
+        m
+    }
+
+    /// Signs `message` with RSASSA-PSS and MGF1 over the same hash and a
+    /// random salt as long as the hash, writing `SIZE` bytes into
+    /// `signature`.
+    pub fn sign_pss(&self, hash: RsaHash, message: &[u8], signature: &mut [u8]) -> SgxError {
+        let size = RsaPublicKey::<L>::SIZE;
+        if signature.len() != size {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let h_len = hash.len();
+        let db_len = size - h_len - 1;
+        let mut em = [0_u8; MAX_SIZE];
+        let em = &mut em[..size];
+        let (db, rest) = em.split_at_mut(db_len);
+        let salt_start = db_len - h_len;
+        db[salt_start - 1] = 1;
+        read_rand(&mut db[salt_start..])?;
+        let mut m_hash = [0_u8; MAX_HASH_LEN];
+        hash.digest(&[message], &mut m_hash[..h_len]);
+        let h = &mut rest[..h_len];
+        hash.digest(&[&[0; 8], &m_hash[..h_len], &db[salt_start..]], h);
+        hash.mask(h, db);
+        db[0] &= 0x7f;
+        rest[h_len] = 0xbc;
+
+        let m = Uint::<L>::from_be_bytes(em).ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)?;
+        let s = self.apply(&m);
+        // A fault in the CRT would reveal a factor through the signature.
+        if self.public.apply(&s) != m {
+            return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+        }
+        s.write_be_bytes(signature);
+        Ok(())
+    }
+
+    /// Decrypts an RSAES-OAEP `ciphertext` into `message`, returning the
+    /// length of the plaintext.
+    ///
+    /// `message` must hold `SIZE - 2 * hash length - 2` bytes, the longest
+    /// plaintext. Fails with `SGX_ERROR_MAC_MISMATCH` for any invalid
+    /// ciphertext, after the same work.
+    pub fn decrypt_oaep(
+        &self,
+        hash: RsaHash,
+        label: &[u8],
+        ciphertext: &[u8],
+        message: &mut [u8],
+    ) -> SgxResult<usize> {
+        let size = RsaPublicKey::<L>::SIZE;
+        let h_len = hash.len();
+        if message.len() < size - 2 * h_len - 2 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let c = self.public.parse(ciphertext).ok_or(sgx_status_t::SGX_ERROR_MAC_MISMATCH)?;
+        let mut em = [0_u8; MAX_SIZE];
+        let em = &mut em[..size];
+        let mut m = self.apply(&c);
+        m.write_be_bytes(em);
+        m.wipe();
+
+        let mut good = is_zero(em[0]);
+        let (seed, db) = em[1..].split_at_mut(h_len);
+        hash.mask(db, seed);
+        hash.mask(seed, db);
+        let mut l_hash = [0_u8; MAX_HASH_LEN];
+        hash.digest(&[label], &mut l_hash[..h_len]);
+        good &= ct_eq(&l_hash[..h_len], &db[..h_len]) as u8;
+
+        // Finds the 1 after the zero padding without branching on the bytes.
+        let mut looking = 1_u8;
+        let mut bad_padding = 0_u8;
+        let mut start = 0_usize;
+        for (i, b) in db[h_len..].iter().enumerate() {
+            let one = is_zero(*b ^ 1);
+            let zero = is_zero(*b);
+            start |= i & 0_usize.wrapping_sub((looking & one) as usize);
+            bad_padding |= looking & ((zero | one) ^ 1);
+            looking &= one ^ 1;
+        }
+        good &= (looking | bad_padding) ^ 1;
+        let result = if good == 1 {
+            let plaintext = &db[h_len + start + 1..];
+            message[..plaintext.len()].copy_from_slice(plaintext);
+            Ok(plaintext.len())
+        } else {
+            Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH)
+        };
+        zeroize(em);
+        result
+    }
+}
+
+impl<const L: usize, const H: usize> Drop for RsaPrivateKey<L, H> {
+    fn drop(&mut self) {
+        for secret in [&mut self.p, &mut self.q, &mut self.dp, &mut self.dq, &mut self.q_inv] {
+            secret.wipe();
+        }
+        self.mod_p.wipe();
+        self.mod_q.wipe();
+    }
+}
+
+impl<const L: usize, const H: usize> fmt::Debug for RsaPrivateKey<L, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RsaPrivateKey").field("public_key", &self.public).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+    use std::vec;
+    use std::vec::Vec;
+
+    /// The primes of a 3072-bit key, and the signatures and ciphertext of
+    /// `MESSAGE` made with it by OpenSSL.
+        const P: &str = "d73bc156fe8e9ad6a2ad5b8dd74929013087f30a60a5e4301a41f4236cd1740eaa5ae7a453589414463cebf67a2e1dfc\
+        466d8dcf967eb1c0098470f30555eefd53d61b885a7660824f6fdc289fada79971d6b06daef1a1761a7facf9d12880ba\
+        26344db96c8295821b1e513080670eb5a3ba5b811f5a57bc3630b7f00dd18dbbfb3d74708b0bd04f15f373310254a6ee\
+        e38e513ce690d519a7871689a6e2cf5f5ff16e69d3009e5cd6908570aa1fb9b3cc7b8b55b69a58c4c21ac7765c7ae847";
+        const Q: &str = "c518d1b775b4e80c8f9ce1139ce3921e33cfdc0e94c8ee8a58dc9f9bec05845331ff857f09568acb0efab1ed571e51b3\
+        aa32cb7e044683826021e078427666d8e7c6ffad58ce597101e87361b324b124272448e8339fa04dcab8e6404eb8b0f3\
+        f111c7dbb53d4e2083eb08d5349e98fb574f26a907974236a864e1e39e1e1f32386b749d9cb3c9f37c35b3089920486e\
+        86b63a7db6375a80775d1e74fed6c89f283d65150dc0990f4f425d469aee01d872b5c370c85dd1c9a648d18e58496ec3";
+        const PSS_SHA384: &str = "4f978208cc1e092bb292608b4cd7f811f02cd926d34864dc93208c5eb7f1b1802b529cb8323cbc7b6f958e63a4d9a0d9\
+        839d29528311788f69b96e7fd3c630b87d2d360b9fb5031192342e7d5cd7ec33bdd8ae48ccf601098ce54b8a1220ab59\
+        1a23b2ee75edbeab2f92b2db3eb0d6a98b8ccfdb25218a46f5d29800674557c79527a53712830346140eb80638449783\
+        1386b71405d0620fcf8d0eaeba64ab4352c8cdee063d17ec5bf411a060298ad9a90f5cf251075852a5a864321bcb0240\
+        3c1fc2a5e8750853d814ab0f1a8749fcf21d79d99682c5fab3b9055034d5a1bcfaaa9e0af051c2ddc34a93eade0fd983\
+        709f8cab51b38643e381eae1116fdb275820fab84e40cffb547dcf9a7b57c305a85321432ff1d10236d54c316221d9ad\
+        36f6d3f66aef82b474c1e1cc68e7a53cedd7d4e8193a19b28419302bddc5b7d9f23f5d4233e44c37e1e3e0a1e7685e53\
+        32e13b8db1605b7fe7bdd2d2f8387152e90db9c7fd47ab4fcc9729fa80cbc2a19d21cb203a15f0d4cfbdb11e563e67d4";
+        const OAEP_SHA384: &str = "a3f8e66e7922a6806c2f76dd45d349b6abfd78779499f1f2c73b33cc51f58227e6698da75ea09a92cd533e2e7f340a6f\
+        f6290a585dc7d6a2bbc031a3b6887aabff4a32365774ba18275b95f4c94a51d5fde18e19c1e2d93aa48dd14053acb7da\
+        428991b9534b82bd8d1c1fba896c97055c18f682c90d590d824e6be7d2593861bd5165382f75e6573e1682d128fff897\
+        42e72f813a0e823ba962132882e90230dde60f6c35841c0a44cd241be22dac499d2e6135028254754fe6491c143411d6\
+        532871bc50810acd27b394eab928595e0e7e153f09238afd563e36b475626f5f8f0040d5d2c2408447ec49703a60b400\
+        ed7399577729b9d491d139767523f2b4d0214b13c6fadc9fad884052f2d7ba85d8bd870a539ee1f8082b46dc2cc615ee\
+        bb86b21d9e4831a6e7bfa5768bd29197c086bde111988f0d1e7e535569b173afc0fc9a1dd3f5f116a9ae9c8ea43e4080\
+        1a7b38310f29fd017becdab599606eb145ee62c9aef134bb07ec5a4ad903e88f9497b87843f363fddb7514c5e6b49fc1";
+        const PKCS1V15_SHA256: &str = "48e3f895a3a571cbf141d2e625d571edfd3dc3327a247d08c51f97a5c4eb5579e3424bd6c95e2afbe3df7582b5383200\
+        83f14c983b460ef2f494a074fe8bda39ec117772aed5c782e0c64b81f61f3234445d9151f2db335e6829d746b6d4f2c2\
+        b3b0c167e32fd16ca88558114e5be0635600bd37a14a1e52c86c5b581fb17522379a3a3f1a32556027270c97865c7a2b\
+        9dad529a92a4413ae93dc8669363c285ddaf69698643291a4e35094ed900385303d01d24f68b16d5140d80bf87f0142b\
+        ee7f78fbee1fe2d708c1f80727dde0c83f0e56d1bdb6f9df889d652a8b2e7eaa55a604089f54c4e147e0d7fc599df3d4\
+        6558793de6b256718249e4b326e0ff3c336b0ec481aa40681925425105aa8adf54832174d35f7e9436a57b948fc2678b\
+        22aea7f912e8eb4af0f504d8f3acfd33ce3e0e13451d6ad48634b904fb1164097bc198b87378e1f867e4e602405ffcb5\
+        5536ea473d507a42dc912019e8e038b45776b760b53c7fe770016f9931333d687938fda7edc5544396d7912ecd60aa95";
+    const MESSAGE: &[u8] = b"pki message";
+    const LABEL: &[u8] = b"l";
+
+    fn key() -> Rsa3072PrivateKey {
+        let mut bytes = hex(P);
+        bytes.extend(hex(Q));
+        bytes.extend(RSA_PUBLIC_EXPONENT.to_be_bytes());
+        Rsa3072PrivateKey::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn known_answer() {
+        let key = key();
+        let public = key.public_key();
+        assert!(public.verify_pss(RsaHash::Sha384, MESSAGE, &hex(PSS_SHA384)));
+        assert!(public.verify_pkcs1v15(RsaHash::Sha256, MESSAGE, &hex(PKCS1V15_SHA256)));
+        let mut message = [0_u8; Rsa3072PublicKey::SIZE];
+        let len = key.decrypt_oaep(RsaHash::Sha384, LABEL, &hex(OAEP_SHA384), &mut message).unwrap();
+        assert_eq!(&message[..len], MESSAGE);
+
+        let mut encoded = vec![0_u8; Rsa3072PrivateKey::ENCODED_SIZE];
+        key.to_bytes(&mut encoded).unwrap();
+        assert_eq!(Rsa3072PrivateKey::from_bytes(&encoded).unwrap().public_key(), public);
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = key();
+        let public = key.public_key();
+        for hash in [RsaHash::Sha256, RsaHash::Sha384] {
+            let mut signature = [0_u8; Rsa3072PublicKey::SIZE];
+            key.sign_pss(hash, MESSAGE, &mut signature).unwrap();
+            assert!(public.verify_pss(hash, MESSAGE, &signature));
+
+            let longest = vec![0x5a; Rsa3072PublicKey::SIZE - 2 * hash.len() - 2];
+            for message in [&[][..], MESSAGE, &longest] {
+                let mut ciphertext = [0_u8; Rsa3072PublicKey::SIZE];
+                public.encrypt_oaep(hash, LABEL, message, &mut ciphertext).unwrap();
+                let mut decrypted = [0_u8; Rsa3072PublicKey::SIZE];
+                let len = key.decrypt_oaep(hash, LABEL, &ciphertext, &mut decrypted).unwrap();
+                assert_eq!(&decrypted[..len], message);
+            }
+            let too_long = vec![0x5a; longest.len() + 1];
+            let mut ciphertext = [0_u8; Rsa3072PublicKey::SIZE];
+            assert!(public.encrypt_oaep(hash, LABEL, &too_long, &mut ciphertext).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_forgeries() {
+        let key = key();
+        let public = key.public_key();
+        let pss = hex(PSS_SHA384);
+        assert!(!public.verify_pss(RsaHash::Sha384, b"other", &pss));
+        assert!(!public.verify_pss(RsaHash::Sha256, MESSAGE, &pss));
+        let mut bad = pss.clone();
+        bad[100] ^= 1;
+        assert!(!public.verify_pss(RsaHash::Sha384, MESSAGE, &bad));
+        assert!(!public.verify_pss(RsaHash::Sha384, MESSAGE, &pss[1..]));
+        // A signature at least the modulus isn't reduced.
+        let mut modulus = [0_u8; Rsa3072PublicKey::SIZE];
+        public.write_modulus(&mut modulus).unwrap();
+        assert!(!public.verify_pss(RsaHash::Sha384, MESSAGE, &modulus));
+        assert!(!public.verify_pkcs1v15(RsaHash::Sha384, MESSAGE, &hex(PKCS1V15_SHA256)));
+
+        let oaep = hex(OAEP_SHA384);
+        let mut message = [0_u8; Rsa3072PublicKey::SIZE];
+        assert_eq!(
+            key.decrypt_oaep(RsaHash::Sha384, b"wrong", &oaep, &mut message),
+            Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH)
+        );
+        let mut bad = oaep.clone();
+        bad[10] ^= 1;
+        assert!(key.decrypt_oaep(RsaHash::Sha384, LABEL, &bad, &mut message).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let p = hex(P);
+        let same: Vec<u8> = [&p[..], &p, &RSA_PUBLIC_EXPONENT.to_be_bytes()].concat();
+        assert!(Rsa3072PrivateKey::from_bytes(&same).is_err());
+        // p - 2 isn't prime.
+        let mut composite: Vec<u8> = [&p[..], &hex(Q), &RSA_PUBLIC_EXPONENT.to_be_bytes()].concat();
+        composite[p.len() - 1] ^= 2;
+        assert!(Rsa3072PrivateKey::from_bytes(&composite).is_err());
+        assert!(Rsa3072PrivateKey::from_bytes(&same[1..]).is_err());
+
+        let mut modulus = [0_u8; Rsa3072PublicKey::SIZE];
+        key().public_key().write_modulus(&mut modulus).unwrap();
+        assert!(Rsa3072PublicKey::new(&modulus, 65536).is_err());
+        assert!(Rsa3072PublicKey::new(&modulus[1..], RSA_PUBLIC_EXPONENT).is_err());
+        modulus[0] = 0x7f;
+        assert!(Rsa3072PublicKey::new(&modulus, RSA_PUBLIC_EXPONENT).is_err());
+    }
+}
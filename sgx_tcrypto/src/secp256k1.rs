@@ -0,0 +1,911 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! ECDSA over secp256k1, with RFC 6979 deterministic nonces and public key
+//! recovery.
+//!
+//! Nonces are derived inside the enclave from the private key and the
+//! message hash, so signing needs no randomness and can't leak the key
+//! through a bad generator. Signing is constant time in the key and the
+//! nonce; verification and recovery only handle public values.
+
+use crate::field256::{
+    from_be_bytes, is_zero, select, sub_words, to_be_bytes, wipe, Modulus, Words,
+};
+use crate::hash::{Hash, Keccak256};
+use crate::hmac::HmacSha256;
+use crate::msm::{straus, wnaf, Group, Table};
+use crate::secret::{Secret, SecretBytes};
+use crate::util::{ct_eq, read_rand, zeroize};
+use core::fmt;
+use sgx_types::*;
+
+/// The length of secp256k1 private keys and message hashes, in bytes.
+pub const SECP256K1_KEY_SIZE: usize = 32;
+
+const P: Modulus = Modulus {
+    m: [0xffff_fffe_ffff_fc2f, 0xffff_ffff_ffff_ffff, 0xffff_ffff_ffff_ffff, 0xffff_ffff_ffff_ffff],
+    inv: 0xd838_091d_d225_3531,
+    r2: [0x0000_07a2_000e_90a1, 1, 0, 0],
+};
+
+const N: Modulus = Modulus {
+    m: [0xbfd2_5e8c_d036_4141, 0xbaae_dce6_af48_a03b, 0xffff_ffff_ffff_fffe, 0xffff_ffff_ffff_ffff],
+    inv: 0x4b0d_ff66_5588_b13f,
+    r2: [
+        0x896c_f214_67d7_d140,
+        0x7414_96c2_0e7c_f878,
+        0xe697_f5e4_5bcd_07c6,
+        0x9d67_1cd5_81c6_9bc5,
+    ],
+};
+
+const GX: Words =
+    [0x59f2_815b_16f8_1798, 0x029b_fcdb_2dce_28d9, 0x55a0_6295_ce87_0b07, 0x79be_667e_f9dc_bbac];
+const GY: Words =
+    [0x9c47_d08f_fb10_d4b8, 0xfd17_b448_a685_5419, 0x5da4_fbfc_0e11_08a8, 0x483a_da77_26a3_c465];
+
+/// A point in projective coordinates, `x = X/Z`, `y = Y/Z`, with the
+/// coordinates in the Montgomery domain.
+#[derive(Clone, Copy)]
+pub(crate) struct Point {
+    x: Words,
+    y: Words,
+    z: Words,
+}
+
+impl Point {
+    pub(crate) fn identity() -> Point {
+        Point { x: [0; 4], y: P.one(), z: [0; 4] }
+    }
+
+    pub(crate) fn generator() -> Point {
+        Point { x: P.encode(&GX), y: P.encode(&GY), z: P.one() }
+    }
+
+    /// Adds with the complete formulas of Renes, Costello and Batina for
+    /// `a = 0`, which have no exceptional cases and also double.
+    pub(crate) fn add(&self, other: &Point) -> Point {
+        let b3 = P.encode(&[21, 0, 0, 0]);
+        let t0 = P.mul(&self.x, &other.x);
+        let t1 = P.mul(&self.y, &other.y);
+        let t2 = P.mul(&self.z, &other.z);
+        let t3 = P.mul(&P.add(&self.x, &self.y), &P.add(&other.x, &other.y));
+        let t3 = P.sub(&t3, &P.add(&t0, &t1));
+        let t4 = P.mul(&P.add(&self.y, &self.z), &P.add(&other.y, &other.z));
+        let t4 = P.sub(&t4, &P.add(&t1, &t2));
+        let y3 = P.mul(&P.add(&self.x, &self.z), &P.add(&other.x, &other.z));
+        let y3 = P.sub(&y3, &P.add(&t0, &t2));
+        let t0 = P.add(&P.add(&t0, &t0), &t0);
+        let t2 = P.mul(&b3, &t2);
+        let z3 = P.add(&t1, &t2);
+        let t1 = P.sub(&t1, &t2);
+        let y3 = P.mul(&b3, &y3);
+        let x3 = P.sub(&P.mul(&t3, &t1), &P.mul(&t4, &y3));
+        let y3 = P.add(&P.mul(&t1, &z3), &P.mul(&y3, &t0));
+        let z3 = P.add(&P.mul(&z3, &t4), &P.mul(&t0, &t3));
+        Point { x: x3, y: y3, z: z3 }
+    }
+
+    /// Doubles with the dedicated formulas of the same paper for `a = 0`.
+    pub(crate) fn double(&self) -> Point {
+        let b3 = P.encode(&[21, 0, 0, 0]);
+        let t0 = P.square(&self.y);
+        let z3 = P.add(&t0, &t0);
+        let z3 = P.add(&z3, &z3);
+        let z3 = P.add(&z3, &z3);
+        let t1 = P.mul(&self.y, &self.z);
+        let t2 = P.mul(&b3, &P.square(&self.z));
+        let x3 = P.mul(&t2, &z3);
+        let y3 = P.add(&t0, &t2);
+        let z3 = P.mul(&t1, &z3);
+        let t2 = P.add(&P.add(&t2, &t2), &t2);
+        let t0 = P.sub(&t0, &t2);
+        let y3 = P.add(&x3, &P.mul(&t0, &y3));
+        let x3 = P.mul(&t0, &P.mul(&self.x, &self.y));
+        Point { x: P.add(&x3, &x3), y: y3, z: z3 }
+    }
+
+    fn neg(&self) -> Point {
+        Point { x: self.x, y: P.neg(&self.y), z: self.z }
+    }
+
+    /// Returns whether the affine `x` of the point is `r` modulo `n`, for
+    /// `r` in `[1, n)`, comparing `X` to `r Z`, and to `(r + n) Z` if that
+    /// is below `p`, rather than inverting `Z`.
+    fn x_matches(&self, r: &Words) -> bool {
+        if is_zero(&self.z) {
+            return false;
+        }
+        if P.mul(&P.encode(r), &self.z) == self.x {
+            return true;
+        }
+        let (bound, _) = sub_words(&P.m, &N.m);
+        // r and n are below p, so the sum is reduced if it is below p.
+        sub_words(r, &bound).1 == 1 && P.mul(&P.encode(&P.add(r, &N.m)), &self.z) == self.x
+    }
+
+    fn cmov(&mut self, other: &Point, choice: u64) {
+        self.x = select(&self.x, &other.x, choice);
+        self.y = select(&self.y, &other.y, choice);
+        self.z = select(&self.z, &other.z, choice);
+    }
+
+    /// Computes `scalar * self`, doubling and adding for every bit.
+    pub(crate) fn mul(&self, scalar: &Words) -> Point {
+        let mut acc = Point::identity();
+        for i in (0..256).rev() {
+            acc = acc.add(&acc);
+            let sum = acc.add(self);
+            acc.cmov(&sum, (scalar[i / 64] >> (i % 64)) & 1);
+        }
+        acc
+    }
+
+    /// Returns the affine coordinates, outside the Montgomery domain, or
+    /// `None` for the identity.
+    pub(crate) fn to_affine(self) -> Option<(Words, Words)> {
+        if is_zero(&self.z) {
+            return None;
+        }
+        let zinv = P.invert(&self.z);
+        Some((P.decode(&P.mul(&self.x, &zinv)), P.decode(&P.mul(&self.y, &zinv))))
+    }
+
+    /// Returns the point with affine coordinates `(x, y)`, if it is on the
+    /// curve.
+    fn from_affine(x: &Words, y: &Words) -> Option<Point> {
+        if !P.contains(x) || !P.contains(y) {
+            return None;
+        }
+        let (x, y) = (P.encode(x), P.encode(y));
+        let rhs = P.add(&P.mul(&P.square(&x), &x), &P.encode(&[7, 0, 0, 0]));
+        if P.square(&y) != rhs {
+            return None;
+        }
+        Some(Point { x, y, z: P.one() })
+    }
+
+    /// Returns the point with abscissa `x` and the given parity of `y`.
+    fn decompress(x: &Words, odd: bool) -> Option<Point> {
+        if !P.contains(x) {
+            return None;
+        }
+        let xm = P.encode(x);
+        let rhs = P.add(&P.mul(&P.square(&xm), &xm), &P.encode(&[7, 0, 0, 0]));
+        // p = 3 mod 4, so a square root is `rhs^((p + 1) / 4)`.
+        let exp = [
+            0xffff_ffff_bfff_ff0c,
+            0xffff_ffff_ffff_ffff,
+            0xffff_ffff_ffff_ffff,
+            0x3fff_ffff_ffff_ffff,
+        ];
+        let y = P.pow(&rhs, &exp);
+        if P.square(&y) != rhs {
+            return None;
+        }
+        let y = P.decode(&y);
+        let y = if (y[0] & 1 == 1) == odd { y } else { P.neg(&y) };
+        Point::from_affine(x, &y)
+    }
+}
+
+impl Group for Point {
+    fn identity() -> Point {
+        Point::identity()
+    }
+
+    fn add(&self, other: &Point) -> Point {
+        Point::add(self, other)
+    }
+
+    fn double(&self) -> Point {
+        Point::double(self)
+    }
+
+    fn neg(&self) -> Point {
+        Point::neg(self)
+    }
+}
+
+impl Point {
+    /// Returns the SEC1 compressed encoding, or `None` for the identity.
+    pub(crate) fn to_sec1(self) -> Option<[u8; 33]> {
+        let (x, y) = self.to_affine()?;
+        let mut out = [0_u8; 33];
+        out[0] = 2 | (y[0] & 1) as u8;
+        out[1..].copy_from_slice(&to_be_bytes(&x));
+        Some(out)
+    }
+
+    pub(crate) fn from_sec1(bytes: &[u8; 33]) -> Option<Point> {
+        if bytes[0] != 2 && bytes[0] != 3 {
+            return None;
+        }
+        let mut x = [0_u8; 32];
+        x.copy_from_slice(&bytes[1..]);
+        Point::decompress(&from_be_bytes(&x), bytes[0] == 3)
+    }
+}
+
+/// A scalar modulo the group order `n`, for the protocols built on the
+/// curve. Arithmetic is constant time.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Scalar(pub(crate) Words);
+
+impl Scalar {
+    pub(crate) const ZERO: Scalar = Scalar([0; 4]);
+
+    /// Returns the group order, as the words of a scalar.
+    pub(crate) fn order() -> Words {
+        N.m
+    }
+
+    /// Returns a uniformly random non-zero scalar.
+    pub(crate) fn random() -> SgxResult<Scalar> {
+        let mut bytes = [0_u8; 32];
+        loop {
+            read_rand(&mut bytes)?;
+            let scalar = Scalar::from_bytes(&bytes);
+            if let Some(scalar) = scalar.filter(|s| !s.is_zero()) {
+                zeroize(&mut bytes);
+                return Ok(scalar);
+            }
+        }
+    }
+
+    /// Parses a big-endian scalar, failing if it isn't below `n`.
+    pub(crate) fn from_bytes(bytes: &[u8; 32]) -> Option<Scalar> {
+        let words = from_be_bytes(bytes);
+        if N.contains(&words) {
+            Some(Scalar(words))
+        } else {
+            None
+        }
+    }
+
+    /// Reduces any 256-bit big-endian value.
+    pub(crate) fn from_bytes_reduced(bytes: &[u8; 32]) -> Scalar {
+        Scalar(hash_to_scalar(bytes))
+    }
+
+    /// Reduces any 256-bit value, given as little-endian words.
+    pub(crate) fn from_words_reduced(words: &Words) -> Scalar {
+        let (d, borrow) = sub_words(words, &N.m);
+        Scalar(select(&d, words, borrow))
+    }
+
+    pub(crate) fn to_bytes(self) -> [u8; 32] {
+        to_be_bytes(&self.0)
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        is_zero(&self.0)
+    }
+
+    pub(crate) fn add(&self, other: &Scalar) -> Scalar {
+        Scalar(N.add(&self.0, &other.0))
+    }
+
+    pub(crate) fn neg(&self) -> Scalar {
+        Scalar(N.neg(&self.0))
+    }
+
+    pub(crate) fn mul(&self, other: &Scalar) -> Scalar {
+        Scalar(N.decode(&N.mul(&N.encode(&self.0), &N.encode(&other.0))))
+    }
+
+    /// Returns the inverse of a non-zero scalar.
+    pub(crate) fn invert(&self) -> Scalar {
+        Scalar(N.decode(&N.invert(&N.encode(&self.0))))
+    }
+
+    /// Returns whether `self > n / 2`.
+    pub(crate) fn is_high(&self) -> bool {
+        sub_words(&self.neg().0, &self.0).1 == 1
+    }
+
+    pub(crate) fn wipe(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+/// Reduces a message hash to a scalar, as ECDSA and RFC 6979 do for 256-bit
+/// hashes.
+fn hash_to_scalar(hash: &[u8; 32]) -> Words {
+    let z = from_be_bytes(hash);
+    let (d, borrow) = sub_words(&z, &N.m);
+    select(&d, &z, borrow)
+}
+
+fn hmac(key: &[u8; 32], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = HmacSha256::new(key);
+    for part in parts {
+        mac.update(part);
+    }
+    let mut out = [0_u8; 32];
+    mac.finalize_into(&mut out);
+    out
+}
+
+/// Generates nonce candidates as RFC 6979 section 3.2 does with HMAC-SHA256.
+struct NonceGenerator {
+    k: [u8; 32],
+    v: [u8; 32],
+    started: bool,
+}
+
+impl NonceGenerator {
+    fn new(key: &[u8; 32], hash: &[u8; 32]) -> NonceGenerator {
+        let z = to_be_bytes(&hash_to_scalar(hash));
+        let mut gen = NonceGenerator { k: [0; 32], v: [1; 32], started: false };
+        gen.k = hmac(&gen.k, &[&gen.v, &[0], key, &z]);
+        gen.v = hmac(&gen.k, &[&gen.v]);
+        gen.k = hmac(&gen.k, &[&gen.v, &[1], key, &z]);
+        gen.v = hmac(&gen.k, &[&gen.v]);
+        gen
+    }
+
+    /// Returns the next candidate in `[1, n)`.
+    fn next(&mut self) -> Words {
+        loop {
+            if self.started {
+                self.k = hmac(&self.k, &[&self.v, &[0]]);
+                self.v = hmac(&self.k, &[&self.v]);
+            }
+            self.started = true;
+            self.v = hmac(&self.k, &[&self.v]);
+            let k = from_be_bytes(&self.v);
+            if N.contains(&k) && !is_zero(&k) {
+                return k;
+            }
+        }
+    }
+}
+
+impl Drop for NonceGenerator {
+    fn drop(&mut self) {
+        zeroize(&mut self.k);
+        zeroize(&mut self.v);
+    }
+}
+
+/// A secp256k1 private key, the scalar `d` in `[1, n)`.
+pub struct Secp256k1PrivateKey {
+    d: SecretBytes<32>,
+}
+
+impl Secp256k1PrivateKey {
+    /// Generates a key with the enclave's random number generator.
+    pub fn generate() -> SgxResult<Secp256k1PrivateKey> {
+        let mut d = SecretBytes::zeroed();
+        loop {
+            read_rand(d.expose_secret_mut())?;
+            let key = Secp256k1PrivateKey::from_bytes(d.expose_secret());
+            if key.is_ok() {
+                return key;
+            }
+        }
+    }
+
+    /// Parses a big-endian key, failing with `SGX_ERROR_INVALID_PARAMETER`
+    /// unless it is in `[1, n)`.
+    pub fn from_bytes(bytes: &[u8; 32]) -> SgxResult<Secp256k1PrivateKey> {
+        let mut d = from_be_bytes(bytes);
+        let valid = N.contains(&d) && !is_zero(&d);
+        wipe(&mut d);
+        if !valid {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(Secp256k1PrivateKey { d: Secret::new(*bytes) })
+    }
+
+    /// Returns the big-endian key, e.g. to seal it.
+    pub fn to_bytes(&self) -> SecretBytes<32> {
+        self.d.clone()
+    }
+
+    /// Returns the key `d + tweak mod n`, as BIP32 derives children, failing
+    /// with `SGX_ERROR_INVALID_PARAMETER` if `tweak` isn't below `n` or the
+    /// sum is zero.
+    pub(crate) fn add_tweak(&self, tweak: &[u8; 32]) -> SgxResult<Secp256k1PrivateKey> {
+        let mut t = from_be_bytes(tweak);
+        let mut d = from_be_bytes(self.d.expose_secret());
+        let valid = N.contains(&t);
+        let mut sum = N.add(&d, &t);
+        wipe(&mut t);
+        wipe(&mut d);
+        let key = if valid && !is_zero(&sum) {
+            Ok(Secp256k1PrivateKey { d: Secret::new(to_be_bytes(&sum)) })
+        } else {
+            Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+        };
+        wipe(&mut sum);
+        key
+    }
+
+    pub fn public_key(&self) -> Secp256k1PublicKey {
+        let mut d = from_be_bytes(self.d.expose_secret());
+        let point = Point::generator().mul(&d);
+        wipe(&mut d);
+        // d is in [1, n), so the point isn't the identity.
+        let (x, y) = point.to_affine().unwrap_or(([0; 4], [0; 4]));
+        Secp256k1PublicKey { x: to_be_bytes(&x), y: to_be_bytes(&y) }
+    }
+
+    /// Signs a 32-byte message hash, with the nonce of RFC 6979.
+    ///
+    /// The signature is normalized to the lower of its two `s` values, as
+    /// Bitcoin and Ethereum require, and carries the recovery id.
+    pub fn sign_prehash(&self, hash: &[u8; 32]) -> SgxResult<Secp256k1RecoverableSignature> {
+        let mut nonces = NonceGenerator::new(self.d.expose_secret(), hash);
+        let z = N.encode(&hash_to_scalar(hash));
+        let mut d = N.encode(&from_be_bytes(self.d.expose_secret()));
+        loop {
+            let mut k = nonces.next();
+            let (x, y) = match Point::generator().mul(&k).to_affine() {
+                Some(affine) => affine,
+                None => continue,
+            };
+            // x < p < 2n, so reducing once gives r.
+            let (reduced, borrow) = sub_words(&x, &N.m);
+            let r = select(&reduced, &x, borrow);
+            if is_zero(&r) {
+                continue;
+            }
+            let mut recovery_id = (y[0] & 1) as u8 | ((borrow ^ 1) as u8) << 1;
+
+            let mut kinv = N.invert(&N.encode(&k));
+            let sum = N.add(&z, &N.mul(&N.encode(&r), &d));
+            let mut s = N.decode(&N.mul(&kinv, &sum));
+            wipe(&mut k);
+            wipe(&mut kinv);
+            if is_zero(&s) {
+                continue;
+            }
+            wipe(&mut d);
+
+            let neg = N.neg(&s);
+            // s > n / 2 exactly when n - s < s.
+            if sub_words(&neg, &s).1 == 1 {
+                s = neg;
+                recovery_id ^= 1;
+            }
+            let mut signature = [0_u8; 64];
+            signature[..32].copy_from_slice(&to_be_bytes(&r));
+            signature[32..].copy_from_slice(&to_be_bytes(&s));
+            return Ok(Secp256k1RecoverableSignature {
+                signature: Secp256k1Signature(signature),
+                recovery_id,
+            });
+        }
+    }
+}
+
+impl fmt::Debug for Secp256k1PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secp256k1PrivateKey").field("public_key", &self.public_key()).finish()
+    }
+}
+
+/// A secp256k1 public key, as big-endian affine coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Secp256k1PublicKey {
+    x: [u8; 32],
+    y: [u8; 32],
+}
+
+impl Secp256k1PublicKey {
+    /// Parses a SEC1 compressed (33 bytes) or uncompressed (65 bytes) key,
+    /// failing with `SGX_ERROR_INVALID_PARAMETER` unless it is on the curve.
+    pub fn from_sec1(bytes: &[u8]) -> SgxResult<Secp256k1PublicKey> {
+        let mut x = [0_u8; 32];
+        let point = match (bytes.len(), bytes.first()) {
+            (33, Some(&prefix)) if prefix == 2 || prefix == 3 => {
+                x.copy_from_slice(&bytes[1..]);
+                Point::decompress(&from_be_bytes(&x), prefix == 3)
+            }
+            (65, Some(4)) => {
+                let mut y = [0_u8; 32];
+                x.copy_from_slice(&bytes[1..33]);
+                y.copy_from_slice(&bytes[33..]);
+                Point::from_affine(&from_be_bytes(&x), &from_be_bytes(&y))
+            }
+            _ => None,
+        };
+        point
+            .and_then(Point::to_affine)
+            .map(|(x, y)| Secp256k1PublicKey { x: to_be_bytes(&x), y: to_be_bytes(&y) })
+            .ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+    }
+
+    /// Returns the SEC1 compressed encoding.
+    pub fn to_compressed(&self) -> [u8; 33] {
+        let mut out = [0_u8; 33];
+        out[0] = 2 | (self.y[31] & 1);
+        out[1..].copy_from_slice(&self.x);
+        out
+    }
+
+    /// Returns the SEC1 uncompressed encoding.
+    pub fn to_uncompressed(&self) -> [u8; 65] {
+        let mut out = [0_u8; 65];
+        out[0] = 4;
+        out[1..33].copy_from_slice(&self.x);
+        out[33..].copy_from_slice(&self.y);
+        out
+    }
+
+    /// Returns the Ethereum address of the key, the last 20 bytes of the
+    /// Keccak-256 hash of its coordinates.
+    pub fn ethereum_address(&self) -> [u8; 20] {
+        let mut hasher = Keccak256::new();
+        hasher.update(&self.x);
+        hasher.update(&self.y);
+        let mut hash = [0_u8; 32];
+        hasher.finalize_into(&mut hash);
+        let mut out = [0_u8; 20];
+        out.copy_from_slice(&hash[12..]);
+        out
+    }
+
+    pub(crate) fn from_point(point: &Point) -> Option<Secp256k1PublicKey> {
+        let (x, y) = point.to_affine()?;
+        Some(Secp256k1PublicKey { x: to_be_bytes(&x), y: to_be_bytes(&y) })
+    }
+
+    pub(crate) fn point(&self) -> Option<Point> {
+        Point::from_affine(&from_be_bytes(&self.x), &from_be_bytes(&self.y))
+    }
+
+    /// Verifies `signature` over a 32-byte message hash. Both values of
+    /// `s` are accepted.
+    pub fn verify_prehash(&self, hash: &[u8; 32], signature: &Secp256k1Signature) -> bool {
+        let (r, s) = signature.scalars();
+        if is_zero(&r) || is_zero(&s) || !N.contains(&r) || !N.contains(&s) {
+            return false;
+        }
+        let q = match self.point() {
+            Some(q) => q,
+            None => return false,
+        };
+        let sinv = N.invert(&N.encode(&s));
+        let u1 = N.decode(&N.mul(&N.encode(&hash_to_scalar(hash)), &sinv));
+        let u2 = N.decode(&N.mul(&N.encode(&r), &sinv));
+        match Point::generator().mul(&u1).add(&q.mul(&u2)).to_affine() {
+            Some((x, _)) => {
+                let (reduced, borrow) = sub_words(&x, &N.m);
+                select(&reduced, &x, borrow) == r
+            }
+            None => false,
+        }
+    }
+}
+
+/// The number of signatures whose `s` are inverted together.
+const BATCH_CHUNK: usize = 16;
+
+/// Verifies a batch of signatures over 32-byte message hashes, returning
+/// whether all of them are valid; [`Secp256k1PublicKey::verify_prehash`]
+/// then tells which ones aren't.
+///
+/// ECDSA signatures carry `R.x` alone, so unlike Ed25519 they can't be
+/// checked with one combined equation. The batch shares what it can: the
+/// `s` of sixteen signatures are inverted at once, the tables of the
+/// generator and of a public key repeated among those are computed once,
+/// and every `u1 G + u2 Q` is one variable-time multiplication whose
+/// abscissa is checked in projective coordinates.
+pub fn verify_batch(items: &[(&Secp256k1PublicKey, &[u8; 32], &Secp256k1Signature)]) -> bool {
+    let generator = Table::new(&Point::generator());
+    items.chunks(BATCH_CHUNK).all(|chunk| verify_chunk(&generator, chunk))
+}
+
+fn verify_chunk(
+    generator: &Table<Point>,
+    chunk: &[(&Secp256k1PublicKey, &[u8; 32], &Secp256k1Signature)],
+) -> bool {
+    let mut inverses = [[0_u64; 4]; BATCH_CHUNK];
+    for (i, (_, _, signature)) in chunk.iter().enumerate() {
+        let (r, s) = signature.scalars();
+        if is_zero(&r) || is_zero(&s) || !N.contains(&r) || !N.contains(&s) {
+            return false;
+        }
+        inverses[i] = N.encode(&s);
+    }
+    N.invert_all(&mut inverses[..chunk.len()], &mut [[0; 4]; BATCH_CHUNK]);
+
+    let mut keys = [chunk[0].0; BATCH_CHUNK];
+    let mut tables = [Table::new(&Point::identity()); BATCH_CHUNK];
+    let mut key_count = 0;
+    for (i, (public, hash, signature)) in chunk.iter().enumerate() {
+        let key = match keys[..key_count].iter().position(|key| key == public) {
+            Some(key) => key,
+            None => {
+                let q = match public.point() {
+                    Some(q) => q,
+                    None => return false,
+                };
+                keys[key_count] = public;
+                tables[key_count] = Table::new(&q);
+                key_count += 1;
+                key_count - 1
+            }
+        };
+        let (r, _) = signature.scalars();
+        let u1 = N.decode(&N.mul(&N.encode(&hash_to_scalar(hash)), &inverses[i]));
+        let u2 = N.decode(&N.mul(&N.encode(&r), &inverses[i]));
+        let point = straus(&[(generator, &wnaf(&u1)), (&tables[key], &wnaf(&u2))]);
+        if !point.x_matches(&r) {
+            return false;
+        }
+    }
+    true
+}
+
+/// An ECDSA signature, the big-endian `r || s`.
+#[derive(Clone, Copy)]
+pub struct Secp256k1Signature(pub [u8; 64]);
+
+impl Secp256k1Signature {
+    pub fn to_bytes(&self) -> [u8; 64] {
+        self.0
+    }
+
+    fn scalars(&self) -> (Words, Words) {
+        let mut r = [0_u8; 32];
+        let mut s = [0_u8; 32];
+        r.copy_from_slice(&self.0[..32]);
+        s.copy_from_slice(&self.0[32..]);
+        (from_be_bytes(&r), from_be_bytes(&s))
+    }
+}
+
+impl fmt::Debug for Secp256k1Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secp256k1Signature(")?;
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl PartialEq for Secp256k1Signature {
+    fn eq(&self, other: &Secp256k1Signature) -> bool {
+        ct_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Secp256k1Signature {}
+
+/// A signature along with the id recovering its public key: bit 0 is the
+/// parity of `R.y` and bit 1 is set if `R.x` overflowed `n`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Secp256k1RecoverableSignature {
+    pub signature: Secp256k1Signature,
+    pub recovery_id: u8,
+}
+
+impl Secp256k1RecoverableSignature {
+    /// Returns `r || s || v`, with `v` the bare recovery id; Ethereum
+    /// transactions add their own offset to it.
+    pub fn to_bytes(&self) -> [u8; 65] {
+        let mut out = [0_u8; 65];
+        out[..64].copy_from_slice(&self.signature.0);
+        out[64] = self.recovery_id;
+        out
+    }
+
+    /// Parses `r || s || v`, with `v` in `0..4`.
+    pub fn from_bytes(bytes: &[u8; 65]) -> SgxResult<Secp256k1RecoverableSignature> {
+        if bytes[64] > 3 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut signature = [0_u8; 64];
+        signature.copy_from_slice(&bytes[..64]);
+        Ok(Secp256k1RecoverableSignature {
+            signature: Secp256k1Signature(signature),
+            recovery_id: bytes[64],
+        })
+    }
+
+    /// Recovers the public key which made this signature over `hash`,
+    /// failing with `SGX_ERROR_INVALID_PARAMETER` if there is none.
+    pub fn recover(&self, hash: &[u8; 32]) -> SgxResult<Secp256k1PublicKey> {
+        let invalid = Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        let (r, s) = self.signature.scalars();
+        if self.recovery_id > 3
+            || is_zero(&r)
+            || is_zero(&s)
+            || !N.contains(&r)
+            || !N.contains(&s)
+        {
+            return invalid;
+        }
+        let mut x = r;
+        if self.recovery_id & 2 != 0 {
+            let mut carry = 0_u64;
+            for (word, m) in x.iter_mut().zip(N.m.iter()) {
+                let (sum, c1) = word.overflowing_add(*m);
+                let (sum, c2) = sum.overflowing_add(carry);
+                *word = sum;
+                carry = (c1 | c2) as u64;
+            }
+            if carry != 0 {
+                return invalid;
+            }
+        }
+        let point = match Point::decompress(&x, self.recovery_id & 1 == 1) {
+            Some(point) => point,
+            None => return invalid,
+        };
+
+        // Q = r^-1 (s R - z G)
+        let rinv = N.invert(&N.encode(&r));
+        let z = N.encode(&hash_to_scalar(hash));
+        let u1 = N.decode(&N.neg(&N.mul(&z, &rinv)));
+        let u2 = N.decode(&N.mul(&N.encode(&s), &rinv));
+        match Point::generator().mul(&u1).add(&point.mul(&u2)).to_affine() {
+            Some((x, y)) => Ok(Secp256k1PublicKey { x: to_be_bytes(&x), y: to_be_bytes(&y) }),
+            None => invalid,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256::Sha256;
+    use crate::util::hex;
+    use std::vec::Vec;
+
+    fn sha256(message: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(message);
+        hasher.finalize()
+    }
+
+    fn key_one() -> Secp256k1PrivateKey {
+        let mut one = [0_u8; 32];
+        one[31] = 1;
+        Secp256k1PrivateKey::from_bytes(&one).unwrap()
+    }
+
+    #[test]
+    fn rfc6979() {
+        // The deterministic signatures of the private key 1 used across
+        // Bitcoin libraries, normalized to low s.
+        let vectors = [
+            (
+                &b"Satoshi Nakamoto"[..],
+                "934b1ea10a4b3c1757e2b0c017d0b6143ce3c9a7e6a4a49860d7a6ab210ee3d8\
+                 2442ce9d2b916064108014783e923ec36b49743e2ffa1c4496f01a512aafd9e5",
+            ),
+            (
+                &b"All those moments will be lost in time, like tears in rain. Time to die..."[..],
+                "8600dbd41e348fe5c9465ab92d23e3db8b98b873beecd930736488696438cb6b\
+                 547fe64427496db33bf66019dacbf0039c04199abb0122918601db38a72cfc21",
+            ),
+        ];
+        let key = key_one();
+        let public = key.public_key();
+        for (message, signature) in vectors {
+            let hash = sha256(message);
+            let sig = key.sign_prehash(&hash).unwrap();
+            assert_eq!(sig.signature.to_bytes()[..], hex(signature)[..]);
+            assert!(public.verify_prehash(&hash, &sig.signature));
+            assert_eq!(sig.recover(&hash).unwrap(), public);
+        }
+    }
+
+    #[test]
+    fn public_key() {
+        let public = key_one().public_key();
+        assert_eq!(
+            public.to_compressed()[..],
+            hex("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")[..]
+        );
+        assert_eq!(public.ethereum_address()[..], hex("7e5f4552091a69125d5dfcb7b8c2659029395bdf")[..]);
+        assert_eq!(Secp256k1PublicKey::from_sec1(&public.to_uncompressed()).unwrap(), public);
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert!(Secp256k1PrivateKey::from_bytes(&[0; 32]).is_err());
+        assert!(Secp256k1PrivateKey::from_bytes(&[0xff; 32]).is_err());
+
+        let mut compressed = key_one().public_key().to_compressed();
+        compressed[0] = 0x04;
+        assert!(Secp256k1PublicKey::from_sec1(&compressed).is_err());
+        // x = 5 isn't the abscissa of a point of the curve.
+        let mut off_curve = [0_u8; 33];
+        off_curve[0] = 0x02;
+        off_curve[32] = 5;
+        assert!(Secp256k1PublicKey::from_sec1(&off_curve).is_err());
+
+        let key = key_one();
+        let hash = sha256(b"Satoshi Nakamoto");
+        let sig = key.sign_prehash(&hash).unwrap().signature;
+        let mut other = hash;
+        other[0] ^= 1;
+        assert!(!key.public_key().verify_prehash(&other, &sig));
+        // Verification takes both values of s, but not s = n.
+        let mut high = sig;
+        let s = Scalar(from_be_bytes(&sig.0[32..].try_into().unwrap())).neg();
+        high.0[32..].copy_from_slice(&to_be_bytes(&s.0));
+        assert!(key.public_key().verify_prehash(&hash, &high));
+        high.0[32..].copy_from_slice(&to_be_bytes(&N.m));
+        assert!(!key.public_key().verify_prehash(&hash, &high));
+        assert!(!key.public_key().verify_prehash(&hash, &Secp256k1Signature([0; 64])));
+    }
+
+    #[test]
+    fn verify_batch() {
+        // More signatures than share an inversion, with repeated keys.
+        let keys: Vec<Secp256k1PrivateKey> =
+            (1..4).map(|i| Secp256k1PrivateKey::from_bytes(&[i; 32]).unwrap()).collect();
+        let publics: Vec<Secp256k1PublicKey> = keys.iter().map(|key| key.public_key()).collect();
+        let hashes: Vec<[u8; 32]> = (0..BATCH_CHUNK as u8 + 5).map(|i| sha256(&[i])).collect();
+        let signatures: Vec<Secp256k1Signature> = hashes
+            .iter()
+            .enumerate()
+            .map(|(i, h)| keys[i % 3].sign_prehash(h).unwrap().signature)
+            .collect();
+        let items: Vec<_> =
+            hashes.iter().enumerate().map(|(i, h)| (&publics[i % 3], h, &signatures[i])).collect();
+        assert!(super::verify_batch(&items));
+        assert!(super::verify_batch(&items[..1]));
+        assert!(super::verify_batch(&[]));
+
+        for bad in [0, BATCH_CHUNK, items.len() - 1] {
+            for byte in [3, 40] {
+                let mut forged = *items[bad].2;
+                forged.0[byte] ^= 1;
+                let mut batch = items.clone();
+                batch[bad].2 = &forged;
+                assert!(!super::verify_batch(&batch), "{} {}", bad, byte);
+            }
+            let mut batch = items.clone();
+            batch[bad].0 = &publics[(bad + 1) % 3];
+            assert!(!super::verify_batch(&batch));
+            let zero = Secp256k1Signature([0; 64]);
+            let mut batch = items.clone();
+            batch[bad].2 = &zero;
+            assert!(!super::verify_batch(&batch));
+        }
+    }
+
+    #[test]
+    fn msm_matches_naive_multiplication() {
+        let points: Vec<Point> =
+            (1..5_u8).map(|i| Point::generator().mul(&[i as u64 * 0x9e37_79b9, 0, 0, 0])).collect();
+        let scalars: [Words; 4] =
+            [N.m, [u64::MAX, 0, 0, 1], [0; 4], Scalar::from_bytes_reduced(&sha256(b"s")).0];
+        let tables: Vec<Table<Point>> = points.iter().map(Table::new).collect();
+        let digits: Vec<_> = scalars.iter().map(wnaf).collect();
+        for n in 1..=points.len() {
+            let terms: Vec<_> = tables[..n].iter().zip(digits.iter()).collect();
+            let expected = points[..n]
+                .iter()
+                .zip(scalars.iter())
+                .fold(Point::identity(), |acc, (p, s)| acc.add(&p.mul(s)));
+            assert_eq!(straus(&terms).to_affine(), expected.to_affine(), "{} terms", n);
+        }
+        // n times a point is the identity.
+        assert_eq!(straus(&[(&tables[0], &digits[0])]).to_affine(), None);
+    }
+}
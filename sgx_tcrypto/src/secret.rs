@@ -0,0 +1,185 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Secret values, wiped when they're dropped and redacted from debug output.
+//!
+//! [`Secret`] holds keys and other secrets of the software implementations,
+//! which return them wrapped too, e.g. when a private key is exported to be
+//! sealed: once the copy is dropped, the secret doesn't linger in freed
+//! enclave memory, to be read back by a later bug. Take the value out of the
+//! wrapper only to hand it to an API expecting a bare value.
+//!
+//! The wrappers don't lock pages: the EPC isn't swapped in the clear, as the
+//! processor encrypts evicted enclave pages with a per-boot paging key, and
+//! an enclave can't call `mlock` anyway.
+
+use crate::ctbignum::Uint;
+use crate::util::{ct_eq, zeroize};
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// A value which can be overwritten with zeroes in a way the compiler won't
+/// remove.
+pub trait Zeroize {
+    fn zeroize(&mut self);
+}
+
+macro_rules! impl_zeroize_int {
+    ($($t:ty),*) => {$(
+        impl Zeroize for $t {
+            fn zeroize(&mut self) {
+                unsafe { ptr::write_volatile(self, 0) };
+                compiler_fence(Ordering::SeqCst);
+            }
+        }
+    )*};
+}
+
+impl_zeroize_int!(u16, u32, u64, u128, usize);
+
+impl Zeroize for u8 {
+    fn zeroize(&mut self) {
+        zeroize(core::slice::from_mut(self));
+    }
+}
+
+impl Zeroize for [u8] {
+    fn zeroize(&mut self) {
+        zeroize(self);
+    }
+}
+
+impl<T: Zeroize, const N: usize> Zeroize for [T; N] {
+    fn zeroize(&mut self) {
+        for x in self.iter_mut() {
+            x.zeroize();
+        }
+    }
+}
+
+impl<const L: usize> Zeroize for Uint<L> {
+    fn zeroize(&mut self) {
+        self.wipe();
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// Holds a secret, zeroized on drop; its `Debug` output omits the value.
+#[derive(Clone, Default)]
+pub struct Secret<T: Zeroize>(T);
+
+/// A secret byte array, such as a key.
+pub type SecretBytes<const N: usize> = Secret<[u8; N]>;
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Secret<T> {
+        Secret(value)
+    }
+
+    pub fn expose_secret(&self) -> &T {
+        &self.0
+    }
+
+    pub fn expose_secret_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<const N: usize> Secret<[u8; N]> {
+    /// Returns a secret of `N` zeroes, to be filled in place rather than
+    /// copied in.
+    pub fn zeroed() -> SecretBytes<N> {
+        Secret([0; N])
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Secret<T> {
+        Secret(value)
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+/// Compares in constant time.
+impl<const N: usize> PartialEq for Secret<[u8; N]> {
+    fn eq(&self, other: &SecretBytes<N>) -> bool {
+        ct_eq(&self.0, &other.0)
+    }
+}
+
+impl<const N: usize> Eq for Secret<[u8; N]> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::MaybeUninit;
+    use std::format;
+
+    /// Drops `secret` in place and returns what is left of its value.
+    fn dropped<T: Zeroize + Copy>(secret: Secret<T>) -> T {
+        let mut slot = MaybeUninit::new(secret);
+        unsafe {
+            slot.assume_init_drop();
+            ptr::read(ptr::addr_of!((*slot.as_ptr()).0))
+        }
+    }
+
+    #[test]
+    fn zeroizes_on_drop() {
+        assert_eq!(dropped(Secret::new([0xa5_u8; 64])), [0; 64]);
+        assert_eq!(dropped(Secret::new([u32::MAX; 8])), [0; 8]);
+        assert_eq!(dropped(Secret::new(u128::MAX)), 0);
+        assert!(dropped(Secret::new(Uint::<4>([u64::MAX; 4]))).is_zero());
+
+        let mut bytes = [0x5a_u8; 33];
+        bytes[..].zeroize();
+        assert_eq!(bytes, [0; 33]);
+        // Clones are secrets of their own, and wiping one leaves the other.
+        let secret = SecretBytes::<4>::new([1, 2, 3, 4]);
+        assert_eq!(dropped(secret.clone()), [0; 4]);
+        assert_eq!(secret.expose_secret(), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn redacts_debug() {
+        let secret = SecretBytes::<4>::new([0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(format!("{:?}", secret), "Secret(..)");
+        assert_eq!(format!("{:#?}", Some(Secret::new(1234_u64))), "Some(\n    Secret(..),\n)");
+        assert_eq!(format!("{:?}", (secret, Secret::new(0xbeef_u16))), "(Secret(..), Secret(..))");
+    }
+
+    #[test]
+    fn compares() {
+        let mut secret = SecretBytes::<16>::zeroed();
+        assert!(secret == Secret::new([0; 16]));
+        secret.expose_secret_mut()[15] = 1;
+        assert!(secret != SecretBytes::zeroed());
+        assert!(secret == Secret::from(*secret.expose_secret()));
+    }
+}
@@ -0,0 +1,333 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! SHA3-256, SHA3-512 and the SHAKE128 and SHAKE256 XOFs (FIPS 202), and
+//! Keccak-256, the sponge over Keccak-f[1600] with the padding Ethereum kept
+//! from before standardization.
+
+use crate::hash::Hash;
+
+/// The rate of the sponges with 512 bits of capacity, SHA3-256, Keccak-256
+/// and SHAKE256.
+const RATE: usize = 136;
+/// The rate of SHA3-512.
+const RATE_512: usize = 72;
+/// The rate of SHAKE128.
+const RATE_SHAKE128: usize = 168;
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000_0000_0000_0001, 0x0000_0000_0000_8082, 0x8000_0000_0000_808a, 0x8000_0000_8000_8000,
+    0x0000_0000_0000_808b, 0x0000_0000_8000_0001, 0x8000_0000_8000_8081, 0x8000_0000_0000_8009,
+    0x0000_0000_0000_008a, 0x0000_0000_0000_0088, 0x0000_0000_8000_8009, 0x0000_0000_8000_000a,
+    0x0000_0000_8000_808b, 0x8000_0000_0000_008b, 0x8000_0000_0000_8089, 0x8000_0000_0000_8003,
+    0x8000_0000_0000_8002, 0x8000_0000_0000_0080, 0x0000_0000_0000_800a, 0x8000_0000_8000_000a,
+    0x8000_0000_8000_8081, 0x8000_0000_0000_8080, 0x0000_0000_8000_0001, 0x8000_0000_8000_8008,
+];
+
+/// The rotation of each lane by rho, indexed by `x + 5 y`.
+const RHO: [u32; 25] = [
+    0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56, 14,
+];
+
+fn keccak_f(a: &mut [u64; 25]) {
+    for rc in ROUND_CONSTANTS.iter() {
+        // Theta.
+        let mut c = [0_u64; 5];
+        for (x, c) in c.iter_mut().enumerate() {
+            *c = a[x] ^ a[x + 5] ^ a[x + 10] ^ a[x + 15] ^ a[x + 20];
+        }
+        for x in 0..5 {
+            let d = c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                a[x + 5 * y] ^= d;
+            }
+        }
+        // Rho and pi: the lane at (x, y) moves to (y, 2x + 3y).
+        let mut b = [0_u64; 25];
+        for x in 0..5 {
+            for y in 0..5 {
+                b[y + 5 * ((2 * x + 3 * y) % 5)] = a[x + 5 * y].rotate_left(RHO[x + 5 * y]);
+            }
+        }
+        // Chi.
+        for y in 0..5 {
+            for x in 0..5 {
+                a[x + 5 * y] = b[x + 5 * y] ^ (!b[(x + 1) % 5 + 5 * y] & b[(x + 2) % 5 + 5 * y]);
+            }
+        }
+        // Iota.
+        a[0] ^= rc;
+    }
+}
+
+/// A sponge absorbing, and then squeezing, `rate` bytes per permutation.
+#[derive(Clone)]
+struct Sponge {
+    state: [u64; 25],
+    /// The number of bytes absorbed into, or squeezed from, the current
+    /// block.
+    pos: usize,
+    rate: usize,
+}
+
+impl Sponge {
+    fn new(rate: usize) -> Sponge {
+        Sponge { state: [0; 25], pos: 0, rate }
+    }
+
+    fn xor_byte(&mut self, i: usize, byte: u8) {
+        self.state[i / 8] ^= (byte as u64) << (8 * (i % 8));
+    }
+
+    fn absorb(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.pos & 7 == 0 && data.len() >= 8 {
+                let mut word = [0_u8; 8];
+                word.copy_from_slice(&data[..8]);
+                self.state[self.pos / 8] ^= u64::from_le_bytes(word);
+                self.pos += 8;
+                data = &data[8..];
+            } else {
+                self.xor_byte(self.pos, data[0]);
+                self.pos += 1;
+                data = &data[1..];
+            }
+            if self.pos == self.rate {
+                keccak_f(&mut self.state);
+                self.pos = 0;
+            }
+        }
+    }
+
+    /// Pads with the domain byte `pad`, and starts squeezing.
+    fn pad(&mut self, pad: u8) {
+        self.xor_byte(self.pos, pad);
+        self.xor_byte(self.rate - 1, 0x80);
+        keccak_f(&mut self.state);
+        self.pos = 0;
+    }
+
+    /// Pads with the domain byte `pad` and writes up to `rate` bytes of
+    /// output.
+    fn squeeze(mut self, pad: u8, out: &mut [u8]) {
+        self.pad(pad);
+        for (chunk, word) in out.chunks_mut(8).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+        }
+    }
+
+    /// Writes the next `out.len()` bytes of output, once padded.
+    fn squeeze_more(&mut self, out: &mut [u8]) {
+        for byte in out.iter_mut() {
+            if self.pos == self.rate {
+                keccak_f(&mut self.state);
+                self.pos = 0;
+            }
+            *byte = (self.state[self.pos / 8] >> (8 * (self.pos % 8))) as u8;
+            self.pos += 1;
+        }
+    }
+}
+
+impl Drop for Sponge {
+    fn drop(&mut self) {
+        for word in self.state.iter_mut() {
+            unsafe { core::ptr::write_volatile(word, 0) };
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Sha3_256(Sponge);
+
+impl Hash for Sha3_256 {
+    const BLOCK_LEN: usize = RATE;
+    const OUTPUT_LEN: usize = 32;
+
+    fn new() -> Sha3_256 {
+        Sha3_256(Sponge::new(RATE))
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.absorb(data)
+    }
+
+    fn finalize_into(self, out: &mut [u8]) {
+        self.0.squeeze(0x06, out)
+    }
+}
+
+#[derive(Clone)]
+pub struct Sha3_512(Sponge);
+
+impl Hash for Sha3_512 {
+    const BLOCK_LEN: usize = RATE_512;
+    const OUTPUT_LEN: usize = 64;
+
+    fn new() -> Sha3_512 {
+        Sha3_512(Sponge::new(RATE_512))
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.absorb(data)
+    }
+
+    fn finalize_into(self, out: &mut [u8]) {
+        self.0.squeeze(0x06, out)
+    }
+}
+
+#[derive(Clone)]
+pub struct Keccak256(Sponge);
+
+impl Hash for Keccak256 {
+    const BLOCK_LEN: usize = RATE;
+    const OUTPUT_LEN: usize = 32;
+
+    fn new() -> Keccak256 {
+        Keccak256(Sponge::new(RATE))
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.absorb(data)
+    }
+
+    fn finalize_into(self, out: &mut [u8]) {
+        self.0.squeeze(0x01, out)
+    }
+}
+
+/// An extendable-output function, SHAKE128 or SHAKE256, absorbing input
+/// and then reading as much output as wanted from a [`ShakeReader`].
+#[derive(Clone)]
+pub struct Shake<const R: usize>(Sponge);
+
+pub type Shake128 = Shake<RATE_SHAKE128>;
+pub type Shake256 = Shake<RATE>;
+
+impl<const R: usize> Shake<R> {
+    pub fn new() -> Shake<R> {
+        Shake(Sponge::new(R))
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.absorb(data)
+    }
+
+    /// Finishes absorbing, and returns the reader of the output.
+    pub fn finalize_xof(self) -> ShakeReader {
+        let mut sponge = self.0;
+        sponge.pad(0x1f);
+        ShakeReader(sponge)
+    }
+
+    /// Writes the first `out.len()` bytes of the output for `data`.
+    pub fn digest_into(data: &[u8], out: &mut [u8]) {
+        let mut shake = Self::new();
+        shake.update(data);
+        shake.finalize_xof().read(out);
+    }
+}
+
+impl<const R: usize> Default for Shake<R> {
+    fn default() -> Shake<R> {
+        Shake::new()
+    }
+}
+
+/// The output of a SHAKE, read in pieces of any length.
+pub struct ShakeReader(Sponge);
+
+impl ShakeReader {
+    /// Writes the next `out.len()` bytes of output.
+    pub fn read(&mut self, out: &mut [u8]) {
+        self.0.squeeze_more(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+    use std::vec;
+    use std::vec::Vec;
+
+    fn digest<H: Hash>(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0_u8; H::OUTPUT_LEN];
+        H::digest_into(data, &mut out);
+        out
+    }
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(
+            digest::<Sha3_256>(b"abc"),
+            hex("3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532")
+        );
+        assert_eq!(
+            digest::<Sha3_256>(b""),
+            hex("a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a")
+        );
+        assert_eq!(
+            digest::<Sha3_512>(b"abc"),
+            hex("b751850b1a57168a5693cd924b6b096e08f621827444f70d884f5d0240d2712e10e116e9192af3c91a7ec57647e3934057340b4cf408d5a56592f8274eec53f0")
+        );
+        assert_eq!(
+            digest::<Keccak256>(b""),
+            hex("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470")
+        );
+        assert_eq!(
+            digest::<Keccak256>(b"abc"),
+            hex("4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45")
+        );
+
+        let mut out = [0_u8; 32];
+        Shake128::digest_into(b"", &mut out);
+        assert_eq!(
+            out[..],
+            hex("7f9c2ba4e88f827d616045507605853ed73b8093f6efbc88eb1a6eacfa66ef26")[..]
+        );
+        // Output read in pieces continues the same stream.
+        let expected = hex("483366601360a8771c6863080cc4114d8db44530f8f1e1ee4f94ea37e78b5739d5a15bef186a5386c75744c0527e1faa9f8726e462a12a4feb06bd8801e751e4");
+        let mut shake = Shake256::new();
+        shake.update(b"a");
+        shake.update(b"bc");
+        let mut reader = shake.finalize_xof();
+        let mut out = [0_u8; 64];
+        let (first, rest) = out.split_at_mut(5);
+        reader.read(first);
+        reader.read(rest);
+        assert_eq!(out[..], expected[..]);
+    }
+
+    #[test]
+    fn incremental() {
+        // Input across several blocks of the rate, fed in uneven pieces.
+        let data: Vec<u8> = (0..200).map(|i| (i % 251) as u8).collect();
+        let expected = hex("5f728f63bf5ee48c77f453c0490398fa645b8d4c4e56be9a41cfec344d6ca899");
+        assert_eq!(digest::<Sha3_256>(&data), expected);
+        for piece in [1, 7, RATE, RATE + 1] {
+            let mut hasher = Sha3_256::new();
+            for chunk in data.chunks(piece) {
+                hasher.update(chunk);
+            }
+            let mut out = [0_u8; 32];
+            hasher.finalize_into(&mut out);
+            assert_eq!(out[..], expected[..], "{}", piece);
+        }
+    }
+}
@@ -0,0 +1,384 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Secret sharing: Shamir's scheme over GF(256), byte by byte, for secrets
+//! of any length, and Feldman's verifiable scheme over P-256 scalars.
+//!
+//! Any `threshold` of the shares of a secret recombine it, and fewer reveal
+//! nothing about it. With Shamir's scheme alone a corrupted share silently
+//! yields a wrong secret; Feldman's scheme publishes commitments to the
+//! sharing polynomial, against which every holder checks its share as it
+//! receives it, and the secret is checked as it is recombined. Shares are
+//! generated inside the enclave, and are secrets of their own: they should
+//! leave it sealed, or over channels which end in the enclaves of their
+//! holders.
+
+use crate::p256::{Point, Scalar};
+use crate::secret::{Secret, SecretBytes};
+use crate::util::read_rand;
+use sgx_types::*;
+
+/// The most shares a secret can be split into, as indices are non-zero
+/// bytes.
+pub const MAX_SHARES: usize = 255;
+
+/// A share of an `N`-byte secret: the evaluations at `index` of the
+/// sharing polynomials.
+#[derive(Clone)]
+pub struct Share<const N: usize> {
+    pub index: u8,
+    pub value: SecretBytes<N>,
+}
+
+impl<const N: usize> Share<N> {
+    pub fn new(index: u8, value: &[u8; N]) -> Share<N> {
+        Share { index, value: Secret::new(*value) }
+    }
+}
+
+impl<const N: usize> Default for Share<N> {
+    fn default() -> Share<N> {
+        Share { index: 0, value: SecretBytes::zeroed() }
+    }
+}
+
+/// Multiplies in GF(256), modulo `x^8 + x^4 + x^3 + x + 1`, in constant
+/// time.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0_u8;
+    for _ in 0..8 {
+        product ^= a & 0_u8.wrapping_sub(b & 1);
+        a = (a << 1) ^ (0x1b & 0_u8.wrapping_sub(a >> 7));
+        b >>= 1;
+    }
+    product
+}
+
+/// Returns the inverse of a non-zero element, `a^254`.
+fn gf_invert(a: u8) -> u8 {
+    let mut acc = 1_u8;
+    let mut power = a;
+    for i in 0..8 {
+        if (254 >> i) & 1 == 1 {
+            acc = gf_mul(acc, power);
+        }
+        power = gf_mul(power, power);
+    }
+    acc
+}
+
+/// Checks that there are between 1 and 255 shares with distinct, non-zero
+/// indices.
+fn check_indices(indices: impl Iterator<Item = u8>) -> SgxError {
+    let mut seen = [false; 256];
+    let mut count = 0;
+    for index in indices {
+        if index == 0 || seen[index as usize] {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        seen[index as usize] = true;
+        count += 1;
+    }
+    if count == 0 {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    Ok(())
+}
+
+/// Splits `secret` into `shares.len()` shares, with indices from 1, any
+/// `threshold` of which recombine it. Fails with
+/// `SGX_ERROR_INVALID_PARAMETER` unless `1 <= threshold <= shares.len()
+/// <= MAX_SHARES`.
+pub fn split_secret<const N: usize>(
+    secret: &[u8; N],
+    threshold: usize,
+    shares: &mut [Share<N>],
+) -> SgxError {
+    if threshold == 0 || threshold > shares.len() || shares.len() > MAX_SHARES {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    for (i, share) in shares.iter_mut().enumerate() {
+        share.index = i as u8 + 1;
+        *share.value.expose_secret_mut() = [0; N];
+    }
+    // Evaluates the polynomials by Horner's rule, drawing each coefficient,
+    // from the highest degree down, as it is needed.
+    let mut coefficients = SecretBytes::<N>::zeroed();
+    for degree in (0..threshold).rev() {
+        if degree == 0 {
+            *coefficients.expose_secret_mut() = *secret;
+        } else {
+            read_rand(coefficients.expose_secret_mut())?;
+        }
+        for share in shares.iter_mut() {
+            let x = share.index;
+            let value = share.value.expose_secret_mut();
+            for (y, c) in value.iter_mut().zip(coefficients.expose_secret().iter()) {
+                *y = gf_mul(*y, x) ^ c;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recombines a secret from shares of it, failing with
+/// `SGX_ERROR_INVALID_PARAMETER` if there are none or two share an index.
+///
+/// Shares aren't authenticated: fewer than the threshold, or a corrupted
+/// one, yield a wrong secret rather than an error.
+pub fn combine_shares<const N: usize>(shares: &[Share<N>]) -> SgxResult<SecretBytes<N>> {
+    check_indices(shares.iter().map(|share| share.index))?;
+    let mut secret = SecretBytes::<N>::zeroed();
+    for share in shares.iter() {
+        // The Lagrange coefficient of the share at 0.
+        let mut lagrange = 1_u8;
+        for other in shares.iter().filter(|other| other.index != share.index) {
+            lagrange = gf_mul(lagrange, gf_mul(other.index, gf_invert(other.index ^ share.index)));
+        }
+        let out = secret.expose_secret_mut();
+        for (s, y) in out.iter_mut().zip(share.value.expose_secret().iter()) {
+            *s ^= gf_mul(lagrange, *y);
+        }
+    }
+    Ok(secret)
+}
+
+fn index_scalar(index: u8) -> Scalar {
+    Scalar([index as u64, 0, 0, 0])
+}
+
+/// Splits a non-zero P-256 scalar, big-endian, into `shares.len()` shares
+/// verifiable against `commitments`, whose length is the threshold:
+/// `commitments[0]` is `secret * G`, and the others commit to the random
+/// coefficients of the polynomial. Fails with `SGX_ERROR_INVALID_PARAMETER`
+/// unless the secret is in `[1, n)` and `1 <= commitments.len() <=
+/// shares.len() <= MAX_SHARES`.
+///
+/// A seed meant to be shared this way is best generated as a scalar; one of
+/// 32 uniform bytes is above `n` with probability `2^-32`.
+pub fn split_verifiable(
+    secret: &[u8; 32],
+    shares: &mut [Share<32>],
+    commitments: &mut [sgx_ec256_public_t],
+) -> SgxError {
+    let threshold = commitments.len();
+    if threshold == 0 || threshold > shares.len() || shares.len() > MAX_SHARES {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    let mut secret = match Scalar::from_bytes(secret).filter(|s| !s.is_zero()) {
+        Some(secret) => secret,
+        None => return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER),
+    };
+    let mut values = [Scalar([0; 4]); MAX_SHARES];
+    let values = &mut values[..shares.len()];
+    for degree in (0..threshold).rev() {
+        let mut coefficient = if degree == 0 { secret } else { Scalar::random()? };
+        // Neither the secret nor the coefficients are zero, so the
+        // commitments aren't the identity.
+        commitments[degree] = match Point::generator().mul(&coefficient.0).to_public() {
+            Some(commitment) => commitment,
+            None => return Err(sgx_status_t::SGX_ERROR_UNEXPECTED),
+        };
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = value.mul(&index_scalar(i as u8 + 1)).add(&coefficient);
+        }
+        coefficient.wipe();
+    }
+    secret.wipe();
+    for (i, (share, value)) in shares.iter_mut().zip(values.iter_mut()).enumerate() {
+        share.index = i as u8 + 1;
+        *share.value.expose_secret_mut() = value.to_bytes();
+        value.wipe();
+    }
+    Ok(())
+}
+
+/// Checks a share against the commitments of its sharing, that
+/// `value * G = sum(index^j * commitments[j])`.
+pub fn verify_share(share: &Share<32>, commitments: &[sgx_ec256_public_t]) -> bool {
+    let value = match Scalar::from_bytes(share.value.expose_secret()) {
+        Some(value) => value,
+        None => return false,
+    };
+    if share.index == 0 || commitments.is_empty() {
+        return false;
+    }
+    let index = [share.index as u64, 0, 0, 0];
+    let mut expected: Option<Point> = None;
+    for commitment in commitments.iter().rev() {
+        let commitment = match Point::from_public(commitment) {
+            Some(commitment) => commitment,
+            None => return false,
+        };
+        expected = Some(match expected {
+            Some(acc) => acc.mul_vartime(&index).add(&commitment),
+            None => commitment,
+        });
+    }
+    let mut value = value;
+    let actual = Point::generator().mul(&value.0);
+    value.wipe();
+    match expected {
+        Some(expected) => expected.same(&actual),
+        None => false,
+    }
+}
+
+/// Recombines a secret shared with [`split_verifiable`] from at least as
+/// many shares as there are commitments, checking every share; the secret
+/// is then the discrete logarithm of `commitments[0]`.
+///
+/// Fails with `SGX_ERROR_INVALID_PARAMETER` if there are too few shares or
+/// two share an index, and with `SGX_ERROR_INVALID_SIGNATURE` if a share
+/// doesn't match the commitments.
+pub fn combine_verifiable(
+    shares: &[Share<32>],
+    commitments: &[sgx_ec256_public_t],
+) -> SgxResult<SecretBytes<32>> {
+    if commitments.is_empty() || shares.len() < commitments.len() {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    check_indices(shares.iter().map(|share| share.index))?;
+    if !shares.iter().all(|share| verify_share(share, commitments)) {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+    }
+    let shares = &shares[..commitments.len()];
+    let mut secret = Scalar([0; 4]);
+    for share in shares.iter() {
+        let x = index_scalar(share.index);
+        let mut lagrange = Scalar([1, 0, 0, 0]);
+        for other in shares.iter().filter(|other| other.index != share.index) {
+            let xj = index_scalar(other.index);
+            lagrange = lagrange.mul(&xj).mul(&xj.sub(&x).invert());
+        }
+        // verify_share checked that the value is below n.
+        let mut value = Scalar::from_bytes(share.value.expose_secret()).unwrap_or(Scalar([0; 4]));
+        secret = secret.add(&value.mul(&lagrange));
+        value.wipe();
+    }
+    let out = Secret::new(secret.to_bytes());
+    secret.wipe();
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+    use std::vec::Vec;
+
+    #[test]
+    fn field() {
+        // The AES field: 0x53 and 0xca are inverses, and {57} {83} = {c1}.
+        assert_eq!(gf_mul(0x53, 0xca), 1);
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        for a in 1..=255 {
+            assert_eq!(gf_mul(a, gf_invert(a)), 1, "{}", a);
+        }
+    }
+
+    #[test]
+    fn threshold() {
+        let secret: [u8; 64] = core::array::from_fn(|i| i as u8 * 3);
+        let mut shares = vec![Share::<64>::default(); 5];
+        split_secret(&secret, 3, &mut shares).unwrap();
+        assert!(shares.iter().enumerate().all(|(i, share)| share.index as usize == i + 1));
+        // Every three shares, in any order, and all five recombine it.
+        for a in 0..5 {
+            for b in 0..5 {
+                for c in 0..5 {
+                    if a != b && b != c && a != c {
+                        let subset = [shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                        assert_eq!(combine_shares(&subset).unwrap().expose_secret(), &secret);
+                    }
+                }
+            }
+        }
+        assert_eq!(combine_shares(&shares).unwrap().expose_secret(), &secret);
+        // Two don't.
+        assert_ne!(combine_shares(&shares[1..3]).unwrap().expose_secret(), &secret);
+
+        let mut single = vec![Share::<64>::default(); 2];
+        split_secret(&secret, 1, &mut single).unwrap();
+        assert_eq!(single[1].value.expose_secret(), &secret);
+        assert!(split_secret(&secret, 0, &mut shares).is_err());
+        assert!(split_secret(&secret, 6, &mut shares).is_err());
+        assert!(split_secret(&secret, 1, &mut vec![Share::default(); MAX_SHARES + 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_indices() {
+        let mut shares = vec![Share::<16>::default(); 3];
+        split_secret(&[9; 16], 2, &mut shares).unwrap();
+        let invalid = Some(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        assert_eq!(combine_shares(&[shares[0].clone(), shares[0].clone()]).err(), invalid);
+        let mut zero = shares[1].clone();
+        zero.index = 0;
+        assert_eq!(combine_shares(&[shares[0].clone(), zero]).err(), invalid);
+        assert_eq!(combine_shares::<16>(&[]).err(), invalid);
+
+        let mut shares = vec![Share::<32>::default(); 3];
+        let mut commitments = [sgx_ec256_public_t::default(); 2];
+        split_verifiable(&[0x11; 32], &mut shares, &mut commitments).unwrap();
+        let duplicate = [shares[2].clone(), shares[2].clone()];
+        assert_eq!(combine_verifiable(&duplicate, &commitments).err(), invalid);
+        let mut zero = shares[1].clone();
+        zero.index = 0;
+        assert!(!verify_share(&zero, &commitments));
+        assert_eq!(combine_verifiable(&[shares[0].clone(), zero], &commitments).err(), invalid);
+    }
+
+    #[test]
+    fn feldman() {
+        let secret = [0x11_u8; 32];
+        let mut shares = vec![Share::<32>::default(); 5];
+        let mut commitments = [sgx_ec256_public_t::default(); 3];
+        split_verifiable(&secret, &mut shares, &mut commitments).unwrap();
+        assert!(shares.iter().all(|share| verify_share(share, &commitments)));
+        assert_eq!(
+            combine_verifiable(&shares[2..], &commitments).unwrap().expose_secret(),
+            &secret
+        );
+        let subset = [shares[4].clone(), shares[0].clone(), shares[2].clone()];
+        assert_eq!(combine_verifiable(&subset, &commitments).unwrap().expose_secret(), &secret);
+        assert_eq!(
+            combine_verifiable(&shares[..2], &commitments).err(),
+            Some(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+        );
+
+        // A tampered value, or a value under another index, fails to verify.
+        let mut tampered: Vec<Share<32>> = shares.clone();
+        tampered[1].value.expose_secret_mut()[31] ^= 1;
+        assert!(!verify_share(&tampered[1], &commitments));
+        assert_eq!(
+            combine_verifiable(&tampered, &commitments).err(),
+            Some(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE)
+        );
+        let mut moved = shares[1].clone();
+        moved.index = 3;
+        assert!(!verify_share(&moved, &commitments));
+        // So does a share against the commitments of another sharing.
+        let mut others = [sgx_ec256_public_t::default(); 3];
+        split_verifiable(&secret, &mut vec![Share::default(); 3], &mut others).unwrap();
+        assert!(!verify_share(&shares[0], &others));
+        assert!(!verify_share(&shares[0], &[]));
+
+        assert!(split_verifiable(&[0; 32], &mut shares, &mut commitments).is_err());
+        assert!(split_verifiable(&[0xff; 32], &mut shares, &mut commitments).is_err());
+        assert!(split_verifiable(&secret, &mut shares[..2], &mut commitments).is_err());
+    }
+}
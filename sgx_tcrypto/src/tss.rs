@@ -0,0 +1,1166 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Two-party ECDSA over secp256k1, after Lindell's 2017 protocol, for
+//! wallets whose key is split between the enclave and another co-signer.
+//!
+//! Party 1 holds a share `x1` of the key and a Paillier key, and party 2
+//! holds the share `x2` along with the encryption of `x1`; the key itself,
+//! `x1 x2`, never exists anywhere. Either party can run in the enclave, and
+//! every type is named after the party using it:
+//!
+//! 1. Key generation exchanges [`KeyGenMsg1`] to [`KeyGenMsg7`], in which
+//!    party 1 proves its Paillier modulus well formed and its encrypted
+//!    share small and consistent with its public share.
+//! 2. Presigning exchanges [`PresignMsg1`] to [`PresignMsg3`], agreeing on a
+//!    nonce before the message is known.
+//! 3. Signing uses up a presignature: party 2 sends a [`SignMsg`], from
+//!    which party 1 finishes the signature and verifies it.
+//!
+//! Protocol states refuse messages out of order with
+//! `SGX_ERROR_INVALID_STATE`. Malformed messages fail with
+//! `SGX_ERROR_INVALID_PARAMETER` and failed proofs with
+//! `SGX_ERROR_INVALID_SIGNATURE`, after which the state is unusable.
+//!
+//! Messages are plain byte arrays for the caller to carry over its own
+//! transport. Those of key generation, like its states, run to tens of
+//! kilobytes, so enclaves generating keys need stacks to match.
+
+use crate::ctbignum::{for_each_small_prime, Uint};
+use crate::paillier::{self, Nat, Nat2, Prime, PublicKey, SecretKey};
+use crate::secp256k1::{
+    Point, Scalar, Secp256k1PublicKey, Secp256k1RecoverableSignature, Secp256k1Signature,
+};
+use crate::secret::SecretBytes;
+use crate::sha256::Sha256;
+use crate::util::{ct_eq, read_rand};
+use core::fmt;
+use sgx_types::*;
+
+/// The rounds of the range proof, each halving the chance of cheating.
+const RANGE_ROUNDS: usize = 40;
+/// The roots proving the Paillier modulus coprime with its totient.
+const MODULUS_PROOF_ROUNDS: usize = 8;
+/// Moduli with a factor below this bound are rejected outright, leaving
+/// each root of the proof at most that chance of being forged.
+const SMALL_FACTOR_BOUND: u32 = 1 << 16;
+
+/// `floor(q / 3)`, for `q` the group order: party 1's share is below it.
+const THIRD: Uint<4> = Uint([
+    0x3ff0_ca2e_f012_15c0,
+    0xe8e4_f44c_e518_3569,
+    0x5555_5555_5555_5554,
+    0x5555_5555_5555_5555,
+]);
+const TWO_THIRDS: Uint<4> = Uint([
+    0x7fe1_945d_e024_2b80,
+    0xd1c9_e899_ca30_6ad2,
+    0xaaaa_aaaa_aaaa_aaa9,
+    0xaaaa_aaaa_aaaa_aaaa,
+]);
+/// `2^256 mod q`.
+const R256: Scalar = Scalar([0x402d_a173_2fc9_bebf, 0x4551_2319_50b7_5fc4, 1, 0]);
+
+const DLOG_P1_SHARE: &str = "tss/dlog/p1-share";
+const DLOG_P2_SHARE: &str = "tss/dlog/p2-share";
+const DLOG_P1_NONCE: &str = "tss/dlog/p1-nonce";
+const DLOG_P2_NONCE: &str = "tss/dlog/p2-nonce";
+const COMMIT_SHARE: &str = "tss/commit/share";
+const COMMIT_CHALLENGE: &str = "tss/commit/challenge";
+const COMMIT_PDL: &str = "tss/commit/pdl";
+const COMMIT_PDL_POINT: &str = "tss/commit/pdl-point";
+const COMMIT_NONCE: &str = "tss/commit/nonce";
+const MODULUS_PROOF: &str = "tss/paillier-modulus";
+
+/// The step of poisoned states.
+const POISONED: u8 = u8::MAX;
+
+fn hash(label: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let mut sha = Sha256::new();
+    sha.update(label.as_bytes());
+    for part in parts {
+        sha.update(&(part.len() as u64).to_be_bytes());
+        sha.update(part);
+    }
+    sha.finalize()
+}
+
+fn random_blind() -> SgxResult<[u8; 32]> {
+    let mut blind = [0_u8; 32];
+    read_rand(&mut blind)?;
+    Ok(blind)
+}
+
+/// Commits to up to three values with a random blind.
+fn commit(label: &str, blind: &[u8; 32], values: &[&[u8]]) -> [u8; 32] {
+    let mut parts: [&[u8]; 4] = [&[]; 4];
+    parts[0] = blind;
+    parts[1..=values.len()].copy_from_slice(values);
+    hash(label, &parts[..=values.len()])
+}
+
+fn check_opening(
+    label: &str,
+    commitment: &[u8; 32],
+    blind: &[u8; 32],
+    values: &[&[u8]],
+) -> SgxError {
+    if ct_eq(&commit(label, blind, values), commitment) {
+        Ok(())
+    } else {
+        Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE)
+    }
+}
+
+fn check_step(step: u8, expected: u8) -> SgxError {
+    if step == expected {
+        Ok(())
+    } else {
+        Err(sgx_status_t::SGX_ERROR_INVALID_STATE)
+    }
+}
+
+fn finish_step<T>(step: &mut u8, result: &SgxResult<T>) {
+    *step = if result.is_ok() { *step + 1 } else { POISONED };
+}
+
+fn parse_point(bytes: &[u8; 33]) -> SgxResult<Point> {
+    Point::from_sec1(bytes).ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+}
+
+/// Encodes a point computed from non-zero secrets, which can't be the
+/// identity but for negligible chance.
+fn encode_point(point: &Point) -> SgxResult<[u8; 33]> {
+    point.to_sec1().ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)
+}
+
+fn same_point(a: &Point, b: &Point) -> bool {
+    match (a.to_sec1(), b.to_sec1()) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn parse_scalar(bytes: &[u8; 32]) -> SgxResult<Scalar> {
+    Scalar::from_bytes(bytes).ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+}
+
+fn parse_ciphertext(key: &PublicKey, bytes: &[u8; paillier::CIPHERTEXT_SIZE]) -> SgxResult<Nat2> {
+    match Nat2::from_be_bytes(bytes) {
+        Some(c) if key.is_ciphertext(&c) => Ok(c),
+        _ => Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER),
+    }
+}
+
+fn to_bytes<const L: usize, const N: usize>(x: &Uint<L>) -> [u8; N] {
+    let mut out = [0_u8; N];
+    x.write_be_bytes(&mut out);
+    out
+}
+
+fn widen<const L: usize>(scalar: &Scalar) -> Uint<L> {
+    Uint(scalar.0).resize()
+}
+
+/// Returns `q^2`, the bound of the masks hiding products in ciphertexts.
+fn order_squared() -> Uint<8> {
+    let q = Uint(Scalar::order()).resize::<8>();
+    q.wrapping_mul(&q)
+}
+
+/// Reduces an integer modulo the group order.
+fn reduce<const L: usize>(x: &Uint<L>) -> Scalar {
+    let mut acc = Scalar::ZERO;
+    for chunk in x.0.chunks(4).rev() {
+        let mut words = [0_u64; 4];
+        words[..chunk.len()].copy_from_slice(chunk);
+        acc = acc.mul(&R256).add(&Scalar::from_words_reduced(&words));
+    }
+    acc
+}
+
+/// A Schnorr proof of knowledge of the discrete log of a point, made
+/// non-interactive with a hash bound to the role of the point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DlogProof {
+    pub commitment: [u8; 33],
+    pub response: [u8; 32],
+}
+
+impl DlogProof {
+    fn challenge(label: &str, public: &[u8; 33], commitment: &[u8; 33]) -> Scalar {
+        Scalar::from_bytes_reduced(&hash(label, &[public, commitment]))
+    }
+
+    fn prove(label: &str, secret: &Scalar, public: &[u8; 33]) -> SgxResult<DlogProof> {
+        let mut k = Scalar::random()?;
+        let commitment = encode_point(&Point::generator().mul(&k.0))?;
+        let e = DlogProof::challenge(label, public, &commitment);
+        let response = k.add(&e.mul(secret)).to_bytes();
+        k.wipe();
+        Ok(DlogProof { commitment, response })
+    }
+
+    fn verify(&self, label: &str, public: &[u8; 33]) -> SgxError {
+        let point = parse_point(public)?;
+        let commitment = parse_point(&self.commitment)?;
+        let response = parse_scalar(&self.response)?;
+        let e = DlogProof::challenge(label, public, &self.commitment);
+        let rhs = commitment.add(&point.mul(&e.0));
+        if same_point(&Point::generator().mul(&response.0), &rhs) {
+            Ok(())
+        } else {
+            Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE)
+        }
+    }
+}
+
+/// Returns the `i`-th value whose `n`-th root proves `n` coprime with its
+/// totient, derived from `n` so that the prover can't choose it.
+fn modulus_proof_base(n: &[u8; paillier::MODULUS_SIZE], i: usize) -> Nat {
+    let mut bytes = [0_u8; paillier::MODULUS_SIZE];
+    for (j, chunk) in bytes.chunks_mut(32).enumerate() {
+        chunk.copy_from_slice(&hash(MODULUS_PROOF, &[n, &[i as u8, j as u8]]));
+    }
+    // n has its top bit set, so clearing it leaves the value below n.
+    bytes[0] &= 0x7f;
+    Nat::from_be_bytes(&bytes).unwrap_or(Nat::ZERO)
+}
+
+fn verify_modulus(
+    key: &PublicKey,
+    n: &[u8; paillier::MODULUS_SIZE],
+    roots: &[[u8; paillier::MODULUS_SIZE]; MODULUS_PROOF_ROUNDS],
+) -> SgxError {
+    let invalid = Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+    let mut coprime = true;
+    for_each_small_prime(SMALL_FACTOR_BOUND, |p| {
+        coprime = key.n().rem_u32_vartime(p) != 0;
+        coprime
+    });
+    if !coprime {
+        return invalid;
+    }
+    for (i, root) in roots.iter().enumerate() {
+        let root = Nat::from_be_bytes(root).ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+        if key.mod_n().pow_vartime(&root, key.n()) != modulus_proof_base(n, i) {
+            return invalid;
+        }
+    }
+    Ok(())
+}
+
+/// The answer to one round of the range proof on party 1's share.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Copy)]
+pub enum RangeResponse {
+    /// Opens both ciphertexts of the pair, one of which is in `[q/3, 2q/3]`
+    /// and the other `q/3` less.
+    Open {
+        values: [[u8; 32]; 2],
+        randomness: [[u8; paillier::MODULUS_SIZE]; 2],
+    },
+    /// Opens the product of the encrypted share and the ciphertext `index`
+    /// of the pair, which decrypts to `value` in `[q/3, 2q/3]`.
+    Shifted {
+        index: u8,
+        value: [u8; 32],
+        randomness: [u8; paillier::MODULUS_SIZE],
+    },
+}
+
+/// The secrets behind one pair of range proof ciphertexts.
+#[derive(Clone, Copy)]
+struct RangeSecret {
+    values: [Uint<4>; 2],
+    randomness: [Nat; 2],
+    /// The index of the value in `[q/3, 2q/3]`.
+    high: u64,
+}
+
+impl RangeSecret {
+    const EMPTY: RangeSecret =
+        RangeSecret { values: [Uint::ZERO; 2], randomness: [Nat::ZERO; 2], high: 0 };
+
+    fn generate(key: &PublicKey) -> SgxResult<RangeSecret> {
+        let mut swap = [0_u8; 1];
+        read_rand(&mut swap)?;
+        let high = (swap[0] & 1) as u64;
+        let bound = THIRD.wrapping_add(&Uint::from_u64(1));
+        let w1 = THIRD.wrapping_add(&Uint::random_below(&bound)?);
+        let w2 = w1.wrapping_sub(&THIRD);
+        let values = [Uint::select(&w1, &w2, high), Uint::select(&w2, &w1, high)];
+        let mut randomness = [Nat::ZERO; 2];
+        for r in randomness.iter_mut() {
+            *r = key.random_unit()?;
+        }
+        Ok(RangeSecret { values, randomness, high })
+    }
+
+    fn respond(&self, key: &PublicKey, challenge: u64, share: &Scalar, r: &Nat) -> RangeResponse {
+        if challenge == 0 {
+            return RangeResponse::Open {
+                values: [to_bytes(&self.values[0]), to_bytes(&self.values[1])],
+                randomness: [to_bytes(&self.randomness[0]), to_bytes(&self.randomness[1])],
+            };
+        }
+        // Open x + w for whichever w keeps the sum in [q/3, 2q/3], without
+        // revealing through timing whether it is the higher one.
+        let x = Uint(share.0);
+        let high = x.wrapping_add(&Uint::select(&self.values[0], &self.values[1], self.high));
+        let low = x.wrapping_add(&Uint::select(&self.values[1], &self.values[0], self.high));
+        let use_low = TWO_THIRDS.lt(&high) as u64;
+        let index = self.high ^ use_low;
+        let rj = Uint::select(&self.randomness[0], &self.randomness[1], index);
+        RangeResponse::Shifted {
+            index: index as u8,
+            value: to_bytes(&Uint::select(&high, &low, use_low)),
+            randomness: to_bytes(&key.mod_n().mul_mod(r, &rj)),
+        }
+    }
+
+    fn wipe(&mut self) {
+        for value in self.values.iter_mut() {
+            value.wipe();
+        }
+        for r in self.randomness.iter_mut() {
+            r.wipe();
+        }
+        self.high = 0;
+    }
+}
+
+fn verify_range(
+    key: &PublicKey,
+    encrypted_share: &Nat2,
+    commitments: &[Nat2; 2],
+    challenge: u64,
+    response: &RangeResponse,
+) -> SgxError {
+    let invalid = Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+    let malformed = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    match (challenge, response) {
+        (0, RangeResponse::Open { values, randomness }) => {
+            let mut plain = [Uint::<4>::ZERO; 2];
+            for i in 0..2 {
+                plain[i] = Uint::from_be_bytes(&values[i]).ok_or(malformed)?;
+                let r = Nat::from_be_bytes(&randomness[i]).ok_or(malformed)?;
+                if key.encrypt_with(&plain[i].resize(), &r) != commitments[i] {
+                    return invalid;
+                }
+            }
+            let apart = |lo: &Uint<4>, hi: &Uint<4>| {
+                !THIRD.lt(lo) && lo.wrapping_add(&THIRD) == *hi
+            };
+            if apart(&plain[0], &plain[1]) || apart(&plain[1], &plain[0]) {
+                Ok(())
+            } else {
+                invalid
+            }
+        }
+        (1, RangeResponse::Shifted { index, value, randomness }) => {
+            let commitment = commitments.get(*index as usize).ok_or(malformed)?;
+            let value = Uint::<4>::from_be_bytes(value).ok_or(malformed)?;
+            let r = Nat::from_be_bytes(randomness).ok_or(malformed)?;
+            if value.lt(&THIRD) || TWO_THIRDS.lt(&value) {
+                return invalid;
+            }
+            if key.encrypt_with(&value.resize(), &r) != key.add(encrypted_share, commitment) {
+                return invalid;
+            }
+            Ok(())
+        }
+        _ => invalid,
+    }
+}
+
+fn challenge_bit(challenge: &[u8; RANGE_ROUNDS / 8], i: usize) -> u64 {
+    ((challenge[i / 8] >> (i % 8)) & 1) as u64
+}
+
+/// Party 1's commitment to its public share and proof.
+#[derive(Clone, Copy)]
+pub struct KeyGenMsg1 {
+    pub commitment: [u8; 32],
+}
+
+/// Party 2's public share, and its commitment to the challenge of the
+/// range proof.
+#[derive(Clone, Copy)]
+pub struct KeyGenMsg2 {
+    pub public_share: [u8; 33],
+    pub proof: DlogProof,
+    pub challenge_commitment: [u8; 32],
+}
+
+/// Party 1's public share, Paillier key and encrypted share, with the
+/// first half of the range proof.
+#[derive(Clone, Copy)]
+pub struct KeyGenMsg3 {
+    pub public_share: [u8; 33],
+    pub proof: DlogProof,
+    pub blind: [u8; 32],
+    pub paillier_n: [u8; paillier::MODULUS_SIZE],
+    pub modulus_proof: [[u8; paillier::MODULUS_SIZE]; MODULUS_PROOF_ROUNDS],
+    pub encrypted_share: [u8; paillier::CIPHERTEXT_SIZE],
+    pub range_commitments: [[[u8; paillier::CIPHERTEXT_SIZE]; 2]; RANGE_ROUNDS],
+}
+
+/// Party 2's range proof challenge, and its challenge proving that the
+/// encrypted share decrypts to the discrete log of party 1's public share.
+#[derive(Clone, Copy)]
+pub struct KeyGenMsg4 {
+    pub challenge: [u8; RANGE_ROUNDS / 8],
+    pub challenge_blind: [u8; 32],
+    pub pdl_ciphertext: [u8; paillier::CIPHERTEXT_SIZE],
+    pub pdl_commitment: [u8; 32],
+}
+
+/// Party 1's answers to the range proof, and its commitment to the
+/// decryption of party 2's challenge.
+#[derive(Clone, Copy)]
+pub struct KeyGenMsg5 {
+    pub range_responses: [RangeResponse; RANGE_ROUNDS],
+    pub pdl_point_commitment: [u8; 32],
+}
+
+/// Party 2's opening of its challenge, `a` and `b` as in `a x1 + b`.
+#[derive(Clone, Copy)]
+pub struct KeyGenMsg6 {
+    pub a: [u8; 32],
+    pub b: [u8; 64],
+    pub blind: [u8; 32],
+}
+
+/// Party 1's opening of the point `(a x1 + b) G`.
+#[derive(Clone, Copy)]
+pub struct KeyGenMsg7 {
+    pub point: [u8; 33],
+    pub blind: [u8; 32],
+}
+
+/// Party 1's side of key generation.
+pub struct Party1KeyGen {
+    step: u8,
+    share: Scalar,
+    public_share: [u8; 33],
+    proof: DlogProof,
+    blind: [u8; 32],
+    other_share: Point,
+    challenge_commitment: [u8; 32],
+    paillier: Option<SecretKey>,
+    share_randomness: Nat,
+    range: [RangeSecret; RANGE_ROUNDS],
+    pdl_commitment: [u8; 32],
+    pdl_plaintext: Nat,
+    pdl_point: [u8; 33],
+    pdl_blind: [u8; 32],
+}
+
+impl Party1KeyGen {
+    /// Picks a share in `[1, q/3)` and commits to it.
+    pub fn new() -> SgxResult<(Party1KeyGen, KeyGenMsg1)> {
+        let share = loop {
+            let share = Scalar::random()?;
+            if Uint(share.0).lt(&THIRD) {
+                break share;
+            }
+        };
+        let public_share = encode_point(&Point::generator().mul(&share.0))?;
+        let proof = DlogProof::prove(DLOG_P1_SHARE, &share, &public_share)?;
+        let blind = random_blind()?;
+        let commitment =
+            commit(COMMIT_SHARE, &blind, &[&public_share, &proof.commitment, &proof.response]);
+        let state = Party1KeyGen {
+            step: 1,
+            share,
+            public_share,
+            proof,
+            blind,
+            other_share: Point::identity(),
+            challenge_commitment: [0; 32],
+            paillier: None,
+            share_randomness: Nat::ZERO,
+            range: [RangeSecret::EMPTY; RANGE_ROUNDS],
+            pdl_commitment: [0; 32],
+            pdl_plaintext: Nat::ZERO,
+            pdl_point: [0; 33],
+            pdl_blind: [0; 32],
+        };
+        Ok((state, KeyGenMsg1 { commitment }))
+    }
+
+    /// Checks party 2's share, then generates the Paillier key, which takes
+    /// a while.
+    pub fn handle_msg2(&mut self, msg: &KeyGenMsg2) -> SgxResult<KeyGenMsg3> {
+        check_step(self.step, 1)?;
+        let result = self.respond_msg2(msg);
+        finish_step(&mut self.step, &result);
+        result
+    }
+
+    fn respond_msg2(&mut self, msg: &KeyGenMsg2) -> SgxResult<KeyGenMsg3> {
+        msg.proof.verify(DLOG_P2_SHARE, &msg.public_share)?;
+        self.other_share = parse_point(&msg.public_share)?;
+        self.challenge_commitment = msg.challenge_commitment;
+
+        let paillier = SecretKey::generate()?;
+        let key = *paillier.public();
+        let n: [u8; paillier::MODULUS_SIZE] = to_bytes(key.n());
+        let mut modulus_proof = [[0_u8; paillier::MODULUS_SIZE]; MODULUS_PROOF_ROUNDS];
+        for (i, root) in modulus_proof.iter_mut().enumerate() {
+            *root = to_bytes(&paillier.nth_root(&modulus_proof_base(&n, i)));
+        }
+        let (encrypted_share, r) = key.encrypt(&widen(&self.share))?;
+        self.share_randomness = r;
+
+        let mut range_commitments = [[[0_u8; paillier::CIPHERTEXT_SIZE]; 2]; RANGE_ROUNDS];
+        for (secret, pair) in self.range.iter_mut().zip(range_commitments.iter_mut()) {
+            *secret = RangeSecret::generate(&key)?;
+            let openings = secret.values.iter().zip(secret.randomness.iter());
+            for (c, (value, r)) in pair.iter_mut().zip(openings) {
+                *c = to_bytes(&key.encrypt_with(&value.resize(), r));
+            }
+        }
+        self.paillier = Some(paillier);
+        Ok(KeyGenMsg3 {
+            public_share: self.public_share,
+            proof: self.proof,
+            blind: self.blind,
+            paillier_n: n,
+            modulus_proof,
+            encrypted_share: to_bytes(&encrypted_share),
+            range_commitments,
+            })
+    }
+
+    /// Answers the range proof challenge, and decrypts party 2's challenge
+    /// only to commit to it.
+    pub fn handle_msg4(&mut self, msg: &KeyGenMsg4) -> SgxResult<KeyGenMsg5> {
+        check_step(self.step, 2)?;
+        let result = self.respond_msg4(msg);
+        finish_step(&mut self.step, &result);
+        result
+    }
+
+    fn respond_msg4(&mut self, msg: &KeyGenMsg4) -> SgxResult<KeyGenMsg5> {
+        check_opening(COMMIT_CHALLENGE, &self.challenge_commitment, &msg.challenge_blind, &[
+            &msg.challenge,
+        ])?;
+        let paillier = self.paillier.as_ref().ok_or(sgx_status_t::SGX_ERROR_INVALID_STATE)?;
+        let key = paillier.public();
+        let mut range_responses = [RangeResponse::Shifted {
+            index: 0,
+            value: [0; 32],
+            randomness: [0; paillier::MODULUS_SIZE],
+        }; RANGE_ROUNDS];
+        for (i, (response, secret)) in range_responses.iter_mut().zip(self.range.iter()).enumerate()
+        {
+            let challenge = challenge_bit(&msg.challenge, i);
+            *response = secret.respond(key, challenge, &self.share, &self.share_randomness);
+        }
+
+        let ciphertext = parse_ciphertext(key, &msg.pdl_ciphertext)?;
+        self.pdl_commitment = msg.pdl_commitment;
+        self.pdl_plaintext = paillier.decrypt(&ciphertext);
+        self.pdl_point = encode_point(&Point::generator().mul(&reduce(&self.pdl_plaintext).0))?;
+        self.pdl_blind = random_blind()?;
+        Ok(KeyGenMsg5 {
+            range_responses,
+            pdl_point_commitment: commit(COMMIT_PDL_POINT, &self.pdl_blind, &[&self.pdl_point]),
+        })
+    }
+
+    /// Checks that party 2's challenge was well formed, which makes it safe
+    /// to reveal its decryption, and returns the key.
+    pub fn handle_msg6(mut self, msg: &KeyGenMsg6) -> SgxResult<(Party1Key, KeyGenMsg7)> {
+        check_step(self.step, 3)?;
+        check_opening(COMMIT_PDL, &self.pdl_commitment, &msg.blind, &[&msg.a, &msg.b])?;
+        let a = parse_scalar(&msg.a)?;
+        let b = Uint::<8>::from_be_bytes(&msg.b).unwrap_or(Uint::ZERO);
+        if !b.lt(&order_squared()) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let expected = widen::<32>(&a).wrapping_mul(&widen(&self.share)).wrapping_add(&b.resize());
+        if !expected.ct_eq(&self.pdl_plaintext) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+        }
+
+        let paillier = self.paillier.take().ok_or(sgx_status_t::SGX_ERROR_INVALID_STATE)?;
+        let public = self.other_share.mul(&self.share.0);
+        let public_key =
+            Secp256k1PublicKey::from_point(&public).ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)?;
+        let key = Party1Key { share: self.share, paillier, public_key };
+        Ok((key, KeyGenMsg7 { point: self.pdl_point, blind: self.pdl_blind }))
+    }
+}
+
+impl Drop for Party1KeyGen {
+    fn drop(&mut self) {
+        self.share.wipe();
+        self.share_randomness.wipe();
+        for secret in self.range.iter_mut() {
+            secret.wipe();
+        }
+        self.pdl_plaintext.wipe();
+    }
+}
+
+/// Party 2's side of key generation.
+pub struct Party2KeyGen {
+    step: u8,
+    share: Scalar,
+    commitment: [u8; 32],
+    challenge: [u8; RANGE_ROUNDS / 8],
+    challenge_blind: [u8; 32],
+    other_share: Point,
+    paillier: Option<PublicKey>,
+    encrypted_share: Nat2,
+    range_commitments: [[Nat2; 2]; RANGE_ROUNDS],
+    pdl_a: Scalar,
+    pdl_b: Uint<8>,
+    pdl_blind: [u8; 32],
+    pdl_point: Point,
+    pdl_point_commitment: [u8; 32],
+}
+
+impl Party2KeyGen {
+    /// Picks a share and the range proof challenge.
+    pub fn new(msg: &KeyGenMsg1) -> SgxResult<(Party2KeyGen, KeyGenMsg2)> {
+        let share = Scalar::random()?;
+        let public_share = encode_point(&Point::generator().mul(&share.0))?;
+        let proof = DlogProof::prove(DLOG_P2_SHARE, &share, &public_share)?;
+        let mut challenge = [0_u8; RANGE_ROUNDS / 8];
+        read_rand(&mut challenge)?;
+        let challenge_blind = random_blind()?;
+        let challenge_commitment = commit(COMMIT_CHALLENGE, &challenge_blind, &[&challenge]);
+        let state = Party2KeyGen {
+            step: 1,
+            share,
+            commitment: msg.commitment,
+            challenge,
+            challenge_blind,
+            other_share: Point::identity(),
+            paillier: None,
+            encrypted_share: Nat2::ZERO,
+            range_commitments: [[Nat2::ZERO; 2]; RANGE_ROUNDS],
+            pdl_a: Scalar::ZERO,
+            pdl_b: Uint::ZERO,
+            pdl_blind: [0; 32],
+            pdl_point: Point::identity(),
+            pdl_point_commitment: [0; 32],
+        };
+        Ok((state, KeyGenMsg2 { public_share, proof, challenge_commitment }))
+    }
+
+    /// Checks party 1's share and Paillier modulus, and challenges it to
+    /// decrypt `a x1 + b` only if its encrypted share is `x1`.
+    pub fn handle_msg3(&mut self, msg: &KeyGenMsg3) -> SgxResult<KeyGenMsg4> {
+        check_step(self.step, 1)?;
+        let result = self.respond_msg3(msg);
+        finish_step(&mut self.step, &result);
+        result
+    }
+
+    fn respond_msg3(&mut self, msg: &KeyGenMsg3) -> SgxResult<KeyGenMsg4> {
+        let proof = &msg.proof;
+        check_opening(COMMIT_SHARE, &self.commitment, &msg.blind, &[
+            &msg.public_share,
+            &proof.commitment,
+            &proof.response,
+        ])?;
+        proof.verify(DLOG_P1_SHARE, &msg.public_share)?;
+        self.other_share = parse_point(&msg.public_share)?;
+
+        let n = Nat::from_be_bytes(&msg.paillier_n).unwrap_or(Nat::ZERO);
+        let key = PublicKey::new(&n).ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+        verify_modulus(&key, &msg.paillier_n, &msg.modulus_proof)?;
+        self.encrypted_share = parse_ciphertext(&key, &msg.encrypted_share)?;
+        for (pair, bytes) in self.range_commitments.iter_mut().zip(msg.range_commitments.iter()) {
+            for i in 0..2 {
+                pair[i] = parse_ciphertext(&key, &bytes[i])?;
+            }
+        }
+
+        self.pdl_a = Scalar::random()?;
+        self.pdl_b = Uint::random_below(&order_squared())?;
+        let scaled = key.scale(&self.encrypted_share, &Uint(self.pdl_a.0), 256);
+        let pdl_ciphertext = key.add(&scaled, &key.encrypt(&self.pdl_b.resize())?.0);
+        self.pdl_blind = random_blind()?;
+        let a = self.pdl_a.to_bytes();
+        let b: [u8; 64] = to_bytes(&self.pdl_b);
+        let pdl_commitment = commit(COMMIT_PDL, &self.pdl_blind, &[&a, &b]);
+        let bg = Point::generator().mul(&reduce(&self.pdl_b).0);
+        self.pdl_point = self.other_share.mul(&self.pdl_a.0).add(&bg);
+        self.paillier = Some(key);
+        Ok(KeyGenMsg4 {
+            challenge: self.challenge,
+            challenge_blind: self.challenge_blind,
+            pdl_ciphertext: to_bytes(&pdl_ciphertext),
+            pdl_commitment,
+        })
+    }
+
+    /// Checks the range proof, and opens the challenge.
+    pub fn handle_msg5(&mut self, msg: &KeyGenMsg5) -> SgxResult<KeyGenMsg6> {
+        check_step(self.step, 2)?;
+        let result = self.respond_msg5(msg);
+        finish_step(&mut self.step, &result);
+        result
+    }
+
+    fn respond_msg5(&mut self, msg: &KeyGenMsg5) -> SgxResult<KeyGenMsg6> {
+        let key = self.paillier.as_ref().ok_or(sgx_status_t::SGX_ERROR_INVALID_STATE)?;
+        let rounds = self.range_commitments.iter().zip(msg.range_responses.iter());
+        for (i, (pair, response)) in rounds.enumerate() {
+            let challenge = challenge_bit(&self.challenge, i);
+            verify_range(key, &self.encrypted_share, pair, challenge, response)?;
+        }
+        self.pdl_point_commitment = msg.pdl_point_commitment;
+        Ok(KeyGenMsg6 { a: self.pdl_a.to_bytes(), b: to_bytes(&self.pdl_b), blind: self.pdl_blind })
+    }
+
+    /// Checks that party 1 decrypted the challenge to the expected point,
+    /// and returns the key.
+    pub fn handle_msg7(self, msg: &KeyGenMsg7) -> SgxResult<Party2Key> {
+        check_step(self.step, 3)?;
+        check_opening(COMMIT_PDL_POINT, &self.pdl_point_commitment, &msg.blind, &[&msg.point])?;
+        if !same_point(&parse_point(&msg.point)?, &self.pdl_point) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+        }
+        let paillier = self.paillier.ok_or(sgx_status_t::SGX_ERROR_INVALID_STATE)?;
+        let public = self.other_share.mul(&self.share.0);
+        let public_key =
+            Secp256k1PublicKey::from_point(&public).ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)?;
+        Ok(Party2Key {
+            share: self.share,
+            paillier,
+            encrypted_share: self.encrypted_share,
+            public_key,
+        })
+    }
+}
+
+impl Drop for Party2KeyGen {
+    fn drop(&mut self) {
+        self.share.wipe();
+        self.pdl_a.wipe();
+        self.pdl_b.wipe();
+    }
+}
+
+/// The size of [`Party1Key::to_bytes`].
+pub const PARTY1_KEY_SIZE: usize = 32 + paillier::MODULUS_SIZE + 33;
+/// The size of [`Party2Key::to_bytes`].
+pub const PARTY2_KEY_SIZE: usize = 32 + paillier::MODULUS_SIZE + paillier::CIPHERTEXT_SIZE + 33;
+
+/// Party 1's share of a key: `x1` and the Paillier secret key.
+pub struct Party1Key {
+    share: Scalar,
+    paillier: SecretKey,
+    public_key: Secp256k1PublicKey,
+}
+
+impl Party1Key {
+    /// Returns the public key of the pair of shares.
+    pub fn public_key(&self) -> Secp256k1PublicKey {
+        self.public_key
+    }
+
+    /// Returns `x1 || p || q || Q`, with `p` and `q` the Paillier primes and
+    /// `Q` the compressed public key, e.g. to seal it.
+    pub fn to_bytes(&self) -> SecretBytes<PARTY1_KEY_SIZE> {
+        let mut secret = SecretBytes::zeroed();
+        let out = secret.expose_secret_mut();
+        let (p, q) = self.paillier.primes();
+        let half = paillier::MODULUS_SIZE / 2;
+        out[..32].copy_from_slice(&self.share.to_bytes());
+        p.write_be_bytes(&mut out[32..32 + half]);
+        q.write_be_bytes(&mut out[32 + half..32 + 2 * half]);
+        out[32 + 2 * half..].copy_from_slice(&self.public_key.to_compressed());
+        secret
+    }
+
+    /// Parses the output of [`Party1Key::to_bytes`], failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` if it is malformed.
+    pub fn from_bytes(bytes: &[u8; PARTY1_KEY_SIZE]) -> SgxResult<Party1Key> {
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        let half = paillier::MODULUS_SIZE / 2;
+        let mut share = [0_u8; 32];
+        share.copy_from_slice(&bytes[..32]);
+        let share = parse_scalar(&share)?;
+        let p = Prime::from_be_bytes(&bytes[32..32 + half]).ok_or(invalid)?;
+        let q = Prime::from_be_bytes(&bytes[32 + half..32 + 2 * half]).ok_or(invalid)?;
+        let paillier = SecretKey::from_primes(&p, &q).ok_or(invalid)?;
+        let public_key = Secp256k1PublicKey::from_sec1(&bytes[32 + 2 * half..])?;
+        if share.is_zero() {
+            return Err(invalid);
+        }
+        Ok(Party1Key { share, paillier, public_key })
+    }
+
+    /// Starts presigning, committing to party 1's nonce share.
+    pub fn presign(&self) -> SgxResult<(Party1Presigning, PresignMsg1)> {
+        let nonce = Scalar::random()?;
+        let nonce_share = encode_point(&Point::generator().mul(&nonce.0))?;
+        let proof = DlogProof::prove(DLOG_P1_NONCE, &nonce, &nonce_share)?;
+        let blind = random_blind()?;
+        let commitment =
+            commit(COMMIT_NONCE, &blind, &[&nonce_share, &proof.commitment, &proof.response]);
+        let state = Party1Presigning {
+            nonce,
+            nonce_share,
+            proof,
+            blind,
+            public_key: self.public_key.to_compressed(),
+        };
+        Ok((state, PresignMsg1 { commitment }))
+    }
+}
+
+impl Drop for Party1Key {
+    fn drop(&mut self) {
+        self.share.wipe();
+    }
+}
+
+impl fmt::Debug for Party1Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Party1Key").field("public_key", &self.public_key).finish()
+    }
+}
+
+/// Party 2's share of a key: `x2` and the encryption of `x1`.
+pub struct Party2Key {
+    share: Scalar,
+    paillier: PublicKey,
+    encrypted_share: Nat2,
+    public_key: Secp256k1PublicKey,
+}
+
+impl Party2Key {
+    /// Returns the public key of the pair of shares.
+    pub fn public_key(&self) -> Secp256k1PublicKey {
+        self.public_key
+    }
+
+    /// Returns `x2 || n || Enc(x1) || Q`, with `Q` the compressed public
+    /// key, e.g. to seal it.
+    pub fn to_bytes(&self) -> SecretBytes<PARTY2_KEY_SIZE> {
+        let mut secret = SecretBytes::zeroed();
+        let out = secret.expose_secret_mut();
+        let n = 32 + paillier::MODULUS_SIZE;
+        let c = n + paillier::CIPHERTEXT_SIZE;
+        out[..32].copy_from_slice(&self.share.to_bytes());
+        self.paillier.n().write_be_bytes(&mut out[32..n]);
+        self.encrypted_share.write_be_bytes(&mut out[n..c]);
+        out[c..].copy_from_slice(&self.public_key.to_compressed());
+        secret
+    }
+
+    /// Parses the output of [`Party2Key::to_bytes`], failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` if it is malformed.
+    pub fn from_bytes(bytes: &[u8; PARTY2_KEY_SIZE]) -> SgxResult<Party2Key> {
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        let n = 32 + paillier::MODULUS_SIZE;
+        let c = n + paillier::CIPHERTEXT_SIZE;
+        let mut share = [0_u8; 32];
+        share.copy_from_slice(&bytes[..32]);
+        let share = parse_scalar(&share)?;
+        let modulus = Nat::from_be_bytes(&bytes[32..n]).ok_or(invalid)?;
+        let paillier = PublicKey::new(&modulus).ok_or(invalid)?;
+        let encrypted_share = Nat2::from_be_bytes(&bytes[n..c]).ok_or(invalid)?;
+        if share.is_zero() || !paillier.is_ciphertext(&encrypted_share) {
+            return Err(invalid);
+        }
+        let public_key = Secp256k1PublicKey::from_sec1(&bytes[c..])?;
+        Ok(Party2Key { share, paillier, encrypted_share, public_key })
+    }
+
+    /// Answers party 1's commitment with party 2's nonce share.
+    pub fn presign(&self, msg: &PresignMsg1) -> SgxResult<(Party2Presigning, PresignMsg2)> {
+        let nonce = Scalar::random()?;
+        let nonce_share = encode_point(&Point::generator().mul(&nonce.0))?;
+        let proof = DlogProof::prove(DLOG_P2_NONCE, &nonce, &nonce_share)?;
+        let state = Party2Presigning {
+            commitment: msg.commitment,
+            nonce,
+            public_key: self.public_key.to_compressed(),
+        };
+        Ok((state, PresignMsg2 { nonce_share, proof }))
+    }
+}
+
+impl Drop for Party2Key {
+    fn drop(&mut self) {
+        self.share.wipe();
+    }
+}
+
+impl fmt::Debug for Party2Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Party2Key").field("public_key", &self.public_key).finish()
+    }
+}
+
+/// Party 1's commitment to its nonce share.
+#[derive(Clone, Copy, Debug)]
+pub struct PresignMsg1 {
+    pub commitment: [u8; 32],
+}
+
+/// Party 2's nonce share.
+#[derive(Clone, Copy, Debug)]
+pub struct PresignMsg2 {
+    pub nonce_share: [u8; 33],
+    pub proof: DlogProof,
+}
+
+/// Party 1's opening of its nonce share.
+#[derive(Clone, Copy, Debug)]
+pub struct PresignMsg3 {
+    pub nonce_share: [u8; 33],
+    pub proof: DlogProof,
+    pub blind: [u8; 32],
+}
+
+/// Party 2's encryption of its half of the signature.
+#[derive(Clone, Copy)]
+pub struct SignMsg {
+    pub ciphertext: [u8; paillier::CIPHERTEXT_SIZE],
+}
+
+/// Returns `r` for the nonce point `R`, and the recovery id of signatures
+/// whose `s` is left as is.
+fn nonce_scalar(point: &Point) -> SgxResult<(Scalar, u8)> {
+    let (x, y) = point.to_affine().ok_or(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE)?;
+    let overflow = !Uint(x).lt(&Uint(Scalar::order()));
+    let r = Scalar::from_words_reduced(&x);
+    if r.is_zero() {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+    }
+    Ok((r, (y[0] & 1) as u8 | (overflow as u8) << 1))
+}
+
+fn check_key(presigned: &[u8; 33], key: &Secp256k1PublicKey) -> SgxError {
+    if *presigned == key.to_compressed() {
+        Ok(())
+    } else {
+        Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+    }
+}
+
+/// Party 1's presigning, waiting for party 2's nonce share.
+pub struct Party1Presigning {
+    nonce: Scalar,
+    nonce_share: [u8; 33],
+    proof: DlogProof,
+    blind: [u8; 32],
+    public_key: [u8; 33],
+}
+
+impl Party1Presigning {
+    pub fn handle_msg2(self, msg: &PresignMsg2) -> SgxResult<(Party1Presignature, PresignMsg3)> {
+        msg.proof.verify(DLOG_P2_NONCE, &msg.nonce_share)?;
+        let point = parse_point(&msg.nonce_share)?.mul(&self.nonce.0);
+        let presignature =
+            Party1Presignature { nonce: self.nonce, point, public_key: self.public_key };
+        let reply =
+            PresignMsg3 { nonce_share: self.nonce_share, proof: self.proof, blind: self.blind };
+        Ok((presignature, reply))
+    }
+}
+
+impl Drop for Party1Presigning {
+    fn drop(&mut self) {
+        self.nonce.wipe();
+    }
+}
+
+/// Party 2's presigning, waiting for party 1's nonce share.
+pub struct Party2Presigning {
+    commitment: [u8; 32],
+    nonce: Scalar,
+    public_key: [u8; 33],
+}
+
+impl Party2Presigning {
+    pub fn handle_msg3(self, msg: &PresignMsg3) -> SgxResult<Party2Presignature> {
+        let proof = &msg.proof;
+        check_opening(COMMIT_NONCE, &self.commitment, &msg.blind, &[
+            &msg.nonce_share,
+            &proof.commitment,
+            &proof.response,
+        ])?;
+        proof.verify(DLOG_P1_NONCE, &msg.nonce_share)?;
+        let point = parse_point(&msg.nonce_share)?.mul(&self.nonce.0);
+        Ok(Party2Presignature { nonce: self.nonce, point, public_key: self.public_key })
+    }
+}
+
+impl Drop for Party2Presigning {
+    fn drop(&mut self) {
+        self.nonce.wipe();
+    }
+}
+
+/// Party 1's share of a nonce, good for one signature with the key it was
+/// made with.
+pub struct Party1Presignature {
+    nonce: Scalar,
+    point: Point,
+    public_key: [u8; 33],
+}
+
+impl Party1Presignature {
+    /// Finishes a signature over a 32-byte message hash from party 2's
+    /// message, failing with `SGX_ERROR_INVALID_SIGNATURE` unless it
+    /// verifies.
+    ///
+    /// As with [`Secp256k1PrivateKey::sign_prehash`], the signature has
+    /// the lower `s` and carries the recovery id.
+    ///
+    /// [`Secp256k1PrivateKey::sign_prehash`]: crate::secp256k1::Secp256k1PrivateKey::sign_prehash
+    pub fn sign(
+        self,
+        key: &Party1Key,
+        hash: &[u8; 32],
+        msg: &SignMsg,
+    ) -> SgxResult<Secp256k1RecoverableSignature> {
+        check_key(&self.public_key, &key.public_key)?;
+        let (r, mut recovery_id) = nonce_scalar(&self.point)?;
+        let ciphertext = parse_ciphertext(key.paillier.public(), &msg.ciphertext)?;
+        let mut plaintext = key.paillier.decrypt(&ciphertext);
+        let mut s = self.nonce.invert().mul(&reduce(&plaintext));
+        plaintext.wipe();
+        if s.is_zero() {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+        }
+        if s.is_high() {
+            s = s.neg();
+            recovery_id ^= 1;
+        }
+        let mut bytes = [0_u8; 64];
+        bytes[..32].copy_from_slice(&r.to_bytes());
+        bytes[32..].copy_from_slice(&s.to_bytes());
+        let signature = Secp256k1Signature(bytes);
+        if !key.public_key.verify_prehash(hash, &signature) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+        }
+        Ok(Secp256k1RecoverableSignature { signature, recovery_id })
+    }
+}
+
+impl Drop for Party1Presignature {
+    fn drop(&mut self) {
+        self.nonce.wipe();
+    }
+}
+
+/// Party 2's share of a nonce, good for one signature with the key it was
+/// made with.
+pub struct Party2Presignature {
+    nonce: Scalar,
+    point: Point,
+    public_key: [u8; 33],
+}
+
+impl Party2Presignature {
+    /// Encrypts `k2^-1 (z + r x1 x2) + rho q` for party 1, from the
+    /// encryption of `x1` and a random `rho < q^2` hiding `x2`.
+    pub fn sign(self, key: &Party2Key, hash: &[u8; 32]) -> SgxResult<SignMsg> {
+        check_key(&self.public_key, &key.public_key)?;
+        let (r, _) = nonce_scalar(&self.point)?;
+        let z = Scalar::from_bytes_reduced(hash);
+        let mut nonce_inv = self.nonce.invert();
+        let mut scale = nonce_inv.mul(&r).mul(&key.share);
+        let mut mask = Uint::<8>::random_below(&order_squared())?.resize::<32>();
+        let mut plaintext = mask
+            .wrapping_mul(&Uint(Scalar::order()).resize())
+            .wrapping_add(&widen(&nonce_inv.mul(&z)));
+        let paillier = &key.paillier;
+        let scaled = paillier.scale(&key.encrypted_share, &Uint(scale.0), 256);
+        let ciphertext = paillier.add(&paillier.encrypt(&plaintext)?.0, &scaled);
+        nonce_inv.wipe();
+        scale.wipe();
+        mask.wipe();
+        plaintext.wipe();
+        Ok(SignMsg { ciphertext: to_bytes(&ciphertext) })
+    }
+}
+
+impl Drop for Party2Presignature {
+    fn drop(&mut self) {
+        self.nonce.wipe();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keygen() -> (Party1Key, Party2Key) {
+        let (mut p1, msg1) = Party1KeyGen::new().unwrap();
+        let (mut p2, msg2) = Party2KeyGen::new(&msg1).unwrap();
+        let msg3 = p1.handle_msg2(&msg2).unwrap();
+        assert_eq!(p1.handle_msg2(&msg2).err(), Some(sgx_status_t::SGX_ERROR_INVALID_STATE));
+        let msg4 = p2.handle_msg3(&msg3).unwrap();
+        let msg5 = p1.handle_msg4(&msg4).unwrap();
+        let msg6 = p2.handle_msg5(&msg5).unwrap();
+        let (key1, msg7) = p1.handle_msg6(&msg6).unwrap();
+        let key2 = p2.handle_msg7(&msg7).unwrap();
+        (key1, key2)
+    }
+
+    fn presign(key1: &Party1Key, key2: &Party2Key) -> (Party1Presignature, Party2Presignature) {
+        let (p1, msg1) = key1.presign().unwrap();
+        let (p2, msg2) = key2.presign(&msg1).unwrap();
+        let (p1, msg3) = p1.handle_msg2(&msg2).unwrap();
+        (p1, p2.handle_msg3(&msg3).unwrap())
+    }
+
+    #[test]
+    fn sign() {
+        let (key1, key2) = keygen();
+        assert_eq!(key1.public_key(), key2.public_key());
+        let key1 = Party1Key::from_bytes(key1.to_bytes().expose_secret()).unwrap();
+        let key2 = Party2Key::from_bytes(key2.to_bytes().expose_secret()).unwrap();
+        let group_key = key1.public_key();
+
+        for i in 0..2_u8 {
+            let hash = [i + 1; 32];
+            let (p1, p2) = presign(&key1, &key2);
+            let partial = p2.sign(&key2, &hash).unwrap();
+            let signature = p1.sign(&key1, &hash, &partial).unwrap();
+            assert!(group_key.verify_prehash(&hash, &signature.signature));
+            assert_eq!(signature.recover(&hash).unwrap(), group_key);
+        }
+
+        // A partial signature that's tampered with, or made for another
+        // message, doesn't verify.
+        let (p1, p2) = presign(&key1, &key2);
+        let mut partial = p2.sign(&key2, &[9; 32]).unwrap();
+        partial.ciphertext[paillier::CIPHERTEXT_SIZE - 1] ^= 1;
+        assert!(p1.sign(&key1, &[9; 32], &partial).is_err());
+        let (p1, p2) = presign(&key1, &key2);
+        let partial = p2.sign(&key2, &[9; 32]).unwrap();
+        assert_eq!(
+            p1.sign(&key1, &[8; 32], &partial).err(),
+            Some(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE)
+        );
+    }
+}
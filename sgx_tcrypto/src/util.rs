@@ -0,0 +1,55 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Helpers shared by the software implementations.
+
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// Overwrites `buf` with zeroes, in a way the compiler won't remove.
+pub(crate) fn zeroize(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Compares `a` and `b` in time depending only on their lengths.
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0_u8, |diff, (x, y)| diff | (x ^ y));
+    // Keep the compiler from turning the fold into an early exit.
+    unsafe { ptr::read_volatile(&diff) == 0 }
+}
+
+/// Fills `buf` with random bytes from the enclave's generator, or from the
+/// provider installed by a test.
+pub(crate) fn read_rand(buf: &mut [u8]) -> sgx_types::SgxError {
+    #[cfg(feature = "test_rng")]
+    if let Some(provider) = crate::rng::provider() {
+        return provider(buf);
+    }
+    crate::provider::current().fill_random(buf)
+}
+
+/// Decodes a hex test vector.
+#[cfg(test)]
+pub(crate) fn hex(s: &str) -> std::vec::Vec<u8> {
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+}
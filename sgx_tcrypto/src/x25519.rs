@@ -0,0 +1,210 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! X25519 key agreement (RFC 7748).
+//!
+//! Private keys are zeroized on drop and can't be printed; the shared
+//! secret should only ever go through a key derivation function.
+
+use crate::field25519::Fe;
+use crate::secret::{Secret, SecretBytes};
+use crate::util::{read_rand, zeroize};
+use core::fmt;
+use sgx_types::*;
+
+/// The length of X25519 keys and shared secrets, in bytes.
+pub const X25519_KEY_SIZE: usize = 32;
+
+/// The u-coordinate of the base point.
+const BASE_POINT: [u8; 32] = {
+    let mut point = [0_u8; 32];
+    point[0] = 9;
+    point
+};
+
+/// Computes `k * u` on Curve25519 with the Montgomery ladder.
+fn scalar_mult(scalar: &[u8; 32], point: &[u8; 32]) -> [u8; 32] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let x1 = Fe::from_bytes(point);
+    let mut x2 = Fe::ONE;
+    let mut z2 = Fe::ZERO;
+    let mut x3 = x1;
+    let mut z3 = Fe::ONE;
+    let mut swap = 0_u64;
+    for t in (0..255).rev() {
+        let bit = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= bit;
+        Fe::cswap(&mut x2, &mut x3, swap);
+        Fe::cswap(&mut z2, &mut z3, swap);
+        swap = bit;
+
+        let a = x2.add(&z2);
+        let aa = a.square();
+        let b = x2.sub(&z2);
+        let bb = b.square();
+        let e = aa.sub(&bb);
+        let c = x3.add(&z3);
+        let d = x3.sub(&z3);
+        let da = d.mul(&a);
+        let cb = c.mul(&b);
+        x3 = da.add(&cb).square();
+        z3 = x1.mul(&da.sub(&cb).square());
+        x2 = aa.mul(&bb);
+        z2 = e.mul(&aa.add(&e.mul_small(121_665)));
+    }
+    Fe::cswap(&mut x2, &mut x3, swap);
+    Fe::cswap(&mut z2, &mut z3, swap);
+
+    let out = x2.mul(&z2.invert()).to_bytes();
+    zeroize(&mut k);
+    for fe in [&mut x2, &mut z2, &mut x3, &mut z3].iter_mut() {
+        fe.wipe();
+    }
+    out
+}
+
+/// An X25519 private key.
+pub struct X25519PrivateKey {
+    scalar: SecretBytes<32>,
+}
+
+impl X25519PrivateKey {
+    /// Generates a key with the enclave's random number generator.
+    pub fn generate() -> SgxResult<X25519PrivateKey> {
+        let mut scalar = SecretBytes::zeroed();
+        read_rand(scalar.expose_secret_mut())?;
+        Ok(X25519PrivateKey { scalar })
+    }
+
+    /// Creates a key from its 32 bytes, which are clamped on use.
+    pub fn from_bytes(bytes: &[u8; 32]) -> X25519PrivateKey {
+        X25519PrivateKey { scalar: Secret::new(*bytes) }
+    }
+
+    /// Returns the bytes of the key, e.g. to seal it.
+    pub fn to_bytes(&self) -> SecretBytes<32> {
+        self.scalar.clone()
+    }
+
+    /// Returns the public key.
+    pub fn public_key(&self) -> X25519PublicKey {
+        X25519PublicKey(scalar_mult(self.scalar.expose_secret(), &BASE_POINT))
+    }
+
+    /// Computes the secret shared with the owner of `peer`.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if `peer` is a low-order
+    /// point, which would make the secret all zeroes whatever the key.
+    pub fn diffie_hellman(&self, peer: &X25519PublicKey) -> SgxResult<X25519SharedSecret> {
+        let shared = scalar_mult(self.scalar.expose_secret(), &peer.0);
+        let secret = X25519SharedSecret(Secret::new(shared));
+        if secret.as_bytes().iter().fold(0_u8, |acc, b| acc | b) == 0 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(secret)
+    }
+}
+
+impl fmt::Debug for X25519PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("X25519PrivateKey").field("public_key", &self.public_key()).finish()
+    }
+}
+
+/// An X25519 public key, the u-coordinate of a point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct X25519PublicKey(pub [u8; 32]);
+
+impl X25519PublicKey {
+    /// Returns the bytes of the key.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+}
+
+/// A secret shared through X25519.
+pub struct X25519SharedSecret(SecretBytes<32>);
+
+impl X25519SharedSecret {
+    /// Returns the secret, to feed to a key derivation function.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        self.0.expose_secret()
+    }
+}
+
+impl fmt::Debug for X25519SharedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("X25519SharedSecret(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex;
+
+    #[test]
+    fn rfc7748() {
+        // Section 5.2.
+        let scalar = X25519PrivateKey::from_bytes(
+            &hex("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4").try_into().unwrap(),
+        );
+        let u = X25519PublicKey(
+            hex("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c").try_into().unwrap(),
+        );
+        let shared = scalar.diffie_hellman(&u).unwrap();
+        assert_eq!(
+            shared.as_bytes()[..],
+            hex("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552")[..]
+        );
+
+        // Section 6.1.
+        let alice = X25519PrivateKey::from_bytes(
+            &hex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a").try_into().unwrap(),
+        );
+        let bob = X25519PrivateKey::from_bytes(
+            &hex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb").try_into().unwrap(),
+        );
+        assert_eq!(
+            alice.public_key().to_bytes()[..],
+            hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")[..]
+        );
+        assert_eq!(
+            bob.public_key().to_bytes()[..],
+            hex("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f")[..]
+        );
+        let shared = alice.diffie_hellman(&bob.public_key()).unwrap();
+        assert_eq!(shared.as_bytes(), bob.diffie_hellman(&alice.public_key()).unwrap().as_bytes());
+        assert_eq!(
+            shared.as_bytes()[..],
+            hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742")[..]
+        );
+    }
+
+    #[test]
+    fn rejects_low_order_points() {
+        let key = X25519PrivateKey::from_bytes(&[0x42; 32]);
+        assert!(key.diffie_hellman(&X25519PublicKey([0; 32])).is_err());
+        let mut one = [0_u8; 32];
+        one[0] = 1;
+        assert!(key.diffie_hellman(&X25519PublicKey(one)).is_err());
+    }
+}
@@ -19,6 +19,9 @@
 //!
 //! These functions allow an ISV to establish secure session between two enclaves using the EC DH Key exchange protocol.
 //!
+//! The [`local_attestation`] module runs such a session between two enclaves of
+//! the same platform, and returns an encrypted channel with the peer it trusts.
+//!
 
 #![no_std]
 #![cfg_attr(
@@ -42,3 +45,5 @@ mod dh;
 pub use self::dh::*;
 
 mod ecp;
+
+pub mod local_attestation;
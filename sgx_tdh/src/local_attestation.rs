@@ -0,0 +1,299 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Local attestation sessions between two enclaves of the same platform.
+//!
+//! Enclaves on one platform don't need quotes to trust each other: each
+//! creates a report targeting the other, which the CPU MACs with a key only
+//! the target can derive. [`initiator`] and [`responder`] run the DH key
+//! exchange of the SDK, in which the reports bind the keys exchanged, and
+//! return a [`SecureChannel`] keyed by the result, once the caller trusts the
+//! identity of the peer:
+//!
+//! ```ignore
+//! // Responder enclave.
+//! let mut responder = local_attestation::responder();
+//! let msg1 = responder.msg1()?;
+//! // Initiator enclave, given msg1 by the host.
+//! let mut initiator = local_attestation::initiator();
+//! let msg2 = initiator.msg2(&msg1)?;
+//! // Responder, given msg2.
+//! let (channel, msg3) = responder.accept(&msg2, |peer| peer.mr_signer.m == SIGNER)?;
+//! // Initiator, given msg3.
+//! let channel = initiator.accept(&msg3, |peer| peer.mr_enclave.m == RESPONDER)?;
+//! ```
+//!
+//! The messages are the `sgx_dh_msg1_t`, `sgx_dh_msg2_t` and `sgx_dh_msg3_t`
+//! of the SDK, without additional properties, for the host to relay.
+//!
+//! The channel encrypts messages with AES-128-GCM, under a key for each
+//! direction derived from the AEK of the session, and numbers them: the IV
+//! and the additional data are the number of the message, little endian, so
+//! the host relaying them can't replay, drop or reorder messages without the
+//! next one failing to open. The transport must thus deliver every message,
+//! in order; a channel doesn't recover from a lost one.
+
+use crate::dh::{SgxDhInitiator, SgxDhMsg1, SgxDhMsg2, SgxDhMsg3, SgxDhMsg3Body, SgxDhResponder};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem;
+use core::ptr;
+use core::slice;
+use sgx_tcrypto::*;
+use sgx_types::*;
+
+const TAG_LEN: usize = 16;
+const INITIATOR_LABEL: [u8; 3] = *b"I2R";
+const RESPONDER_LABEL: [u8; 3] = *b"R2I";
+
+/// Overwrites `buf` with zeroes, in a way the compiler won't remove.
+fn zeroize(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
+fn to_bytes<T: Copy>(value: &T) -> Vec<u8> {
+    let bytes =
+        unsafe { slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) };
+    Vec::from(bytes)
+}
+
+/// Reads a `T` from `bytes`, which must have exactly its size.
+fn from_bytes<T: Copy>(bytes: &[u8]) -> SgxResult<T> {
+    if bytes.len() != mem::size_of::<T>() {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    Ok(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+/// Starts the initiator side of a session.
+pub fn initiator() -> Initiator {
+    Initiator {
+        session: SgxDhInitiator::init_session(),
+    }
+}
+
+/// Starts the responder side of a session.
+pub fn responder() -> Responder {
+    Responder {
+        session: SgxDhResponder::init_session(),
+    }
+}
+
+/// The side of a session which answers the first message of the responder.
+pub struct Initiator {
+    session: SgxDhInitiator,
+}
+
+impl Initiator {
+    /// Processes `msg1` from the responder, and returns msg2 to send it.
+    pub fn msg2(&mut self, msg1: &[u8]) -> SgxResult<Vec<u8>> {
+        let msg1: SgxDhMsg1 = from_bytes(msg1)?;
+        let mut msg2 = SgxDhMsg2::default();
+        self.session.proc_msg1(&msg1, &mut msg2)?;
+        Ok(to_bytes(&msg2))
+    }
+
+    /// Processes `msg3` from the responder, and returns the channel with it
+    /// if `trust` trusts its identity.
+    ///
+    /// Fails with `SGX_ERROR_NO_PRIVILEGE` if `trust` doesn't.
+    pub fn accept<F>(mut self, msg3: &[u8], trust: F) -> SgxResult<SecureChannel>
+    where
+        F: FnOnce(&sgx_dh_session_enclave_identity_t) -> bool,
+    {
+        let msg3 = read_msg3(msg3)?;
+        let mut aek = sgx_key_128bit_t::default();
+        let mut peer = sgx_dh_session_enclave_identity_t::default();
+        self.session.proc_msg3(&msg3, &mut aek, &mut peer)?;
+        let channel = SecureChannel::new(&aek, INITIATOR_LABEL, RESPONDER_LABEL, peer);
+        zeroize(&mut aek);
+        let channel = channel?;
+        if !trust(&peer) {
+            return Err(sgx_status_t::SGX_ERROR_NO_PRIVILEGE);
+        }
+        Ok(channel)
+    }
+}
+
+/// The side of a session which sends the first message.
+pub struct Responder {
+    session: SgxDhResponder,
+}
+
+impl Responder {
+    /// Returns msg1 to send the initiator.
+    pub fn msg1(&mut self) -> SgxResult<Vec<u8>> {
+        let mut msg1 = SgxDhMsg1::default();
+        self.session.gen_msg1(&mut msg1)?;
+        Ok(to_bytes(&msg1))
+    }
+
+    /// Processes `msg2` from the initiator, and if `trust` trusts its
+    /// identity, returns the channel with it and msg3 to send it.
+    ///
+    /// Fails with `SGX_ERROR_NO_PRIVILEGE` if `trust` doesn't, without
+    /// returning msg3.
+    pub fn accept<F>(mut self, msg2: &[u8], trust: F) -> SgxResult<(SecureChannel, Vec<u8>)>
+    where
+        F: FnOnce(&sgx_dh_session_enclave_identity_t) -> bool,
+    {
+        let msg2: SgxDhMsg2 = from_bytes(msg2)?;
+        let mut msg3 = SgxDhMsg3::new();
+        let mut aek = sgx_key_128bit_t::default();
+        let mut peer = sgx_dh_session_enclave_identity_t::default();
+        self.session
+            .proc_msg2(&msg2, &mut msg3, &mut aek, &mut peer)?;
+        let channel = SecureChannel::new(&aek, RESPONDER_LABEL, INITIATOR_LABEL, peer);
+        zeroize(&mut aek);
+        let channel = channel?;
+        if !trust(&peer) {
+            return Err(sgx_status_t::SGX_ERROR_NO_PRIVILEGE);
+        }
+        Ok((channel, write_msg3(&msg3)))
+    }
+}
+
+fn write_msg3(msg3: &SgxDhMsg3) -> Vec<u8> {
+    let raw = sgx_dh_msg3_t {
+        cmac: msg3.cmac,
+        msg3_body: sgx_dh_msg3_body_t {
+            report: msg3.msg3_body.report,
+            additional_prop_length: 0,
+            additional_prop: [],
+        },
+    };
+    to_bytes(&raw)
+}
+
+/// Reads a msg3 without additional properties.
+fn read_msg3(bytes: &[u8]) -> SgxResult<SgxDhMsg3> {
+    let raw: sgx_dh_msg3_t = from_bytes(bytes)?;
+    if raw.msg3_body.additional_prop_length != 0 {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    Ok(SgxDhMsg3 {
+        cmac: raw.cmac,
+        msg3_body: SgxDhMsg3Body {
+            report: raw.msg3_body.report,
+            additional_prop: Box::default(),
+        },
+    })
+}
+
+/// Derives the key of a direction of the channel from the AEK, as the SDK
+/// derives the AEK from the shared key.
+fn derive_key(aek: &sgx_key_128bit_t, label: [u8; 3]) -> SgxResult<sgx_aes_gcm_128bit_key_t> {
+    let input = [0x01, label[0], label[1], label[2], 0x00, 0x80, 0x00];
+    rsgx_rijndael128_cmac_slice(aek, &input)
+}
+
+/// An encrypted channel with an enclave on the same platform, whose
+/// identity local attestation established.
+pub struct SecureChannel {
+    send_key: sgx_aes_gcm_128bit_key_t,
+    receive_key: sgx_aes_gcm_128bit_key_t,
+    sent: u64,
+    received: u64,
+    peer: sgx_dh_session_enclave_identity_t,
+}
+
+impl SecureChannel {
+    fn new(
+        aek: &sgx_key_128bit_t,
+        send: [u8; 3],
+        receive: [u8; 3],
+        peer: sgx_dh_session_enclave_identity_t,
+    ) -> SgxResult<SecureChannel> {
+        Ok(SecureChannel {
+            send_key: derive_key(aek, send)?,
+            receive_key: derive_key(aek, receive)?,
+            sent: 0,
+            received: 0,
+            peer,
+        })
+    }
+
+    /// Returns the identity of the peer enclave.
+    pub fn peer_identity(&self) -> &sgx_dh_session_enclave_identity_t {
+        &self.peer
+    }
+
+    /// Encrypts `plaintext` as the next message to the peer.
+    pub fn seal(&mut self, plaintext: &[u8]) -> SgxResult<Vec<u8>> {
+        let number = self.sent.to_le_bytes();
+        let mut iv = [0_u8; SGX_AESGCM_IV_SIZE];
+        iv[..8].copy_from_slice(&number);
+        let mut message = vec![0_u8; plaintext.len() + TAG_LEN];
+        let mut tag = sgx_aes_gcm_128bit_tag_t::default();
+        let (ciphertext, _) = message.split_at_mut(plaintext.len());
+        rsgx_rijndael128GCM_encrypt(
+            &self.send_key,
+            plaintext,
+            &iv,
+            &number,
+            ciphertext,
+            &mut tag,
+        )?;
+        message[plaintext.len()..].copy_from_slice(&tag);
+        self.sent = self
+            .sent
+            .checked_add(1)
+            .ok_or(sgx_status_t::SGX_ERROR_INVALID_STATE)?;
+        Ok(message)
+    }
+
+    /// Decrypts `message`, which must be the next message from the peer.
+    ///
+    /// Fails with `SGX_ERROR_MAC_MISMATCH` if it isn't, or was tampered with.
+    pub fn open(&mut self, message: &[u8]) -> SgxResult<Vec<u8>> {
+        if message.len() < TAG_LEN {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let (ciphertext, tag) = message.split_at(message.len() - TAG_LEN);
+        let mut mac = sgx_aes_gcm_128bit_tag_t::default();
+        mac.copy_from_slice(tag);
+        let number = self.received.to_le_bytes();
+        let mut iv = [0_u8; SGX_AESGCM_IV_SIZE];
+        iv[..8].copy_from_slice(&number);
+        let mut plaintext = vec![0_u8; ciphertext.len()];
+        if let Err(error) = rsgx_rijndael128GCM_decrypt(
+            &self.receive_key,
+            ciphertext,
+            &iv,
+            &number,
+            &mac,
+            &mut plaintext,
+        ) {
+            zeroize(&mut plaintext);
+            return Err(error);
+        }
+        self.received = self
+            .received
+            .checked_add(1)
+            .ok_or(sgx_status_t::SGX_ERROR_INVALID_STATE)?;
+        Ok(plaintext)
+    }
+}
+
+impl Drop for SecureChannel {
+    fn drop(&mut self) {
+        zeroize(&mut self.send_key);
+        zeroize(&mut self.receive_key);
+    }
+}
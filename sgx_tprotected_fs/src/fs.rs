@@ -415,6 +415,32 @@ impl SgxFileStream {
         unsafe { rsgx_fflush(self.stream) }
     }
 
+    ///
+    /// The close function closes the protected file, returning the error of the close.
+    ///
+    /// # Description
+    ///
+    /// close is similar to the C file API fclose. Closing writes the remaining modified nodes
+    /// and the final meta-data node of the file, which dropping the stream does as well but
+    /// without reporting a failure.
+    ///
+    /// # Requirements
+    ///
+    /// Header: sgx_tprotected_fs.edl
+    ///
+    /// Library: libsgx_tprotected_fs.a
+    ///
+    /// # Return value
+    ///
+    /// If the function failed, error code is returned.
+    ///
+    pub fn close(self) -> SysError {
+        let stream = self.stream;
+        // The stream is closed here, not again by `drop`.
+        core::mem::forget(self);
+        unsafe { rsgx_fclose(stream) }
+    }
+
     ///
     /// The error function returns the latest operation error code.
     ///
@@ -359,6 +359,25 @@ impl<'a, T: 'a + Copy + ContiguousMemory> SgxMacAadata<'a, [T]> {
         })
     }
 
+    ///
+    /// Authenticates `additional_text` with the fields of the key request in
+    /// `request`, for `SealingBuilder`.
+    ///
+    pub(crate) fn mac_aadata_with(request: &SealRequest, additional_text: &[T]) -> SgxResult<Self> {
+        let size = mem::size_of::<T>();
+        let len = mem::size_of_val(additional_text);
+        if size == 0 || len == 0 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let aad_slice: &[u8] =
+            unsafe { slice::from_raw_parts(additional_text.as_ptr() as *const u8, len) };
+        let result = SgxInternalSealedData::mac_aadata_with(request, aad_slice);
+        result.map(|x| SgxMacAadata {
+            inner: x,
+            marker: PhantomData,
+        })
+    }
+
     ///
     /// This function is used to verify the authenticity of the input sealed data structure using AES-GMAC. This function verifies the MAC generated with sgx_mac_aadataorsgx_mac_aadata_ex.
     ///
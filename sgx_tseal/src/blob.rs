@@ -0,0 +1,260 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Sealed blobs which follow the enclave across TCB upgrades.
+//!
+//! Sealed data records the ISV SVN and the CPU SVN its key was derived at,
+//! and an enclave can derive the keys of every SVN up to its own, so data
+//! sealed before an upgrade of the enclave or of the platform microcode still
+//! unseals after it. The older TCB, which the upgrade may have been made to
+//! fix, can still unseal it too. [`SealedBlob::unseal_and_upgrade`] reseals
+//! such a blob at the current TCB as it unseals it, and [`upgrade_all`] does
+//! so for a whole set of blobs.
+//!
+//! A blob is laid out as:
+//!
+//! | offset | size | field                                        |
+//! |--------|------|----------------------------------------------|
+//! | 0      | 4    | magic, `SBLB`                                |
+//! | 4      | 1    | format version, [`BLOB_VERSION`]             |
+//! | 5      | 1    | reserved, zero                               |
+//! | 6      | 2    | ISV SVN, little endian                       |
+//! | 8      | 2    | config SVN, little endian                    |
+//! | 10     | 2    | reserved, zero                               |
+//! | 12     | 16   | CPU SVN                                      |
+//! | 28     | 4    | sealed data length, little endian            |
+//! | 32     |      | `sgx_sealed_data_t`                          |
+//!
+//! The SVNs repeat those of the key request in the sealed data, which a blob
+//! must match to parse, so that the untrusted application can tell which
+//! blobs are stale without parsing the sealed data.
+
+use crate::builder::SealingBuilder;
+use crate::internal::SgxInternalSealedData;
+use crate::seal::SgxSealedData;
+use alloc::vec::Vec;
+use core::fmt;
+use sgx_tcrypto::secret::Zeroize;
+use sgx_tse::rsgx_self_report;
+use sgx_types::*;
+
+/// The version of the blob format written by [`SealedBlob::seal`].
+pub const BLOB_VERSION: u8 = 1;
+
+const MAGIC: [u8; 4] = *b"SBLB";
+const HEADER_LEN: usize = 32;
+
+/// The security versions a blob is sealed at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tcb {
+    pub isv_svn: sgx_isv_svn_t,
+    pub config_svn: sgx_config_svn_t,
+    pub cpu_svn: [u8; SGX_CPUSVN_SIZE],
+}
+
+impl Tcb {
+    /// The TCB the enclave runs at.
+    pub fn current() -> Tcb {
+        let body = rsgx_self_report().body;
+        Tcb {
+            isv_svn: body.isv_svn,
+            config_svn: body.config_svn,
+            cpu_svn: body.cpu_svn.svn,
+        }
+    }
+
+    fn of(key_request: &sgx_key_request_t) -> Tcb {
+        Tcb {
+            isv_svn: key_request.isv_svn,
+            config_svn: key_request.config_svn,
+            cpu_svn: key_request.cpu_svn.svn,
+        }
+    }
+}
+
+/// Sealed data along with the TCB it was sealed at.
+#[derive(Clone)]
+pub struct SealedBlob {
+    bytes: Vec<u8>,
+}
+
+impl SealedBlob {
+    /// Seals `plaintext` with `builder`, at the current TCB.
+    pub fn seal(builder: &SealingBuilder<'_>, plaintext: &[u8]) -> SgxResult<SealedBlob> {
+        let sealed = builder.seal_slice(plaintext)?;
+        let raw = sealed.internal().to_bytes()?;
+        let tcb = Tcb::of(sealed.get_key_request());
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + raw.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&[BLOB_VERSION, 0]);
+        bytes.extend_from_slice(&tcb.isv_svn.to_le_bytes());
+        bytes.extend_from_slice(&tcb.config_svn.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(&tcb.cpu_svn);
+        bytes.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&raw);
+        Ok(SealedBlob { bytes })
+    }
+
+    /// Parses a blob, failing with `SGX_ERROR_INVALID_PARAMETER` if it's
+    /// malformed, of an unknown version, or if its header doesn't match its
+    /// sealed data; nothing is authenticated yet.
+    pub fn from_bytes(bytes: Vec<u8>) -> SgxResult<SealedBlob> {
+        let blob = SealedBlob { bytes };
+        blob.sealed()?;
+        Ok(blob)
+    }
+
+    fn sealed(&self) -> SgxResult<SgxSealedData<'static, [u8]>> {
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        let bytes = &self.bytes;
+        if bytes.len() < HEADER_LEN || bytes[..4] != MAGIC {
+            return Err(invalid);
+        }
+        if bytes[4] != BLOB_VERSION || bytes[5] != 0 || bytes[10..12] != [0, 0] {
+            return Err(invalid);
+        }
+        let mut len = [0_u8; 4];
+        len.copy_from_slice(&bytes[28..HEADER_LEN]);
+        if u32::from_le_bytes(len) as usize != bytes.len() - HEADER_LEN {
+            return Err(invalid);
+        }
+        let inner = SgxInternalSealedData::from_bytes(&bytes[HEADER_LEN..])?;
+        if Tcb::of(inner.get_key_request()) != self.tcb() {
+            return Err(invalid);
+        }
+        Ok(SgxSealedData::from_internal(inner))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// The TCB the blob was sealed at, as its header records it.
+    pub fn tcb(&self) -> Tcb {
+        let mut cpu_svn = [0_u8; SGX_CPUSVN_SIZE];
+        cpu_svn.copy_from_slice(&self.bytes[12..28]);
+        Tcb {
+            isv_svn: u16::from_le_bytes([self.bytes[6], self.bytes[7]]),
+            config_svn: u16::from_le_bytes([self.bytes[8], self.bytes[9]]),
+            cpu_svn,
+        }
+    }
+
+    /// Returns whether the blob was sealed at the TCB the enclave runs at.
+    pub fn is_current(&self) -> bool {
+        self.tcb() == Tcb::current()
+    }
+
+    /// Unseals the blob, checked against `builder` as
+    /// [`SealingBuilder::unseal`] does.
+    ///
+    /// Blobs sealed at an older TCB unseal as well; one sealed at a newer
+    /// TCB, after the enclave or the platform was rolled back, fails with
+    /// `SGX_ERROR_INVALID_ISVSVN` or `SGX_ERROR_INVALID_CPUSVN`.
+    pub fn unseal(&self, builder: &SealingBuilder<'_>) -> SgxResult<Vec<u8>> {
+        let sealed = self.sealed()?;
+        let unsealed = builder.unseal_slice(&sealed)?;
+        Ok(unsealed.decrypt.into_vec())
+    }
+
+    /// Unseals the blob as [`unseal`] does, and if it was sealed at another
+    /// TCB than the current one, reseals it with `builder`. Returns the
+    /// plaintext, and the new blob to store in place of this one if there is
+    /// one.
+    ///
+    /// [`unseal`]: SealedBlob::unseal
+    pub fn unseal_and_upgrade(
+        &self,
+        builder: &SealingBuilder<'_>,
+    ) -> SgxResult<(Vec<u8>, Option<SealedBlob>)> {
+        let mut plaintext = self.unseal(builder)?;
+        if self.is_current() {
+            return Ok((plaintext, None));
+        }
+        match SealedBlob::seal(builder, &plaintext) {
+            Ok(blob) => Ok((plaintext, Some(blob))),
+            Err(e) => {
+                plaintext.zeroize();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SealedBlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SealedBlob")
+            .field("tcb", &self.tcb())
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// How far [`upgrade_all`] has gone, reported after each blob.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UpgradeProgress {
+    /// The number of blobs looked at so far.
+    pub done: usize,
+    pub total: usize,
+    /// The number of blobs resealed so far.
+    pub upgraded: usize,
+}
+
+/// Reseals, with `builder`, the blobs of `blobs` which were sealed at another
+/// TCB than the current one, calling `progress` after each blob.
+///
+/// The blobs are only replaced once all of them are resealed, so an error,
+/// e.g. a blob of another class than `builder`, leaves `blobs` as they were.
+/// Returns the indices of the replaced blobs, for the caller to write them
+/// back in one batch.
+pub fn upgrade_all<F: FnMut(&UpgradeProgress)>(
+    builder: &SealingBuilder<'_>,
+    blobs: &mut [SealedBlob],
+    mut progress: F,
+) -> SgxResult<Vec<usize>> {
+    let current = Tcb::current();
+    let mut status = UpgradeProgress {
+        done: 0,
+        total: blobs.len(),
+        upgraded: 0,
+    };
+    let mut resealed = Vec::new();
+    for (i, blob) in blobs.iter().enumerate() {
+        if blob.tcb() != current {
+            let mut plaintext = blob.unseal(builder)?;
+            let result = SealedBlob::seal(builder, &plaintext);
+            plaintext.zeroize();
+            resealed.push((i, result?));
+            status.upgraded += 1;
+        }
+        status.done += 1;
+        progress(&status);
+    }
+
+    let mut indices = Vec::with_capacity(resealed.len());
+    for (i, blob) in resealed {
+        blobs[i] = blob;
+        indices.push(i);
+    }
+    Ok(indices)
+}
@@ -0,0 +1,237 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! The key policy, masks, key ID and additional data of sealed blobs and MACs.
+//!
+//! `seal_data` seals every blob to the signer of the enclave, which lets any
+//! enclave of the same signer and product, at the same or a later ISV SVN,
+//! unseal it. A [`SealingBuilder`] picks, for each class of data, what the
+//! sealing key is derived from instead:
+//!
+//! * the [`KeyPolicy`], binding the key to the measurement of the enclave,
+//!   to its signer, or to both;
+//! * the attribute mask, e.g. to leave the DEBUG bit out so that debug and
+//!   production builds share data, which `seal_data` doesn't allow;
+//! * the misc-select mask;
+//! * for MACs, a fixed key ID, so that a class of MACs always has the same
+//!   key;
+//! * the additional data, authenticated along with the payload.
+//!
+//! Blobs record their key request, so a builder also checks, when it unseals,
+//! that a blob was sealed with its fields: a blob of a class sealed to the
+//! signer can't be passed for one of a class sealed to the enclave.
+
+use crate::aad::SgxMacAadata;
+use crate::internal::{default_key_policy, SealRequest};
+use crate::seal::{SgxSealedData, SgxUnsealedData};
+use alloc::boxed::Box;
+use sgx_types::marker::ContiguousMemory;
+use sgx_types::*;
+
+/// The measurement a sealing key is derived from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyPolicy {
+    /// `MRENCLAVE`: only the same build of the enclave can unseal.
+    MrEnclave,
+    /// `MRSIGNER`: later versions from the same signer can unseal too.
+    MrSigner,
+    /// Both, so that only the same build of the same signer can unseal.
+    Both,
+}
+
+impl KeyPolicy {
+    fn bits(self) -> u16 {
+        match self {
+            KeyPolicy::MrEnclave => SGX_KEYPOLICY_MRENCLAVE,
+            KeyPolicy::MrSigner => SGX_KEYPOLICY_MRSIGNER,
+            KeyPolicy::Both => SGX_KEYPOLICY_MRENCLAVE | SGX_KEYPOLICY_MRSIGNER,
+        }
+    }
+}
+
+/// Seals, authenticates and unseals data with a chosen key request.
+///
+/// A new builder starts from the choices of `seal_data`: the signer of the
+/// enclave, with its KSS fields if it has them, the default attribute and
+/// misc-select masks, a random key ID for every blob or MAC and no additional
+/// data.
+/// A blob is unsealed with the builder which sealed it:
+///
+/// ```ignore
+/// let seed_class = SealingBuilder::new()
+///     .key_policy(KeyPolicy::MrEnclave)
+///     .additional_data(b"wallet-seed-v1");
+/// let sealed = seed_class.seal(&seed)?;
+/// let unsealed = seed_class.unseal(&sealed)?;
+/// ```
+#[derive(Clone, Copy)]
+pub struct SealingBuilder<'b> {
+    request: SealRequest,
+    additional: &'b [u8],
+}
+
+impl<'b> SealingBuilder<'b> {
+    pub fn new() -> SealingBuilder<'b> {
+        SealingBuilder {
+            request: SealRequest {
+                key_policy: default_key_policy(),
+                attribute_mask: sgx_attributes_t {
+                    flags: TSEAL_DEFAULT_FLAGSMASK,
+                    xfrm: 0,
+                },
+                misc_mask: TSEAL_DEFAULT_MISCMASK,
+                mac_key_id: None,
+            },
+            additional: &[],
+        }
+    }
+
+    /// Sets the measurement the key is derived from, keeping the other bits
+    /// of the key policy, such as the KSS fields.
+    pub fn key_policy(mut self, policy: KeyPolicy) -> Self {
+        let measurements = SGX_KEYPOLICY_MRENCLAVE | SGX_KEYPOLICY_MRSIGNER;
+        self.request.key_policy = (self.request.key_policy & !measurements) | policy.bits();
+        self
+    }
+
+    /// Also derives the key without the ISV product ID, so that the enclaves
+    /// of all the products of the signer share it.
+    pub fn without_isv_prod_id(mut self) -> Self {
+        self.request.key_policy |= SGX_KEYPOLICY_NOISVPRODID;
+        self
+    }
+
+    /// Sets the attributes the key is derived from. The INITTED and DEBUG
+    /// bits must be in the mask.
+    pub fn attribute_mask(mut self, mask: sgx_attributes_t) -> Self {
+        self.request.attribute_mask = mask;
+        self
+    }
+
+    /// Sets the misc-select bits the key is derived from.
+    pub fn misc_mask(mut self, mask: sgx_misc_select_t) -> Self {
+        self.request.misc_mask = mask;
+        self
+    }
+
+    /// Derives the key of every MAC with `key_id` rather than a random one,
+    /// and requires it of the MACs [`verify_mac`] checks.
+    ///
+    /// Sealed blobs still draw a random key ID each. The SDK encrypts them
+    /// with a fixed, all-zero IV, so two blobs under the same key would
+    /// reuse the AES-GCM nonce, which leaks the XOR of their payloads and
+    /// lets the authentication key be recovered, whatever their additional
+    /// data. A MAC encrypts nothing, so sharing its key is safe.
+    ///
+    /// [`verify_mac`]: SealingBuilder::verify_mac
+    pub fn mac_key_id(mut self, key_id: sgx_key_id_t) -> Self {
+        self.request.mac_key_id = Some(key_id);
+        self
+    }
+
+    /// Sets the additional data, authenticated but not encrypted, and stored
+    /// in the clear in the blob.
+    pub fn additional_data(mut self, additional: &'b [u8]) -> Self {
+        self.additional = additional;
+        self
+    }
+
+    /// Seals `data` as `SgxSealedData::seal_data_ex` does, with the fields of
+    /// the builder.
+    pub fn seal<'a, T: Copy + ContiguousMemory>(
+        &self,
+        data: &'a T,
+    ) -> SgxResult<SgxSealedData<'a, T>> {
+        SgxSealedData::<T>::seal_data_with(&self.request, self.additional, data)
+    }
+
+    /// Seals the slice `data`.
+    pub fn seal_slice<'a, T: Copy + ContiguousMemory>(
+        &self,
+        data: &'a [T],
+    ) -> SgxResult<SgxSealedData<'a, [T]>> {
+        SgxSealedData::<[T]>::seal_data_with(&self.request, self.additional, data)
+    }
+
+    /// Authenticates the additional data alone, as
+    /// `SgxMacAadata::mac_aadata_ex` does.
+    pub fn mac<'a>(&self) -> SgxResult<SgxMacAadata<'a, [u8]>> {
+        SgxMacAadata::<[u8]>::mac_aadata_with(&self.request, self.additional)
+    }
+
+    /// Unseals `sealed`, once it's checked against the builder.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if the blob was sealed with
+    /// another key policy or other masks, and with `SGX_ERROR_MAC_MISMATCH`
+    /// if its additional data isn't that of the builder; otherwise as
+    /// `unseal_data` does.
+    pub fn unseal<'a, T: Copy + ContiguousMemory>(
+        &self,
+        sealed: &SgxSealedData<'a, T>,
+    ) -> SgxResult<SgxUnsealedData<'a, T>> {
+        self.check(sealed.get_key_request(), sealed.get_additional_txt())?;
+        sealed.unseal_data()
+    }
+
+    /// Unseals the slice sealed in `sealed`, checked as [`unseal`] does.
+    ///
+    /// [`unseal`]: SealingBuilder::unseal
+    pub fn unseal_slice<'a, T: Copy + ContiguousMemory>(
+        &self,
+        sealed: &SgxSealedData<'a, [T]>,
+    ) -> SgxResult<SgxUnsealedData<'a, [T]>> {
+        self.check(sealed.get_key_request(), sealed.get_additional_txt())?;
+        sealed.unseal_data()
+    }
+
+    /// Verifies `mac`, checked as [`unseal`] does and, if the builder has
+    /// one, against its MAC key ID, and returns the additional data.
+    ///
+    /// [`unseal`]: SealingBuilder::unseal
+    pub fn verify_mac(&self, mac: &SgxMacAadata<'_, [u8]>) -> SgxResult<Box<[u8]>> {
+        if !self.request.matches_mac(mac.get_key_request()) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        self.check(mac.get_key_request(), mac.get_additional_txt())?;
+        mac.unmac_aadata()
+    }
+
+    fn check(&self, key_request: &sgx_key_request_t, additional: &[u8]) -> SgxError {
+        if !self.request.matches(key_request) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        if additional != self.additional {
+            return Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH);
+        }
+        Ok(())
+    }
+
+    /// The same class, without its additional data, for callers which set
+    /// their own.
+    pub(crate) fn detached(&self) -> SealingBuilder<'static> {
+        SealingBuilder {
+            request: self.request,
+            additional: &[],
+        }
+    }
+}
+
+impl Default for SealingBuilder<'_> {
+    fn default() -> Self {
+        SealingBuilder::new()
+    }
+}
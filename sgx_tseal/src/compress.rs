@@ -0,0 +1,333 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Sealed data compressed before it's sealed.
+//!
+//! Sealed wallet snapshots repeat the same structures, keys and addresses
+//! over and over, and compress to a fraction of their size. [`Compression`]
+//! compresses a payload with LZ4, in its block format, then seals it with a
+//! [`SealingBuilder`], and decompresses it again as it unseals it. LZ4 needs
+//! no tables beyond a fixed hash table, and is implemented here with bounds
+//! checks on every read and write, so nothing of the host's libc or of an
+//! external crate runs on the payload. Payloads which don't compress are
+//! stored as they are.
+//!
+//! The limits of a [`Compression`] bound what unsealing allocates, so that
+//! a blob can't decompress to more memory than the enclave has: the length
+//! of the payload, which is checked before anything is allocated, and its
+//! ratio to the compressed length. Sealed data is authenticated, so only
+//! enclaves with the seal key can make such a blob, but one of another build
+//! sharing the key, or an older build, may be vulnerable.
+//!
+//! The length of compressed data depends on its contents: payloads mixing
+//! secrets with data an attacker chooses shouldn't be compressed, or the
+//! length of the sealed data tells the attacker how much the two have in
+//! common.
+//!
+//! The plaintext sealed is laid out as:
+//!
+//! | offset | size | field                                         |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | magic, `SCMP`                                 |
+//! | 4      | 1    | method, 0 if stored and 1 if LZ4              |
+//! | 5      | 3    | reserved, zero                                |
+//! | 8      | 8    | length of the payload, little endian          |
+//! | 16     |      | the payload, stored or as an LZ4 block        |
+
+use crate::builder::SealingBuilder;
+use crate::internal::SgxInternalSealedData;
+use crate::seal::SgxSealedData;
+use alloc::vec::Vec;
+use sgx_tcrypto::secret::Zeroize;
+use sgx_types::*;
+
+const MAGIC: [u8; 4] = *b"SCMP";
+const HEADER_LEN: usize = 16;
+
+const STORED: u8 = 0;
+const LZ4: u8 = 1;
+
+const MIN_MATCH: usize = 4;
+const LAST_LITERALS: usize = 5;
+const MF_LIMIT: usize = 12;
+const MAX_OFFSET: usize = 65535;
+const HASH_LOG: u32 = 12;
+
+/// Compresses payloads before sealing them, and bounds what unsealing them
+/// decompresses.
+///
+/// ```ignore
+/// let compression = Compression::new().with_max_len(64 << 20);
+/// let sealed = compression.seal(&snapshot_class, &snapshot)?;
+/// let snapshot = compression.unseal(&snapshot_class, &sealed)?;
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compression {
+    max_len: usize,
+    max_ratio: usize,
+}
+
+impl Compression {
+    /// Allows payloads of up to 16 MiB, compressed up to 255 times, which is
+    /// about as much as LZ4 compresses anything.
+    pub fn new() -> Compression {
+        Compression {
+            max_len: 16 << 20,
+            max_ratio: 255,
+        }
+    }
+
+    /// Sets the length the payloads may have.
+    pub fn with_max_len(mut self, max_len: usize) -> Compression {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Sets how many times longer than its compressed form a payload may be.
+    pub fn with_max_ratio(mut self, max_ratio: usize) -> Compression {
+        self.max_ratio = max_ratio;
+        self
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    pub fn max_ratio(&self) -> usize {
+        self.max_ratio
+    }
+
+    /// Compresses `payload` and seals it with `builder`, returning the
+    /// `sgx_sealed_data_t`.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if the payload is longer than
+    /// the limit, so that whatever seals also unseals.
+    pub fn seal(&self, builder: &SealingBuilder<'_>, payload: &[u8]) -> SgxResult<Vec<u8>> {
+        if payload.len() > self.max_len {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut plaintext = Vec::with_capacity(HEADER_LEN + compress_bound(payload.len()));
+        plaintext.extend_from_slice(&MAGIC);
+        plaintext.extend_from_slice(&[LZ4, 0, 0, 0]);
+        plaintext.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        compress(payload, &mut plaintext);
+        let compressed = plaintext.len() - HEADER_LEN;
+        if compressed >= payload.len() || payload.len() > compressed.saturating_mul(self.max_ratio)
+        {
+            plaintext[HEADER_LEN..].zeroize();
+            plaintext.truncate(HEADER_LEN);
+            plaintext[4] = STORED;
+            plaintext.extend_from_slice(payload);
+        }
+
+        let sealed = builder.seal_slice(&plaintext[..]);
+        let result = sealed.and_then(|sealed| sealed.internal().to_bytes());
+        plaintext.zeroize();
+        result
+    }
+
+    /// Unseals `sealed` with `builder`, checked as
+    /// [`SealingBuilder::unseal`] does, and decompresses it.
+    ///
+    /// Fails with `SGX_ERROR_OUT_OF_MEMORY` if the payload is longer than the
+    /// limits allow, before it's decompressed, and with
+    /// `SGX_ERROR_INVALID_PARAMETER` if it isn't compressed data.
+    pub fn unseal(&self, builder: &SealingBuilder<'_>, sealed: &[u8]) -> SgxResult<Vec<u8>> {
+        let inner = SgxInternalSealedData::from_bytes(sealed)?;
+        let sealed = SgxSealedData::<[u8]>::from_internal(inner);
+        let mut plaintext = builder.unseal_slice(&sealed)?.decrypt;
+        let result = self.decode(&plaintext);
+        plaintext.zeroize();
+        result
+    }
+
+    fn decode(&self, plaintext: &[u8]) -> SgxResult<Vec<u8>> {
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        if plaintext.len() < HEADER_LEN || plaintext[..4] != MAGIC || plaintext[5..8] != [0; 3] {
+            return Err(invalid);
+        }
+        let mut len = [0_u8; 8];
+        len.copy_from_slice(&plaintext[8..HEADER_LEN]);
+        let len = u64::from_le_bytes(len);
+        let payload = &plaintext[HEADER_LEN..];
+
+        let max_len = payload
+            .len()
+            .saturating_mul(self.max_ratio)
+            .min(self.max_len);
+        match plaintext[4] {
+            STORED if len == payload.len() as u64 => Ok(payload.to_vec()),
+            LZ4 if len > max_len as u64 => Err(sgx_status_t::SGX_ERROR_OUT_OF_MEMORY),
+            LZ4 => decompress(payload, len as usize),
+            _ => Err(invalid),
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::new()
+    }
+}
+
+/// The longest an LZ4 block of `len` bytes can be.
+fn compress_bound(len: usize) -> usize {
+    len + len / 255 + 16
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+}
+
+fn push_len(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+fn push_sequence(out: &mut Vec<u8>, literals: &[u8], offset: usize, match_len: usize) {
+    let literal_len = literals.len();
+    let match_code = match_len.saturating_sub(MIN_MATCH);
+    let token = (literal_len.min(15) << 4) as u8 | match_code.min(15) as u8;
+    out.push(token);
+    if literal_len >= 15 {
+        push_len(out, literal_len - 15);
+    }
+    out.extend_from_slice(literals);
+    if match_len == 0 {
+        return;
+    }
+    out.extend_from_slice(&(offset as u16).to_le_bytes());
+    if match_code >= 15 {
+        push_len(out, match_code - 15);
+    }
+}
+
+/// Appends `data` to `out` as an LZ4 block, matching greedily the latest
+/// earlier occurrence of every 4 bytes.
+fn compress(data: &[u8], out: &mut Vec<u8>) {
+    let len = data.len();
+    let mut anchor = 0;
+    if len > MF_LIMIT {
+        // Positions plus one, so that zero is none.
+        let mut table = vec![0_u32; 1 << HASH_LOG];
+        let mut at = 0;
+        while at < len - MF_LIMIT {
+            let sequence = read_u32(data, at);
+            let slot = hash(sequence);
+            let candidate = table[slot] as usize;
+            table[slot] = at as u32 + 1;
+            if candidate == 0 || at - (candidate - 1) > MAX_OFFSET {
+                at += 1;
+                continue;
+            }
+            let candidate = candidate - 1;
+            if read_u32(data, candidate) != sequence {
+                at += 1;
+                continue;
+            }
+            let mut match_len = MIN_MATCH;
+            while at + match_len < len - LAST_LITERALS
+                && data[candidate + match_len] == data[at + match_len]
+            {
+                match_len += 1;
+            }
+            push_sequence(out, &data[anchor..at], at - candidate, match_len);
+            at += match_len;
+            anchor = at;
+        }
+    }
+    push_sequence(out, &data[anchor..], 0, 0);
+}
+
+fn read_len(block: &[u8], at: &mut usize, mut len: usize) -> SgxResult<usize> {
+    let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    if len < 15 {
+        return Ok(len);
+    }
+    loop {
+        let byte = *block.get(*at).ok_or(invalid)?;
+        *at += 1;
+        len = len.checked_add(byte as usize).ok_or(invalid)?;
+        if byte != 255 {
+            return Ok(len);
+        }
+    }
+}
+
+/// Decompresses an LZ4 block which must decompress to exactly `len` bytes,
+/// never writing past them.
+fn decompress(block: &[u8], len: usize) -> SgxResult<Vec<u8>> {
+    let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    let mut out = Vec::with_capacity(len);
+    let mut at = 0;
+    let result = loop {
+        let token = match block.get(at) {
+            Some(&token) => token as usize,
+            None => break Err(invalid),
+        };
+        at += 1;
+
+        let literals = match read_len(block, &mut at, token >> 4) {
+            Ok(literals) => literals,
+            Err(e) => break Err(e),
+        };
+        if literals > block.len() - at || literals > len - out.len() {
+            break Err(invalid);
+        }
+        out.extend_from_slice(&block[at..at + literals]);
+        at += literals;
+        if at == block.len() {
+            break if out.len() == len {
+                Ok(())
+            } else {
+                Err(invalid)
+            };
+        }
+
+        if block.len() - at < 2 {
+            break Err(invalid);
+        }
+        let offset = u16::from_le_bytes([block[at], block[at + 1]]) as usize;
+        at += 2;
+        let match_len = match read_len(block, &mut at, token & 15) {
+            Ok(match_len) => match_len.saturating_add(MIN_MATCH),
+            Err(e) => break Err(e),
+        };
+        if offset == 0 || offset > out.len() || match_len > len - out.len() {
+            break Err(invalid);
+        }
+        let start = out.len() - offset;
+        for i in start..start + match_len {
+            let byte = out[i];
+            out.push(byte);
+        }
+    };
+    match result {
+        Ok(()) => Ok(out),
+        Err(e) => {
+            out.zeroize();
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,416 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Monotonic counters, for detecting the rollback of sealed state.
+//!
+//! The host keeps sealed data, and can hand an enclave an older copy of it
+//! which still unseals. Storing the value of a counter which can't go back in
+//! the sealed data, and comparing it with the counter when unsealing, detects
+//! this. [`MonotonicCounter`] abstracts over where the counter is kept:
+//!
+//! * [`PlatformCounter`], with the `pse` feature, is a counter of the platform
+//!   services enclave. The PSE is deprecated and absent from most current
+//!   platforms, where creating a counter fails with
+//!   `SGX_ERROR_SERVICE_UNAVAILABLE`.
+//! * [`RemoteCounter`] is kept by a counter service, reached through a
+//!   [`CounterTransport`] which should be an attested TLS connection to it.
+//! * [`QuorumCounter`] is replicated across several counters, typically
+//!   remote counters kept by other enclaves, and answers as long as a majority
+//!   of them do.
+//!
+//! A [`RemoteCounter`] sends requests of:
+//!
+//! | offset | size | field                                        |
+//! |--------|------|----------------------------------------------|
+//! | 0      | 1    | operation, 1 to read and 2 to increment      |
+//! | 1      | 2    | counter id length, little endian             |
+//! | 3      |      | counter id, 16-byte random nonce             |
+//!
+//! and expects responses of:
+//!
+//! | offset | size | field                                        |
+//! |--------|------|----------------------------------------------|
+//! | 0      | 1    | status, below                                |
+//! | 1      | 16   | nonce of the request                         |
+//! | 17     | 8    | counter value, little endian                 |
+//!
+//! The status is 0 on success, 1 if the service has no counter of that id
+//! and 2 if the enclave may not use it, which fail with
+//! `SGX_ERROR_MC_NOT_FOUND` and `SGX_ERROR_MC_NO_ACCESS_RIGHT`.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use sgx_tcrypto::rng::fill_random;
+use sgx_types::*;
+
+/// A counter which can only go up.
+pub trait MonotonicCounter {
+    /// Returns the current value of the counter.
+    fn read(&mut self) -> SgxResult<u64>;
+
+    /// Increments the counter, returning its new value.
+    fn increment(&mut self) -> SgxResult<u64>;
+}
+
+impl<C: MonotonicCounter + ?Sized> MonotonicCounter for &mut C {
+    fn read(&mut self) -> SgxResult<u64> {
+        (**self).read()
+    }
+
+    fn increment(&mut self) -> SgxResult<u64> {
+        (**self).increment()
+    }
+}
+
+impl<C: MonotonicCounter + ?Sized> MonotonicCounter for Box<C> {
+    fn read(&mut self) -> SgxResult<u64> {
+        (**self).read()
+    }
+
+    fn increment(&mut self) -> SgxResult<u64> {
+        (**self).increment()
+    }
+}
+
+#[cfg(feature = "pse")]
+pub use self::pse::PlatformCounter;
+
+#[cfg(feature = "pse")]
+mod pse {
+    use super::MonotonicCounter;
+    use crate::builder::KeyPolicy;
+    use sgx_types::*;
+
+    // The platform services were removed from the SDK, so they are only
+    // declared here, for enclaves still linking `sgx_tservice`. The UUID of a
+    // counter is a 3-byte id followed by a 13-byte nonce.
+    extern "C" {
+        fn sgx_create_pse_session() -> sgx_status_t;
+        fn sgx_close_pse_session() -> sgx_status_t;
+        fn sgx_create_monotonic_counter_ex(
+            owner_policy: uint16_t,
+            owner_attribute_mask: *const sgx_attributes_t,
+            counter_uuid: *mut [u8; 16],
+            counter_value: *mut uint32_t,
+        ) -> sgx_status_t;
+        fn sgx_destroy_monotonic_counter(counter_uuid: *const [u8; 16]) -> sgx_status_t;
+        fn sgx_increment_monotonic_counter(
+            counter_uuid: *const [u8; 16],
+            counter_value: *mut uint32_t,
+        ) -> sgx_status_t;
+        fn sgx_read_monotonic_counter(
+            counter_uuid: *const [u8; 16],
+            counter_value: *mut uint32_t,
+        ) -> sgx_status_t;
+    }
+
+    const MC_POLICY_SIGNER: u16 = 0x1;
+    const MC_POLICY_ENCLAVE: u16 = 0x2;
+
+    /// Runs `f` in a session with the platform services enclave.
+    fn in_session<F: FnOnce() -> sgx_status_t>(f: F) -> SgxError {
+        match unsafe { sgx_create_pse_session() } {
+            sgx_status_t::SGX_SUCCESS => {}
+            e => return Err(e),
+        }
+        let status = f();
+        unsafe { sgx_close_pse_session() };
+        match status {
+            sgx_status_t::SGX_SUCCESS => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    /// A monotonic counter of the platform services enclave.
+    ///
+    /// Counters are 32-bit, and their number per enclave is limited by the
+    /// platform. The UUID identifies the counter and has to be kept, e.g.
+    /// sealed, to open it again.
+    pub struct PlatformCounter {
+        uuid: [u8; 16],
+    }
+
+    impl PlatformCounter {
+        /// Creates a counter starting at zero, which only enclaves with the
+        /// same identity, under `owner`, can use.
+        pub fn create(owner: KeyPolicy) -> SgxResult<PlatformCounter> {
+            let owner_policy = match owner {
+                KeyPolicy::MrEnclave => MC_POLICY_ENCLAVE,
+                KeyPolicy::MrSigner => MC_POLICY_SIGNER,
+                KeyPolicy::Both => MC_POLICY_ENCLAVE | MC_POLICY_SIGNER,
+            };
+            let mask = sgx_attributes_t {
+                flags: TSEAL_DEFAULT_FLAGSMASK,
+                xfrm: 0,
+            };
+            let mut uuid = [0_u8; 16];
+            let mut value = 0_u32;
+            in_session(|| unsafe {
+                sgx_create_monotonic_counter_ex(owner_policy, &mask, &mut uuid, &mut value)
+            })?;
+            Ok(PlatformCounter { uuid })
+        }
+
+        /// Opens the counter identified by `uuid`.
+        pub fn open(uuid: [u8; 16]) -> PlatformCounter {
+            PlatformCounter { uuid }
+        }
+
+        pub fn uuid(&self) -> [u8; 16] {
+            self.uuid
+        }
+
+        /// Destroys the counter, freeing it for the platform.
+        pub fn destroy(self) -> SgxError {
+            in_session(|| unsafe { sgx_destroy_monotonic_counter(&self.uuid) })
+        }
+    }
+
+    impl MonotonicCounter for PlatformCounter {
+        fn read(&mut self) -> SgxResult<u64> {
+            let mut value = 0_u32;
+            in_session(|| unsafe { sgx_read_monotonic_counter(&self.uuid, &mut value) })?;
+            Ok(u64::from(value))
+        }
+
+        fn increment(&mut self) -> SgxResult<u64> {
+            let mut value = 0_u32;
+            in_session(|| unsafe { sgx_increment_monotonic_counter(&self.uuid, &mut value) })?;
+            Ok(u64::from(value))
+        }
+    }
+}
+
+/// A channel to a counter service.
+///
+/// It should be an attested TLS connection, or another channel authenticating
+/// the service, as the counter is only as trustworthy as whoever answers.
+pub trait CounterTransport {
+    /// Sends `request` to the service and returns its response.
+    fn call(&mut self, request: &[u8]) -> SgxResult<Vec<u8>>;
+}
+
+const OP_READ: u8 = 1;
+const OP_INCREMENT: u8 = 2;
+const NONCE_LEN: usize = 16;
+const RESPONSE_LEN: usize = 1 + NONCE_LEN + 8;
+
+/// A counter kept by a counter service.
+///
+/// Every request carries a fresh nonce which the response has to echo, so
+/// responses can't be replayed, and the counter refuses values lower than one
+/// it has already seen, failing with `SGX_ERROR_INVALID_STATE`.
+pub struct RemoteCounter<T> {
+    transport: T,
+    id: Vec<u8>,
+    last: u64,
+}
+
+impl<T: CounterTransport> RemoteCounter<T> {
+    /// The counter `id` of the service at the other end of `transport`.
+    pub fn new(transport: T, id: &[u8]) -> SgxResult<RemoteCounter<T>> {
+        if id.len() > u16::MAX as usize {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(RemoteCounter {
+            transport,
+            id: id.to_vec(),
+            last: 0,
+        })
+    }
+
+    /// Refuses values below `value`, e.g. one recorded in sealed state.
+    pub fn with_floor(mut self, value: u64) -> RemoteCounter<T> {
+        self.last = value;
+        self
+    }
+
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    fn call(&mut self, op: u8) -> SgxResult<u64> {
+        let mut nonce = [0_u8; NONCE_LEN];
+        fill_random(&mut nonce)?;
+        let mut request = Vec::with_capacity(3 + self.id.len() + NONCE_LEN);
+        request.push(op);
+        request.extend_from_slice(&(self.id.len() as u16).to_le_bytes());
+        request.extend_from_slice(&self.id);
+        request.extend_from_slice(&nonce);
+
+        let response = self.transport.call(&request)?;
+        if response.len() != RESPONSE_LEN || response[1..1 + NONCE_LEN] != nonce {
+            return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+        }
+        match response[0] {
+            0 => {}
+            1 => return Err(sgx_status_t::SGX_ERROR_MC_NOT_FOUND),
+            2 => return Err(sgx_status_t::SGX_ERROR_MC_NO_ACCESS_RIGHT),
+            _ => return Err(sgx_status_t::SGX_ERROR_UNEXPECTED),
+        }
+        let mut value = [0_u8; 8];
+        value.copy_from_slice(&response[1 + NONCE_LEN..]);
+        let value = u64::from_le_bytes(value);
+
+        let regressed = match op {
+            OP_INCREMENT => value <= self.last,
+            _ => value < self.last,
+        };
+        if regressed {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+        }
+        self.last = value;
+        Ok(value)
+    }
+}
+
+impl<T: CounterTransport> MonotonicCounter for RemoteCounter<T> {
+    fn read(&mut self) -> SgxResult<u64> {
+        self.call(OP_READ)
+    }
+
+    fn increment(&mut self) -> SgxResult<u64> {
+        self.call(OP_INCREMENT)
+    }
+}
+
+/// The most increments [`QuorumCounter::increment`] makes on one replica to
+/// bring it to the new value.
+pub const MAX_CATCH_UP: u32 = 64;
+
+/// A counter replicated across several counters.
+///
+/// A value is read from a quorum of replicas, more than half of them by
+/// default, as the highest value any of them returns. Incrementing brings a
+/// quorum of replicas to one more than the value of the quorum, incrementing
+/// replicas which fell behind up to [`MAX_CATCH_UP`] times, so any later
+/// read, which shares at least one replica with that quorum, sees the new
+/// value. The counter thus survives the loss of the replicas outside a
+/// quorum, and rolling it back takes a quorum of them.
+///
+/// The value of the quorum is the threshold-th highest value read, counting
+/// the replicas which didn't answer as ahead of the others. Every earlier
+/// increment reached a quorum, so it is at least the last value returned,
+/// but when all replicas answer, one reporting a value far ahead of the
+/// others doesn't make them count up to it. The cap on catching up bounds
+/// the increments a replica can cause otherwise.
+pub struct QuorumCounter<C> {
+    replicas: Vec<C>,
+    threshold: usize,
+}
+
+impl<C: MonotonicCounter> QuorumCounter<C> {
+    /// A counter over `replicas`, answering once more than half of them do.
+    pub fn new(replicas: Vec<C>) -> SgxResult<QuorumCounter<C>> {
+        let threshold = replicas.len() / 2 + 1;
+        QuorumCounter::with_threshold(replicas, threshold)
+    }
+
+    /// A counter over `replicas`, answering once `threshold` of them do.
+    ///
+    /// The threshold has to be more than half of the replicas, or two
+    /// quorums could miss each other, and at most all of them.
+    pub fn with_threshold(replicas: Vec<C>, threshold: usize) -> SgxResult<QuorumCounter<C>> {
+        if threshold <= replicas.len() / 2 || threshold > replicas.len() {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(QuorumCounter {
+            replicas,
+            threshold,
+        })
+    }
+
+    pub fn replicas(&self) -> &[C] {
+        &self.replicas
+    }
+
+    pub fn into_replicas(self) -> Vec<C> {
+        self.replicas
+    }
+}
+
+impl<C: MonotonicCounter> MonotonicCounter for QuorumCounter<C> {
+    /// # Errors
+    ///
+    /// Returns the error of the last replica which failed if less than a
+    /// quorum answered.
+    fn read(&mut self) -> SgxResult<u64> {
+        let mut answered = 0;
+        let mut value = 0;
+        let mut error = sgx_status_t::SGX_ERROR_SERVICE_UNAVAILABLE;
+        for replica in self.replicas.iter_mut() {
+            match replica.read() {
+                Ok(v) => {
+                    answered += 1;
+                    value = value.max(v);
+                }
+                Err(e) => error = e,
+            }
+        }
+        if answered < self.threshold {
+            return Err(error);
+        }
+        Ok(value)
+    }
+
+    /// # Errors
+    ///
+    /// Returns the error of the last replica which failed if less than a
+    /// quorum reached the new value, a replica still behind after
+    /// [`MAX_CATCH_UP`] increments failing with `SGX_ERROR_INVALID_STATE`.
+    /// The replicas which did reach it keep it, so the counter may still have
+    /// moved.
+    fn increment(&mut self) -> SgxResult<u64> {
+        let mut values = Vec::with_capacity(self.replicas.len());
+        let mut error = sgx_status_t::SGX_ERROR_SERVICE_UNAVAILABLE;
+        for replica in self.replicas.iter_mut() {
+            match replica.read() {
+                Ok(v) => values.push(v),
+                Err(e) => error = e,
+            }
+        }
+        if values.len() < self.threshold {
+            return Err(error);
+        }
+        let ahead = self.replicas.len() - values.len();
+        values.sort_unstable_by(|a, b| b.cmp(a));
+        let target = values[self.threshold - 1 - ahead]
+            .checked_add(1)
+            .ok_or(sgx_status_t::SGX_ERROR_MC_USED_UP)?;
+
+        let mut reached = 0;
+        for replica in self.replicas.iter_mut() {
+            let mut caught_up = Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+            for _ in 0..MAX_CATCH_UP {
+                match replica.increment() {
+                    Ok(v) if v >= target => caught_up = Ok(()),
+                    Ok(_) => continue,
+                    Err(e) => caught_up = Err(e),
+                }
+                break;
+            }
+            match caught_up {
+                Ok(()) => reached += 1,
+                Err(e) => error = e,
+            }
+        }
+        if reached < self.threshold {
+            return Err(error);
+        }
+        Ok(target)
+    }
+}
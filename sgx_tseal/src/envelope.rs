@@ -0,0 +1,378 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Envelope encryption.
+//!
+//! A payload is encrypted under a fresh random data key, and the data key is
+//! wrapped by a long-lived key encryption key: the enclave sealing key, with
+//! [`SealKeyWrap`], or a key held by a key management service, through any
+//! other implementation of [`KeyWrap`]. Large payloads are thus encrypted
+//! once, and rotating the key encryption key only rewraps the data keys.
+//!
+//! [`seal`] returns a self-describing envelope:
+//!
+//! | offset | size | field                                        |
+//! |--------|------|----------------------------------------------|
+//! | 0      | 4    | magic, `SENV`                                |
+//! | 4      | 1    | format version, [`ENVELOPE_VERSION`]         |
+//! | 5      | 1    | payload cipher, [`EnvelopeCipher`]           |
+//! | 6      | 1    | key wrap scheme, [`KeyWrapKind`]             |
+//! | 7      | 1    | reserved, zero                               |
+//! | 8      | 2    | key id length, little endian                 |
+//! | 10     | 4    | wrapped key length, little endian            |
+//! | 14     |      | key id, wrapped data key, nonce              |
+//! |        |      | ciphertext, 16-byte tag                      |
+//!
+//! Everything before the ciphertext is authenticated along with the
+//! additional data of the caller, which isn't stored in the envelope and must
+//! be passed again to [`open`].
+//!
+//! A KMS is reached through an ocall the application declares in its EDL.
+//! The data key must only cross the enclave boundary wrapped, or over a
+//! channel ending in the enclave such as an attested TLS connection, never in
+//! the clear to the untrusted application.
+
+use crate::internal::SgxInternalSealedData;
+use alloc::vec::Vec;
+use core::ptr;
+use sgx_tcrypto::aead::{Aead, Aes128GcmSiv, Tag, XChaCha20Poly1305, TAG_LEN};
+use sgx_tcrypto::rng::fill_random;
+use sgx_types::*;
+
+/// The version of the envelope format written by [`seal`].
+pub const ENVELOPE_VERSION: u8 = 1;
+
+const MAGIC: [u8; 4] = *b"SENV";
+const HEADER_LEN: usize = 14;
+const DATA_KEY_LEN: usize = 32;
+
+/// The cipher encrypting the payload of an envelope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EnvelopeCipher {
+    /// AES-128-GCM-SIV, with a 96-bit nonce.
+    Aes128GcmSiv = 1,
+    /// XChaCha20-Poly1305, with a 192-bit nonce.
+    XChaCha20Poly1305 = 2,
+}
+
+impl EnvelopeCipher {
+    fn from_u8(v: u8) -> Option<EnvelopeCipher> {
+        match v {
+            1 => Some(EnvelopeCipher::Aes128GcmSiv),
+            2 => Some(EnvelopeCipher::XChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    /// The length of the data key, in bytes.
+    pub fn key_len(self) -> usize {
+        match self {
+            EnvelopeCipher::Aes128GcmSiv => Aes128GcmSiv::KEY_LEN,
+            EnvelopeCipher::XChaCha20Poly1305 => XChaCha20Poly1305::KEY_LEN,
+        }
+    }
+
+    /// The length of the nonce, in bytes.
+    pub fn nonce_len(self) -> usize {
+        match self {
+            EnvelopeCipher::Aes128GcmSiv => Aes128GcmSiv::NONCE_LEN,
+            EnvelopeCipher::XChaCha20Poly1305 => XChaCha20Poly1305::NONCE_LEN,
+        }
+    }
+}
+
+/// The scheme wrapping the data key of an envelope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KeyWrapKind {
+    /// Sealed with the enclave sealing key.
+    SealKey = 1,
+    /// Wrapped by a key management service.
+    Kms = 2,
+}
+
+impl KeyWrapKind {
+    fn from_u8(v: u8) -> Option<KeyWrapKind> {
+        match v {
+            1 => Some(KeyWrapKind::SealKey),
+            2 => Some(KeyWrapKind::Kms),
+            _ => None,
+        }
+    }
+}
+
+/// A key encryption key, wrapping and unwrapping data keys.
+pub trait KeyWrap {
+    /// The scheme recorded in the envelopes.
+    fn kind(&self) -> KeyWrapKind;
+
+    /// Identifies the key encryption key, e.g. by the name of a KMS key, so
+    /// that the right one can be picked to open an envelope. Empty by default.
+    fn key_id(&self) -> &[u8] {
+        &[]
+    }
+
+    /// Wraps `key`.
+    fn wrap(&self, key: &[u8]) -> SgxResult<Vec<u8>>;
+
+    /// Unwraps `wrapped` into `key`, which has the length of the data key.
+    fn unwrap(&self, wrapped: &[u8], key: &mut [u8]) -> SgxError;
+}
+
+/// Wraps data keys with the enclave sealing key, as
+/// [`SgxSealedData`](crate::SgxSealedData) seals data.
+#[derive(Clone, Copy, Default)]
+pub struct SealKeyWrap {
+    policy: Option<(u16, sgx_attributes_t, sgx_misc_select_t)>,
+}
+
+impl SealKeyWrap {
+    /// Seals to the signer of the enclave, with the default masks of
+    /// `seal_data`.
+    pub fn new() -> SealKeyWrap {
+        SealKeyWrap { policy: None }
+    }
+
+    /// Seals with the given key policy and masks, as `seal_data_ex`.
+    pub fn with_policy(
+        key_policy: u16,
+        attribute_mask: sgx_attributes_t,
+        misc_mask: sgx_misc_select_t,
+    ) -> SealKeyWrap {
+        SealKeyWrap {
+            policy: Some((key_policy, attribute_mask, misc_mask)),
+        }
+    }
+}
+
+impl KeyWrap for SealKeyWrap {
+    fn kind(&self) -> KeyWrapKind {
+        KeyWrapKind::SealKey
+    }
+
+    fn wrap(&self, key: &[u8]) -> SgxResult<Vec<u8>> {
+        let sealed = match self.policy {
+            None => SgxInternalSealedData::seal_data(&[], key)?,
+            Some((key_policy, attribute_mask, misc_mask)) => SgxInternalSealedData::seal_data_ex(
+                key_policy,
+                attribute_mask,
+                misc_mask,
+                &[],
+                key,
+            )?,
+        };
+
+        sealed.to_bytes()
+    }
+
+    fn unwrap(&self, wrapped: &[u8], key: &mut [u8]) -> SgxError {
+        let sealed = SgxInternalSealedData::from_bytes(wrapped)?;
+        let mut unsealed = sealed.unseal_data()?;
+        let result = if unsealed.decrypt.len() == key.len() {
+            key.copy_from_slice(&unsealed.decrypt);
+            Ok(())
+        } else {
+            Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH)
+        };
+        wipe(&mut unsealed.decrypt);
+        result
+    }
+}
+
+fn wipe(buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+        unsafe { ptr::write_volatile(b, 0) };
+    }
+}
+
+/// The parsed fields of an envelope, borrowed from it.
+#[derive(Clone, Copy, Debug)]
+pub struct EnvelopeHeader<'a> {
+    pub version: u8,
+    pub cipher: EnvelopeCipher,
+    pub wrap_kind: KeyWrapKind,
+    pub key_id: &'a [u8],
+    pub wrapped_key: &'a [u8],
+    pub nonce: &'a [u8],
+    authenticated: &'a [u8],
+    ciphertext: &'a [u8],
+    tag: &'a [u8],
+}
+
+impl<'a> EnvelopeHeader<'a> {
+    /// Parses `envelope`, failing with `SGX_ERROR_INVALID_PARAMETER` if it is
+    /// malformed or of an unknown version; nothing is authenticated yet.
+    pub fn parse(envelope: &'a [u8]) -> SgxResult<EnvelopeHeader<'a>> {
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        if envelope.len() < HEADER_LEN || envelope[..4] != MAGIC {
+            return Err(invalid);
+        }
+        if envelope[4] != ENVELOPE_VERSION || envelope[7] != 0 {
+            return Err(invalid);
+        }
+        let cipher = EnvelopeCipher::from_u8(envelope[5]).ok_or(invalid)?;
+        let wrap_kind = KeyWrapKind::from_u8(envelope[6]).ok_or(invalid)?;
+        let key_id_len = u16::from_le_bytes([envelope[8], envelope[9]]) as usize;
+        let mut wrapped_len = [0_u8; 4];
+        wrapped_len.copy_from_slice(&envelope[10..14]);
+        let wrapped_len = u32::from_le_bytes(wrapped_len) as usize;
+
+        let rest = &envelope[HEADER_LEN..];
+        let fields = key_id_len + wrapped_len + cipher.nonce_len();
+        if rest.len() < fields + TAG_LEN {
+            return Err(invalid);
+        }
+        let (key_id, rest) = rest.split_at(key_id_len);
+        let (wrapped_key, rest) = rest.split_at(wrapped_len);
+        let (nonce, rest) = rest.split_at(cipher.nonce_len());
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        Ok(EnvelopeHeader {
+            version: envelope[4],
+            cipher,
+            wrap_kind,
+            key_id,
+            wrapped_key,
+            nonce,
+            authenticated: &envelope[..HEADER_LEN + fields],
+            ciphertext,
+            tag,
+        })
+    }
+
+    /// The length of the payload.
+    pub fn payload_len(&self) -> usize {
+        self.ciphertext.len()
+    }
+}
+
+fn encrypt_with<A: Aead>(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    src: &[u8],
+    dst: &mut [u8],
+) -> SgxResult<Tag> {
+    A::new(key)?.encrypt(nonce, aad, src, dst)
+}
+
+fn decrypt_with<A: Aead>(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    src: &[u8],
+    tag: &Tag,
+    dst: &mut [u8],
+) -> SgxError {
+    A::new(key)?.decrypt(nonce, aad, src, tag, dst)
+}
+
+/// Encrypts `plaintext` under a fresh data key wrapped by `wrap`, and returns
+/// the envelope; `aad` is authenticated but not stored.
+pub fn seal<W: KeyWrap>(
+    wrap: &W,
+    cipher: EnvelopeCipher,
+    aad: &[u8],
+    plaintext: &[u8],
+) -> SgxResult<Vec<u8>> {
+    let key_id = wrap.key_id();
+    if key_id.len() > u16::MAX as usize {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+
+    let mut data_key = [0_u8; DATA_KEY_LEN];
+    let key = &mut data_key[..cipher.key_len()];
+    let result = fill_random(key)
+        .and_then(|_| wrap.wrap(key))
+        .and_then(|wrapped| {
+            if wrapped.len() > u32::MAX as usize {
+                return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+            }
+            let mut envelope = Vec::with_capacity(
+                HEADER_LEN
+                    + key_id.len()
+                    + wrapped.len()
+                    + cipher.nonce_len()
+                    + plaintext.len()
+                    + TAG_LEN,
+            );
+            envelope.extend_from_slice(&MAGIC);
+            envelope.extend_from_slice(&[ENVELOPE_VERSION, cipher as u8, wrap.kind() as u8, 0]);
+            envelope.extend_from_slice(&(key_id.len() as u16).to_le_bytes());
+            envelope.extend_from_slice(&(wrapped.len() as u32).to_le_bytes());
+            envelope.extend_from_slice(key_id);
+            envelope.extend_from_slice(&wrapped);
+            let nonce_start = envelope.len();
+            envelope.resize(nonce_start + cipher.nonce_len(), 0);
+            fill_random(&mut envelope[nonce_start..])?;
+
+            let header_len = envelope.len();
+            let mut full_aad = Vec::with_capacity(header_len + aad.len());
+            full_aad.extend_from_slice(&envelope);
+            full_aad.extend_from_slice(aad);
+            envelope.resize(header_len + plaintext.len(), 0);
+            let (head, body) = envelope.split_at_mut(header_len);
+            let nonce = &head[nonce_start..];
+            let tag = match cipher {
+                EnvelopeCipher::Aes128GcmSiv => {
+                    encrypt_with::<Aes128GcmSiv>(key, nonce, &full_aad, plaintext, body)?
+                }
+                EnvelopeCipher::XChaCha20Poly1305 => {
+                    encrypt_with::<XChaCha20Poly1305>(key, nonce, &full_aad, plaintext, body)?
+                }
+            };
+            envelope.extend_from_slice(&tag);
+            Ok(envelope)
+        });
+    wipe(&mut data_key);
+    result
+}
+
+/// Unwraps the data key of `envelope` with `wrap`, and decrypts the payload.
+///
+/// Fails with `SGX_ERROR_INVALID_PARAMETER` if the envelope is malformed or
+/// was wrapped by another scheme or key than `wrap`, and with
+/// `SGX_ERROR_MAC_MISMATCH` if it, or `aad`, was tampered with.
+pub fn open<W: KeyWrap>(wrap: &W, envelope: &[u8], aad: &[u8]) -> SgxResult<Vec<u8>> {
+    let header = EnvelopeHeader::parse(envelope)?;
+    if header.wrap_kind != wrap.kind() || header.key_id != wrap.key_id() {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+
+    let mut data_key = [0_u8; DATA_KEY_LEN];
+    let key = &mut data_key[..header.cipher.key_len()];
+    let result = wrap.unwrap(header.wrapped_key, key).and_then(|_| {
+        let mut full_aad = Vec::with_capacity(header.authenticated.len() + aad.len());
+        full_aad.extend_from_slice(header.authenticated);
+        full_aad.extend_from_slice(aad);
+        let mut tag = [0_u8; TAG_LEN];
+        tag.copy_from_slice(header.tag);
+        let mut plaintext = vec![0_u8; header.ciphertext.len()];
+        let (nonce, src) = (header.nonce, header.ciphertext);
+        match header.cipher {
+            EnvelopeCipher::Aes128GcmSiv => {
+                decrypt_with::<Aes128GcmSiv>(key, nonce, &full_aad, src, &tag, &mut plaintext)?
+            }
+            EnvelopeCipher::XChaCha20Poly1305 => {
+                decrypt_with::<XChaCha20Poly1305>(key, nonce, &full_aad, src, &tag, &mut plaintext)?
+            }
+        }
+        Ok(plaintext)
+    });
+    wipe(&mut data_key);
+    result
+}
@@ -0,0 +1,447 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Escrowed backups of sealed keys, recoverable by M of N operators.
+//!
+//! A sealed key only unseals on the platform it was sealed on, so losing the
+//! platform loses the key. An [`EscrowSession`] exports a backup of it for
+//! disaster recovery: the key is encrypted under a fresh escrow key, which is
+//! split with Shamir's scheme into a share for each operator of an
+//! [`EscrowPolicy`], encrypted to the operator's X25519 key. Any `threshold`
+//! operators together can recover the key, and fewer learn nothing about it.
+//!
+//! The enclave only exports a backup once `threshold` operators approved it,
+//! by signing the [`challenge`] of the session with their Ed25519 key. The
+//! challenge covers the policy and a random nonce of the session, so an
+//! approval can't be replayed to another session or policy.
+//!
+//! The policy itself comes from outside the enclave, so a key is bound to
+//! its policy when it's sealed, by [`EscrowPolicy::seal`]: the additional
+//! data of the sealed key is `SESP` followed by the digest of the policy,
+//! and [`export`] refuses a key sealed under another policy. Otherwise the
+//! host could start a session under a policy of its own single operator.
+//!
+//! A backup is laid out as:
+//!
+//! | offset | size    | field                                        |
+//! |--------|---------|----------------------------------------------|
+//! | 0      | 4       | magic, `SESC`                                |
+//! | 4      | 1       | format version, [`ESCROW_VERSION`]           |
+//! | 5      | 1       | threshold                                    |
+//! | 6      | 1       | number of operators, N                       |
+//! | 7      | 1       | reserved, zero                               |
+//! | 8      | 32      | SHA-256 digest of the policy                 |
+//! | 40     | 32      | nonce of the session                         |
+//! | 72     | N * 105 | the shares, in the order of the operators    |
+//! |        | 24      | nonce of the key                             |
+//! |        |         | key, encrypted under the escrow key          |
+//! |        | 16      | tag                                          |
+//!
+//! and each share as an ephemeral X25519 public key, a 24-byte nonce, the
+//! encrypted index and 32-byte value of the share, and a 16-byte tag. The
+//! ciphers are XChaCha20-Poly1305, authenticating the first 72 bytes of the
+//! backup, and the share keys are derived with HKDF-SHA256.
+//!
+//! [`challenge`]: EscrowSession::challenge
+//! [`export`]: EscrowSession::export
+
+use crate::builder::SealingBuilder;
+use crate::seal::SgxSealedData;
+use alloc::vec::Vec;
+use sgx_tcrypto::aead::{Aead, Tag, XChaCha20Poly1305, TAG_LEN};
+use sgx_tcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use sgx_tcrypto::hash::{Hash, Sha256};
+use sgx_tcrypto::kdf::hkdf_sha256;
+use sgx_tcrypto::rng::fill_random;
+use sgx_tcrypto::secret::{SecretBytes, Zeroize};
+use sgx_tcrypto::shamir::{combine_shares, split_secret, Share, MAX_SHARES};
+use sgx_tcrypto::x25519::{X25519PrivateKey, X25519PublicKey};
+use sgx_types::*;
+
+/// The version of the backup format written by [`EscrowSession::export`].
+pub const ESCROW_VERSION: u8 = 1;
+
+const MAGIC: [u8; 4] = *b"SESC";
+const POLICY_MAGIC: [u8; 4] = *b"SESP";
+const POLICY_AAD_LEN: usize = 36;
+const HEADER_LEN: usize = 72;
+const NONCE_LEN: usize = 24;
+const SHARE_LEN: usize = 32 + NONCE_LEN + 33 + TAG_LEN;
+const CHALLENGE_CONTEXT: &[u8] = b"sgx_tseal escrow approval v1";
+const SHARE_CONTEXT: &[u8] = b"sgx_tseal escrow share v1";
+
+/// An operator holding a share of the backups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Operator {
+    /// Signs approvals.
+    pub signing_key: Ed25519PublicKey,
+    /// Receives the share.
+    pub encryption_key: X25519PublicKey,
+}
+
+/// The operators of backups and how many of them it takes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EscrowPolicy {
+    operators: Vec<Operator>,
+    threshold: usize,
+}
+
+impl EscrowPolicy {
+    /// A policy where `threshold` of `operators` approve exports and recover
+    /// backups.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` unless `1 <= threshold <=
+    /// operators.len() <= 255` and the operators have distinct signing keys.
+    pub fn new(operators: Vec<Operator>, threshold: usize) -> SgxResult<EscrowPolicy> {
+        if threshold == 0 || threshold > operators.len() || operators.len() > MAX_SHARES {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        for (i, operator) in operators.iter().enumerate() {
+            if operators[..i]
+                .iter()
+                .any(|other| other.signing_key == operator.signing_key)
+            {
+                return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+            }
+        }
+        Ok(EscrowPolicy {
+            operators,
+            threshold,
+        })
+    }
+
+    pub fn operators(&self) -> &[Operator] {
+        &self.operators
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// The SHA-256 digest of the threshold and the keys of the operators,
+    /// recorded in the backups.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(&[self.threshold as u8, self.operators.len() as u8]);
+        for operator in &self.operators {
+            hasher.update(&operator.signing_key.to_bytes());
+            hasher.update(&operator.encryption_key.0);
+        }
+        let mut digest = [0_u8; 32];
+        hasher.finalize_into(&mut digest);
+        digest
+    }
+
+    /// Seals `key` with the class of `builder`, bound to the policy, for
+    /// sessions under the policy to export.
+    ///
+    /// The additional data of `builder` is replaced by the digest of the
+    /// policy.
+    pub fn seal<'a>(
+        &self,
+        builder: &SealingBuilder<'_>,
+        key: &'a [u8],
+    ) -> SgxResult<SgxSealedData<'a, [u8]>> {
+        let aad = self.additional_data();
+        builder.detached().additional_data(&aad).seal_slice(key)
+    }
+
+    fn additional_data(&self) -> [u8; POLICY_AAD_LEN] {
+        let mut aad = [0_u8; POLICY_AAD_LEN];
+        aad[..4].copy_from_slice(&POLICY_MAGIC);
+        aad[4..].copy_from_slice(&self.digest());
+        aad
+    }
+}
+
+/// An export of a backup, waiting for the approval of the operators.
+pub struct EscrowSession {
+    policy: EscrowPolicy,
+    nonce: [u8; 32],
+    approved: Vec<bool>,
+}
+
+impl EscrowSession {
+    /// Starts a session under `policy`, with a fresh nonce.
+    pub fn new(policy: EscrowPolicy) -> SgxResult<EscrowSession> {
+        let mut nonce = [0_u8; 32];
+        fill_random(&mut nonce)?;
+        let approved = vec![false; policy.operators.len()];
+        Ok(EscrowSession {
+            policy,
+            nonce,
+            approved,
+        })
+    }
+
+    pub fn policy(&self) -> &EscrowPolicy {
+        &self.policy
+    }
+
+    /// The message operators sign to approve the export: a context string,
+    /// the digest of the policy and the nonce of the session.
+    pub fn challenge(&self) -> Vec<u8> {
+        let mut challenge = Vec::with_capacity(CHALLENGE_CONTEXT.len() + 64);
+        challenge.extend_from_slice(CHALLENGE_CONTEXT);
+        challenge.extend_from_slice(&self.policy.digest());
+        challenge.extend_from_slice(&self.nonce);
+        challenge
+    }
+
+    /// Records the approval of the operator at `index` of the policy.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if there's no such operator,
+    /// and with `SGX_ERROR_INVALID_SIGNATURE` if `signature` isn't theirs
+    /// over the challenge.
+    pub fn approve(&mut self, index: usize, signature: &Ed25519Signature) -> SgxError {
+        let operator = self
+            .policy
+            .operators
+            .get(index)
+            .ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+        if !operator.signing_key.verify(&self.challenge(), signature) {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+        }
+        self.approved[index] = true;
+        Ok(())
+    }
+
+    /// Returns the number of operators who approved so far.
+    pub fn approvals(&self) -> usize {
+        self.approved.iter().filter(|&&approved| approved).count()
+    }
+
+    /// Unseals the key sealed in `sealed` by [`EscrowPolicy::seal`], checked
+    /// against `builder`, and exports a backup of it, ending the session.
+    ///
+    /// Fails with `SGX_ERROR_NO_PRIVILEGE` if fewer than `threshold`
+    /// operators approved, or if the key was sealed under another policy
+    /// than the one of the session.
+    pub fn export(
+        self,
+        builder: &SealingBuilder<'_>,
+        sealed: &SgxSealedData<'_, [u8]>,
+    ) -> SgxResult<EscrowBackup> {
+        if self.approvals() < self.policy.threshold {
+            return Err(sgx_status_t::SGX_ERROR_NO_PRIVILEGE);
+        }
+        let aad = self.policy.additional_data();
+        if sealed.get_additional_txt() != aad {
+            return Err(sgx_status_t::SGX_ERROR_NO_PRIVILEGE);
+        }
+        let mut key = builder
+            .detached()
+            .additional_data(&aad)
+            .unseal_slice(sealed)?
+            .decrypt;
+
+        let mut bytes = Vec::with_capacity(
+            HEADER_LEN + self.policy.operators.len() * SHARE_LEN + NONCE_LEN + key.len() + TAG_LEN,
+        );
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&[
+            ESCROW_VERSION,
+            self.policy.threshold as u8,
+            self.policy.operators.len() as u8,
+            0,
+        ]);
+        bytes.extend_from_slice(&self.policy.digest());
+        bytes.extend_from_slice(&self.nonce);
+
+        let result = encrypt_backup(&self.policy, &key, &mut bytes);
+        key.zeroize();
+        result?;
+        Ok(EscrowBackup { bytes })
+    }
+}
+
+/// Appends the shares and the encrypted key to the header in `bytes`.
+fn encrypt_backup(policy: &EscrowPolicy, key: &[u8], bytes: &mut Vec<u8>) -> SgxError {
+    let mut escrow_key = SecretBytes::<32>::zeroed();
+    fill_random(escrow_key.expose_secret_mut())?;
+    let mut shares = vec![Share::<32>::default(); policy.operators.len()];
+    split_secret(escrow_key.expose_secret(), policy.threshold, &mut shares)?;
+
+    for (operator, share) in policy.operators.iter().zip(shares.iter()) {
+        let ephemeral = X25519PrivateKey::generate()?;
+        let ephemeral_public = ephemeral.public_key();
+        let share_key = share_key(
+            &ephemeral.diffie_hellman(&operator.encryption_key)?,
+            &ephemeral_public,
+            &operator.encryption_key,
+        )?;
+        let mut plaintext = SecretBytes::<33>::zeroed();
+        plaintext.expose_secret_mut()[0] = share.index;
+        plaintext.expose_secret_mut()[1..].copy_from_slice(share.value.expose_secret());
+
+        bytes.extend_from_slice(&ephemeral_public.0);
+        let start = bytes.len();
+        seal_within(
+            share_key.expose_secret(),
+            plaintext.expose_secret(),
+            bytes,
+            start,
+        )?;
+    }
+
+    let start = bytes.len();
+    seal_within(escrow_key.expose_secret(), key, bytes, start)
+}
+
+/// Encrypts `plaintext` under `key` with a random nonce, appending the nonce,
+/// ciphertext and tag to `bytes`, and authenticating the header.
+fn seal_within(key: &[u8; 32], plaintext: &[u8], bytes: &mut Vec<u8>, start: usize) -> SgxError {
+    let mut nonce = [0_u8; NONCE_LEN];
+    fill_random(&mut nonce)?;
+    bytes.extend_from_slice(&nonce);
+    bytes.resize(start + NONCE_LEN + plaintext.len(), 0);
+    let (header, rest) = bytes.split_at_mut(start + NONCE_LEN);
+    let tag =
+        XChaCha20Poly1305::new(key)?.encrypt(&nonce, &header[..HEADER_LEN], plaintext, rest)?;
+    bytes.extend_from_slice(&tag);
+    Ok(())
+}
+
+/// Decrypts what `seal_within` wrote at `at` in `bytes` into `plaintext`.
+fn open_within(key: &[u8; 32], bytes: &[u8], at: usize, plaintext: &mut [u8]) -> SgxError {
+    let nonce = &bytes[at..at + NONCE_LEN];
+    let ciphertext = &bytes[at + NONCE_LEN..at + NONCE_LEN + plaintext.len()];
+    let mut tag: Tag = [0; TAG_LEN];
+    tag.copy_from_slice(&bytes[at + NONCE_LEN + plaintext.len()..][..TAG_LEN]);
+    XChaCha20Poly1305::new(key)?.decrypt(nonce, &bytes[..HEADER_LEN], ciphertext, &tag, plaintext)
+}
+
+fn share_key(
+    shared: &sgx_tcrypto::x25519::X25519SharedSecret,
+    ephemeral: &X25519PublicKey,
+    recipient: &X25519PublicKey,
+) -> SgxResult<SecretBytes<32>> {
+    let mut salt = [0_u8; 64];
+    salt[..32].copy_from_slice(&ephemeral.0);
+    salt[32..].copy_from_slice(&recipient.0);
+    let mut key = SecretBytes::<32>::zeroed();
+    hkdf_sha256(
+        &salt,
+        shared.as_bytes(),
+        SHARE_CONTEXT,
+        key.expose_secret_mut(),
+    )?;
+    Ok(key)
+}
+
+/// A backup exported by an [`EscrowSession`].
+#[derive(Clone, Debug)]
+pub struct EscrowBackup {
+    bytes: Vec<u8>,
+}
+
+impl EscrowBackup {
+    /// Parses a backup, failing with `SGX_ERROR_INVALID_PARAMETER` if it's
+    /// malformed or of an unknown version.
+    pub fn from_bytes(bytes: Vec<u8>) -> SgxResult<EscrowBackup> {
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        if bytes.len() < HEADER_LEN || bytes[..4] != MAGIC || bytes[4] != ESCROW_VERSION {
+            return Err(invalid);
+        }
+        let (threshold, operators) = (bytes[5] as usize, bytes[6] as usize);
+        if threshold == 0 || threshold > operators || bytes[7] != 0 {
+            return Err(invalid);
+        }
+        if bytes.len() < HEADER_LEN + operators * SHARE_LEN + NONCE_LEN + TAG_LEN {
+            return Err(invalid);
+        }
+        Ok(EscrowBackup { bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Returns the number of shares needed to recover the key.
+    pub fn threshold(&self) -> usize {
+        self.bytes[5] as usize
+    }
+
+    /// Returns the number of operators, and shares.
+    pub fn operators(&self) -> usize {
+        self.bytes[6] as usize
+    }
+
+    /// Returns the digest of the policy the backup was exported under.
+    pub fn policy_digest(&self) -> [u8; 32] {
+        let mut digest = [0_u8; 32];
+        digest.copy_from_slice(&self.bytes[8..40]);
+        digest
+    }
+
+    /// Decrypts the share of the operator at `index`, with their X25519
+    /// private key.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if there's no such operator,
+    /// and with `SGX_ERROR_MAC_MISMATCH` if the key isn't theirs or the
+    /// backup was tampered with.
+    pub fn open_share(&self, index: usize, key: &X25519PrivateKey) -> SgxResult<Share<32>> {
+        if index >= self.operators() {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let at = HEADER_LEN + index * SHARE_LEN;
+        let mut ephemeral = X25519PublicKey([0; 32]);
+        ephemeral.0.copy_from_slice(&self.bytes[at..at + 32]);
+        let share_key = share_key(
+            &key.diffie_hellman(&ephemeral)?,
+            &ephemeral,
+            &key.public_key(),
+        )?;
+
+        let mut plaintext = SecretBytes::<33>::zeroed();
+        open_within(
+            share_key.expose_secret(),
+            &self.bytes,
+            at + 32,
+            plaintext.expose_secret_mut(),
+        )?;
+        let mut share = Share::<32> {
+            index: plaintext.expose_secret()[0],
+            ..Default::default()
+        };
+        share
+            .value
+            .expose_secret_mut()
+            .copy_from_slice(&plaintext.expose_secret()[1..]);
+        Ok(share)
+    }
+
+    /// Recovers the key from `threshold` or more of its shares.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if there are too few shares
+    /// or two share an index, and with `SGX_ERROR_MAC_MISMATCH` if one of
+    /// them is wrong.
+    pub fn recover(&self, shares: &[Share<32>]) -> SgxResult<Vec<u8>> {
+        if shares.len() < self.threshold() {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let escrow_key = combine_shares(shares)?;
+        let at = HEADER_LEN + self.operators() * SHARE_LEN;
+        let mut key = vec![0_u8; self.bytes.len() - at - NONCE_LEN - TAG_LEN];
+        open_within(escrow_key.expose_secret(), &self.bytes, at, &mut key)?;
+        Ok(key)
+    }
+}
@@ -0,0 +1,383 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Importing existing keys into the enclave, wrapped to an attested key.
+//!
+//! A user migrating a key into the enclave must know the key only reaches
+//! the enclave. A [`KeyImportSession`] draws an ephemeral X25519 wrapping
+//! key and a session ID, and reports them in an [`ImportOffer`] whose
+//! report data binds both, for the host to turn into a quote. The client
+//! verifies the quote, checks [`import_report_data`] against it, and wraps
+//! its key to the offer with [`wrap_for_import`]. The enclave unwraps the
+//! key, seals it and returns a [`Receipt`], a MAC only the holder of the
+//! wrapping key can compute, which proves to the client that the enclave it
+//! attested holds the key now.
+//!
+//! A session accepts a single import, and destroys its wrapping key after
+//! the first attempt, which succeeds or not: a wrapped key can't be replayed
+//! into another session, whose key and ID differ, nor tried again against
+//! the same one. Its states are:
+//!
+//! | state                         | after                             |
+//! |-------------------------------|-----------------------------------|
+//! | [`ImportState::Offered`]      | [`KeyImportSession::new`]         |
+//! | [`ImportState::Imported`]     | a successful [`import`]           |
+//! | [`ImportState::Failed`]       | a failed [`import`], or [`abort`] |
+//!
+//! A wrapped key is laid out as:
+//!
+//! | offset | size | field                                     |
+//! |--------|------|-------------------------------------------|
+//! | 0      | 4    | magic, `SKIM`                             |
+//! | 4      | 1    | format version, [`IMPORT_VERSION`]        |
+//! | 5      | 16   | session ID                                |
+//! | 21     | 32   | ephemeral X25519 public key of the client |
+//! | 53     | 24   | nonce                                     |
+//! | 77     |      | key, encrypted with XChaCha20-Poly1305    |
+//! |        | 16   | tag                                       |
+//!
+//! The first 77 bytes are authenticated. The wrapping and receipt keys are
+//! derived with HKDF-SHA256 from the shared secret, salted with the session
+//! ID, over both public keys.
+//!
+//! [`import`]: KeyImportSession::import
+//! [`abort`]: KeyImportSession::abort
+
+use crate::builder::SealingBuilder;
+use alloc::vec::Vec;
+use core::fmt;
+use sgx_tcrypto::aead::{Aead, Tag, XChaCha20Poly1305, TAG_LEN};
+use sgx_tcrypto::hash::{Hash, Sha256};
+use sgx_tcrypto::kdf::hkdf_sha256;
+use sgx_tcrypto::mac::HmacSha256;
+use sgx_tcrypto::rng::fill_random;
+use sgx_tcrypto::secret::{SecretBytes, Zeroize};
+use sgx_tcrypto::x25519::{X25519PrivateKey, X25519PublicKey};
+use sgx_tse::rsgx_create_report;
+use sgx_types::*;
+
+/// The version of the format of wrapped keys.
+pub const IMPORT_VERSION: u8 = 1;
+
+/// The length of session IDs, in bytes.
+pub const SESSION_ID_LEN: usize = 16;
+
+const MAGIC: [u8; 4] = *b"SKIM";
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = 5 + SESSION_ID_LEN + 32 + NONCE_LEN;
+const CONTEXT: &[u8] = b"sgx_tseal key import v1";
+const RECEIPT_CONTEXT: &[u8] = b"sgx_tseal key import receipt v1";
+
+/// The state of a [`KeyImportSession`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportState {
+    /// Waiting for the wrapped key.
+    Offered,
+    /// The key was imported.
+    Imported,
+    /// The import failed or was aborted; the wrapping key is destroyed.
+    Failed,
+}
+
+/// What the enclave publishes for the client to wrap its key to.
+#[derive(Clone, Copy)]
+pub struct ImportOffer {
+    pub session_id: [u8; SESSION_ID_LEN],
+    pub public_key: X25519PublicKey,
+    /// A report for the target given to the session, whose report data is
+    /// [`import_report_data`] of the session ID and public key.
+    pub report: sgx_report_t,
+}
+
+impl fmt::Debug for ImportOffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImportOffer")
+            .field("session_id", &self.session_id)
+            .field("public_key", &self.public_key.0)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Proof that the enclave unwrapped the key: an HMAC-SHA256 under the
+/// receipt key of the session, over the session ID and the SHA-256 digest of
+/// the key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Receipt(pub [u8; 32]);
+
+/// The result of an import.
+#[derive(Clone, Debug)]
+pub struct ImportedKey {
+    /// The key, sealed with the builder passed to [`KeyImportSession::import`].
+    pub sealed: Vec<u8>,
+    pub receipt: Receipt,
+}
+
+/// The report data binding `session_id` and `public_key` to a report or
+/// quote: their SHA-256 digest, with a context string, then zeroes.
+pub fn import_report_data(
+    session_id: &[u8; SESSION_ID_LEN],
+    public_key: &X25519PublicKey,
+) -> sgx_report_data_t {
+    let mut hasher = Sha256::new();
+    hasher.update(CONTEXT);
+    hasher.update(session_id);
+    hasher.update(&public_key.0);
+    let mut report_data = sgx_report_data_t::default();
+    hasher.finalize_into(&mut report_data.d[..32]);
+    report_data
+}
+
+/// The wrapping and receipt keys of a session.
+struct SessionKeys {
+    wrap: SecretBytes<32>,
+    receipt: SecretBytes<32>,
+}
+
+fn session_keys(
+    shared: &[u8; 32],
+    session_id: &[u8; SESSION_ID_LEN],
+    enclave: &X25519PublicKey,
+    client: &X25519PublicKey,
+) -> SgxResult<SessionKeys> {
+    let mut info = Vec::with_capacity(CONTEXT.len() + 64);
+    info.extend_from_slice(CONTEXT);
+    info.extend_from_slice(&enclave.0);
+    info.extend_from_slice(&client.0);
+    let mut okm = SecretBytes::<64>::zeroed();
+    hkdf_sha256(session_id, shared, &info, okm.expose_secret_mut())?;
+    let mut keys = SessionKeys {
+        wrap: SecretBytes::zeroed(),
+        receipt: SecretBytes::zeroed(),
+    };
+    keys.wrap
+        .expose_secret_mut()
+        .copy_from_slice(&okm.expose_secret()[..32]);
+    keys.receipt
+        .expose_secret_mut()
+        .copy_from_slice(&okm.expose_secret()[32..]);
+    Ok(keys)
+}
+
+fn receipt(keys: &SessionKeys, session_id: &[u8; SESSION_ID_LEN], key: &[u8]) -> Receipt {
+    let mut digest = [0_u8; 32];
+    Sha256::digest_into(key, &mut digest);
+    let mut mac = HmacSha256::new(keys.receipt.expose_secret());
+    mac.update(RECEIPT_CONTEXT);
+    mac.update(session_id);
+    mac.update(&digest);
+    Receipt(mac.finalize())
+}
+
+/// An import of a key into the enclave.
+pub struct KeyImportSession {
+    state: ImportState,
+    session_id: [u8; SESSION_ID_LEN],
+    private_key: Option<X25519PrivateKey>,
+    public_key: X25519PublicKey,
+}
+
+impl KeyImportSession {
+    /// Starts a session, with a report of its offer for `target_info`, e.g.
+    /// that of the quoting enclave.
+    pub fn new(target_info: &sgx_target_info_t) -> SgxResult<(KeyImportSession, ImportOffer)> {
+        let mut session_id = [0_u8; SESSION_ID_LEN];
+        fill_random(&mut session_id)?;
+        let private_key = X25519PrivateKey::generate()?;
+        let public_key = private_key.public_key();
+        let report =
+            rsgx_create_report(target_info, &import_report_data(&session_id, &public_key))?;
+        let session = KeyImportSession {
+            state: ImportState::Offered,
+            session_id,
+            private_key: Some(private_key),
+            public_key,
+        };
+        let offer = ImportOffer {
+            session_id,
+            public_key,
+            report,
+        };
+        Ok((session, offer))
+    }
+
+    pub fn state(&self) -> ImportState {
+        self.state
+    }
+
+    pub fn session_id(&self) -> &[u8; SESSION_ID_LEN] {
+        &self.session_id
+    }
+
+    /// Ends the session without importing, destroying the wrapping key.
+    pub fn abort(&mut self) {
+        if self.state == ImportState::Offered {
+            self.state = ImportState::Failed;
+        }
+        self.private_key = None;
+    }
+
+    /// Unwraps the key in `wrapped`, and seals it with `builder`.
+    ///
+    /// The session ends either way. Fails with `SGX_ERROR_INVALID_STATE` if
+    /// it already ended, with `SGX_ERROR_INVALID_PARAMETER` if `wrapped` is
+    /// malformed or for another session, and with `SGX_ERROR_MAC_MISMATCH`
+    /// if it doesn't authenticate.
+    pub fn import(
+        &mut self,
+        builder: &SealingBuilder<'_>,
+        wrapped: &[u8],
+    ) -> SgxResult<ImportedKey> {
+        self.import_with(builder, wrapped, |_| Ok(()))
+    }
+
+    /// Imports as [`import`] does, once `validate` accepted the unwrapped
+    /// key, e.g. checked that it's a valid scalar of its curve.
+    ///
+    /// [`import`]: KeyImportSession::import
+    pub fn import_with<F: FnOnce(&[u8]) -> SgxError>(
+        &mut self,
+        builder: &SealingBuilder<'_>,
+        wrapped: &[u8],
+        validate: F,
+    ) -> SgxResult<ImportedKey> {
+        if self.state != ImportState::Offered {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+        }
+        let private_key = self
+            .private_key
+            .take()
+            .ok_or(sgx_status_t::SGX_ERROR_INVALID_STATE)?;
+        self.state = ImportState::Failed;
+
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        if wrapped.len() <= HEADER_LEN + TAG_LEN
+            || wrapped[..4] != MAGIC
+            || wrapped[4] != IMPORT_VERSION
+            || wrapped[5..5 + SESSION_ID_LEN] != self.session_id
+        {
+            return Err(invalid);
+        }
+        let mut client = X25519PublicKey([0; 32]);
+        client.0.copy_from_slice(&wrapped[21..53]);
+        let shared = private_key.diffie_hellman(&client)?;
+        drop(private_key);
+        let keys = session_keys(
+            shared.as_bytes(),
+            &self.session_id,
+            &self.public_key,
+            &client,
+        )?;
+
+        let end = wrapped.len() - TAG_LEN;
+        let mut tag: Tag = [0; TAG_LEN];
+        tag.copy_from_slice(&wrapped[end..]);
+        let mut key = vec![0_u8; end - HEADER_LEN];
+        XChaCha20Poly1305::new(keys.wrap.expose_secret())?.decrypt(
+            &wrapped[53..HEADER_LEN],
+            &wrapped[..HEADER_LEN],
+            &wrapped[HEADER_LEN..end],
+            &tag,
+            &mut key,
+        )?;
+
+        let result = validate(&key)
+            .and_then(|_| builder.seal_slice(&key[..]))
+            .and_then(|sealed| sealed.internal().to_bytes())
+            .map(|sealed| ImportedKey {
+                sealed,
+                receipt: receipt(&keys, &self.session_id, &key),
+            });
+        key.zeroize();
+        if result.is_ok() {
+            self.state = ImportState::Imported;
+        }
+        result
+    }
+}
+
+impl fmt::Debug for KeyImportSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyImportSession")
+            .field("state", &self.state)
+            .field("session_id", &self.session_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What a client keeps to verify the receipt of its import.
+pub struct ReceiptCheck {
+    keys: SessionKeys,
+    session_id: [u8; SESSION_ID_LEN],
+    digest: [u8; 32],
+}
+
+impl ReceiptCheck {
+    /// Verifies `receipt` in constant time, failing with
+    /// `SGX_ERROR_MAC_MISMATCH` if the enclave didn't import the key.
+    pub fn verify(&self, receipt: &Receipt) -> SgxError {
+        let mut mac = HmacSha256::new(self.keys.receipt.expose_secret());
+        mac.update(RECEIPT_CONTEXT);
+        mac.update(&self.session_id);
+        mac.update(&self.digest);
+        mac.verify(&receipt.0)
+    }
+}
+
+/// Wraps `key` to the session of `offer`, for a client which verified the
+/// quote of the offer, and returns the wrapped key with what checks the
+/// receipt.
+///
+/// The caller must check that the report data of the quote is
+/// [`import_report_data`] of the offer first: the offer itself comes through
+/// the host.
+pub fn wrap_for_import(offer: &ImportOffer, key: &[u8]) -> SgxResult<(Vec<u8>, ReceiptCheck)> {
+    if key.is_empty() {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    let ephemeral = X25519PrivateKey::generate()?;
+    let client = ephemeral.public_key();
+    let shared = ephemeral.diffie_hellman(&offer.public_key)?;
+    let keys = session_keys(
+        shared.as_bytes(),
+        &offer.session_id,
+        &offer.public_key,
+        &client,
+    )?;
+
+    let mut wrapped = Vec::with_capacity(HEADER_LEN + key.len() + TAG_LEN);
+    wrapped.extend_from_slice(&MAGIC);
+    wrapped.push(IMPORT_VERSION);
+    wrapped.extend_from_slice(&offer.session_id);
+    wrapped.extend_from_slice(&client.0);
+    let mut nonce = [0_u8; NONCE_LEN];
+    fill_random(&mut nonce)?;
+    wrapped.extend_from_slice(&nonce);
+    wrapped.resize(HEADER_LEN + key.len(), 0);
+    let (header, ciphertext) = wrapped.split_at_mut(HEADER_LEN);
+    let tag = XChaCha20Poly1305::new(keys.wrap.expose_secret())?
+        .encrypt(&nonce, header, key, ciphertext)?;
+    wrapped.extend_from_slice(&tag);
+
+    let mut digest = [0_u8; 32];
+    Sha256::digest_into(key, &mut digest);
+    let check = ReceiptCheck {
+        keys,
+        session_id: offer.session_id,
+        digest,
+    };
+    Ok((wrapped, check))
+}
@@ -28,6 +28,51 @@ use sgx_types::*;
 const KEY_POLICY_KSS: uint16_t =
     SGX_KEYPOLICY_CONFIGID | SGX_KEYPOLICY_ISVFAMILYID | SGX_KEYPOLICY_ISVEXTPRODID;
 
+/// The key policy of `seal_data` and `mac_aadata`: the signer of the enclave,
+/// along with the KSS fields when the enclave has them.
+pub fn default_key_policy() -> u16 {
+    let report = rsgx_self_report();
+    if (report.body.attributes.flags & SGX_FLAGS_KSS) != 0 {
+        SGX_KEYPOLICY_MRSIGNER | KEY_POLICY_KSS
+    } else {
+        SGX_KEYPOLICY_MRSIGNER
+    }
+}
+
+/// The fields of the key request which the sealer chooses.
+#[derive(Clone, Copy)]
+pub struct SealRequest {
+    pub key_policy: u16,
+    pub attribute_mask: sgx_attributes_t,
+    pub misc_mask: sgx_misc_select_t,
+    /// The key ID of MACs over additional data, a random one being drawn for
+    /// each MAC when `None`. Sealed blobs always draw a random key ID: they
+    /// are encrypted with the fixed IV of the SDK, so a shared key would
+    /// repeat its nonce.
+    pub mac_key_id: Option<sgx_key_id_t>,
+}
+
+impl SealRequest {
+    /// Returns whether `key_request` was made with these fields.
+    pub fn matches(&self, key_request: &sgx_key_request_t) -> bool {
+        key_request.key_name == SGX_KEYSELECT_SEAL
+            && key_request.key_policy == self.key_policy
+            && key_request.attribute_mask.flags == self.attribute_mask.flags
+            && key_request.attribute_mask.xfrm == self.attribute_mask.xfrm
+            && key_request.misc_mask == self.misc_mask
+    }
+
+    /// Returns whether the MAC of `key_request` was made with these fields,
+    /// including the key ID if there is one.
+    pub fn matches_mac(&self, key_request: &sgx_key_request_t) -> bool {
+        self.matches(key_request)
+            && match self.mac_key_id {
+                Some(key_id) => key_id.id == key_request.key_id.id,
+                None => true,
+            }
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct SgxInternalUnsealedData {
     pub payload_size: u32,
@@ -263,6 +308,43 @@ impl SgxInternalSealedData {
         })
     }
 
+    /// Returns the `sgx_sealed_data_t` encoding of the blob, copied through a
+    /// buffer aligned for the structure.
+    pub fn to_bytes(&self) -> SgxResult<Vec<u8>> {
+        let len =
+            Self::calc_raw_sealed_data_size(self.get_add_mac_txt_len(), self.get_encrypt_txt_len());
+        if len == u32::MAX {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut raw = vec![0_u64; len as usize / 8 + 1];
+        unsafe { self.to_raw_sealed_data_t(raw.as_mut_ptr() as *mut sgx_sealed_data_t, len) }
+            .ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)?;
+        let mut bytes = vec![0_u8; len as usize];
+        unsafe {
+            ptr::copy_nonoverlapping(raw.as_ptr() as *const u8, bytes.as_mut_ptr(), bytes.len())
+        };
+        Ok(bytes)
+    }
+
+    /// Parses the `sgx_sealed_data_t` encoding of a blob, failing with
+    /// `SGX_ERROR_INVALID_PARAMETER` if it's malformed.
+    pub fn from_bytes(bytes: &[u8]) -> SgxResult<Self> {
+        if bytes.len() < mem::size_of::<sgx_sealed_data_t>() || bytes.len() >= u32::MAX as usize {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut raw = vec![0_u64; bytes.len() / 8 + 1];
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), raw.as_mut_ptr() as *mut u8, bytes.len())
+        };
+        unsafe {
+            Self::from_raw_sealed_data_t(
+                raw.as_mut_ptr() as *mut sgx_sealed_data_t,
+                bytes.len() as u32,
+            )
+        }
+        .ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+    }
+
     pub fn seal_data(additional_text: &[u8], encrypt_text: &[u8]) -> SgxResult<Self> {
         //let attribute_mask = sgx_attributes_t{flags: SGX_FLAGS_RESERVED | SGX_FLAGS_INITTED | SGX_FLAGS_DEBUG, xfrm: 0};
         /* intel sgx sdk 1.8 */
@@ -271,11 +353,7 @@ impl SgxInternalSealedData {
             xfrm: 0,
         };
         /* intel sgx sdk 2.4 */
-        let mut key_policy = SGX_KEYPOLICY_MRSIGNER;
-        let report = rsgx_self_report();
-        if (report.body.attributes.flags & SGX_FLAGS_KSS) != 0 {
-            key_policy = SGX_KEYPOLICY_MRSIGNER | KEY_POLICY_KSS;
-        }
+        let key_policy = default_key_policy();
 
         Self::seal_data_ex(
             key_policy,
@@ -293,6 +371,26 @@ impl SgxInternalSealedData {
         additional_text: &[u8],
         encrypt_text: &[u8],
     ) -> SgxResult<Self> {
+        let request = SealRequest {
+            key_policy,
+            attribute_mask,
+            misc_mask,
+            mac_key_id: None,
+        };
+        Self::seal_data_with(&request, additional_text, encrypt_text)
+    }
+
+    pub fn seal_data_with(
+        request: &SealRequest,
+        additional_text: &[u8],
+        encrypt_text: &[u8],
+    ) -> SgxResult<Self> {
+        let SealRequest {
+            key_policy,
+            attribute_mask,
+            misc_mask,
+            ..
+        } = *request;
         let additional_len = additional_text.len();
         let encrypt_len = encrypt_text.len();
 
@@ -408,12 +506,7 @@ impl SgxInternalSealedData {
             flags: TSEAL_DEFAULT_FLAGSMASK,
             xfrm: 0,
         };
-        let mut key_policy: u16 = SGX_KEYPOLICY_MRSIGNER;
-        let report = rsgx_self_report();
-
-        if (report.body.attributes.flags & SGX_FLAGS_KSS) != 0 {
-            key_policy = SGX_KEYPOLICY_MRSIGNER | KEY_POLICY_KSS;
-        }
+        let key_policy = default_key_policy();
 
         Self::mac_aadata_ex(
             key_policy,
@@ -429,6 +522,22 @@ impl SgxInternalSealedData {
         misc_mask: sgx_misc_select_t,
         additional_text: &[u8],
     ) -> SgxResult<Self> {
+        let request = SealRequest {
+            key_policy,
+            attribute_mask,
+            misc_mask,
+            mac_key_id: None,
+        };
+        Self::mac_aadata_with(&request, additional_text)
+    }
+
+    pub fn mac_aadata_with(request: &SealRequest, additional_text: &[u8]) -> SgxResult<Self> {
+        let SealRequest {
+            key_policy,
+            attribute_mask,
+            misc_mask,
+            ..
+        } = *request;
         let additional_len = additional_text.len();
         if additional_len >= u32::MAX as usize {
             return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
@@ -469,11 +578,16 @@ impl SgxInternalSealedData {
         /* intel sgx sdk 2.4 */
         let mut report = rsgx_self_report();
 
-        let error = rsgx_read_rand(&mut key_id.id);
-        if let Err(e) = error {
-            report = sgx_report_t::default();
-            key_id = sgx_key_id_t::default();
-            return Err(e);
+        match request.mac_key_id {
+            Some(fixed) => key_id = fixed,
+            None => {
+                let error = rsgx_read_rand(&mut key_id.id);
+                if let Err(e) = error {
+                    report = sgx_report_t::default();
+                    key_id = sgx_key_id_t::default();
+                    return Err(e);
+                }
+            }
         }
 
         let key_request = sgx_key_request_t {
@@ -0,0 +1,519 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Generations of a symmetric key, rotated on a schedule.
+//!
+//! A [`KeyRing`] holds the generations of a data key. One generation is
+//! active and encrypts; the generations it replaced stay decrypt-only for an
+//! overlap window, so that data encrypted under them can still be read while
+//! it's re-encrypted, and are then retired: their key is zeroized and what is
+//! still encrypted under it can't be read anymore. Rotating thus needs no
+//! downtime.
+//!
+//! [`tick`] runs the schedule of the [`RotationPolicy`] by a trusted
+//! [`Clock`]: it rotates the active generation once it's old enough, calls
+//! the re-encryption hooks for each decrypt-only generation, and retires the
+//! generations whose window is over, once the hooks re-encrypted their data.
+//! Call it periodically, e.g. from an ecall the host makes on a timer; the
+//! host can delay rotations by not calling it, but not undo them.
+//!
+//! A ciphertext starts with the generation of its key, little endian, and a
+//! 24-byte nonce, and ends with a 16-byte tag; the cipher is
+//! XChaCha20-Poly1305, authenticating the generation and the additional data
+//! of the caller.
+//!
+//! The ring is persisted with [`seal`], which seals its keys and schedule.
+//! A host restoring an older sealed ring brings back retired keys; keep the
+//! [`digest`] recorded in rollback-protected state, such as a
+//! [`VersionedSealedState`](crate::state::VersionedSealedState), when that
+//! matters.
+//!
+//! [`tick`]: KeyRing::tick
+//! [`seal`]: KeyRing::seal
+//! [`digest`]: KeyRing::digest
+
+use crate::builder::SealingBuilder;
+use crate::internal::SgxInternalSealedData;
+use crate::seal::SgxSealedData;
+use crate::time::Clock;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem;
+use sgx_tcrypto::aead::{Aead, Tag, XChaCha20Poly1305, TAG_LEN};
+use sgx_tcrypto::hash::{Hash, Sha256};
+use sgx_tcrypto::rng::fill_random;
+use sgx_tcrypto::secret::{SecretBytes, Zeroize};
+use sgx_types::*;
+
+const MAGIC: [u8; 4] = *b"SKRG";
+const RING_VERSION: u8 = 1;
+const NONCE_LEN: usize = 24;
+const OVERHEAD: usize = 4 + NONCE_LEN + TAG_LEN;
+const RECORD_LEN: usize = 4 + 1 + 1 + 8 + 8 + 32;
+
+/// What a generation of the key is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KeyState {
+    /// Encrypts and decrypts.
+    Active = 1,
+    /// Only decrypts, until its overlap window is over.
+    DecryptOnly = 2,
+    /// Zeroized.
+    Retired = 3,
+}
+
+impl KeyState {
+    fn from_u8(v: u8) -> Option<KeyState> {
+        match v {
+            1 => Some(KeyState::Active),
+            2 => Some(KeyState::DecryptOnly),
+            3 => Some(KeyState::Retired),
+            _ => None,
+        }
+    }
+}
+
+/// When generations rotate and retire, in seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// How long a generation is active.
+    pub rotate_after: u64,
+    /// How long a replaced generation still decrypts.
+    pub overlap: u64,
+}
+
+/// The public fields of a generation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Generation {
+    pub id: u32,
+    pub state: KeyState,
+    /// When it became active.
+    pub created: u64,
+    /// When it was replaced, if it was.
+    pub replaced: Option<u64>,
+    /// Whether the re-encryption hooks are done with it.
+    pub reencrypted: bool,
+}
+
+struct Key {
+    info: Generation,
+    key: SecretBytes<32>,
+}
+
+/// What [`KeyRing::tick`] did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tick {
+    /// The generation made active, if the ring rotated.
+    pub rotated: Option<u32>,
+    /// The generations the hooks re-encrypted the data of.
+    pub reencrypted: Vec<u32>,
+    /// The generations retired.
+    pub retired: Vec<u32>,
+}
+
+/// A re-encryption hook, moving the data of a decrypt-only generation to the
+/// active one.
+pub type ReencryptHook = Box<dyn FnMut(&Rewrap<'_>) -> SgxError>;
+
+/// Re-encrypts data of a decrypt-only generation, for the hooks.
+pub struct Rewrap<'r> {
+    keys: &'r [Key],
+    generation: u32,
+}
+
+impl Rewrap<'_> {
+    /// The generation whose data is being re-encrypted.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Returns whether `ciphertext` is encrypted under that generation.
+    pub fn is_under(&self, ciphertext: &[u8]) -> bool {
+        generation_of(ciphertext) == Some(self.generation)
+    }
+
+    /// Decrypts `ciphertext`, under any generation which decrypts, and
+    /// encrypts it again under the active one.
+    pub fn rewrap(&self, aad: &[u8], ciphertext: &[u8]) -> SgxResult<Vec<u8>> {
+        rewrap(self.keys, aad, ciphertext)
+    }
+}
+
+/// The generations of a symmetric key, and their schedule.
+///
+/// ```ignore
+/// let policy = RotationPolicy { rotate_after: 30 * DAY, overlap: 7 * DAY };
+/// let mut ring = KeyRing::new(clock, policy)?;
+/// ring.on_reencrypt(Box::new(move |rewrap| store.rewrap_all(rewrap)));
+/// let ciphertext = ring.encrypt(b"record 7", &record)?;
+/// ring.tick()?;
+/// let record = ring.decrypt(b"record 7", &ciphertext)?;
+/// ```
+pub struct KeyRing<K: Clock> {
+    clock: K,
+    policy: RotationPolicy,
+    keys: Vec<Key>,
+    hooks: Vec<ReencryptHook>,
+}
+
+impl<K: Clock> KeyRing<K> {
+    /// Starts a ring with a new active generation, 1.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if `rotate_after` is zero.
+    pub fn new(clock: K, policy: RotationPolicy) -> SgxResult<KeyRing<K>> {
+        if policy.rotate_after == 0 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let now = clock.now()?;
+        let mut ring = KeyRing {
+            clock,
+            policy,
+            keys: Vec::new(),
+            hooks: Vec::new(),
+        };
+        ring.keys.push(new_key(1, now)?);
+        Ok(ring)
+    }
+
+    pub fn policy(&self) -> RotationPolicy {
+        self.policy
+    }
+
+    /// Changes the schedule; it applies from the next [`tick`].
+    ///
+    /// [`tick`]: KeyRing::tick
+    pub fn set_policy(&mut self, policy: RotationPolicy) -> SgxError {
+        if policy.rotate_after == 0 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        self.policy = policy;
+        Ok(())
+    }
+
+    /// Adds a hook re-encrypting the data of each generation replaced.
+    ///
+    /// A hook is called by [`tick`] for each decrypt-only generation until
+    /// all the hooks succeeded for it, and the generation only retires once
+    /// they did; a hook should thus re-encrypt whatever it can and fail if it
+    /// couldn't, e.g. because the storage was unavailable.
+    ///
+    /// [`tick`]: KeyRing::tick
+    pub fn on_reencrypt(&mut self, hook: ReencryptHook) {
+        self.hooks.push(hook);
+    }
+
+    /// Returns the active generation.
+    pub fn active(&self) -> Generation {
+        self.keys[self.keys.len() - 1].info
+    }
+
+    /// Returns the generations, oldest first.
+    pub fn generations(&self) -> impl Iterator<Item = Generation> + '_ {
+        self.keys.iter().map(|key| key.info)
+    }
+
+    /// Encrypts `plaintext` under the active generation.
+    pub fn encrypt(&self, aad: &[u8], plaintext: &[u8]) -> SgxResult<Vec<u8>> {
+        encrypt(&self.keys[self.keys.len() - 1], aad, plaintext)
+    }
+
+    /// Decrypts `ciphertext` under the generation it names.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if the ring has no such
+    /// generation or the ciphertext is too short, with
+    /// `SGX_ERROR_INVALID_STATE` if the generation is retired, and with
+    /// `SGX_ERROR_MAC_MISMATCH` if it doesn't authenticate.
+    pub fn decrypt(&self, aad: &[u8], ciphertext: &[u8]) -> SgxResult<Vec<u8>> {
+        decrypt(&self.keys, aad, ciphertext)
+    }
+
+    /// Decrypts `ciphertext` and encrypts it again under the active
+    /// generation.
+    pub fn rewrap(&self, aad: &[u8], ciphertext: &[u8]) -> SgxResult<Vec<u8>> {
+        rewrap(&self.keys, aad, ciphertext)
+    }
+
+    /// Rotates now, whatever the schedule, and returns the new active
+    /// generation; e.g. when the active key may have leaked.
+    pub fn rotate(&mut self) -> SgxResult<u32> {
+        let now = self.clock.now()?;
+        self.rotate_at(now)
+    }
+
+    fn rotate_at(&mut self, now: u64) -> SgxResult<u32> {
+        let id = self
+            .active()
+            .id
+            .checked_add(1)
+            .ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)?;
+        let key = new_key(id, now)?;
+        let active = self.keys.len() - 1;
+        self.keys[active].info.state = KeyState::DecryptOnly;
+        self.keys[active].info.replaced = Some(now);
+        self.keys.push(key);
+        Ok(id)
+    }
+
+    /// Runs the schedule at the time of the clock: rotates, re-encrypts and
+    /// retires what's due.
+    ///
+    /// A hook failing leaves its generation decrypt-only, to be tried again
+    /// by the next tick, and the error is returned after the other
+    /// generations were handled.
+    pub fn tick(&mut self) -> SgxResult<Tick> {
+        let now = self.clock.now()?;
+        let mut tick = Tick::default();
+        let active = self.active();
+        if now.saturating_sub(active.created) >= self.policy.rotate_after {
+            tick.rotated = Some(self.rotate_at(now)?);
+        }
+
+        let mut hooks = mem::take(&mut self.hooks);
+        let mut result = Ok(());
+        for i in 0..self.keys.len() {
+            let info = self.keys[i].info;
+            if info.state != KeyState::DecryptOnly || info.reencrypted {
+                continue;
+            }
+            let rewrap = Rewrap {
+                keys: &self.keys,
+                generation: info.id,
+            };
+            match hooks.iter_mut().try_for_each(|hook| hook(&rewrap)) {
+                Ok(()) => {
+                    self.keys[i].info.reencrypted = true;
+                    tick.reencrypted.push(info.id);
+                }
+                Err(e) => result = Err(e),
+            }
+        }
+        self.hooks = hooks;
+
+        for key in self.keys.iter_mut() {
+            let info = &mut key.info;
+            let overlap = self.policy.overlap;
+            let over = matches!(info.replaced, Some(at) if now.saturating_sub(at) >= overlap);
+            if info.state == KeyState::DecryptOnly && info.reencrypted && over {
+                info.state = KeyState::Retired;
+                key.key.expose_secret_mut().zeroize();
+                tick.retired.push(info.id);
+            }
+        }
+        result.map(|_| tick)
+    }
+
+    /// Drops the records of retired generations.
+    pub fn prune(&mut self) {
+        self.keys.retain(|key| key.info.state != KeyState::Retired);
+    }
+
+    /// The SHA-256 digest of the serialized ring, keys included, which
+    /// changes with every rotation and retirement.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut bytes = self.to_bytes();
+        let mut digest = [0_u8; 32];
+        Sha256::digest_into(&bytes, &mut digest);
+        bytes.zeroize();
+        digest
+    }
+
+    /// Seals the generations and their schedule with `builder`.
+    pub fn seal(&self, builder: &SealingBuilder<'_>) -> SgxResult<Vec<u8>> {
+        let mut bytes = self.to_bytes();
+        let sealed = builder.seal_slice(&bytes[..]);
+        let result = sealed.and_then(|sealed| sealed.internal().to_bytes());
+        bytes.zeroize();
+        result
+    }
+
+    /// Unseals a ring sealed by [`seal`], to be run by `clock`; the hooks
+    /// have to be added again.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if the ring is malformed,
+    /// and otherwise as [`SealingBuilder::unseal`] does.
+    ///
+    /// [`seal`]: KeyRing::seal
+    pub fn unseal(builder: &SealingBuilder<'_>, clock: K, sealed: &[u8]) -> SgxResult<KeyRing<K>> {
+        let inner = SgxInternalSealedData::from_bytes(sealed)?;
+        let sealed = SgxSealedData::<[u8]>::from_internal(inner);
+        let mut unsealed = builder.unseal_slice(&sealed)?;
+        let result = Self::from_bytes(clock, &unsealed.decrypt);
+        unsealed.decrypt.zeroize();
+        result
+    }
+
+    /// The ring as `SKRG`, version, the policy, the number of generations
+    /// and their records; the caller zeroizes it.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(25 + self.keys.len() * RECORD_LEN);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(RING_VERSION);
+        bytes.extend_from_slice(&self.policy.rotate_after.to_le_bytes());
+        bytes.extend_from_slice(&self.policy.overlap.to_le_bytes());
+        bytes.extend_from_slice(&(self.keys.len() as u32).to_le_bytes());
+        for key in &self.keys {
+            let info = &key.info;
+            bytes.extend_from_slice(&info.id.to_le_bytes());
+            bytes.push(info.state as u8);
+            bytes.push(info.reencrypted as u8);
+            bytes.extend_from_slice(&info.created.to_le_bytes());
+            bytes.extend_from_slice(&info.replaced.unwrap_or(u64::MAX).to_le_bytes());
+            bytes.extend_from_slice(key.key.expose_secret());
+        }
+        bytes
+    }
+
+    fn from_bytes(clock: K, bytes: &[u8]) -> SgxResult<KeyRing<K>> {
+        let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        if bytes.len() < 25 || bytes[..4] != MAGIC || bytes[4] != RING_VERSION {
+            return Err(invalid);
+        }
+        let policy = RotationPolicy {
+            rotate_after: read_u64(&bytes[5..]),
+            overlap: read_u64(&bytes[13..]),
+        };
+        let count = read_u32(&bytes[21..]) as usize;
+        if policy.rotate_after == 0 || count == 0 || bytes.len() != 25 + count * RECORD_LEN {
+            return Err(invalid);
+        }
+
+        let mut keys = Vec::with_capacity(count);
+        for record in bytes[25..].chunks(RECORD_LEN) {
+            let replaced = read_u64(&record[14..]);
+            let info = Generation {
+                id: read_u32(record),
+                state: KeyState::from_u8(record[4]).ok_or(invalid)?,
+                reencrypted: record[5] != 0,
+                created: read_u64(&record[6..]),
+                replaced: if replaced == u64::MAX {
+                    None
+                } else {
+                    Some(replaced)
+                },
+            };
+            let mut key = SecretBytes::<32>::zeroed();
+            key.expose_secret_mut().copy_from_slice(&record[22..]);
+            keys.push(Key { info, key });
+        }
+        // Only the last generation is active, and ids increase.
+        let ordered = keys.windows(2).all(|w| w[0].info.id < w[1].info.id);
+        let active = keys
+            .iter()
+            .enumerate()
+            .all(|(i, key)| (key.info.state == KeyState::Active) == (i == count - 1));
+        if !ordered || !active {
+            return Err(invalid);
+        }
+        Ok(KeyRing {
+            clock,
+            policy,
+            keys,
+            hooks: Vec::new(),
+        })
+    }
+}
+
+fn new_key(id: u32, now: u64) -> SgxResult<Key> {
+    let mut key = SecretBytes::<32>::zeroed();
+    fill_random(key.expose_secret_mut())?;
+    Ok(Key {
+        info: Generation {
+            id,
+            state: KeyState::Active,
+            created: now,
+            replaced: None,
+            reencrypted: false,
+        },
+        key,
+    })
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut v = [0_u8; 4];
+    v.copy_from_slice(&bytes[..4]);
+    u32::from_le_bytes(v)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut v = [0_u8; 8];
+    v.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(v)
+}
+
+fn generation_of(ciphertext: &[u8]) -> Option<u32> {
+    if ciphertext.len() < OVERHEAD {
+        return None;
+    }
+    Some(read_u32(ciphertext))
+}
+
+/// The additional data of a ciphertext: its generation, then that of the
+/// caller.
+fn full_aad(id: u32, aad: &[u8]) -> Vec<u8> {
+    let mut full = Vec::with_capacity(4 + aad.len());
+    full.extend_from_slice(&id.to_le_bytes());
+    full.extend_from_slice(aad);
+    full
+}
+
+fn encrypt(key: &Key, aad: &[u8], plaintext: &[u8]) -> SgxResult<Vec<u8>> {
+    let mut nonce = [0_u8; NONCE_LEN];
+    fill_random(&mut nonce)?;
+    let mut ciphertext = vec![0_u8; OVERHEAD + plaintext.len()];
+    ciphertext[..4].copy_from_slice(&key.info.id.to_le_bytes());
+    ciphertext[4..4 + NONCE_LEN].copy_from_slice(&nonce);
+    let end = 4 + NONCE_LEN + plaintext.len();
+    let tag = XChaCha20Poly1305::new(key.key.expose_secret())?.encrypt(
+        &nonce,
+        &full_aad(key.info.id, aad),
+        plaintext,
+        &mut ciphertext[4 + NONCE_LEN..end],
+    )?;
+    ciphertext[end..].copy_from_slice(&tag);
+    Ok(ciphertext)
+}
+
+fn decrypt(keys: &[Key], aad: &[u8], ciphertext: &[u8]) -> SgxResult<Vec<u8>> {
+    let id = generation_of(ciphertext).ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+    let key = keys
+        .iter()
+        .find(|key| key.info.id == id)
+        .ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+    if key.info.state == KeyState::Retired {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_STATE);
+    }
+    let end = ciphertext.len() - TAG_LEN;
+    let mut tag: Tag = [0; TAG_LEN];
+    tag.copy_from_slice(&ciphertext[end..]);
+    let mut plaintext = vec![0_u8; end - 4 - NONCE_LEN];
+    XChaCha20Poly1305::new(key.key.expose_secret())?.decrypt(
+        &ciphertext[4..4 + NONCE_LEN],
+        &full_aad(id, aad),
+        &ciphertext[4 + NONCE_LEN..end],
+        &tag,
+        &mut plaintext,
+    )?;
+    Ok(plaintext)
+}
+
+fn rewrap(keys: &[Key], aad: &[u8], ciphertext: &[u8]) -> SgxResult<Vec<u8>> {
+    let mut plaintext = decrypt(keys, aad, ciphertext)?;
+    let result = encrypt(&keys[keys.len() - 1], aad, &plaintext);
+    plaintext.zeroize();
+    result
+}
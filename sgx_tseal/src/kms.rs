@@ -0,0 +1,550 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Data keys wrapped by an external key management service.
+//!
+//! Deployments whose keys must be rooted in an HSM keep the key encryption
+//! key in a KMS, and the enclave asks the KMS to wrap and unwrap its data
+//! keys. A [`KmsClient`] is the connection to one, and a [`KmsKeyWrap`] plugs
+//! it into [`envelope`](crate::envelope), keeping unwrapped keys in enclave
+//! memory for a while so that opening many envelopes of the same data key
+//! takes a single round trip.
+//!
+//! A cached key expires after its TTL, measured with a trusted [`Clock`],
+//! and is then unwrapped by the KMS again, so that the KMS can revoke the
+//! access of the enclave within a TTL. Expired keys are zeroized.
+//!
+//! With the `ra_tls` feature, [`RaTlsKms`] is a client for a KMS reached
+//! over attested TLS: the enclave presents an RA-TLS certificate, which the
+//! KMS checks before releasing keys, and authenticates the KMS either as an
+//! attested enclave itself or by the digest of its certificate. It speaks a
+//! small JSON protocol over HTTP/1.1, with binary fields in padded base64:
+//!
+//! | request                       | body                 | response                  |
+//! |-------------------------------|----------------------|---------------------------|
+//! | `POST /v1/keys/{id}/wrap`     | `{"plaintext": ..}`  | `{"ciphertext": ..}`      |
+//! | `POST /v1/keys/{id}/unwrap`   | `{"ciphertext": ..}` | `{"plaintext": ..}`       |
+//! | `POST /v1/keys/{id}/generate` | `{"length": n}`      | both `plaintext` and      |
+//! |                               |                      | `ciphertext`              |
+//!
+//! and its statuses map to:
+//!
+//! | status     | error                             |
+//! |------------|-----------------------------------|
+//! | 200        | success                           |
+//! | 400, 422   | `SGX_ERROR_MAC_MISMATCH`          |
+//! | 401, 403   | `SGX_ERROR_NO_PRIVILEGE`          |
+//! | 404        | `SGX_ERROR_INVALID_PARAMETER`     |
+//! | 429, 503   | `SGX_ERROR_SERVICE_UNAVAILABLE`   |
+//! | others     | `SGX_ERROR_UNEXPECTED`            |
+
+use crate::envelope::{KeyWrap, KeyWrapKind};
+use crate::time::Clock;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::ptr;
+use sgx_tcrypto::rng::fill_random;
+use sgx_tcrypto::secret::SecretBytes;
+use sgx_types::*;
+
+/// The longest data key which is cached, in bytes.
+pub const MAX_CACHED_KEY_LEN: usize = 32;
+
+/// A connection to a key management service.
+pub trait KmsClient {
+    /// Wraps `key` under the key encryption key named `key_id`.
+    fn wrap_key(&mut self, key_id: &[u8], key: &[u8]) -> SgxResult<Vec<u8>>;
+
+    /// Unwraps `wrapped` with the key encryption key named `key_id` into
+    /// `key`, which has the length of the data key.
+    fn unwrap_key(&mut self, key_id: &[u8], wrapped: &[u8], key: &mut [u8]) -> SgxError;
+
+    /// Fills `key` with a new data key and returns it wrapped under `key_id`.
+    ///
+    /// By default the enclave draws the key and has the KMS wrap it; a KMS
+    /// which generates keys in its HSM can do so instead.
+    fn generate_data_key(&mut self, key_id: &[u8], key: &mut [u8]) -> SgxResult<Vec<u8>> {
+        fill_random(key)?;
+        self.wrap_key(key_id, key)
+    }
+}
+
+impl<C: KmsClient + ?Sized> KmsClient for &mut C {
+    fn wrap_key(&mut self, key_id: &[u8], key: &[u8]) -> SgxResult<Vec<u8>> {
+        (**self).wrap_key(key_id, key)
+    }
+
+    fn unwrap_key(&mut self, key_id: &[u8], wrapped: &[u8], key: &mut [u8]) -> SgxError {
+        (**self).unwrap_key(key_id, wrapped, key)
+    }
+
+    fn generate_data_key(&mut self, key_id: &[u8], key: &mut [u8]) -> SgxResult<Vec<u8>> {
+        (**self).generate_data_key(key_id, key)
+    }
+}
+
+struct CachedKey {
+    wrapped: Vec<u8>,
+    key: SecretBytes<MAX_CACHED_KEY_LEN>,
+    len: usize,
+    expires: u64,
+}
+
+/// Wraps the data keys of envelopes with a KMS key, caching unwrapped keys.
+///
+/// ```ignore
+/// let wrap = KmsKeyWrap::new(kms, clock, b"tenants/acme")
+///     .with_ttl(600)
+///     .with_capacity(128);
+/// let sealed = envelope::seal(&wrap, EnvelopeCipher::XChaCha20Poly1305, aad, data)?;
+/// ```
+pub struct KmsKeyWrap<C: KmsClient, K: Clock> {
+    client: RefCell<C>,
+    clock: K,
+    key_id: Vec<u8>,
+    ttl: u64,
+    capacity: usize,
+    cache: RefCell<Vec<CachedKey>>,
+}
+
+impl<C: KmsClient, K: Clock> KmsKeyWrap<C, K> {
+    /// Wraps under the KMS key named `key_id`, caching up to 64 keys for five
+    /// minutes.
+    pub fn new(client: C, clock: K, key_id: &[u8]) -> KmsKeyWrap<C, K> {
+        KmsKeyWrap {
+            client: RefCell::new(client),
+            clock,
+            key_id: key_id.to_vec(),
+            ttl: 300,
+            capacity: 64,
+            cache: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Sets how long, in seconds, a key is cached after the KMS returned it.
+    /// Zero disables the cache.
+    pub fn with_ttl(mut self, ttl: u64) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the number of keys cached, evicting the oldest beyond it.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Draws a new data key into `key` through the KMS, caching it, and
+    /// returns it wrapped.
+    pub fn generate_data_key(&self, key: &mut [u8]) -> SgxResult<Vec<u8>> {
+        let now = self.clock.now()?;
+        let wrapped = self
+            .client
+            .borrow_mut()
+            .generate_data_key(&self.key_id, key)?;
+        self.insert(now, &wrapped, key);
+        Ok(wrapped)
+    }
+
+    /// Returns the number of keys cached and not expired.
+    pub fn cached(&self) -> SgxResult<usize> {
+        let now = self.clock.now()?;
+        self.evict_expired(now);
+        Ok(self.cache.borrow().len())
+    }
+
+    /// Zeroizes and forgets the cached keys, e.g. once the KMS revoked the
+    /// key encryption key.
+    pub fn flush(&self) {
+        self.cache.borrow_mut().clear();
+    }
+
+    pub fn into_client(self) -> C {
+        self.client.into_inner()
+    }
+
+    fn evict_expired(&self, now: u64) {
+        self.cache.borrow_mut().retain(|entry| entry.expires > now);
+    }
+
+    fn insert(&self, now: u64, wrapped: &[u8], key: &[u8]) {
+        if self.ttl == 0 || self.capacity == 0 || key.len() > MAX_CACHED_KEY_LEN {
+            return;
+        }
+        let mut cache = self.cache.borrow_mut();
+        cache.retain(|entry| entry.expires > now && entry.wrapped != wrapped);
+        if cache.len() >= self.capacity {
+            let excess = cache.len() + 1 - self.capacity;
+            cache.drain(..excess);
+        }
+        let mut entry = CachedKey {
+            wrapped: wrapped.to_vec(),
+            key: SecretBytes::zeroed(),
+            len: key.len(),
+            expires: now.saturating_add(self.ttl),
+        };
+        entry.key.expose_secret_mut()[..key.len()].copy_from_slice(key);
+        cache.push(entry);
+    }
+}
+
+impl<C: KmsClient, K: Clock> KeyWrap for KmsKeyWrap<C, K> {
+    fn kind(&self) -> KeyWrapKind {
+        KeyWrapKind::Kms
+    }
+
+    fn key_id(&self) -> &[u8] {
+        &self.key_id
+    }
+
+    fn wrap(&self, key: &[u8]) -> SgxResult<Vec<u8>> {
+        let now = self.clock.now()?;
+        let wrapped = self.client.borrow_mut().wrap_key(&self.key_id, key)?;
+        self.insert(now, &wrapped, key);
+        Ok(wrapped)
+    }
+
+    fn unwrap(&self, wrapped: &[u8], key: &mut [u8]) -> SgxError {
+        let now = self.clock.now()?;
+        self.evict_expired(now);
+        if let Some(entry) = self
+            .cache
+            .borrow()
+            .iter()
+            .find(|entry| entry.wrapped == wrapped && entry.len == key.len())
+        {
+            key.copy_from_slice(&entry.key.expose_secret()[..entry.len]);
+            return Ok(());
+        }
+
+        self.client
+            .borrow_mut()
+            .unwrap_key(&self.key_id, wrapped, key)?;
+        self.insert(now, wrapped, key);
+        Ok(())
+    }
+}
+
+fn wipe(buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+        unsafe { ptr::write_volatile(b, 0) };
+    }
+}
+
+#[cfg(feature = "ra_tls")]
+pub use self::ra_tls::{RaTlsKms, ServerAuth};
+
+#[cfg(feature = "ra_tls")]
+mod ra_tls {
+    use super::{wipe, KmsClient};
+    use crate::time::Clock;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use core::mem;
+    use sgx_tcrypto::hash::{Hash, Sha256};
+    use sgx_ttls::http::{self, Limits, Request, Transport};
+    use sgx_ttls::json::Value;
+    use sgx_ttls::ra_tls::{RaTlsConnector, RaTlsIdentity};
+    use sgx_types::*;
+
+    /// How the enclave authenticates the KMS.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ServerAuth {
+        /// The KMS runs in an enclave, and presents an RA-TLS certificate
+        /// satisfying the server policy of the connector.
+        Attested,
+        /// The KMS presents the certificate with this SHA-256 digest.
+        Pinned([u8; 32]),
+    }
+
+    /// A [`KmsClient`] for a KMS reached over attested TLS.
+    ///
+    /// The TLS stack is the application's: `connect` opens a session to the
+    /// KMS presenting the RA-TLS identity it's given as the client
+    /// certificate, and returns the session with the certificate of the
+    /// server, which is checked before any request is sent. Sessions are
+    /// kept alive across requests, and reopened once the KMS closes them or
+    /// a request fails.
+    pub struct RaTlsKms<T, F, K>
+    where
+        T: Transport,
+        F: FnMut(&RaTlsIdentity) -> SgxResult<(T, Vec<u8>)>,
+        K: Clock,
+    {
+        connector: RaTlsConnector,
+        server: ServerAuth,
+        connect: F,
+        clock: K,
+        host: String,
+        limits: Limits,
+        session: Option<T>,
+    }
+
+    impl<T, F, K> RaTlsKms<T, F, K>
+    where
+        T: Transport,
+        F: FnMut(&RaTlsIdentity) -> SgxResult<(T, Vec<u8>)>,
+        K: Clock,
+    {
+        /// A client of the KMS at `host`, authenticating it as an attested
+        /// enclave with the policy of `connector`, at the time of `clock`.
+        ///
+        /// Fails with `SGX_ERROR_INVALID_PARAMETER` if the connector has no
+        /// identity of its own: the KMS must be able to attest the enclave.
+        pub fn new(
+            connector: RaTlsConnector,
+            host: &str,
+            clock: K,
+            connect: F,
+        ) -> SgxResult<RaTlsKms<T, F, K>> {
+            if connector.identity().is_none() {
+                return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+            }
+            Ok(RaTlsKms {
+                connector,
+                server: ServerAuth::Attested,
+                connect,
+                clock,
+                host: host.to_string(),
+                limits: Limits {
+                    max_body_bytes: 64 * 1024,
+                    ..Limits::default()
+                },
+                session: None,
+            })
+        }
+
+        /// Authenticates the KMS by the digest of its certificate instead,
+        /// for a KMS outside an enclave, such as a service fronting an HSM.
+        pub fn pin_server(mut self, certificate_sha256: [u8; 32]) -> Self {
+            self.server = ServerAuth::Pinned(certificate_sha256);
+            self.session = None;
+            self
+        }
+
+        /// Sets the bounds on the responses of the KMS.
+        pub fn with_limits(mut self, limits: Limits) -> Self {
+            self.limits = limits;
+            self
+        }
+
+        fn session(&mut self) -> SgxResult<&mut T> {
+            if self.session.is_none() {
+                let identity = self
+                    .connector
+                    .identity()
+                    .ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)?;
+                let (session, certificate) = (self.connect)(identity)?;
+                match self.server {
+                    ServerAuth::Attested => {
+                        let now = self.clock.now()? as time_t;
+                        self.connector
+                            .verify_server(&certificate, now)
+                            .map_err(|_| sgx_status_t::SGX_ERROR_INVALID_SIGNATURE)?;
+                    }
+                    ServerAuth::Pinned(expected) => {
+                        let mut digest = [0_u8; 32];
+                        Sha256::digest_into(&certificate, &mut digest);
+                        if digest != expected {
+                            return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+                        }
+                    }
+                }
+                self.session = Some(session);
+            }
+            self.session
+                .as_mut()
+                .ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)
+        }
+
+        /// Posts `body` to the operation `op` of the key `key_id`, and returns
+        /// the members of the response.
+        fn call(
+            &mut self,
+            key_id: &[u8],
+            op: &str,
+            body: Value,
+        ) -> SgxResult<Vec<(String, Value)>> {
+            let key_id = core::str::from_utf8(key_id)
+                .ok()
+                .filter(|id| {
+                    !id.is_empty()
+                        && id
+                            .bytes()
+                            .all(|b| b.is_ascii_alphanumeric() || b"-_.~".contains(&b))
+                })
+                .ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?;
+            let request = Request::post::<()>(
+                &self.host,
+                &format!("/v1/keys/{}/{}", key_id, op),
+                Vec::new(),
+            )
+            .and_then(|request| request.header("content-type", "application/json"))
+            .map_err(|_| sgx_status_t::SGX_ERROR_INVALID_PARAMETER)?
+            .body(body.to_string().into_bytes());
+
+            let limits = self.limits;
+            let result = http::send(self.session()?, &request, &limits);
+            let response = match result {
+                Ok(response) => response,
+                Err(_) => {
+                    self.session = None;
+                    return Err(sgx_status_t::SGX_ERROR_NETWORK_FAILURE);
+                }
+            };
+            let close = response.header("connection");
+            if matches!(close, Some(value) if value.eq_ignore_ascii_case("close")) {
+                self.session = None;
+            }
+
+            let status = response.status();
+            let mut body = response.into_body();
+            let parsed = Value::parse(&body);
+            wipe(&mut body);
+            match status {
+                200 => (),
+                400 | 422 => return Err(sgx_status_t::SGX_ERROR_MAC_MISMATCH),
+                401 | 403 => return Err(sgx_status_t::SGX_ERROR_NO_PRIVILEGE),
+                404 => return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER),
+                429 | 503 => return Err(sgx_status_t::SGX_ERROR_SERVICE_UNAVAILABLE),
+                _ => return Err(sgx_status_t::SGX_ERROR_UNEXPECTED),
+            }
+            match parsed {
+                Ok(Value::Object(members)) => Ok(members),
+                _ => Err(sgx_status_t::SGX_ERROR_UNEXPECTED),
+            }
+        }
+    }
+
+    impl<T, F, K> KmsClient for RaTlsKms<T, F, K>
+    where
+        T: Transport,
+        F: FnMut(&RaTlsIdentity) -> SgxResult<(T, Vec<u8>)>,
+        K: Clock,
+    {
+        fn wrap_key(&mut self, key_id: &[u8], key: &[u8]) -> SgxResult<Vec<u8>> {
+            let body = object("plaintext", Value::String(base64_encode(key)));
+            let mut members = self.call(key_id, "wrap", body)?;
+            take_base64(&mut members, "ciphertext")
+        }
+
+        fn unwrap_key(&mut self, key_id: &[u8], wrapped: &[u8], key: &mut [u8]) -> SgxError {
+            let body = object("ciphertext", Value::String(base64_encode(wrapped)));
+            let mut members = self.call(key_id, "unwrap", body)?;
+            let mut plaintext = take_base64(&mut members, "plaintext")?;
+            let result = copy_key(&plaintext, key);
+            wipe(&mut plaintext);
+            result
+        }
+
+        fn generate_data_key(&mut self, key_id: &[u8], key: &mut [u8]) -> SgxResult<Vec<u8>> {
+            let body = object("length", Value::Integer(key.len() as i64));
+            let mut members = self.call(key_id, "generate", body)?;
+            let mut plaintext = take_base64(&mut members, "plaintext")?;
+            let result = copy_key(&plaintext, key);
+            wipe(&mut plaintext);
+            result?;
+            take_base64(&mut members, "ciphertext")
+        }
+    }
+
+    fn object(name: &str, value: Value) -> Value {
+        Value::Object(vec![(name.to_string(), value)])
+    }
+
+    fn copy_key(plaintext: &[u8], key: &mut [u8]) -> SgxError {
+        if plaintext.len() != key.len() {
+            return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
+        }
+        key.copy_from_slice(plaintext);
+        Ok(())
+    }
+
+    /// Takes the base64 member `name` out of `members`, wiping its encoding.
+    fn take_base64(members: &mut [(String, Value)], name: &str) -> SgxResult<Vec<u8>> {
+        let value = members
+            .iter_mut()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| mem::replace(value, Value::Null));
+        match value {
+            Some(Value::String(text)) => {
+                let mut text = text.into_bytes();
+                let decoded = base64_decode(&text);
+                wipe(&mut text);
+                decoded.ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)
+            }
+            _ => Err(sgx_status_t::SGX_ERROR_UNEXPECTED),
+        }
+    }
+
+    /// Returns the padded base64 encoding of `data`.
+    fn base64_encode(data: &[u8]) -> String {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        let mut encoded = String::with_capacity(data.chunks(3).len() * 4);
+        for chunk in data.chunks(3) {
+            let bits = chunk
+                .iter()
+                .enumerate()
+                .fold(0_u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+                } else {
+                    encoded.push('=');
+                }
+            }
+        }
+        encoded
+    }
+
+    /// Decodes padded base64, rejecting non-zero trailing bits.
+    fn base64_decode(text: &[u8]) -> Option<Vec<u8>> {
+        let chunks = text.len() / 4;
+        if chunks * 4 != text.len() {
+            return None;
+        }
+        let mut data = Vec::with_capacity(chunks * 3);
+        for (n, chunk) in text.chunks(4).enumerate() {
+            let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+            if padding > 2 || (padding > 0 && n + 1 != chunks) {
+                return None;
+            }
+            let mut bits = 0_u32;
+            for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+                let value = match c {
+                    b'A'..=b'Z' => c - b'A',
+                    b'a'..=b'z' => c - b'a' + 26,
+                    b'0'..=b'9' => c - b'0' + 52,
+                    b'+' => 62,
+                    b'/' => 63,
+                    _ => return None,
+                };
+                bits |= (value as u32) << (18 - 6 * i);
+            }
+            let len = 3 - padding;
+            if bits & (0xff_ffff >> (8 * len)) != 0 {
+                return None;
+            }
+            for i in 0..len {
+                data.push((bits >> (16 - 8 * i)) as u8);
+            }
+        }
+        Some(data)
+    }
+}
@@ -0,0 +1,580 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! An ordered key-value store kept in protected files.
+//!
+//! A [`SealedKv`] keeps a log of writes in a data file, each record
+//! encrypted and authenticated on its own with AES-GCM under the record key,
+//! along with its position in the log. A root file, a protected file
+//! committed through a [`Transaction`] after every batch, records how much
+//! of the log is committed and the SHA-256 hash chain over it, which is
+//! checked in full on opening. A batch which was written but whose root
+//! wasn't committed when the enclave crashed is discarded, so every
+//! [`WriteBatch`] is applied entirely or not at all.
+//!
+//! The records superseded by later writes are only dropped by [`compact`],
+//! which writes the live entries to a new data file and switches the root
+//! over to it.
+//!
+//! All entries are held in enclave memory, decrypted. The store can be
+//! rolled back as a whole, including its root; to detect this keep its
+//! [`root`] hash or [`generation`] in a
+//! [`VersionedSealedState`](crate::state::VersionedSealedState) or another
+//! [`MonotonicCounter`](crate::counter::MonotonicCounter).
+//!
+//! The files are those of `sgx_tstd::sgxfs`, so this module needs the `kv`
+//! feature.
+//!
+//! [`compact`]: SealedKv::compact
+//! [`root`]: SealedKv::root
+//! [`generation`]: SealedKv::generation
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Bound;
+use sgx_tcrypto::hash::{Hash, Sha256};
+use sgx_tcrypto::rng::fill_random;
+use sgx_tcrypto::secret::Zeroize;
+use sgx_tcrypto::{rsgx_rijndael128GCM_decrypt, rsgx_rijndael128GCM_encrypt};
+use sgx_types::*;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sgxfs::{self, OpenOptions, SgxFile, Transaction, NODE_SIZE};
+
+/// Name of the protected file recording the committed state of a `SealedKv`.
+const KV_ROOT_NAME: &str = "kv.root";
+
+/// Returns the name of the data file of a `SealedKv` at `epoch`.
+fn kv_data_name(epoch: u64) -> String {
+    format!("kv.{}.data", epoch)
+}
+
+const KV_PUT: u8 = 1;
+const KV_DELETE: u8 = 2;
+
+fn kv_invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "sealed key-value store failed verification")
+}
+
+/// A set of writes to a `SealedKv`, applied atomically by `SealedKv::write`.
+///
+/// Writes are applied in the order they were added, so a later write to a key
+/// replaces an earlier one of the same batch.
+#[derive(Clone, Default)]
+pub struct WriteBatch {
+    ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+    /// Creates an empty batch.
+    pub fn new() -> WriteBatch {
+        WriteBatch::default()
+    }
+
+    /// Sets `key` to `value`.
+    pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> &mut WriteBatch {
+        self.ops.push((key.as_ref().to_vec(), Some(value.as_ref().to_vec())));
+        self
+    }
+
+    /// Removes `key`, if it's set.
+    pub fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> &mut WriteBatch {
+        self.ops.push((key.as_ref().to_vec(), None));
+        self
+    }
+
+    /// Returns the number of writes in the batch.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns whether the batch has no writes.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl fmt::Debug for WriteBatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteBatch").field("len", &self.ops.len()).finish()
+    }
+}
+
+/// The committed state of a `SealedKv`, as stored in its root file.
+#[derive(Clone, Copy)]
+struct KvRoot {
+    /// The data file in use, bumped by every compaction.
+    epoch: u64,
+    /// The number of commits, of batches and compactions.
+    generation: u64,
+    /// The length of the committed records of the data file.
+    len: u64,
+    /// The number of committed records.
+    records: u64,
+    /// The hash chain over the committed records.
+    hash: sgx_sha256_hash_t,
+}
+
+impl KvRoot {
+    const SIZE: usize = 32 + SGX_SHA256_HASH_SIZE;
+
+    fn empty(epoch: u64, generation: u64) -> KvRoot {
+        KvRoot {
+            epoch,
+            generation,
+            len: 0,
+            records: 0,
+            hash: sgx_sha256_hash_t::default(),
+        }
+    }
+
+    /// Reads the root file of `dir`, or returns `None` if there's none.
+    fn load(dir: &Path, policy: &sgxfs::KeyPolicy) -> io::Result<Option<KvRoot>> {
+        let mut file = match SgxFile::open_with_policy(dir.join(KV_ROOT_NAME), policy) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut data = Vec::with_capacity(KvRoot::SIZE);
+        file.read_to_end(&mut data)?;
+        if data.len() != KvRoot::SIZE {
+            return Err(kv_invalid());
+        }
+        let u64_at = |at: usize| {
+            let mut bytes = [0_u8; 8];
+            bytes.copy_from_slice(&data[at..at + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        let mut root = KvRoot::empty(u64_at(0), u64_at(8));
+        root.len = u64_at(16);
+        root.records = u64_at(24);
+        root.hash.copy_from_slice(&data[32..]);
+        Ok(Some(root))
+    }
+
+    /// Commits the root file of `dir`, through a `Transaction`.
+    fn save(&self, dir: &Path, policy: &sgxfs::KeyPolicy) -> io::Result<()> {
+        let mut data = Vec::with_capacity(KvRoot::SIZE);
+        data.extend_from_slice(&self.epoch.to_le_bytes());
+        data.extend_from_slice(&self.generation.to_le_bytes());
+        data.extend_from_slice(&self.len.to_le_bytes());
+        data.extend_from_slice(&self.records.to_le_bytes());
+        data.extend_from_slice(&self.hash);
+
+        let mut transaction = Transaction::with_policy(dir.join(KV_ROOT_NAME), policy)?;
+        transaction.write_all(&data)?;
+        transaction.commit()
+    }
+
+    /// Returns the additional data of the next record, binding it to its
+    /// place in the data file.
+    fn aad(&self) -> [u8; 16] {
+        let mut aad = [0_u8; 16];
+        aad[..8].copy_from_slice(&self.epoch.to_le_bytes());
+        aad[8..].copy_from_slice(&self.records.to_le_bytes());
+        aad
+    }
+
+    /// Advances the root past `record`, as stored in the data file.
+    fn advance(&mut self, record: &[u8]) {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.hash);
+        hasher.update(record);
+        hasher.finalize_into(&mut self.hash);
+        self.len += record.len() as u64;
+        self.records += 1;
+    }
+}
+
+/// Opens the data file of a `SealedKv` for reading and writing, creating it
+/// if `create`.
+fn open_kv_data(path: &Path, policy: &sgxfs::KeyPolicy, create: bool) -> io::Result<SgxFile> {
+    let mut opts = OpenOptions::new();
+    opts.read(!create).write(create).update(true);
+    opts.open_with_policy(path, policy)
+}
+
+/// Encrypts a write of `value` to `key`, or its removal, and appends it to
+/// `buf` as the next record after `root`, advancing `root`.
+///
+/// A record is stored as its length, a random nonce, the encrypted operation,
+/// key and value, and the GCM tag.
+fn encode_kv_record(
+    buf: &mut Vec<u8>,
+    root: &mut KvRoot,
+    record_key: &sgx_aes_gcm_128bit_key_t,
+    key: &[u8],
+    value: Option<&[u8]>,
+) -> io::Result<()> {
+    let value_len = value.map_or(0, <[u8]>::len);
+    if key.len() + value_len > u32::MAX as usize - 5 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "key-value record is too large"));
+    }
+    let mut plaintext = Vec::with_capacity(5 + key.len() + value_len);
+    plaintext.push(if value.is_some() { KV_PUT } else { KV_DELETE });
+    plaintext.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    plaintext.extend_from_slice(key);
+    plaintext.extend_from_slice(value.unwrap_or_default());
+
+    let mut nonce = [0_u8; SGX_AESGCM_IV_SIZE];
+    fill_random(&mut nonce)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "failed to generate a record nonce"))?;
+    let start = buf.len();
+    buf.extend_from_slice(&(plaintext.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&nonce);
+    buf.resize(start + 4 + nonce.len() + plaintext.len(), 0);
+    let mut tag = sgx_aes_gcm_128bit_tag_t::default();
+    let aad = root.aad();
+    let encrypted = rsgx_rijndael128GCM_encrypt(
+        record_key,
+        &plaintext,
+        &nonce,
+        &aad,
+        &mut buf[start + 4 + nonce.len()..],
+        &mut tag,
+    );
+    plaintext.zeroize();
+    if encrypted.is_err() {
+        buf.truncate(start);
+        return Err(io::Error::new(io::ErrorKind::Other, "failed to encrypt the record"));
+    }
+    buf.extend_from_slice(&tag);
+    root.advance(&buf[start..]);
+    Ok(())
+}
+
+/// Decrypts the record at the start of `data`, the next one after `root`,
+/// and applies it to `entries`, advancing `root` and `data`.
+fn apply_kv_record(
+    data: &mut &[u8],
+    root: &mut KvRoot,
+    record_key: &sgx_aes_gcm_128bit_key_t,
+    entries: &mut BTreeMap<Vec<u8>, Vec<u8>>,
+) -> io::Result<()> {
+    const OVERHEAD: usize = 4 + SGX_AESGCM_IV_SIZE + SGX_AESGCM_MAC_SIZE;
+    if data.len() < OVERHEAD {
+        return Err(kv_invalid());
+    }
+    let len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if len < 5 || data.len() - OVERHEAD < len {
+        return Err(kv_invalid());
+    }
+    let (record, rest) = data.split_at(OVERHEAD + len);
+    let nonce = &record[4..4 + SGX_AESGCM_IV_SIZE];
+    let ciphertext = &record[4 + SGX_AESGCM_IV_SIZE..4 + SGX_AESGCM_IV_SIZE + len];
+    let mut tag = sgx_aes_gcm_128bit_tag_t::default();
+    tag.copy_from_slice(&record[record.len() - SGX_AESGCM_MAC_SIZE..]);
+
+    let mut plaintext = vec![0_u8; len];
+    let aad = root.aad();
+    if rsgx_rijndael128GCM_decrypt(record_key, ciphertext, nonce, &aad, &tag, &mut plaintext)
+        .is_err()
+    {
+        return Err(kv_invalid());
+    }
+    let key_len = u32::from_le_bytes([plaintext[1], plaintext[2], plaintext[3], plaintext[4]]);
+    let key_len = key_len as usize;
+    let valid = match plaintext[0] {
+        KV_PUT if key_len <= len - 5 => {
+            let value = plaintext[5 + key_len..].to_vec();
+            entries.insert(plaintext[5..5 + key_len].to_vec(), value);
+            true
+        }
+        KV_DELETE if key_len == len - 5 => {
+            entries.remove(&plaintext[5..]);
+            true
+        }
+        _ => false,
+    };
+    plaintext.zeroize();
+    if !valid {
+        return Err(kv_invalid());
+    }
+    root.advance(record);
+    *data = rest;
+    Ok(())
+}
+
+/// An ordered key-value store kept in protected files.
+///
+/// See the [module documentation](self) for how it's stored.
+pub struct SealedKv {
+    dir: PathBuf,
+    policy: sgxfs::KeyPolicy,
+    record_key: sgx_aes_gcm_128bit_key_t,
+    file: SgxFile,
+    root: KvRoot,
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+    poisoned: bool,
+}
+
+impl SealedKv {
+    /// Opens the store in `dir`, in files encrypted with the key of `policy`
+    /// and records encrypted with `record_key`, creating it if there's none.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `InvalidData` is returned if a record or the root
+    /// fails verification.
+    pub fn open<P: AsRef<Path>>(
+        dir: P,
+        policy: &sgxfs::KeyPolicy,
+        record_key: &sgx_aes_gcm_128bit_key_t,
+    ) -> io::Result<SealedKv> {
+        let dir = dir.as_ref();
+        let root = match KvRoot::load(dir, policy)? {
+            Some(root) => root,
+            None => {
+                std::untrusted::fs::create_dir_all(dir)?;
+                let root = KvRoot::empty(0, 0);
+                open_kv_data(&dir.join(kv_data_name(0)), policy, true)?.sync_all()?;
+                root.save(dir, policy)?;
+                root
+            }
+        };
+
+        let mut file = open_kv_data(&dir.join(kv_data_name(root.epoch)), policy, false)?;
+        let mut data = vec![0_u8; root.len as usize];
+        file.read_exact(&mut data).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => kv_invalid(),
+            _ => e,
+        })?;
+
+        let mut replayed = KvRoot::empty(root.epoch, root.generation);
+        let mut entries = BTreeMap::new();
+        let mut rest = &data[..];
+        while !rest.is_empty() {
+            apply_kv_record(&mut rest, &mut replayed, record_key, &mut entries)?;
+        }
+        if replayed.records != root.records || replayed.hash != root.hash {
+            return Err(kv_invalid());
+        }
+
+        // Remove what a crash during a compaction may have left behind.
+        if root.epoch > 0 {
+            let _ = sgxfs::remove(dir.join(kv_data_name(root.epoch - 1)));
+        }
+        let _ = sgxfs::remove(dir.join(kv_data_name(root.epoch + 1)));
+        Ok(SealedKv {
+            dir: dir.to_path_buf(),
+            policy: *policy,
+            record_key: *record_key,
+            file,
+            root,
+            entries,
+            poisoned: false,
+        })
+    }
+
+    /// Returns the value of `key`.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<&[u8]> {
+        self.entries.get(key.as_ref()).map(Vec::as_slice)
+    }
+
+    /// Returns whether `key` is set.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> bool {
+        self.entries.contains_key(key.as_ref())
+    }
+
+    /// Returns an iterator over the entries, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.entries.iter().map(|(key, value)| (key.as_slice(), value.as_slice()))
+    }
+
+    /// Returns an iterator over the entries with keys between `start` and
+    /// `end`, in key order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` is after `end`, or if they're the same key and both
+    /// excluded.
+    pub fn range<'a>(
+        &'a self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> impl Iterator<Item = (&'a [u8], &'a [u8])> {
+        self.entries
+            .range::<[u8], _>((start, end))
+            .map(|(key, value)| (key.as_slice(), value.as_slice()))
+    }
+
+    /// Returns an iterator over the entries whose keys start with `prefix`,
+    /// in key order.
+    pub fn scan_prefix<'a>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = (&'a [u8], &'a [u8])> {
+        self.range(Bound::Included(prefix), Bound::Unbounded)
+            .take_while(move |(key, _)| key.starts_with(prefix))
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the store has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Sets `key` to `value`, committing it to disk.
+    pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) -> io::Result<()> {
+        let mut batch = WriteBatch::new();
+        batch.put(key, value);
+        self.write(batch)
+    }
+
+    /// Removes `key`, committing it to disk.
+    pub fn delete<K: AsRef<[u8]>>(&mut self, key: K) -> io::Result<()> {
+        let mut batch = WriteBatch::new();
+        batch.delete(key);
+        self.write(batch)
+    }
+
+    /// Applies the writes of `batch` and commits them to disk, all of them or
+    /// none of them.
+    ///
+    /// # Errors
+    ///
+    /// If writing to disk fails, the batch may or may not have been committed,
+    /// and the store returns an error of kind `Other` for further writes until
+    /// it's opened again. An error of kind `InvalidInput` is returned, and
+    /// nothing written, if a key and its value are 4 GiB or larger.
+    pub fn write(&mut self, batch: WriteBatch) -> io::Result<()> {
+        self.check_poisoned()?;
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let mut next = self.root;
+        let mut buf = Vec::new();
+        for (key, value) in &batch.ops {
+            encode_kv_record(&mut buf, &mut next, &self.record_key, key, value.as_deref())?;
+        }
+        next.generation += 1;
+
+        // Records past the committed length were never committed, and are
+        // overwritten.
+        let committed = (|| {
+            self.file.seek(SeekFrom::Start(self.root.len))?;
+            self.file.write_all(&buf)?;
+            self.file.sync_all()?;
+            next.save(&self.dir, &self.policy)
+        })();
+        if let Err(e) = committed {
+            self.poisoned = true;
+            return Err(e);
+        }
+        self.root = next;
+
+        for (key, value) in batch.ops {
+            match value {
+                Some(value) => self.entries.insert(key, value),
+                None => self.entries.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    /// Rewrites the data file with only the live entries, dropping the
+    /// records of overwritten and removed keys.
+    ///
+    /// The new data file is written in full before the root is switched over
+    /// to it, so a crash leaves either the old or the new one in use.
+    pub fn compact(&mut self) -> io::Result<()> {
+        self.check_poisoned()?;
+        const CHUNK_SIZE: usize = 16 * NODE_SIZE;
+
+        let mut next = KvRoot::empty(self.root.epoch + 1, self.root.generation + 1);
+        let path = self.dir.join(kv_data_name(next.epoch));
+        let mut file = open_kv_data(&path, &self.policy, true)?;
+        let written = (|| {
+            let mut buf = Vec::with_capacity(CHUNK_SIZE);
+            for (key, value) in &self.entries {
+                encode_kv_record(&mut buf, &mut next, &self.record_key, key, Some(value))?;
+                if buf.len() >= CHUNK_SIZE {
+                    file.write_all(&buf)?;
+                    buf.clear();
+                }
+            }
+            file.write_all(&buf)?;
+            file.sync_all()
+        })();
+        if let Err(e) = written {
+            drop(file);
+            let _ = sgxfs::remove(&path);
+            return Err(e);
+        }
+
+        if let Err(e) = next.save(&self.dir, &self.policy) {
+            self.poisoned = true;
+            return Err(e);
+        }
+        self.file = file;
+        let _ = sgxfs::remove(self.dir.join(kv_data_name(self.root.epoch)));
+        self.root = next;
+        Ok(())
+    }
+
+    /// Returns the hash chain over the committed records, which
+    /// authenticates the whole store.
+    ///
+    /// This is all zeroes for a store without records.
+    pub fn root(&self) -> sgx_sha256_hash_t {
+        self.root.hash
+    }
+
+    /// Returns the number of batches and compactions committed to the store.
+    pub fn generation(&self) -> u64 {
+        self.root.generation
+    }
+
+    /// Returns the number of records in the data file, which `compact`
+    /// brings down to the number of entries.
+    pub fn log_records(&self) -> u64 {
+        self.root.records
+    }
+
+    fn check_poisoned(&self) -> io::Result<()> {
+        if self.poisoned {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "sealed key-value store must be reopened after a failed commit",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SealedKv {
+    fn drop(&mut self) {
+        self.record_key.zeroize();
+    }
+}
+
+impl fmt::Debug for SealedKv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SealedKv")
+            .field("dir", &self.dir)
+            .field("generation", &self.root.generation)
+            .field("len", &self.entries.len())
+            .field("log_records", &self.root.records)
+            .finish()
+    }
+}
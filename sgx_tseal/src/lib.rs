@@ -70,6 +70,49 @@
 //! non-confidential data to provide data origin authentication only. The single
 //! output of this function is the authentication tag.
 //!
+//! A [`SealingBuilder`] chooses the key policy, masks and additional data of
+//! each class of sealed data, and the key ID of its MACs, and checks them
+//! again when unsealing.
+//!
+//! The [`blob`] module records the TCB data was sealed at, and reseals it at
+//! the current one after an upgrade of the enclave or the platform.
+//!
+//! A [`compress::Compression`] compresses payloads before sealing them, and
+//! bounds how much unsealing decompresses.
+//!
+//! The [`counter`] module keeps monotonic counters on the platform, on a
+//! remote service or on a quorum of enclaves, to detect rolled back state.
+//!
+//! A [`state::VersionedSealedState`] binds sealed state to such a counter,
+//! and refuses to load older state.
+//!
+//! A `kv::SealedKv`, with the `kv` feature, keeps an ordered key-value store
+//! in protected files, with batched atomic writes and a hash chain over its
+//! log whose root can be bound to such a counter.
+//!
+//! A [`usage::UsageLedger`] counts the signatures and decryptions of keys in
+//! such state, and refuses them past the limits or the expiry of each key.
+//!
+//! The [`envelope`] module encrypts payloads under random data keys, which are
+//! wrapped by the seal key or by a key management service.
+//!
+//! The [`kms`] module wraps them with a key held by an external KMS instead,
+//! reached over attested TLS, and caches the unwrapped keys for a while by
+//! the trusted time of a [`time::Clock`].
+//!
+//! A [`keyring::KeyRing`] holds the generations of a data key, rotates them
+//! on a schedule, and hooks the re-encryption of data before retiring them.
+//!
+//! A [`tenant::TenantKeyspace`] derives the keys of each tenant of an
+//! enclave from one root key, and labels their sealed data so that a tenant
+//! can't unseal the data of another.
+//!
+//! A [`import::KeyImportSession`] imports an existing key, which a client
+//! wraps to an attested ephemeral key of the enclave, and seals it.
+//!
+//! The [`escrow`] module exports backups of sealed keys which any M of N
+//! operators recover together, once M of them approved the export.
+//!
 
 #![no_std]
 #![cfg_attr(
@@ -88,6 +131,12 @@ extern crate sgx_tcrypto;
 extern crate sgx_trts;
 extern crate sgx_tse;
 extern crate sgx_types;
+#[cfg(feature = "ra_tls")]
+extern crate sgx_ttls;
+#[cfg(all(feature = "kv", not(target_env = "sgx")))]
+extern crate sgx_tstd as std;
+#[cfg(all(feature = "kv", target_env = "sgx"))]
+extern crate std;
 
 mod seal;
 pub use self::seal::{SgxSealedData, SgxUnsealedData};
@@ -95,4 +144,22 @@ pub use self::seal::{SgxSealedData, SgxUnsealedData};
 mod aad;
 pub use self::aad::SgxMacAadata;
 
+mod builder;
+pub use self::builder::{KeyPolicy, SealingBuilder};
+
 mod internal;
+
+pub mod blob;
+pub mod compress;
+pub mod counter;
+pub mod envelope;
+pub mod escrow;
+pub mod import;
+pub mod keyring;
+pub mod kms;
+#[cfg(feature = "kv")]
+pub mod kv;
+pub mod state;
+pub mod tenant;
+pub mod time;
+pub mod usage;
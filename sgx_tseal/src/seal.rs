@@ -302,6 +302,32 @@ impl<'a, T: 'a + Copy + ContiguousMemory> SgxSealedData<'a, T> {
         })
     }
 
+    ///
+    /// Seals `encrypt_text` with the fields of the key request in `request`, for
+    /// `SealingBuilder`.
+    ///
+    pub(crate) fn seal_data_with(
+        request: &SealRequest,
+        additional_text: &[u8],
+        encrypt_text: &'a T,
+    ) -> SgxResult<Self> {
+        let size = mem::size_of::<T>();
+        if size == 0 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let encrypt_slice: &[u8] = unsafe {
+            slice::from_raw_parts(
+                encrypt_text as *const _ as *const u8,
+                mem::size_of_val(encrypt_text),
+            )
+        };
+        let result = SgxInternalSealedData::seal_data_with(request, additional_text, encrypt_slice);
+        result.map(|x| SgxSealedData {
+            inner: x,
+            marker: PhantomData,
+        })
+    }
+
     ///
     /// This function is used to AES-GCM decrypt the input sealed data structure.
     /// Two output data sets result: one is the decrypted data; the second is the
@@ -618,6 +644,30 @@ impl<'a, T: 'a + Copy + ContiguousMemory> SgxSealedData<'a, [T]> {
         })
     }
 
+    ///
+    /// Seals `encrypt_text` with the fields of the key request in `request`, for
+    /// `SealingBuilder`.
+    ///
+    pub(crate) fn seal_data_with(
+        request: &SealRequest,
+        additional_text: &[u8],
+        encrypt_text: &'a [T],
+    ) -> SgxResult<Self> {
+        let size = mem::size_of::<T>();
+        let len = mem::size_of_val(encrypt_text);
+        if size == 0 || len == 0 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let encrypt_slice: &[u8] =
+            unsafe { slice::from_raw_parts(encrypt_text.as_ptr() as *const u8, len) };
+
+        let result = SgxInternalSealedData::seal_data_with(request, additional_text, encrypt_slice);
+        result.map(|x| SgxSealedData {
+            inner: x,
+            marker: PhantomData,
+        })
+    }
+
     ///
     /// This function is used to AES-GCM decrypt the input sealed data structure.
     /// Two output data sets result: one is the decrypted data; the second is the
@@ -770,6 +820,17 @@ impl<'a, T: 'a + ?Sized> SgxSealedData<'a, T> {
         SgxSealedData::default()
     }
 
+    pub(crate) fn from_internal(inner: SgxInternalSealedData) -> Self {
+        SgxSealedData {
+            inner,
+            marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn internal(&self) -> &SgxInternalSealedData {
+        &self.inner
+    }
+
     ///
     /// Get the size of payload in SgxSealedData.
     ///
@@ -0,0 +1,241 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Sealed state protected against rollback.
+//!
+//! A [`VersionedSealedState`] seals a value along with a version, and keeps
+//! the version in step with a [`MonotonicCounter`]. Loading sealed state
+//! older than the counter fails with [`RollbackDetected`], so the host can't
+//! bring back, say, the wallet balances of before a payment.
+//!
+//! [`save`] seals the value at the next version, hands it to the caller to
+//! store, and only then increments the counter. If the enclave stops in
+//! between, the stored state is one version ahead of the counter, and the
+//! next [`load`] accepts it and completes the increment. The state sealed by
+//! a save which didn't complete can thus come back once, in place of the
+//! state saved after it at the same version; callers should only act on a
+//! save, e.g. acknowledge a payment, once it returned.
+//!
+//! The additional data of the sealed state is `SVST` followed by the
+//! version, little endian.
+//!
+//! [`save`]: VersionedSealedState::save
+//! [`load`]: VersionedSealedState::load
+
+use crate::builder::SealingBuilder;
+use crate::counter::MonotonicCounter;
+use crate::internal::SgxInternalSealedData;
+use crate::seal::SgxSealedData;
+use alloc::vec::Vec;
+use core::fmt;
+use sgx_types::marker::ContiguousMemory;
+use sgx_types::*;
+
+const MAGIC: [u8; 4] = *b"SVST";
+const AAD_LEN: usize = 12;
+
+fn additional_data(version: u64) -> [u8; AAD_LEN] {
+    let mut aad = [0_u8; AAD_LEN];
+    aad[..4].copy_from_slice(&MAGIC);
+    aad[4..].copy_from_slice(&version.to_le_bytes());
+    aad
+}
+
+/// Sealed state which isn't the latest one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RollbackDetected {
+    /// The version of the sealed state.
+    pub version: u64,
+    /// The value of the counter.
+    pub counter: u64,
+}
+
+impl fmt::Display for RollbackDetected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sealed state version {} doesn't match the counter at {}",
+            self.version, self.counter
+        )
+    }
+}
+
+/// The errors of [`VersionedSealedState`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The state isn't the latest one.
+    Rollback(RollbackDetected),
+    /// Sealing, unsealing or the counter failed.
+    Sgx(sgx_status_t),
+}
+
+impl From<sgx_status_t> for StateError {
+    fn from(status: sgx_status_t) -> StateError {
+        StateError::Sgx(status)
+    }
+}
+
+impl From<RollbackDetected> for StateError {
+    fn from(rollback: RollbackDetected) -> StateError {
+        StateError::Rollback(rollback)
+    }
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Rollback(rollback) => rollback.fmt(f),
+            StateError::Sgx(status) => write!(f, "{}", status.as_str()),
+        }
+    }
+}
+
+pub type StateResult<T> = Result<T, StateError>;
+
+/// A value sealed with a version bound to a monotonic counter.
+pub struct VersionedSealedState<T, C> {
+    builder: SealingBuilder<'static>,
+    counter: C,
+    version: u64,
+    value: T,
+}
+
+impl<T: Copy + ContiguousMemory, C: MonotonicCounter> VersionedSealedState<T, C> {
+    /// Starts keeping `value`, to be sealed with the class of `builder` and
+    /// versioned by `counter`, from the current value of the counter.
+    ///
+    /// Nothing is sealed until [`save`]. The additional data of `builder` is
+    /// replaced by the version.
+    ///
+    /// [`save`]: VersionedSealedState::save
+    pub fn new(builder: &SealingBuilder<'_>, mut counter: C, value: T) -> StateResult<Self> {
+        let version = counter.read()?;
+        Ok(VersionedSealedState {
+            builder: builder.detached(),
+            counter,
+            version,
+            value,
+        })
+    }
+
+    /// Unseals state saved by [`save`], checking that it's the latest one.
+    ///
+    /// State one version ahead of the counter, from a save which stopped
+    /// before incrementing it, is accepted once the increment is completed.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`StateError::Rollback`] if the state is older than the
+    /// counter, or more than one version ahead of it, which means the counter
+    /// was rolled back or isn't the one of the state; and otherwise as
+    /// [`SealingBuilder::unseal`] does.
+    ///
+    /// [`save`]: VersionedSealedState::save
+    pub fn load(builder: &SealingBuilder<'_>, mut counter: C, sealed: &[u8]) -> StateResult<Self> {
+        let inner = SgxInternalSealedData::from_bytes(sealed)?;
+        let additional = inner.get_additional_txt();
+        if additional.len() != AAD_LEN || additional[..4] != MAGIC {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER.into());
+        }
+        let mut version = [0_u8; 8];
+        version.copy_from_slice(&additional[4..]);
+        let version = u64::from_le_bytes(version);
+
+        let builder = builder.detached();
+        let aad = additional_data(version);
+        let sealed = SgxSealedData::<T>::from_internal(inner);
+        let value = *builder
+            .additional_data(&aad)
+            .unseal(&sealed)?
+            .get_decrypt_txt();
+
+        let current = counter.read()?;
+        if version < current || version - current > 1 {
+            return Err(RollbackDetected {
+                version,
+                counter: current,
+            }
+            .into());
+        }
+        if version > current {
+            let counter = counter.increment()?;
+            if counter != version {
+                return Err(RollbackDetected { version, counter }.into());
+            }
+        }
+        Ok(VersionedSealedState {
+            builder,
+            counter,
+            version,
+            value,
+        })
+    }
+
+    /// Seals the value at the next version, passes it to `persist` to store,
+    /// and once that succeeded increments the counter, so that only this
+    /// state loads from then on.
+    ///
+    /// Nothing changes if sealing or `persist` fails.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`StateError::Rollback`] if the counter was incremented by
+    /// someone else in the meantime, leaving the saved state behind it.
+    pub fn save<F: FnOnce(&[u8]) -> SgxError>(&mut self, persist: F) -> StateResult<()> {
+        let next = self
+            .version
+            .checked_add(1)
+            .ok_or(sgx_status_t::SGX_ERROR_MC_USED_UP)?;
+        let aad = additional_data(next);
+        let sealed = self.builder.additional_data(&aad).seal(&self.value)?;
+        let bytes: Vec<u8> = sealed.internal().to_bytes()?;
+        persist(&bytes)?;
+
+        let counter = self.counter.increment()?;
+        if counter != next {
+            return Err(RollbackDetected {
+                version: next,
+                counter,
+            }
+            .into());
+        }
+        self.version = next;
+        Ok(())
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Returns the value to update; the update lasts once saved.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    /// Returns the version of the state last saved or loaded.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn counter(&mut self) -> &mut C {
+        &mut self.counter
+    }
+
+    pub fn into_inner(self) -> (T, C) {
+        (self.value, self.counter)
+    }
+}
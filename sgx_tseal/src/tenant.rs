@@ -0,0 +1,256 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Keys and sealed data scoped to the tenants of a multi-tenant enclave.
+//!
+//! A [`TenantKeyspace`] holds a random root key, sealed to the enclave, and
+//! derives a master key for each tenant from it with HKDF-SHA256, over the
+//! ID of the tenant, and the keys of the tenant from its master key, over
+//! their purpose. The keys of a tenant thus reveal nothing about those of
+//! another, or about the root key.
+//!
+//! A [`Tenant`] seals data under its own key, and labels the blob with its
+//! ID, authenticated along with the payload. Unsealing checks the label
+//! before anything else, so a blob of one tenant passed for another fails
+//! with `SGX_ERROR_NO_PRIVILEGE` rather than merely failing to decrypt,
+//! which lets callers tell a confused deputy from corrupted data.
+//!
+//! A tenant blob is laid out as:
+//!
+//! | offset | size | field                                 |
+//! |--------|------|---------------------------------------|
+//! | 0      | 4    | magic, `STNT`                         |
+//! | 4      | 1    | format version, [`TENANT_VERSION`]    |
+//! | 5      | 1    | length of the tenant ID, N            |
+//! | 6      | N    | tenant ID                             |
+//! | 6 + N  | 24   | nonce                                 |
+//! |        |      | ciphertext, 16-byte tag               |
+//!
+//! The cipher is XChaCha20-Poly1305, authenticating the header and the
+//! additional data of the caller.
+
+use crate::builder::SealingBuilder;
+use crate::internal::SgxInternalSealedData;
+use crate::seal::SgxSealedData;
+use alloc::vec::Vec;
+use core::fmt;
+use sgx_tcrypto::aead::{Aead, Tag, XChaCha20Poly1305, TAG_LEN};
+use sgx_tcrypto::ed25519::Ed25519PrivateKey;
+use sgx_tcrypto::kdf::hkdf_sha256;
+use sgx_tcrypto::rng::fill_random;
+use sgx_tcrypto::secret::{SecretBytes, Zeroize};
+use sgx_types::*;
+
+/// The version of the blob format written by [`Tenant::seal`].
+pub const TENANT_VERSION: u8 = 1;
+
+/// The longest tenant ID, in bytes.
+pub const MAX_TENANT_ID_LEN: usize = 255;
+
+const MAGIC: [u8; 4] = *b"STNT";
+const NONCE_LEN: usize = 24;
+const SALT: &[u8] = b"sgx_tseal tenant v1";
+const TENANT_INFO: &[u8] = b"tenant ";
+const SEAL_PURPOSE: &[u8] = b"seal";
+const SIGN_PURPOSE: &[u8] = b"sign";
+
+/// The root key the keys of tenants are derived from.
+pub struct TenantKeyspace {
+    root: SecretBytes<32>,
+}
+
+impl TenantKeyspace {
+    /// Draws a new root key.
+    pub fn generate() -> SgxResult<TenantKeyspace> {
+        let mut root = SecretBytes::<32>::zeroed();
+        fill_random(root.expose_secret_mut())?;
+        Ok(TenantKeyspace { root })
+    }
+
+    /// Seals the root key with `builder`, to be stored by the host.
+    pub fn seal(&self, builder: &SealingBuilder<'_>) -> SgxResult<Vec<u8>> {
+        builder
+            .seal_slice(&self.root.expose_secret()[..])?
+            .internal()
+            .to_bytes()
+    }
+
+    /// Unseals a root key sealed by [`seal`], checked against `builder`.
+    ///
+    /// [`seal`]: TenantKeyspace::seal
+    pub fn unseal(builder: &SealingBuilder<'_>, sealed: &[u8]) -> SgxResult<TenantKeyspace> {
+        let inner = SgxInternalSealedData::from_bytes(sealed)?;
+        let sealed = SgxSealedData::<[u8]>::from_internal(inner);
+        let mut unsealed = builder.unseal_slice(&sealed)?;
+        let mut root = SecretBytes::<32>::zeroed();
+        let result = if unsealed.decrypt.len() == 32 {
+            root.expose_secret_mut().copy_from_slice(&unsealed.decrypt);
+            Ok(TenantKeyspace { root })
+        } else {
+            Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+        };
+        unsealed.decrypt.zeroize();
+        result
+    }
+
+    /// Derives the keys of the tenant `id`.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if `id` is empty or longer
+    /// than [`MAX_TENANT_ID_LEN`].
+    pub fn tenant(&self, id: &[u8]) -> SgxResult<Tenant> {
+        if id.is_empty() || id.len() > MAX_TENANT_ID_LEN {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut tenant = Tenant {
+            id: id.to_vec(),
+            master: SecretBytes::zeroed(),
+            seal_key: SecretBytes::zeroed(),
+        };
+        let mut info = Vec::with_capacity(TENANT_INFO.len() + id.len());
+        info.extend_from_slice(TENANT_INFO);
+        info.extend_from_slice(id);
+        hkdf_sha256(
+            SALT,
+            self.root.expose_secret(),
+            &info,
+            tenant.master.expose_secret_mut(),
+        )?;
+        let mut seal_key = SecretBytes::<32>::zeroed();
+        tenant.derive_key(SEAL_PURPOSE, seal_key.expose_secret_mut())?;
+        tenant.seal_key = seal_key;
+        Ok(tenant)
+    }
+}
+
+impl fmt::Debug for TenantKeyspace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantKeyspace").finish_non_exhaustive()
+    }
+}
+
+/// The keys of one tenant.
+pub struct Tenant {
+    id: Vec<u8>,
+    master: SecretBytes<32>,
+    seal_key: SecretBytes<32>,
+}
+
+impl Tenant {
+    pub fn id(&self) -> &[u8] {
+        &self.id
+    }
+
+    /// Derives a key of the tenant for `purpose`, e.g. `b"api-token"`, into
+    /// `key`. Distinct purposes give independent keys; `seal` and `sign` are
+    /// those of [`seal`] and [`signing_key`].
+    ///
+    /// [`seal`]: Tenant::seal
+    /// [`signing_key`]: Tenant::signing_key
+    pub fn derive_key(&self, purpose: &[u8], key: &mut [u8]) -> SgxError {
+        hkdf_sha256(SALT, self.master.expose_secret(), purpose, key)
+    }
+
+    /// Derives the Ed25519 signing key of the tenant.
+    pub fn signing_key(&self) -> SgxResult<Ed25519PrivateKey> {
+        let mut seed = SecretBytes::<32>::zeroed();
+        self.derive_key(SIGN_PURPOSE, seed.expose_secret_mut())?;
+        Ok(Ed25519PrivateKey::from_seed(seed.expose_secret()))
+    }
+
+    /// Seals `plaintext` under the key of the tenant, labeled with its ID;
+    /// `aad` is authenticated but not stored.
+    pub fn seal(&self, aad: &[u8], plaintext: &[u8]) -> SgxResult<Vec<u8>> {
+        let header_len = 6 + self.id.len();
+        let mut blob = Vec::with_capacity(header_len + NONCE_LEN + plaintext.len() + TAG_LEN);
+        blob.extend_from_slice(&MAGIC);
+        blob.push(TENANT_VERSION);
+        blob.push(self.id.len() as u8);
+        blob.extend_from_slice(&self.id);
+
+        let mut nonce = [0_u8; NONCE_LEN];
+        fill_random(&mut nonce)?;
+        blob.extend_from_slice(&nonce);
+        let start = blob.len();
+        blob.resize(start + plaintext.len(), 0);
+        let full_aad = full_aad(&blob[..header_len], aad);
+        let tag = XChaCha20Poly1305::new(self.seal_key.expose_secret())?.encrypt(
+            &nonce,
+            &full_aad,
+            plaintext,
+            &mut blob[start..],
+        )?;
+        blob.extend_from_slice(&tag);
+        Ok(blob)
+    }
+
+    /// Unseals a blob sealed by [`seal`] for this tenant.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if the blob is malformed,
+    /// with `SGX_ERROR_NO_PRIVILEGE` if it's labeled with another tenant,
+    /// and with `SGX_ERROR_MAC_MISMATCH` if it doesn't authenticate.
+    ///
+    /// [`seal`]: Tenant::seal
+    pub fn unseal(&self, aad: &[u8], blob: &[u8]) -> SgxResult<Vec<u8>> {
+        if tenant_of(blob)? != &self.id[..] {
+            return Err(sgx_status_t::SGX_ERROR_NO_PRIVILEGE);
+        }
+        let header_len = 6 + self.id.len();
+        let start = header_len + NONCE_LEN;
+        let end = blob.len() - TAG_LEN;
+        let mut tag: Tag = [0; TAG_LEN];
+        tag.copy_from_slice(&blob[end..]);
+        let mut plaintext = vec![0_u8; end - start];
+        XChaCha20Poly1305::new(self.seal_key.expose_secret())?.decrypt(
+            &blob[header_len..start],
+            &full_aad(&blob[..header_len], aad),
+            &blob[start..end],
+            &tag,
+            &mut plaintext,
+        )?;
+        Ok(plaintext)
+    }
+}
+
+impl fmt::Debug for Tenant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tenant")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns the tenant ID a blob is labeled with, without authenticating it.
+///
+/// Fails with `SGX_ERROR_INVALID_PARAMETER` if the blob is malformed.
+pub fn tenant_of(blob: &[u8]) -> SgxResult<&[u8]> {
+    let invalid = sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    if blob.len() < 6 || blob[..4] != MAGIC || blob[4] != TENANT_VERSION {
+        return Err(invalid);
+    }
+    let id_len = blob[5] as usize;
+    if id_len == 0 || blob.len() < 6 + id_len + NONCE_LEN + TAG_LEN {
+        return Err(invalid);
+    }
+    Ok(&blob[6..6 + id_len])
+}
+
+fn full_aad(header: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut full = Vec::with_capacity(header.len() + aad.len());
+    full.extend_from_slice(header);
+    full.extend_from_slice(aad);
+    full
+}
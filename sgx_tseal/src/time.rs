@@ -0,0 +1,86 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Trusted time, for expiring cached keys and scheduling rotations.
+//!
+//! The enclave can't read a clock the host doesn't control: `time(2)` and
+//! the TSC are both the host's to set. A [`Clock`] is a source of time the
+//! enclave trusts instead, such as the time signed by an attested service
+//! or a time server whose responses the enclave authenticates.
+//!
+//! A closure returning the time is a clock, and a [`MonotonicClock`] keeps
+//! another clock from going backwards.
+
+use core::cell::Cell;
+use sgx_types::*;
+
+/// A trusted source of time, in seconds since the Unix epoch.
+pub trait Clock {
+    /// Returns the current time, or an error if it can't be trusted now.
+    fn now(&self) -> SgxResult<u64>;
+}
+
+impl<F: Fn() -> SgxResult<u64>> Clock for F {
+    fn now(&self) -> SgxResult<u64> {
+        self()
+    }
+}
+
+/// A clock which never goes backwards.
+///
+/// Reads earlier than the latest time read return the latest time, so that
+/// keys don't come back to life and schedules don't run twice if the source
+/// is set back, e.g. when it fails over to another server.
+#[derive(Debug)]
+pub struct MonotonicClock<C: Clock> {
+    clock: C,
+    latest: Cell<u64>,
+}
+
+impl<C: Clock> MonotonicClock<C> {
+    pub fn new(clock: C) -> MonotonicClock<C> {
+        MonotonicClock {
+            clock,
+            latest: Cell::new(0),
+        }
+    }
+
+    /// Starts from `latest`, e.g. the time recorded in sealed state.
+    pub fn with_floor(clock: C, latest: u64) -> MonotonicClock<C> {
+        MonotonicClock {
+            clock,
+            latest: Cell::new(latest),
+        }
+    }
+
+    /// Returns the latest time read.
+    pub fn latest(&self) -> u64 {
+        self.latest.get()
+    }
+
+    pub fn into_inner(self) -> C {
+        self.clock
+    }
+}
+
+impl<C: Clock> Clock for MonotonicClock<C> {
+    fn now(&self) -> SgxResult<u64> {
+        let now = self.clock.now()?.max(self.latest.get());
+        self.latest.set(now);
+        Ok(now)
+    }
+}
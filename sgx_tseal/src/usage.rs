@@ -0,0 +1,375 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Usage accounting and limits of keys, kept in rollback-protected state.
+//!
+//! A [`UsageLedger`] counts, for each key it tracks, the signatures issued
+//! and the bytes decrypted with it, and refuses uses past the limits or the
+//! expiry of the key. A caller which gets hold of the enclave, e.g. a
+//! compromised host process, can then only use a wallet key so many times
+//! and until so long, and the counts are there to audit from inside the
+//! enclave.
+//!
+//! The ledger is a [`VersionedSealedState`], so the host can't roll it back
+//! to counts of before some uses. Every use is charged before it's allowed:
+//! [`charge_signature`] and [`charge_decryption`] save the ledger with the
+//! use counted, and only then return, so that a use the enclave makes is
+//! always in the stored ledger. A save which stored the ledger but didn't
+//! complete keeps the use counted although it was refused; uses are never
+//! counted less than they were made.
+//!
+//! Saving increments the counter of the state, once per charge. Platform
+//! counters wear out, so keys signing often should be charged in batches,
+//! e.g. with [`charge_signatures`] before signing a batch of transactions.
+//!
+//! [`charge_signature`]: UsageLedger::charge_signature
+//! [`charge_signatures`]: UsageLedger::charge_signatures
+//! [`charge_decryption`]: UsageLedger::charge_decryption
+
+use crate::builder::SealingBuilder;
+use crate::counter::MonotonicCounter;
+use crate::state::{StateResult, VersionedSealedState};
+use crate::time::Clock;
+use sgx_types::marker::ContiguousMemory;
+use sgx_types::*;
+
+/// The number of keys a ledger tracks at most.
+pub const MAX_TRACKED_KEYS: usize = 16;
+
+/// The ID of a tracked key, e.g. the hash of its public key.
+pub type KeyId = [u8; 32];
+
+/// The uses allowed of a key.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UsageLimits {
+    /// The number of signatures the key may issue.
+    pub max_signatures: u64,
+    /// The number of bytes the key may decrypt.
+    pub max_bytes_decrypted: u64,
+    /// The time, in seconds since the Unix epoch, from which the key can't
+    /// be used any more.
+    pub not_after: u64,
+}
+
+impl UsageLimits {
+    /// No limits and no expiry.
+    pub const UNLIMITED: UsageLimits = UsageLimits {
+        max_signatures: u64::MAX,
+        max_bytes_decrypted: u64::MAX,
+        not_after: u64::MAX,
+    };
+}
+
+impl Default for UsageLimits {
+    fn default() -> Self {
+        UsageLimits::UNLIMITED
+    }
+}
+
+/// The usage of a tracked key.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyUsage {
+    pub key_id: KeyId,
+    pub limits: UsageLimits,
+    /// The number of signatures charged.
+    pub signatures: u64,
+    /// The number of bytes of decryption charged.
+    pub bytes_decrypted: u64,
+    /// The time the key was registered.
+    pub registered: u64,
+    /// The time of the latest charge, or 0 if the key hasn't been used.
+    pub last_used: u64,
+}
+
+impl KeyUsage {
+    /// Returns whether the key has expired at `now`.
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.limits.not_after
+    }
+
+    /// Returns the number of signatures the key may still issue.
+    pub fn signatures_left(&self) -> u64 {
+        self.limits.max_signatures.saturating_sub(self.signatures)
+    }
+
+    /// Returns the number of bytes the key may still decrypt.
+    pub fn bytes_left(&self) -> u64 {
+        self.limits
+            .max_bytes_decrypted
+            .saturating_sub(self.bytes_decrypted)
+    }
+}
+
+unsafe impl ContiguousMemory for KeyUsage {}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Ledger {
+    len: u64,
+    keys: [KeyUsage; MAX_TRACKED_KEYS],
+}
+
+unsafe impl ContiguousMemory for Ledger {}
+
+impl Ledger {
+    fn keys(&self) -> &[KeyUsage] {
+        &self.keys[..self.len as usize]
+    }
+
+    fn find(&mut self, key_id: &KeyId) -> SgxResult<&mut KeyUsage> {
+        let len = self.len as usize;
+        self.keys[..len]
+            .iter_mut()
+            .find(|usage| usage.key_id == *key_id)
+            .ok_or(sgx_status_t::SGX_ERROR_INVALID_PARAMETER)
+    }
+}
+
+enum Charge {
+    Signatures(u64),
+    Bytes(u64),
+}
+
+/// The usage of a set of keys, sealed with a version bound to a monotonic
+/// counter.
+///
+/// Uses are refused with `SGX_ERROR_NO_PRIVILEGE` once a key has expired or
+/// would go past one of its limits, and with `SGX_ERROR_INVALID_PARAMETER`
+/// for keys the ledger doesn't track.
+///
+/// ```ignore
+/// let mut ledger = UsageLedger::load(&class, counter, clock, &stored)?;
+/// ledger.charge_signature(&wallet_key_id, |sealed| store(sealed))?;
+/// let signature = wallet_key.sign(&transaction)?;
+/// ```
+pub struct UsageLedger<C: MonotonicCounter, K: Clock> {
+    state: VersionedSealedState<Ledger, C>,
+    clock: K,
+}
+
+impl<C: MonotonicCounter, K: Clock> UsageLedger<C, K> {
+    /// Starts an empty ledger, sealed with the class of `builder` and
+    /// versioned by `counter`, with the time of `clock`.
+    ///
+    /// Nothing is sealed until a key is registered.
+    pub fn new(builder: &SealingBuilder<'_>, counter: C, clock: K) -> StateResult<Self> {
+        let ledger = Ledger {
+            len: 0,
+            keys: [KeyUsage::default(); MAX_TRACKED_KEYS],
+        };
+        let state = VersionedSealedState::new(builder, counter, ledger)?;
+        Ok(UsageLedger { state, clock })
+    }
+
+    /// Unseals a ledger saved by an earlier instance, refusing older ones as
+    /// [`VersionedSealedState::load`] does.
+    pub fn load(
+        builder: &SealingBuilder<'_>,
+        counter: C,
+        clock: K,
+        sealed: &[u8],
+    ) -> StateResult<Self> {
+        let state = VersionedSealedState::<Ledger, C>::load(builder, counter, sealed)?;
+        if state.get().len as usize > MAX_TRACKED_KEYS {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER.into());
+        }
+        Ok(UsageLedger { state, clock })
+    }
+
+    /// Starts tracking `key_id` with `limits`, and saves the ledger.
+    ///
+    /// Fails with `SGX_ERROR_INVALID_PARAMETER` if the key is tracked
+    /// already, and with `SGX_ERROR_OUT_OF_MEMORY` if the ledger tracks
+    /// [`MAX_TRACKED_KEYS`] keys.
+    pub fn register<F>(
+        &mut self,
+        key_id: &KeyId,
+        limits: UsageLimits,
+        persist: F,
+    ) -> StateResult<()>
+    where
+        F: FnOnce(&[u8]) -> SgxError,
+    {
+        let now = self.clock.now()?;
+        self.update(persist, |ledger| {
+            if ledger.find(key_id).is_ok() {
+                return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+            }
+            let len = ledger.len as usize;
+            if len == MAX_TRACKED_KEYS {
+                return Err(sgx_status_t::SGX_ERROR_OUT_OF_MEMORY);
+            }
+            ledger.keys[len] = KeyUsage {
+                key_id: *key_id,
+                limits,
+                registered: now,
+                ..KeyUsage::default()
+            };
+            ledger.len += 1;
+            Ok(())
+        })
+    }
+
+    /// Replaces the limits of `key_id`, and saves the ledger.
+    ///
+    /// The counts are kept, so lowering a limit below them refuses the key
+    /// any further use of that kind.
+    pub fn set_limits<F>(
+        &mut self,
+        key_id: &KeyId,
+        limits: UsageLimits,
+        persist: F,
+    ) -> StateResult<()>
+    where
+        F: FnOnce(&[u8]) -> SgxError,
+    {
+        self.update(persist, |ledger| {
+            ledger.find(key_id)?.limits = limits;
+            Ok(())
+        })
+    }
+
+    /// Expires `key_id` now, e.g. once it's suspected to be compromised, and
+    /// saves the ledger.
+    pub fn revoke<F>(&mut self, key_id: &KeyId, persist: F) -> StateResult<()>
+    where
+        F: FnOnce(&[u8]) -> SgxError,
+    {
+        let now = self.clock.now()?;
+        self.update(persist, |ledger| {
+            let usage = ledger.find(key_id)?;
+            usage.limits.not_after = usage.limits.not_after.min(now);
+            Ok(())
+        })
+    }
+
+    /// Charges one signature to `key_id`, and saves the ledger; the key may
+    /// issue the signature once this returned.
+    pub fn charge_signature<F>(&mut self, key_id: &KeyId, persist: F) -> StateResult<()>
+    where
+        F: FnOnce(&[u8]) -> SgxError,
+    {
+        self.charge(key_id, Charge::Signatures(1), persist)
+    }
+
+    /// Charges `count` signatures to `key_id` at once, and saves the ledger.
+    pub fn charge_signatures<F>(
+        &mut self,
+        key_id: &KeyId,
+        count: u64,
+        persist: F,
+    ) -> StateResult<()>
+    where
+        F: FnOnce(&[u8]) -> SgxError,
+    {
+        self.charge(key_id, Charge::Signatures(count), persist)
+    }
+
+    /// Charges the decryption of `len` bytes to `key_id`, and saves the
+    /// ledger; the key may decrypt them once this returned.
+    pub fn charge_decryption<F>(&mut self, key_id: &KeyId, len: u64, persist: F) -> StateResult<()>
+    where
+        F: FnOnce(&[u8]) -> SgxError,
+    {
+        self.charge(key_id, Charge::Bytes(len), persist)
+    }
+
+    fn charge<F>(&mut self, key_id: &KeyId, charge: Charge, persist: F) -> StateResult<()>
+    where
+        F: FnOnce(&[u8]) -> SgxError,
+    {
+        let now = self.clock.now()?;
+        self.update(persist, |ledger| {
+            let usage = ledger.find(key_id)?;
+            if usage.is_expired(now) {
+                return Err(sgx_status_t::SGX_ERROR_NO_PRIVILEGE);
+            }
+            let (used, max) = match charge {
+                Charge::Signatures(count) => (
+                    usage.signatures.checked_add(count),
+                    usage.limits.max_signatures,
+                ),
+                Charge::Bytes(len) => (
+                    usage.bytes_decrypted.checked_add(len),
+                    usage.limits.max_bytes_decrypted,
+                ),
+            };
+            let used = match used {
+                Some(used) if used <= max => used,
+                _ => return Err(sgx_status_t::SGX_ERROR_NO_PRIVILEGE),
+            };
+            match charge {
+                Charge::Signatures(_) => usage.signatures = used,
+                Charge::Bytes(_) => usage.bytes_decrypted = used,
+            }
+            usage.last_used = usage.last_used.max(now);
+            Ok(())
+        })
+    }
+
+    /// Applies `change` to the ledger and saves it, restoring the ledger if
+    /// either fails before anything was stored.
+    fn update<F, G>(&mut self, persist: F, change: G) -> StateResult<()>
+    where
+        F: FnOnce(&[u8]) -> SgxError,
+        G: FnOnce(&mut Ledger) -> SgxError,
+    {
+        let before = *self.state.get();
+        change(self.state.get_mut())?;
+        let mut stored = false;
+        let saved = self.state.save(|sealed| {
+            persist(sealed)?;
+            stored = true;
+            Ok(())
+        });
+        if saved.is_err() && !stored {
+            *self.state.get_mut() = before;
+        }
+        saved
+    }
+
+    /// Returns the usage of `key_id`, if the ledger tracks it.
+    pub fn usage(&self, key_id: &KeyId) -> Option<&KeyUsage> {
+        self.state
+            .get()
+            .keys()
+            .iter()
+            .find(|usage| usage.key_id == *key_id)
+    }
+
+    /// Returns the usage of every tracked key, in the order they were
+    /// registered.
+    pub fn keys(&self) -> &[KeyUsage] {
+        self.state.get().keys()
+    }
+
+    /// Returns the version of the ledger last saved or loaded.
+    pub fn version(&self) -> u64 {
+        self.state.version()
+    }
+
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    pub fn into_inner(self) -> (C, K) {
+        let (_, counter) = self.state.into_inner();
+        (counter, self.clock)
+    }
+}
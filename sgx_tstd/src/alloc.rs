@@ -118,6 +118,10 @@ pub fn rust_oom(layout: Layout) -> ! {
     let hook: fn(Layout) =
         if hook.is_null() { default_alloc_error_hook } else { unsafe { mem::transmute(hook) } };
     hook(layout);
+    #[cfg(feature = "backtrace")]
+    if let Some(mut out) = crate::sys::stdio::panic_output() {
+        let _ = crate::sys::backtrace::oom::print(&mut out);
+    }
     crate::sys::abort_internal()
 }
 
@@ -83,6 +83,8 @@
 // a backtrace or actually symbolizing it.
 
 pub use crate::sys_common::backtrace::__rust_begin_short_backtrace;
+pub use crate::sys::backtrace::{Frame, IpFormat};
+pub use crate::sys_common::backtrace::{Symbol, SymbolName};
 
 use crate::cell::UnsafeCell;
 use crate::ffi::c_void;
@@ -91,14 +93,20 @@ use crate::fs;
 use crate::enclave;
 use crate::io;
 use crate::panic::{BacktraceStyle, get_backtrace_style, set_backtrace_style};
-use crate::path::Path;
+use crate::path::{Path, PathBuf};
+use crate::string::String;
 use crate::sync::Once;
 use crate::sys::backtrace::{self, BytesOrWideString};
 use crate::sys_common::backtrace::{
+    demangle,
+    demanglers,
+    ip_format,
     lock,
     output_filename,
+    read_source_line,
     resolve_frame_unsynchronized,
-    SymbolName,
+    resolve_unsynchronized,
+    source_line_provider,
 };
 use crate::vec::Vec;
 
@@ -169,6 +177,43 @@ enum BytesOrWide {
     Wide(Vec<u16>),
 }
 
+/// A backtrace made of raw instruction pointers only.
+///
+/// Capturing a `RawBacktrace` walks the stack into a fixed-size buffer without
+/// allocating or consulting any debug information, which keeps it cheap
+/// enough for the panic path. Symbols are only looked up when `resolve` is
+/// called, possibly much later. Alternatively the addresses returned by
+/// `ips` can be shipped out of the enclave and symbolicated there.
+#[derive(Clone)]
+pub struct RawBacktrace {
+    ips: [usize; MAX_RAW_FRAMES],
+    start: usize,
+    len: usize,
+}
+
+/// Maximum number of frames recorded by `RawBacktrace::capture`.
+const MAX_RAW_FRAMES: usize = 128;
+
+/// A resolved backtrace frame which owns all of its data.
+///
+/// This is what `Backtrace::capture_owned` returns. It doesn't reference the
+/// enclave image or any raw unwinding state, which makes it suitable to be
+/// serialized (see the `backtrace` feature of `sgx_serialize`) and shipped
+/// out of the enclave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedFrame {
+    /// The instruction pointer of the frame.
+    pub ip: usize,
+    /// The demangled symbol name, if it could be resolved.
+    pub name: Option<String>,
+    /// The source file of the symbol, if debug info is available.
+    pub filename: Option<PathBuf>,
+    /// The source line of the symbol, if debug info is available.
+    pub lineno: Option<u32>,
+    /// The source column of the symbol, if debug info is available.
+    pub colno: Option<u32>,
+}
+
 impl fmt::Debug for Backtrace {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         let capture = match &self.inner {
@@ -251,6 +296,10 @@ impl Backtrace {
                 match style {
                     BacktraceStyle::Full => true,
                     BacktraceStyle::Short => true,
+                    BacktraceStyle::Json => true,
+                    BacktraceStyle::Colored => true,
+                    BacktraceStyle::Raw => true,
+                    BacktraceStyle::Compact => true,
                     BacktraceStyle::Off => false,
                 }
             }
@@ -345,6 +394,247 @@ impl Backtrace {
     }
 }
 
+impl Backtrace {
+    /// Captures a backtrace and resolves it into plain owned data.
+    ///
+    /// Unlike `capture`, the returned frames don't refer to the enclave image
+    /// any more, so they can be sealed or handed to the host through an ocall
+    /// and symbolicated or printed later. One `OwnedFrame` is returned per
+    /// resolved symbol, so inlined functions get an entry each.
+    ///
+    /// An empty list is returned if backtraces are disabled.
+    #[inline(never)] // want to make sure there's a frame here to remove
+    pub fn capture_owned() -> Vec<OwnedFrame> {
+        if !Backtrace::enabled() {
+            return Vec::new();
+        }
+        Backtrace::create(Backtrace::capture_owned as usize).to_owned_frames()
+    }
+
+    /// Converts the frames of this backtrace into plain owned data.
+    ///
+    /// This resolves the backtrace first if that hasn't happened yet.
+    #[must_use]
+    pub fn to_owned_frames(&self) -> Vec<OwnedFrame> {
+        let capture = match &self.inner {
+            Inner::Captured(c) => c.force(),
+            _ => return Vec::new(),
+        };
+
+        let mut owned = Vec::new();
+        for frame in &capture.frames[capture.actual_start..] {
+            let ip = frame.frame.ip() as usize;
+            if frame.symbols.is_empty() {
+                owned.push(OwnedFrame::unresolved(ip));
+                continue;
+            }
+            for symbol in frame.symbols.iter() {
+                owned.push(OwnedFrame::new(
+                    ip,
+                    symbol.name.as_deref(),
+                    symbol.filename.as_ref().map(|b| match b {
+                        BytesOrWide::Bytes(w) => BytesOrWideString::Bytes(w),
+                        BytesOrWide::Wide(w) => BytesOrWideString::Wide(w),
+                    }),
+                    symbol.lineno,
+                    symbol.colno,
+                ));
+            }
+        }
+        owned
+    }
+}
+
+impl OwnedFrame {
+    fn unresolved(ip: usize) -> OwnedFrame {
+        OwnedFrame {
+            ip,
+            name: None,
+            filename: None,
+            lineno: None,
+            colno: None,
+        }
+    }
+
+    fn new(
+        ip: usize,
+        name: Option<&[u8]>,
+        filename: Option<BytesOrWideString<'_>>,
+        lineno: Option<u32>,
+        colno: Option<u32>,
+    ) -> OwnedFrame {
+        OwnedFrame {
+            ip,
+            name: name.map(|b| format!("{:#}", SymbolName::new(b))),
+            filename: filename.map(|b| match b {
+                BytesOrWideString::Bytes(w) => {
+                    use crate::os::unix::prelude::*;
+                    PathBuf::from(crate::ffi::OsStr::from_bytes(w))
+                }
+                BytesOrWideString::Wide(_) => PathBuf::from("<unknown>"),
+            }),
+            lineno,
+            colno,
+        }
+    }
+}
+
+impl RawBacktrace {
+    /// Captures the instruction pointers of the current call stack.
+    ///
+    /// At most 128 frames are recorded. Nothing is captured if backtraces
+    /// are disabled.
+    #[inline(never)] // want to make sure there's a frame here to remove
+    pub fn capture() -> RawBacktrace {
+        RawBacktrace::create(RawBacktrace::capture as usize)
+    }
+
+    // Capture frames past the function at `ip`.
+    fn create(ip: usize) -> RawBacktrace {
+        let mut raw = RawBacktrace {
+            ips: [0; MAX_RAW_FRAMES],
+            start: 0,
+            len: 0,
+        };
+        if !Backtrace::enabled() {
+            return raw;
+        }
+
+        let mut actual_start = None;
+        // SAFETY: We don't attempt to lock this reentrantly.
+        let _lock = unsafe { lock() };
+        unsafe {
+            backtrace::trace_unsynchronized(|frame| {
+                raw.ips[raw.len] = frame.ip() as usize;
+                raw.len += 1;
+                if frame.symbol_address() as usize == ip && actual_start.is_none() {
+                    actual_start = Some(raw.len);
+                }
+                raw.len < MAX_RAW_FRAMES
+            });
+        }
+        raw.start = actual_start.unwrap_or(0);
+        raw
+    }
+
+    /// Returns the captured instruction pointers, innermost frame first.
+    pub fn ips(&self) -> &[usize] {
+        &self.ips[self.start..self.len]
+    }
+
+    /// Resolves the captured instruction pointers into owned frames.
+    ///
+    /// One `OwnedFrame` is returned per symbol, and a nameless one for each
+    /// address that couldn't be resolved.
+    pub fn resolve(&self) -> Vec<OwnedFrame> {
+        let mut owned = Vec::new();
+        // SAFETY: We don't attempt to lock this reentrantly.
+        let _lock = unsafe { lock() };
+        for &ip in self.ips() {
+            unsafe { resolve_owned(ip, &mut owned) };
+        }
+        owned
+    }
+}
+
+// Resolves `ip` into `owned`, with a nameless frame if it can't be resolved.
+// The backtrace lock must be held.
+unsafe fn resolve_owned(ip: usize, owned: &mut Vec<OwnedFrame>) {
+    let mut hit = false;
+    resolve_unsynchronized(ip as *mut c_void, |symbol| {
+        hit = true;
+        owned.push(OwnedFrame::new(
+            ip,
+            symbol.name().map(|m| m.as_bytes()),
+            symbol.filename_raw(),
+            symbol.lineno(),
+            symbol.colno(),
+        ));
+    });
+    if !hit {
+        owned.push(OwnedFrame::unresolved(ip));
+    }
+}
+
+/// A frame of the current call stack, as yielded by `frames`.
+#[derive(Debug, Clone)]
+pub struct FrameInfo {
+    /// The instruction pointer of the frame.
+    pub ip: usize,
+    /// The symbols of the frame, innermost first.
+    ///
+    /// A frame has more than one symbol when functions were inlined into it,
+    /// and a single nameless one when it couldn't be resolved.
+    pub symbols: Vec<OwnedFrame>,
+}
+
+/// An iterator over the frames of a call stack, returned by `frames`.
+///
+/// Frames are resolved one at a time as the iterator advances, so stopping
+/// early skips the symbolication of the remaining frames.
+#[derive(Clone)]
+pub struct Frames {
+    raw: RawBacktrace,
+    next: usize,
+}
+
+impl Iterator for Frames {
+    type Item = FrameInfo;
+
+    fn next(&mut self) -> Option<FrameInfo> {
+        let ip = *self.raw.ips().get(self.next)?;
+        self.next += 1;
+
+        let mut symbols = Vec::new();
+        // SAFETY: We don't attempt to lock this reentrantly.
+        let _lock = unsafe { lock() };
+        unsafe { resolve_owned(ip, &mut symbols) };
+        Some(FrameInfo { ip, symbols })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.raw.ips().len() - self.next;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Frames {}
+
+/// Returns an iterator over the resolved frames of the current call stack,
+/// innermost first.
+///
+/// This is meant for applications building their own crash reports, e.g.
+/// Sentry-compatible payloads, out of the resolved frames rather than the
+/// printed backtrace. The call stack is captured right away, at most 128
+/// frames of it, and symbolicated lazily as the iterator advances. The
+/// iterator is empty if backtraces are disabled.
+#[inline(never)] // want to make sure there's a frame here to remove
+pub fn frames() -> impl Iterator<Item = FrameInfo> {
+    Frames {
+        raw: RawBacktrace::create(frames as usize),
+        next: 0,
+    }
+}
+
+impl fmt::Debug for RawBacktrace {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_list()
+            .entries(self.ips().iter().map(|ip| *ip as *const c_void))
+            .finish()
+    }
+}
+
+impl fmt::Display for RawBacktrace {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(fmt, "stack backtrace (unresolved):")?;
+        let ip_format = ip_format();
+        for (idx, ip) in self.ips().iter().enumerate() {
+            writeln!(fmt, "{:4}: {}", idx, ip_format.render(*ip as *mut c_void))?;
+        }
+        Ok(())
+    }
+}
+
 impl<'a> Backtrace {
     /// Returns an iterator over the backtrace frames.
     #[must_use]
@@ -377,7 +667,24 @@ impl fmt::Display for Backtrace {
             output_filename(fmt, path, style, None)
         };
 
+        let mut source_line = source_line_provider().map(|provider| {
+            move |file: BytesOrWideString<'_>, line: u32| read_source_line(provider, file, line)
+        });
+
+        let demanglers = demanglers();
+        let mut demangler = |name: &str| demangle(&demanglers, name);
+
         let mut f = backtrace::BacktraceFmt::new(fmt, style, &mut print_path);
+        if let Some(max_frames) = crate::sys_common::backtrace::max_frames() {
+            f.set_max_frames(max_frames);
+        }
+        if let Some(source_line) = source_line.as_mut() {
+            f.set_source_line_provider(source_line);
+        }
+        f.set_ip_format(ip_format());
+        if !demanglers.is_empty() {
+            f.set_demangler(&mut demangler);
+        }
         f.add_context()?;
         for frame in frames {
             if frame.symbols.is_empty() {
@@ -478,6 +785,15 @@ pub enum PrintFormat {
     Short,
     /// Show all the frames with absolute path for files.
     Full,
+    /// Show all the frames as a JSON document.
+    Json,
+    /// Show only relevant data, highlighted with ANSI escape codes when the
+    /// host's stderr is a terminal.
+    Colored,
+    /// Show the instruction pointers of the frames without resolving them.
+    Raw,
+    /// Show only relevant data, on a single line.
+    Compact,
 }
 
 /// Enable backtrace for dumping call stack on crash.
@@ -494,7 +810,126 @@ pub fn enable_backtrace<P: AsRef<Path>>(path: P, format: PrintFormat) -> io::Res
     let style = match format {
         PrintFormat::Short => BacktraceStyle::Short,
         PrintFormat::Full => BacktraceStyle::Full,
+        PrintFormat::Json => BacktraceStyle::Json,
+        PrintFormat::Colored => BacktraceStyle::Colored,
+        PrintFormat::Raw => BacktraceStyle::Raw,
+        PrintFormat::Compact => BacktraceStyle::Compact,
     };
     set_backtrace_style(style);
     Ok(())
 }
+
+/// Installs a callback providing source lines for full backtraces.
+///
+/// The enclave can't read its own source tree, so when debug info is
+/// available `provider` is asked for the line at each printed location, and
+/// what it returns is shown below that location in `PrintFormat::Full`
+/// backtraces. The callback typically reads the file through an ocall or
+/// looks it up in a source map bundled with the enclave.
+pub fn set_source_line_provider(provider: Option<fn(&Path, u32) -> Option<String>>) {
+    crate::sys_common::backtrace::set_source_line_provider(provider)
+}
+
+/// Registers a demangler for symbol names the built-in one doesn't recognize.
+///
+/// Only Rust symbol names are demangled out of the box. Enclaves linking code
+/// written in other languages, such as Go or Swift, can register demanglers
+/// for those. A demangler is given the raw symbol name and returns `None` if
+/// it doesn't recognize it; demanglers are tried in registration order and
+/// the raw name is printed if none of them succeeds.
+pub fn register_demangler(demangler: fn(&str) -> Option<String>) {
+    crate::sys_common::backtrace::register_demangler(demangler)
+}
+
+/// Removes all demanglers installed by `register_demangler`.
+pub fn clear_demanglers() {
+    crate::sys_common::backtrace::clear_demanglers()
+}
+
+/// Selects how instruction pointers are printed in backtraces.
+///
+/// `PrintFormat::Full`, `PrintFormat::Json` and `PrintFormat::Raw` traces
+/// print absolute addresses by default, which tells anyone reading the logs
+/// where the enclave was loaded. `IpFormat::Relative` prints offsets from the
+/// enclave base instead, which symbolicate offline just as well against the
+/// enclave image, and `IpFormat::Hashed` goes further and only prints salted
+/// hashes of those offsets.
+pub fn set_ip_format(ip_format: IpFormat) {
+    crate::sys_common::backtrace::set_ip_format(ip_format)
+}
+
+/// Enables or disables collapsing of repeated identical backtraces.
+///
+/// When enabled, a backtrace printed by the panic hook which is identical to
+/// the previous one (same instruction pointers) isn't printed again. Instead
+/// a short note with the number of repetitions is emitted after 1, 2, 4, 8,
+/// ... repetitions, and once more when a different backtrace shows up. This
+/// protects the logging channel from panic storms in retrying worker loops.
+pub fn set_deduplicate(enabled: bool) {
+    crate::sys_common::backtrace::set_deduplicate(enabled)
+}
+
+/// Limits the number of frames printed in backtraces.
+///
+/// By default the limit is read from the `RUST_BACKTRACE_LIMIT` environment
+/// variable of the host, this overrides it. `None` removes the limit.
+pub fn set_max_frames(max_frames: Option<usize>) {
+    crate::sys_common::backtrace::set_max_frames(max_frames)
+}
+
+/// Enables or disables printing a backtrace when an allocation fails.
+///
+/// Symbolicating a backtrace needs memory, so enabling this sets aside an
+/// emergency reserve which is freed right before the backtrace is printed by
+/// the allocation error handler. At most 32 frames are printed, in the short
+/// format.
+pub fn set_oom_backtrace(enabled: bool) {
+    backtrace::oom::set_enabled(enabled)
+}
+
+/// Drops all symbols cached by previous backtrace resolutions.
+///
+/// Resolved symbols are cached by instruction pointer so that repeated panics
+/// don't pay for DWARF lookups again. This only needs to be called if the
+/// enclave image used for symbolication changes.
+pub fn clear_symbol_cache() {
+    // SAFETY: We don't attempt to lock this reentrantly.
+    let _lock = unsafe { lock() };
+    unsafe { backtrace::cache::clear() }
+}
+
+/// Installs a filter deciding which symbols appear in backtraces printed by
+/// the panic hook.
+///
+/// Symbols for which `filter` returns `false` are left out of the trace.
+/// Passing `None` removes a previously installed filter.
+pub fn set_frame_filter(filter: Option<fn(&Frame, &Symbol) -> bool>) {
+    crate::sys_common::backtrace::set_frame_filter(filter)
+}
+
+/// A frame filter hiding the enclave runtime and standard library frames.
+///
+/// This can be passed to `set_frame_filter` to keep only application frames
+/// in user-facing traces.
+pub fn hide_runtime_frames(_frame: &Frame, symbol: &Symbol) -> bool {
+    const RUNTIME_PREFIXES: &[&str] = &[
+        "sgx_tstd::",
+        "sgx_trts::",
+        "sgx_unwind::",
+        "std::",
+        "core::",
+        "alloc::",
+        "panic_unwind::",
+        "panic_abort::",
+        "__rust_",
+        "rust_begin_unwind",
+        "rust_panic",
+    ];
+
+    let name = match symbol.name() {
+        Some(name) => format!("{:#}", name),
+        None => return true,
+    };
+    let name = name.trim_start_matches('<');
+    !RUNTIME_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
@@ -0,0 +1,192 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Memory dumps of the enclave for post-mortem debugging.
+//!
+//! This module is only available in debug builds. Enclave code registers the
+//! memory it wants to inspect after a crash as `DumpRegion`s, and [`dump`]
+//! writes all registered regions to a protected file on the host, either
+//! when asked to or, with [`set_dump_on_panic`], whenever a thread panics.
+//! The file is encrypted like any `SgxFile`, with the key set through
+//! [`set_dump_key`] so that it can be decrypted outside of the enclave, or
+//! with the enclave's auto key otherwise.
+//!
+//! The dump starts with the magic `SGXDUMP\0` and a little-endian `u32`
+//! version and region count. Each region follows as its name length (`u32`)
+//! and name, its offset from the enclave base and its length (both `u64`),
+//! and its contents.
+//!
+//! [`dump`]: fn.dump.html
+//! [`set_dump_on_panic`]: fn.set_dump_on_panic.html
+//! [`set_dump_key`]: fn.set_dump_key.html
+
+use crate::io::{self, Write};
+use crate::panic::{self, HookId};
+use crate::path::{Path, PathBuf};
+use crate::sgxfs::SgxFile;
+use crate::slice;
+use crate::string::String;
+use crate::sys_common::mutex::SgxThreadMutex;
+use crate::vec::Vec;
+use sgx_types::sgx_key_128bit_t;
+
+const DUMP_MAGIC: &[u8; 8] = b"SGXDUMP\0";
+const DUMP_VERSION: u32 = 1;
+
+struct Region {
+    id: usize,
+    name: String,
+    ptr: *const u8,
+    len: usize,
+}
+
+struct DumpConfig {
+    regions: Vec<Region>,
+    next_id: usize,
+    path: Option<PathBuf>,
+    key: Option<sgx_key_128bit_t>,
+    panic_hook: Option<HookId>,
+}
+
+static LOCK: SgxThreadMutex = SgxThreadMutex::new();
+// Protected by `LOCK`.
+static mut CONFIG: DumpConfig = DumpConfig {
+    regions: Vec::new(),
+    next_id: 0,
+    path: None,
+    key: None,
+    panic_hook: None,
+};
+
+fn with_config<R>(f: impl FnOnce(&mut DumpConfig) -> R) -> R {
+    // SAFETY: `CONFIG` is only accessed while holding `LOCK`.
+    unsafe {
+        let _ = LOCK.lock();
+        let r = f(&mut CONFIG);
+        let _ = LOCK.unlock();
+        r
+    }
+}
+
+/// A region of enclave memory included in dumps.
+///
+/// The region is part of every dump written by [`dump`] until the
+/// `DumpRegion` is dropped.
+///
+/// [`dump`]: fn.dump.html
+#[derive(Debug)]
+pub struct DumpRegion {
+    id: usize,
+}
+
+impl DumpRegion {
+    /// Registers `len` bytes at `ptr` to be dumped under `name`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads of `len` bytes for as long as the
+    /// returned `DumpRegion` is alive.
+    pub unsafe fn register(name: &str, ptr: *const u8, len: usize) -> DumpRegion {
+        let name = String::from(name);
+        with_config(|config| {
+            let id = config.next_id;
+            config.next_id += 1;
+            config.regions.push(Region { id, name, ptr, len });
+            DumpRegion { id }
+        })
+    }
+}
+
+impl Drop for DumpRegion {
+    fn drop(&mut self) {
+        let id = self.id;
+        with_config(|config| config.regions.retain(|region| region.id != id));
+    }
+}
+
+/// Sets the path of the dump file on the host.
+///
+/// Dumps aren't written until a path is set. Every dump overwrites the
+/// previous one.
+pub fn set_dump_path<P: AsRef<Path>>(path: P) {
+    let path = path.as_ref().to_path_buf();
+    with_config(|config| config.path = Some(path));
+}
+
+/// Sets the key the dump file is encrypted with.
+///
+/// `None`, the default, encrypts it with the enclave's auto key, in which
+/// case only the enclave itself can read it back.
+pub fn set_dump_key(key: Option<sgx_key_128bit_t>) {
+    with_config(|config| config.key = key);
+}
+
+/// Enables or disables writing a dump whenever a thread panics.
+///
+/// The dump is written by a panic hook added with `panic::add_hook`, after
+/// the panic hooks which were there before.
+///
+/// # Panics
+///
+/// Panics if called from a panicking thread.
+pub fn set_dump_on_panic(enabled: bool) {
+    let old_hook = with_config(|config| config.panic_hook.take());
+    if let Some(hook) = old_hook {
+        let _ = panic::remove_hook(hook);
+    }
+    if enabled {
+        let hook = panic::add_hook(Box::new(|_| {
+            let _ = dump();
+        }));
+        with_config(|config| config.panic_hook = Some(hook));
+    }
+}
+
+/// Writes all registered regions to the dump file.
+///
+/// Returns an error if no dump path was set or if writing the file failed.
+pub fn dump() -> io::Result<()> {
+    with_config(|config| {
+        let path = config
+            .path
+            .as_ref()
+            .ok_or(io::const_io_error!(io::ErrorKind::NotFound, "no dump path was set"))?;
+        let mut file = match config.key {
+            Some(ref key) => SgxFile::create_ex(path, key)?,
+            None => SgxFile::create(path)?,
+        };
+        write_dump(&mut file, &config.regions)?;
+        file.flush()
+    })
+}
+
+fn write_dump(w: &mut dyn Write, regions: &[Region]) -> io::Result<()> {
+    let base = crate::enclave::get_enclave_base() as usize;
+
+    w.write_all(DUMP_MAGIC)?;
+    w.write_all(&DUMP_VERSION.to_le_bytes())?;
+    w.write_all(&(regions.len() as u32).to_le_bytes())?;
+    for region in regions {
+        w.write_all(&(region.name.len() as u32).to_le_bytes())?;
+        w.write_all(region.name.as_bytes())?;
+        w.write_all(&((region.ptr as usize).wrapping_sub(base) as u64).to_le_bytes())?;
+        w.write_all(&(region.len as u64).to_le_bytes())?;
+        // SAFETY: the region is valid for reads as promised to `register`.
+        w.write_all(unsafe { slice::from_raw_parts(region.ptr, region.len) })?;
+    }
+    Ok(())
+}
@@ -22,12 +22,17 @@ use crate::ffi::OsString;
 use crate::fmt;
 use crate::io::{self, IoSlice, IoSliceMut, Read, ReadBuf, Seek, SeekFrom, Write};
 use crate::path::{Path, PathBuf};
+use crate::sync::{Arc, PoisonError, SgxMutex, SgxMutexGuard};
 use crate::sys::fs as fs_imp;
 use crate::sys_common::{AsInner, AsInnerMut, FromInner, IntoInner};
 use crate::time::SystemTime;
 #[cfg(not(feature = "untrusted_fs"))]
 use crate::untrusted::path::PathEx;
 
+mod vfs;
+
+pub use self::vfs::{set_fs_backend, MemFs, Vfs, VfsFile, VfsMetadata, VfsOpenOptions};
+
 /// A reference to an open file on the filesystem.
 ///
 /// An instance of a `File` can be read and/or written depending on what options
@@ -98,7 +103,14 @@ use crate::untrusted::path::PathEx;
 /// [`sync_all`]: File::sync_all
 #[cfg_attr(not(test), rustc_diagnostic_item = "File")]
 pub struct File {
-    inner: fs_imp::File,
+    inner: FileInner,
+}
+
+enum FileInner {
+    Host(fs_imp::File),
+    /// A file of the backend installed with `set_fs_backend`, shared with
+    /// the handles made by `try_clone`.
+    Virtual(Arc<SgxMutex<Box<dyn VfsFile>>>),
 }
 
 /// Metadata information about a file.
@@ -399,7 +411,10 @@ impl File {
     /// }
     /// ```
     pub fn sync_all(&self) -> io::Result<()> {
-        self.inner.fsync()
+        match self.inner {
+            FileInner::Host(ref inner) => inner.fsync(),
+            FileInner::Virtual(ref file) => lock(file).sync_all(),
+        }
     }
 
     /// This function is similar to [`sync_all`], except that it might not
@@ -429,7 +444,10 @@ impl File {
     /// }
     /// ```
     pub fn sync_data(&self) -> io::Result<()> {
-        self.inner.datasync()
+        match self.inner {
+            FileInner::Host(ref inner) => inner.datasync(),
+            FileInner::Virtual(ref file) => lock(file).sync_all(),
+        }
     }
 
     /// Truncates or extends the underlying file, updating the size of
@@ -465,7 +483,53 @@ impl File {
     /// Note that this method alters the content of the underlying file, even
     /// though it takes `&self` rather than `&mut self`.
     pub fn set_len(&self, size: u64) -> io::Result<()> {
-        self.inner.truncate(size)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.truncate(size),
+            FileInner::Virtual(ref file) => lock(file).set_len(size),
+        }
+    }
+
+    /// Allocates disk space for the `len` bytes starting at `offset`,
+    /// extending the file if the range ends past its end.
+    ///
+    /// Later writes to the range are then guaranteed not to fail for lack of
+    /// space. The contents of the file aren't changed, any new bytes read as
+    /// 0s.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file is not opened for
+    /// writing, or if the host filesystem doesn't support preallocation.
+    pub fn allocate(&self, offset: u64, len: u64) -> io::Result<()> {
+        match self.inner {
+            FileInner::Host(ref inner) => inner.allocate(offset, len),
+            FileInner::Virtual(ref file) => {
+                let mut file = lock(file);
+                let end = offset.checked_add(len).ok_or(io::const_io_error!(
+                    io::ErrorKind::InvalidInput,
+                    "allocated range overflows the file size"
+                ))?;
+                if file.len()? < end { file.set_len(end) } else { Ok(()) }
+            }
+        }
+    }
+
+    /// Deallocates the disk space of the `len` bytes starting at `offset`,
+    /// leaving a hole in the file.
+    ///
+    /// The size of the file isn't changed, and the bytes in the hole read as
+    /// 0s afterwards. This lets the host reclaim the space of regions of a
+    /// large file which are no longer used.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file is not opened for
+    /// writing, or if the host filesystem doesn't support sparse files.
+    pub fn punch_hole(&self, offset: u64, len: u64) -> io::Result<()> {
+        match self.inner {
+            FileInner::Host(ref inner) => inner.punch_hole(offset, len),
+            FileInner::Virtual(_) => vfs::unsupported(),
+        }
     }
 
     /// Queries metadata about the underlying file.
@@ -482,7 +546,13 @@ impl File {
     /// }
     /// ```
     pub fn metadata(&self) -> io::Result<Metadata> {
-        self.inner.file_attr().map(Metadata)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.file_attr().map(Metadata),
+            FileInner::Virtual(ref file) => {
+                let len = lock(file).len()?;
+                Ok(Metadata(fs_imp::FileAttr::from_vfs(VfsMetadata { len, is_dir: false })))
+            }
+        }
     }
 
     /// Creates a new `File` instance that shares the same underlying file handle
@@ -525,7 +595,11 @@ impl File {
     /// }
     /// ```
     pub fn try_clone(&self) -> io::Result<File> {
-        Ok(File { inner: self.inner.duplicate()? })
+        let inner = match self.inner {
+            FileInner::Host(ref inner) => FileInner::Host(inner.duplicate()?),
+            FileInner::Virtual(ref file) => FileInner::Virtual(file.clone()),
+        };
+        Ok(File { inner })
     }
 
     /// Changes the permissions on the underlying file.
@@ -561,7 +635,10 @@ impl File {
     /// Note that this method alters the permissions of the underlying file,
     /// even though it takes `&self` rather than `&mut self`.
     pub fn set_permissions(&self, perm: Permissions) -> io::Result<()> {
-        self.inner.set_permissions(perm.0)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.set_permissions(perm.0),
+            FileInner::Virtual(_) => vfs::unsupported(),
+        }
     }
 }
 
@@ -571,28 +648,43 @@ impl File {
 // `AsHandle`/`From<OwnedHandle>`/`Into<OwnedHandle>` and
 // `AsRawHandle`/`IntoRawHandle`/`FromRawHandle` on Windows.
 
+// Files of a backend have no descriptor, so the conversions to the host
+// file panic for them.
 impl AsInner<fs_imp::File> for File {
     fn as_inner(&self) -> &fs_imp::File {
-        &self.inner
+        match self.inner {
+            FileInner::Host(ref inner) => inner,
+            FileInner::Virtual(_) => panic!("file is not on the host filesystem"),
+        }
     }
 }
 impl FromInner<fs_imp::File> for File {
     fn from_inner(f: fs_imp::File) -> File {
-        File { inner: f }
+        File { inner: FileInner::Host(f) }
     }
 }
 impl IntoInner<fs_imp::File> for File {
     fn into_inner(self) -> fs_imp::File {
-        self.inner
+        match self.inner {
+            FileInner::Host(inner) => inner,
+            FileInner::Virtual(_) => panic!("file is not on the host filesystem"),
+        }
     }
 }
 
 impl fmt::Debug for File {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.inner.fmt(f)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.fmt(f),
+            FileInner::Virtual(_) => f.debug_struct("File").finish_non_exhaustive(),
+        }
     }
 }
 
+fn lock(file: &SgxMutex<Box<dyn VfsFile>>) -> SgxMutexGuard<'_, Box<dyn VfsFile>> {
+    file.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 /// Indicates how much extra capacity is needed to read the rest of the file.
 fn buffer_capacity_required(mut file: &File) -> usize {
     let size = file.metadata().map(|m| m.len()).unwrap_or(0);
@@ -604,20 +696,32 @@ fn buffer_capacity_required(mut file: &File) -> usize {
 
 impl Read for File {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(buf)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.read(buf),
+            FileInner::Virtual(ref file) => lock(file).read(buf),
+        }
     }
 
     fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
-        self.inner.read_vectored(bufs)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.read_vectored(bufs),
+            FileInner::Virtual(ref file) => lock(file).read_vectored(bufs),
+        }
     }
 
     fn read_buf(&mut self, buf: &mut ReadBuf<'_>) -> io::Result<()> {
-        self.inner.read_buf(buf)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.read_buf(buf),
+            FileInner::Virtual(ref file) => lock(file).read_buf(buf),
+        }
     }
 
     #[inline]
     fn is_read_vectored(&self) -> bool {
-        self.inner.is_read_vectored()
+        match self.inner {
+            FileInner::Host(ref inner) => inner.is_read_vectored(),
+            FileInner::Virtual(_) => false,
+        }
     }
 
     // Reserves space in the buffer based on the file size when available.
@@ -634,45 +738,72 @@ impl Read for File {
 }
 impl Write for File {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.write(buf)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.write(buf),
+            FileInner::Virtual(ref file) => lock(file).write(buf),
+        }
     }
 
     fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
-        self.inner.write_vectored(bufs)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.write_vectored(bufs),
+            FileInner::Virtual(ref file) => lock(file).write_vectored(bufs),
+        }
     }
 
     #[inline]
     fn is_write_vectored(&self) -> bool {
-        self.inner.is_write_vectored()
+        match self.inner {
+            FileInner::Host(ref inner) => inner.is_write_vectored(),
+            FileInner::Virtual(_) => false,
+        }
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.inner.flush()
+        match self.inner {
+            FileInner::Host(ref inner) => inner.flush(),
+            FileInner::Virtual(ref file) => lock(file).flush(),
+        }
     }
 }
 
 impl Seek for File {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        self.inner.seek(pos)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.seek(pos),
+            FileInner::Virtual(ref file) => lock(file).seek(pos),
+        }
     }
 }
 
 impl Read for &File {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(buf)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.read(buf),
+            FileInner::Virtual(ref file) => lock(file).read(buf),
+        }
     }
 
     fn read_buf(&mut self, buf: &mut ReadBuf<'_>) -> io::Result<()> {
-        self.inner.read_buf(buf)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.read_buf(buf),
+            FileInner::Virtual(ref file) => lock(file).read_buf(buf),
+        }
     }
 
     fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
-        self.inner.read_vectored(bufs)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.read_vectored(bufs),
+            FileInner::Virtual(ref file) => lock(file).read_vectored(bufs),
+        }
     }
 
     #[inline]
     fn is_read_vectored(&self) -> bool {
-        self.inner.is_read_vectored()
+        match self.inner {
+            FileInner::Host(ref inner) => inner.is_read_vectored(),
+            FileInner::Virtual(_) => false,
+        }
     }
 
     // Reserves space in the buffer based on the file size when available.
@@ -689,26 +820,41 @@ impl Read for &File {
 }
 impl Write for &File {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.write(buf)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.write(buf),
+            FileInner::Virtual(ref file) => lock(file).write(buf),
+        }
     }
 
     fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
-        self.inner.write_vectored(bufs)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.write_vectored(bufs),
+            FileInner::Virtual(ref file) => lock(file).write_vectored(bufs),
+        }
     }
 
     #[inline]
     fn is_write_vectored(&self) -> bool {
-        self.inner.is_write_vectored()
+        match self.inner {
+            FileInner::Host(ref inner) => inner.is_write_vectored(),
+            FileInner::Virtual(_) => false,
+        }
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.inner.flush()
+        match self.inner {
+            FileInner::Host(ref inner) => inner.flush(),
+            FileInner::Virtual(ref file) => lock(file).flush(),
+        }
     }
 }
 
 impl Seek for &File {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        self.inner.seek(pos)
+        match self.inner {
+            FileInner::Host(ref inner) => inner.seek(pos),
+            FileInner::Virtual(ref file) => lock(file).seek(pos),
+        }
     }
 }
 
@@ -926,7 +1072,11 @@ impl OpenOptions {
     }
 
     fn _open(&self, path: &Path) -> io::Result<File> {
-        fs_imp::File::open(path, &self.0).map(|inner| File { inner })
+        if let Some(backend) = vfs::backend() {
+            let file = backend.open(path, &self.0.to_vfs())?;
+            return Ok(File { inner: FileInner::Virtual(Arc::new(SgxMutex::new(file))) });
+        }
+        fs_imp::File::open(path, &self.0).map(|inner| File { inner: FileInner::Host(inner) })
     }
 }
 
@@ -1547,7 +1697,10 @@ impl AsInner<fs_imp::DirEntry> for DirEntry {
 /// }
 /// ```
 pub fn remove_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
-    fs_imp::unlink(path.as_ref())
+    match vfs::backend() {
+        Some(backend) => backend.remove_file(path.as_ref()),
+        None => fs_imp::unlink(path.as_ref()),
+    }
 }
 
 /// Given a path, query the file system to get information about a file,
@@ -1584,7 +1737,12 @@ pub fn remove_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
 /// }
 /// ```
 pub fn metadata<P: AsRef<Path>>(path: P) -> io::Result<Metadata> {
-    fs_imp::stat(path.as_ref()).map(Metadata)
+    match vfs::backend() {
+        Some(backend) => {
+            backend.metadata(path.as_ref()).map(|m| Metadata(fs_imp::FileAttr::from_vfs(m)))
+        }
+        None => fs_imp::stat(path.as_ref()).map(Metadata),
+    }
 }
 
 /// Query the metadata about a file without following symlinks.
@@ -1617,7 +1775,12 @@ pub fn metadata<P: AsRef<Path>>(path: P) -> io::Result<Metadata> {
 /// }
 /// ```
 pub fn symlink_metadata<P: AsRef<Path>>(path: P) -> io::Result<Metadata> {
-    fs_imp::lstat(path.as_ref()).map(Metadata)
+    match vfs::backend() {
+        Some(backend) => {
+            backend.metadata(path.as_ref()).map(|m| Metadata(fs_imp::FileAttr::from_vfs(m)))
+        }
+        None => fs_imp::lstat(path.as_ref()).map(Metadata),
+    }
 }
 
 /// Rename a file or directory to a new name, replacing the original file if
@@ -1659,7 +1822,10 @@ pub fn symlink_metadata<P: AsRef<Path>>(path: P) -> io::Result<Metadata> {
 /// }
 /// ```
 pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<()> {
-    fs_imp::rename(from.as_ref(), to.as_ref())
+    match vfs::backend() {
+        Some(backend) => backend.rename(from.as_ref(), to.as_ref()),
+        None => fs_imp::rename(from.as_ref(), to.as_ref()),
+    }
 }
 
 /// Copies the contents of one file to another. This function will also
@@ -1710,6 +1876,11 @@ pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<()>
 /// }
 /// ```
 pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<u64> {
+    if vfs::backend().is_some() {
+        let mut reader = File::open(from.as_ref())?;
+        let mut writer = File::create(to.as_ref())?;
+        return io::copy(&mut reader, &mut writer);
+    }
     fs_imp::copy(from.as_ref(), to.as_ref())
 }
 
@@ -1753,6 +1924,9 @@ pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<u64> {
 /// }
 /// ```
 pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Result<()> {
+    if vfs::backend().is_some() {
+        return vfs::unsupported();
+    }
     fs_imp::link(original.as_ref(), link.as_ref())
 }
 
@@ -1779,6 +1953,9 @@ pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Re
 /// }
 /// ```
 pub fn soft_link<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Result<()> {
+    if vfs::backend().is_some() {
+        return vfs::unsupported();
+    }
     fs_imp::symlink(original.as_ref(), link.as_ref())
 }
 
@@ -1812,6 +1989,9 @@ pub fn soft_link<P: AsRef<Path>, Q: AsRef<Path>>(original: P, link: Q) -> io::Re
 /// }
 /// ```
 pub fn read_link<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
+    if vfs::backend().is_some() {
+        return vfs::unsupported();
+    }
     fs_imp::readlink(path.as_ref())
 }
 
@@ -1852,6 +2032,9 @@ pub fn read_link<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
 /// }
 /// ```
 pub fn canonicalize<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
+    if vfs::backend().is_some() {
+        return vfs::unsupported();
+    }
     fs_imp::canonicalize(path.as_ref())
 }
 
@@ -1970,7 +2153,10 @@ pub fn create_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
 /// ```
 #[doc(alias = "rmdir")]
 pub fn remove_dir<P: AsRef<Path>>(path: P) -> io::Result<()> {
-    fs_imp::rmdir(path.as_ref())
+    match vfs::backend() {
+        Some(backend) => backend.remove_dir(path.as_ref()),
+        None => fs_imp::rmdir(path.as_ref()),
+    }
 }
 
 /// Removes a directory at this path, after removing all its contents. Use
@@ -2010,6 +2196,9 @@ pub fn remove_dir<P: AsRef<Path>>(path: P) -> io::Result<()> {
 /// }
 /// ```
 pub fn remove_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    if vfs::backend().is_some() {
+        return vfs::unsupported();
+    }
     fs_imp::remove_dir_all(path.as_ref())
 }
 
@@ -2084,6 +2273,9 @@ pub fn remove_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
 /// }
 /// ```
 pub fn read_dir<P: AsRef<Path>>(path: P) -> io::Result<ReadDir> {
+    if vfs::backend().is_some() {
+        return vfs::unsupported();
+    }
     fs_imp::readdir(path.as_ref()).map(ReadDir)
 }
 
@@ -2118,6 +2310,9 @@ pub fn read_dir<P: AsRef<Path>>(path: P) -> io::Result<ReadDir> {
 /// }
 /// ```
 pub fn set_permissions<P: AsRef<Path>>(path: P, perm: Permissions) -> io::Result<()> {
+    if vfs::backend().is_some() {
+        return vfs::unsupported();
+    }
     fs_imp::set_perm(path.as_ref(), perm.0)
 }
 
@@ -2179,7 +2374,14 @@ impl DirBuilder {
     }
 
     fn _create(&self, path: &Path) -> io::Result<()> {
-        if self.recursive { self.create_dir_all(path) } else { self.inner.mkdir(path) }
+        if self.recursive { self.create_dir_all(path) } else { self.mkdir(path) }
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        match vfs::backend() {
+            Some(backend) => backend.create_dir(path),
+            None => self.inner.mkdir(path),
+        }
     }
 
     fn create_dir_all(&self, path: &Path) -> io::Result<()> {
@@ -2187,7 +2389,7 @@ impl DirBuilder {
             return Ok(());
         }
 
-        match self.inner.mkdir(path) {
+        match self.mkdir(path) {
             Ok(()) => return Ok(()),
             Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
             Err(_) if path.is_dir() => return Ok(()),
@@ -2202,7 +2404,7 @@ impl DirBuilder {
                 ));
             }
         }
-        match self.inner.mkdir(path) {
+        match self.mkdir(path) {
             Ok(()) => Ok(()),
             Err(_) if path.is_dir() => Ok(()),
             Err(e) => Err(e),
@@ -2240,5 +2442,12 @@ impl AsInnerMut<fs_imp::DirBuilder> for DirBuilder {
 // instead.
 #[inline]
 pub fn try_exists<P: AsRef<Path>>(path: P) -> io::Result<bool> {
-    fs_imp::try_exists(path.as_ref())
+    match vfs::backend() {
+        Some(backend) => match backend.metadata(path.as_ref()) {
+            Ok(_) => Ok(true),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(error) => Err(error),
+        },
+        None => fs_imp::try_exists(path.as_ref()),
+    }
 }
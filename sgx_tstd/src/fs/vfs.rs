@@ -0,0 +1,404 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Pluggable backends for the untrusted filesystem.
+//!
+//! By default the functions of this module go to the host filesystem through
+//! ocalls. A `Vfs` installed with `set_fs_backend` takes over instead, which
+//! lets code using files run against `MemFs`, an in-enclave filesystem, in
+//! unit tests and in the simulator without touching the host.
+//!
+//! Only plain files and directories are supported by backends. Directory
+//! listings, links, permissions and other host specific operations return an
+//! error of kind `Unsupported` while a backend is installed.
+
+use crate::collections::BTreeMap;
+use crate::io::{self, Read, Seek, SeekFrom, Write};
+use crate::path::{Component, Path, PathBuf};
+use crate::sync::{Arc, PoisonError, SgxMutex, SgxMutexGuard, SgxThreadRwLock};
+
+/// A filesystem backend.
+///
+/// Paths are passed as given to the public functions, so backends have to
+/// resolve relative paths themselves.
+pub trait Vfs: Send + Sync {
+    /// Opens the file at `path`.
+    fn open(&self, path: &Path, opts: &VfsOpenOptions) -> io::Result<Box<dyn VfsFile>>;
+
+    /// Returns the metadata of the file or directory at `path`.
+    fn metadata(&self, path: &Path) -> io::Result<VfsMetadata>;
+
+    /// Removes the file at `path`.
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Renames the file or directory at `from` to `to`, replacing the
+    /// file at `to` if any.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Creates the directory at `path`, whose parent has to exist.
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Removes the empty directory at `path`.
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+}
+
+/// A file opened by a `Vfs`.
+pub trait VfsFile: Read + Write + Seek + Send {
+    /// Returns the size of the file.
+    fn len(&self) -> io::Result<u64>;
+
+    /// Truncates or extends the file to `size` bytes.
+    fn set_len(&mut self, size: u64) -> io::Result<()>;
+
+    /// Commits the file to its storage.
+    fn sync_all(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How a file is opened, as set on `OpenOptions`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VfsOpenOptions {
+    pub read: bool,
+    pub write: bool,
+    pub append: bool,
+    pub truncate: bool,
+    pub create: bool,
+    pub create_new: bool,
+}
+
+/// The metadata of a file or directory of a `Vfs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VfsMetadata {
+    pub len: u64,
+    pub is_dir: bool,
+}
+
+static BACKEND_LOCK: SgxThreadRwLock = SgxThreadRwLock::new();
+// protected by `BACKEND_LOCK`.
+static mut BACKEND: Option<Arc<dyn Vfs>> = None;
+
+/// Installs `backend` as the filesystem of the enclave, or goes back to the
+/// host filesystem if `None`, returning the previous backend.
+///
+/// Files which are already open keep using the filesystem they were opened
+/// on.
+pub fn set_fs_backend(backend: Option<Arc<dyn Vfs>>) -> Option<Arc<dyn Vfs>> {
+    unsafe {
+        let _ = BACKEND_LOCK.write();
+        let previous = crate::mem::replace(&mut BACKEND, backend);
+        let _ = BACKEND_LOCK.write_unlock();
+        previous
+    }
+}
+
+/// Returns the installed backend, if any.
+pub(super) fn backend() -> Option<Arc<dyn Vfs>> {
+    unsafe {
+        let _ = BACKEND_LOCK.read();
+        let backend = BACKEND.clone();
+        let _ = BACKEND_LOCK.read_unlock();
+        backend
+    }
+}
+
+pub(super) fn unsupported<T>() -> io::Result<T> {
+    Err(io::const_io_error!(
+        io::ErrorKind::Unsupported,
+        "operation not supported by the filesystem backend"
+    ))
+}
+
+fn lock<T>(mutex: &SgxMutex<T>) -> SgxMutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+enum Node {
+    File(Arc<SgxMutex<Vec<u8>>>),
+    Dir,
+}
+
+/// A filesystem held in enclave memory.
+///
+/// Relative paths are resolved against the root, and `..` components aren't
+/// resolved at all. Everything is lost when the `MemFs` is dropped.
+pub struct MemFs {
+    nodes: SgxMutex<BTreeMap<PathBuf, Node>>,
+}
+
+impl MemFs {
+    /// Creates an empty filesystem.
+    pub fn new() -> MemFs {
+        MemFs { nodes: SgxMutex::new(BTreeMap::new()) }
+    }
+
+    fn normalize(path: &Path) -> PathBuf {
+        path.components()
+            .filter(|c| !matches!(c, Component::RootDir | Component::CurDir))
+            .collect()
+    }
+
+    fn check_parent(nodes: &BTreeMap<PathBuf, Node>, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            None => Ok(()),
+            Some(parent) if parent.as_os_str().is_empty() => Ok(()),
+            Some(parent) => match nodes.get(parent) {
+                Some(Node::Dir) => Ok(()),
+                Some(Node::File(_)) => Err(io::const_io_error!(
+                    io::ErrorKind::NotADirectory,
+                    "parent is not a directory"
+                )),
+                None => Err(io::const_io_error!(
+                    io::ErrorKind::NotFound,
+                    "parent directory not found"
+                )),
+            },
+        }
+    }
+}
+
+impl Default for MemFs {
+    fn default() -> MemFs {
+        MemFs::new()
+    }
+}
+
+const NOT_FOUND: io::Error =
+    io::const_io_error!(io::ErrorKind::NotFound, "no such file or directory");
+
+impl Vfs for MemFs {
+    fn open(&self, path: &Path, opts: &VfsOpenOptions) -> io::Result<Box<dyn VfsFile>> {
+        let path = MemFs::normalize(path);
+        let mut nodes = lock(&self.nodes);
+        let data = match nodes.get(&path) {
+            Some(Node::Dir) => {
+                return Err(io::const_io_error!(io::ErrorKind::IsADirectory, "is a directory"));
+            }
+            Some(Node::File(_)) if opts.create_new => {
+                return Err(io::const_io_error!(io::ErrorKind::AlreadyExists, "file exists"));
+            }
+            Some(Node::File(data)) => data.clone(),
+            None if opts.create || opts.create_new => {
+                MemFs::check_parent(&nodes, &path)?;
+                let data = Arc::new(SgxMutex::new(Vec::new()));
+                nodes.insert(path, Node::File(data.clone()));
+                data
+            }
+            None => return Err(NOT_FOUND),
+        };
+        if opts.truncate && (opts.write || opts.append) {
+            lock(&data).clear();
+        }
+        Ok(Box::new(MemFile {
+            data,
+            pos: 0,
+            read: opts.read,
+            write: opts.write || opts.append,
+            append: opts.append,
+        }))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<VfsMetadata> {
+        let path = MemFs::normalize(path);
+        match lock(&self.nodes).get(&path) {
+            Some(Node::File(data)) => {
+                Ok(VfsMetadata { len: lock(data).len() as u64, is_dir: false })
+            }
+            Some(Node::Dir) => Ok(VfsMetadata { len: 0, is_dir: true }),
+            // The root always exists.
+            None if path.as_os_str().is_empty() => Ok(VfsMetadata { len: 0, is_dir: true }),
+            None => Err(NOT_FOUND),
+        }
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let path = MemFs::normalize(path);
+        let mut nodes = lock(&self.nodes);
+        match nodes.get(&path) {
+            Some(Node::File(_)) => {
+                nodes.remove(&path);
+                Ok(())
+            }
+            Some(Node::Dir) => {
+                Err(io::const_io_error!(io::ErrorKind::IsADirectory, "is a directory"))
+            }
+            None => Err(NOT_FOUND),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (MemFs::normalize(from), MemFs::normalize(to));
+        let mut nodes = lock(&self.nodes);
+        MemFs::check_parent(&nodes, &to)?;
+        match (nodes.get(&from), nodes.get(&to)) {
+            (None, _) => return Err(NOT_FOUND),
+            (Some(_), _) if from == to => return Ok(()),
+            (Some(Node::File(_)), Some(Node::Dir)) => {
+                return Err(io::const_io_error!(io::ErrorKind::IsADirectory, "is a directory"));
+            }
+            (Some(Node::Dir), Some(Node::File(_))) => {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::NotADirectory,
+                    "not a directory"
+                ));
+            }
+            (Some(Node::Dir), Some(Node::Dir)) if nodes.keys().any(|p| p.parent() == Some(&to)) => {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::DirectoryNotEmpty,
+                    "directory not empty"
+                ));
+            }
+            (Some(Node::Dir), _) if to.starts_with(&from) => {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::InvalidInput,
+                    "cannot move a directory into itself"
+                ));
+            }
+            _ => {}
+        }
+
+        // Move the node and, for a directory, everything below it.
+        let moved: Vec<PathBuf> = nodes.keys().filter(|p| p.starts_with(&from)).cloned().collect();
+        for path in moved {
+            let node = nodes.remove(&path).unwrap();
+            // `path` starts with `from`, as just checked.
+            let dest = to.join(path.strip_prefix(&from).unwrap());
+            nodes.insert(dest, node);
+        }
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let path = MemFs::normalize(path);
+        let mut nodes = lock(&self.nodes);
+        if path.as_os_str().is_empty() || nodes.contains_key(&path) {
+            return Err(io::const_io_error!(io::ErrorKind::AlreadyExists, "file exists"));
+        }
+        MemFs::check_parent(&nodes, &path)?;
+        nodes.insert(path, Node::Dir);
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let path = MemFs::normalize(path);
+        let mut nodes = lock(&self.nodes);
+        match nodes.get(&path) {
+            Some(Node::Dir) if nodes.keys().any(|p| p.parent() == Some(&path)) => Err(
+                io::const_io_error!(io::ErrorKind::DirectoryNotEmpty, "directory not empty"),
+            ),
+            Some(Node::Dir) => {
+                nodes.remove(&path);
+                Ok(())
+            }
+            Some(Node::File(_)) => Err(io::const_io_error!(
+                io::ErrorKind::NotADirectory,
+                "not a directory"
+            )),
+            None => Err(NOT_FOUND),
+        }
+    }
+}
+
+/// A file of a `MemFs`.
+///
+/// The contents are shared by all the handles of the file, and outlive its
+/// removal from the filesystem like on the host.
+struct MemFile {
+    data: Arc<SgxMutex<Vec<u8>>>,
+    pos: u64,
+    read: bool,
+    write: bool,
+    append: bool,
+}
+
+const BAD_DESCRIPTOR: io::Error =
+    io::const_io_error!(io::ErrorKind::PermissionDenied, "bad file descriptor");
+
+impl Read for MemFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.read {
+            return Err(BAD_DESCRIPTOR);
+        }
+        let data = lock(&self.data);
+        let start = crate::cmp::min(self.pos, data.len() as u64) as usize;
+        let len = crate::cmp::min(buf.len(), data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Write for MemFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.write {
+            return Err(BAD_DESCRIPTOR);
+        }
+        let mut data = lock(&self.data);
+        if self.append {
+            self.pos = data.len() as u64;
+        }
+        let start = self.pos as usize;
+        let end = start + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(buf);
+        self.pos = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (lock(&self.data).len() as u64, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        match base.checked_add_signed(offset) {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position"
+            )),
+        }
+    }
+}
+
+impl VfsFile for MemFile {
+    fn len(&self) -> io::Result<u64> {
+        Ok(lock(&self.data).len() as u64)
+    }
+
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        if !self.write {
+            return Err(BAD_DESCRIPTOR);
+        }
+        lock(&self.data).resize(size as usize, 0);
+        Ok(())
+    }
+}
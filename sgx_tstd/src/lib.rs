@@ -234,6 +234,8 @@ pub mod env;
 pub mod error;
 pub mod ffi;
 pub mod sgxfs;
+#[cfg(debug_assertions)]
+pub mod dump;
 #[cfg(feature = "untrusted_fs")]
 pub mod fs;
 pub mod io;
@@ -251,12 +253,28 @@ pub mod lazy;
 
 pub mod task {
     //! Types and Traits for working with asynchronous tasks.
+    //!
+    //! With the `net` feature, this also provides a single-threaded
+    //! [`Executor`] and asynchronous TCP sockets, [`AsyncTcpListener`] and
+    //! [`AsyncTcpStream`], waiting for readiness through an epoll ocall.
 
     #[doc(inline)]
     pub use core::task::*;
 
     #[doc(inline)]
     pub use alloc_crate::task::*;
+
+    #[cfg(feature = "net")]
+    pub use self::executor::{block_on, Executor, Spawner};
+    #[cfg(feature = "net")]
+    pub use self::net::{AsyncTcpListener, AsyncTcpStream};
+
+    #[cfg(feature = "net")]
+    mod executor;
+    #[cfg(feature = "net")]
+    mod net;
+    #[cfg(feature = "net")]
+    mod reactor;
 }
 
 pub mod arch {
@@ -16,8 +16,6 @@
 // under the License..
 
 use crate::cmp::Ordering;
-#[cfg(feature = "net")]
-use crate::convert::TryInto;
 use crate::fmt;
 use crate::hash;
 use crate::io::{self, Write};
@@ -26,8 +24,6 @@ use crate::mem;
 use crate::net::{htons, ntohs, IpAddr, Ipv4Addr, Ipv6Addr};
 use crate::option;
 use crate::slice;
-#[cfg(feature = "net")]
-use crate::sys_common::net::LookupHost;
 use crate::sys_common::{AsInner, FromInner, IntoInner};
 use crate::vec;
 
@@ -918,19 +914,6 @@ impl ToSocketAddrs for (Ipv6Addr, u16) {
     }
 }
 
-#[cfg(feature = "net")]
-#[allow(clippy::unnecessary_wraps, clippy::needless_collect)]
-fn resolve_socket_addr(lh: LookupHost) -> io::Result<vec::IntoIter<SocketAddr>> {
-    let p = lh.port();
-    let v: Vec<_> = lh
-        .map(|mut a| {
-            a.set_port(p);
-            a
-        })
-        .collect();
-    Ok(v.into_iter())
-}
-
 impl ToSocketAddrs for (&str, u16) {
     type Iter = vec::IntoIter<SocketAddr>;
     fn to_socket_addrs(&self) -> io::Result<vec::IntoIter<SocketAddr>> {
@@ -949,7 +932,7 @@ impl ToSocketAddrs for (&str, u16) {
         #[cfg(not(feature = "net"))]
         let r = Err(io::const_io_error!(io::ErrorKind::InvalidInput, "invalid socket address"));
         #[cfg(feature = "net")]
-        let r = resolve_socket_addr((host, port).try_into()?);
+        let r = super::resolve::resolve(host, port).map(Vec::into_iter);
         r
     }
 }
@@ -973,7 +956,16 @@ impl ToSocketAddrs for str {
         #[cfg(not(feature = "net"))]
         let r = Err(io::const_io_error!(io::ErrorKind::InvalidInput, "invalid socket address"));
         #[cfg(feature = "net")]
-        let r = resolve_socket_addr(self.try_into()?);
+        let r = {
+            let (host, port) = self.rsplit_once(':').ok_or(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                "invalid socket address"
+            ))?;
+            let port: u16 = port.parse().map_err(|_| {
+                io::const_io_error!(io::ErrorKind::InvalidInput, "invalid port value")
+            })?;
+            (host, port).to_socket_addrs()
+        };
         r
     }
 }
@@ -0,0 +1,242 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! Length-prefixed message framing, as used by gRPC.
+//!
+//! Each message is sent as a frame made of a compressed flag byte, the
+//! big-endian `u32` length of the message and the message itself.
+//! [`FramedRead`] and [`FramedWrite`] read and write such frames over any
+//! stream, and the varint functions encode the integers of protobuf
+//! messages, so that enclaves can talk to gRPC services with hand-written
+//! messages.
+
+use crate::convert::TryFrom;
+use crate::io::{self, Read, Write};
+
+/// The size of a frame header: the compressed flag and the length.
+const HEADER_LEN: usize = 5;
+
+/// The default limit on the length of read messages, 4 MiB as in gRPC.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 4 * 1024 * 1024;
+
+/// The maximum length of an encoded varint.
+pub const MAX_VARINT_LEN: usize = 10;
+
+/// A framed message.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    /// Whether the message is compressed, with the compression negotiated
+    /// out of band, e.g. by the `grpc-encoding` header.
+    pub compressed: bool,
+    /// The message.
+    pub message: Vec<u8>,
+}
+
+impl Frame {
+    /// Creates an uncompressed frame of `message`.
+    pub fn new(message: Vec<u8>) -> Frame {
+        Frame { compressed: false, message }
+    }
+}
+
+/// Reads frames from a stream.
+#[derive(Debug)]
+pub struct FramedRead<R> {
+    inner: R,
+    max_frame_len: usize,
+}
+
+impl<R: Read> FramedRead<R> {
+    /// Reads frames from `inner`, rejecting messages longer than
+    /// [`DEFAULT_MAX_FRAME_LEN`].
+    pub fn new(inner: R) -> FramedRead<R> {
+        FramedRead::with_max_frame_len(inner, DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Reads frames from `inner`, rejecting messages longer than
+    /// `max_frame_len`.
+    pub fn with_max_frame_len(inner: R, max_frame_len: usize) -> FramedRead<R> {
+        FramedRead { inner, max_frame_len }
+    }
+
+    /// Reads the next frame, or returns `None` if the stream ends before it.
+    ///
+    /// A stream ending within a frame is an `UnexpectedEof` error, and a
+    /// message over the limit an `InvalidData` error.
+    pub fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut header = [0_u8; HEADER_LEN];
+        let mut filled = 0;
+        while filled < HEADER_LEN {
+            match self.inner.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => {
+                    return Err(io::const_io_error!(
+                        io::ErrorKind::UnexpectedEof,
+                        "stream ended within a frame header",
+                    ));
+                }
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let compressed = match header[0] {
+            0 => false,
+            1 => true,
+            _ => {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::InvalidData,
+                    "invalid frame compression flag",
+                ));
+            }
+        };
+        let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        if len > self.max_frame_len {
+            return Err(io::const_io_error!(io::ErrorKind::InvalidData, "frame is too long"));
+        }
+
+        let mut message = vec![0; len];
+        self.inner.read_exact(&mut message)?;
+        Ok(Some(Frame { compressed, message }))
+    }
+
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this `FramedRead`, returning the underlying stream.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Iterator for FramedRead<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<io::Result<Frame>> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Writes frames to a stream.
+#[derive(Debug)]
+pub struct FramedWrite<W> {
+    inner: W,
+}
+
+impl<W: Write> FramedWrite<W> {
+    /// Writes frames to `inner`.
+    pub fn new(inner: W) -> FramedWrite<W> {
+        FramedWrite { inner }
+    }
+
+    /// Writes `frame`.
+    pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.write(frame.compressed, &frame.message)
+    }
+
+    /// Writes `message` in an uncompressed frame.
+    pub fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        self.write(false, message)
+    }
+
+    // The header and the message go out in a single write, so that wrapping
+    // an unbuffered stream doesn't split small frames.
+    fn write(&mut self, compressed: bool, message: &[u8]) -> io::Result<()> {
+        let len = u32::try_from(message.len()).map_err(|_| {
+            io::const_io_error!(io::ErrorKind::InvalidInput, "message is too long to frame")
+        })?;
+        let mut buf = Vec::with_capacity(HEADER_LEN + message.len());
+        buf.push(compressed as u8);
+        buf.extend_from_slice(&len.to_be_bytes());
+        buf.extend_from_slice(message);
+        self.inner.write_all(&buf)
+    }
+
+    /// Flushes the underlying stream.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Unwraps this `FramedWrite`, returning the underlying stream.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Appends the varint encoding of `value` to `buf`.
+pub fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Decodes the varint at the start of `buf`, returning it along with the
+/// number of bytes it took.
+///
+/// Returns `None` if `buf` ends within the varint, or if it's longer than
+/// [`MAX_VARINT_LEN`] bytes or overflows a `u64`.
+pub fn decode_varint(buf: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0_u64;
+    for (i, &byte) in buf.iter().take(MAX_VARINT_LEN).enumerate() {
+        // The tenth byte only holds the top bit.
+        if i == MAX_VARINT_LEN - 1 && byte > 1 {
+            return None;
+        }
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Returns the length of the varint encoding of `value`.
+pub fn encoded_len_varint(value: u64) -> usize {
+    // One byte per started group of 7 bits, with at least one byte.
+    let bits = 64 - (value | 1).leading_zeros() as usize;
+    (bits + 6) / 7
+}
+
+/// Maps a signed integer to an unsigned one, encoded by a short varint if
+/// the integer is close to 0, as protobuf's `sint64` does.
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Reverses [`zigzag_encode`].
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
@@ -24,6 +24,9 @@
 //!
 //! * [`TcpListener`] and [`TcpStream`] provide functionality for communication over TCP
 //! * [`UdpSocket`] provides functionality for communication over UDP
+//! * [`UnixListener`], [`UnixStream`] and [`UnixDatagram`] provide communication with
+//!   co-located host processes over Unix domain sockets; they are re-exported from
+//!   [`os::unix::net`](crate::os::unix::net)
 //! * [`IpAddr`] represents IP addresses of either IPv4 or IPv6; [`Ipv4Addr`] and
 //!   [`Ipv6Addr`] are respectively IPv4 and IPv6 addresses
 //! * [`SocketAddr`] represents socket addresses of either IPv4 or IPv6; [`SocketAddrV4`]
@@ -38,16 +41,33 @@ pub use self::addr::{SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
 pub use self::ip::{IpAddr, Ipv4Addr, Ipv6Addr, Ipv6MulticastScope};
 pub use self::parser::AddrParseError;
 #[cfg(feature = "net")]
+pub use self::poll::{poll, Events, PollFd};
+#[cfg(feature = "net")]
+pub use self::resolve::{lookup_host, set_resolver, HostResolver, PinnedResolver, Resolver};
+#[cfg(feature = "net")]
 pub use self::tcp::IntoIncoming;
 #[cfg(feature = "net")]
-pub use self::tcp::{Incoming, TcpListener, TcpStream};
+pub use self::tcp::{set_deadline_clock, Incoming, TcpListener, TcpStream};
 #[cfg(feature = "net")]
 pub use self::udp::UdpSocket;
+#[cfg(feature = "net")]
+pub use crate::os::unix::net::{UnixDatagram, UnixListener, UnixStream};
 
 mod addr;
+pub mod framed;
 mod ip;
 mod parser;
 #[cfg(feature = "net")]
+mod poll;
+#[cfg(feature = "net")]
+pub mod pool;
+#[cfg(feature = "net")]
+pub mod proxy;
+#[cfg(feature = "net")]
+pub mod ratelimit;
+#[cfg(feature = "net")]
+mod resolve;
+#[cfg(feature = "net")]
 mod tcp;
 #[cfg(feature = "net")]
 mod udp;
@@ -0,0 +1,170 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+use crate::fmt;
+use crate::io;
+use crate::ops::{BitOr, BitOrAssign};
+use crate::os::unix::io::{AsRawFd, RawFd};
+use crate::sys::net as net_imp;
+use crate::time::Duration;
+
+use sgx_libc as libc;
+
+/// A set of readiness events of a socket, as waited for and reported by
+/// [`poll`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Events(libc::c_short);
+
+impl Events {
+    /// No events.
+    pub const NONE: Events = Events(0);
+    /// Data can be read without blocking.
+    pub const READABLE: Events = Events(libc::POLLIN);
+    /// Data can be written without blocking.
+    pub const WRITABLE: Events = Events(libc::POLLOUT);
+    /// An error is pending on the socket; only ever reported.
+    pub const ERROR: Events = Events(libc::POLLERR);
+    /// The peer hung up; only ever reported.
+    pub const HANGUP: Events = Events(libc::POLLHUP);
+    /// The descriptor isn't open; only ever reported.
+    pub const INVALID: Events = Events(libc::POLLNVAL);
+
+    /// Returns `true` if all events of `other` are in `self`.
+    pub fn contains(self, other: Events) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if there are no events.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for Events {
+    type Output = Events;
+
+    fn bitor(self, other: Events) -> Events {
+        Events(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Events {
+    fn bitor_assign(&mut self, other: Events) {
+        self.0 |= other.0;
+    }
+}
+
+impl fmt::Debug for Events {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const NAMES: [(Events, &str); 5] = [
+            (Events::READABLE, "READABLE"),
+            (Events::WRITABLE, "WRITABLE"),
+            (Events::ERROR, "ERROR"),
+            (Events::HANGUP, "HANGUP"),
+            (Events::INVALID, "INVALID"),
+        ];
+        let mut set = f.debug_set();
+        for (events, name) in NAMES {
+            if self.contains(events) {
+                set.entry(&format_args!("{}", name));
+            }
+        }
+        set.finish()
+    }
+}
+
+/// A socket to [`poll`], along with the events to wait for and, once polled,
+/// the events which occurred.
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct PollFd(libc::pollfd);
+
+impl PollFd {
+    /// Waits for `events` on `socket`.
+    ///
+    /// The socket has to outlive the `poll` calls, or an unrelated descriptor
+    /// may be polled instead.
+    pub fn new<S: AsRawFd>(socket: &S, events: Events) -> PollFd {
+        PollFd::from_raw_fd(socket.as_raw_fd(), events)
+    }
+
+    /// Waits for `events` on the raw descriptor `fd`.
+    pub fn from_raw_fd(fd: RawFd, events: Events) -> PollFd {
+        PollFd(libc::pollfd { fd, events: events.0, revents: 0 })
+    }
+
+    /// Returns the polled descriptor.
+    pub fn fd(&self) -> RawFd {
+        self.0.fd
+    }
+
+    /// Returns the events waited for.
+    pub fn events(&self) -> Events {
+        Events(self.0.events)
+    }
+
+    /// Changes the events waited for.
+    pub fn set_events(&mut self, events: Events) {
+        self.0.events = events.0;
+    }
+
+    /// Returns the events which occurred during the last `poll`.
+    pub fn revents(&self) -> Events {
+        Events(self.0.revents)
+    }
+}
+
+impl fmt::Debug for PollFd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PollFd")
+            .field("fd", &self.fd())
+            .field("events", &self.events())
+            .field("revents", &self.revents())
+            .finish()
+    }
+}
+
+/// Waits until at least one of `fds` is ready, or `timeout` elapses, through
+/// a single `poll` ocall.
+///
+/// Returns the number of sockets with events, which are then available from
+/// [`PollFd::revents`], or 0 if the timeout elapsed. A `timeout` of `None`
+/// waits forever, and a zero `timeout` returns immediately.
+///
+/// Together with [`TcpStream::set_nonblocking`], this lets one thread serve
+/// many connections: poll them all, then read from or write to the ready
+/// ones until they fail with [`io::ErrorKind::WouldBlock`].
+///
+/// [`TcpStream::set_nonblocking`]: crate::net::TcpStream::set_nonblocking
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::net::{poll, Events, PollFd, TcpListener};
+///
+/// let listener = TcpListener::bind("127.0.0.1:8080")?;
+/// listener.set_nonblocking(true)?;
+/// let mut fds = [PollFd::new(&listener, Events::READABLE)];
+/// if poll(&mut fds, None)? > 0 && fds[0].revents().contains(Events::READABLE) {
+///     let (_stream, _addr) = listener.accept()?;
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn poll(fds: &mut [PollFd], timeout: Option<Duration>) -> io::Result<usize> {
+    // SAFETY: `PollFd` is a transparent wrapper of `pollfd`.
+    let fds = unsafe { &mut *(fds as *mut [PollFd] as *mut [libc::pollfd]) };
+    net_imp::poll(fds, timeout)
+}
@@ -0,0 +1,215 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! A pool of attested connections.
+//!
+//! Attesting a peer costs a quote verification per handshake, which adds up
+//! when an enclave talks to the same peers over and over. [`AttestedPool`]
+//! keeps connections open once their peer has been attested, keyed by the
+//! peer's MRENCLAVE, and hands them out again instead of connecting anew.
+
+use crate::collections::HashMap;
+use crate::fmt;
+use crate::io;
+use crate::ops::{Deref, DerefMut};
+use crate::sync::SgxMutex;
+use crate::time::{Duration, Instant};
+#[cfg(not(feature = "untrusted_time"))]
+use crate::untrusted::time::InstantEx;
+
+use sgx_types::{sgx_measurement_t, SGX_HASH_SIZE};
+
+/// A connection to an attested peer, e.g. an RA-TLS stream.
+pub trait AttestedConnection {
+    /// Returns the MRENCLAVE the peer was attested with when the connection
+    /// was established.
+    fn peer_mr_enclave(&self) -> sgx_measurement_t;
+
+    /// Returns whether the connection is still usable.
+    ///
+    /// Called before an idle connection is handed out again; unhealthy
+    /// connections are dropped.
+    fn is_healthy(&mut self) -> bool {
+        true
+    }
+}
+
+type PeerKey = [u8; SGX_HASH_SIZE];
+
+struct Idle<C> {
+    conn: C,
+    since: Instant,
+}
+
+/// A pool of connections to attested peers, keyed by the peers' MRENCLAVE.
+///
+/// Connections are checked out with [`get`](AttestedPool::get) and go back
+/// to the pool when the returned guard is dropped. Idle connections older
+/// than the idle timeout are dropped on checkout and by
+/// [`reap`](AttestedPool::reap).
+pub struct AttestedPool<C> {
+    idle: SgxMutex<HashMap<PeerKey, Vec<Idle<C>>>>,
+    max_idle_per_peer: usize,
+    idle_timeout: Duration,
+}
+
+impl<C: AttestedConnection> AttestedPool<C> {
+    /// Creates an empty pool, keeping up to `max_idle_per_peer` idle
+    /// connections per peer for up to `idle_timeout` each.
+    pub fn new(max_idle_per_peer: usize, idle_timeout: Duration) -> AttestedPool<C> {
+        AttestedPool { idle: SgxMutex::new(HashMap::new()), max_idle_per_peer, idle_timeout }
+    }
+
+    /// Checks out a connection to the peer with MRENCLAVE `mr_enclave`.
+    ///
+    /// The most recently used healthy idle connection is reused if there is
+    /// one. Otherwise `connect` establishes and attests a new connection,
+    /// which is rejected with `PermissionDenied` if its peer has another
+    /// MRENCLAVE.
+    pub fn get<F>(&self, mr_enclave: &sgx_measurement_t, connect: F) -> io::Result<Pooled<'_, C>>
+    where
+        F: FnOnce() -> io::Result<C>,
+    {
+        while let Some(mut conn) = self.pop_idle(&mr_enclave.m) {
+            if conn.is_healthy() {
+                return Ok(Pooled { pool: self, conn: Some(conn) });
+            }
+        }
+
+        let conn = connect()?;
+        if conn.peer_mr_enclave().m != mr_enclave.m {
+            return Err(io::const_io_error!(
+                io::ErrorKind::PermissionDenied,
+                "peer was attested with another MRENCLAVE",
+            ));
+        }
+        Ok(Pooled { pool: self, conn: Some(conn) })
+    }
+
+    /// Returns `conn` to the pool, dropping it if its peer has as many idle
+    /// connections as allowed already.
+    pub fn put(&self, conn: C) {
+        let key = conn.peer_mr_enclave().m;
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.entry(key).or_insert_with(Vec::new);
+        if conns.len() < self.max_idle_per_peer {
+            conns.push(Idle { conn, since: Instant::now() });
+        }
+    }
+
+    /// Drops the idle connections older than the idle timeout, returning how
+    /// many were dropped.
+    pub fn reap(&self) -> usize {
+        let mut idle = self.idle.lock().unwrap();
+        let mut reaped = 0;
+        for conns in idle.values_mut() {
+            let before = conns.len();
+            conns.retain(|idle| idle.since.elapsed() < self.idle_timeout);
+            reaped += before - conns.len();
+        }
+        idle.retain(|_, conns| !conns.is_empty());
+        reaped
+    }
+
+    /// Returns the number of idle connections.
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap().values().map(Vec::len).sum()
+    }
+
+    /// Drops all the idle connections.
+    pub fn clear(&self) {
+        self.idle.lock().unwrap().clear();
+    }
+
+    /// Takes the most recent idle connection to `key` which hasn't timed out,
+    /// dropping the timed out ones.
+    fn pop_idle(&self, key: &PeerKey) -> Option<C> {
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.get_mut(key)?;
+        let conn = loop {
+            match conns.pop() {
+                Some(idle) if idle.since.elapsed() < self.idle_timeout => break Some(idle.conn),
+                // The older connections have been idle even longer.
+                Some(_) => {
+                    conns.clear();
+                    break None;
+                }
+                None => break None,
+            }
+        };
+        if conns.is_empty() {
+            idle.remove(key);
+        }
+        conn
+    }
+}
+
+impl<C> fmt::Debug for AttestedPool<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttestedPool")
+            .field("max_idle_per_peer", &self.max_idle_per_peer)
+            .field("idle_timeout", &self.idle_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A connection checked out of an [`AttestedPool`], returned to it when
+/// dropped.
+pub struct Pooled<'a, C: AttestedConnection> {
+    pool: &'a AttestedPool<C>,
+    conn: Option<C>,
+}
+
+impl<C: AttestedConnection> Pooled<'_, C> {
+    /// Takes the connection out of the pool for good.
+    pub fn detach(mut self) -> C {
+        self.conn.take().unwrap()
+    }
+
+    /// Drops the connection instead of returning it to the pool, e.g. after
+    /// an I/O error left it in an unknown state.
+    pub fn discard(mut self) {
+        self.conn = None;
+    }
+}
+
+impl<C: AttestedConnection> Deref for Pooled<'_, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl<C: AttestedConnection> DerefMut for Pooled<'_, C> {
+    fn deref_mut(&mut self) -> &mut C {
+        self.conn.as_mut().unwrap()
+    }
+}
+
+impl<C: AttestedConnection> Drop for Pooled<'_, C> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.put(conn);
+        }
+    }
+}
+
+impl<C: AttestedConnection + fmt::Debug> fmt::Debug for Pooled<'_, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Pooled").field(&self.conn).finish()
+    }
+}
@@ -0,0 +1,241 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! Connection and request rate limiting.
+//!
+//! A [`RateLimiter`] enforces a global rate and a per-source-address rate
+//! with token buckets kept in the enclave, so that throttling doesn't depend
+//! on the host. Source addresses are reported by the host though; the global
+//! limit holds whatever the host reports, the per-address limits only if it
+//! reports the real peers.
+
+use crate::collections::HashMap;
+use crate::fmt;
+use crate::io;
+use crate::net::{IpAddr, TcpStream};
+use crate::sync::SgxMutex;
+use crate::time::{Duration, Instant};
+#[cfg(not(feature = "untrusted_time"))]
+use crate::untrusted::time::InstantEx;
+
+/// A rate: `burst` events at once, refilled over `period`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rate {
+    burst: u32,
+    period: Duration,
+}
+
+impl Rate {
+    /// Allows `burst` events per `period`, all of which may happen at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `burst` or `period` is zero.
+    pub fn new(burst: u32, period: Duration) -> Rate {
+        assert!(burst > 0 && period > Duration::ZERO, "rate must be positive");
+        Rate { burst, period }
+    }
+
+    /// Allows `burst` events per second.
+    pub fn per_second(burst: u32) -> Rate {
+        Rate::new(burst, Duration::from_secs(1))
+    }
+
+    /// Allows `burst` events per minute.
+    pub fn per_minute(burst: u32) -> Rate {
+        Rate::new(burst, Duration::from_secs(60))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn full(rate: &Rate, now: Instant) -> TokenBucket {
+        TokenBucket { tokens: rate.burst as f64, last: now }
+    }
+
+    fn refill(&mut self, rate: &Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last);
+        let refill = elapsed.as_secs_f64() * rate.burst as f64 / rate.period.as_secs_f64();
+        self.tokens = (self.tokens + refill).min(rate.burst as f64);
+        self.last = now;
+    }
+
+    fn is_full(&self, rate: &Rate) -> bool {
+        self.tokens >= rate.burst as f64
+    }
+}
+
+struct Buckets {
+    global: Option<TokenBucket>,
+    per_source: HashMap<IpAddr, TokenBucket>,
+}
+
+/// Token-bucket rate limiter, with a global and a per-source-address rate.
+///
+/// [`check`](RateLimiter::check) takes a token for an event, such as a
+/// connection or a request, from both buckets of its source.
+/// [`incoming`](RateLimiter::incoming) applies it to accepted connections.
+pub struct RateLimiter {
+    global: Option<Rate>,
+    per_source: Option<Rate>,
+    max_sources: usize,
+    buckets: SgxMutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter with no limits, tracking up to 10000 source
+    /// addresses.
+    pub fn new() -> RateLimiter {
+        RateLimiter {
+            global: None,
+            per_source: None,
+            max_sources: 10_000,
+            buckets: SgxMutex::new(Buckets { global: None, per_source: HashMap::new() }),
+        }
+    }
+
+    /// Limits the events from all sources together to `rate`.
+    pub fn global(mut self, rate: Rate) -> RateLimiter {
+        self.global = Some(rate);
+        self
+    }
+
+    /// Limits the events from each source address to `rate`.
+    pub fn per_source(mut self, rate: Rate) -> RateLimiter {
+        self.per_source = Some(rate);
+        self
+    }
+
+    /// Tracks up to `max_sources` source addresses.
+    ///
+    /// When more sources are active, the buckets which have refilled are
+    /// forgotten, and events from new sources are rejected if none has.
+    pub fn max_sources(mut self, max_sources: usize) -> RateLimiter {
+        self.max_sources = max_sources;
+        self
+    }
+
+    /// Takes a token for an event from `source`, returning whether the event
+    /// is allowed.
+    pub fn check(&self, source: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let mut global = match self.global {
+            Some(ref rate) => {
+                let mut bucket = buckets.global.unwrap_or_else(|| TokenBucket::full(rate, now));
+                bucket.refill(rate, now);
+                if bucket.tokens < 1.0 {
+                    buckets.global = Some(bucket);
+                    return false;
+                }
+                Some(bucket)
+            }
+            None => None,
+        };
+
+        if let Some(ref rate) = self.per_source {
+            if !buckets.per_source.contains_key(&source)
+                && buckets.per_source.len() >= self.max_sources
+            {
+                buckets.per_source.retain(|_, bucket| {
+                    bucket.refill(rate, now);
+                    !bucket.is_full(rate)
+                });
+                if buckets.per_source.len() >= self.max_sources {
+                    return false;
+                }
+            }
+            let bucket =
+                buckets.per_source.entry(source).or_insert_with(|| TokenBucket::full(rate, now));
+            bucket.refill(rate, now);
+            if bucket.tokens < 1.0 {
+                return false;
+            }
+            bucket.tokens -= 1.0;
+        }
+
+        if let Some(ref mut bucket) = global {
+            bucket.tokens -= 1.0;
+        }
+        buckets.global = global;
+        true
+    }
+
+    /// Wraps `incoming`, e.g. [`TcpListener::incoming`], so that connections
+    /// over the limits are closed as soon as they are accepted.
+    ///
+    /// [`TcpListener::incoming`]: crate::net::TcpListener::incoming
+    pub fn incoming<I>(&self, incoming: I) -> RateLimited<'_, I>
+    where
+        I: Iterator<Item = io::Result<TcpStream>>,
+    {
+        RateLimited { incoming, limiter: self }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        RateLimiter::new()
+    }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("global", &self.global)
+            .field("per_source", &self.per_source)
+            .field("max_sources", &self.max_sources)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An iterator over the connections accepted within the limits of a
+/// [`RateLimiter`].
+///
+/// This `struct` is created by the [`RateLimiter::incoming`] method.
+#[derive(Debug)]
+pub struct RateLimited<'a, I> {
+    incoming: I,
+    limiter: &'a RateLimiter,
+}
+
+impl<I> Iterator for RateLimited<'_, I>
+where
+    I: Iterator<Item = io::Result<TcpStream>>,
+{
+    type Item = io::Result<TcpStream>;
+
+    fn next(&mut self) -> Option<io::Result<TcpStream>> {
+        loop {
+            let stream = match self.incoming.next()? {
+                Ok(stream) => stream,
+                Err(e) => return Some(Err(e)),
+            };
+            // Connections whose peer can't be told are dropped too.
+            if let Ok(peer) = stream.peer_addr() {
+                if self.limiter.check(peer.ip()) {
+                    return Some(Ok(stream));
+                }
+            }
+        }
+    }
+}
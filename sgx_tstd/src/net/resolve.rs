@@ -0,0 +1,207 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! Host name resolution.
+//!
+//! Names are resolved by the host, through a `getaddrinfo` ocall, so the
+//! addresses they resolve to are only as trustworthy as the host: it can
+//! point a connection to any server, including one on its own network. TLS
+//! with certificate verification still fails against the wrong server, but a
+//! [`Resolver`] checks the addresses before connecting at all, and can
+//! replace the host lookup entirely, e.g. by a DNSSEC-validating resolver
+//! talking to a trusted server.
+
+use crate::collections::HashMap;
+use crate::convert::TryFrom;
+use crate::fmt;
+use crate::io;
+use crate::net::{IpAddr, SocketAddr};
+use crate::sync::{Arc, SgxThreadRwLock};
+use crate::sys_common::net::LookupHost;
+
+/// Resolves host names to socket addresses.
+///
+/// Installed with [`set_resolver`], it resolves the names passed to
+/// [`ToSocketAddrs`](crate::net::ToSocketAddrs), and so to
+/// `TcpStream::connect("host:port")` and the like.
+pub trait Resolver: Send + Sync {
+    /// Returns the addresses of `host`, with port `port`.
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolves `host` through the host's `getaddrinfo`, ignoring any installed
+/// resolver.
+pub fn lookup_host(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let addrs = LookupHost::try_from((host, port))?;
+    Ok(addrs
+        .map(|mut addr| {
+            addr.set_port(port);
+            addr
+        })
+        .collect())
+}
+
+/// The resolver used when none is installed, asking the host.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HostResolver;
+
+impl Resolver for HostResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        lookup_host(host, port)
+    }
+}
+
+/// Returns whether `ip` is an address the host network shouldn't be able to
+/// redirect public names to: loopback, private, link-local, shared,
+/// unspecified or broadcast.
+fn is_internal(ip: &IpAddr) -> bool {
+    match *ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_shared()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal(&IpAddr::V4(ip)),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+            }
+        },
+    }
+}
+
+/// Lower-cases `host` and strips the final dot of a fully qualified name.
+fn normalize(host: &str) -> String {
+    host.strip_suffix('.').unwrap_or(host).to_ascii_lowercase()
+}
+
+/// A resolver answering from pinned addresses.
+///
+/// Pinned names always resolve to their pinned addresses. Other names are
+/// passed to the fallback resolver if there is one, and rejected with
+/// `PermissionDenied` otherwise. Fallback answers can be restricted to public
+/// addresses, so that the host can't redirect a name to an internal service.
+pub struct PinnedResolver {
+    pins: HashMap<String, Vec<IpAddr>>,
+    fallback: Option<Arc<dyn Resolver>>,
+    public_only: bool,
+}
+
+impl PinnedResolver {
+    /// Creates a resolver with no pinned name and no fallback.
+    pub fn new() -> PinnedResolver {
+        PinnedResolver { pins: HashMap::new(), fallback: None, public_only: false }
+    }
+
+    /// Pins `host` to `addrs`, replacing any previous pin.
+    pub fn pin<I>(&mut self, host: &str, addrs: I) -> &mut PinnedResolver
+    where
+        I: IntoIterator<Item = IpAddr>,
+    {
+        self.pins.insert(normalize(host), addrs.into_iter().collect());
+        self
+    }
+
+    /// Resolves names which aren't pinned with `resolver`, e.g.
+    /// [`HostResolver`].
+    pub fn fallback(&mut self, resolver: Arc<dyn Resolver>) -> &mut PinnedResolver {
+        self.fallback = Some(resolver);
+        self
+    }
+
+    /// Drops the internal addresses from fallback answers, failing if none
+    /// is left.
+    pub fn public_only(&mut self, public_only: bool) -> &mut PinnedResolver {
+        self.public_only = public_only;
+        self
+    }
+}
+
+impl Default for PinnedResolver {
+    fn default() -> PinnedResolver {
+        PinnedResolver::new()
+    }
+}
+
+impl Resolver for PinnedResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        if let Some(addrs) = self.pins.get(&normalize(host)) {
+            return Ok(addrs.iter().map(|&ip| SocketAddr::new(ip, port)).collect());
+        }
+
+        let fallback = self.fallback.as_ref().ok_or(io::const_io_error!(
+            io::ErrorKind::PermissionDenied,
+            "host name is not pinned"
+        ))?;
+        let mut addrs = fallback.resolve(host, port)?;
+        if self.public_only {
+            let resolved = addrs.len();
+            addrs.retain(|addr| !is_internal(&addr.ip()));
+            if addrs.is_empty() && resolved > 0 {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::PermissionDenied,
+                    "host name resolved only to internal addresses"
+                ));
+            }
+        }
+        Ok(addrs)
+    }
+}
+
+impl fmt::Debug for PinnedResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedResolver")
+            .field("pins", &self.pins)
+            .field("fallback", &self.fallback.is_some())
+            .field("public_only", &self.public_only)
+            .finish()
+    }
+}
+
+static RESOLVER_LOCK: SgxThreadRwLock = SgxThreadRwLock::new();
+// protected by `RESOLVER_LOCK`.
+static mut RESOLVER: Option<Arc<dyn Resolver>> = None;
+
+/// Installs `resolver` to resolve host names in the enclave, or goes back to
+/// asking the host if `None`, returning the previous resolver.
+pub fn set_resolver(resolver: Option<Arc<dyn Resolver>>) -> Option<Arc<dyn Resolver>> {
+    unsafe {
+        let _ = RESOLVER_LOCK.write();
+        let previous = crate::mem::replace(&mut RESOLVER, resolver);
+        let _ = RESOLVER_LOCK.write_unlock();
+        previous
+    }
+}
+
+/// Resolves `host` with the installed resolver.
+pub(super) fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let resolver = unsafe {
+        let _ = RESOLVER_LOCK.read();
+        let resolver = RESOLVER.clone();
+        let _ = RESOLVER_LOCK.read_unlock();
+        resolver
+    };
+    match resolver {
+        Some(resolver) => resolver.resolve(host, port),
+        None => lookup_host(host, port),
+    }
+}
@@ -61,6 +61,18 @@ use sgx_libc::c_int;
 /// ```
 pub struct TcpStream(net_imp::TcpStream);
 
+/// Installs the clock the read and write timeouts of [`TcpStream`]s are
+/// measured with, or removes it with `None`.
+///
+/// The clock returns the time since an origin of its choice, and must be one
+/// the host can't hold back, e.g. one derived from trusted time; it returns
+/// `None` when it can't tell the time, and timed calls then fail as if they
+/// timed out. Until a clock is installed, timeouts are measured with
+/// [`Instant`](crate::time::Instant), whose time comes from the host.
+pub fn set_deadline_clock(clock: Option<fn() -> Option<Duration>>) {
+    net_imp::set_deadline_clock(clock)
+}
+
 /// A TCP socket server, listening for connections.
 ///
 /// After creating a `TcpListener` by [`bind`]ing it to a socket address, it listens
@@ -143,6 +155,11 @@ impl TcpStream {
     /// the addresses result in a successful connection, the error returned from
     /// the last connection attempt (the last address) is returned.
     ///
+    /// If a proxy is installed with [`set_proxy`], the connection is
+    /// tunneled through it.
+    ///
+    /// [`set_proxy`]: crate::net::proxy::set_proxy
+    ///
     /// # Examples
     ///
     /// Open a TCP connection to `127.0.0.1:8080`:
@@ -174,6 +191,12 @@ impl TcpStream {
     /// }
     /// ```
     pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<TcpStream> {
+        super::proxy::connect(addr)
+    }
+
+    /// Opens a TCP connection to a remote host, bypassing the installed
+    /// proxy.
+    pub(crate) fn connect_direct<A: ToSocketAddrs>(addr: A) -> io::Result<TcpStream> {
         super::each_addr(addr, net_imp::TcpStream::connect).map(TcpStream)
     }
 
@@ -307,6 +330,13 @@ impl TcpStream {
     /// indefinitely. An [`Err`] is returned if the zero [`Duration`] is
     /// passed to this method.
     ///
+    /// The timeout is enforced in the enclave: each read waits for the socket
+    /// with the time left, and the time is checked again after every wake, so
+    /// the host can't stretch it by ignoring socket options or reporting
+    /// readiness that isn't there. The time is that of the clock installed
+    /// with [`set_deadline_clock`]; until one is, it's the host's, and the
+    /// host can stretch the timeout by holding its clock back.
+    ///
     /// # Platform-specific behavior
     ///
     /// Platforms may return a different error code whenever a read times out as
@@ -350,6 +380,13 @@ impl TcpStream {
     /// indefinitely. An [`Err`] is returned if the zero [`Duration`] is
     /// passed to this method.
     ///
+    /// The timeout is enforced in the enclave: each write waits for the socket
+    /// with the time left, and the time is checked again after every wake, so
+    /// the host can't stretch it by ignoring socket options or reporting
+    /// readiness that isn't there. The time is that of the clock installed
+    /// with [`set_deadline_clock`]; until one is, it's the host's, and the
+    /// host can stretch the timeout by holding its clock back.
+    ///
     /// # Platform-specific behavior
     ///
     /// Platforms may return a different error code whenever a write times out
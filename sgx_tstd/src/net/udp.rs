@@ -531,6 +531,71 @@ impl UdpSocket {
         self.0.multicast_loop_v6()
     }
 
+    /// Sets the value of the `IP_MULTICAST_IF` option for this socket.
+    ///
+    /// Selects the local interface, by its address, that outgoing multicast
+    /// packets are sent from. [`Ipv4Addr::UNSPECIFIED`] lets the system pick
+    /// one by the routing table.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::net::{Ipv4Addr, UdpSocket};
+    ///
+    /// let socket = UdpSocket::bind("0.0.0.0:34254").expect("couldn't bind to address");
+    /// socket.set_multicast_if_v4(&Ipv4Addr::new(10, 0, 0, 1))
+    ///     .expect("set_multicast_if_v4 call failed");
+    /// ```
+    pub fn set_multicast_if_v4(&self, interface: &Ipv4Addr) -> io::Result<()> {
+        self.0.set_multicast_if_v4(interface)
+    }
+
+    /// Gets the value of the `IP_MULTICAST_IF` option for this socket.
+    ///
+    /// For more information about this option, see [`UdpSocket::set_multicast_if_v4`].
+    pub fn multicast_if_v4(&self) -> io::Result<Ipv4Addr> {
+        self.0.multicast_if_v4()
+    }
+
+    /// Sets the value of the `IPV6_MULTICAST_IF` option for this socket.
+    ///
+    /// Selects the local interface, by its index, that outgoing multicast
+    /// packets are sent from. 0 lets the system pick one.
+    pub fn set_multicast_if_v6(&self, interface: u32) -> io::Result<()> {
+        self.0.set_multicast_if_v6(interface)
+    }
+
+    /// Gets the value of the `IPV6_MULTICAST_IF` option for this socket.
+    ///
+    /// For more information about this option, see [`UdpSocket::set_multicast_if_v6`].
+    pub fn multicast_if_v6(&self) -> io::Result<u32> {
+        self.0.multicast_if_v6()
+    }
+
+    /// Sets the value of the `IPV6_MULTICAST_HOPS` option for this socket.
+    ///
+    /// Indicates the hop limit of outgoing multicast packets, the IPv6
+    /// counterpart of [`UdpSocket::set_multicast_ttl_v4`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::net::UdpSocket;
+    ///
+    /// let socket = UdpSocket::bind("[::]:34254").expect("couldn't bind to address");
+    /// socket.set_multicast_hops_v6(8).expect("set_multicast_hops_v6 call failed");
+    /// ```
+    pub fn set_multicast_hops_v6(&self, hops: u32) -> io::Result<()> {
+        self.0.set_multicast_hops_v6(hops)
+    }
+
+    /// Gets the value of the `IPV6_MULTICAST_HOPS` option for this socket.
+    ///
+    /// For more information about this option, see [`UdpSocket::set_multicast_hops_v6`].
+    pub fn multicast_hops_v6(&self) -> io::Result<u32> {
+        self.0.multicast_hops_v6()
+    }
+
     /// Sets the value for the `IP_TTL` option on this socket.
     ///
     /// This value sets the time-to-live field that is used in every packet sent
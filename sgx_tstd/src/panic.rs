@@ -20,10 +20,13 @@
 use crate::any::Any;
 use crate::collections;
 use crate::panicking;
-#[cfg(feature = "backtrace")]
-use crate::sync::atomic::{AtomicUsize, Ordering};
+use crate::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::string::String;
 use crate::sync::{SgxMutex, SgxRwLock};
 use crate::thread::Result;
+#[cfg(feature = "backtrace")]
+use crate::vec::Vec;
+use sgx_types::{sgx_panic_info_t, sgx_status_t};
 
 #[doc(hidden)]
 #[allow_internal_unstable(libstd_sys_internals, const_format_args, core_panic)]
@@ -50,6 +53,10 @@ pub use core::panic::panic_2021;
 
 pub use crate::panicking::{set_hook, take_hook};
 
+pub use crate::panicking::{add_hook, remove_hook, HookId};
+
+pub use crate::panicking::{register_secret, unregister_secret, zeroize_secrets, SecretId};
+
 pub use crate::panicking::update_hook;
 
 pub use core::panic::{Location, PanicInfo};
@@ -140,6 +147,121 @@ pub fn catch_unwind<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> Result<R> {
     unsafe { panicking::r#try(f) }
 }
 
+/// Runs the body of an ecall, turning a panic into a status code.
+///
+/// A panic escaping an ecall would otherwise abort the enclave, and the host
+/// would only see `SGX_ERROR_ENCLAVE_CRASHED`. `catch_ecall` instead catches
+/// it, wipes the buffers registered with [`register_secret`] and returns
+/// `SGX_ERROR_ECALL_PANICKED`, leaving the enclave usable for further
+/// ecalls. The panic, including its backtrace, is recorded as usual and can
+/// be retrieved with [`take_panic_info`], e.g. to hand it to the host.
+///
+/// Like [`catch_unwind`], this only catches unwinding panics.
+///
+/// [`register_secret`]: ./fn.register_secret.html
+/// [`take_panic_info`]: ./fn.take_panic_info.html
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::panic;
+/// use sgx_types::sgx_status_t;
+///
+/// #[no_mangle]
+/// pub extern "C" fn ecall_process(input: *const u8, len: usize) -> sgx_status_t {
+///     panic::catch_ecall(|| {
+///         // ...
+///         sgx_status_t::SGX_SUCCESS
+///     })
+/// }
+/// ```
+pub fn catch_ecall<F: FnOnce() -> sgx_status_t + UnwindSafe>(f: F) -> sgx_status_t {
+    match catch_unwind(f) {
+        Ok(status) => status,
+        Err(_) => {
+            zeroize_secrets();
+            sgx_status_t::SGX_ERROR_ECALL_PANICKED
+        }
+    }
+}
+
+// Set once any `CircuitBreaker` trips, never cleared.
+static POISONED: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether a [`CircuitBreaker`] has tripped and poisoned the enclave.
+///
+/// [`CircuitBreaker`]: ./struct.CircuitBreaker.html
+pub fn is_poisoned() -> bool {
+    POISONED.load(Ordering::SeqCst)
+}
+
+/// Limits the number of panics of an ecall entry point.
+///
+/// A panic which can be triggered from the outside is a way to probe the
+/// enclave, and catching it with [`catch_ecall`] lets the prober try again
+/// as often as they want. A `CircuitBreaker` counts the panics of the ecall
+/// it guards, and once they reach its threshold it poisons the whole
+/// enclave: from then on every ecall guarded by any `CircuitBreaker` is
+/// rejected with `SGX_ERROR_ENCLAVE_POISONED` without running. The enclave
+/// stays poisoned until it is destroyed.
+///
+/// [`catch_ecall`]: ./fn.catch_ecall.html
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::panic::CircuitBreaker;
+/// use sgx_types::sgx_status_t;
+///
+/// #[no_mangle]
+/// pub extern "C" fn ecall_process(input: *const u8, len: usize) -> sgx_status_t {
+///     static BREAKER: CircuitBreaker = CircuitBreaker::new(3);
+///
+///     BREAKER.call(|| {
+///         // ...
+///         sgx_status_t::SGX_SUCCESS
+///     })
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: usize,
+    panics: AtomicUsize,
+}
+
+impl CircuitBreaker {
+    /// Creates a circuit breaker tripping after `threshold` panics.
+    ///
+    /// A threshold of zero is treated as one.
+    pub const fn new(threshold: usize) -> CircuitBreaker {
+        CircuitBreaker { threshold, panics: AtomicUsize::new(0) }
+    }
+
+    /// Runs the body of an ecall like [`catch_ecall`], unless the enclave is
+    /// poisoned.
+    ///
+    /// [`catch_ecall`]: ./fn.catch_ecall.html
+    pub fn call<F: FnOnce() -> sgx_status_t + UnwindSafe>(&self, f: F) -> sgx_status_t {
+        if is_poisoned() {
+            return sgx_status_t::SGX_ERROR_ENCLAVE_POISONED;
+        }
+
+        let status = catch_ecall(f);
+        if status == sgx_status_t::SGX_ERROR_ECALL_PANICKED {
+            let panics = self.panics.fetch_add(1, Ordering::SeqCst) + 1;
+            if panics >= self.threshold {
+                POISONED.store(true, Ordering::SeqCst);
+            }
+        }
+        status
+    }
+
+    /// Returns the number of panics caught by this circuit breaker.
+    pub fn panics(&self) -> usize {
+        self.panics.load(Ordering::SeqCst)
+    }
+}
+
 /// Triggers a panic without invoking the panic hook.
 ///
 /// This is designed to be used in conjunction with [`catch_unwind`] to, for
@@ -204,6 +326,112 @@ pub fn always_abort() {
     crate::panicking::panic_count::set_always_abort();
 }
 
+/// Error code of panics which weren't raised by [`panic_with_code`].
+///
+/// [`panic_with_code`]: ./fn.panic_with_code.html
+pub const DEFAULT_PANIC_CODE: u32 = 1;
+
+/// Panics the current thread with an application error code.
+///
+/// The code ends up in the [`EnclavePanicInfo`] recorded for this panic,
+/// which lets the untrusted host tell failures apart without parsing panic
+/// messages.
+///
+/// [`EnclavePanicInfo`]: ./struct.EnclavePanicInfo.html
+#[inline]
+#[track_caller]
+pub fn panic_with_code<M: Into<String>>(code: u32, msg: M) -> ! {
+    crate::panicking::begin_panic(panicking::CodedPanic { code, message: msg.into() });
+}
+
+/// A description of a panic, recorded by the panic runtime.
+///
+/// The panic runtime records the first panic of every thread, before the
+/// panic hooks run, and keeps it until it is retrieved with
+/// [`take_panic_info`]. Unlike `PanicInfo` it owns all of its data, so it
+/// can outlive the panic, and [`to_raw`] turns it into an
+/// `sgx_panic_info_t` which can be handed to the untrusted host as the `out`
+/// parameter of an ecall.
+///
+/// [`take_panic_info`]: ./fn.take_panic_info.html
+/// [`to_raw`]: #method.to_raw
+#[derive(Debug, Clone)]
+pub struct EnclavePanicInfo {
+    /// The code passed to [`panic_with_code`], or [`DEFAULT_PANIC_CODE`].
+    ///
+    /// [`panic_with_code`]: ./fn.panic_with_code.html
+    /// [`DEFAULT_PANIC_CODE`]: ./constant.DEFAULT_PANIC_CODE.html
+    pub code: u32,
+    /// The panic message, or `Box<dyn Any>` if the payload isn't a string.
+    pub message: String,
+    /// The source file the panic originated from.
+    pub file: String,
+    /// The line the panic originated from.
+    pub line: u32,
+    /// The column the panic originated from.
+    pub column: u32,
+    /// The backtrace of the panic, empty if backtraces are disabled or if the
+    /// thread was already panicking. Only its instruction pointers are
+    /// captured when panicking, and they are symbolicated by
+    /// [`take_panic_info`].
+    ///
+    /// [`take_panic_info`]: ./fn.take_panic_info.html
+    #[cfg(feature = "backtrace")]
+    pub backtrace: Vec<crate::backtrace::OwnedFrame>,
+}
+
+impl EnclavePanicInfo {
+    /// Converts this description into its C representation.
+    ///
+    /// The message and file name are truncated to the sizes of the
+    /// `sgx_panic_info_t` buffers, as is the backtrace. Frames are stored as
+    /// offsets from the enclave base so that the enclave layout isn't
+    /// disclosed to the host.
+    pub fn to_raw(&self) -> sgx_panic_info_t {
+        let mut raw = sgx_panic_info_t::default();
+        raw.code = self.code;
+        raw.line = self.line;
+        raw.column = self.column;
+        raw.message_len = copy_truncated(&mut raw.message, &self.message) as u32;
+        raw.file_len = copy_truncated(&mut raw.file, &self.file) as u32;
+
+        #[cfg(feature = "backtrace")]
+        {
+            let base = crate::enclave::get_enclave_base() as usize;
+            let mut last_ip = None;
+            // Inlined symbols share the instruction pointer of their frame.
+            for frame in &self.backtrace {
+                if raw.frame_count as usize == sgx_types::SGX_PANIC_MAX_FRAMES {
+                    break;
+                }
+                if last_ip.replace(frame.ip) == Some(frame.ip) {
+                    continue;
+                }
+                raw.frames[raw.frame_count as usize] = frame.ip.wrapping_sub(base) as u64;
+                raw.frame_count += 1;
+            }
+        }
+        raw
+    }
+}
+
+// Copies as much of `s` as fits into `buf` without splitting a character.
+fn copy_truncated(buf: &mut [u8], s: &str) -> usize {
+    let mut len = s.len().min(buf.len());
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    buf[..len].copy_from_slice(&s.as_bytes()[..len]);
+    len
+}
+
+/// Takes the description of the last panic of the current thread.
+///
+/// Returns `None` if the thread didn't panic since the last call.
+pub fn take_panic_info() -> Option<EnclavePanicInfo> {
+    panicking::take_panic_info()
+}
+
 /// The configuration for whether and how the default panic hook will capture
 /// and display the backtrace.
 #[cfg(feature = "backtrace")]
@@ -215,6 +443,17 @@ pub enum BacktraceStyle {
     Short,
     /// Prints a backtrace with all possible information.
     Full,
+    /// Prints a backtrace as a JSON document suitable for log collectors.
+    Json,
+    /// Prints a terser backtrace highlighted with ANSI escape codes when the
+    /// host's stderr is a terminal, and a plain `Short` one otherwise.
+    Colored,
+    /// Prints the raw instruction pointers of the frames, leaving
+    /// symbolication to be done later or outside of the enclave.
+    Raw,
+    /// Prints a terser backtrace on a single line, so that it fits in one
+    /// log record.
+    Compact,
     /// Disable collecting and displaying backtraces.
     Off,
 }
@@ -230,6 +469,10 @@ impl BacktraceStyle {
             BacktraceStyle::Short => 1,
             BacktraceStyle::Full => 2,
             BacktraceStyle::Off => 3,
+            BacktraceStyle::Json => 4,
+            BacktraceStyle::Colored => 5,
+            BacktraceStyle::Raw => 6,
+            BacktraceStyle::Compact => 7,
         }
     }
 
@@ -239,6 +482,10 @@ impl BacktraceStyle {
             1 => BacktraceStyle::Short,
             2 => BacktraceStyle::Full,
             3 => BacktraceStyle::Off,
+            4 => BacktraceStyle::Json,
+            5 => BacktraceStyle::Colored,
+            6 => BacktraceStyle::Raw,
+            7 => BacktraceStyle::Compact,
             _ => unreachable!(),
         })
     }
@@ -31,19 +31,26 @@ use crate::panic::BacktraceStyle;
 use core::panic::{BoxMeUp, Location, PanicInfo};
 
 use crate::any::Any;
+use crate::cell::RefCell;
 use crate::fmt;
 use crate::intrinsics;
 use crate::mem::{self, ManuallyDrop};
+use crate::panic::{EnclavePanicInfo, DEFAULT_PANIC_CODE};
+use crate::ptr;
+use crate::string::String;
 #[cfg(feature = "backtrace")]
-use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::atomic::AtomicBool;
+use crate::sync::atomic::{compiler_fence, Ordering};
 #[cfg(feature = "stdio")]
 use crate::sys::stdio::panic_output;
 #[cfg(feature = "backtrace")]
 use crate::sys_common::backtrace;
+use crate::sys_common::mutex::SgxThreadMutex;
 use crate::sys_common::rwlock::SgxThreadRwLock;
 #[cfg(feature = "stdio")]
 use crate::sys_common::thread_info;
 use crate::thread;
+use crate::vec::Vec;
 
 use sgx_trts::trts::rsgx_abort;
 
@@ -100,6 +107,19 @@ impl Hook {
 static HOOK_LOCK: SgxThreadRwLock = SgxThreadRwLock::new();
 static mut HOOK: Hook = Hook::Default;
 
+type ChainedHook = Box<dyn Fn(&PanicInfo<'_>) + 'static + Sync + Send>;
+
+// Hooks added with `add_hook`, in the order they run. Like `HOOK` they are
+// protected by `HOOK_LOCK`.
+static mut CHAINED_HOOKS: Vec<(HookId, ChainedHook)> = Vec::new();
+static mut NEXT_HOOK_ID: usize = 0;
+
+/// Identifies a panic hook registered with [`add_hook`].
+///
+/// [`add_hook`]: ./fn.add_hook.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HookId(usize);
+
 /// Registers a custom panic hook, replacing any that was previously registered.
 ///
 /// The panic hook is invoked when a thread panics, but before the panic runtime
@@ -271,6 +291,83 @@ where
     }
 }
 
+/// Registers an additional panic hook, to run after the ones already
+/// registered.
+///
+/// Unlike [`set_hook`], this doesn't replace anything: when a thread panics
+/// the hook installed with [`set_hook`] (or the default one) runs first,
+/// followed by every hook added with `add_hook`, in the order they were
+/// added. This lets independent components each react to a panic, say one
+/// logging it and another one wiping key material, without clobbering each
+/// other's hook.
+///
+/// The returned `HookId` can be passed to [`remove_hook`] to unregister the
+/// hook again.
+///
+/// [`set_hook`]: ./fn.set_hook.html
+/// [`remove_hook`]: ./fn.remove_hook.html
+///
+/// # Panics
+///
+/// Panics if called from a panicking thread.
+///
+/// # Examples
+///
+/// The following will print the normal panic message, then "Zeroizing" and
+/// then "Counting":
+///
+/// ```should_panic
+/// use std::panic;
+///
+/// panic::add_hook(Box::new(|_| println!("Zeroizing")));
+/// panic::add_hook(Box::new(|_| println!("Counting")));
+///
+/// panic!("Normal panic");
+/// ```
+pub fn add_hook(hook: Box<dyn Fn(&PanicInfo<'_>) + 'static + Sync + Send>) -> HookId {
+    if thread::panicking() {
+        panic!("cannot modify the panic hook from a panicking thread");
+    }
+
+    // SAFETY: `CHAINED_HOOKS` and `NEXT_HOOK_ID` can only be modified while
+    // holding write access to `HOOK_LOCK`.
+    unsafe {
+        let _guard = HOOK_LOCK.write();
+        let id = HookId(NEXT_HOOK_ID);
+        NEXT_HOOK_ID += 1;
+        CHAINED_HOOKS.push((id, hook));
+        let _ = HOOK_LOCK.write_unlock();
+        id
+    }
+}
+
+/// Unregisters a panic hook registered with [`add_hook`], returning it.
+///
+/// Returns `None` if the hook was already removed.
+///
+/// [`add_hook`]: ./fn.add_hook.html
+///
+/// # Panics
+///
+/// Panics if called from a panicking thread.
+pub fn remove_hook(id: HookId) -> Option<Box<dyn Fn(&PanicInfo<'_>) + 'static + Sync + Send>> {
+    if thread::panicking() {
+        panic!("cannot modify the panic hook from a panicking thread");
+    }
+
+    // SAFETY: `CHAINED_HOOKS` can only be modified while holding write
+    // access to `HOOK_LOCK`.
+    unsafe {
+        let _guard = HOOK_LOCK.write();
+        let hook = CHAINED_HOOKS
+            .iter()
+            .position(|(hook_id, _)| *hook_id == id)
+            .map(|index| CHAINED_HOOKS.remove(index).1);
+        let _ = HOOK_LOCK.write_unlock();
+        hook
+    }
+}
+
 #[cfg(not(feature = "stdio"))]
 fn default_hook(_info: &PanicInfo<'_>) {}
 
@@ -288,13 +385,7 @@ fn default_hook(info: &PanicInfo<'_>) {
     // The current implementation always returns `Some`.
     let location = info.location().unwrap();
 
-    let msg = match info.payload().downcast_ref::<&'static str>() {
-        Some(s) => *s,
-        None => match info.payload().downcast_ref::<String>() {
-            Some(s) => &s[..],
-            None => "Box<dyn Any>",
-        },
-    };
+    let msg = payload_as_str(info.payload());
     let thread = thread_info::current_thread();
     let name = thread.as_ref().and_then(|t| t.name()).unwrap_or("<unnamed>");
 
@@ -312,6 +403,23 @@ fn default_hook(info: &PanicInfo<'_>) {
                 Some(BacktraceStyle::Full) => {
                     drop(backtrace::print(err, crate::sys::backtrace::PrintFmt::Full))
                 }
+                Some(BacktraceStyle::Json) => {
+                    drop(backtrace::print(err, crate::sys::backtrace::PrintFmt::Json))
+                }
+                Some(BacktraceStyle::Colored) => {
+                    let format = if crate::sys::stdio::stderr_is_terminal() {
+                        crate::sys::backtrace::PrintFmt::Colored
+                    } else {
+                        crate::sys::backtrace::PrintFmt::Short
+                    };
+                    drop(backtrace::print(err, format))
+                }
+                Some(BacktraceStyle::Compact) => {
+                    drop(backtrace::print(err, crate::sys::backtrace::PrintFmt::Compact))
+                }
+                Some(BacktraceStyle::Raw) => {
+                    let _ = write!(err, "{}", crate::backtrace::RawBacktrace::capture());
+                }
                 Some(BacktraceStyle::Off) => {
                     if FIRST_PANIC.swap(false, Ordering::SeqCst) {
                         let _ = writeln!(
@@ -331,6 +439,151 @@ fn default_hook(info: &PanicInfo<'_>) {
     }
 }
 
+/// Identifies a buffer registered with [`register_secret`].
+///
+/// [`register_secret`]: ./fn.register_secret.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SecretId(usize);
+
+static SECRETS_LOCK: SgxThreadMutex = SgxThreadMutex::new();
+// Buffers wiped by `zeroize_secrets`, protected by `SECRETS_LOCK`.
+static mut SECRETS: Vec<(SecretId, *mut u8, usize)> = Vec::new();
+static mut NEXT_SECRET_ID: usize = 0;
+
+/// Registers a buffer to be zeroized when an ecall panics.
+///
+/// [`catch_ecall`] wipes every registered buffer after catching a panic, so
+/// that key material which was being worked on when the panic happened
+/// doesn't linger in enclave memory. The returned `SecretId` unregisters the
+/// buffer again through [`unregister_secret`].
+///
+/// [`catch_ecall`]: ./fn.catch_ecall.html
+/// [`unregister_secret`]: ./fn.unregister_secret.html
+///
+/// # Safety
+///
+/// `ptr` must be valid for writes of `len` bytes until the buffer is
+/// unregistered.
+pub unsafe fn register_secret(ptr: *mut u8, len: usize) -> SecretId {
+    // SAFETY: `SECRETS` and `NEXT_SECRET_ID` are only accessed while holding
+    // `SECRETS_LOCK`.
+    unsafe {
+        let _ = SECRETS_LOCK.lock();
+        let id = SecretId(NEXT_SECRET_ID);
+        NEXT_SECRET_ID += 1;
+        SECRETS.push((id, ptr, len));
+        let _ = SECRETS_LOCK.unlock();
+        id
+    }
+}
+
+/// Unregisters a buffer registered with [`register_secret`].
+///
+/// [`register_secret`]: ./fn.register_secret.html
+pub fn unregister_secret(id: SecretId) {
+    // SAFETY: `SECRETS` is only accessed while holding `SECRETS_LOCK`.
+    unsafe {
+        let _ = SECRETS_LOCK.lock();
+        SECRETS.retain(|(secret_id, _, _)| *secret_id != id);
+        let _ = SECRETS_LOCK.unlock();
+    }
+}
+
+/// Overwrites every buffer registered with [`register_secret`] with zeroes.
+///
+/// [`register_secret`]: ./fn.register_secret.html
+pub fn zeroize_secrets() {
+    // SAFETY: `SECRETS` is only accessed while holding `SECRETS_LOCK`, and
+    // the buffers are valid for writes as promised to `register_secret`.
+    unsafe {
+        let _ = SECRETS_LOCK.lock();
+        for &(_, ptr, len) in SECRETS.iter() {
+            for i in 0..len {
+                ptr::write_volatile(ptr.add(i), 0);
+            }
+        }
+        compiler_fence(Ordering::SeqCst);
+        let _ = SECRETS_LOCK.unlock();
+    }
+}
+
+/// The payload of panics raised by `panic::panic_with_code`.
+pub struct CodedPanic {
+    pub code: u32,
+    pub message: String,
+}
+
+fn payload_as_str(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else if let Some(coded) = payload.downcast_ref::<CodedPanic>() {
+        &coded.message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+// A recorded panic, with the instruction pointers of its backtrace, which are
+// only resolved when the description is taken.
+struct RecordedPanic {
+    info: EnclavePanicInfo,
+    #[cfg(feature = "backtrace")]
+    backtrace: Option<crate::backtrace::RawBacktrace>,
+}
+
+thread_local! { static PANIC_INFO: RefCell<Option<RecordedPanic>> = RefCell::new(None) }
+
+// Records the description of a panic for `take_panic_info`. Only the first
+// of nested panics is recorded, this is the one which caused the others.
+//
+// The panic path only walks the stack into a fixed-size buffer: symbols are
+// looked up by `take_panic_info`, and nested panics capture no backtrace.
+fn record_panic_info(info: &PanicInfo<'_>, nested: bool) {
+    let _ = PANIC_INFO.try_with(|slot| {
+        if let Ok(mut slot) = slot.try_borrow_mut() {
+            if nested && slot.is_some() {
+                return;
+            }
+            let code = match info.payload().downcast_ref::<CodedPanic>() {
+                Some(coded) => coded.code,
+                None => DEFAULT_PANIC_CODE,
+            };
+            // The current implementation always returns `Some`.
+            let location = info.location().unwrap();
+            *slot = Some(RecordedPanic {
+                info: EnclavePanicInfo {
+                    code,
+                    message: String::from(payload_as_str(info.payload())),
+                    file: String::from(location.file()),
+                    line: location.line(),
+                    column: location.column(),
+                    #[cfg(feature = "backtrace")]
+                    backtrace: Vec::new(),
+                },
+                #[cfg(feature = "backtrace")]
+                backtrace: if nested { None } else { Some(crate::backtrace::RawBacktrace::capture()) },
+            });
+        }
+    });
+}
+
+pub fn take_panic_info() -> Option<EnclavePanicInfo> {
+    let recorded = PANIC_INFO
+        .try_with(|slot| slot.try_borrow_mut().ok().and_then(|mut slot| slot.take()))
+        .ok()
+        .flatten()?;
+    #[cfg(feature = "backtrace")]
+    let info = EnclavePanicInfo {
+        backtrace: recorded.backtrace.map_or_else(Vec::new, |backtrace| backtrace.resolve()),
+        ..recorded.info
+    };
+    #[cfg(not(feature = "backtrace"))]
+    let info = recorded.info;
+    Some(info)
+}
+
 #[doc(hidden)]
 pub mod panic_count {
     use crate::cell::Cell;
@@ -709,27 +962,24 @@ fn rust_panic_with_hook(
         rsgx_abort()
     }
 
+    let mut info = PanicInfo::internal_constructor(message, location, can_unwind);
+    info.set_payload(payload.get());
+    record_panic_info(&info, panics > 1);
+
     unsafe {
-        let mut info = PanicInfo::internal_constructor(message, location, can_unwind);
         let _guard = HOOK_LOCK.read();
         match HOOK {
             // Some platforms (like wasm) know that printing to stderr won't ever actually
             // print anything, and if that's the case we can skip the default
-            // hook. Since string formatting happens lazily when calling `payload`
-            // methods, this means we avoid formatting the string at all!
-            // (The panic runtime might still call `payload.take_box()` though and trigger
-            // formatting.)
+            // hook.
             #[cfg(feature = "stdio")]
             Hook::Default if panic_output().is_none() => {}
-            Hook::Default => {
-                info.set_payload(payload.get());
-                default_hook(&info);
-            }
-            Hook::Custom(ptr) => {
-                info.set_payload(payload.get());
-                (*ptr)(&info);
-            }
+            Hook::Default => default_hook(&info),
+            Hook::Custom(ptr) => (*ptr)(&info),
         };
+        for (_, hook) in CHAINED_HOOKS.iter() {
+            hook(&info);
+        }
         let _ = HOOK_LOCK.read_unlock();
     }
 
@@ -17,11 +17,36 @@
 
 //! Filesystem manipulation operations.
 
-use crate::io::{self, SeekFrom, Seek, Read, Write};
-use crate::path::Path;
+use crate::cmp;
+use crate::error::Error;
+use crate::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::ffi::{OsStr, OsString};
+use crate::fmt;
+use crate::io::{self, BufReader, BufWriter, SeekFrom, Seek, Read, Write};
+use crate::os::unix::prelude::*;
+use crate::path::{Component, Path, PathBuf};
+use crate::ptr;
+use crate::borrow::Cow;
+use crate::sync::{SgxThreadCondvar, SgxThreadMutex, SgxThreadRwLock};
+#[cfg(feature = "thread")]
+use crate::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+#[cfg(feature = "thread")]
+use crate::time::{Duration, Instant};
 use crate::sys::sgxfs as fs_imp;
 use crate::sys_common::{AsInner, AsInnerMut, FromInner, IntoInner};
 use sgx_types::{sgx_key_128bit_t, sgx_align_key_128bit_t};
+use sgx_types::{sgx_attributes_t, sgx_cpu_svn_t, sgx_isv_svn_t, sgx_key_id_t, sgx_key_request_t};
+use sgx_types::sgx_status_t;
+use sgx_types::{sgx_cmac_128bit_key_t, sgx_cmac_128bit_tag_t, sgx_sha256_hash_t};
+use sgx_types::{SGX_KEYSELECT_SEAL, TSEAL_DEFAULT_FLAGSMASK, TSEAL_DEFAULT_MISCMASK};
+
+
+/// Size of the nodes protected files are encrypted in.
+///
+/// Protected files are read and written one node at a time, so buffers of this
+/// size, as used by `SgxFile::open_buffered` and `SgxFile::create_buffered`,
+/// avoid reading or re-encrypting nodes more often than needed.
+pub const NODE_SIZE: usize = fs_imp::NODE_SIZE;
 
 /// A reference to an open file on the filesystem.
 ///
@@ -31,7 +56,15 @@ use sgx_types::{sgx_key_128bit_t, sgx_align_key_128bit_t};
 ///
 /// Files are automatically closed when they go out of scope.
 pub struct SgxFile {
+    /// The registration of the file with the flusher thread, which has to be
+    /// dropped before the file is closed.
+    #[cfg(feature = "thread")]
+    write_behind: Option<WriteBehindGuard>,
     inner: fs_imp::SgxFile,
+    /// The path the file is accounted under, if it's in a namespace with a
+    /// quota, see `set_quota`.
+    accounted: Option<PathBuf>,
+    append: bool,
 }
 
 /// Options and flags which can be used to configure how a file is opened.
@@ -42,7 +75,11 @@ pub struct SgxFile {
 /// builder.
 ///
 #[derive(Clone, Debug)]
-pub struct OpenOptions(fs_imp::OpenOptions);
+pub struct OpenOptions {
+    inner: fs_imp::OpenOptions,
+    #[cfg(feature = "thread")]
+    write_behind: Option<WriteBehind>,
+}
 
 /// Read the entire contents of a file into a bytes vector.
 ///
@@ -126,6 +163,77 @@ impl SgxFile {
         OpenOptions::new().write(true).open_ex(path.as_ref(), key)
     }
 
+    /// Opens a file in read-only mode, wrapped in a `BufReader` sized to the
+    /// protected file node size.
+    ///
+    /// This is the way to read through large files incrementally.
+    pub fn open_buffered<P: AsRef<Path>>(path: P) -> io::Result<BufReader<SgxFile>> {
+        SgxFile::open(path).map(|file| BufReader::with_capacity(NODE_SIZE, file))
+    }
+
+    /// Opens a file in write-only mode, wrapped in a `BufWriter` sized to the
+    /// protected file node size.
+    ///
+    /// This function will create a file if it does not exist,
+    /// and will truncate it if it does.
+    pub fn create_buffered<P: AsRef<Path>>(path: P) -> io::Result<BufWriter<SgxFile>> {
+        SgxFile::create(path).map(|file| BufWriter::with_capacity(NODE_SIZE, file))
+    }
+
+    /// Truncates or extends the underlying file, updating the size of this file
+    /// to become `size`.
+    ///
+    /// If `size` is greater than the current size of the file, the file is
+    /// extended with zeroes. The file cursor isn't changed.
+    ///
+    /// # Errors
+    ///
+    /// Protected files can't shrink, an error of kind `Unsupported` is
+    /// returned if `size` is less than the current size of the file. The file
+    /// must also be open for writing.
+    pub fn set_len(&self, size: u64) -> io::Result<()> {
+        let path = match self.accounted {
+            Some(ref path) => path,
+            None => return self.inner.set_len(size),
+        };
+        let previous = charge(path, |len| cmp::max(len, size))?;
+        let result = self.inner.set_len(size);
+        if result.is_err() {
+            put_back_charge(path, previous);
+        }
+        result
+    }
+
+    /// Writes all cached nodes of the file and flushes them to disk.
+    ///
+    /// Once this returns the content of the file is committed and will be
+    /// found again after a crash of the enclave.
+    pub fn sync_all(&self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Closes the file, returning the error of the close which dropping the
+    /// file ignores.
+    ///
+    /// Closing writes the final metadata node of the file, so this is the way
+    /// to know the file was closed intact.
+    pub fn close(self) -> io::Result<()> {
+        // The file has to leave the flusher thread before it can be closed.
+        #[cfg(feature = "thread")]
+        drop(self.write_behind);
+        self.inner.close()
+    }
+
+    /// Atomically replaces the contents of a file with `contents`.
+    ///
+    /// Either the new contents are committed in full or the file is left
+    /// untouched, even if the enclave crashes in between. See `Transaction`.
+    pub fn write_atomic<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> io::Result<()> {
+        let mut transaction = Transaction::new(path)?;
+        transaction.write_all(contents.as_ref())?;
+        transaction.commit()
+    }
+
     pub fn is_eof(&self) -> bool {
         self.inner.is_eof()
     }
@@ -137,6 +245,30 @@ impl SgxFile {
     pub fn clear_cache(&self) -> io::Result<()> {
         self.inner.clear_cache()
     }
+
+    /// Writes `buf` at the cursor, charging the quota of the file for the
+    /// bytes actually written.
+    ///
+    /// The whole of `buf` is charged first, so a write which would take the
+    /// namespace over quota writes nothing, and the charge is then settled to
+    /// the length written, which is nothing if the write fails.
+    fn write_charged(&self, buf: &[u8]) -> io::Result<usize> {
+        let path = match self.accounted {
+            Some(ref path) => path,
+            None => return self.inner.write(buf),
+        };
+        let start = if self.append { None } else { Some(self.inner.tell()?) };
+        let grow = |len: usize| {
+            move |size: u64| match start {
+                Some(start) => cmp::max(size, start + len as u64),
+                None => size + len as u64,
+            }
+        };
+        let previous = charge(path, grow(buf.len()))?;
+        let result = self.inner.write(buf);
+        settle_charge(path, previous, grow(result.as_ref().map_or(0, |len| *len)));
+        result
+    }
 }
 
 impl AsInner<fs_imp::SgxFile> for SgxFile {
@@ -144,7 +276,13 @@ impl AsInner<fs_imp::SgxFile> for SgxFile {
 }
 impl FromInner<fs_imp::SgxFile> for SgxFile {
     fn from_inner(f: fs_imp::SgxFile) -> SgxFile {
-        SgxFile { inner: f }
+        SgxFile {
+            #[cfg(feature = "thread")]
+            write_behind: None,
+            inner: f,
+            accounted: None,
+            append: false,
+        }
     }
 }
 impl IntoInner<fs_imp::SgxFile> for SgxFile {
@@ -161,7 +299,12 @@ impl Read for SgxFile {
 
 impl Write for SgxFile {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.write(buf)
+        let len = self.write_charged(buf)?;
+        #[cfg(feature = "thread")]
+        if let Some(ref write_behind) = self.write_behind {
+            write_behind.wrote(len);
+        }
+        Ok(len)
     }
     fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
 }
@@ -180,7 +323,12 @@ impl<'a> Read for &'a SgxFile {
 
 impl<'a> Write for &'a SgxFile {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.write(buf)
+        let len = self.write_charged(buf)?;
+        #[cfg(feature = "thread")]
+        if let Some(ref write_behind) = self.write_behind {
+            write_behind.wrote(len);
+        }
+        Ok(len)
     }
     fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
 }
@@ -197,7 +345,11 @@ impl OpenOptions {
     /// All options are initially set to `false`.
     ///
     pub fn new() -> OpenOptions {
-        OpenOptions(fs_imp::OpenOptions::new())
+        OpenOptions {
+            inner: fs_imp::OpenOptions::new(),
+            #[cfg(feature = "thread")]
+            write_behind: None,
+        }
     }
 
     /// Sets the option for read access.
@@ -206,7 +358,7 @@ impl OpenOptions {
     /// `read`-able if opened.
     ///
     pub fn read(&mut self, read: bool) -> &mut OpenOptions {
-        self.0.read(read); self
+        self.inner.read(read); self
     }
 
     /// Sets the option for write access.
@@ -215,7 +367,7 @@ impl OpenOptions {
     /// `write`-able if opened.
     ///
     pub fn write(&mut self, write: bool) -> &mut OpenOptions {
-        self.0.write(write); self
+        self.inner.write(write); self
     }
 
     /// Sets the option for the append mode.
@@ -241,17 +393,34 @@ impl OpenOptions {
     /// `seek(SeekFrom::Current(0))`, and restore it before the next read.
     ///
     pub fn append(&mut self, append: bool) -> &mut OpenOptions {
-        self.0.append(append); self
+        self.inner.append(append); self
     }
 
     /// Sets the option for update a previous file.
     pub fn update(&mut self, update: bool) -> &mut OpenOptions {
-        self.0.update(update); self
+        self.inner.update(update); self
     }
 
     /// Sets the option for binary a file.
     pub fn binary(&mut self, binary: bool) -> &mut OpenOptions {
-        self.0.binary(binary); self
+        self.inner.binary(binary); self
+    }
+
+    /// Sets the file to be flushed in the background by a thread of the
+    /// enclave, instead of only when its cache is full, when it's synced or
+    /// when it's closed.
+    ///
+    /// Writes then return without waiting for the MAC and write ocalls of
+    /// the protected FS, most of the time. Contents which haven't been
+    /// flushed yet are lost if the enclave crashes, like without this
+    /// option, so `sync_all` still has to be called to commit a write.
+    ///
+    /// The flusher thread is started with the first file opened with this
+    /// option. Opening the file fails if it can't be started.
+    #[cfg(feature = "thread")]
+    pub fn write_behind(&mut self, write_behind: Option<WriteBehind>) -> &mut OpenOptions {
+        self.write_behind = write_behind;
+        self
     }
 
     /// Opens a file at `path` with the options specified by `self`.
@@ -263,35 +432,85 @@ impl OpenOptions {
         self._open_ex(path.as_ref(), key)
     }
 
+    /// Opens a file at `path` with the options specified by `self`,
+    /// encrypted with the key of `policy`.
+    ///
+    /// With `KeyPolicy::Seal`, a file opened for `write` or `append` gets a
+    /// key ID if it has none yet.
+    pub fn open_with_policy<P: AsRef<Path>>(
+        &self,
+        path: P,
+        policy: &KeyPolicy,
+    ) -> io::Result<SgxFile> {
+        let path = path.as_ref();
+        match policy.key(path, self.inner.is_create())? {
+            Some(key) => self._open_ex(path, &key.0),
+            None => self._open(path),
+        }
+    }
+
     fn _open(&self, path: &Path) -> io::Result<SgxFile> {
-        let inner = fs_imp::SgxFile::open(path, &self.0)?;
-        Ok(SgxFile { inner })
+        let path = &*checked_path(path)?;
+        let inner = fs_imp::SgxFile::open(path, &self.inner)?;
+        self.finish_open(path, inner)
     }
 
     fn _open_ex(&self, path: &Path, key: &sgx_key_128bit_t) -> io::Result<SgxFile> {
-        let inner = fs_imp::SgxFile::open_ex(path, &self.0, key)?;
-        Ok(SgxFile { inner })
+        let path = &*checked_path(path)?;
+        let inner = fs_imp::SgxFile::open_ex(path, &self.inner, key)?;
+        self.finish_open(path, inner)
+    }
+
+    /// Records the size of a newly opened file if it's in a namespace with a
+    /// quota, and registers it with the flusher thread if needed.
+    fn finish_open(&self, path: &Path, inner: fs_imp::SgxFile) -> io::Result<SgxFile> {
+        let mut file = SgxFile {
+            #[cfg(feature = "thread")]
+            write_behind: None,
+            inner,
+            accounted: None,
+            append: self.inner.is_append(),
+        };
+        #[cfg(feature = "thread")]
+        if let Some(write_behind) = self.write_behind {
+            file.write_behind = Some(WriteBehindGuard::register(&file.inner, write_behind)?);
+        }
+        if with_namespace(path, |_| ()).is_some() {
+            let pos = file.inner.tell()?;
+            let len = file.inner.seek(SeekFrom::End(0))?;
+            file.inner.seek(SeekFrom::Start(pos))?;
+            with_namespace(path, |namespace| namespace.files.insert(path.to_path_buf(), len));
+            file.accounted = Some(path.to_path_buf());
+        }
+        Ok(file)
     }
 }
 
 impl AsInnerMut<fs_imp::OpenOptions> for OpenOptions {
-    fn as_inner_mut(&mut self) -> &mut fs_imp::OpenOptions { &mut self.0 }
+    fn as_inner_mut(&mut self) -> &mut fs_imp::OpenOptions { &mut self.inner }
 }
 
 pub fn remove<P: AsRef<Path>>(path: P) -> io::Result<()> {
-    fs_imp::remove(path.as_ref())
+    let path = &*checked_path(path.as_ref())?;
+    fs_imp::remove(path)?;
+    with_namespace(path, |namespace| namespace.files.remove(path));
+    // The key ID of a file sealed with `KeyPolicy::Seal`, if any.
+    if let Ok(key_id_path) = key_id_path(path) {
+        let _ = crate::untrusted::fs::remove_file(key_id_path);
+    }
+    Ok(())
 }
 
 pub fn export_auto_key<P: AsRef<Path>>(path: P) -> io::Result<sgx_key_128bit_t> {
-    fs_imp::export_auto_key(path.as_ref())
+    fs_imp::export_auto_key(&checked_path(path.as_ref())?)
 }
 
 pub fn export_align_auto_key<P: AsRef<Path>>(path: P) -> io::Result<sgx_align_key_128bit_t> {
-    fs_imp::export_align_auto_key(path.as_ref())
+    fs_imp::export_align_auto_key(&checked_path(path.as_ref())?)
 }
 
 pub fn import_auto_key<P: AsRef<Path>>(path: P, key: &sgx_key_128bit_t) -> io::Result<()> {
-    fs_imp::import_auto_key(path.as_ref(), key)
+    fs_imp::import_auto_key(&checked_path(path.as_ref())?, key)
 }
 
 /// Copies the contents of one file to another.
@@ -303,5 +522,1887 @@ pub fn import_auto_key<P: AsRef<Path>>(path: P, key: &sgx_key_128bit_t) -> io::R
 /// On success, the total number of bytes copied is returned.
 ///
 pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<u64> {
-    fs_imp::copy(from.as_ref(), to.as_ref())
+    fs_imp::copy(&checked_path(from.as_ref())?, &checked_path(to.as_ref())?)
+}
+
+/// Write-behind settings of a protected file, see
+/// `OpenOptions::write_behind`.
+#[cfg(feature = "thread")]
+#[derive(Clone, Copy, Debug)]
+pub struct WriteBehind {
+    interval: Duration,
+    threshold: usize,
+}
+
+#[cfg(feature = "thread")]
+impl WriteBehind {
+    /// Flushes the file about every `interval` while it has unflushed
+    /// writes.
+    pub fn new(interval: Duration) -> WriteBehind {
+        WriteBehind { interval, threshold: usize::MAX }
+    }
+
+    /// Also flushes the file as soon as `bytes` bytes have been written to it
+    /// since the last flush.
+    pub fn threshold(mut self, bytes: usize) -> WriteBehind {
+        self.threshold = bytes;
+        self
+    }
+}
+
+/// A file flushed by the flusher thread.
+#[cfg(feature = "thread")]
+struct WriteBehindFile {
+    handle: fs_imp::FlushHandle,
+    settings: WriteBehind,
+    /// Bytes written since the last flush.
+    dirty: AtomicUsize,
+}
+
+/// Protects `WRITE_BEHIND` and `FLUSHER_STARTED`.
+#[cfg(feature = "thread")]
+static WRITE_BEHIND_LOCK: SgxThreadMutex = SgxThreadMutex::new();
+/// Signalled when a file reaches its threshold.
+#[cfg(feature = "thread")]
+static WRITE_BEHIND_WAKE: SgxThreadCondvar = SgxThreadCondvar::new();
+/// Broadcast when the flusher is done with the files it was flushing.
+#[cfg(feature = "thread")]
+static WRITE_BEHIND_FLUSHED: SgxThreadCondvar = SgxThreadCondvar::new();
+/// The files of the flusher thread, with the time they've waited since they
+/// were last flushed.
+#[cfg(feature = "thread")]
+static mut WRITE_BEHIND: Vec<(Arc<WriteBehindFile>, Duration)> = Vec::new();
+#[cfg(feature = "thread")]
+static mut FLUSHER_STARTED: bool = false;
+
+/// Removes a file from the flusher thread when dropped.
+#[cfg(feature = "thread")]
+struct WriteBehindGuard(Arc<WriteBehindFile>);
+
+#[cfg(feature = "thread")]
+impl WriteBehindGuard {
+    fn register(inner: &fs_imp::SgxFile, settings: WriteBehind) -> io::Result<WriteBehindGuard> {
+        let file = Arc::new(WriteBehindFile {
+            handle: inner.flush_handle(),
+            settings,
+            dirty: AtomicUsize::new(0),
+        });
+        unsafe {
+            let _ = WRITE_BEHIND_LOCK.lock();
+            let started = if FLUSHER_STARTED {
+                Ok(())
+            } else {
+                crate::thread::Builder::new()
+                    .name("sgxfs-flusher".to_string())
+                    .spawn(flusher)
+                    .map(|_| FLUSHER_STARTED = true)
+            };
+            if started.is_ok() {
+                WRITE_BEHIND.push((file.clone(), Duration::ZERO));
+                // The flusher may have to wake up earlier for this file.
+                let _ = WRITE_BEHIND_WAKE.signal();
+            }
+            let _ = WRITE_BEHIND_LOCK.unlock();
+            started.map(|_| WriteBehindGuard(file))
+        }
+    }
+
+    fn wrote(&self, len: usize) {
+        let dirty = self.0.dirty.fetch_add(len, Ordering::Relaxed) + len;
+        if dirty >= self.0.settings.threshold && dirty - len < self.0.settings.threshold {
+            unsafe {
+                let _ = WRITE_BEHIND_WAKE.signal();
+            }
+        }
+    }
+}
+
+#[cfg(feature = "thread")]
+impl Drop for WriteBehindGuard {
+    fn drop(&mut self) {
+        // The flusher holds a reference to the files it's flushing, and
+        // drops them with the lock held once done: once the file is removed
+        // and the flusher is done with it, it won't touch it again, and the
+        // file can be closed.
+        unsafe {
+            let _ = WRITE_BEHIND_LOCK.lock();
+            WRITE_BEHIND.retain(|(file, _)| !Arc::ptr_eq(file, &self.0));
+            while Arc::strong_count(&self.0) > 1 {
+                let _ = WRITE_BEHIND_FLUSHED.wait(&WRITE_BEHIND_LOCK);
+            }
+            let _ = WRITE_BEHIND_LOCK.unlock();
+        }
+    }
+}
+
+/// The body of the flusher thread.
+#[cfg(feature = "thread")]
+fn flusher() {
+    /// How long to wait for with no file to flush.
+    const IDLE: Duration = Duration::from_secs(1);
+
+    let mut flushing = Vec::new();
+    unsafe {
+        let _ = WRITE_BEHIND_LOCK.lock();
+        loop {
+            let wait = WRITE_BEHIND
+                .iter()
+                .map(|(file, waited)| file.settings.interval.saturating_sub(*waited))
+                .min()
+                .unwrap_or(IDLE);
+            // Woken up early or not, files have waited for as long as the
+            // flusher slept.
+            let start = Instant::_now();
+            let _ = WRITE_BEHIND_WAKE.wait_timeout(&WRITE_BEHIND_LOCK, wait);
+            let slept = Instant::_now().saturating_duration_since(start);
+
+            for (file, waited) in WRITE_BEHIND.iter_mut() {
+                *waited += slept;
+                let dirty = file.dirty.load(Ordering::Relaxed);
+                if dirty >= file.settings.threshold
+                    || (dirty > 0 && *waited >= file.settings.interval)
+                {
+                    file.dirty.fetch_sub(dirty, Ordering::Relaxed);
+                    flushing.push(file.clone());
+                    *waited = Duration::ZERO;
+                } else if dirty == 0 {
+                    *waited = Duration::ZERO;
+                }
+            }
+            if flushing.is_empty() {
+                continue;
+            }
+
+            // Flushing goes to the host, which writers registering or
+            // dropping files shouldn't wait for.
+            let _ = WRITE_BEHIND_LOCK.unlock();
+            for file in &flushing {
+                // A failed flush leaves the file in a bad state, which the
+                // owner of the file will see on its next operation.
+                let _ = file.handle.flush();
+            }
+            let _ = WRITE_BEHIND_LOCK.lock();
+            flushing.clear();
+            let _ = WRITE_BEHIND_FLUSHED.broadcast();
+        }
+    }
+}
+
+/// Restrictions on the paths of protected files.
+///
+/// Paths often come from the host, which could pass `../../sealed/key` to
+/// get a file written somewhere else than intended. Once a policy is
+/// installed with `set_fs_policy`, every path given to this module is
+/// normalized, resolving `.` and `..` components without looking at the
+/// host filesystem, and must then be inside one of the allowed roots. The
+/// normalized path is the one used.
+///
+/// The host can still redirect files with symbolic links, which the enclave
+/// can't see. This is harmless to the confidentiality and integrity of the
+/// files, which are bound to their names, but not to their availability.
+#[derive(Clone, Debug, Default)]
+pub struct FsPolicy {
+    roots: Vec<PathBuf>,
+}
+
+impl FsPolicy {
+    /// Creates a policy which allows no path at all.
+    pub fn new() -> FsPolicy {
+        FsPolicy { roots: Vec::new() }
+    }
+
+    /// Allows the paths under the directory `root`.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `InvalidInput` is returned if `root` escapes its
+    /// starting point with `..` components.
+    pub fn allow_root<P: AsRef<Path>>(&mut self, root: P) -> io::Result<&mut FsPolicy> {
+        self.roots.push(normalize(root.as_ref())?);
+        Ok(self)
+    }
+
+    /// Normalizes `path` and checks it against the allowed roots.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `InvalidInput` is returned if `path` escapes its
+    /// starting point with `..` components, and of kind `PermissionDenied` if
+    /// it's outside of all the allowed roots.
+    pub fn check<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+        let path = normalize(path.as_ref())?;
+        if self.roots.iter().any(|root| path.starts_with(root)) {
+            Ok(path)
+        } else {
+            Err(io::const_io_error!(
+                io::ErrorKind::PermissionDenied,
+                "path is outside of the allowed roots"
+            ))
+        }
+    }
+}
+
+/// Resolves the `.` and `..` components of `path`.
+fn normalize(path: &Path) -> io::Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    let mut depth = 0_usize;
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => normalized.push(component),
+            Component::CurDir => {}
+            Component::ParentDir if depth == 0 => {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::InvalidInput,
+                    "path escapes its root with `..`"
+                ));
+            }
+            Component::ParentDir => {
+                normalized.pop();
+                depth -= 1;
+            }
+            Component::Normal(name) => {
+                normalized.push(name);
+                depth += 1;
+            }
+        }
+    }
+    Ok(normalized)
+}
+
+static FS_POLICY_LOCK: SgxThreadRwLock = SgxThreadRwLock::new();
+// protected by `FS_POLICY_LOCK`.
+static mut FS_POLICY: Option<FsPolicy> = None;
+
+/// Installs `policy` to check all the paths of protected files against, or
+/// removes the current policy if `None`.
+pub fn set_fs_policy(policy: Option<FsPolicy>) {
+    unsafe {
+        let _ = FS_POLICY_LOCK.write();
+        FS_POLICY = policy;
+        let _ = FS_POLICY_LOCK.write_unlock();
+    }
+}
+
+/// Checks `path` against the installed policy, if any.
+fn checked_path(path: &Path) -> io::Result<Cow<'_, Path>> {
+    unsafe {
+        let _ = FS_POLICY_LOCK.read();
+        let result = match FS_POLICY {
+            Some(ref policy) => policy.check(path).map(Cow::Owned),
+            None => Ok(Cow::Borrowed(path)),
+        };
+        let _ = FS_POLICY_LOCK.read_unlock();
+        result
+    }
+}
+
+/// Protects `QUOTAS`.
+static QUOTAS_LOCK: SgxThreadMutex = SgxThreadMutex::new();
+/// The namespaces with a quota.
+static mut QUOTAS: Vec<Namespace> = Vec::new();
+
+/// A directory of protected files sharing a quota.
+struct Namespace {
+    dir: PathBuf,
+    limit: u64,
+    /// The size of the files of the namespace known to the enclave.
+    files: BTreeMap<PathBuf, u64>,
+}
+
+impl Namespace {
+    fn usage(&self) -> u64 {
+        self.files.values().sum()
+    }
+}
+
+/// Runs `f` on the innermost namespace containing `path`, if any.
+fn with_namespace<F, R>(path: &Path, f: F) -> Option<R>
+where
+    F: FnOnce(&mut Namespace) -> R,
+{
+    unsafe {
+        let _ = QUOTAS_LOCK.lock();
+        let result = QUOTAS
+            .iter_mut()
+            .filter(|namespace| path.starts_with(&namespace.dir))
+            .max_by_key(|namespace| namespace.dir.components().count())
+            .map(f);
+        let _ = QUOTAS_LOCK.unlock();
+        result
+    }
+}
+
+/// Grows the accounted size of the file at `path` to `grow(size)`, failing if
+/// this takes its namespace over quota.
+///
+/// Returns the accounted size from before, for `put_back_charge` or
+/// `settle_charge` to undo or adjust the charge with.
+fn charge<F: FnOnce(u64) -> u64>(path: &Path, grow: F) -> io::Result<Option<u64>> {
+    let result = with_namespace(path, |namespace| {
+        let previous = namespace.files.get(path).copied();
+        let size = previous.unwrap_or(0);
+        let new_size = grow(size);
+        if new_size <= size {
+            return Ok(previous);
+        }
+        let required = namespace.usage() - size + new_size;
+        if required > namespace.limit {
+            return Err(QuotaExceeded {
+                namespace: namespace.dir.clone(),
+                limit: namespace.limit,
+                required,
+            });
+        }
+        namespace.files.insert(path.to_path_buf(), new_size);
+        Ok(previous)
+    });
+    match result {
+        Some(Err(e)) => Err(io::Error::new(io::ErrorKind::FilesystemQuotaExceeded, e)),
+        Some(Ok(previous)) => Ok(previous),
+        None => Ok(None),
+    }
+}
+
+/// Replaces a charge made from the accounted size `previous` of the file at
+/// `path` by the smaller `grow(previous)`, once known.
+///
+/// This can't go over quota, being at most what was charged.
+fn settle_charge<F: FnOnce(u64) -> u64>(path: &Path, previous: Option<u64>, grow: F) {
+    with_namespace(path, |namespace| {
+        let size = previous.unwrap_or(0);
+        match grow(size) {
+            new_size if new_size > size => namespace.files.insert(path.to_path_buf(), new_size),
+            _ => match previous {
+                Some(len) => namespace.files.insert(path.to_path_buf(), len),
+                None => namespace.files.remove(path),
+            },
+        }
+    });
+}
+
+/// Limits the protected files under the directory `namespace` to `limit`
+/// bytes in total, replacing any previous limit on it.
+///
+/// A write which would take the namespace over its limit fails with an
+/// error of kind `FilesystemQuotaExceeded`, wrapping a `QuotaExceeded`, and
+/// writes nothing. Namespaces can be nested, files are accounted to the
+/// innermost namespace they are in.
+///
+/// Sizes are those of the contents of the files, not of their encrypted form
+/// on the host. Files are accounted from the first time they're opened by the
+/// enclave, so an enclave which restarts should open the files it already has
+/// (see `read_dir`) before letting tenants write new ones. Paths are compared
+/// as given, so all files of a namespace have to be opened through paths
+/// starting with `namespace`.
+pub fn set_quota<P: AsRef<Path>>(namespace: P, limit: u64) {
+    let dir = namespace.as_ref();
+    unsafe {
+        let _ = QUOTAS_LOCK.lock();
+        match QUOTAS.iter_mut().find(|namespace| namespace.dir == dir) {
+            Some(namespace) => namespace.limit = limit,
+            None => QUOTAS.push(Namespace {
+                dir: dir.to_path_buf(),
+                limit,
+                files: BTreeMap::new(),
+            }),
+        }
+        let _ = QUOTAS_LOCK.unlock();
+    }
+}
+
+/// Removes the quota of the directory `namespace`, returning whether it had
+/// one.
+///
+/// Files which are already open keep being accounted to the namespace until
+/// they're closed, but can no longer exceed it.
+pub fn remove_quota<P: AsRef<Path>>(namespace: P) -> bool {
+    let dir = namespace.as_ref();
+    unsafe {
+        let _ = QUOTAS_LOCK.lock();
+        let len = QUOTAS.len();
+        QUOTAS.retain(|namespace| namespace.dir != dir);
+        let removed = QUOTAS.len() != len;
+        let _ = QUOTAS_LOCK.unlock();
+        removed
+    }
+}
+
+/// Returns the number of bytes accounted to the directory `namespace`, or
+/// `None` if it has no quota.
+pub fn quota_usage<P: AsRef<Path>>(namespace: P) -> Option<u64> {
+    let dir = namespace.as_ref();
+    unsafe {
+        let _ = QUOTAS_LOCK.lock();
+        let usage = QUOTAS.iter().find(|namespace| namespace.dir == dir).map(Namespace::usage);
+        let _ = QUOTAS_LOCK.unlock();
+        usage
+    }
+}
+
+/// The error of a write which would take a namespace over its quota.
+///
+/// It is wrapped in an `io::Error` of kind `FilesystemQuotaExceeded`.
+#[derive(Clone, Debug)]
+pub struct QuotaExceeded {
+    namespace: PathBuf,
+    limit: u64,
+    required: u64,
+}
+
+impl QuotaExceeded {
+    /// Returns the directory of the namespace whose quota was exceeded.
+    pub fn namespace(&self) -> &Path {
+        &self.namespace
+    }
+
+    /// Returns the quota of the namespace, in bytes.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Returns the number of bytes the namespace would have used.
+    pub fn required(&self) -> u64 {
+        self.required
+    }
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "quota of {} bytes exceeded for {}, {} bytes required",
+            self.limit,
+            self.namespace.display(),
+            self.required
+        )
+    }
+}
+
+impl Error for QuotaExceeded {}
+
+/// Name of the directory transactions stage their files in, next to the
+/// files they replace.
+const TRANSACTION_DIR: &str = ".sgxfs-tmp";
+
+/// A transactional write of a protected file.
+///
+/// The new contents are written to a staging file, and only once they and
+/// their MAC have been flushed to disk does `commit` rename the staging file
+/// over the target, through an ocall. A crash of the enclave before that
+/// leaves the previous contents in place, and a crash during the rename
+/// can't leave a half-written file behind either.
+///
+/// Protected files record their name in their encrypted metadata and refuse
+/// to open under a different one, so the staging file has the same name as
+/// the target, in a directory of its own under a `.sgxfs-tmp` directory next
+/// to it. Concurrent transactions on a file thus don't share a staging file,
+/// the last one committed wins. A transaction which is dropped without being
+/// committed removes its staging file.
+pub struct Transaction {
+    file: Option<SgxFile>,
+    path: PathBuf,
+    staging: PathBuf,
+    committed: bool,
+}
+
+impl Transaction {
+    /// Starts a transaction replacing the file at `path`, encrypted with the
+    /// enclave's auto key.
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Transaction> {
+        Transaction::start(path.as_ref(), None)
+    }
+
+    /// Starts a transaction replacing the file at `path`, encrypted with
+    /// `key`.
+    pub fn new_ex<P: AsRef<Path>>(path: P, key: &sgx_key_128bit_t) -> io::Result<Transaction> {
+        Transaction::start(path.as_ref(), Some(key))
+    }
+
+    /// Starts a transaction replacing the file at `path`, encrypted with the
+    /// key of `policy`.
+    pub fn with_policy<P: AsRef<Path>>(path: P, policy: &KeyPolicy) -> io::Result<Transaction> {
+        let key = policy.key(path.as_ref(), true)?;
+        Transaction::start(path.as_ref(), key.as_ref().map(|key| &key.0))
+    }
+
+    fn start(path: &Path, key: Option<&sgx_key_128bit_t>) -> io::Result<Transaction> {
+        let path = &*checked_path(path)?;
+        let staging = staging_path(path)?;
+        let file = match key {
+            Some(key) => SgxFile::create_ex(&staging, key),
+            None => SgxFile::create(&staging),
+        };
+        let file = match file {
+            Ok(file) => file,
+            Err(e) => {
+                remove_staging_dir(&staging);
+                return Err(e);
+            }
+        };
+        Ok(Transaction {
+            file: Some(file),
+            path: path.to_path_buf(),
+            staging,
+            committed: false,
+        })
+    }
+
+    /// Commits the transaction, replacing the target file with the staged
+    /// contents.
+    ///
+    /// If this returns an error the target file may or may not have been
+    /// replaced, but is never left partially written. It's left untouched if
+    /// the staged contents couldn't be flushed or closed.
+    pub fn commit(mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+            // Closing the file writes its final metadata node, which has to
+            // be on disk before the file replaces the target.
+            file.close()?;
+        }
+        crate::untrusted::fs::rename(&self.staging, &self.path)?;
+        self.committed = true;
+
+        let len = with_namespace(&self.staging, |namespace| namespace.files.remove(&self.staging));
+        with_namespace(&self.path, |namespace| match len.flatten() {
+            Some(len) => namespace.files.insert(self.path.clone(), len),
+            None => namespace.files.remove(&self.path),
+        });
+        Ok(())
+    }
+
+    fn file(&mut self) -> &mut SgxFile {
+        // The file is only taken out by `commit`, which consumes `self`.
+        self.file.as_mut().unwrap()
+    }
+}
+
+impl Write for Transaction {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> { self.file().flush() }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.committed {
+            drop(self.file.take());
+            let _ = remove(&self.staging);
+        }
+        remove_staging_dir(&self.staging);
+    }
+}
+
+/// Returns a new staging path for the file at `path`, creating its
+/// directory.
+fn staging_path(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or(io::const_io_error!(
+        io::ErrorKind::InvalidInput,
+        "path has no file name"
+    ))?;
+    let mut id = [0_u8; 8];
+    sgx_trts::trts::rsgx_read_rand(&mut id).map_err(|_| {
+        io::const_io_error!(io::ErrorKind::Other, "failed to generate a staging name")
+    })?;
+    let dir = path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(TRANSACTION_DIR)
+        .join(format!("{:016x}", u64::from_le_bytes(id)));
+    crate::untrusted::fs::create_dir_all(&dir)?;
+    Ok(dir.join(name))
+}
+
+/// Removes the directory of the staging file `staging`, once it's gone.
+fn remove_staging_dir(staging: &Path) {
+    if let Some(dir) = staging.parent() {
+        let _ = crate::untrusted::fs::remove_dir(dir);
+    }
+}
+
+/// Name of the directory snapshots are kept in, next to the files they were
+/// taken of.
+const SNAPSHOT_DIR: &str = ".sgxfs-snapshots";
+
+/// Number of bytes of contents protected files keep in their metadata node,
+/// ahead of their data nodes.
+const METADATA_CONTENTS_SIZE: u64 = 3072;
+
+/// Puts back the accounted size of the file at `path` from before a charge
+/// for a change which failed.
+fn put_back_charge(path: &Path, previous: Option<u64>) {
+    with_namespace(path, |namespace| match previous {
+        Some(len) => namespace.files.insert(path.to_path_buf(), len),
+        None => namespace.files.remove(path),
+    });
+}
+
+/// Returns the path of the `tag` snapshot of the file at `path`.
+fn snapshot_path(path: &Path, tag: &str) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or(io::const_io_error!(
+        io::ErrorKind::InvalidInput,
+        "path has no file name"
+    ))?;
+    let mut components = Path::new(tag).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => {}
+        _ => {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                "snapshot tag must be a single file name"
+            ))
+        }
+    }
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    Ok(dir.join(SNAPSHOT_DIR).join(tag).join(name))
+}
+
+impl SgxFile {
+    /// Takes a snapshot of the file at `path`, under `tag`, replacing any
+    /// previous snapshot with that tag.
+    ///
+    /// The snapshot is a copy of the encrypted file, so it opens with the
+    /// same key and needs no decryption, kept in a `.sgxfs-snapshots/<tag>`
+    /// directory next to the file. On host file systems with reflinks it
+    /// shares the unchanged nodes with the file rather than duplicating
+    /// them.
+    ///
+    /// Only what has been flushed to disk is captured, so the file should be
+    /// flushed or closed by its writers first.
+    pub fn snapshot<P: AsRef<Path>>(path: P, tag: &str) -> io::Result<()> {
+        let path = &*checked_path(path.as_ref())?;
+        let snapshot = snapshot_path(path, tag)?;
+        if let Some(dir) = snapshot.parent() {
+            crate::untrusted::fs::create_dir_all(dir)?;
+        }
+        fs_imp::clone_file(path, &snapshot)
+    }
+
+    /// Replaces the file at `path` with its `tag` snapshot, which is kept.
+    ///
+    /// The snapshot is staged next to the file and renamed over it, as by a
+    /// `Transaction`, so the file is never left partially restored. It
+    /// shouldn't be open while being restored.
+    ///
+    /// In a namespace with a quota the restored file is charged for the
+    /// contents its snapshot can hold, bounded by its size on the host since
+    /// they're only known once decrypted, and the restore fails if this takes
+    /// the namespace over quota. Opening the file charges its exact size.
+    pub fn restore<P: AsRef<Path>>(path: P, tag: &str) -> io::Result<()> {
+        let path = &*checked_path(path.as_ref())?;
+        let snapshot = snapshot_path(path, tag)?;
+        let previous = if with_namespace(path, |_| ()).is_some() {
+            let len = crate::untrusted::fs::metadata(&snapshot)?.len();
+            let bound = len.saturating_sub(NODE_SIZE as u64) + METADATA_CONTENTS_SIZE;
+            let previous = with_namespace(path, |namespace| namespace.files.remove(path)).flatten();
+            if let Err(e) = charge(path, |_| bound) {
+                put_back_charge(path, previous);
+                return Err(e);
+            }
+            Some(previous)
+        } else {
+            None
+        };
+
+        let staging = staging_path(path)?;
+        if let Err(e) = fs_imp::clone_file(&snapshot, &staging)
+            .and_then(|_| crate::untrusted::fs::rename(&staging, path))
+        {
+            let _ = crate::untrusted::fs::remove_file(&staging);
+            remove_staging_dir(&staging);
+            if let Some(previous) = previous {
+                put_back_charge(path, previous);
+            }
+            return Err(e);
+        }
+        remove_staging_dir(&staging);
+        Ok(())
+    }
+
+    /// Removes the `tag` snapshot of the file at `path`.
+    pub fn remove_snapshot<P: AsRef<Path>>(path: P, tag: &str) -> io::Result<()> {
+        let path = &*checked_path(path.as_ref())?;
+        crate::untrusted::fs::remove_file(snapshot_path(path, tag)?)
+    }
+}
+
+/// The key a protected file is encrypted with.
+#[derive(Clone, Copy)]
+pub enum KeyPolicy {
+    /// The auto key of the protected FS, derived from the enclave's signer.
+    Auto,
+    /// A key supplied by the enclave.
+    Key(sgx_key_128bit_t),
+    /// A seal key of the enclave, derived with the `SGX_KEYPOLICY_*` flags
+    /// of `key_policy` at the given security version.
+    ///
+    /// Every file gets a key of its own, derived with a random key ID drawn
+    /// when the file is first written under this policy. The key ID isn't
+    /// secret and is kept in a `.sgxfs-keys` directory next to the file: a
+    /// host tampering with it only makes the file fail to open.
+    ///
+    /// The security versions are recorded so the same key can be derived
+    /// again after the platform or the enclave has been upgraded, until the
+    /// files have been rekeyed.
+    Seal {
+        key_policy: u16,
+        isv_svn: sgx_isv_svn_t,
+        cpu_svn: sgx_cpu_svn_t,
+    },
+}
+
+impl KeyPolicy {
+    /// A seal key derived with `key_policy` at the current security version
+    /// of the enclave and the platform.
+    pub fn seal(key_policy: u16) -> KeyPolicy {
+        let report = unsafe { &*sgx_types::sgx_self_report() };
+        KeyPolicy::Seal {
+            key_policy,
+            isv_svn: report.body.isv_svn,
+            cpu_svn: report.body.cpu_svn,
+        }
+    }
+
+    /// Returns the key of the policy for the file at `path`, or `None` for
+    /// the auto key.
+    ///
+    /// A seal key is derived with the key ID of the file, which is drawn if
+    /// the file has none yet and `create`.
+    fn key(&self, path: &Path, create: bool) -> io::Result<Option<FileKey>> {
+        match *self {
+            KeyPolicy::Auto => Ok(None),
+            KeyPolicy::Key(key) => Ok(Some(FileKey(key))),
+            KeyPolicy::Seal { key_policy, isv_svn, cpu_svn } => {
+                let request = sgx_key_request_t {
+                    key_name: SGX_KEYSELECT_SEAL,
+                    key_policy,
+                    isv_svn,
+                    cpu_svn,
+                    key_id: file_key_id(&checked_path(path)?, create)?,
+                    attribute_mask: sgx_attributes_t {
+                        flags: TSEAL_DEFAULT_FLAGSMASK,
+                        xfrm: 0,
+                    },
+                    misc_mask: TSEAL_DEFAULT_MISCMASK,
+                    ..Default::default()
+                };
+                let mut key = FileKey(sgx_key_128bit_t::default());
+                match unsafe { sgx_types::sgx_get_key(&request, &mut key.0) } {
+                    sgx_status_t::SGX_SUCCESS => Ok(Some(key)),
+                    sgx_status_t::SGX_ERROR_INVALID_CPUSVN
+                    | sgx_status_t::SGX_ERROR_INVALID_ISVSVN => Err(io::const_io_error!(
+                        io::ErrorKind::InvalidInput,
+                        "security version of the key policy is newer than the enclave's"
+                    )),
+                    _ => Err(io::const_io_error!(
+                        io::ErrorKind::Other,
+                        "failed to derive the seal key"
+                    )),
+                }
+            }
+        }
+    }
+}
+
+/// The key of a protected file, overwritten with zeroes when dropped.
+struct FileKey(sgx_key_128bit_t);
+
+impl Drop for FileKey {
+    fn drop(&mut self) {
+        wipe_bytes(&mut self.0);
+    }
+}
+
+/// Overwrites `buf` with zeroes, in a way the compiler can't optimize out.
+fn wipe_bytes(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
+/// Name of the directory the key IDs of files sealed with `KeyPolicy::Seal`
+/// are kept in, next to the files.
+const KEY_ID_DIR: &str = ".sgxfs-keys";
+
+/// Returns the path the key ID of the file at `path` is kept at.
+fn key_id_path(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or(io::const_io_error!(
+        io::ErrorKind::InvalidInput,
+        "path has no file name"
+    ))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    Ok(dir.join(KEY_ID_DIR).join(name))
+}
+
+/// Returns the key ID of the file at `path`, drawing a random one and
+/// recording it if the file has none yet and `create`.
+fn file_key_id(path: &Path, create: bool) -> io::Result<sgx_key_id_t> {
+    let key_id_path = key_id_path(path)?;
+    let mut key_id = sgx_key_id_t::default();
+    match crate::untrusted::fs::read(&key_id_path) {
+        Ok(id) if id.len() == key_id.id.len() => {
+            key_id.id.copy_from_slice(&id);
+            return Ok(key_id);
+        }
+        Ok(_) => {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidData,
+                "malformed key ID of a protected file"
+            ))
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound && create => {}
+        Err(e) => return Err(e),
+    }
+    sgx_trts::trts::rsgx_read_rand(&mut key_id.id).map_err(|_| {
+        io::const_io_error!(io::ErrorKind::Other, "failed to generate a key ID")
+    })?;
+    if let Some(dir) = key_id_path.parent() {
+        crate::untrusted::fs::create_dir_all(dir)?;
+    }
+    crate::untrusted::fs::write(&key_id_path, key_id.id)?;
+    Ok(key_id)
+}
+
+impl fmt::Debug for KeyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            KeyPolicy::Auto => f.write_str("Auto"),
+            KeyPolicy::Key(_) => f.write_str("Key(..)"),
+            KeyPolicy::Seal { key_policy, isv_svn, ref cpu_svn } => f
+                .debug_struct("Seal")
+                .field("key_policy", &key_policy)
+                .field("isv_svn", &isv_svn)
+                .field("cpu_svn", &cpu_svn.svn)
+                .finish(),
+        }
+    }
+}
+
+impl SgxFile {
+    /// Opens a file in read-only mode, encrypted with the key of `policy`.
+    pub fn open_with_policy<P: AsRef<Path>>(path: P, policy: &KeyPolicy) -> io::Result<SgxFile> {
+        match policy.key(path.as_ref(), false)? {
+            Some(key) => SgxFile::open_ex(path, &key.0),
+            None => SgxFile::open(path),
+        }
+    }
+
+    /// Opens a file in write-only mode, encrypted with the key of `policy`.
+    ///
+    /// This function will create a file if it does not exist,
+    /// and will truncate it if it does.
+    pub fn create_with_policy<P: AsRef<Path>>(path: P, policy: &KeyPolicy) -> io::Result<SgxFile> {
+        match policy.key(path.as_ref(), true)? {
+            Some(key) => SgxFile::create_ex(path, &key.0),
+            None => SgxFile::create(path),
+        }
+    }
+
+    /// Re-encrypts the file at `path` from the key of `from` to the key of
+    /// `to`.
+    ///
+    /// The contents are copied through a `Transaction` a few nodes at a time,
+    /// so large files don't have to fit in enclave memory and a crash leaves
+    /// the file encrypted under either the old key or the new one. `progress`
+    /// is called after every chunk with the number of bytes copied so far
+    /// and the size of the file.
+    pub fn rekey<P, F>(path: P, from: &KeyPolicy, to: &KeyPolicy, mut progress: F) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnMut(u64, u64),
+    {
+        const CHUNK_SIZE: usize = 16 * NODE_SIZE;
+
+        let path = path.as_ref();
+        let mut source = SgxFile::open_with_policy(path, from)?;
+        let total = source.seek(SeekFrom::End(0))?;
+        source.seek(SeekFrom::Start(0))?;
+
+        let mut transaction = Transaction::with_policy(path, to)?;
+        let mut buf = vec![0_u8; CHUNK_SIZE];
+        let mut copied = 0_u64;
+        loop {
+            let len = match source.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            transaction.write_all(&buf[..len])?;
+            copied += len as u64;
+            progress(copied, total);
+        }
+        drop(source);
+        transaction.commit()
+    }
+}
+
+/// Random access to a protected file through a cache of decrypted nodes.
+///
+/// The file is paged into enclave memory one `NODE_SIZE` page at a time as
+/// it's read, keeping at most `capacity` pages and evicting the least
+/// recently used one when full. This bounds the EPC used to read from a
+/// large file regardless of its size or of the access pattern.
+#[derive(Debug)]
+pub struct SealedMmap {
+    file: SgxFile,
+    len: u64,
+    capacity: usize,
+    pages: HashMap<u64, Page>,
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct Page {
+    data: Box<[u8]>,
+    last_used: u64,
+}
+
+impl SealedMmap {
+    /// Maps `file`, caching at most `capacity` pages.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `InvalidInput` is returned if `capacity` is zero.
+    pub fn new(mut file: SgxFile, capacity: usize) -> io::Result<SealedMmap> {
+        if capacity == 0 {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidInput,
+                "cache capacity must be at least one page"
+            ));
+        }
+        let len = file.seek(SeekFrom::End(0))?;
+        Ok(SealedMmap {
+            file,
+            len,
+            capacity,
+            pages: HashMap::with_capacity(capacity),
+            clock: 0,
+        })
+    }
+
+    /// Opens and maps the file at `path`, encrypted with the key of `policy`.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        policy: &KeyPolicy,
+        capacity: usize,
+    ) -> io::Result<SealedMmap> {
+        SealedMmap::new(SgxFile::open_with_policy(path, policy)?, capacity)
+    }
+
+    /// Returns the size of the file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of pages currently in the cache.
+    pub fn cached_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Reads bytes starting at `offset` into `buf`, returning how many were
+    /// read.
+    ///
+    /// Fewer bytes than requested are only read at the end of the file.
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while read < buf.len() {
+            let pos = offset + read as u64;
+            if pos >= self.len {
+                break;
+            }
+            let start = (pos % NODE_SIZE as u64) as usize;
+            let page = self.page(pos / NODE_SIZE as u64)?;
+            let len = cmp::min(page.len() - start, buf.len() - read);
+            buf[read..read + len].copy_from_slice(&page[start..start + len]);
+            read += len;
+        }
+        Ok(read)
+    }
+
+    /// Reads exactly `buf.len()` bytes starting at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `UnexpectedEof` is returned if the file ends first.
+    pub fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        if self.read_at(offset, buf)? < buf.len() {
+            return Err(io::const_io_error!(
+                io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Drops all cached pages.
+    pub fn evict_all(&mut self) {
+        self.pages.clear();
+    }
+
+    fn page(&mut self, index: u64) -> io::Result<&[u8]> {
+        self.clock += 1;
+        if !self.pages.contains_key(&index) {
+            if self.pages.len() >= self.capacity {
+                let lru = self
+                    .pages
+                    .iter()
+                    .min_by_key(|(_, page)| page.last_used)
+                    .map(|(&index, _)| index);
+                if let Some(lru) = lru {
+                    self.pages.remove(&lru);
+                }
+            }
+
+            let start = index * NODE_SIZE as u64;
+            let len = cmp::min(NODE_SIZE as u64, self.len - start) as usize;
+            let mut data = vec![0_u8; len].into_boxed_slice();
+            self.file.seek(SeekFrom::Start(start))?;
+            self.file.read_exact(&mut data)?;
+            self.pages.insert(index, Page { data, last_used: 0 });
+        }
+
+        // The page was either already cached or has just been inserted.
+        let page = self.pages.get_mut(&index).unwrap();
+        page.last_used = self.clock;
+        Ok(&page.data)
+    }
+}
+
+/// An append-only log of records stored in a protected file.
+///
+/// Every record is followed by a MAC over the record, its sequence number
+/// and the MAC of the record before it, so a record can't be dropped, moved
+/// or replayed from another log with the same key without breaking the chain
+/// from there on. Opening a log verifies the whole chain.
+///
+/// The chain can't tell a log which was cut after a complete record, or
+/// rolled back to an older copy, from a shorter log. To detect this, keep
+/// the `head` of the log somewhere the host can't roll back, such as a
+/// monotonic counter or the sealed state of the enclave, and compare it
+/// after opening.
+pub struct AppendOnlyLog {
+    file: SgxFile,
+    chain: MacChain,
+}
+
+/// The running MAC of a log.
+struct MacChain {
+    key: sgx_cmac_128bit_key_t,
+    head: sgx_cmac_128bit_tag_t,
+    records: u64,
+}
+
+impl MacChain {
+    fn new(key: &sgx_cmac_128bit_key_t) -> MacChain {
+        MacChain {
+            key: *key,
+            head: sgx_cmac_128bit_tag_t::default(),
+            records: 0,
+        }
+    }
+
+    /// Computes the MAC of the next record, without advancing the chain.
+    fn mac(&self, record: &[u8]) -> io::Result<sgx_cmac_128bit_tag_t> {
+        let mut msg = Vec::with_capacity(self.head.len() + 12 + record.len());
+        msg.extend_from_slice(&self.head);
+        msg.extend_from_slice(&self.records.to_le_bytes());
+        msg.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        msg.extend_from_slice(record);
+
+        let mut mac = sgx_cmac_128bit_tag_t::default();
+        let status = unsafe {
+            sgx_types::sgx_rijndael128_cmac_msg(&self.key, msg.as_ptr(), msg.len() as u32, &mut mac)
+        };
+        match status {
+            sgx_status_t::SGX_SUCCESS => Ok(mac),
+            _ => Err(io::const_io_error!(io::ErrorKind::Other, "failed to compute the record MAC")),
+        }
+    }
+
+    fn advance(&mut self, mac: sgx_cmac_128bit_tag_t) {
+        self.head = mac;
+        self.records += 1;
+    }
+}
+
+impl Drop for MacChain {
+    fn drop(&mut self) {
+        unsafe { ptr::write_volatile(&mut self.key, sgx_cmac_128bit_key_t::default()) };
+    }
+}
+
+/// Reads the next record of a log, checking it against `chain`.
+///
+/// Returns `None` at the end of the log.
+fn read_record(file: &mut SgxFile, chain: &mut MacChain) -> io::Result<Option<Vec<u8>>> {
+    const TRUNCATED: io::Error =
+        io::const_io_error!(io::ErrorKind::InvalidData, "log ends within a record");
+
+    let mut len = [0_u8; 4];
+    match file.read(&mut len[..1])? {
+        0 => return Ok(None),
+        _ => file.read_exact(&mut len[1..]).map_err(|_| TRUNCATED)?,
+    }
+    let mut record = vec![0_u8; u32::from_le_bytes(len) as usize];
+    file.read_exact(&mut record).map_err(|_| TRUNCATED)?;
+    let mut mac = sgx_cmac_128bit_tag_t::default();
+    file.read_exact(&mut mac).map_err(|_| TRUNCATED)?;
+
+    let expected = chain.mac(&record)?;
+    // Compare in constant time so the check doesn't tell how much of a
+    // forged MAC is right.
+    if expected.iter().zip(mac.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) != 0 {
+        return Err(io::const_io_error!(
+            io::ErrorKind::InvalidData,
+            "log record failed verification"
+        ));
+    }
+    chain.advance(mac);
+    Ok(Some(record))
+}
+
+impl AppendOnlyLog {
+    /// Opens the log at `path`, encrypted with the key of `policy` and
+    /// chained with `mac_key`, creating it if it doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `InvalidData` is returned if a record fails
+    /// verification or the log ends within a record.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        policy: &KeyPolicy,
+        mac_key: &sgx_cmac_128bit_key_t,
+    ) -> io::Result<AppendOnlyLog> {
+        let mut opts = OpenOptions::new();
+        opts.append(true).update(true);
+        let mut file = match policy.key(path.as_ref(), true)? {
+            Some(key) => opts.open_ex(path, &key.0)?,
+            None => opts.open(path)?,
+        };
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut chain = MacChain::new(mac_key);
+        while read_record(&mut file, &mut chain)?.is_some() {}
+        Ok(AppendOnlyLog { file, chain })
+    }
+
+    /// Replays the records of the log at `path`, in order, verifying each
+    /// of them.
+    pub fn replay<P: AsRef<Path>>(
+        path: P,
+        policy: &KeyPolicy,
+        mac_key: &sgx_cmac_128bit_key_t,
+    ) -> io::Result<LogReplay> {
+        Ok(LogReplay {
+            file: SgxFile::open_with_policy(path, policy)?,
+            chain: MacChain::new(mac_key),
+            done: false,
+        })
+    }
+
+    /// Appends a record to the log, returning its sequence number.
+    ///
+    /// The record is only committed once the log has been synced.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `InvalidInput` is returned if the record is 4 GiB or
+    /// larger.
+    pub fn append(&mut self, record: &[u8]) -> io::Result<u64> {
+        if record.len() > u32::MAX as usize {
+            return Err(io::const_io_error!(io::ErrorKind::InvalidInput, "log record is too large"));
+        }
+        let mac = self.chain.mac(record)?;
+        let mut buf = Vec::with_capacity(4 + record.len() + mac.len());
+        buf.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        buf.extend_from_slice(record);
+        buf.extend_from_slice(&mac);
+        // Write the record in one go so a failed write doesn't leave the
+        // chain ahead of the file.
+        self.file.write_all(&buf)?;
+
+        let seq = self.chain.records;
+        self.chain.advance(mac);
+        Ok(seq)
+    }
+
+    /// Commits the appended records to disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    /// Returns the MAC of the last record, which authenticates the whole log.
+    ///
+    /// This is all zeroes for an empty log.
+    pub fn head(&self) -> sgx_cmac_128bit_tag_t {
+        self.chain.head
+    }
+
+    /// Returns the number of records in the log.
+    pub fn len(&self) -> u64 {
+        self.chain.records
+    }
+
+    /// Returns whether the log has no records.
+    pub fn is_empty(&self) -> bool {
+        self.chain.records == 0
+    }
+}
+
+/// Iterator over the records of a log, as returned by
+/// `AppendOnlyLog::replay`.
+///
+/// The iterator stops after the first error.
+pub struct LogReplay {
+    file: SgxFile,
+    chain: MacChain,
+    done: bool,
+}
+
+impl LogReplay {
+    /// Returns the MAC of the last record replayed so far.
+    pub fn head(&self) -> sgx_cmac_128bit_tag_t {
+        self.chain.head
+    }
+
+    /// Returns the number of records replayed so far.
+    pub fn records(&self) -> u64 {
+        self.chain.records
+    }
+}
+
+impl Iterator for LogReplay {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        if self.done {
+            return None;
+        }
+        let record = read_record(&mut self.file, &mut self.chain).transpose();
+        if !matches!(record, Some(Ok(_))) {
+            self.done = true;
+        }
+        record
+    }
+}
+
+/// Protects `FILE_LOCKS`.
+static FILE_LOCKS_LOCK: SgxThreadMutex = SgxThreadMutex::new();
+/// Signalled whenever a file lock is released.
+static FILE_LOCKS_RELEASED: SgxThreadCondvar = SgxThreadCondvar::new();
+/// The file locks held by threads of the enclave, as the path, the number of
+/// shared holders and whether it's held exclusively.
+static mut FILE_LOCKS: Vec<(PathBuf, usize, bool)> = Vec::new();
+
+/// Takes the lock of the enclave on `path`, waiting for it if `block`.
+fn lock_in_enclave(path: &Path, exclusive: bool, block: bool) -> io::Result<()> {
+    unsafe {
+        let _ = FILE_LOCKS_LOCK.lock();
+        let result = loop {
+            let index = match FILE_LOCKS.iter().position(|(p, _, _)| p == path) {
+                Some(index) => index,
+                None => {
+                    FILE_LOCKS.push((path.to_path_buf(), 0, false));
+                    FILE_LOCKS.len() - 1
+                }
+            };
+            let (_, ref mut shared, ref mut held) = FILE_LOCKS[index];
+            if !*held && (!exclusive || *shared == 0) {
+                if exclusive {
+                    *held = true;
+                } else {
+                    *shared += 1;
+                }
+                break Ok(());
+            }
+            if !block {
+                break Err(io::const_io_error!(
+                    io::ErrorKind::WouldBlock,
+                    "file is locked by another thread of the enclave"
+                ));
+            }
+            let _ = FILE_LOCKS_RELEASED.wait(&FILE_LOCKS_LOCK);
+        };
+        let _ = FILE_LOCKS_LOCK.unlock();
+        result
+    }
+}
+
+fn unlock_in_enclave(path: &Path, exclusive: bool) {
+    unsafe {
+        let _ = FILE_LOCKS_LOCK.lock();
+        if let Some(index) = FILE_LOCKS.iter().position(|(p, _, _)| p == path) {
+            let (_, ref mut shared, ref mut held) = FILE_LOCKS[index];
+            if exclusive {
+                *held = false;
+            } else {
+                *shared -= 1;
+            }
+            if *shared == 0 && !*held {
+                FILE_LOCKS.swap_remove(index);
+            }
+        }
+        let _ = FILE_LOCKS_RELEASED.broadcast();
+        let _ = FILE_LOCKS_LOCK.unlock();
+    }
+}
+
+/// An advisory lock on a protected file, released when dropped.
+///
+/// The lock is taken in two places. A table inside the enclave keeps its own
+/// threads apart without trusting the host, and a `flock` on the host file,
+/// through an ocall, keeps other enclave instances out. Like any advisory
+/// lock it only excludes code which takes it too.
+///
+/// Files are identified by their path as given, so all users of a file have
+/// to spell its path the same way.
+pub struct FileLock {
+    path: PathBuf,
+    exclusive: bool,
+    host: Option<fs_imp::HostLock>,
+}
+
+impl FileLock {
+    fn acquire(path: &Path, exclusive: bool, block: bool) -> io::Result<FileLock> {
+        let path = &*checked_path(path)?;
+        lock_in_enclave(path, exclusive, block)?;
+        match fs_imp::HostLock::lock(path, exclusive, !block) {
+            Ok(host) => Ok(FileLock {
+                path: path.to_path_buf(),
+                exclusive,
+                host: Some(host),
+            }),
+            Err(e) => {
+                unlock_in_enclave(path, exclusive);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns whether the lock is held exclusively.
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Release the host lock first, so a thread woken up below finds it
+        // free.
+        drop(self.host.take());
+        unlock_in_enclave(&self.path, self.exclusive);
+    }
+}
+
+impl fmt::Debug for FileLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileLock")
+            .field("path", &self.path)
+            .field("exclusive", &self.exclusive)
+            .finish()
+    }
+}
+
+impl SgxFile {
+    /// Locks the file at `path` exclusively, waiting until no one else holds
+    /// a lock on it.
+    pub fn lock_exclusive<P: AsRef<Path>>(path: P) -> io::Result<FileLock> {
+        FileLock::acquire(path.as_ref(), true, true)
+    }
+
+    /// Locks the file at `path` shared with other readers, waiting until no
+    /// one holds it exclusively.
+    pub fn lock_shared<P: AsRef<Path>>(path: P) -> io::Result<FileLock> {
+        FileLock::acquire(path.as_ref(), false, true)
+    }
+
+    /// Locks the file at `path` exclusively if no one else holds a lock on
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `WouldBlock` is returned if the file is locked.
+    pub fn try_lock<P: AsRef<Path>>(path: P) -> io::Result<FileLock> {
+        FileLock::acquire(path.as_ref(), true, false)
+    }
+
+    /// Locks the file at `path` shared with other readers if no one holds it
+    /// exclusively.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `WouldBlock` is returned if the file is locked
+    /// exclusively.
+    pub fn try_lock_shared<P: AsRef<Path>>(path: P) -> io::Result<FileLock> {
+        FileLock::acquire(path.as_ref(), false, false)
+    }
+}
+
+/// Name of the manifest listing the protected files of a directory.
+const MANIFEST_NAME: &str = ".sgxfs-manifest";
+
+/// The list of protected files stored in a directory.
+///
+/// The directory listing comes from the host, which could hide files from
+/// the enclave or make up new ones. A manifest is a protected file kept in
+/// the directory alongside the files it lists, so the enclave can check the
+/// listing against it: [`read_dir`] fails if a listed file is missing and
+/// ignores files which aren't listed.
+///
+/// The manifest isn't updated by itself, files have to be added and removed
+/// and the manifest saved again whenever the set of files changes. Saving
+/// goes through a `Transaction`. Note that like any protected file the
+/// manifest can still be replaced by an older version of itself.
+///
+/// Protected files are only bound to their file names, so the manifest also
+/// records the path of its directory, with `.` and `..` resolved, and
+/// opening it from another directory fails: the host can't swap in the
+/// manifest of another directory of the enclave. The directory has to be
+/// opened with the same path, relative or absolute, as it was saved with.
+///
+/// [`read_dir`]: fn.read_dir.html
+#[derive(Clone, Debug)]
+pub struct Manifest {
+    dir: PathBuf,
+    key: Option<sgx_key_128bit_t>,
+    entries: BTreeSet<OsString>,
+}
+
+impl Manifest {
+    /// Creates an empty manifest for `dir`, encrypted with the enclave's
+    /// auto key.
+    ///
+    /// Nothing is written until the manifest is saved.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Manifest {
+        Manifest {
+            dir: dir.as_ref().to_path_buf(),
+            key: None,
+            entries: BTreeSet::new(),
+        }
+    }
+
+    /// Creates an empty manifest for `dir`, encrypted with `key`.
+    pub fn new_ex<P: AsRef<Path>>(dir: P, key: &sgx_key_128bit_t) -> Manifest {
+        Manifest {
+            key: Some(*key),
+            ..Manifest::new(dir)
+        }
+    }
+
+    /// Reads the manifest of `dir`, encrypted with the enclave's auto key.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Manifest> {
+        Manifest::new(dir).load()
+    }
+
+    /// Reads the manifest of `dir`, encrypted with `key`.
+    pub fn open_ex<P: AsRef<Path>>(dir: P, key: &sgx_key_128bit_t) -> io::Result<Manifest> {
+        Manifest::new_ex(dir, key).load()
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(MANIFEST_NAME)
+    }
+
+    // The path of the directory as recorded in the manifest.
+    fn bound_dir(&self) -> io::Result<Vec<u8>> {
+        Ok(normalize(&self.dir)?.into_os_string().into_vec())
+    }
+
+    fn load(mut self) -> io::Result<Manifest> {
+        let path = self.path();
+        let mut file = match self.key {
+            Some(ref key) => SgxFile::open_ex(&path, key)?,
+            None => SgxFile::open(&path)?,
+        };
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        const INVALID: io::Error =
+            io::const_io_error!(io::ErrorKind::InvalidData, "malformed protected file manifest");
+        let mut data = &data[..];
+        let read_u32 = |data: &mut &[u8]| -> io::Result<u32> {
+            if data.len() < 4 {
+                return Err(INVALID);
+            }
+            let (bytes, rest) = data.split_at(4);
+            *data = rest;
+            Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        };
+        let read_bytes = |data: &mut &[u8]| -> io::Result<Vec<u8>> {
+            let len = read_u32(data)? as usize;
+            if data.len() < len {
+                return Err(INVALID);
+            }
+            let (bytes, rest) = data.split_at(len);
+            *data = rest;
+            Ok(bytes.to_vec())
+        };
+        if read_bytes(&mut data)? != self.bound_dir()? {
+            return Err(io::const_io_error!(
+                io::ErrorKind::InvalidData,
+                "protected file manifest of another directory"
+            ));
+        }
+        let count = read_u32(&mut data)?;
+        for _ in 0..count {
+            self.entries.insert(OsString::from_vec(read_bytes(&mut data)?));
+        }
+        if !data.is_empty() {
+            return Err(INVALID);
+        }
+        Ok(self)
+    }
+
+    /// Writes the manifest to its directory.
+    pub fn save(&self) -> io::Result<()> {
+        let dir = self.bound_dir()?;
+        let mut data = Vec::new();
+        data.extend_from_slice(&(dir.len() as u32).to_le_bytes());
+        data.extend_from_slice(&dir);
+        data.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for name in &self.entries {
+            data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+        }
+
+        let path = self.path();
+        let mut transaction = match self.key {
+            Some(ref key) => Transaction::new_ex(&path, key)?,
+            None => Transaction::new(&path)?,
+        };
+        transaction.write_all(&data)?;
+        transaction.commit()
+    }
+
+    /// Adds the file `name` of the directory to the manifest.
+    ///
+    /// Returns whether the file wasn't listed yet.
+    pub fn insert<S: AsRef<OsStr>>(&mut self, name: S) -> bool {
+        self.entries.insert(name.as_ref().to_os_string())
+    }
+
+    /// Removes the file `name` of the directory from the manifest.
+    ///
+    /// Returns whether the file was listed.
+    pub fn remove<S: AsRef<OsStr>>(&mut self, name: S) -> bool {
+        self.entries.remove(name.as_ref())
+    }
+
+    /// Returns whether the file `name` of the directory is listed.
+    pub fn contains<S: AsRef<OsStr>>(&self, name: S) -> bool {
+        self.entries.contains(name.as_ref())
+    }
+
+    /// Returns an iterator over the names of the listed files.
+    pub fn iter(&self) -> impl Iterator<Item = &OsStr> {
+        self.entries.iter().map(|name| name.as_os_str())
+    }
+
+    /// Lists the files of the directory, checking the listing of the host
+    /// against the manifest.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `NotFound` is returned if a file of the manifest is
+    /// missing from the directory.
+    pub fn read_dir(&self) -> io::Result<ReadDir> {
+        let mut listed = BTreeSet::new();
+        for entry in crate::untrusted::fs::read_dir(checked_path(&self.dir)?)? {
+            listed.insert(entry?.file_name());
+        }
+
+        let mut entries = Vec::with_capacity(self.entries.len());
+        for name in &self.entries {
+            if !listed.contains(name) {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::NotFound,
+                    "a file of the protected file manifest is missing"
+                ));
+            }
+            entries.push(DirEntry { path: self.dir.join(name) });
+        }
+        Ok(ReadDir { inner: entries.into_iter() })
+    }
+}
+
+/// Iterator over the protected files of a directory, as returned by
+/// [`read_dir`].
+///
+/// [`read_dir`]: fn.read_dir.html
+#[derive(Debug)]
+pub struct ReadDir {
+    inner: crate::vec::IntoIter<DirEntry>,
+}
+
+impl Iterator for ReadDir {
+    type Item = DirEntry;
+
+    fn next(&mut self) -> Option<DirEntry> {
+        self.inner.next()
+    }
+}
+
+/// A protected file of a directory, yielded by `ReadDir`.
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    path: PathBuf,
+}
+
+impl DirEntry {
+    /// Returns the full path to the file.
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Returns the name of the file, without any leading path component.
+    pub fn file_name(&self) -> OsString {
+        self.path.file_name().map(OsStr::to_os_string).unwrap_or_default()
+    }
+}
+
+/// Returns an iterator over the protected files of a directory.
+///
+/// The files are those listed in the manifest of the directory, encrypted
+/// with the enclave's auto key, after checking that they are all present.
+/// See `Manifest`.
+pub fn read_dir<P: AsRef<Path>>(dir: P) -> io::Result<ReadDir> {
+    Manifest::open(dir)?.read_dir()
+}
+
+/// Like `read_dir`, for a manifest encrypted with `key`.
+pub fn read_dir_ex<P: AsRef<Path>>(dir: P, key: &sgx_key_128bit_t) -> io::Result<ReadDir> {
+    Manifest::open_ex(dir, key)?.read_dir()
+}
+
+/// The state of a protected file as recorded in a `SealedManifest`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The SHA-256 digest of the contents of the file.
+    pub digest: sgx_sha256_hash_t,
+    /// The number of times the file has been recorded.
+    pub version: u64,
+}
+
+/// A record of the current contents of the protected files of the enclave.
+///
+/// Each protected file is authenticated on its own, so the host can replace
+/// one of them with an older version without the file noticing. Recording
+/// the digest of every file after writing it, in a manifest which is itself
+/// a protected file, lets the enclave check at startup that all its files are
+/// the ones it last wrote.
+///
+/// The manifest can be rolled back as a whole in the same way, along with
+/// all the files. To detect this, keep its `generation`, which is bumped on
+/// every save, in a monotonic counter.
+#[derive(Clone, Debug)]
+pub struct SealedManifest {
+    path: PathBuf,
+    policy: KeyPolicy,
+    generation: u64,
+    entries: BTreeMap<PathBuf, ManifestEntry>,
+}
+
+/// A file which doesn't match its `SealedManifest` entry.
+///
+/// It is wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Clone, Debug)]
+pub struct ManifestMismatch {
+    path: PathBuf,
+}
+
+impl ManifestMismatch {
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl fmt::Display for ManifestMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} doesn't match the sealed manifest", self.path.display())
+    }
+}
+
+impl Error for ManifestMismatch {}
+
+/// Computes the SHA-256 digest of the contents of `file`, from its start.
+fn digest_file(file: &mut SgxFile) -> io::Result<sgx_sha256_hash_t> {
+    const DIGEST_FAILED: io::Error =
+        io::const_io_error!(io::ErrorKind::Other, "failed to compute the file digest");
+
+    file.seek(SeekFrom::Start(0))?;
+    let mut handle: sgx_types::sgx_sha_state_handle_t = ptr::null_mut();
+    if unsafe { sgx_types::sgx_sha256_init(&mut handle) } != sgx_status_t::SGX_SUCCESS {
+        return Err(DIGEST_FAILED);
+    }
+
+    let mut buf = vec![0_u8; 16 * NODE_SIZE];
+    let result = loop {
+        let len = match file.read(&mut buf) {
+            Ok(0) => {
+                let mut digest = sgx_sha256_hash_t::default();
+                break match unsafe { sgx_types::sgx_sha256_get_hash(handle, &mut digest) } {
+                    sgx_status_t::SGX_SUCCESS => Ok(digest),
+                    _ => Err(DIGEST_FAILED),
+                };
+            }
+            Ok(len) => len,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => break Err(e),
+        };
+        let status = unsafe { sgx_types::sgx_sha256_update(buf.as_ptr(), len as u32, handle) };
+        if status != sgx_status_t::SGX_SUCCESS {
+            break Err(DIGEST_FAILED);
+        }
+    };
+    unsafe { sgx_types::sgx_sha256_close(handle) };
+    result
+}
+
+impl SealedManifest {
+    /// Creates an empty manifest stored at `path`, encrypted with the key of
+    /// `policy`.
+    ///
+    /// Nothing is written until the manifest is saved.
+    pub fn new<P: AsRef<Path>>(path: P, policy: &KeyPolicy) -> SealedManifest {
+        SealedManifest {
+            path: path.as_ref().to_path_buf(),
+            policy: *policy,
+            generation: 0,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the manifest stored at `path`, encrypted with the key of
+    /// `policy`.
+    pub fn open<P: AsRef<Path>>(path: P, policy: &KeyPolicy) -> io::Result<SealedManifest> {
+        const INVALID: io::Error =
+            io::const_io_error!(io::ErrorKind::InvalidData, "malformed sealed manifest");
+        fn take<'a>(data: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+            if data.len() < len {
+                return Err(INVALID);
+            }
+            let (bytes, rest) = data.split_at(len);
+            *data = rest;
+            Ok(bytes)
+        }
+        fn take_u64(data: &mut &[u8]) -> io::Result<u64> {
+            let mut bytes = [0_u8; 8];
+            bytes.copy_from_slice(take(data, 8)?);
+            Ok(u64::from_le_bytes(bytes))
+        }
+
+        let mut manifest = SealedManifest::new(path, policy);
+        let mut data = Vec::new();
+        SgxFile::open_with_policy(&manifest.path, policy)?.read_to_end(&mut data)?;
+
+        let mut data = &data[..];
+        manifest.generation = take_u64(&mut data)?;
+        let count = take_u64(&mut data)?;
+        for _ in 0..count {
+            let len = take_u64(&mut data)? as usize;
+            let path = PathBuf::from(OsString::from_vec(take(&mut data, len)?.to_vec()));
+            let mut digest = sgx_sha256_hash_t::default();
+            digest.copy_from_slice(take(&mut data, digest.len())?);
+            let version = take_u64(&mut data)?;
+            manifest.entries.insert(path, ManifestEntry { digest, version });
+        }
+        if !data.is_empty() {
+            return Err(INVALID);
+        }
+        Ok(manifest)
+    }
+
+    /// Writes the manifest, bumping its generation.
+    pub fn save(&mut self) -> io::Result<()> {
+        let generation = self.generation + 1;
+        let mut data = Vec::new();
+        data.extend_from_slice(&generation.to_le_bytes());
+        data.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        for (path, entry) in &self.entries {
+            let path = path.as_os_str().as_bytes();
+            data.extend_from_slice(&(path.len() as u64).to_le_bytes());
+            data.extend_from_slice(path);
+            data.extend_from_slice(&entry.digest);
+            data.extend_from_slice(&entry.version.to_le_bytes());
+        }
+
+        let mut transaction = Transaction::with_policy(&self.path, &self.policy)?;
+        transaction.write_all(&data)?;
+        transaction.commit()?;
+        self.generation = generation;
+        Ok(())
+    }
+
+    /// Returns the number of times the manifest has been saved.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Records the current contents of the file at `path`, encrypted with the
+    /// key of `policy`, returning its new version.
+    ///
+    /// The manifest has to be saved for the record to last.
+    pub fn record<P: AsRef<Path>>(&mut self, path: P, policy: &KeyPolicy) -> io::Result<u64> {
+        let path = path.as_ref();
+        let digest = digest_file(&mut SgxFile::open_with_policy(path, policy)?)?;
+        let version = self.entries.get(path).map_or(1, |entry| entry.version + 1);
+        self.entries.insert(path.to_path_buf(), ManifestEntry { digest, version });
+        Ok(version)
+    }
+
+    /// Stops recording the file at `path`, returning its last entry.
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> Option<ManifestEntry> {
+        self.entries.remove(path.as_ref())
+    }
+
+    /// Returns the entry of the file at `path`, if it's recorded.
+    pub fn entry<P: AsRef<Path>>(&self, path: P) -> Option<&ManifestEntry> {
+        self.entries.get(path.as_ref())
+    }
+
+    /// Returns an iterator over the recorded files and their entries.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &ManifestEntry)> {
+        self.entries.iter().map(|(path, entry)| (path.as_path(), entry))
+    }
+
+    /// Checks that all recorded files, encrypted with the key of `policy`,
+    /// have the contents they were last recorded with.
+    ///
+    /// # Errors
+    ///
+    /// An error of kind `InvalidData` wrapping a `ManifestMismatch` is
+    /// returned for the first file which is missing or doesn't match.
+    pub fn verify(&self, policy: &KeyPolicy) -> io::Result<()> {
+        self.verify_with(|path| SgxFile::open_with_policy(path, policy))
+    }
+
+    /// Like `verify`, for files opened by `open`.
+    pub fn verify_with<F>(&self, mut open: F) -> io::Result<()>
+    where
+        F: FnMut(&Path) -> io::Result<SgxFile>,
+    {
+        for (path, entry) in &self.entries {
+            let matches = match open(path) {
+                Ok(mut file) => digest_file(&mut file)? == entry.digest,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => false,
+                Err(e) => return Err(e),
+            };
+            if !matches {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    ManifestMismatch { path: path.clone() },
+                ));
+            }
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,105 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! A least-recently-used cache of resolved symbols keyed by frame IP.
+//!
+//! Resolving a frame through libbacktrace walks the DWARF information of the
+//! enclave image every time, which is by far the most expensive part of
+//! printing a backtrace. Long-lived enclaves tend to panic from the same
+//! handful of call sites, so the symbols of recently seen addresses are kept
+//! around and replayed instead of being looked up again.
+
+use crate::sys_common::backtrace::Symbol;
+use crate::vec::Vec;
+
+/// Number of distinct instruction pointers kept in the cache.
+const SYMBOL_CACHE_SIZE: usize = 256;
+
+struct CacheEntry {
+    ip: usize,
+    symbols: Vec<Symbol>,
+}
+
+/// The cache itself. Entries are ordered from least to most recently used.
+pub struct SymbolCache {
+    entries: Vec<CacheEntry>,
+    capacity: usize,
+}
+
+impl SymbolCache {
+    pub const fn new(capacity: usize) -> SymbolCache {
+        SymbolCache {
+            entries: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Looks up the symbols of `ip`, marking the entry as most recently used.
+    pub fn get(&mut self, ip: usize) -> Option<&[Symbol]> {
+        let pos = self.entries.iter().position(|entry| entry.ip == ip)?;
+        let entry = self.entries.remove(pos);
+        self.entries.push(entry);
+        self.entries.last().map(|entry| entry.symbols.as_slice())
+    }
+
+    /// Records the symbols of `ip`, evicting the least recently used entry if
+    /// the cache is full.
+    pub fn insert(&mut self, ip: usize, symbols: Vec<Symbol>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(pos) = self.entries.iter().position(|entry| entry.ip == ip) {
+            self.entries.remove(pos);
+        } else if self.entries.len() >= self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push(CacheEntry { ip, symbols });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries = Vec::new();
+    }
+}
+
+static mut CACHE: SymbolCache = SymbolCache::new(SYMBOL_CACHE_SIZE);
+
+/// Resolves `ip` through the cache, calling `resolve` on a miss.
+///
+/// This is unsynchronized, callers must hold the backtrace lock.
+pub unsafe fn resolve<R>(ip: usize, resolve: R, cb: &mut dyn FnMut(&Symbol))
+where
+    R: FnOnce(&mut dyn FnMut(&Symbol)),
+{
+    if let Some(symbols) = CACHE.get(ip) {
+        symbols.iter().for_each(|symbol| cb(symbol));
+        return;
+    }
+
+    let mut symbols = Vec::new();
+    resolve(&mut |symbol| {
+        symbols.push(symbol.clone());
+        cb(symbol);
+    });
+    CACHE.insert(ip, symbols);
+}
+
+/// Drops every cached symbol.
+///
+/// This is unsynchronized, callers must hold the backtrace lock.
+pub unsafe fn clear() {
+    CACHE.clear();
+}
@@ -0,0 +1,108 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Collapsing of repeated identical backtraces.
+//!
+//! Worker loops which retry after a panic tend to print the very same
+//! backtrace over and over, flooding the ocall logging channel. Traces are
+//! identified by a hash of their instruction pointers, and a trace identical
+//! to the previous one is only summarized instead of being printed again.
+
+use crate::sys::backtrace::Frame;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Incrementally hashes the instruction pointers of a trace with FNV-1a.
+pub struct TraceHasher {
+    hash: u64,
+}
+
+impl TraceHasher {
+    pub const fn new() -> TraceHasher {
+        TraceHasher {
+            hash: FNV_OFFSET_BASIS,
+        }
+    }
+
+    pub fn write_frame(&mut self, frame: &Frame) {
+        self.write_ip(frame.ip() as usize)
+    }
+
+    pub fn write_ip(&mut self, ip: usize) {
+        for byte in ip.to_le_bytes() {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// What to do with a trace after comparing it with the previous one.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Verdict {
+    /// The trace differs from the previous one and should be printed. The
+    /// previous trace was repeated `unreported` more times than last said.
+    Print { unreported: usize },
+    /// The trace is the `repeats`th repetition of the previous one and should
+    /// only be summarized if `report` is set.
+    Repeated { repeats: usize, report: bool },
+}
+
+/// Tracks the last printed trace and how often it has been repeated since.
+pub struct Deduplicator {
+    last: Option<u64>,
+    repeats: usize,
+    reported: usize,
+}
+
+impl Deduplicator {
+    pub const fn new() -> Deduplicator {
+        Deduplicator {
+            last: None,
+            repeats: 0,
+            reported: 0,
+        }
+    }
+
+    /// Records a trace with the given hash.
+    ///
+    /// Repetitions are reported at exponentially growing intervals, so a
+    /// panic storm costs a logarithmic number of lines.
+    pub fn observe(&mut self, hash: u64) -> Verdict {
+        if self.last == Some(hash) {
+            self.repeats += 1;
+            let report = self.repeats.is_power_of_two();
+            if report {
+                self.reported = self.repeats;
+            }
+            return Verdict::Repeated {
+                repeats: self.repeats,
+                report,
+            };
+        }
+
+        let unreported = self.repeats - self.reported;
+        self.last = Some(hash);
+        self.repeats = 0;
+        self.reported = 0;
+        Verdict::Print { unreported }
+    }
+}
@@ -15,7 +15,7 @@
 // specific language governing permissions and limitations
 // under the License..
 
-pub use self::printing::{BacktraceFmt, BacktraceFrameFmt, PrintFmt};
+pub use self::printing::{BacktraceFmt, BacktraceFrameFmt, IpFormat, PrintFmt};
 /// Backtrace support built on libgcc with some extra OS-specific support
 ///
 /// Some methods of getting a backtrace:
@@ -96,11 +96,17 @@ pub use self::tracing::{trace_unsynchronized, Frame};
 mod tracing;
 // symbol resolvers:
 mod printing;
+// resolved symbol cache:
+pub mod cache;
+// repeated trace collapsing:
+pub mod dedup;
+// allocation failure backtraces:
+pub mod oom;
 
 /// A platform independent representation of a string. When working with `std`
 /// enabled it is recommended to the convenience methods for providing
 /// conversions to `std` types.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum BytesOrWideString<'a> {
     /// A slice, typically provided on Unix platforms.
     Bytes(&'a [u8]),
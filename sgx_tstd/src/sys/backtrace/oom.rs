@@ -0,0 +1,152 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Backtraces for allocation failures.
+//!
+//! Symbolicating a backtrace needs memory, libbacktrace allocates while it
+//! walks the debug info, and that is exactly what is missing once the
+//! allocator fails. A reserve is allocated up front and handed back to the
+//! allocator right before printing. The trace itself is captured into a
+//! fixed-size buffer and printed in the short format, so that printing it
+//! doesn't allocate on its own.
+
+use crate::alloc::{GlobalAlloc, Layout, System};
+use crate::ffi::c_void;
+use crate::fmt;
+use crate::io::{self, Write};
+use crate::ptr;
+use crate::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use crate::sys::backtrace::{self, BacktraceFmt, BytesOrWideString, PrintFmt};
+use crate::sys_common::backtrace::{output_filename, resolve_unsynchronized, try_lock};
+
+/// Size of the memory handed back to the allocator before printing.
+const RESERVE_SIZE: usize = 64 * 1024;
+
+/// Max number of frames printed on allocation failure.
+const MAX_OOM_FRAMES: usize = 32;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RESERVE: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+
+fn reserve_layout() -> Layout {
+    // SAFETY: the size is small and the alignment a power of two.
+    unsafe { Layout::from_size_align_unchecked(RESERVE_SIZE, 16) }
+}
+
+/// Enables or disables printing a backtrace on allocation failure.
+///
+/// Enabling it allocates the emergency reserve, disabling it frees it.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+    if enabled {
+        reserve();
+    } else {
+        release();
+    }
+}
+
+fn reserve() {
+    if !RESERVE.load(Ordering::SeqCst).is_null() {
+        return;
+    }
+    let reserve = unsafe { System.alloc(reserve_layout()) };
+    if reserve.is_null() {
+        return;
+    }
+    if RESERVE
+        .compare_exchange(ptr::null_mut(), reserve, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        unsafe { System.dealloc(reserve, reserve_layout()) };
+    }
+}
+
+fn release() {
+    let reserve = RESERVE.swap(ptr::null_mut(), Ordering::SeqCst);
+    if !reserve.is_null() {
+        unsafe { System.dealloc(reserve, reserve_layout()) };
+    }
+}
+
+/// Prints the backtrace of an allocation failure to `w`, if enabled.
+///
+/// The emergency reserve is freed first and isn't allocated again, only the
+/// first allocation failure gets a symbolicated backtrace.
+pub fn print(w: &mut dyn Write) -> io::Result<()> {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    release();
+
+    // One more frame than printed, so that the formatter can tell the
+    // backtrace was truncated.
+    let mut ips = [ptr::null_mut(); MAX_OOM_FRAMES + 1];
+    let mut len = 0;
+    unsafe {
+        backtrace::trace_unsynchronized(|frame| {
+            ips[len] = frame.ip();
+            len += 1;
+            len < ips.len()
+        });
+    }
+    write!(w, "{}", DisplayOomBacktrace { ips: &ips[..len] })
+}
+
+struct DisplayOomBacktrace<'a> {
+    ips: &'a [*mut c_void],
+}
+
+impl fmt::Display for DisplayOomBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut print_path = |fmt: &mut fmt::Formatter<'_>, bows: BytesOrWideString<'_>| {
+            output_filename(fmt, bows, PrintFmt::Short, None)
+        };
+        writeln!(fmt, "stack backtrace:")?;
+        let mut bt_fmt = BacktraceFmt::new(fmt, PrintFmt::Short, &mut print_path);
+        bt_fmt.set_max_frames(MAX_OOM_FRAMES);
+        bt_fmt.add_context()?;
+
+        // The allocation may have failed while this very thread was printing
+        // a backtrace, in which case the lock is held and the frames are
+        // printed without symbols rather than deadlocking.
+        let lock = unsafe { try_lock() };
+        for &ip in self.ips {
+            let mut hit = false;
+            let mut res = Ok(());
+            if lock.is_some() {
+                unsafe {
+                    resolve_unsynchronized(ip, |symbol| {
+                        hit = true;
+                        res = bt_fmt.frame().print_raw_with_column(
+                            ip,
+                            symbol.name(),
+                            symbol.filename_raw(),
+                            symbol.lineno(),
+                            symbol.colno(),
+                        );
+                    });
+                }
+            }
+            if !hit {
+                res = bt_fmt.frame().print_raw(ip, None, None, None);
+            }
+            res?;
+        }
+        drop(lock);
+        bt_fmt.finish()
+    }
+}
@@ -16,12 +16,23 @@
 // under the License..
 
 use crate::ffi::c_void;
-use crate::fmt;
+use crate::fmt::{self, Write};
+use crate::string::String;
+use crate::sys::backtrace::dedup::TraceHasher;
 use crate::sys::backtrace::{BytesOrWideString, Frame};
 use crate::sys_common::backtrace::{Symbol, SymbolName};
 
 const HEX_WIDTH: usize = 2 + 2 * core::mem::size_of::<usize>();
 
+/// Number of MRENCLAVE bytes printed by `BacktraceFmt::add_context`.
+const MRENCLAVE_PREFIX_LEN: usize = 8;
+
+// ANSI escape sequences used by `PrintFmt::Colored`.
+const COLOR_SYMBOL: &str = "\x1b[32m";
+const COLOR_PATH: &str = "\x1b[35m";
+const COLOR_LINE: &str = "\x1b[33m";
+const COLOR_RESET: &str = "\x1b[0m";
+
 /// A formatter for backtraces.
 ///
 /// This type can be used to print a backtrace regardless of where the backtrace
@@ -30,9 +41,17 @@ const HEX_WIDTH: usize = 2 + 2 * core::mem::size_of::<usize>();
 pub struct BacktraceFmt<'a, 'b> {
     fmt: &'a mut fmt::Formatter<'b>,
     frame_index: usize,
+    entries: usize,
     format: PrintFmt,
     print_path:
         &'a mut (dyn FnMut(&mut fmt::Formatter<'_>, BytesOrWideString<'_>) -> fmt::Result + 'b),
+    frame_filter: Option<&'a mut (dyn FnMut(&Frame, &Symbol) -> bool + 'b)>,
+    max_frames: Option<usize>,
+    truncated: bool,
+    source_line:
+        Option<&'a mut (dyn FnMut(BytesOrWideString<'_>, u32) -> Option<String> + 'b)>,
+    ip_format: IpFormat,
+    demangler: Option<&'a mut (dyn FnMut(&str) -> Option<String> + 'b)>,
 }
 
 /// The styles of printing that we can print
@@ -44,11 +63,103 @@ pub enum PrintFmt {
     Short,
     /// Prints a backtrace that contains all possible information
     Full,
+    /// Prints a backtrace as a single JSON document, one object per symbol
+    Json,
+    /// Prints a `Short` backtrace highlighting symbol names, file paths and
+    /// line numbers with ANSI escape codes
+    Colored,
+    /// Prints a `Short` backtrace on a single line, each frame rendered as
+    /// `symbol@file:line` and separated from its caller by ` <- `
+    Compact,
 
     #[doc(hidden)]
     __Nonexhaustive,
 }
 
+/// How instruction pointers are printed by `PrintFmt::Full` and
+/// `PrintFmt::Json`.
+///
+/// Absolute addresses reveal where the enclave was loaded. The other formats
+/// only print offsets from the enclave base, which can still be symbolicated
+/// offline against the enclave image.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IpFormat {
+    /// Prints the address the instruction pointer was loaded at.
+    Absolute,
+    /// Prints the offset of the instruction pointer from the enclave base.
+    Relative,
+    /// Prints a hash of the offset from the enclave base, salted with the
+    /// given value. Whoever knows the salt can map the hashes back to
+    /// offsets; everybody else can only tell whether two frames are equal.
+    Hashed(u64),
+}
+
+impl IpFormat {
+    /// Renders `frame_ip` in this format.
+    ///
+    /// Rendering doesn't allocate, so that backtraces of allocation failures
+    /// can print instruction pointers.
+    pub fn render(self, frame_ip: *mut c_void) -> RenderedIp {
+        RenderedIp { format: self, ip: frame_ip as usize }
+    }
+}
+
+/// An instruction pointer rendered in an `IpFormat`.
+pub struct RenderedIp {
+    format: IpFormat,
+    ip: usize,
+}
+
+impl fmt::Display for RenderedIp {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Written to a buffer first, so that the width of `fmt` pads all of it.
+        let mut buf = IpBuf { bytes: [0; IpBuf::CAPACITY], len: 0 };
+        let base = sgx_trts::enclave::rsgx_get_enclave_base() as usize;
+        match (self.format, self.ip.checked_sub(base)) {
+            (IpFormat::Absolute, _) => write!(buf, "{:#x}", self.ip)?,
+            (IpFormat::Relative, Some(offset)) => write!(buf, "+{:#x}", offset)?,
+            (IpFormat::Hashed(salt), Some(offset)) => {
+                let mut hasher = TraceHasher::new();
+                hasher.write_ip(salt as usize);
+                hasher.write_ip(offset);
+                write!(buf, "#{:016x}", hasher.finish())?
+            }
+            // Not an address inside the enclave, so there is no offset to
+            // print and nothing worth hiding either way.
+            (_, None) => buf.write_str("?")?,
+        }
+        fmt.pad(buf.as_str())
+    }
+}
+
+/// A fixed buffer holding a rendered instruction pointer.
+struct IpBuf {
+    bytes: [u8; IpBuf::CAPACITY],
+    len: usize,
+}
+
+impl IpBuf {
+    /// Enough for `+0x` and 16 hex digits.
+    const CAPACITY: usize = 24;
+
+    fn as_str(&self) -> &str {
+        // Only whole `str`s are written.
+        crate::str::from_utf8(&self.bytes[..self.len]).unwrap_or("?")
+    }
+}
+
+impl fmt::Write for IpBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
 impl<'a, 'b> BacktraceFmt<'a, 'b> {
     /// Create a new `BacktraceFmt` which will write output to the provided
     /// `fmt`.
@@ -66,18 +177,110 @@ impl<'a, 'b> BacktraceFmt<'a, 'b> {
         BacktraceFmt {
             fmt,
             frame_index: 0,
+            entries: 0,
             format,
             print_path,
+            frame_filter: None,
+            max_frames: None,
+            truncated: false,
+            source_line: None,
+            ip_format: IpFormat::Absolute,
+            demangler: None,
         }
     }
 
+    /// Installs a callback demangling symbol names the built-in demangler
+    /// doesn't recognize.
+    ///
+    /// The callback is given the raw symbol name and returns its demangled
+    /// form, or `None` to print the raw name.
+    pub fn set_demangler(
+        &mut self,
+        demangler: &'a mut (dyn FnMut(&str) -> Option<String> + 'b),
+    ) {
+        self.demangler = Some(demangler);
+    }
+
+    /// Selects how instruction pointers are printed, absolute addresses by
+    /// default.
+    pub fn set_ip_format(&mut self, ip_format: IpFormat) {
+        self.ip_format = ip_format;
+    }
+
+    /// Installs a callback fetching source lines for `PrintFmt::Full`.
+    ///
+    /// The enclave has no access to the source tree, so the callback is given
+    /// the file and line of every symbol and returns the text of that line if
+    /// it can find it. The line is then printed below the location.
+    pub fn set_source_line_provider(
+        &mut self,
+        source_line: &'a mut (dyn FnMut(BytesOrWideString<'_>, u32) -> Option<String> + 'b),
+    ) {
+        self.source_line = Some(source_line);
+    }
+
+    /// Limits the number of frames printed to `max_frames`.
+    ///
+    /// Frames past the limit are dropped and `finish` notes that the
+    /// backtrace was truncated. Callers walking the stack can check
+    /// `is_truncated` to stop unwinding early.
+    pub fn set_max_frames(&mut self, max_frames: usize) {
+        self.max_frames = Some(max_frames);
+    }
+
+    /// Returns whether frames have been dropped because of `set_max_frames`.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Installs a callback deciding which symbols get printed.
+    ///
+    /// The callback is consulted by `BacktraceFrameFmt::symbol` and symbols
+    /// for which it returns `false` are elided from the output entirely,
+    /// without consuming a frame index. Raw frames printed through
+    /// `print_raw` are never filtered since they have no `Symbol`.
+    pub fn set_frame_filter(
+        &mut self,
+        frame_filter: &'a mut (dyn FnMut(&Frame, &Symbol) -> bool + 'b),
+    ) {
+        self.frame_filter = Some(frame_filter);
+    }
+
     /// Prints a preamble for the backtrace about to be printed.
     ///
-    /// This is required on some platforms for backtraces to be fully
-    /// symbolicated later, and otherwise this should just be the first method
-    /// you call after creating a `BacktraceFmt`.
+    /// The preamble identifies where the backtrace comes from: the id of the
+    /// enclave, the current thread, and a prefix of the enclave measurement
+    /// (MRENCLAVE). The thread is identified by the address of its TCS-bound
+    /// thread data, printed according to `set_ip_format`. This should be the
+    /// first method you call after creating a `BacktraceFmt`.
     pub fn add_context(&mut self) -> fmt::Result {
-        Ok(())
+        let enclave_id = crate::enclave::get_enclave_id();
+        let thread = self.ip_format.render(crate::thread::rsgx_thread_self() as *mut c_void);
+        // SAFETY: the report of the enclave is computed once and kept alive
+        // by the trusted runtime.
+        let report = unsafe { &*sgx_types::sgx_self_report() };
+        let mrenclave = HexBytes(&report.body.mr_enclave.m[..MRENCLAVE_PREFIX_LEN]);
+
+        match self.format {
+            PrintFmt::Json => {
+                write!(
+                    self.fmt,
+                    "{{\"context\":{{\"enclave_id\":{},\"thread\":\"{}\",\"mrenclave\":\"{}\"}},",
+                    enclave_id, thread, mrenclave
+                )?;
+                self.fmt.write_str("\"frames\":[")
+            }
+            PrintFmt::Compact => write!(
+                self.fmt,
+                "[enclave {} thread {} mrenclave {}] ",
+                enclave_id, thread, mrenclave
+            ),
+            _ => writeln!(
+                self.fmt,
+                "      enclave {}, thread {}, mrenclave {}",
+                enclave_id, thread, mrenclave
+            ),
+        }
     }
 
     /// Adds a frame to the backtrace output.
@@ -89,15 +292,28 @@ impl<'a, 'b> BacktraceFmt<'a, 'b> {
         BacktraceFrameFmt {
             fmt: self,
             symbol_index: 0,
+            elided: false,
         }
     }
 
     /// Completes the backtrace output.
     ///
-    /// This is currently a no-op but is added for future compatibility with
-    /// backtrace formats.
+    /// This closes the JSON document for `PrintFmt::Json`, ends the line for
+    /// `PrintFmt::Compact` and notes frames dropped by `set_max_frames` for
+    /// the other formats.
     pub fn finish(&mut self) -> fmt::Result {
-        // Currently a no-op-- including this hook to allow for future additions.
+        match (self.format, self.max_frames) {
+            (PrintFmt::Json, _) if self.truncated => {
+                self.fmt.write_str("],\"truncated\":true}\n")?
+            }
+            (PrintFmt::Json, _) => self.fmt.write_str("]}\n")?,
+            (PrintFmt::Compact, _) if self.truncated => self.fmt.write_str(" <- ...\n")?,
+            (PrintFmt::Compact, _) => self.fmt.write_str("\n")?,
+            (_, Some(max_frames)) if self.truncated => {
+                writeln!(self.fmt, "note: backtrace truncated after {} frames", max_frames)?
+            }
+            _ => {}
+        }
         Ok(())
     }
 }
@@ -108,12 +324,22 @@ impl<'a, 'b> BacktraceFmt<'a, 'b> {
 pub struct BacktraceFrameFmt<'fmt, 'a, 'b> {
     fmt: &'fmt mut BacktraceFmt<'a, 'b>,
     symbol_index: usize,
+    elided: bool,
 }
 
 impl BacktraceFrameFmt<'_, '_, '_> {
     /// Prints a raw traced `Frame` and `Symbol`, typically from within the raw
     /// callbacks of this crate.
+    ///
+    /// If a frame filter is installed and rejects this symbol nothing is
+    /// printed.
     pub fn symbol(&mut self, frame: &Frame, symbol: &Symbol) -> fmt::Result {
+        if let Some(filter) = self.fmt.frame_filter.as_mut() {
+            if !filter(frame, symbol) {
+                self.elided = true;
+                return Ok(());
+            }
+        }
         self.print_raw_with_column(
             frame.ip(),
             symbol.name(),
@@ -168,19 +394,40 @@ impl BacktraceFrameFmt<'_, '_, '_> {
     ) -> fmt::Result {
         // No need to print "null" frames, it basically just means that the
         // system backtrace was a bit eager to trace back super far.
-        if let PrintFmt::Short = self.fmt.format {
+        if let PrintFmt::Short | PrintFmt::Colored | PrintFmt::Compact = self.fmt.format {
             if frame_ip.is_null() {
                 return Ok(());
             }
         }
 
+        if let Some(max_frames) = self.fmt.max_frames {
+            if self.fmt.frame_index >= max_frames {
+                self.fmt.truncated = true;
+                return Ok(());
+            }
+        }
+
+        match self.fmt.format {
+            PrintFmt::Json => {
+                return self.print_json(frame_ip, symbol_name, filename, lineno, colno);
+            }
+            PrintFmt::Compact => return self.print_compact(symbol_name, filename, lineno),
+            _ => {}
+        }
+
         // Print the index of the frame as well as the optional instruction
         // pointer of the frame. If we're beyond the first symbol of this frame
         // though we just print appropriate whitespace.
         if self.symbol_index == 0 {
             write!(self.fmt.fmt, "{:4}: ", self.fmt.frame_index)?;
-            if let PrintFmt::Full = self.fmt.format {
-                write!(self.fmt.fmt, "{:1$?} - ", frame_ip, HEX_WIDTH)?;
+            match (self.fmt.format, self.fmt.ip_format) {
+                (PrintFmt::Full, IpFormat::Absolute) => {
+                    write!(self.fmt.fmt, "{:1$?} - ", frame_ip, HEX_WIDTH)?
+                }
+                (PrintFmt::Full, ip_format) => {
+                    write!(self.fmt.fmt, "{:>1$} - ", ip_format.render(frame_ip), HEX_WIDTH)?
+                }
+                _ => {}
             }
         } else {
             write!(self.fmt.fmt, "      ")?;
@@ -190,23 +437,62 @@ impl BacktraceFrameFmt<'_, '_, '_> {
         }
 
         // Next up write out the symbol name, using the alternate formatting for
-        // more information if we're a full backtrace. Here we also handle
+        // more information if we're a full backtrace. Names demangled by a
+        // custom demangler are printed as they come. Here we also handle
         // symbols which don't have a name,
-        match (symbol_name, &self.fmt.format) {
-            (Some(name), PrintFmt::Short) => write!(self.fmt.fmt, "{:#}", name)?,
-            (Some(name), PrintFmt::Full) => write!(self.fmt.fmt, "{}", name)?,
-            (None, _) | (_, PrintFmt::__Nonexhaustive) => write!(self.fmt.fmt, "<unknown>")?,
+        let demangled = symbol_name.as_ref().and_then(|name| self.custom_demangle(name));
+        match (demangled, symbol_name, &self.fmt.format) {
+            (Some(name), _, PrintFmt::Colored) => {
+                write!(self.fmt.fmt, "{}{}{}", COLOR_SYMBOL, name, COLOR_RESET)?
+            }
+            (Some(name), _, PrintFmt::Short) | (Some(name), _, PrintFmt::Full) => {
+                self.fmt.fmt.write_str(&name)?
+            }
+            (None, Some(name), PrintFmt::Short) => write!(self.fmt.fmt, "{:#}", name)?,
+            (None, Some(name), PrintFmt::Full) => write!(self.fmt.fmt, "{}", name)?,
+            (None, Some(name), PrintFmt::Colored) => {
+                write!(self.fmt.fmt, "{}{:#}{}", COLOR_SYMBOL, name, COLOR_RESET)?
+            }
+            (_, None, _)
+            | (_, _, PrintFmt::Json)
+            | (_, _, PrintFmt::Compact)
+            | (_, _, PrintFmt::__Nonexhaustive) => write!(self.fmt.fmt, "<unknown>")?,
         }
         self.fmt.fmt.write_str("\n")?;
 
         // And last up, print out the filename/line number if they're available.
         if let (Some(file), Some(line)) = (filename, lineno) {
             self.print_fileline(file, line, colno)?;
+            if let PrintFmt::Full = self.fmt.format {
+                self.print_source_line(file, line)?;
+            }
         }
 
         Ok(())
     }
 
+    fn custom_demangle(&mut self, name: &SymbolName<'_>) -> Option<String> {
+        if name.is_demangled() {
+            return None;
+        }
+        let demangler = self.fmt.demangler.as_mut()?;
+        demangler(name.as_str()?)
+    }
+
+    fn print_source_line(&mut self, file: BytesOrWideString<'_>, line: u32) -> fmt::Result {
+        let source = match self.fmt.source_line.as_mut() {
+            Some(source_line) => source_line(file, line),
+            None => return Ok(()),
+        };
+
+        // The snippet is aligned with the location printed above it.
+        if let Some(source) = source {
+            write!(self.fmt.fmt, "{:1$}", "", HEX_WIDTH)?;
+            writeln!(self.fmt.fmt, "             {:>5} | {}", line, source.trim_end())?;
+        }
+        Ok(())
+    }
+
     fn print_fileline(
         &mut self,
         file: BytesOrWideString<'_>,
@@ -220,23 +506,158 @@ impl BacktraceFrameFmt<'_, '_, '_> {
         }
         write!(self.fmt.fmt, "             at ")?;
 
+        let colored = self.fmt.format == PrintFmt::Colored;
+
         // Delegate to our internal callback to print the filename and then
         // print out the line number.
+        if colored {
+            self.fmt.fmt.write_str(COLOR_PATH)?;
+        }
         (self.fmt.print_path)(self.fmt.fmt, file)?;
-        write!(self.fmt.fmt, ":{}", line)?;
+        if colored {
+            write!(self.fmt.fmt, "{}:{}{}", COLOR_RESET, COLOR_LINE, line)?;
+        } else {
+            write!(self.fmt.fmt, ":{}", line)?;
+        }
 
         // Add column number, if available.
         if let Some(colno) = colno {
             write!(self.fmt.fmt, ":{}", colno)?;
         }
+        if colored {
+            self.fmt.fmt.write_str(COLOR_RESET)?;
+        }
 
         writeln!(self.fmt.fmt)?;
         Ok(())
     }
+
+    fn print_compact(
+        &mut self,
+        symbol_name: Option<SymbolName<'_>>,
+        filename: Option<BytesOrWideString<'_>>,
+        lineno: Option<u32>,
+    ) -> fmt::Result {
+        // Frames are innermost first, so each one is followed by its caller.
+        if self.fmt.entries > 0 {
+            self.fmt.fmt.write_str(" <- ")?;
+        }
+        self.fmt.entries += 1;
+
+        let demangled = symbol_name.as_ref().and_then(|name| self.custom_demangle(name));
+        match (demangled, symbol_name) {
+            (Some(name), _) => self.fmt.fmt.write_str(&name)?,
+            (None, Some(name)) => write!(self.fmt.fmt, "{:#}", name)?,
+            (None, None) => self.fmt.fmt.write_str("<unknown>")?,
+        }
+        if let (Some(file), Some(line)) = (filename, lineno) {
+            self.fmt.fmt.write_str("@")?;
+            (self.fmt.print_path)(self.fmt.fmt, file)?;
+            write!(self.fmt.fmt, ":{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn print_json(
+        &mut self,
+        frame_ip: *mut c_void,
+        symbol_name: Option<SymbolName<'_>>,
+        filename: Option<BytesOrWideString<'_>>,
+        lineno: Option<u32>,
+        colno: Option<u32>,
+    ) -> fmt::Result {
+        // Entries are separated by commas, the surrounding array is opened in
+        // `add_context` and closed in `finish`.
+        if self.fmt.entries > 0 {
+            self.fmt.fmt.write_str(",")?;
+        }
+        self.fmt.entries += 1;
+
+        write!(
+            self.fmt.fmt,
+            "{{\"index\":{},\"ip\":\"{}\",\"symbol\":",
+            self.fmt.frame_index,
+            self.fmt.ip_format.render(frame_ip)
+        )?;
+        let demangled = symbol_name.as_ref().and_then(|name| self.custom_demangle(name));
+        match (demangled, symbol_name) {
+            (Some(name), _) => {
+                self.fmt.fmt.write_str("\"")?;
+                JsonEscape(self.fmt.fmt).write_str(&name)?;
+                self.fmt.fmt.write_str("\"")?;
+            }
+            (None, Some(name)) => {
+                self.fmt.fmt.write_str("\"")?;
+                write!(JsonEscape(self.fmt.fmt), "{}", name)?;
+                self.fmt.fmt.write_str("\"")?;
+            }
+            (None, None) => self.fmt.fmt.write_str("null")?,
+        }
+
+        // Paths are escaped here rather than going through `print_path`,
+        // which writes straight to the formatter.
+        self.fmt.fmt.write_str(",\"file\":")?;
+        match filename {
+            Some(BytesOrWideString::Bytes(bytes)) => {
+                self.fmt.fmt.write_str("\"")?;
+                JsonEscape(self.fmt.fmt).write_str(&String::from_utf8_lossy(bytes))?;
+                self.fmt.fmt.write_str("\"")?;
+            }
+            Some(BytesOrWideString::Wide(_)) => self.fmt.fmt.write_str("\"<unknown>\"")?,
+            None => self.fmt.fmt.write_str("null")?,
+        }
+
+        self.fmt.fmt.write_str(",\"line\":")?;
+        write_json_u32(self.fmt.fmt, lineno)?;
+        self.fmt.fmt.write_str(",\"column\":")?;
+        write_json_u32(self.fmt.fmt, colno)?;
+        self.fmt.fmt.write_str("}")
+    }
+}
+
+/// Prints a byte slice as lowercase hex.
+struct HexBytes<'a>(&'a [u8]);
+
+impl fmt::Display for HexBytes<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(fmt, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_json_u32(fmt: &mut fmt::Formatter<'_>, value: Option<u32>) -> fmt::Result {
+    match value {
+        Some(value) => write!(fmt, "{}", value),
+        None => fmt.write_str("null"),
+    }
+}
+
+/// Escapes everything written through it as the contents of a JSON string.
+struct JsonEscape<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl fmt::Write for JsonEscape<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '"' => self.0.write_str("\\\"")?,
+                '\\' => self.0.write_str("\\\\")?,
+                '\n' => self.0.write_str("\\n")?,
+                '\r' => self.0.write_str("\\r")?,
+                '\t' => self.0.write_str("\\t")?,
+                c if (c as u32) < 0x20 => write!(self.0, "\\u{:04x}", c as u32)?,
+                c => self.0.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Drop for BacktraceFrameFmt<'_, '_, '_> {
     fn drop(&mut self) {
-        self.fmt.frame_index += 1;
+        if !self.elided || self.symbol_index > 0 {
+            self.fmt.frame_index += 1;
+        }
     }
 }
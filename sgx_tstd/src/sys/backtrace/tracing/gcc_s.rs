@@ -73,7 +73,8 @@ impl Frame {
     }
 
     pub fn module_base_address(&self) -> Option<*mut c_void> {
-        None
+        // Everything we can unwind through lives in the enclave image.
+        Some(sgx_trts::enclave::rsgx_get_enclave_base() as *mut c_void)
     }
 }
 
@@ -18,6 +18,7 @@
 use crate::os::unix::prelude::*;
 
 use crate::ffi::{CStr, CString, OsStr, OsString};
+use crate::fs::{VfsMetadata, VfsOpenOptions};
 use crate::fmt;
 use crate::io::{self, Error, IoSlice, IoSliceMut, ReadBuf, SeekFrom};
 use crate::mem;
@@ -95,6 +96,13 @@ impl FileAttr {
     fn from_stat64(stat: stat64) -> Self {
         Self { stat }
     }
+
+    pub fn from_vfs(metadata: VfsMetadata) -> Self {
+        let mut stat: stat64 = unsafe { mem::zeroed() };
+        stat.st_size = metadata.len as off64_t;
+        stat.st_mode = if metadata.is_dir { libc::S_IFDIR | 0o755 } else { libc::S_IFREG | 0o644 };
+        Self { stat }
+    }
 }
 
 impl FileAttr {
@@ -295,6 +303,17 @@ impl OpenOptions {
         }
     }
 
+    pub fn to_vfs(&self) -> VfsOpenOptions {
+        VfsOpenOptions {
+            read: self.read,
+            write: self.write,
+            append: self.append,
+            truncate: self.truncate,
+            create: self.create,
+            create_new: self.create_new,
+        }
+    }
+
     pub fn read(&mut self, read: bool) {
         self.read = read;
     }
@@ -408,6 +427,23 @@ impl File {
         cvt_r(|| unsafe { libc::ftruncate64(self.as_raw_fd(), size) }).map(drop)
     }
 
+    pub fn allocate(&self, offset: u64, len: u64) -> io::Result<()> {
+        self.fallocate(0, offset, len)
+    }
+
+    pub fn punch_hole(&self, offset: u64, len: u64) -> io::Result<()> {
+        self.fallocate(libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE, offset, len)
+    }
+
+    fn fallocate(&self, mode: c_int, offset: u64, len: u64) -> io::Result<()> {
+        use crate::convert::TryInto;
+        let offset: off64_t =
+            offset.try_into().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let len: off64_t =
+            len.try_into().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        cvt_r(|| unsafe { libc::fallocate64(self.as_raw_fd(), mode, offset, len) }).map(drop)
+    }
+
     pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
         self.0.read(buf)
     }
@@ -747,9 +783,9 @@ pub fn chroot(_dir: &Path) -> io::Result<()> {
 
 mod libc {
     pub use sgx_libc::ocall::{
-        chmod, closedir, dirfd, fchmod, fcntl_arg0, fdatasync, free, fstat64, fstatat64, fsync,
-        ftruncate64, linkat, lseek64, lstat64, mkdir, open64, opendir, readdir64_r, readlink,
-        realpath, rename, rmdir, stat64, symlink, unlink,
+        chmod, closedir, dirfd, fallocate64, fchmod, fcntl_arg0, fdatasync, free, fstat64,
+        fstatat64, fsync, ftruncate64, linkat, lseek64, lstat64, mkdir, open64, opendir,
+        readdir64_r, readlink, realpath, rename, rmdir, stat64, symlink, unlink,
     };
     pub use sgx_libc::*;
 }
@@ -181,7 +181,7 @@ impl Socket {
         self.0.duplicate().map(Socket)
     }
 
-    fn recv_with_flags(&self, buf: &mut [u8], flags: c_int) -> io::Result<usize> {
+    pub fn recv_with_flags(&self, buf: &mut [u8], flags: c_int) -> io::Result<usize> {
         let ret = cvt(unsafe {
             libc::recv(self.as_raw_fd(), buf.as_mut_ptr() as *mut c_void, buf.len(), flags)
         })?;
@@ -398,6 +398,42 @@ impl FromRawFd for Socket {
     }
 }
 
+/// Waits for readiness events on `fds`, returning the number of descriptors
+/// with events, or 0 if `timeout` elapsed first.
+///
+/// Interrupted waits are resumed with the remaining timeout.
+pub fn poll(fds: &mut [libc::pollfd], timeout: Option<Duration>) -> io::Result<usize> {
+    let start = Instant::now();
+    loop {
+        let timeout = match timeout {
+            Some(timeout) => {
+                let timeout = timeout.saturating_sub(start.elapsed());
+                // Round up, so that a short timeout doesn't turn into a busy loop.
+                let millis = timeout
+                    .as_secs()
+                    .saturating_mul(1_000)
+                    .saturating_add((timeout.subsec_nanos() as u64 + 999_999) / 1_000_000);
+                cmp::min(millis, c_int::MAX as u64) as c_int
+            }
+            None => -1,
+        };
+
+        match unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            // The count comes from the host, and callers index `fds` with it.
+            n if n < 0 || n as usize > fds.len() => {
+                return Err(io::Error::from_raw_os_error(libc::EINVAL));
+            }
+            n => return Ok(n as usize),
+        }
+    }
+}
+
 mod libc {
     pub use sgx_libc::ocall::{
         accept4, connect, gai_strerror, ioctl_arg1, poll, recv, recvfrom, recvmsg, sendmsg, shutdown, socket,
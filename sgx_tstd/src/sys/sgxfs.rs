@@ -19,12 +19,39 @@ use crate::os::unix::prelude::*;
 use crate::ffi::{CString, CStr};
 use crate::io::{self, Error, SeekFrom};
 use crate::path::Path;
+use crate::sync::Arc;
+use crate::sys::{cvt_r, fd::FileDesc};
 use crate::sys_common::FromInner;
 use sgx_libc as libc;
 use sgx_tprotected_fs::{self, SgxFileStream};
 use sgx_types::{sgx_status_t, sgx_key_128bit_t, sgx_align_key_128bit_t};
 
-pub struct SgxFile(SgxFileStream);
+// The stream is shared with the `FlushHandle`s of the file.
+pub struct SgxFile(Arc<SgxFileStream>);
+
+/// A handle flushing a file from another thread.
+///
+/// Owners of handles have to make sure they are dropped before the file, so
+/// that it's closed by its owner.
+#[cfg(feature = "thread")]
+#[derive(Clone)]
+pub struct FlushHandle(Arc<SgxFileStream>);
+
+// The protected FS serializes the operations on a file.
+#[cfg(feature = "thread")]
+unsafe impl Send for FlushHandle {}
+#[cfg(feature = "thread")]
+unsafe impl Sync for FlushHandle {}
+
+#[cfg(feature = "thread")]
+impl FlushHandle {
+    pub fn flush(&self) -> io::Result<()> {
+        self.0.flush().map_err(flush_error)
+    }
+}
+
+/// Size of the data nodes protected files are encrypted in.
+pub const NODE_SIZE: usize = 4096;
 
 #[derive(Clone, Debug)]
 pub struct OpenOptions {
@@ -55,6 +82,12 @@ impl OpenOptions {
     pub fn append(&mut self, append: bool) {
         self.append = append;
     }
+    pub fn is_append(&self) -> bool {
+        self.append
+    }
+    pub fn is_create(&self) -> bool {
+        self.write || self.append
+    }
     pub fn update(&mut self, update: bool) {
         self.update = update;
     }
@@ -101,7 +134,7 @@ impl SgxFile {
             SgxFileStream::open(path, opts, key)
         };
 
-        file.map(SgxFile)
+        file.map(|stream| SgxFile(Arc::new(stream)))
             .map_err(|err| {
                 match err {
                     1 => Error::from_sgx_error(sgx_status_t::SGX_ERROR_UNEXPECTED),
@@ -184,19 +217,63 @@ impl SgxFile {
     }
 
     pub fn flush(&self) -> io::Result<()> {
-        self.0.flush().map_err(|err| {
-            match err {
-                1 => Error::from_sgx_error(sgx_status_t::SGX_ERROR_UNEXPECTED),
-                2 => Error::from_sgx_error(sgx_status_t::SGX_ERROR_INVALID_PARAMETER),
-                3 => Error::from_sgx_error(sgx_status_t::SGX_ERROR_OUT_OF_MEMORY),
-                4 | 5 => Error::from_raw_os_error(err),
-                r if r > 4096 => {
-                    let status = sgx_status_t::from_repr(r as u32).unwrap_or(sgx_status_t::SGX_ERROR_UNEXPECTED);
-                    Error::from_sgx_error(status)
-                },
-                _ => Error::from_raw_os_error(err),
+        self.0.flush().map_err(flush_error)
+    }
+
+    /// Closes the file, failing without closing it if it's still shared with
+    /// a `FlushHandle`.
+    pub fn close(self) -> io::Result<()> {
+        match Arc::try_unwrap(self.0) {
+            Ok(stream) => stream.close().map_err(flush_error),
+            Err(_) => Err(io::const_io_error!(
+                io::ErrorKind::ResourceBusy,
+                "file is still shared with a flush handle"
+            )),
+        }
+    }
+
+    #[cfg(feature = "thread")]
+    pub fn flush_handle(&self) -> FlushHandle {
+        FlushHandle(self.0.clone())
+    }
+
+    pub fn set_len(&self, size: u64) -> io::Result<()> {
+        let pos = self.tell()?;
+        let len = self.seek(SeekFrom::End(0))?;
+
+        // Protected files can't seek past their end, so they are extended by
+        // writing zeroes. There is no way to shrink them.
+        let res = if size < len {
+            Err(io::const_io_error!(
+                io::ErrorKind::Unsupported,
+                "protected files can't be truncated"
+            ))
+        } else {
+            let zeroes = [0_u8; NODE_SIZE];
+            let mut remaining = size - len;
+            let mut res = Ok(());
+            while remaining > 0 {
+                let chunk = remaining.min(NODE_SIZE as u64) as usize;
+                match self.write(&zeroes[..chunk]) {
+                    Ok(0) => {
+                        res = Err(io::const_io_error!(
+                            io::ErrorKind::WriteZero,
+                            "failed to extend protected file"
+                        ));
+                        break;
+                    }
+                    Ok(n) => remaining -= n as u64,
+                    Err(e) => {
+                        res = Err(e);
+                        break;
+                    }
+                }
             }
-        })
+            res
+        };
+
+        self.seek(SeekFrom::Start(pos))?;
+        res
     }
 
     pub fn is_eof(&self) -> bool {
@@ -292,14 +369,96 @@ pub fn import_auto_key(path: &Path, key: &sgx_key_128bit_t) -> io::Result<()> {
     })
 }
 
+fn flush_error(err: i32) -> Error {
+    match err {
+        1 => Error::from_sgx_error(sgx_status_t::SGX_ERROR_UNEXPECTED),
+        2 => Error::from_sgx_error(sgx_status_t::SGX_ERROR_INVALID_PARAMETER),
+        3 => Error::from_sgx_error(sgx_status_t::SGX_ERROR_OUT_OF_MEMORY),
+        4 | 5 => Error::from_raw_os_error(err),
+        r if r > 4096 => {
+            let status = sgx_status_t::from_repr(r as u32).unwrap_or(sgx_status_t::SGX_ERROR_UNEXPECTED);
+            Error::from_sgx_error(status)
+        },
+        _ => Error::from_raw_os_error(err),
+    }
+}
+
 fn cstr(path: &Path) -> io::Result<CString> {
     Ok(CString::new(path.as_os_str().as_bytes())?)
 }
 
 impl FromInner<SgxFileStream> for SgxFile {
     fn from_inner(stream: SgxFileStream) -> SgxFile {
-        SgxFile(stream)
+        SgxFile(Arc::new(stream))
+    }
+}
+
+/// An advisory `flock` on a host file, held through a descriptor of its own
+/// and released when dropped.
+pub struct HostLock(FileDesc);
+
+impl HostLock {
+    pub fn lock(path: &Path, exclusive: bool, nonblocking: bool) -> io::Result<HostLock> {
+        let path = cstr(path)?;
+        let fd = cvt_r(|| unsafe {
+            libc::ocall::open64(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC, 0)
+        })?;
+        let fd = unsafe { FileDesc::from_raw_fd(fd) };
+
+        let mut operation = if exclusive { libc::LOCK_EX } else { libc::LOCK_SH };
+        if nonblocking {
+            operation |= libc::LOCK_NB;
+        }
+        cvt_r(|| unsafe { libc::ocall::flock(fd.as_raw_fd(), operation) })?;
+        Ok(HostLock(fd))
+    }
+}
+
+/// Copies the host file at `from`, as it is on disk, to `to`.
+///
+/// On file systems with reflinks the copy shares the blocks of `from` until
+/// either file is written to; elsewhere the blocks are copied.
+pub fn clone_file(from: &Path, to: &Path) -> io::Result<()> {
+    let open = |path: &Path, flags| -> io::Result<FileDesc> {
+        let path = cstr(path)?;
+        let fd = cvt_r(|| unsafe { libc::ocall::open64(path.as_ptr(), flags, 0o600) })?;
+        Ok(unsafe { FileDesc::from_raw_fd(fd) })
+    };
+    let src = open(from, libc::O_RDONLY | libc::O_CLOEXEC)?;
+    let dst = open(to, libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC)?;
+
+    match cvt_r(|| unsafe { libc::ocall::ficlone(dst.as_raw_fd(), src.as_raw_fd()) }) {
+        Ok(_) => {}
+        Err(e) if matches!(
+            e.raw_os_error(),
+            Some(libc::EOPNOTSUPP | libc::EXDEV | libc::EINVAL | libc::ENOTTY)
+        ) => {
+            let mut buf = vec![0_u8; 16 * NODE_SIZE];
+            loop {
+                let len = match src.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(len) => len,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                let mut written = 0;
+                while written < len {
+                    match dst.write(&buf[written..len]) {
+                        Ok(0) => return Err(io::const_io_error!(
+                            io::ErrorKind::WriteZero,
+                            "failed to write whole buffer"
+                        )),
+                        Ok(n) => written += n,
+                        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+        Err(e) => return Err(e),
     }
+    cvt_r(|| unsafe { libc::ocall::fsync(dst.as_raw_fd()) })?;
+    Ok(())
 }
 
 pub fn copy(from: &Path, to: &Path) -> io::Result<u64> {
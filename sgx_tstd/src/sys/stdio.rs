@@ -110,6 +110,11 @@ pub fn panic_output() -> Option<impl io::Write> {
     Some(Stderr::new())
 }
 
+/// Returns whether the host's stderr refers to a terminal.
+pub fn stderr_is_terminal() -> bool {
+    unsafe { libc::ocall::isatty(libc::STDERR_FILENO) == 1 }
+}
+
 impl AsFd for io::Stdin {
     #[inline]
     fn as_fd(&self) -> BorrowedFd<'_> {
@@ -20,32 +20,215 @@
 pub use crate::sys_common::gnu::*;
 
 use crate::borrow::Cow;
+use crate::env;
 use crate::fmt;
 use crate::io;
 use crate::io::prelude::*;
 use crate::path::{Path, PathBuf};
+use crate::string::String;
+use crate::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use crate::sync::SgxThreadMutex as ThreadMutex;
-use crate::sys::backtrace::{self, BacktraceFmt, BytesOrWideString, PrintFmt};
+use crate::vec::Vec;
+use crate::sys::backtrace::dedup::{Deduplicator, TraceHasher, Verdict};
+use crate::sys::backtrace::{self, BacktraceFmt, BytesOrWideString, Frame, IpFormat, PrintFmt};
 
 /// Max number of frames to print.
 const MAX_NB_FRAMES: usize = 100;
 
-pub unsafe fn lock() -> impl Drop {
-    struct Guard;
-    static LOCK: ThreadMutex = ThreadMutex::new();
+/// Environment variable limiting the number of printed frames.
+const BACKTRACE_LIMIT_VAR: &str = "RUST_BACKTRACE_LIMIT";
 
-    impl Drop for Guard {
-        fn drop(&mut self) {
-            unsafe {
-                LOCK.unlock();
-            }
+// Frame limit applied when printing: 0 if not initialized yet, 1 if there is
+// no limit, and `n + 2` for a limit of `n` frames.
+static MAX_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the maximum number of frames printed, overriding
+/// `RUST_BACKTRACE_LIMIT`.
+pub fn set_max_frames(max_frames: Option<usize>) {
+    let value = match max_frames {
+        Some(n) => n.saturating_add(2),
+        None => 1,
+    };
+    MAX_FRAMES.store(value, Ordering::Relaxed);
+}
+
+/// Returns the maximum number of frames printed.
+///
+/// Unless set through `set_max_frames` this is read once from the
+/// `RUST_BACKTRACE_LIMIT` environment variable of the host.
+pub fn max_frames() -> Option<usize> {
+    match MAX_FRAMES.load(Ordering::Relaxed) {
+        0 => {
+            let max_frames = env::var(BACKTRACE_LIMIT_VAR)
+                .ok()
+                .and_then(|s| s.trim().parse().ok());
+            set_max_frames(max_frames);
+            max_frames
+        }
+        1 => None,
+        n => Some(n - 2),
+    }
+}
+
+/// Filter consulted for every symbol printed by `print`, protected by
+/// `CONFIG_LOCK`.
+static mut FRAME_FILTER: Option<fn(&Frame, &Symbol) -> bool> = None;
+
+/// Whether `print` collapses repeated identical backtraces.
+static DEDUPLICATE: AtomicBool = AtomicBool::new(false);
+
+/// State of the collapsing of repeated backtraces, protected by `lock`.
+static mut DEDUPLICATOR: Deduplicator = Deduplicator::new();
+
+/// Enables or disables collapsing of repeated identical backtraces.
+pub fn set_deduplicate(enabled: bool) {
+    DEDUPLICATE.store(enabled, Ordering::Relaxed);
+}
+
+/// Source line callback used by `print`, protected by `CONFIG_LOCK`.
+static mut SOURCE_LINE_PROVIDER: Option<fn(&Path, u32) -> Option<String>> = None;
+
+/// Sets the callback used to fetch source lines for full backtraces.
+pub fn set_source_line_provider(provider: Option<fn(&Path, u32) -> Option<String>>) {
+    unsafe {
+        let _lock = config_lock();
+        SOURCE_LINE_PROVIDER = provider;
+    }
+}
+
+/// Returns the callback used to fetch source lines for full backtraces.
+pub fn source_line_provider() -> Option<fn(&Path, u32) -> Option<String>> {
+    unsafe {
+        let _lock = config_lock();
+        SOURCE_LINE_PROVIDER
+    }
+}
+
+/// Fetches the source line at `file:line` through `provider`.
+pub fn read_source_line(
+    provider: fn(&Path, u32) -> Option<String>,
+    file: BytesOrWideString<'_>,
+    line: u32,
+) -> Option<String> {
+    match file {
+        BytesOrWideString::Bytes(bytes) => {
+            use crate::os::unix::prelude::*;
+            provider(Path::new(crate::ffi::OsStr::from_bytes(bytes)), line)
         }
+        BytesOrWideString::Wide(_) => None,
+    }
+}
+
+/// Format of the instruction pointers printed by `print`, protected by
+/// `CONFIG_LOCK`.
+static mut IP_FORMAT: IpFormat = IpFormat::Absolute;
+
+/// Sets how instruction pointers are printed in full backtraces.
+pub fn set_ip_format(ip_format: IpFormat) {
+    unsafe {
+        let _lock = config_lock();
+        IP_FORMAT = ip_format;
     }
+}
+
+/// Returns how instruction pointers are printed in full backtraces.
+pub fn ip_format() -> IpFormat {
+    unsafe {
+        let _lock = config_lock();
+        IP_FORMAT
+    }
+}
+
+/// Custom demanglers consulted by `print` in registration order, protected by
+/// `CONFIG_LOCK`.
+static mut DEMANGLERS: Vec<fn(&str) -> Option<String>> = Vec::new();
+
+/// Registers a demangler for symbol names the built-in one doesn't recognize.
+pub fn register_demangler(demangler: fn(&str) -> Option<String>) {
+    unsafe {
+        let _lock = config_lock();
+        DEMANGLERS.push(demangler);
+    }
+}
+
+/// Removes all registered demanglers.
+pub fn clear_demanglers() {
+    unsafe {
+        let _lock = config_lock();
+        DEMANGLERS.clear();
+    }
+}
 
+/// Returns the registered demanglers.
+pub fn demanglers() -> Vec<fn(&str) -> Option<String>> {
+    unsafe {
+        let _lock = config_lock();
+        DEMANGLERS.clone()
+    }
+}
+
+/// Demangles `name` with the first of `demanglers` which recognizes it.
+pub fn demangle(demanglers: &[fn(&str) -> Option<String>], name: &str) -> Option<String> {
+    demanglers.iter().find_map(|demangler| demangler(name))
+}
+
+/// Sets the filter used to elide symbols from printed backtraces.
+pub fn set_frame_filter(filter: Option<fn(&Frame, &Symbol) -> bool>) {
+    unsafe {
+        let _lock = config_lock();
+        FRAME_FILTER = filter;
+    }
+}
+
+fn frame_filter() -> Option<fn(&Frame, &Symbol) -> bool> {
+    unsafe {
+        let _lock = config_lock();
+        FRAME_FILTER
+    }
+}
+
+/// Protects the settings above. It's only held to read or write them, never
+/// while printing, so that a panic hook can change them while the thread it
+/// runs on holds `LOCK`.
+static CONFIG_LOCK: ThreadMutex = ThreadMutex::new();
+
+struct ConfigGuard;
+
+impl Drop for ConfigGuard {
+    fn drop(&mut self) {
+        unsafe {
+            CONFIG_LOCK.unlock();
+        }
+    }
+}
+
+unsafe fn config_lock() -> ConfigGuard {
+    CONFIG_LOCK.lock();
+    ConfigGuard
+}
+
+static LOCK: ThreadMutex = ThreadMutex::new();
+
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        unsafe {
+            LOCK.unlock();
+        }
+    }
+}
+
+pub unsafe fn lock() -> impl Drop {
     LOCK.lock();
     Guard
 }
 
+/// Like `lock`, but returns `None` instead of blocking if the lock is held.
+pub unsafe fn try_lock() -> Option<impl Drop> {
+    LOCK.try_lock().ok().map(|_| Guard)
+}
+
 /// Prints the current backtrace.
 pub fn print(w: &mut dyn Write, format: PrintFmt) -> io::Result<()> {
     // Use a lock to prevent mixed output in multithreading context.
@@ -57,6 +240,31 @@ pub fn print(w: &mut dyn Write, format: PrintFmt) -> io::Result<()> {
 }
 
 unsafe fn _print(w: &mut dyn Write, format: PrintFmt) -> io::Result<()> {
+    if DEDUPLICATE.load(Ordering::Relaxed) {
+        let mut hasher = TraceHasher::new();
+        backtrace::trace_unsynchronized(|frame| {
+            hasher.write_frame(frame);
+            true
+        });
+        match DEDUPLICATOR.observe(hasher.finish()) {
+            Verdict::Repeated { repeats, report } => {
+                if report {
+                    writeln!(
+                        w,
+                        "note: backtrace identical to the last one, repeated {} times",
+                        repeats
+                    )?;
+                }
+                return Ok(());
+            }
+            Verdict::Print { unreported } => {
+                if unreported > 0 {
+                    writeln!(w, "note: last backtrace repeated {} more times", unreported)?;
+                }
+            }
+        }
+    }
+
     struct DisplayBacktrace {
         format: PrintFmt,
     }
@@ -75,15 +283,39 @@ unsafe fn _print_fmt(fmt: &mut fmt::Formatter<'_>, print_fmt: PrintFmt) -> fmt::
     let mut print_path = move |fmt: &mut fmt::Formatter<'_>, bows: BytesOrWideString<'_>| {
         output_filename(fmt, bows, print_fmt, cwd.as_ref())
     };
-    writeln!(fmt, "stack backtrace:")?;
+    match print_fmt {
+        PrintFmt::Json => {}
+        PrintFmt::Compact => write!(fmt, "stack backtrace: ")?,
+        _ => writeln!(fmt, "stack backtrace:")?,
+    }
+    let mut frame_filter = frame_filter();
+    let mut source_line = source_line_provider().map(|provider| {
+        move |file: BytesOrWideString<'_>, line: u32| read_source_line(provider, file, line)
+    });
+    let demanglers = demanglers();
+    let mut demangler = |name: &str| demangle(&demanglers, name);
     let mut bt_fmt = BacktraceFmt::new(fmt, print_fmt, &mut print_path);
+    if let Some(frame_filter) = frame_filter.as_mut() {
+        bt_fmt.set_frame_filter(frame_filter);
+    }
+    if let Some(max_frames) = max_frames() {
+        bt_fmt.set_max_frames(max_frames);
+    }
+    if let Some(source_line) = source_line.as_mut() {
+        bt_fmt.set_source_line_provider(source_line);
+    }
+    bt_fmt.set_ip_format(ip_format());
+    if !demanglers.is_empty() {
+        bt_fmt.set_demangler(&mut demangler);
+    }
     bt_fmt.add_context()?;
     let mut idx = 0;
     let mut res = Ok(());
+    let short = matches!(print_fmt, PrintFmt::Short | PrintFmt::Colored | PrintFmt::Compact);
     // Start immediately if we're not using a short backtrace.
-    let mut start = print_fmt != PrintFmt::Short;
+    let mut start = !short;
     backtrace::trace_unsynchronized(|frame| {
-        if print_fmt == PrintFmt::Short && idx > MAX_NB_FRAMES {
+        if (short && idx > MAX_NB_FRAMES) || bt_fmt.is_truncated() {
             return false;
         }
 
@@ -91,7 +323,7 @@ unsafe fn _print_fmt(fmt: &mut fmt::Formatter<'_>, print_fmt: PrintFmt) -> fmt::
         let mut stop = false;
         resolve_frame_unsynchronized(frame, |symbol| {
             hit = true;
-            if print_fmt == PrintFmt::Short {
+            if short {
                 if let Some(sym) = symbol.name().and_then(|s| s.as_str()) {
                     if start && sym.contains("__rust_begin_short_backtrace") {
                         stop = true;
@@ -120,7 +352,8 @@ unsafe fn _print_fmt(fmt: &mut fmt::Formatter<'_>, print_fmt: PrintFmt) -> fmt::
     });
     res?;
     bt_fmt.finish()?;
-    if print_fmt == PrintFmt::Short {
+    // Compact and JSON backtraces are a single line, or a single document.
+    if matches!(print_fmt, PrintFmt::Short | PrintFmt::Colored) {
         writeln!(
             fmt,
             "note: Some details are omitted, \
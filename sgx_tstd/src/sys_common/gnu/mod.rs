@@ -20,6 +20,7 @@ use crate::fmt;
 use crate::str;
 use crate::sys::backtrace::BytesOrWideString;
 use crate::sys::backtrace::Frame;
+use crate::sys::backtrace::cache;
 
 use sgx_demangle::{try_demangle, Demangle};
 
@@ -77,7 +78,7 @@ pub unsafe fn resolve_unsynchronized<F>(addr: *mut c_void, mut cb: F)
 where
     F: FnMut(&Symbol),
 {
-    resolve_imp(ResolveWhat::Address(addr), &mut cb)
+    resolve_cached(ResolveWhat::Address(addr), &mut cb)
 }
 
 /// Same as `resolve_frame`, only unsafe as it's unsynchronized.
@@ -93,7 +94,12 @@ pub unsafe fn resolve_frame_unsynchronized<F>(frame: &Frame, mut cb: F)
 where
     F: FnMut(&Symbol),
 {
-    resolve_imp(ResolveWhat::Frame(frame), &mut cb)
+    resolve_cached(ResolveWhat::Frame(frame), &mut cb)
+}
+
+unsafe fn resolve_cached(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&Symbol)) {
+    let ip = what.address_or_ip() as usize;
+    cache::resolve(ip, |cb| resolve_imp(what, cb), cb)
 }
 
 /// A trait representing the resolution of a symbol in a file.
@@ -105,10 +111,10 @@ where
 /// A symbol can give contextual information about a function, for example the
 /// name, filename, line number, precise address, etc. Not all information is
 /// always available in a symbol, however, so all methods return an `Option`.
+#[derive(Clone)]
 pub struct Symbol {
-    // TODO: this lifetime bound needs to be persisted eventually to `Symbol`,
-    // but that's currently a breaking change. For now this is safe since
-    // `Symbol` is only ever handed out by reference and can't be cloned.
+    // All fields are owned copies of what libbacktrace reported, which is
+    // what allows symbols to be cloned into the resolution cache.
     name: Option<Vec<u8>>,
     addr: Option<*mut c_void>,
     filename: Option<Vec<u8>>,
@@ -222,6 +228,12 @@ impl<'a> SymbolName<'a> {
     pub fn as_bytes(&self) -> &'a [u8] {
         self.bytes
     }
+
+    /// Returns whether the symbol name was recognized by the built-in
+    /// demangler.
+    pub fn is_demangled(&self) -> bool {
+        self.demangled.is_some()
+    }
 }
 
 fn format_symbol_name(
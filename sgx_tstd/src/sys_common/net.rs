@@ -25,12 +25,15 @@ use crate::io::{self, ErrorKind, IoSlice, IoSliceMut};
 use crate::mem;
 use crate::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
 use crate::ptr;
-use crate::sys::net::{cvt, cvt_gai, cvt_r, init, wrlen_t, Socket};
+use crate::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+use crate::sys::net::{self as sys_net, cvt, cvt_gai, cvt_r, init, wrlen_t, Socket};
 use crate::sys_common::{AsInner, FromInner, IntoInner};
-use crate::time::Duration;
+use crate::time::{Duration, Instant};
+#[cfg(not(feature = "untrusted_time"))]
+use crate::untrusted::time::InstantEx;
 
 
-use sgx_libc::{c_int, c_uint, c_void};
+use sgx_libc::{c_int, c_short, c_uint, c_void};
 
 type IpV4MultiCastType = c_int;
 
@@ -175,22 +178,116 @@ impl<'a> TryFrom<(&'a str, u16)> for LookupHost {
 
 pub struct TcpStream {
     inner: Socket,
+    timeouts: Timeouts,
+}
+
+/// The clock deadlines are measured with, if one was installed.
+static DEADLINE_CLOCK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+pub fn set_deadline_clock(clock: Option<fn() -> Option<Duration>>) {
+    let clock = clock.map_or(ptr::null_mut(), |clock| clock as *mut ());
+    DEADLINE_CLOCK.store(clock, Ordering::Release);
+}
+
+fn deadline_clock() -> Option<fn() -> Option<Duration>> {
+    let clock = DEADLINE_CLOCK.load(Ordering::Acquire);
+    if clock.is_null() {
+        None
+    } else {
+        // SAFETY: only `set_deadline_clock` stores, and it stores such functions.
+        Some(unsafe { mem::transmute::<*mut (), fn() -> Option<Duration>>(clock) })
+    }
+}
+
+/// The time a timed call took so far, by the installed clock or else by the
+/// host's.
+enum Elapsed {
+    Trusted(fn() -> Option<Duration>, Duration),
+    Host(Instant),
+}
+
+impl Elapsed {
+    fn start() -> Option<Elapsed> {
+        match deadline_clock() {
+            Some(clock) => clock().map(|start| Elapsed::Trusted(clock, start)),
+            None => Some(Elapsed::Host(Instant::now())),
+        }
+    }
+
+    /// Returns the time elapsed, or `None` if the clock can't be trusted now.
+    fn elapsed(&self) -> Option<Duration> {
+        match *self {
+            Elapsed::Trusted(clock, start) => clock().map(|now| now.saturating_sub(start)),
+            Elapsed::Host(start) => Some(start.elapsed()),
+        }
+    }
+}
+
+/// The timeouts of a stream, in nanoseconds, 0 meaning none.
+///
+/// They are kept and enforced in the enclave rather than set as socket
+/// options: the host can ignore `SO_RCVTIMEO`, report readiness that isn't
+/// there or interrupt calls over and over, but each wait is bounded by the
+/// time left before the deadline, and the deadline is checked again after
+/// every wake. The host can still hold a single ocall forever, which no
+/// enclave code can prevent.
+///
+/// The time is that of the clock installed with `set_deadline_clock`. Until
+/// one is, it's `Instant`, which the host provides, and a host freezing its
+/// clock stretches the deadlines.
+#[derive(Default)]
+struct Timeouts {
+    read: AtomicU64,
+    write: AtomicU64,
+    nonblocking: AtomicBool,
+}
+
+impl Timeouts {
+    fn set(slot: &AtomicU64, dur: Option<Duration>) -> io::Result<()> {
+        let nanos = match dur {
+            Some(dur) if dur == Duration::ZERO => {
+                return Err(io::const_io_error!(
+                    io::ErrorKind::InvalidInput,
+                    "cannot set a 0 duration timeout",
+                ));
+            }
+            Some(dur) => cmp::min(dur.as_nanos(), u64::MAX as u128) as u64,
+            None => 0,
+        };
+        slot.store(nanos, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn get(slot: &AtomicU64) -> Option<Duration> {
+        match slot.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+
+    fn duplicate(&self) -> Timeouts {
+        Timeouts {
+            read: AtomicU64::new(self.read.load(Ordering::Relaxed)),
+            write: AtomicU64::new(self.write.load(Ordering::Relaxed)),
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Relaxed)),
+        }
+    }
 }
 
 impl TcpStream {
     pub fn new(sockfd: c_int) -> io::Result<TcpStream> {
         let sock = Socket::new(sockfd)?;
-        Ok(TcpStream { inner: sock })
+        Ok(TcpStream::from_inner(sock))
     }
 
     pub fn new_v4() -> io::Result<TcpStream> {
         let sock = Socket::new_raw(c::AF_INET, c::SOCK_STREAM)?;
-        Ok(TcpStream { inner: sock })
+        Ok(TcpStream::from_inner(sock))
     }
 
     pub fn new_v6() -> io::Result<TcpStream> {
         let sock = Socket::new_raw(c::AF_INET6, c::SOCK_STREAM)?;
-        Ok(TcpStream { inner: sock })
+        Ok(TcpStream::from_inner(sock))
     }
 
     pub fn connect(addr: io::Result<&SocketAddr>) -> io::Result<TcpStream> {
@@ -201,7 +298,7 @@ impl TcpStream {
         let sock = Socket::new_socket_addr_type(addr, c::SOCK_STREAM)?;
         let (addrp, len) = addr.into_inner();
         cvt_r(|| unsafe { c::connect(sock.as_raw(), addrp, len) })?;
-        Ok(TcpStream { inner: sock })
+        Ok(TcpStream::from_inner(sock))
     }
 
     pub fn connect_socket(&self, addr: io::Result<&SocketAddr>) -> io::Result<()> {
@@ -218,7 +315,7 @@ impl TcpStream {
 
         let sock = Socket::new_socket_addr_type(addr, c::SOCK_STREAM)?;
         sock.connect_timeout(addr, timeout)?;
-        Ok(TcpStream { inner: sock })
+        Ok(TcpStream::from_inner(sock))
     }
 
     pub fn connect_socket_timeout(&self, addr: &SocketAddr, timeout: Duration) -> io::Result<()> {
@@ -234,31 +331,74 @@ impl TcpStream {
     }
 
     pub fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
-        self.inner.set_timeout(dur, c::SO_RCVTIMEO)
+        Timeouts::set(&self.timeouts.read, dur)
     }
 
     pub fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
-        self.inner.set_timeout(dur, c::SO_SNDTIMEO)
+        Timeouts::set(&self.timeouts.write, dur)
     }
 
     pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
-        self.inner.timeout(c::SO_RCVTIMEO)
+        Ok(Timeouts::get(&self.timeouts.read))
     }
 
     pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
-        self.inner.timeout(c::SO_SNDTIMEO)
+        Ok(Timeouts::get(&self.timeouts.write))
+    }
+
+    /// Runs `op` once the socket is ready for `events`, before `timeout`
+    /// elapses.
+    ///
+    /// `op` gets the flags to pass to the call: `MSG_DONTWAIT` when waiting
+    /// with a timeout, so that readiness the host made up can't block it.
+    /// Once the deadline passes, or if the deadline clock fails, fails with
+    /// `EAGAIN` as a socket timeout does.
+    fn with_deadline<T, F>(&self, timeout: &AtomicU64, events: c_short, mut op: F) -> io::Result<T>
+    where
+        F: FnMut(c_int) -> io::Result<T>,
+    {
+        let timeout = match Timeouts::get(timeout) {
+            Some(timeout) if !self.timeouts.nonblocking.load(Ordering::Relaxed) => timeout,
+            _ => return op(0),
+        };
+
+        let timed_out = || io::Error::from_raw_os_error(c::EAGAIN);
+        let start = Elapsed::start().ok_or_else(timed_out)?;
+        loop {
+            let elapsed = start.elapsed().ok_or_else(timed_out)?;
+            let remaining = timeout.saturating_sub(elapsed);
+            if remaining == Duration::ZERO {
+                return Err(timed_out());
+            }
+            let mut fds = [c::pollfd { fd: self.inner.as_raw(), events, revents: 0 }];
+            if sys_net::poll(&mut fds, Some(remaining))? == 0 {
+                continue;
+            }
+            match op(c::MSG_DONTWAIT) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => continue,
+                res => return res,
+            }
+        }
     }
 
     pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.peek(buf)
+        self.with_deadline(&self.timeouts.read, c::POLLIN, |flags| {
+            self.inner.recv_with_flags(buf, c::MSG_PEEK | flags)
+        })
     }
 
     pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(buf)
+        self.with_deadline(&self.timeouts.read, c::POLLIN, |flags| {
+            self.inner.recv_with_flags(buf, flags)
+        })
     }
 
     pub fn read_vectored(&self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
-        self.inner.read_vectored(bufs)
+        if Timeouts::get(&self.timeouts.read).is_none() {
+            return self.inner.read_vectored(bufs);
+        }
+        // `readv` takes no flags, so a timed read only fills the first non-empty buffer.
+        io::default_read_vectored(|buf| self.read(buf), bufs)
     }
 
     #[inline]
@@ -268,14 +408,24 @@ impl TcpStream {
 
     pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
         let len = cmp::min(buf.len(), <wrlen_t>::MAX as usize) as wrlen_t;
-        let ret = cvt(unsafe {
-            c::send(self.inner.as_raw(), buf.as_ptr() as *const c_void, len, c::MSG_NOSIGNAL)
-        })?;
-        Ok(ret as usize)
+        self.with_deadline(&self.timeouts.write, c::POLLOUT, |flags| {
+            let ret = cvt(unsafe {
+                c::send(
+                    self.inner.as_raw(),
+                    buf.as_ptr() as *const c_void,
+                    len,
+                    c::MSG_NOSIGNAL | flags,
+                )
+            })?;
+            Ok(ret as usize)
+        })
     }
 
     pub fn write_vectored(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
-        self.inner.write_vectored(bufs)
+        if Timeouts::get(&self.timeouts.write).is_none() {
+            return self.inner.write_vectored(bufs);
+        }
+        io::default_write_vectored(|buf| self.write(buf), bufs)
     }
 
     #[inline]
@@ -296,7 +446,8 @@ impl TcpStream {
     }
 
     pub fn duplicate(&self) -> io::Result<TcpStream> {
-        self.inner.duplicate().map(|s| TcpStream { inner: s })
+        let timeouts = self.timeouts.duplicate();
+        self.inner.duplicate().map(|inner| TcpStream { inner, timeouts })
     }
 
     pub fn set_linger(&self, linger: Option<Duration>) -> io::Result<()> {
@@ -329,13 +480,15 @@ impl TcpStream {
     }
 
     pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
-        self.inner.set_nonblocking(nonblocking)
+        self.inner.set_nonblocking(nonblocking)?;
+        self.timeouts.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
     }
 }
 
 impl FromInner<Socket> for TcpStream {
     fn from_inner(socket: Socket) -> TcpStream {
-        TcpStream { inner: socket }
+        TcpStream { inner: socket, timeouts: Timeouts::default() }
     }
 }
 
@@ -429,7 +582,7 @@ impl TcpListener {
         let mut len = mem::size_of_val(&storage) as c::socklen_t;
         let sock = self.inner.accept(&mut storage as *mut _ as *mut _, &mut len)?;
         let addr = sockaddr_to_addr(&storage, len as usize)?;
-        Ok((TcpStream { inner: sock }, addr))
+        Ok((TcpStream::from_inner(sock), addr))
     }
 
     pub fn duplicate(&self) -> io::Result<TcpListener> {
@@ -632,6 +785,33 @@ impl UdpSocket {
         Ok(raw != 0)
     }
 
+    pub fn set_multicast_if_v4(&self, interface: &Ipv4Addr) -> io::Result<()> {
+        setsockopt(&self.inner, c::IPPROTO_IP, c::IP_MULTICAST_IF, interface.into_inner())
+    }
+
+    pub fn multicast_if_v4(&self) -> io::Result<Ipv4Addr> {
+        let raw: c::in_addr = getsockopt(&self.inner, c::IPPROTO_IP, c::IP_MULTICAST_IF)?;
+        Ok(Ipv4Addr::from(raw.s_addr.to_ne_bytes()))
+    }
+
+    pub fn set_multicast_if_v6(&self, interface: u32) -> io::Result<()> {
+        setsockopt(&self.inner, c::IPPROTO_IPV6, c::IPV6_MULTICAST_IF, interface as c_int)
+    }
+
+    pub fn multicast_if_v6(&self) -> io::Result<u32> {
+        let raw: c_int = getsockopt(&self.inner, c::IPPROTO_IPV6, c::IPV6_MULTICAST_IF)?;
+        Ok(raw as u32)
+    }
+
+    pub fn set_multicast_hops_v6(&self, hops: u32) -> io::Result<()> {
+        setsockopt(&self.inner, c::IPPROTO_IPV6, c::IPV6_MULTICAST_HOPS, hops as c_int)
+    }
+
+    pub fn multicast_hops_v6(&self) -> io::Result<u32> {
+        let raw: c_int = getsockopt(&self.inner, c::IPPROTO_IPV6, c::IPV6_MULTICAST_HOPS)?;
+        Ok(raw as u32)
+    }
+
     pub fn join_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> io::Result<()> {
         let mreq = c::ip_mreq {
             imr_multiaddr: multiaddr.into_inner(),
@@ -0,0 +1,234 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! A single-threaded executor driven by the reactor.
+
+use crate::cell::RefCell;
+use crate::collections::VecDeque;
+use crate::fmt;
+use crate::future::Future;
+use crate::pin::Pin;
+use crate::rc::Rc;
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::{Arc, SgxMutex};
+use crate::task::{Context, Poll, Wake, Waker};
+
+use super::reactor;
+
+type LocalTask = Pin<Box<dyn Future<Output = ()>>>;
+
+/// The id of the future passed to `block_on`, which isn't stored with the
+/// spawned tasks.
+const MAIN_TASK: usize = usize::MAX;
+
+struct RunQueue {
+    ready: SgxMutex<VecDeque<usize>>,
+    // Set while the executor waits on the reactor, so that only wakes from
+    // other threads pay for an ocall notifying it.
+    parked: AtomicBool,
+}
+
+impl RunQueue {
+    fn push(&self, id: usize) {
+        self.ready.lock().unwrap().push_back(id);
+        if self.parked.load(Ordering::SeqCst) {
+            reactor::notify();
+        }
+    }
+
+    fn pop(&self) -> Option<usize> {
+        self.ready.lock().unwrap().pop_front()
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    queue: Arc<RunQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.queue.push(self.id);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.queue.push(self.id);
+    }
+}
+
+/// A single-threaded executor of futures, waiting for sockets through an
+/// epoll ocall.
+///
+/// Futures run on the thread calling [`Executor::block_on`], and need not be
+/// `Send`. When none of them can make progress the thread blocks in the
+/// reactor until a socket they wait for becomes ready, or a waker is called
+/// from another thread.
+pub struct Executor {
+    tasks: Vec<Option<LocalTask>>,
+    spawned: Rc<RefCell<Vec<LocalTask>>>,
+    queue: Arc<RunQueue>,
+}
+
+/// A handle spawning tasks onto an [`Executor`], usable from within the
+/// tasks themselves.
+#[derive(Clone)]
+pub struct Spawner {
+    spawned: Rc<RefCell<Vec<LocalTask>>>,
+}
+
+impl Spawner {
+    /// Spawns `future`, to run alongside the other tasks of the executor.
+    pub fn spawn<F: Future<Output = ()> + 'static>(&self, future: F) {
+        self.spawned.borrow_mut().push(Box::pin(future));
+    }
+}
+
+impl fmt::Debug for Spawner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spawner").finish_non_exhaustive()
+    }
+}
+
+impl Executor {
+    /// Creates an executor with no tasks.
+    pub fn new() -> Executor {
+        Executor {
+            tasks: Vec::new(),
+            spawned: Rc::new(RefCell::new(Vec::new())),
+            queue: Arc::new(RunQueue {
+                ready: SgxMutex::new(VecDeque::new()),
+                parked: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Returns a handle spawning tasks onto this executor.
+    pub fn spawner(&self) -> Spawner {
+        Spawner { spawned: self.spawned.clone() }
+    }
+
+    /// Spawns `future`, to run once the executor is driven by `block_on`.
+    pub fn spawn<F: Future<Output = ()> + 'static>(&self, future: F) {
+        self.spawner().spawn(future)
+    }
+
+    /// Runs `future` to completion, along with the spawned tasks, returning
+    /// its output.
+    ///
+    /// Tasks which haven't completed by then are kept, and resume with the
+    /// next call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if waiting on the reactor fails.
+    pub fn block_on<F: Future>(&mut self, future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let main_waker = self.waker(MAIN_TASK);
+        let mut main_ready = true;
+        loop {
+            if main_ready {
+                main_ready = false;
+                let mut cx = Context::from_waker(&main_waker);
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            }
+
+            self.start_spawned();
+            let mut progressed = false;
+            while let Some(id) = self.queue.pop() {
+                progressed = true;
+                if id == MAIN_TASK {
+                    main_ready = true;
+                    continue;
+                }
+                self.poll_task(id);
+                self.start_spawned();
+            }
+
+            if !progressed && !main_ready {
+                self.park();
+            }
+        }
+    }
+
+    /// Moves the tasks spawned since the last call into the run queue.
+    fn start_spawned(&mut self) {
+        let spawned = crate::mem::take(&mut *self.spawned.borrow_mut());
+        for task in spawned {
+            let id = match self.tasks.iter().position(Option::is_none) {
+                Some(id) => {
+                    self.tasks[id] = Some(task);
+                    id
+                }
+                None => {
+                    self.tasks.push(Some(task));
+                    self.tasks.len() - 1
+                }
+            };
+            self.queue.push(id);
+        }
+    }
+
+    fn poll_task(&mut self, id: usize) {
+        let waker = self.waker(id);
+        // Wakes of completed tasks may still be queued.
+        let task = match self.tasks.get_mut(id) {
+            Some(Some(task)) => task,
+            _ => return,
+        };
+        let mut cx = Context::from_waker(&waker);
+        if task.as_mut().poll(&mut cx).is_ready() {
+            self.tasks[id] = None;
+        }
+    }
+
+    fn waker(&self, id: usize) -> Waker {
+        Waker::from(Arc::new(TaskWaker { id, queue: self.queue.clone() }))
+    }
+
+    /// Blocks in the reactor until a task may be able to make progress.
+    fn park(&self) {
+        self.queue.parked.store(true, Ordering::SeqCst);
+        // A wake racing with the store above is either queued already, or
+        // notifies the reactor.
+        if self.queue.ready.lock().unwrap().is_empty() {
+            if let Err(e) = reactor::turn(None) {
+                panic!("failed to wait on the reactor: {}", e);
+            }
+        }
+        self.queue.parked.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Executor {
+        Executor::new()
+    }
+}
+
+impl fmt::Debug for Executor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Executor")
+            .field("tasks", &self.tasks.iter().filter(|task| task.is_some()).count())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runs `future` to completion on a new [`Executor`].
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    Executor::new().block_on(future)
+}
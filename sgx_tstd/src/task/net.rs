@@ -0,0 +1,252 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! Asynchronous adapters of the TCP sockets.
+
+use crate::fmt;
+use crate::future::Future;
+use crate::io::{self, Read, Write};
+use crate::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use crate::os::unix::io::{AsRawFd, RawFd};
+use crate::pin::Pin;
+use crate::task::{Context, Poll};
+
+use super::reactor::{self, Interest};
+
+/// A future calling `f` until it's ready.
+struct PollFn<F>(F);
+
+impl<F> Unpin for PollFn<F> {}
+
+impl<T, F: FnMut(&mut Context<'_>) -> Poll<T>> Future for PollFn<F> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        (self.get_mut().0)(cx)
+    }
+}
+
+fn poll_fn<T, F: FnMut(&mut Context<'_>) -> Poll<T>>(f: F) -> PollFn<F> {
+    PollFn(f)
+}
+
+/// Runs the non-blocking operation `op`, waiting for `interest` on `fd` if it
+/// would block.
+fn poll_io<R, F>(
+    fd: RawFd,
+    interest: Interest,
+    cx: &mut Context<'_>,
+    mut op: F,
+) -> Poll<io::Result<R>>
+where
+    F: FnMut() -> io::Result<R>,
+{
+    loop {
+        match op() {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                reactor::wait(fd, interest, cx.waker());
+                return Poll::Pending;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            result => return Poll::Ready(result),
+        }
+    }
+}
+
+/// A TCP listener accepting connections asynchronously.
+///
+/// The futures of its methods are driven by [`Executor`](super::Executor).
+pub struct AsyncTcpListener {
+    inner: TcpListener,
+}
+
+impl AsyncTcpListener {
+    /// Creates a listener bound to `addr`, as [`TcpListener::bind`].
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<AsyncTcpListener> {
+        AsyncTcpListener::from_std(TcpListener::bind(addr)?)
+    }
+
+    /// Makes `listener` asynchronous, switching it to non-blocking mode.
+    pub fn from_std(listener: TcpListener) -> io::Result<AsyncTcpListener> {
+        listener.set_nonblocking(true)?;
+        reactor::register(listener.as_raw_fd())?;
+        Ok(AsyncTcpListener { inner: listener })
+    }
+
+    /// Returns the underlying listener.
+    pub fn get_ref(&self) -> &TcpListener {
+        &self.inner
+    }
+
+    /// Returns the local address of the listener.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Accepts a new connection, or registers the current task to be woken
+    /// once one arrives.
+    pub fn poll_accept(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<(AsyncTcpStream, SocketAddr)>> {
+        match poll_io(self.inner.as_raw_fd(), Interest::Read, cx, || self.inner.accept()) {
+            Poll::Ready(Ok((stream, addr))) => {
+                Poll::Ready(AsyncTcpStream::from_std(stream).map(|stream| (stream, addr)))
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Accepts a new connection.
+    pub async fn accept(&self) -> io::Result<(AsyncTcpStream, SocketAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+}
+
+impl Drop for AsyncTcpListener {
+    fn drop(&mut self) {
+        reactor::deregister(self.inner.as_raw_fd());
+    }
+}
+
+impl fmt::Debug for AsyncTcpListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AsyncTcpListener").field(&self.inner).finish()
+    }
+}
+
+/// A TCP stream read and written asynchronously.
+///
+/// The futures of its methods are driven by [`Executor`](super::Executor).
+pub struct AsyncTcpStream {
+    inner: TcpStream,
+}
+
+impl AsyncTcpStream {
+    /// Opens a connection to `addr` without blocking the executor.
+    pub async fn connect(addr: SocketAddr) -> io::Result<AsyncTcpStream> {
+        let stream = match addr {
+            SocketAddr::V4(_) => TcpStream::new_v4()?,
+            SocketAddr::V6(_) => TcpStream::new_v6()?,
+        };
+        stream.set_nonblocking(true)?;
+        reactor::register(stream.as_raw_fd())?;
+        let stream = AsyncTcpStream { inner: stream };
+
+        match stream.inner.connect_socket(addr) {
+            Ok(()) => return Ok(stream),
+            Err(ref e) if e.raw_os_error() == Some(sgx_libc::EINPROGRESS) => {}
+            Err(e) => return Err(e),
+        }
+        // The socket becomes writable once the connection is established or
+        // has failed.
+        poll_fn(|cx| {
+            if let Some(e) = stream.inner.take_error()? {
+                return Poll::Ready(Err(e));
+            }
+            match stream.inner.peer_addr() {
+                Ok(_) => Poll::Ready(Ok(())),
+                Err(ref e) if e.kind() == io::ErrorKind::NotConnected => {
+                    reactor::wait(stream.inner.as_raw_fd(), Interest::Write, cx.waker());
+                    Poll::Pending
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        })
+        .await?;
+        Ok(stream)
+    }
+
+    /// Makes `stream` asynchronous, switching it to non-blocking mode.
+    pub fn from_std(stream: TcpStream) -> io::Result<AsyncTcpStream> {
+        stream.set_nonblocking(true)?;
+        reactor::register(stream.as_raw_fd())?;
+        Ok(AsyncTcpStream { inner: stream })
+    }
+
+    /// Returns the underlying stream.
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.inner
+    }
+
+    /// Returns the address of the remote peer.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    /// Returns the local address of the stream.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Shuts down the read half, the write half or both halves of the
+    /// connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    /// Reads into `buf`, or registers the current task to be woken once data
+    /// arrives.
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        poll_io(self.inner.as_raw_fd(), Interest::Read, cx, || (&self.inner).read(buf))
+    }
+
+    /// Writes from `buf`, or registers the current task to be woken once the
+    /// stream can take more data.
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        poll_io(self.inner.as_raw_fd(), Interest::Write, cx, || (&self.inner).write(buf))
+    }
+
+    /// Reads into `buf`, returning the number of bytes read, or 0 at the end
+    /// of the stream.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_fn(|cx| self.poll_read(cx, buf)).await
+    }
+
+    /// Writes from `buf`, returning the number of bytes written.
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        poll_fn(|cx| self.poll_write(cx, buf)).await
+    }
+
+    /// Writes all of `buf`.
+    pub async fn write_all(&self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf).await? {
+                0 => {
+                    return Err(io::const_io_error!(
+                        io::ErrorKind::WriteZero,
+                        "failed to write whole buffer"
+                    ))
+                }
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AsyncTcpStream {
+    fn drop(&mut self) {
+        reactor::deregister(self.inner.as_raw_fd());
+    }
+}
+
+impl fmt::Debug for AsyncTcpStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AsyncTcpStream").field(&self.inner).finish()
+    }
+}
@@ -0,0 +1,199 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! The epoll reactor waking the tasks waiting for sockets.
+
+use crate::collections::HashMap;
+use crate::io;
+use crate::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use crate::sync::SgxThreadMutex;
+use crate::sys::cvt;
+use crate::sys::fd::FileDesc;
+use crate::task::Waker;
+use crate::time::Duration;
+
+use sgx_libc as libc;
+
+/// The readiness a task waits for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Interest {
+    Read,
+    Write,
+}
+
+#[derive(Default)]
+struct Source {
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+struct Reactor {
+    epoll: FileDesc,
+    // Written to by `notify` to interrupt `epoll_wait`, read end first.
+    notifier: (FileDesc, FileDesc),
+    sources: HashMap<RawFd, Source>,
+}
+
+/// Identifies the notification pipe in epoll events, as no socket can have
+/// this descriptor.
+const NOTIFY_TOKEN: u64 = u64::MAX;
+
+static REACTOR_LOCK: SgxThreadMutex = SgxThreadMutex::new();
+// protected by `REACTOR_LOCK`.
+static mut REACTOR: Option<Reactor> = None;
+
+impl Reactor {
+    fn new() -> io::Result<Reactor> {
+        let epoll = cvt(unsafe { libc::ocall::epoll_create1(libc::EPOLL_CLOEXEC) })?;
+        let epoll = unsafe { FileDesc::from_raw_fd(epoll) };
+
+        let mut fds = [0; 2];
+        cvt(unsafe {
+            libc::ocall::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC)
+        })?;
+        let notifier = unsafe { (FileDesc::from_raw_fd(fds[0]), FileDesc::from_raw_fd(fds[1])) };
+
+        let mut event = libc::epoll_event { events: libc::EPOLLIN as u32, u64: NOTIFY_TOKEN };
+        cvt(unsafe {
+            libc::ocall::epoll_ctl(
+                epoll.as_raw_fd(),
+                libc::EPOLL_CTL_ADD,
+                notifier.0.as_raw_fd(),
+                &mut event,
+            )
+        })?;
+        Ok(Reactor { epoll, notifier, sources: HashMap::new() })
+    }
+}
+
+/// Runs `f` on the reactor, creating it on first use.
+fn with_reactor<F, R>(f: F) -> io::Result<R>
+where
+    F: FnOnce(&mut Reactor) -> io::Result<R>,
+{
+    unsafe {
+        let _ = REACTOR_LOCK.lock();
+        let result = match REACTOR {
+            Some(ref mut reactor) => f(reactor),
+            None => Reactor::new().and_then(|reactor| f(REACTOR.insert(reactor))),
+        };
+        let _ = REACTOR_LOCK.unlock();
+        result
+    }
+}
+
+/// Starts watching `fd`, which has to be non-blocking.
+///
+/// Readiness is edge-triggered, so tasks only wait for it once an operation
+/// on `fd` failed with `WouldBlock`.
+pub(crate) fn register(fd: RawFd) -> io::Result<()> {
+    with_reactor(|reactor| {
+        let events = libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLRDHUP | libc::EPOLLET;
+        let mut event = libc::epoll_event { events: events as u32, u64: fd as u64 };
+        cvt(unsafe {
+            libc::ocall::epoll_ctl(reactor.epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event)
+        })?;
+        reactor.sources.insert(fd, Source::default());
+        Ok(())
+    })
+}
+
+/// Stops watching `fd`, which has to happen before it's closed.
+pub(crate) fn deregister(fd: RawFd) {
+    let _ = with_reactor(|reactor| {
+        reactor.sources.remove(&fd);
+        let mut event = libc::epoll_event { events: 0, u64: 0 };
+        cvt(unsafe {
+            libc::ocall::epoll_ctl(reactor.epoll.as_raw_fd(), libc::EPOLL_CTL_DEL, fd, &mut event)
+        })
+        .map(drop)
+    });
+}
+
+/// Wakes `waker` once `fd` becomes ready for `interest`.
+pub(crate) fn wait(fd: RawFd, interest: Interest, waker: &Waker) {
+    let _ = with_reactor(|reactor| {
+        if let Some(source) = reactor.sources.get_mut(&fd) {
+            let slot = match interest {
+                Interest::Read => &mut source.reader,
+                Interest::Write => &mut source.writer,
+            };
+            match slot {
+                Some(old) if old.will_wake(waker) => {}
+                _ => *slot = Some(waker.clone()),
+            }
+        }
+        Ok(())
+    });
+}
+
+/// Interrupts a `turn` in progress, or makes the next one return at once.
+pub(crate) fn notify() {
+    let _ = with_reactor(|reactor| reactor.notifier.1.write(&[1]).map(drop));
+}
+
+/// Waits for readiness events for up to `timeout`, or until `notify` is
+/// called, and wakes the tasks waiting for them.
+pub(crate) fn turn(timeout: Option<Duration>) -> io::Result<()> {
+    const MAX_EVENTS: usize = 64;
+
+    // The lock isn't held while waiting, so that tasks can keep registering.
+    let epoll = with_reactor(|reactor| Ok(reactor.epoll.as_raw_fd()))?;
+    let timeout = match timeout {
+        Some(timeout) => {
+            let millis = timeout.as_millis() + (timeout.subsec_nanos() % 1_000_000 != 0) as u128;
+            millis.min(libc::c_int::MAX as u128) as libc::c_int
+        }
+        None => -1,
+    };
+    let mut events = [libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+    let n = match cvt(unsafe {
+        libc::ocall::epoll_wait(epoll, events.as_mut_ptr(), MAX_EVENTS as libc::c_int, timeout)
+    }) {
+        Ok(n) => n as usize,
+        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => 0,
+        Err(e) => return Err(e),
+    };
+
+    let mut wakers = Vec::new();
+    with_reactor(|reactor| {
+        for event in &events[..n] {
+            let (token, ready) = (event.u64, event.events as libc::c_int);
+            if token == NOTIFY_TOKEN {
+                let mut buf = [0; 64];
+                while let Ok(len) = reactor.notifier.0.read(&mut buf) {
+                    if len < buf.len() {
+                        break;
+                    }
+                }
+                continue;
+            }
+            if let Some(source) = reactor.sources.get_mut(&(token as RawFd)) {
+                let closed = ready & (libc::EPOLLERR | libc::EPOLLHUP) != 0;
+                if closed || ready & (libc::EPOLLIN | libc::EPOLLRDHUP) != 0 {
+                    wakers.extend(source.reader.take());
+                }
+                if closed || ready & libc::EPOLLOUT != 0 {
+                    wakers.extend(source.writer.take());
+                }
+            }
+        }
+        Ok(())
+    })?;
+    // Woken outside the lock, as waking may register again.
+    wakers.into_iter().for_each(Waker::wake);
+    Ok(())
+}
@@ -0,0 +1,260 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Caches of attestation evidence, and of its verification.
+//!
+//! Quoting takes a round trip to the QE and fetching collateral, and [`verify_quote`] a dozen
+//! signature verifications; a service handling thousands of connections a minute can't afford
+//! either on every one of them. A [`QuoteCache`] keeps the evidence the enclave produced for each
+//! report data, and a [`VerificationCache`] the result of verifying the evidence of peers, for a
+//! while:
+//!
+//! ```ignore
+//! let mut quotes = QuoteCache::new(pccs, 300);
+//! let bundle = quotes.evidence(&report_data, clock.now()?)?;
+//!
+//! let mut verified = VerificationCache::new(INTEL_ROOT_CA, policy, 600);
+//! let quote = verified.verify(&bundle, &expected_report_data, clock.now()?)?;
+//! ```
+//!
+//! Entries are fresh for their time to live from when they were made, by the trusted time the
+//! caller passes, and a verification no longer than its collateral is valid. Time going
+//! backwards, which only a tampered clock makes happen, makes entries stale rather than fresh.
+//!
+//! Both caches are bound to the report data, which carries the nonce of a challenge or the hash
+//! of a key: evidence is only reused for the same report data, and a verification only returned
+//! if the quote carries the report data the caller expects, so a cached result can't answer
+//! another challenge. Each holds at most its capacity of entries, evicting the oldest one first.
+
+use crate::dcap::{
+    verify_quote, CollateralSource, DcapError, DcapResult, EvidenceBundle, OcallQuoter,
+    QuoteBuilder, Quoter, VerificationPolicy, VerifiedQuote,
+};
+use crate::policy::Policy;
+use alloc::vec::Vec;
+use sgx_tcrypto::hash::{Hash, Sha256};
+use sgx_types::*;
+
+/// The number of entries a cache holds by default.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Returns whether an entry made at `created` is still fresh at `now`.
+fn fresh(created: time_t, max_age: time_t, now: time_t) -> bool {
+    now >= created && now - created < max_age
+}
+
+/// Evicts the oldest entry of `entries` if there are `capacity` of them.
+fn make_room<T>(entries: &mut Vec<T>, capacity: usize) {
+    if entries.len() >= capacity && !entries.is_empty() {
+        entries.remove(0);
+    }
+}
+
+struct QuoteEntry {
+    report_data: sgx_report_data_t,
+    created: time_t,
+    bundle: EvidenceBundle,
+}
+
+/// The evidence of the enclave, produced by a [`QuoteBuilder`] for each report data and kept
+/// for `max_age` seconds.
+pub struct QuoteCache<S: CollateralSource, Q: Quoter = OcallQuoter> {
+    builder: QuoteBuilder<S, Q>,
+    max_age: time_t,
+    capacity: usize,
+    entries: Vec<QuoteEntry>,
+}
+
+impl<S: CollateralSource> QuoteCache<S, OcallQuoter> {
+    /// Creates a cache quoting through the ocalls, with collateral from `source`.
+    pub fn new(source: S, max_age: time_t) -> QuoteCache<S, OcallQuoter> {
+        QuoteCache::with_quoter(source, OcallQuoter, max_age)
+    }
+}
+
+impl<S: CollateralSource, Q: Quoter> QuoteCache<S, Q> {
+    pub fn with_quoter(source: S, quoter: Q, max_age: time_t) -> QuoteCache<S, Q> {
+        QuoteCache {
+            builder: QuoteBuilder::with_quoter(source, quoter),
+            max_age,
+            capacity: DEFAULT_CAPACITY,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Sets the number of report data whose evidence is kept, at least one and
+    /// [`DEFAULT_CAPACITY`] by default.
+    pub fn capacity(mut self, capacity: usize) -> QuoteCache<S, Q> {
+        self.capacity = capacity.max(1);
+        self.entries.truncate(self.capacity);
+        self
+    }
+
+    /// Returns the evidence of the enclave for `report_data`, produced less than the time to
+    /// live before `now`, or produces it as [`QuoteBuilder::build`] does.
+    pub fn evidence(
+        &mut self,
+        report_data: &sgx_report_data_t,
+        now: time_t,
+    ) -> DcapResult<&EvidenceBundle> {
+        let max_age = self.max_age;
+        self.entries.retain(|entry| fresh(entry.created, max_age, now));
+        if let Some(i) = self.entries.iter().position(|entry| entry.report_data.d == report_data.d)
+        {
+            return Ok(&self.entries[i].bundle);
+        }
+
+        self.builder.set_report_data(*report_data);
+        let bundle = self.builder.build()?;
+        make_room(&mut self.entries, self.capacity);
+        self.entries.push(QuoteEntry { report_data: *report_data, created: now, bundle });
+        Ok(&self.entries[self.entries.len() - 1].bundle)
+    }
+
+    /// Drops all the evidence, e.g. after a TCB recovery of the platform.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn into_inner(self) -> (S, Q) {
+        self.builder.into_inner()
+    }
+}
+
+struct VerifiedEntry {
+    digest: [u8; 32],
+    created: time_t,
+    verified: VerifiedQuote,
+}
+
+/// The results of verifying the evidence of peers against a policy, kept for `max_age` seconds
+/// and no longer than their collateral is valid.
+///
+/// Only evidence which verified is kept, so that evidence failing at one time, such as
+/// collateral not yet valid, can verify later.
+pub struct VerificationCache {
+    root_ca: Vec<u8>,
+    policy: Policy,
+    max_age: time_t,
+    capacity: usize,
+    entries: Vec<VerifiedEntry>,
+}
+
+impl VerificationCache {
+    /// Creates a cache verifying evidence up to the DER certificate `root_ca`, trusting what
+    /// `policy` trusts, as [`VerificationPolicy::new`] does.
+    pub fn new(root_ca: &[u8], policy: Policy, max_age: time_t) -> VerificationCache {
+        VerificationCache {
+            root_ca: Vec::from(root_ca),
+            policy,
+            max_age,
+            capacity: DEFAULT_CAPACITY,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Sets the number of verifications kept, [`DEFAULT_CAPACITY`] by default; with none,
+    /// every evidence is verified.
+    pub fn capacity(mut self, capacity: usize) -> VerificationCache {
+        self.capacity = capacity;
+        self.entries.truncate(capacity);
+        self
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Verifies `evidence` at the trusted time `now`, unless the same evidence verified less
+    /// than the time to live before, and returns what it attests if its quote carries
+    /// `report_data`.
+    ///
+    /// Fails with [`DcapError::ReportMismatch`] if the quote doesn't carry `report_data`, before
+    /// anything is verified, and otherwise as [`verify_quote`] does.
+    pub fn verify(
+        &mut self,
+        evidence: &EvidenceBundle,
+        report_data: &sgx_report_data_t,
+        now: time_t,
+    ) -> DcapResult<VerifiedQuote> {
+        if evidence.quote().report_body().report_data.d != report_data.d {
+            return Err(DcapError::ReportMismatch);
+        }
+        let max_age = self.max_age;
+        self.entries
+            .retain(|entry| fresh(entry.created, max_age, now) && now <= entry.verified.expires());
+        let digest = digest(evidence);
+        if let Some(entry) = self.entries.iter().find(|entry| entry.digest == digest) {
+            return Ok(entry.verified.clone());
+        }
+
+        let policy = VerificationPolicy::new(&self.root_ca, now, self.policy.clone());
+        let verified = verify_quote(evidence, &policy)?;
+        if self.capacity > 0 {
+            make_room(&mut self.entries, self.capacity);
+            self.entries.push(VerifiedEntry { digest, created: now, verified: verified.clone() });
+        }
+        Ok(verified)
+    }
+
+    /// Drops all the verifications, e.g. after Intel published new collateral.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Returns the SHA-256 of the quote and the collateral of `evidence`, each prefixed by its
+/// length, which identifies the evidence.
+fn digest(evidence: &EvidenceBundle) -> [u8; 32] {
+    let collateral = evidence.collateral();
+    let mut hash = Sha256::new();
+    hash.update(&collateral.version.to_le_bytes());
+    hash.update(&collateral.tee_type.to_le_bytes());
+    let fields = [
+        evidence.quote_bytes(),
+        &collateral.pck_crl_issuer_chain,
+        &collateral.root_ca_crl,
+        &collateral.pck_crl,
+        &collateral.tcb_info_issuer_chain,
+        &collateral.tcb_info,
+        &collateral.qe_identity_issuer_chain,
+        &collateral.qe_identity,
+    ];
+    for field in fields {
+        hash.update(&(field.len() as u64).to_le_bytes());
+        hash.update(field);
+    }
+    let mut digest = [0_u8; 32];
+    hash.finalize_into(&mut digest);
+    digest
+}
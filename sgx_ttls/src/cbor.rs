@@ -0,0 +1,413 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! CBOR (RFC 8949) values, as COSE and the CTAP2 messages of hardware wallets use them.
+//!
+//! Decoding accepts the deterministic encoding of CTAP2 and COSE only, which is what signed
+//! structures need to be reproducible: definite lengths, arguments in their shortest form, no
+//! duplicate map keys, and nesting no deeper than [`MAX_DEPTH`]. Maps keep their entries in
+//! order and are encoded as given, so builders of canonical CTAP2 maps give keys in canonical
+//! order. Floats are decoded from any width and encoded as 64-bit.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The deepest nesting of arrays, maps and tags decoding accepts.
+pub const MAX_DEPTH: usize = 64;
+
+/// A CBOR data item.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Unsigned(u64),
+    /// The integer `-1 - n`.
+    Negative(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    /// A map, with its entries in order.
+    Map(Vec<(Value, Value)>),
+    Tag(u64, Box<Value>),
+    Bool(bool),
+    Null,
+    Undefined,
+    Float(f64),
+}
+
+/// Input which isn't a CBOR item in the accepted encoding, with the offset of the first byte
+/// found wrong.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct CborError {
+    pub offset: usize,
+}
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid CBOR at byte {}", self.offset)
+    }
+}
+
+pub type CborResult<T> = Result<T, CborError>;
+
+/// Appends the head of an item of `major` type with `argument`, in its shortest form.
+fn write_head(out: &mut Vec<u8>, major: u8, argument: u64) {
+    let major = major << 5;
+    if argument < 24 {
+        out.push(major | argument as u8);
+    } else if argument <= u8::MAX as u64 {
+        out.push(major | 24);
+        out.push(argument as u8);
+    } else if argument <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(argument as u16).to_be_bytes());
+    } else if argument <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(argument as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&argument.to_be_bytes());
+    }
+}
+
+impl Value {
+    /// Decodes `data`, which must hold exactly one item.
+    pub fn decode(data: &[u8]) -> CborResult<Value> {
+        let mut decoder = Decoder { data, pos: 0 };
+        let value = decoder.item(0)?;
+        if decoder.pos != data.len() {
+            return Err(decoder.error());
+        }
+        Ok(value)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    /// Appends the encoding to `out`.
+    pub fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Unsigned(n) => write_head(out, 0, *n),
+            Value::Negative(n) => write_head(out, 1, *n),
+            Value::Bytes(bytes) => {
+                write_head(out, 2, bytes.len() as u64);
+                out.extend_from_slice(bytes);
+            }
+            Value::Text(text) => {
+                write_head(out, 3, text.len() as u64);
+                out.extend_from_slice(text.as_bytes());
+            }
+            Value::Array(values) => {
+                write_head(out, 4, values.len() as u64);
+                for value in values {
+                    value.encode_into(out);
+                }
+            }
+            Value::Map(entries) => {
+                write_head(out, 5, entries.len() as u64);
+                for (key, value) in entries {
+                    key.encode_into(out);
+                    value.encode_into(out);
+                }
+            }
+            Value::Tag(tag, value) => {
+                write_head(out, 6, *tag);
+                value.encode_into(out);
+            }
+            Value::Bool(false) => out.push(0xf4),
+            Value::Bool(true) => out.push(0xf5),
+            Value::Null => out.push(0xf6),
+            Value::Undefined => out.push(0xf7),
+            Value::Float(x) => {
+                out.push(0xfb);
+                out.extend_from_slice(&x.to_bits().to_be_bytes());
+            }
+        }
+    }
+
+    /// Returns the value of the entry `key` of a map.
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer, if it's within `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Unsigned(n) if n <= i64::MAX as u64 => Some(n as i64),
+            Value::Negative(n) if n <= i64::MAX as u64 => Some(-1 - n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&[(Value, Value)]> {
+        match self {
+            Value::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Value {
+        if n < 0 {
+            Value::Negative(!n as u64)
+        } else {
+            Value::Unsigned(n as u64)
+        }
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(bytes: &[u8]) -> Value {
+        Value::Bytes(Vec::from(bytes))
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Value {
+        Value::Text(String::from(text))
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn error(&self) -> CborError {
+        CborError { offset: self.pos }
+    }
+
+    fn take(&mut self, len: usize) -> CborResult<&'a [u8]> {
+        if self.data.len() - self.pos < len {
+            return Err(self.error());
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Reads the head of an item, returning its major type, its additional information and
+    /// its argument, which must be in its shortest form.
+    fn head(&mut self) -> CborResult<(u8, u8, u64)> {
+        let start = self.pos;
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let argument = match info {
+            0..=23 => info as u64,
+            24..=27 => {
+                let bytes = self.take(1 << (info - 24))?;
+                let argument = bytes.iter().fold(0_u64, |n, &b| n << 8 | b as u64);
+                // Floats have no shorter form to compare to.
+                let shortest = match info {
+                    24 => argument >= 24,
+                    25 => argument > u8::MAX as u64,
+                    26 => argument > u16::MAX as u64,
+                    _ => argument > u32::MAX as u64,
+                };
+                if !shortest && major != 7 {
+                    return Err(CborError { offset: start });
+                }
+                argument
+            }
+            _ => return Err(CborError { offset: start }),
+        };
+        Ok((major, info, argument))
+    }
+
+    /// Returns `argument` as a length of items at least a byte each, which must fit in what's
+    /// left of the input.
+    fn len(&self, argument: u64, start: usize) -> CborResult<usize> {
+        if argument > (self.data.len() - self.pos) as u64 {
+            return Err(CborError { offset: start });
+        }
+        Ok(argument as usize)
+    }
+
+    fn item(&mut self, depth: usize) -> CborResult<Value> {
+        let start = self.pos;
+        let (major, info, argument) = self.head()?;
+        if depth >= MAX_DEPTH && (4..=6).contains(&major) {
+            return Err(CborError { offset: start });
+        }
+        Ok(match major {
+            0 => Value::Unsigned(argument),
+            1 => Value::Negative(argument),
+            2 => {
+                let len = self.len(argument, start)?;
+                Value::Bytes(Vec::from(self.take(len)?))
+            }
+            3 => {
+                let len = self.len(argument, start)?;
+                let text = core::str::from_utf8(self.take(len)?)
+                    .map_err(|_| CborError { offset: start })?;
+                Value::Text(String::from(text))
+            }
+            4 => {
+                let len = self.len(argument, start)?;
+                let mut values = Vec::with_capacity(len);
+                for _ in 0..len {
+                    values.push(self.item(depth + 1)?);
+                }
+                Value::Array(values)
+            }
+            5 => {
+                let len = self.len(argument, start)?;
+                let mut entries = Vec::with_capacity(len);
+                let mut keys = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let key_start = self.pos;
+                    let key = self.item(depth + 1)?;
+                    keys.push(&self.data[key_start..self.pos]);
+                    entries.push((key, self.item(depth + 1)?));
+                }
+                // Equal keys have equal encodings, the encoding being deterministic.
+                keys.sort_unstable();
+                if keys.windows(2).any(|pair| pair[0] == pair[1]) {
+                    return Err(CborError { offset: start });
+                }
+                Value::Map(entries)
+            }
+            6 => Value::Tag(argument, Box::new(self.item(depth + 1)?)),
+            _ => match (info, argument) {
+                (20, _) => Value::Bool(false),
+                (21, _) => Value::Bool(true),
+                (22, _) => Value::Null,
+                (23, _) => Value::Undefined,
+                (25, half) => Value::Float(half_to_f64(half as u16)),
+                (26, single) => Value::Float(f32::from_bits(single as u32) as f64),
+                (27, double) => Value::Float(f64::from_bits(double)),
+                _ => return Err(CborError { offset: start }),
+            },
+        })
+    }
+}
+
+/// Converts an IEEE 754 half-precision float to `f64`.
+fn half_to_f64(half: u16) -> f64 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (half >> 10 & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f64;
+    sign * match exponent {
+        0 => mantissa * f64::from_bits(0x3e70_0000_0000_0000), // 2^-24
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1024.0 + mantissa) * f64::from_bits(((exponent - 25 + 1023) as u64) << 52),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        // RFC 8949 appendix A.
+        let value = Value::Map(vec![
+            (
+                Value::from("a"),
+                Value::Array(vec![
+                    Value::from(1),
+                    Value::from(-1000),
+                    Value::from(&b"\x01\x02"[..]),
+                ]),
+            ),
+            (Value::Unsigned(1), Value::Tag(1, Box::new(Value::from(1363896240)))),
+            (Value::from(true), Value::Null),
+        ]);
+        let bytes = value.encode();
+        assert_eq!(Value::decode(&bytes), Ok(value));
+        assert_eq!(Value::decode(&[0xf9, 0x3c, 0x00]), Ok(Value::Float(1.0)));
+        assert_eq!(Value::decode(&[0xfa, 0x47, 0xc3, 0x50, 0x00]), Ok(Value::Float(100000.0)));
+        assert_eq!(
+            Value::decode(&[0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Ok(Value::Negative(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn rejections() {
+        let cases: [(&[u8], usize); 12] = [
+            (&[], 0),
+            (&[0x01, 0x02], 1),
+            // Arguments not in their shortest form.
+            (&[0x18, 0x17], 0),
+            (&[0x19, 0x00, 0xff], 0),
+            (&[0x82, 0x01, 0x1a, 0x00, 0x00, 0xff, 0xff], 2),
+            // Indefinite lengths and reserved additional information.
+            (&[0x9f, 0x01, 0xff], 0),
+            (&[0x1c], 0),
+            // Lengths past the input, truncated items.
+            (&[0x5b, 0, 0, 0, 1, 0, 0, 0, 0], 0),
+            (&[0x43, 0x01, 0x02], 0),
+            (&[0x19, 0x01], 1),
+            // Invalid UTF-8, duplicate map keys.
+            (&[0x62, 0xc3, 0x28], 0),
+            (&[0xa2, 0x01, 0x00, 0x01, 0x01], 0),
+        ];
+        for (data, offset) in cases {
+            assert_eq!(Value::decode(data), Err(CborError { offset }), "{:02x?}", data);
+        }
+        // Unassigned simple values.
+        assert_eq!(Value::decode(&[0xf0]), Err(CborError { offset: 0 }));
+        assert_eq!(Value::decode(&[0xf8, 0x20]), Err(CborError { offset: 0 }));
+    }
+
+    #[test]
+    fn depth() {
+        let nested = |depth: usize| {
+            let mut data = vec![0x81; depth];
+            data.push(0x00);
+            data
+        };
+        assert!(Value::decode(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(Value::decode(&nested(MAX_DEPTH + 1)), Err(CborError { offset: MAX_DEPTH }));
+    }
+}
@@ -0,0 +1,338 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! COSE_Sign1 and COSE_Encrypt0 messages (RFC 9052), over the values of [`cbor`](crate::cbor).
+//!
+//! Messages are signed with the [`SigningKey`]s of the [`pki`](crate::pki) module, in ES256 or
+//! EdDSA, and encrypted with AES-128-GCM or ChaCha20-Poly1305 under a random nonce, of which a
+//! key shouldn't encrypt more than 2^32 messages. The algorithm goes in the protected header,
+//! and a message is only accepted with the algorithm the caller expects: that of the key it's
+//! verified with, or the cipher given. Headers with critical labels are rejected, as are
+//! detached payloads. Messages are written tagged, and read tagged or not.
+
+use crate::cbor::{CborError, Value};
+use crate::pki::{sign_raw, verify_raw, PkiError, PublicKey, SigningKey};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use sgx_tcrypto::aead::{Aead, Aes128Gcm, ChaCha20Poly1305, Tag, TAG_LEN};
+use sgx_tcrypto::rng::fill_random;
+use sgx_types::*;
+
+const SIGN1_TAG: u64 = 18;
+const ENCRYPT0_TAG: u64 = 16;
+
+const ALG_LABEL: i64 = 1;
+const CRIT_LABEL: i64 = 2;
+const KID_LABEL: i64 = 4;
+const IV_LABEL: i64 = 5;
+
+const ES256: i64 = -7;
+const EDDSA: i64 = -8;
+
+/// The length of the nonces of both ciphers.
+const NONCE_LEN: usize = 12;
+
+/// An error signing, verifying, encrypting or decrypting a message.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CoseError {
+    /// Signing, verifying or encrypting failed.
+    Crypto(sgx_status_t),
+    /// The message isn't CBOR of a COSE message of the expected kind.
+    Malformed,
+    /// The key isn't for ES256 or EdDSA, or the header names another algorithm than the one
+    /// expected, or critical labels.
+    UnsupportedAlgorithm,
+    /// The signature doesn't verify.
+    BadSignature,
+    /// The ciphertext or the external data was modified, or the key is wrong.
+    MacMismatch,
+}
+
+impl fmt::Display for CoseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CoseError::Crypto(status) => write!(f, "cryptographic operation failed: {}", status),
+            CoseError::Malformed => f.write_str("malformed COSE message"),
+            CoseError::UnsupportedAlgorithm => f.write_str("unsupported algorithm"),
+            CoseError::BadSignature => f.write_str("signature verification failed"),
+            CoseError::MacMismatch => f.write_str("message authentication failed"),
+        }
+    }
+}
+
+impl From<CborError> for CoseError {
+    fn from(_: CborError) -> CoseError {
+        CoseError::Malformed
+    }
+}
+
+impl From<PkiError> for CoseError {
+    fn from(error: PkiError) -> CoseError {
+        match error {
+            PkiError::Crypto(status) => CoseError::Crypto(status),
+            _ => CoseError::UnsupportedAlgorithm,
+        }
+    }
+}
+
+pub type CoseResult<T> = Result<T, CoseError>;
+
+/// The content encryption algorithm of a COSE_Encrypt0 message.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Cipher {
+    /// AES-128-GCM, `A128GCM`, with a 16-byte key.
+    Aes128Gcm,
+    /// ChaCha20-Poly1305 with a 32-byte key.
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    fn id(self) -> i64 {
+        match self {
+            Cipher::Aes128Gcm => 1,
+            Cipher::ChaCha20Poly1305 => 24,
+        }
+    }
+}
+
+/// Returns the `alg` of signatures by `key`.
+fn algorithm(key: &PublicKey) -> CoseResult<i64> {
+    match key {
+        PublicKey::P256(_) => Ok(ES256),
+        PublicKey::Ed25519(_) => Ok(EDDSA),
+        PublicKey::Rsa { .. } => Err(CoseError::UnsupportedAlgorithm),
+    }
+}
+
+/// Returns the serialized protected header naming `alg`.
+fn protected_header(alg: i64) -> Vec<u8> {
+    Value::Map(vec![(Value::from(ALG_LABEL), Value::from(alg))]).encode()
+}
+
+/// Returns the unprotected header, with `key_id` and `nonce` if given.
+fn unprotected_header(key_id: Option<&[u8]>, nonce: Option<&[u8]>) -> Value {
+    let mut header = Vec::new();
+    if let Some(key_id) = key_id {
+        header.push((Value::from(KID_LABEL), Value::from(key_id)));
+    }
+    if let Some(nonce) = nonce {
+        header.push((Value::from(IV_LABEL), Value::from(nonce)));
+    }
+    Value::Map(header)
+}
+
+/// A decoded message: the serialized and decoded protected header, the unprotected header,
+/// and the other items of the array.
+struct Message {
+    protected: Vec<u8>,
+    protected_header: Value,
+    unprotected_header: Value,
+    items: Vec<Value>,
+}
+
+impl Message {
+    /// Decodes a message of `items` array items, tagged `tag` or untagged.
+    fn decode(message: &[u8], tag: u64, len: usize) -> CoseResult<Message> {
+        let items = match Value::decode(message)? {
+            Value::Tag(t, value) if t == tag => *value,
+            Value::Tag(..) => return Err(CoseError::Malformed),
+            value => value,
+        };
+        let mut items = match items {
+            Value::Array(values) if values.len() == len => values.into_iter(),
+            _ => return Err(CoseError::Malformed),
+        };
+        let protected = match items.next() {
+            Some(Value::Bytes(protected)) => protected,
+            _ => return Err(CoseError::Malformed),
+        };
+        // An empty protected header is encoded as an empty string rather than an empty map.
+        let protected_header = match protected.is_empty() {
+            true => Value::Map(Vec::new()),
+            false => Value::decode(&protected)?,
+        };
+        let unprotected_header = items.next().unwrap();
+        let (protected_map, unprotected_map) =
+            match (protected_header.as_map(), unprotected_header.as_map()) {
+                (Some(protected), Some(unprotected)) => (protected, unprotected),
+                _ => return Err(CoseError::Malformed),
+            };
+        if protected_map.iter().any(|(label, _)| unprotected_map.iter().any(|(l, _)| l == label)) {
+            return Err(CoseError::Malformed);
+        }
+        let message = Message {
+            protected,
+            protected_header,
+            unprotected_header,
+            items: items.collect(),
+        };
+        if message.header(CRIT_LABEL).is_some() {
+            return Err(CoseError::UnsupportedAlgorithm);
+        }
+        Ok(message)
+    }
+
+    fn header(&self, label: i64) -> Option<&Value> {
+        let label = Value::from(label);
+        self.protected_header.get(&label).or_else(|| self.unprotected_header.get(&label))
+    }
+
+    /// Fails unless the protected header names the algorithm `alg`.
+    fn check_algorithm(&self, alg: i64) -> CoseResult<()> {
+        match self.protected_header.get(&Value::from(ALG_LABEL)) {
+            Some(value) if value.as_i64() == Some(alg) => Ok(()),
+            _ => Err(CoseError::UnsupportedAlgorithm),
+        }
+    }
+}
+
+/// Returns the `Sig_structure` of a COSE_Sign1 message.
+fn sig_structure(protected: &[u8], external_aad: &[u8], payload: &[u8]) -> Vec<u8> {
+    Value::Array(vec![
+        Value::from("Signature1"),
+        Value::from(protected),
+        Value::from(external_aad),
+        Value::from(payload),
+    ])
+    .encode()
+}
+
+/// Returns the `Enc_structure` of a COSE_Encrypt0 message.
+fn enc_structure(protected: &[u8], external_aad: &[u8]) -> Vec<u8> {
+    Value::Array(vec![Value::from("Encrypt0"), Value::from(protected), Value::from(external_aad)])
+        .encode()
+}
+
+/// Returns the COSE_Sign1 message of `payload` signed by `key`, along with `external_aad`
+/// which isn't part of the message, and with a header naming the key `key_id` if given.
+pub fn sign1(
+    payload: &[u8],
+    external_aad: &[u8],
+    key_id: Option<&[u8]>,
+    key: &dyn SigningKey,
+) -> CoseResult<Vec<u8>> {
+    let protected = protected_header(algorithm(&key.public_key())?);
+    let signature = sign_raw(key, &sig_structure(&protected, external_aad, payload))?;
+    let message = Value::Array(vec![
+        Value::Bytes(protected),
+        unprotected_header(key_id, None),
+        Value::from(payload),
+        Value::Bytes(signature),
+    ]);
+    Ok(Value::Tag(SIGN1_TAG, Box::new(message)).encode())
+}
+
+/// Verifies the COSE_Sign1 `message` with `key` and `external_aad`, returning its payload.
+pub fn verify_sign1(message: &[u8], external_aad: &[u8], key: &PublicKey) -> CoseResult<Vec<u8>> {
+    let message = Message::decode(message, SIGN1_TAG, 4)?;
+    message.check_algorithm(algorithm(key)?)?;
+    let (payload, signature) = match (&message.items[0], &message.items[1]) {
+        (Value::Bytes(payload), Value::Bytes(signature)) => (payload, signature),
+        _ => return Err(CoseError::Malformed),
+    };
+    let sig_structure = sig_structure(&message.protected, external_aad, payload);
+    if !verify_raw(key, &sig_structure, signature)? {
+        return Err(CoseError::BadSignature);
+    }
+    Ok(payload.clone())
+}
+
+fn seal<A: Aead>(key: &[u8], nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> SgxResult<Vec<u8>> {
+    let aead = A::new(key)?;
+    let mut ciphertext = vec![0_u8; plaintext.len() + TAG_LEN];
+    let tag = aead.encrypt(nonce, aad, plaintext, &mut ciphertext)?;
+    ciphertext[plaintext.len()..].copy_from_slice(&tag);
+    Ok(ciphertext)
+}
+
+fn open<A: Aead>(key: &[u8], nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> SgxResult<Vec<u8>> {
+    let aead = A::new(key)?;
+    let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_LEN);
+    let mut tag_copy = Tag::default();
+    tag_copy.copy_from_slice(tag);
+    let mut plaintext = vec![0_u8; ciphertext.len()];
+    aead.decrypt(nonce, aad, ciphertext, &tag_copy, &mut plaintext)?;
+    Ok(plaintext)
+}
+
+/// Returns the COSE_Encrypt0 message of `plaintext` encrypted with `cipher` under `key`, along
+/// with `external_aad` which isn't part of the message, and with a header naming the key
+/// `key_id` if given.
+pub fn encrypt0(
+    cipher: Cipher,
+    key: &[u8],
+    plaintext: &[u8],
+    external_aad: &[u8],
+    key_id: Option<&[u8]>,
+) -> CoseResult<Vec<u8>> {
+    let protected = protected_header(cipher.id());
+    let mut nonce = [0_u8; NONCE_LEN];
+    fill_random(&mut nonce).map_err(CoseError::Crypto)?;
+    let aad = enc_structure(&protected, external_aad);
+    let ciphertext = match cipher {
+        Cipher::Aes128Gcm => seal::<Aes128Gcm>(key, &nonce, &aad, plaintext),
+        Cipher::ChaCha20Poly1305 => seal::<ChaCha20Poly1305>(key, &nonce, &aad, plaintext),
+    }
+    .map_err(CoseError::Crypto)?;
+    let message = Value::Array(vec![
+        Value::Bytes(protected),
+        unprotected_header(key_id, Some(&nonce)),
+        Value::Bytes(ciphertext),
+    ]);
+    Ok(Value::Tag(ENCRYPT0_TAG, Box::new(message)).encode())
+}
+
+/// Decrypts the COSE_Encrypt0 `message`, which must be encrypted with `cipher`, with `key` and
+/// `external_aad`.
+pub fn decrypt0(
+    cipher: Cipher,
+    key: &[u8],
+    message: &[u8],
+    external_aad: &[u8],
+) -> CoseResult<Vec<u8>> {
+    let message = Message::decode(message, ENCRYPT0_TAG, 3)?;
+    message.check_algorithm(cipher.id())?;
+    let nonce = match message.header(IV_LABEL) {
+        Some(Value::Bytes(nonce)) if nonce.len() == NONCE_LEN => nonce,
+        _ => return Err(CoseError::Malformed),
+    };
+    let ciphertext = match &message.items[0] {
+        Value::Bytes(ciphertext) if ciphertext.len() >= TAG_LEN => ciphertext,
+        _ => return Err(CoseError::Malformed),
+    };
+    let aad = enc_structure(&message.protected, external_aad);
+    match cipher {
+        Cipher::Aes128Gcm => open::<Aes128Gcm>(key, nonce, &aad, ciphertext),
+        Cipher::ChaCha20Poly1305 => open::<ChaCha20Poly1305>(key, nonce, &aad, ciphertext),
+    }
+    .map_err(|status| match status {
+        sgx_status_t::SGX_ERROR_MAC_MISMATCH => CoseError::MacMismatch,
+        status => CoseError::Crypto(status),
+    })
+}
+
+/// Returns the key identifier of a COSE_Sign1 or COSE_Encrypt0 `message`, without verifying
+/// it, to pick the key to verify or decrypt it with.
+pub fn key_id(message: &[u8]) -> CoseResult<Option<Vec<u8>>> {
+    let message = Message::decode(message, SIGN1_TAG, 4)
+        .or_else(|_| Message::decode(message, ENCRYPT0_TAG, 3))?;
+    match message.header(KID_LABEL) {
+        Some(Value::Bytes(key_id)) => Ok(Some(key_id.clone())),
+        Some(_) => Err(CoseError::Malformed),
+        None => Ok(None),
+    }
+}
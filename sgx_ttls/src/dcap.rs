@@ -0,0 +1,1655 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! DCAP quotes, and the collateral to verify them.
+//!
+//! Attesting an enclave with DCAP takes a report targeting the quoting enclave (QE), the quote
+//! the QE signs over it, and the collateral of the platform: the TCB info of its FMSPC, the
+//! identity of the QE, the CRLs of the PCK certificates and of the root CA, and the issuer
+//! chains of all of them. [`QuoteBuilder`] goes through all of it and returns an
+//! [`EvidenceBundle`] a verifier can check on its own, offline, such as an auditor sent the
+//! bundle of a wallet enclave.
+//!
+//! Quotes are obtained through the `sgx_tls_*_ocall`s of `sgx_ttls.edl`, as RA-TLS obtains
+//! them, or through another [`Quoter`]. Collateral comes from a [`CollateralSource`], such as
+//! a PCCS, or the host caching it; it's signed by Intel, so it needs no trusted channel.
+//!
+//! [`verify_quote`] verifies a bundle inside the enclave, as the QVL of Intel does out of it,
+//! so that an enclave deciding whether to trust another relies on neither the host nor the
+//! quote verification enclave: the certificate chains and CRLs up to a pinned root CA, the
+//! signatures of the quote, the TCB info and the QE identity, and the TCB levels of the
+//! platform and the QE, at a trusted time.
+//!
+//! Deployments which also need evidence of the host, such as a TPM quote of its measured boot,
+//! add it to the bundle with a [`HostAttester`], bound to the quote, and [`verify_evidence`]
+//! checks it with a [`HostVerifier`] of each kind they require, as [`tpm`](crate::tpm) does
+//! for TPM 2.0 quotes. The binding only ties the host evidence to the quote, so that it can't
+//! be presented with another one; that the TPM is on the platform of the enclave is up to the
+//! deployment, e.g. by enrolling the keys of both.
+//!
+//! An evidence bundle is the CBOR map:
+//!
+//! | key            | value                                                               |
+//! |----------------|---------------------------------------------------------------------|
+//! | `"version"`    | [`BUNDLE_VERSION`]                                                  |
+//! | `"quote"`      | the quote, as bytes                                                 |
+//! | `"collateral"` | a map of the fields of [`Collateral`], by name, the integers as     |
+//! |                | unsigned integers and the rest as bytes                             |
+//! | `"host"`       | if there's host evidence, an array of maps of its `"kind"`, as      |
+//! |                | text, and `"evidence"`, as bytes                                    |
+
+use crate::cbor::Value;
+use crate::der::{self, DerError, Reader};
+use crate::json;
+use crate::pki::{
+    self, Certificate, ChainPolicy, Crl, KeyUsage, PkiError, PublicKey, COMMON_NAME_OID,
+};
+use crate::policy::{Policy, PolicyError};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
+use core::mem;
+use core::ptr;
+use core::slice;
+use sgx_tcrypto::hash::{Hash, Sha256};
+use sgx_tse::rsgx_create_report;
+use sgx_types::*;
+
+/// The version of the quotes parsed.
+pub const QUOTE_VERSION: u16 = 3;
+
+/// The version of the bundles written by [`EvidenceBundle::to_bytes`].
+pub const BUNDLE_VERSION: u64 = 1;
+
+/// The longest quote the QE is asked for.
+pub const MAX_QUOTE_LEN: u32 = 64 * 1024;
+
+const ECDSA_P256: u16 = 2;
+const PCK_CERT_CHAIN: u16 = 5;
+
+const HEADER_LEN: usize = 48;
+const REPORT_BODY_LEN: usize = 384;
+const SIGNED_LEN: usize = HEADER_LEN + REPORT_BODY_LEN;
+const SIGNATURE_DATA_MIN_LEN: usize = 64 + 64 + REPORT_BODY_LEN + 64 + 2 + 2 + 4;
+const ATTRIBUTES_OFFSET: usize = 48;
+
+/// The QE vendor ID of the quotes of the QE of Intel.
+const INTEL_QE_VENDOR_ID: [u8; 16] = [
+    0x93, 0x9a, 0x72, 0x33, 0xf7, 0x9c, 0x4c, 0xa9, 0x94, 0x0a, 0x0d, 0xb3, 0x95, 0x7f, 0x06, 0x07,
+];
+
+/// OID 1.2.840.113741.1.13.1 of the SGX extension of PCK certificates.
+const SGX_EXTENSION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf8, 0x4d, 0x01, 0x0d, 0x01];
+const TCB_ARC: u8 = 2;
+const PCE_ID_ARC: u8 = 3;
+const FMSPC_ARC: u8 = 4;
+const PCE_SVN_ARC: u8 = 17;
+const CPU_SVN_ARC: u8 = 18;
+
+const PROCESSOR_CA: &str = "Intel SGX PCK Processor CA";
+const PLATFORM_CA: &str = "Intel SGX PCK Platform CA";
+const TCB_SIGNING: &str = "Intel SGX TCB Signing";
+
+/// An error producing or reading DCAP evidence.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DcapError {
+    /// Creating the report, or an ocall, failed.
+    Sgx(sgx_status_t),
+    /// The QE failed to produce the quote.
+    Quote(sgx_quote3_error_t),
+    /// The quote isn't a well-formed ECDSA quote of version [`QUOTE_VERSION`].
+    MalformedQuote,
+    /// The PCK certificates of the quote are malformed, or lack their SGX extension.
+    MalformedCertificate,
+    /// The quote isn't one of the report it was asked for, or doesn't carry the report data
+    /// expected of it.
+    ReportMismatch,
+    /// Fetching the collateral failed.
+    Collateral(sgx_status_t),
+    /// An evidence bundle is malformed or of an unknown version.
+    MalformedBundle,
+    /// The collateral isn't well-formed.
+    MalformedCollateral,
+    /// A certificate chain or CRL doesn't verify up to the root CA at the trusted time.
+    UntrustedCertificate(PkiError),
+    /// A certificate of a chain is revoked.
+    Revoked,
+    /// A signature over the quote, the report of the QE or the collateral doesn't verify, or
+    /// the QE didn't bind the attestation key to its report.
+    BadSignature,
+    /// The collateral isn't that of the platform or QE of the quote.
+    CollateralMismatch,
+    /// The collateral is past its next update, or was issued after the trusted time.
+    CollateralExpired,
+    /// The report of the QE doesn't match the QE identity.
+    UntrustedQe,
+    /// The TCB info has no TCB level the platform is at.
+    UnsupportedTcb,
+    /// The platform or the enclave of the quote isn't trusted by the policy.
+    Policy(PolicyError),
+    /// The bundle has no host evidence of a kind required.
+    MissingHostEvidence,
+    /// Host evidence is malformed, isn't bound to the quote, or isn't trusted.
+    UntrustedHost,
+}
+
+impl fmt::Display for DcapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DcapError::Sgx(status) => write!(f, "SGX operation failed: {}", status),
+            DcapError::Quote(error) => write!(f, "quote generation failed: {}", error),
+            DcapError::MalformedQuote => f.write_str("malformed quote"),
+            DcapError::MalformedCertificate => f.write_str("malformed PCK certificate"),
+            DcapError::ReportMismatch => f.write_str("quote doesn't match the report"),
+            DcapError::Collateral(status) => write!(f, "fetching collateral failed: {}", status),
+            DcapError::MalformedBundle => f.write_str("malformed evidence bundle"),
+            DcapError::MalformedCollateral => f.write_str("malformed collateral"),
+            DcapError::UntrustedCertificate(error) => {
+                write!(f, "collateral certificate not trusted: {}", error)
+            }
+            DcapError::Revoked => f.write_str("certificate revoked"),
+            DcapError::BadSignature => f.write_str("quote signature verification failed"),
+            DcapError::CollateralMismatch => f.write_str("collateral not for the quote"),
+            DcapError::CollateralExpired => f.write_str("collateral expired"),
+            DcapError::UntrustedQe => f.write_str("QE doesn't match its identity"),
+            DcapError::UnsupportedTcb => f.write_str("TCB level of the platform unknown"),
+            DcapError::Policy(error) => error.fmt(f),
+            DcapError::MissingHostEvidence => f.write_str("host evidence missing"),
+            DcapError::UntrustedHost => f.write_str("host evidence not trusted"),
+        }
+    }
+}
+
+impl From<DerError> for DcapError {
+    fn from(_: DerError) -> DcapError {
+        DcapError::MalformedCertificate
+    }
+}
+
+impl From<PkiError> for DcapError {
+    fn from(_: PkiError) -> DcapError {
+        DcapError::MalformedCertificate
+    }
+}
+
+pub type DcapResult<T> = Result<T, DcapError>;
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn read_report_body(bytes: &[u8]) -> sgx_report_body_t {
+    assert!(bytes.len() >= mem::size_of::<sgx_report_body_t>());
+    unsafe { ptr::read_unaligned(bytes.as_ptr() as *const sgx_report_body_t) }
+}
+
+/// Returns the bytes of `body`, as a quote lays it out.
+pub(crate) fn report_body_bytes(body: &sgx_report_body_t) -> &[u8] {
+    let len = mem::size_of::<sgx_report_body_t>();
+    unsafe { slice::from_raw_parts(body as *const sgx_report_body_t as *const u8, len) }
+}
+
+/// A parsed ECDSA quote of version 3, borrowing its bytes.
+#[derive(Copy, Clone)]
+pub struct Quote<'a> {
+    bytes: &'a [u8],
+    signature: &'a [u8],
+    attestation_key: &'a [u8],
+    qe_report: &'a [u8],
+    qe_report_signature: &'a [u8],
+    qe_auth_data: &'a [u8],
+    certification_data: &'a [u8],
+}
+
+impl<'a> Quote<'a> {
+    /// Parses `bytes`, which must hold exactly one quote whose certification data is the PCK
+    /// certificate chain, as the QE of DCAP writes them. Nothing is verified.
+    pub fn parse(bytes: &'a [u8]) -> DcapResult<Quote<'a>> {
+        let malformed = DcapError::MalformedQuote;
+        if bytes.len() < SIGNED_LEN + 4 + SIGNATURE_DATA_MIN_LEN {
+            return Err(malformed);
+        }
+        if read_u16(bytes, 0) != QUOTE_VERSION || read_u16(bytes, 2) != ECDSA_P256 {
+            return Err(malformed);
+        }
+        let signature_len = read_u32(bytes, SIGNED_LEN) as usize;
+        let data = &bytes[SIGNED_LEN + 4..];
+        if signature_len != data.len() {
+            return Err(malformed);
+        }
+
+        let (signature, data) = data.split_at(64);
+        let (attestation_key, data) = data.split_at(64);
+        let (qe_report, data) = data.split_at(REPORT_BODY_LEN);
+        let (qe_report_signature, data) = data.split_at(64);
+        let auth_len = read_u16(data, 0) as usize;
+        let data = &data[2..];
+        if data.len() < auth_len + 6 {
+            return Err(malformed);
+        }
+        let (qe_auth_data, data) = data.split_at(auth_len);
+        let certification_type = read_u16(data, 0);
+        let certification_len = read_u32(data, 2) as usize;
+        let certification_data = &data[6..];
+        if certification_type != PCK_CERT_CHAIN || certification_len != certification_data.len() {
+            return Err(malformed);
+        }
+        Ok(Quote {
+            bytes,
+            signature,
+            attestation_key,
+            qe_report,
+            qe_report_signature,
+            qe_auth_data,
+            certification_data,
+        })
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn header(&self) -> sgx_quote_header_t {
+        unsafe { ptr::read_unaligned(self.bytes.as_ptr() as *const sgx_quote_header_t) }
+    }
+
+    /// Returns the report body of the attested enclave.
+    pub fn report_body(&self) -> sgx_report_body_t {
+        read_report_body(&self.bytes[HEADER_LEN..])
+    }
+
+    /// Returns the header and the report body, which the attestation key signs.
+    pub fn signed_data(&self) -> &'a [u8] {
+        &self.bytes[..SIGNED_LEN]
+    }
+
+    /// Returns the ECDSA signature of the attestation key, `r` then `s`, big endian.
+    pub fn signature(&self) -> &'a [u8] {
+        self.signature
+    }
+
+    /// Returns the P-256 attestation key of the QE, `x` then `y`, big endian.
+    pub fn attestation_key(&self) -> &'a [u8] {
+        self.attestation_key
+    }
+
+    /// Returns the report body of the QE, which the PCK signs.
+    pub fn qe_report(&self) -> &'a [u8] {
+        self.qe_report
+    }
+
+    pub fn qe_report_body(&self) -> sgx_report_body_t {
+        read_report_body(self.qe_report)
+    }
+
+    /// Returns the ECDSA signature of the PCK over the report of the QE.
+    pub fn qe_report_signature(&self) -> &'a [u8] {
+        self.qe_report_signature
+    }
+
+    /// Returns the data the QE hashed, along with the attestation key, into its report data.
+    pub fn qe_auth_data(&self) -> &'a [u8] {
+        self.qe_auth_data
+    }
+
+    /// Returns the DER PCK certificate chain, from the PCK certificate up to the root CA.
+    pub fn pck_chain(&self) -> DcapResult<Vec<Vec<u8>>> {
+        let chain = pem_certificates(self.certification_data)?;
+        if chain.len() < 2 {
+            return Err(DcapError::MalformedCertificate);
+        }
+        Ok(chain)
+    }
+
+    /// Returns what the PCK certificate of the quote says of the platform.
+    pub fn pck_info(&self) -> DcapResult<PckInfo> {
+        let chain = self.pck_chain()?;
+        PckInfo::parse(&chain[0], &chain[1])
+    }
+}
+
+impl fmt::Debug for Quote<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Quote")
+            .field("len", &self.bytes.len())
+            .field("mr_enclave", &self.report_body().mr_enclave.m)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Decodes the certificates of the PEM `pem`, ignoring anything around them, such as the NUL
+/// terminating the chains of the QE.
+pub(crate) fn pem_certificates(pem: &[u8]) -> DcapResult<Vec<Vec<u8>>> {
+    const BEGIN: &[u8] = b"-----BEGIN CERTIFICATE-----";
+    const END: &[u8] = b"-----END CERTIFICATE-----";
+    let find = |data: &[u8], marker: &[u8]| data.windows(marker.len()).position(|w| w == marker);
+
+    let mut certificates = Vec::new();
+    let mut rest = pem;
+    while let Some(begin) = find(rest, BEGIN) {
+        rest = &rest[begin + BEGIN.len()..];
+        let end = find(rest, END).ok_or(DcapError::MalformedCertificate)?;
+        let der = base64_decode(&rest[..end]).ok_or(DcapError::MalformedCertificate)?;
+        certificates.push(der);
+        rest = &rest[end + END.len()..];
+    }
+    Ok(certificates)
+}
+
+/// Decodes padded base64, skipping whitespace, and rejecting non-zero trailing bits.
+fn base64_decode(text: &[u8]) -> Option<Vec<u8>> {
+    let mut symbols: Vec<u8> = text.iter().copied().filter(|c| !c.is_ascii_whitespace()).collect();
+    if !symbols.len().is_multiple_of(4) {
+        return None;
+    }
+    let padding = symbols.iter().rev().take_while(|&&c| c == b'=').count();
+    if padding > 2 {
+        return None;
+    }
+    symbols.truncate(symbols.len() - padding);
+
+    let mut data = Vec::with_capacity(symbols.len() * 3 / 4);
+    for chunk in symbols.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut bits = 0_u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return None,
+            };
+            bits |= (value as u32) << (18 - 6 * i);
+        }
+        let len = chunk.len() - 1;
+        if bits & (0xff_ffff >> (8 * len)) != 0 {
+            return None;
+        }
+        for i in 0..len {
+            data.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(data)
+}
+
+/// The CA which issued a PCK certificate.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PckCa {
+    /// The CA of the PCK certificates of single-socket platforms.
+    Processor,
+    /// The CA of the PCK certificates of multi-package platforms.
+    Platform,
+}
+
+impl PckCa {
+    /// Returns the name of the CA in the API of the PCS, `processor` or `platform`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PckCa::Processor => "processor",
+            PckCa::Platform => "platform",
+        }
+    }
+}
+
+/// The platform a PCK certificate was issued to, as its SGX extension describes it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PckInfo {
+    /// The family, model, stepping and platform type of the processor, which TCB info is
+    /// published for.
+    pub fmspc: [u8; 6],
+    pub pce_id: [u8; 2],
+    pub ca: PckCa,
+    /// The SVNs of the TCB components of the platform.
+    pub tcb_components: [u8; 16],
+    pub pce_svn: u16,
+    pub cpu_svn: [u8; 16],
+}
+
+impl PckInfo {
+    /// Reads the SGX extension of the DER PCK certificate `pck`, issued by `issuer`.
+    pub fn parse(pck: &[u8], issuer: &[u8]) -> DcapResult<PckInfo> {
+        let malformed = DcapError::MalformedCertificate;
+        let ca = match Certificate::parse(issuer)?.subject_attribute(COMMON_NAME_OID) {
+            Some(PROCESSOR_CA) => PckCa::Processor,
+            Some(PLATFORM_CA) => PckCa::Platform,
+            _ => return Err(malformed),
+        };
+        let certificate = Certificate::parse(pck)?;
+        let extension = certificate.extension(SGX_EXTENSION_OID).ok_or(malformed)?;
+
+        let (mut fmspc, mut pce_id, mut tcb) = (None, None, None);
+        let mut outer = Reader::new(extension.value);
+        let mut items = outer.read_nested(der::SEQUENCE)?;
+        outer.finish()?;
+        while !items.is_empty() {
+            let mut item = items.read_nested(der::SEQUENCE)?;
+            match sgx_arc(item.read(der::OID)?, &[]) {
+                Some(FMSPC_ARC) => fmspc = Some(item.read(der::OCTET_STRING)?),
+                Some(PCE_ID_ARC) => pce_id = Some(item.read(der::OCTET_STRING)?),
+                Some(TCB_ARC) => tcb = Some(item.read_nested(der::SEQUENCE)?),
+                _ => continue,
+            }
+            item.finish()?;
+        }
+
+        let mut info = PckInfo {
+            fmspc: fmspc.ok_or(malformed)?.try_into().map_err(|_| malformed)?,
+            pce_id: pce_id.ok_or(malformed)?.try_into().map_err(|_| malformed)?,
+            ca,
+            tcb_components: [0; 16],
+            pce_svn: 0,
+            cpu_svn: [0; 16],
+        };
+        let mut tcb = tcb.ok_or(malformed)?;
+        let mut seen = 0_u32;
+        while !tcb.is_empty() {
+            let mut item = tcb.read_nested(der::SEQUENCE)?;
+            let arc = sgx_arc(item.read(der::OID)?, &[TCB_ARC]).ok_or(malformed)?;
+            match arc {
+                1..=16 => info.tcb_components[arc as usize - 1] = read_svn(&mut item, 1)? as u8,
+                PCE_SVN_ARC => info.pce_svn = read_svn(&mut item, 2)? as u16,
+                CPU_SVN_ARC => {
+                    let cpu_svn = item.read(der::OCTET_STRING)?;
+                    info.cpu_svn = cpu_svn.try_into().map_err(|_| malformed)?;
+                }
+                _ => continue,
+            }
+            item.finish()?;
+            seen |= 1 << arc;
+        }
+        if seen != 0x7_fffe {
+            return Err(malformed);
+        }
+        Ok(info)
+    }
+}
+
+/// Returns the last arc of `oid`, if it's an arc of the SGX extension below `path`.
+fn sgx_arc(oid: &[u8], path: &[u8]) -> Option<u8> {
+    let prefix = SGX_EXTENSION_OID.len() + path.len();
+    if oid.len() != prefix + 1 || oid[..SGX_EXTENSION_OID.len()] != *SGX_EXTENSION_OID {
+        return None;
+    }
+    if oid[SGX_EXTENSION_OID.len()..prefix] != *path || oid[prefix] >= 0x80 {
+        return None;
+    }
+    Some(oid[prefix])
+}
+
+/// Reads an SVN of at most `len` bytes.
+fn read_svn(item: &mut Reader<'_>, len: usize) -> DcapResult<u32> {
+    let value = der::read_uint(item.read(der::INTEGER)?)?;
+    if value.len() > len {
+        return Err(DcapError::MalformedCertificate);
+    }
+    Ok(value.iter().fold(0, |svn, &b| svn << 8 | b as u32))
+}
+
+/// The collateral of a platform, as `sgx_ql_qve_collateral_t` holds it.
+///
+/// The issuer chains are PEM, the CRLs DER or hex-encoded DER, and the TCB info and QE
+/// identity the signed JSON of the PCS.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Collateral {
+    pub version: u32,
+    /// 0 for SGX, 0x81 for TDX.
+    pub tee_type: u32,
+    pub pck_crl_issuer_chain: Vec<u8>,
+    pub root_ca_crl: Vec<u8>,
+    pub pck_crl: Vec<u8>,
+    pub tcb_info_issuer_chain: Vec<u8>,
+    pub tcb_info: Vec<u8>,
+    pub qe_identity_issuer_chain: Vec<u8>,
+    pub qe_identity: Vec<u8>,
+}
+
+impl Collateral {
+    /// Returns the earliest next update of the TCB info, the QE identity and the CRLs, after
+    /// which the collateral no longer verifies. Nothing is verified.
+    pub fn next_update(&self) -> DcapResult<time_t> {
+        let malformed = DcapError::MalformedCollateral;
+        let json_next_update = |text: &[u8], key: &str| {
+            let value = json::Value::parse(trim_nul(text)).map_err(|_| malformed)?;
+            json_date(value.get(key).and_then(|body| body.get("nextUpdate"))).ok_or(malformed)
+        };
+        let crl_next_update = |crl: &[u8]| -> DcapResult<time_t> {
+            let der = crl_der(crl)?;
+            let crl = Crl::parse(&der).map_err(|_| malformed)?;
+            Ok(crl.next_update().unwrap_or(time_t::MAX))
+        };
+        let next_updates = [
+            json_next_update(&self.tcb_info, "tcbInfo")?,
+            json_next_update(&self.qe_identity, "enclaveIdentity")?,
+            crl_next_update(&self.root_ca_crl)?,
+            crl_next_update(&self.pck_crl)?,
+        ];
+        Ok(next_updates.iter().copied().min().unwrap_or(time_t::MAX))
+    }
+
+    fn fields(&self) -> [(&'static str, &Vec<u8>); 7] {
+        [
+            ("pck_crl_issuer_chain", &self.pck_crl_issuer_chain),
+            ("root_ca_crl", &self.root_ca_crl),
+            ("pck_crl", &self.pck_crl),
+            ("tcb_info_issuer_chain", &self.tcb_info_issuer_chain),
+            ("tcb_info", &self.tcb_info),
+            ("qe_identity_issuer_chain", &self.qe_identity_issuer_chain),
+            ("qe_identity", &self.qe_identity),
+        ]
+    }
+
+    pub(crate) fn to_value(&self) -> Value {
+        let mut entries = vec![
+            (Value::from("version"), Value::Unsigned(self.version as u64)),
+            (Value::from("tee_type"), Value::Unsigned(self.tee_type as u64)),
+        ];
+        for (name, field) in self.fields() {
+            entries.push((Value::from(name), Value::Bytes(field.clone())));
+        }
+        Value::Map(entries)
+    }
+
+    pub(crate) fn from_value(value: &Value) -> DcapResult<Collateral> {
+        let malformed = DcapError::MalformedBundle;
+        let integer = |name: &str| match value.get(&Value::from(name)) {
+            Some(&Value::Unsigned(n)) if n <= u32::MAX as u64 => Ok(n as u32),
+            _ => Err(malformed),
+        };
+        let bytes = |name: &str| match value.get(&Value::from(name)) {
+            Some(Value::Bytes(bytes)) => Ok(bytes.clone()),
+            _ => Err(malformed),
+        };
+        Ok(Collateral {
+            version: integer("version")?,
+            tee_type: integer("tee_type")?,
+            pck_crl_issuer_chain: bytes("pck_crl_issuer_chain")?,
+            root_ca_crl: bytes("root_ca_crl")?,
+            pck_crl: bytes("pck_crl")?,
+            tcb_info_issuer_chain: bytes("tcb_info_issuer_chain")?,
+            tcb_info: bytes("tcb_info")?,
+            qe_identity_issuer_chain: bytes("qe_identity_issuer_chain")?,
+            qe_identity: bytes("qe_identity")?,
+        })
+    }
+}
+
+/// Where the collateral of platforms comes from.
+pub trait CollateralSource {
+    /// Returns the collateral of the platforms of `fmspc`, whose PCK certificates `ca` issues.
+    fn collateral(&mut self, fmspc: &[u8; 6], ca: PckCa) -> DcapResult<Collateral>;
+}
+
+impl<S: CollateralSource + ?Sized> CollateralSource for &mut S {
+    fn collateral(&mut self, fmspc: &[u8; 6], ca: PckCa) -> DcapResult<Collateral> {
+        (**self).collateral(fmspc, ca)
+    }
+}
+
+/// The quoting enclave.
+pub trait Quoter {
+    /// Returns the target info of the QE, for reports to target it.
+    fn target_info(&mut self) -> DcapResult<sgx_target_info_t>;
+
+    /// Returns the quote of `report`.
+    fn quote(&mut self, report: &sgx_report_t) -> DcapResult<Vec<u8>>;
+}
+
+impl<Q: Quoter + ?Sized> Quoter for &mut Q {
+    fn target_info(&mut self) -> DcapResult<sgx_target_info_t> {
+        (**self).target_info()
+    }
+
+    fn quote(&mut self, report: &sgx_report_t) -> DcapResult<Vec<u8>> {
+        (**self).quote(report)
+    }
+}
+
+extern "C" {
+    fn sgx_tls_get_qe_target_info_ocall(
+        result: *mut sgx_quote3_error_t,
+        target_info: *mut sgx_target_info_t,
+        target_info_size: usize,
+    ) -> sgx_status_t;
+    fn sgx_tls_get_quote_size_ocall(
+        result: *mut sgx_quote3_error_t,
+        quote_size: *mut u32,
+    ) -> sgx_status_t;
+    fn sgx_tls_get_quote_ocall(
+        result: *mut sgx_quote3_error_t,
+        report: *const sgx_report_t,
+        report_size: usize,
+        quote: *mut u8,
+        quote_size: u32,
+    ) -> sgx_status_t;
+}
+
+/// Turns the status of an ocall and its result into an error.
+fn ocall_result(status: sgx_status_t, result: sgx_quote3_error_t) -> DcapResult<()> {
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(DcapError::Sgx(status));
+    }
+    if result != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        return Err(DcapError::Quote(result));
+    }
+    Ok(())
+}
+
+/// The QE of the platform, reached through the `sgx_tls_*_ocall`s of `sgx_ttls.edl`, which
+/// the enclave has to import.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct OcallQuoter;
+
+impl Quoter for OcallQuoter {
+    fn target_info(&mut self) -> DcapResult<sgx_target_info_t> {
+        let mut target_info = sgx_target_info_t::default();
+        let mut result = sgx_quote3_error_t::SGX_QL_SUCCESS;
+        let size = mem::size_of::<sgx_target_info_t>();
+        let status =
+            unsafe { sgx_tls_get_qe_target_info_ocall(&mut result, &mut target_info, size) };
+        ocall_result(status, result)?;
+        Ok(target_info)
+    }
+
+    fn quote(&mut self, report: &sgx_report_t) -> DcapResult<Vec<u8>> {
+        let mut result = sgx_quote3_error_t::SGX_QL_SUCCESS;
+        let mut size = 0_u32;
+        let status = unsafe { sgx_tls_get_quote_size_ocall(&mut result, &mut size) };
+        ocall_result(status, result)?;
+        if size == 0 || size > MAX_QUOTE_LEN {
+            return Err(DcapError::MalformedQuote);
+        }
+
+        let mut quote = vec![0_u8; size as usize];
+        let report_size = mem::size_of::<sgx_report_t>();
+        let status = unsafe {
+            sgx_tls_get_quote_ocall(&mut result, report, report_size, quote.as_mut_ptr(), size)
+        };
+        ocall_result(status, result)?;
+        Ok(quote)
+    }
+}
+
+/// Produces the evidence of the enclave: its quote, with the collateral to verify it.
+///
+/// ```ignore
+/// let bundle = QuoteBuilder::new(pccs).report_data(report_data).build()?;
+/// send_to_auditor(&bundle.to_bytes());
+/// ```
+pub struct QuoteBuilder<S: CollateralSource, Q: Quoter = OcallQuoter> {
+    source: S,
+    quoter: Q,
+    report_data: sgx_report_data_t,
+}
+
+impl<S: CollateralSource> QuoteBuilder<S, OcallQuoter> {
+    /// Creates a builder quoting through the ocalls, with collateral from `source`.
+    pub fn new(source: S) -> QuoteBuilder<S, OcallQuoter> {
+        QuoteBuilder::with_quoter(source, OcallQuoter)
+    }
+}
+
+impl<S: CollateralSource, Q: Quoter> QuoteBuilder<S, Q> {
+    pub fn with_quoter(source: S, quoter: Q) -> QuoteBuilder<S, Q> {
+        QuoteBuilder { source, quoter, report_data: sgx_report_data_t::default() }
+    }
+
+    /// Sets the report data of the quote, zero by default, e.g. the hash of a key of the
+    /// enclave to bind it to the quote.
+    pub fn report_data(mut self, report_data: sgx_report_data_t) -> QuoteBuilder<S, Q> {
+        self.report_data = report_data;
+        self
+    }
+
+    pub(crate) fn set_report_data(&mut self, report_data: sgx_report_data_t) {
+        self.report_data = report_data;
+    }
+
+    /// Creates a report targeting the QE, has the QE quote it, and fetches the collateral of
+    /// the platform the PCK certificate of the quote names.
+    ///
+    /// Fails with [`DcapError::ReportMismatch`] if the quote returned isn't one of the report,
+    /// which only a host tampering with the ocalls makes happen.
+    pub fn build(&mut self) -> DcapResult<EvidenceBundle> {
+        let target_info = self.quoter.target_info()?;
+        let report = rsgx_create_report(&target_info, &self.report_data).map_err(DcapError::Sgx)?;
+        let quote = self.quoter.quote(&report)?;
+
+        let parsed = Quote::parse(&quote)?;
+        if parsed.signed_data()[HEADER_LEN..] != *report_body_bytes(&report.body) {
+            return Err(DcapError::ReportMismatch);
+        }
+        let pck = parsed.pck_info()?;
+        let collateral = self.source.collateral(&pck.fmspc, pck.ca)?;
+        Ok(EvidenceBundle { quote, collateral, host: Vec::new() })
+    }
+
+    pub fn into_inner(self) -> (S, Q) {
+        (self.source, self.quoter)
+    }
+}
+
+/// A quote with the collateral to verify it, self-contained.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvidenceBundle {
+    quote: Vec<u8>,
+    collateral: Collateral,
+    host: Vec<HostEvidence>,
+}
+
+impl EvidenceBundle {
+    /// Bundles `quote` with its `collateral`, failing unless the quote parses.
+    pub fn new(quote: Vec<u8>, collateral: Collateral) -> DcapResult<EvidenceBundle> {
+        Quote::parse(&quote)?;
+        Ok(EvidenceBundle { quote, collateral, host: Vec::new() })
+    }
+
+    pub fn quote(&self) -> Quote<'_> {
+        // Parsed when the bundle was made.
+        Quote::parse(&self.quote).unwrap()
+    }
+
+    pub fn quote_bytes(&self) -> &[u8] {
+        &self.quote
+    }
+
+    pub fn collateral(&self) -> &Collateral {
+        &self.collateral
+    }
+
+    /// Returns the host evidence, in the order it was added.
+    pub fn host_evidence(&self) -> &[HostEvidence] {
+        &self.host
+    }
+
+    /// Returns what host evidence is bound to: the SHA-256 of the quote.
+    pub fn binding(&self) -> [u8; 32] {
+        let mut binding = [0_u8; 32];
+        Sha256::digest_into(&self.quote, &mut binding);
+        binding
+    }
+
+    /// Adds the evidence `attester` produces, bound to the quote, replacing any evidence of
+    /// the same kind.
+    pub fn co_sign(&mut self, attester: &mut dyn HostAttester) -> DcapResult<()> {
+        let evidence = attester.attest(&self.binding())?;
+        let kind = String::from(attester.kind());
+        self.host.retain(|host| host.kind != kind);
+        self.host.push(HostEvidence { kind, evidence });
+        Ok(())
+    }
+
+    /// Returns the quote and the collateral, dropping any host evidence.
+    pub fn into_parts(self) -> (Vec<u8>, Collateral) {
+        (self.quote, self.collateral)
+    }
+
+    /// Returns the CBOR of the bundle.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut entries = vec![
+            (Value::from("version"), Value::Unsigned(BUNDLE_VERSION)),
+            (Value::from("quote"), Value::Bytes(self.quote.clone())),
+            (Value::from("collateral"), self.collateral.to_value()),
+        ];
+        if !self.host.is_empty() {
+            let host = self
+                .host
+                .iter()
+                .map(|host| {
+                    Value::Map(vec![
+                        (Value::from("kind"), Value::from(host.kind.as_str())),
+                        (Value::from("evidence"), Value::Bytes(host.evidence.clone())),
+                    ])
+                })
+                .collect();
+            entries.push((Value::from("host"), Value::Array(host)));
+        }
+        Value::Map(entries).encode()
+    }
+
+    /// Parses a bundle written by [`to_bytes`]. Nothing is verified.
+    ///
+    /// [`to_bytes`]: EvidenceBundle::to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> DcapResult<EvidenceBundle> {
+        let malformed = DcapError::MalformedBundle;
+        let value = Value::decode(bytes).map_err(|_| malformed)?;
+        if value.get(&Value::from("version")) != Some(&Value::Unsigned(BUNDLE_VERSION)) {
+            return Err(malformed);
+        }
+        let quote = value.get(&Value::from("quote")).and_then(Value::as_bytes).ok_or(malformed)?;
+        let collateral = value.get(&Value::from("collateral")).ok_or(malformed)?;
+        let collateral = Collateral::from_value(collateral)?;
+        let mut bundle = EvidenceBundle::new(Vec::from(quote), collateral)?;
+        if let Some(host) = value.get(&Value::from("host")) {
+            for host in host.as_array().ok_or(malformed)? {
+                let kind = host.get(&Value::from("kind")).and_then(Value::as_text);
+                let evidence = host.get(&Value::from("evidence")).and_then(Value::as_bytes);
+                let (kind, evidence) = kind.zip(evidence).ok_or(malformed)?;
+                if bundle.host.iter().any(|host| host.kind == kind) {
+                    return Err(malformed);
+                }
+                let host = HostEvidence { kind: String::from(kind), evidence: Vec::from(evidence) };
+                bundle.host.push(host);
+            }
+        }
+        Ok(bundle)
+    }
+}
+
+/// Evidence of the host of the enclave, bound to its quote.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostEvidence {
+    /// The kind of evidence, such as [`tpm::TPM2`](crate::tpm::TPM2).
+    pub kind: String,
+    pub evidence: Vec<u8>,
+}
+
+/// Produces evidence of the host, such as a quote of its TPM obtained through an ocall.
+pub trait HostAttester {
+    /// Returns the kind of evidence produced.
+    fn kind(&self) -> &str;
+
+    /// Returns evidence bound to `binding`, e.g. as the qualifying data of a TPM quote.
+    fn attest(&mut self, binding: &[u8; 32]) -> DcapResult<Vec<u8>>;
+}
+
+/// Verifies evidence of the host of some kind.
+pub trait HostVerifier {
+    /// Returns the kind of evidence verified.
+    fn kind(&self) -> &str;
+
+    /// Verifies `evidence`, and that it's bound to `binding`, at the trusted time `now`.
+    ///
+    /// Fails with [`DcapError::UntrustedHost`] if it doesn't verify.
+    fn verify(&self, evidence: &[u8], binding: &[u8; 32], now: time_t) -> DcapResult<()>;
+}
+
+/// What quotes are verified against: the root CA of Intel and the trusted time, and the
+/// [`Policy`] deciding which platforms and enclaves are trusted.
+#[derive(Clone, Debug)]
+pub struct VerificationPolicy {
+    root_ca: Vec<u8>,
+    now: time_t,
+    policy: Policy,
+}
+
+impl VerificationPolicy {
+    /// Creates a policy verifying quotes and their collateral up to the DER certificate
+    /// `root_ca` of the Intel SGX Root CA, which the enclave pins, at `now`, in seconds since
+    /// the Unix epoch, and trusting the platforms and enclaves `policy` trusts.
+    ///
+    /// Take `now` from a trusted time source: the host controls the enclave's view of time
+    /// otherwise, and could pass off collateral from before a TCB recovery.
+    pub fn new(root_ca: &[u8], now: time_t, policy: Policy) -> VerificationPolicy {
+        VerificationPolicy { root_ca: Vec::from(root_ca), now, policy }
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+}
+
+/// A quote [`verify_quote`] verified.
+#[derive(Clone)]
+pub struct VerifiedQuote {
+    status: sgx_ql_qv_result_t,
+    report_body: sgx_report_body_t,
+    pck: PckInfo,
+    tcb_date: time_t,
+    advisory_ids: Vec<String>,
+    expires: time_t,
+}
+
+impl VerifiedQuote {
+    /// Returns the status of the TCB of the platform and of its QE.
+    pub fn status(&self) -> sgx_ql_qv_result_t {
+        self.status
+    }
+
+    /// Returns the report body of the attested enclave.
+    pub fn report_body(&self) -> &sgx_report_body_t {
+        &self.report_body
+    }
+
+    /// Returns what the PCK certificate of the quote says of the platform.
+    pub fn pck(&self) -> &PckInfo {
+        &self.pck
+    }
+
+    /// Returns the date of the TCB level of the platform, in seconds since the Unix epoch.
+    pub fn tcb_date(&self) -> time_t {
+        self.tcb_date
+    }
+
+    /// Returns the Intel security advisories, such as `INTEL-SA-00615`, which the TCB levels
+    /// of the platform and of its QE are affected by.
+    pub fn advisory_ids(&self) -> &[String] {
+        &self.advisory_ids
+    }
+
+    /// Returns the earliest next update of the collateral, after which it no longer verifies.
+    pub fn expires(&self) -> time_t {
+        self.expires
+    }
+}
+
+impl fmt::Debug for VerifiedQuote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VerifiedQuote")
+            .field("status", &self.status)
+            .field("mr_enclave", &self.report_body.mr_enclave.m)
+            .field("mr_signer", &self.report_body.mr_signer.m)
+            .field("fmspc", &self.pck.fmspc)
+            .field("tcb_date", &self.tcb_date)
+            .field("advisory_ids", &self.advisory_ids)
+            .field("expires", &self.expires)
+            .finish()
+    }
+}
+
+/// Verifies the quote of `evidence` with its collateral, entirely inside the enclave, and
+/// returns what it attests if the policy trusts the platform and the enclave.
+///
+/// This checks, as the QVL of Intel does:
+///
+/// * the PCK certificate chain of the quote, and the issuer chains of the collateral, up to the
+///   root CA of the policy, and the certificates of all of them against the CRLs;
+/// * that the PCK CRL is issued by a PCK Platform or Processor CA which may sign CRLs, and the
+///   TCB info and the QE identity by the TCB signing certificate;
+/// * the signature of the PCK over the report of the QE, and that the QE bound the attestation
+///   key to its report;
+/// * the signature of the attestation key over the quote;
+/// * the signatures of the TCB info and the QE identity, that they're for the platform and QE
+///   of the quote, and that no collateral is past its next update;
+/// * the report of the QE against its identity;
+/// * and the TCB level of the platform and of the QE, whose statuses converge into the one
+///   returned.
+///
+/// The [`Policy`] of `policy` then checks the status and advisories of the platform, and the
+/// report body of the enclave.
+pub fn verify_quote(
+    evidence: &EvidenceBundle,
+    policy: &VerificationPolicy,
+) -> DcapResult<VerifiedQuote> {
+    let quote = evidence.quote();
+    let collateral = evidence.collateral();
+    let now = policy.now;
+    if quote.header().vendor_id != INTEL_QE_VENDOR_ID || collateral.tee_type != 0 {
+        return Err(DcapError::CollateralMismatch);
+    }
+
+    let root = Certificate::parse(&policy.root_ca).map_err(DcapError::UntrustedCertificate)?;
+    let root_crl = crl_der(&collateral.root_ca_crl)?;
+    let root_crl = Crl::parse(&root_crl).map_err(|_| DcapError::MalformedCollateral)?;
+    root_crl.verify(&root, now).map_err(crl_error)?;
+
+    let crl_chain = pem_certificates(&collateral.pck_crl_issuer_chain)?;
+    verify_issuer_chain(&crl_chain, policy, &[&root_crl])?;
+    let pck_crl = crl_der(&collateral.pck_crl)?;
+    let pck_crl = Crl::parse(&pck_crl).map_err(|_| DcapError::MalformedCollateral)?;
+    let crl_issuer = Certificate::parse(&crl_chain[0])?;
+    let pck_ca = matches!(
+        crl_issuer.subject_attribute(COMMON_NAME_OID),
+        Some(PROCESSOR_CA | PLATFORM_CA)
+    );
+    let signs_crls = crl_issuer.key_usage().is_some_and(|usage| usage.contains(KeyUsage::CRL_SIGN));
+    if !pck_ca || !crl_issuer.is_ca() || !signs_crls {
+        return Err(DcapError::UntrustedCertificate(PkiError::InvalidIssuer));
+    }
+    pck_crl.verify(&crl_issuer, now).map_err(crl_error)?;
+
+    let pck_chain = quote.pck_chain()?;
+    verify_issuer_chain(&pck_chain, policy, &[&root_crl, &pck_crl])?;
+    let pck = PckInfo::parse(&pck_chain[0], &pck_chain[1])?;
+    let pck_key = Certificate::parse(&pck_chain[0])?.public_key()?;
+
+    if !pki::verify_raw(&pck_key, quote.qe_report(), quote.qe_report_signature())? {
+        return Err(DcapError::BadSignature);
+    }
+    let qe_body = quote.qe_report_body();
+    let mut binding = [0_u8; 32];
+    let mut hash = Sha256::new();
+    hash.update(quote.attestation_key());
+    hash.update(quote.qe_auth_data());
+    hash.finalize_into(&mut binding);
+    if qe_body.report_data.d[..32] != binding || qe_body.report_data.d[32..] != [0; 32] {
+        return Err(DcapError::BadSignature);
+    }
+    let attestation_key = p256_key(quote.attestation_key());
+    if !pki::verify_raw(&attestation_key, quote.signed_data(), quote.signature())? {
+        return Err(DcapError::BadSignature);
+    }
+
+    let tcb_info = SignedJson::verify(
+        &collateral.tcb_info,
+        "tcbInfo",
+        &collateral.tcb_info_issuer_chain,
+        policy,
+        &root_crl,
+    )?;
+    let qe_identity = SignedJson::verify(
+        &collateral.qe_identity,
+        "enclaveIdentity",
+        &collateral.qe_identity_issuer_chain,
+        policy,
+        &root_crl,
+    )?;
+    let platform = platform_tcb(&tcb_info.body, &pck)?;
+    let qe = qe_tcb(&qe_identity.body, &qe_body)?;
+
+    let status = converge(platform.status, qe.status);
+    let mut advisory_ids = platform.advisory_ids;
+    for id in qe.advisory_ids {
+        if !advisory_ids.contains(&id) {
+            advisory_ids.push(id);
+        }
+    }
+    let expires = [
+        tcb_info.next_update,
+        qe_identity.next_update,
+        root_crl.next_update().unwrap_or(time_t::MAX),
+        pck_crl.next_update().unwrap_or(time_t::MAX),
+    ];
+    let verified = VerifiedQuote {
+        status,
+        report_body: quote.report_body(),
+        pck,
+        tcb_date: platform.date,
+        advisory_ids,
+        expires: expires.iter().copied().min().unwrap_or(time_t::MAX),
+    };
+    policy.policy.check_quote(&verified).map_err(DcapError::Policy)?;
+    Ok(verified)
+}
+
+/// Verifies `evidence` as [`verify_quote`] does, and its host evidence of the kind of each of
+/// `hosts`, which it must have. Evidence of other kinds is ignored.
+pub fn verify_evidence(
+    evidence: &EvidenceBundle,
+    policy: &VerificationPolicy,
+    hosts: &[&dyn HostVerifier],
+) -> DcapResult<VerifiedQuote> {
+    let verified = verify_quote(evidence, policy)?;
+    let binding = evidence.binding();
+    for verifier in hosts {
+        let host = evidence
+            .host
+            .iter()
+            .find(|host| host.kind == verifier.kind())
+            .ok_or(DcapError::MissingHostEvidence)?;
+        verifier.verify(&host.evidence, &binding, policy.now)?;
+    }
+    Ok(verified)
+}
+
+/// Maps the errors of checking a CRL, telling expired collateral apart.
+fn crl_error(error: PkiError) -> DcapError {
+    match error {
+        PkiError::Expired | PkiError::NotYetValid => DcapError::CollateralExpired,
+        error => DcapError::UntrustedCertificate(error),
+    }
+}
+
+/// Validates the DER `chain` up to the root CA of `policy`, checking each certificate but the
+/// root CA against the one of `crls` its issuer issued.
+fn verify_issuer_chain(
+    chain: &[Vec<u8>],
+    policy: &VerificationPolicy,
+    crls: &[&Crl<'_>],
+) -> DcapResult<()> {
+    let chain: Vec<&[u8]> = chain.iter().map(Vec::as_slice).collect();
+    let anchors = [policy.root_ca.as_slice()];
+    pki::verify_chain(&chain, &anchors, &ChainPolicy::new(policy.now))
+        .map_err(DcapError::UntrustedCertificate)?;
+    for der in chain.iter().filter(|der| **der != policy.root_ca) {
+        let certificate = Certificate::parse(der)?;
+        let crl = crls
+            .iter()
+            .find(|crl| crl.issuer() == certificate.issuer())
+            .ok_or(DcapError::CollateralMismatch)?;
+        if crl.is_revoked(certificate.serial()) {
+            return Err(DcapError::Revoked);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the DER of a CRL of the collateral, which is either DER or hex-encoded DER.
+fn crl_der(crl: &[u8]) -> DcapResult<Vec<u8>> {
+    let crl = trim_nul(crl);
+    if crl.first() == Some(&der::SEQUENCE) {
+        return Ok(Vec::from(crl));
+    }
+    core::str::from_utf8(crl).ok().and_then(hex_decode).ok_or(DcapError::MalformedCollateral)
+}
+
+/// Strips the NULs terminating the strings of `sgx_ql_qve_collateral_t`.
+fn trim_nul(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    &bytes[..len]
+}
+
+/// Decodes hex digits of either case.
+pub(crate) fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    text.chunks(2).map(|pair| Some(digit(pair[0])? << 4 | digit(pair[1])?)).collect()
+}
+
+/// Returns the P-256 key of `x` then `y`, big endian.
+fn p256_key(raw: &[u8]) -> PublicKey {
+    let mut public = sgx_ec256_public_t::default();
+    public.gx.copy_from_slice(&raw[..32]);
+    public.gy.copy_from_slice(&raw[32..64]);
+    public.gx.reverse();
+    public.gy.reverse();
+    PublicKey::P256(public)
+}
+
+/// TCB info or a QE identity, once its signature verified.
+struct SignedJson {
+    body: json::Value,
+    next_update: time_t,
+}
+
+impl SignedJson {
+    /// Verifies the JSON `text`, an object with the member `key` and the `signature` of the
+    /// exact text of the member by the leaf of the PEM `issuer_chain`, which must be the TCB
+    /// signing certificate, and that it's current.
+    fn verify(
+        text: &[u8],
+        key: &str,
+        issuer_chain: &[u8],
+        policy: &VerificationPolicy,
+        root_crl: &Crl<'_>,
+    ) -> DcapResult<SignedJson> {
+        let malformed = DcapError::MalformedCollateral;
+        let text = trim_nul(text);
+        let value = json::Value::parse(text).map_err(|_| malformed)?;
+        if value.as_object().map(<[_]>::len) != Some(2) {
+            return Err(malformed);
+        }
+        let signature = value.get("signature").and_then(json::Value::as_str).ok_or(malformed)?;
+        let signature = hex_decode(signature).ok_or(malformed)?;
+        let body = value.get(key).ok_or(malformed)?;
+        let signed = raw_member(text, key).ok_or(malformed)?;
+
+        let chain = pem_certificates(issuer_chain)?;
+        if chain.is_empty() {
+            return Err(malformed);
+        }
+        verify_issuer_chain(&chain, policy, &[root_crl])?;
+        let signer = Certificate::parse(&chain[0])?;
+        if signer.subject_attribute(COMMON_NAME_OID) != Some(TCB_SIGNING) {
+            return Err(DcapError::UntrustedCertificate(PkiError::InvalidIssuer));
+        }
+        let signer = signer.public_key()?;
+        if !pki::verify_raw(&signer, signed, &signature)? {
+            return Err(DcapError::BadSignature);
+        }
+
+        let issue_date = json_date(body.get("issueDate")).ok_or(malformed)?;
+        let next_update = json_date(body.get("nextUpdate")).ok_or(malformed)?;
+        if policy.now < issue_date || policy.now > next_update {
+            return Err(DcapError::CollateralExpired);
+        }
+        Ok(SignedJson { body: body.clone(), next_update })
+    }
+}
+
+/// Returns the exact text of the value of the member `key` of the JSON object `text`, which
+/// is well-formed and has no duplicate members, as the signatures of the PCS cover it.
+fn raw_member<'t>(text: &'t [u8], key: &str) -> Option<&'t [u8]> {
+    let skip_space = |mut i: usize| {
+        while text.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        i
+    };
+    let mut i = skip_space(0);
+    if text.get(i) != Some(&b'{') {
+        return None;
+    }
+    loop {
+        let start = skip_space(i + 1);
+        let end = skip_string(text, start)?;
+        let colon = skip_space(end);
+        if text.get(colon) != Some(&b':') {
+            return None;
+        }
+        let value = skip_space(colon + 1);
+        let value_end = skip_value(text, value)?;
+        if text[start..end] == *format!("\"{}\"", key).as_bytes() {
+            return Some(&text[value..value_end]);
+        }
+        i = skip_space(value_end);
+        if text.get(i) != Some(&b',') {
+            return None;
+        }
+    }
+}
+
+/// Returns the index past the JSON string starting at `text[at]`.
+fn skip_string(text: &[u8], at: usize) -> Option<usize> {
+    if text.get(at) != Some(&b'"') {
+        return None;
+    }
+    let mut i = at + 1;
+    while i < text.len() {
+        match text[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Returns the index past the well-formed JSON value starting at `text[at]`.
+fn skip_value(text: &[u8], at: usize) -> Option<usize> {
+    match *text.get(at)? {
+        b'"' => skip_string(text, at),
+        b'{' | b'[' => {
+            let mut depth = 0_usize;
+            let mut i = at;
+            while i < text.len() {
+                match text[i] {
+                    b'"' => {
+                        i = skip_string(text, i)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(i + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            None
+        }
+        _ => {
+            let scalar = |c: &u8| !matches!(c, b',' | b'}' | b']') && !c.is_ascii_whitespace();
+            Some(at + text[at..].iter().take_while(|c| scalar(c)).count())
+        }
+    }
+}
+
+/// Reads a date of the PCS, `YYYY-MM-DDThh:mm:ssZ`, in seconds since the Unix epoch.
+fn json_date(value: Option<&json::Value>) -> Option<time_t> {
+    let text = value?.as_str()?.as_bytes();
+    if text.len() != 20 || text[4] != b'-' || text[7] != b'-' || text[10] != b'T' {
+        return None;
+    }
+    if text[13] != b':' || text[16] != b':' || text[19] != b'Z' {
+        return None;
+    }
+    let number = |range: core::ops::Range<usize>| {
+        text[range].iter().try_fold(0_i64, |n, &c| match c {
+            b'0'..=b'9' => Some(n * 10 + (c - b'0') as i64),
+            _ => None,
+        })
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = pki::days_from_civil(year, month, day);
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Returns the hex string `name` of `body` as `N` bytes.
+fn json_hex<const N: usize>(body: &json::Value, name: &str) -> DcapResult<[u8; N]> {
+    let malformed = DcapError::MalformedCollateral;
+    let text = body.get(name).and_then(json::Value::as_str).ok_or(malformed)?;
+    hex_decode(text).ok_or(malformed)?.try_into().map_err(|_| malformed)
+}
+
+/// Returns the integer `name` of `value`, if it's between 0 and `max`.
+fn json_uint(value: &json::Value, name: &str, max: u16) -> DcapResult<u16> {
+    match value.get(name).and_then(json::Value::as_i64) {
+        Some(n) if (0..=max as i64).contains(&n) => Ok(n as u16),
+        _ => Err(DcapError::MalformedCollateral),
+    }
+}
+
+/// The TCB statuses of the PCS, and the statuses quotes verify with at them.
+const TCB_STATUSES: [(&str, sgx_ql_qv_result_t); 7] = [
+    ("UpToDate", sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OK),
+    ("SWHardeningNeeded", sgx_ql_qv_result_t::SGX_QL_QV_RESULT_SW_HARDENING_NEEDED),
+    ("ConfigurationNeeded", sgx_ql_qv_result_t::SGX_QL_QV_RESULT_CONFIG_NEEDED),
+    (
+        "ConfigurationAndSWHardeningNeeded",
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_CONFIG_AND_SW_HARDENING_NEEDED,
+    ),
+    ("OutOfDate", sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OUT_OF_DATE),
+    (
+        "OutOfDateConfigurationNeeded",
+        sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OUT_OF_DATE_CONFIG_NEEDED,
+    ),
+    ("Revoked", sgx_ql_qv_result_t::SGX_QL_QV_RESULT_REVOKED),
+];
+
+/// Returns the status of quotes at the TCB status `name` of the PCS, e.g. `UpToDate`.
+pub(crate) fn tcb_status(name: &str) -> Option<sgx_ql_qv_result_t> {
+    TCB_STATUSES.iter().find(|(known, _)| *known == name).map(|(_, status)| *status)
+}
+
+/// Returns the name of the TCB status of the PCS quotes verify with `status` at.
+pub(crate) fn tcb_status_name(status: sgx_ql_qv_result_t) -> Option<&'static str> {
+    TCB_STATUSES.iter().find(|(_, known)| *known == status).map(|(name, _)| *name)
+}
+
+/// A TCB level of TCB info or of a QE identity.
+struct TcbLevel {
+    status: sgx_ql_qv_result_t,
+    date: time_t,
+    advisory_ids: Vec<String>,
+}
+
+impl TcbLevel {
+    fn parse(level: &json::Value) -> DcapResult<TcbLevel> {
+        let malformed = DcapError::MalformedCollateral;
+        let status = level.get("tcbStatus").and_then(json::Value::as_str);
+        let status = status.and_then(tcb_status).ok_or(malformed)?;
+        let date = json_date(level.get("tcbDate")).ok_or(malformed)?;
+        let mut advisory_ids = Vec::new();
+        if let Some(ids) = level.get("advisoryIDs") {
+            for id in ids.as_array().ok_or(malformed)? {
+                advisory_ids.push(String::from(id.as_str().ok_or(malformed)?));
+            }
+        }
+        Ok(TcbLevel { status, date, advisory_ids })
+    }
+}
+
+/// Returns the first, i.e. highest, TCB level of the TCB info `body` which the platform of
+/// `pck` is at.
+fn platform_tcb(body: &json::Value, pck: &PckInfo) -> DcapResult<TcbLevel> {
+    let malformed = DcapError::MalformedCollateral;
+    let version = body.get("version").and_then(json::Value::as_i64).ok_or(malformed)?;
+    match version {
+        2 => {}
+        3 if body.get("id").and_then(json::Value::as_str) == Some("SGX") => {}
+        3 => return Err(DcapError::CollateralMismatch),
+        _ => return Err(malformed),
+    }
+    if json_hex::<6>(body, "fmspc")? != pck.fmspc || json_hex::<2>(body, "pceId")? != pck.pce_id {
+        return Err(DcapError::CollateralMismatch);
+    }
+
+    let levels = body.get("tcbLevels").and_then(json::Value::as_array).ok_or(malformed)?;
+    for level in levels {
+        let tcb = level.get("tcb").ok_or(malformed)?;
+        let mut components = [0_u8; 16];
+        if version == 2 {
+            for (i, component) in components.iter_mut().enumerate() {
+                *component = json_uint(tcb, &format!("sgxtcbcomp{:02}svn", i + 1), 255)? as u8;
+            }
+        } else {
+            let svns = tcb.get("sgxtcbcomponents").and_then(json::Value::as_array);
+            let svns = svns.filter(|svns| svns.len() == 16).ok_or(malformed)?;
+            for (component, svn) in components.iter_mut().zip(svns) {
+                *component = json_uint(svn, "svn", 255)? as u8;
+            }
+        }
+        let pce_svn = json_uint(tcb, "pcesvn", u16::MAX)?;
+        let reached = pck.tcb_components.iter().zip(components.iter()).all(|(pck, tcb)| pck >= tcb);
+        if reached && pck.pce_svn >= pce_svn {
+            return TcbLevel::parse(level);
+        }
+    }
+    Err(DcapError::UnsupportedTcb)
+}
+
+/// Checks the report `qe` of the QE against the QE identity `body`, and returns the first
+/// TCB level the QE is at, or an out-of-date one if it's below them all.
+fn qe_tcb(body: &json::Value, qe: &sgx_report_body_t) -> DcapResult<TcbLevel> {
+    let malformed = DcapError::MalformedCollateral;
+    if body.get("id").and_then(json::Value::as_str) != Some("QE") {
+        return Err(DcapError::CollateralMismatch);
+    }
+    if body.get("version").and_then(json::Value::as_i64) != Some(2) {
+        return Err(malformed);
+    }
+    let misc_select = u32::from_be_bytes(json_hex(body, "miscselect")?);
+    let misc_mask = u32::from_be_bytes(json_hex(body, "miscselectMask")?);
+    let attributes: [u8; 16] = json_hex(body, "attributes")?;
+    let attributes_mask: [u8; 16] = json_hex(body, "attributesMask")?;
+    let mr_signer: [u8; 32] = json_hex(body, "mrsigner")?;
+    let isv_prod_id = json_uint(body, "isvprodid", u16::MAX)?;
+
+    let report = report_body_bytes(qe);
+    let qe_attributes = &report[ATTRIBUTES_OFFSET..ATTRIBUTES_OFFSET + 16];
+    let masked = |value: &[u8]| -> Vec<u8> {
+        value.iter().zip(attributes_mask.iter()).map(|(v, m)| v & m).collect()
+    };
+    if qe.misc_select & misc_mask != misc_select & misc_mask
+        || masked(qe_attributes) != masked(&attributes)
+        || qe.mr_signer.m != mr_signer
+        || qe.isv_prod_id != isv_prod_id
+    {
+        return Err(DcapError::UntrustedQe);
+    }
+
+    let levels = body.get("tcbLevels").and_then(json::Value::as_array).ok_or(malformed)?;
+    for level in levels {
+        let tcb = level.get("tcb").ok_or(malformed)?;
+        if qe.isv_svn >= json_uint(tcb, "isvsvn", u16::MAX)? {
+            return TcbLevel::parse(level);
+        }
+    }
+    Ok(TcbLevel {
+        status: sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OUT_OF_DATE,
+        date: 0,
+        advisory_ids: Vec::new(),
+    })
+}
+
+/// Returns the status of a quote from those of the TCB levels of the platform and the QE.
+fn converge(platform: sgx_ql_qv_result_t, qe: sgx_ql_qv_result_t) -> sgx_ql_qv_result_t {
+    use sgx_ql_qv_result_t::*;
+    match (qe, platform) {
+        (SGX_QL_QV_RESULT_REVOKED, _) => SGX_QL_QV_RESULT_REVOKED,
+        (
+            SGX_QL_QV_RESULT_OUT_OF_DATE,
+            SGX_QL_QV_RESULT_OK | SGX_QL_QV_RESULT_SW_HARDENING_NEEDED,
+        ) => SGX_QL_QV_RESULT_OUT_OF_DATE,
+        (
+            SGX_QL_QV_RESULT_OUT_OF_DATE,
+            SGX_QL_QV_RESULT_CONFIG_NEEDED | SGX_QL_QV_RESULT_CONFIG_AND_SW_HARDENING_NEEDED,
+        ) => SGX_QL_QV_RESULT_OUT_OF_DATE_CONFIG_NEEDED,
+        _ => platform,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A quote of an enclave signed by [2; 32], of a platform of FMSPC 00906ED50000 whose TCB is
+    // SWHardeningNeeded, with its collateral, all issued on 2026-01-01 under a test root CA.
+    const QUOTE: &[u8] = include_bytes!("../testdata/dcap/quote.bin");
+    const ROOT: &[u8] = include_bytes!("../testdata/dcap/root.der");
+    const PCK_CA: &[u8] = include_bytes!("../testdata/dcap/inter.der");
+    const NOW: time_t = 1_780_272_000;
+
+    fn collateral() -> Collateral {
+        Collateral {
+            version: 3,
+            tee_type: 0,
+            pck_crl_issuer_chain: Vec::from(&include_bytes!("../testdata/dcap/crl_chain.pem")[..]),
+            root_ca_crl: Vec::from(&include_bytes!("../testdata/dcap/root_crl.der")[..]),
+            pck_crl: Vec::from(&include_bytes!("../testdata/dcap/pck_crl.hex")[..]),
+            tcb_info_issuer_chain: Vec::from(&include_bytes!("../testdata/dcap/tcb_chain.pem")[..]),
+            tcb_info: Vec::from(&include_bytes!("../testdata/dcap/tcb_info.json")[..]),
+            qe_identity_issuer_chain: Vec::from(
+                &include_bytes!("../testdata/dcap/tcb_chain.pem")[..],
+            ),
+            qe_identity: Vec::from(&include_bytes!("../testdata/dcap/qe_identity.json")[..]),
+        }
+    }
+
+    fn policy(root: &[u8], now: time_t) -> VerificationPolicy {
+        let enclave = Policy::new()
+            .mr_signer(sgx_measurement_t { m: [2; 32] })
+            .accept_status(sgx_ql_qv_result_t::SGX_QL_QV_RESULT_SW_HARDENING_NEEDED);
+        VerificationPolicy::new(root, now, enclave)
+    }
+
+    fn verify(
+        quote: &[u8],
+        collateral: Collateral,
+        policy: &VerificationPolicy,
+    ) -> DcapResult<VerifiedQuote> {
+        verify_quote(&EvidenceBundle::new(Vec::from(quote), collateral)?, policy)
+    }
+
+    #[test]
+    fn parse() {
+        let quote = Quote::parse(QUOTE).unwrap();
+        assert_eq!(quote.report_body().isv_prod_id, 7);
+        assert_eq!({ quote.header().pce_svn }, 13);
+        assert_eq!(quote.pck_chain().unwrap().len(), 3);
+        let info = quote.pck_info().unwrap();
+        assert_eq!(info.fmspc, [0x00, 0x90, 0x6e, 0xd5, 0, 0]);
+        assert_eq!(info.ca, PckCa::Processor);
+
+        for len in [0, 100, 436, QUOTE.len() - 1] {
+            assert_eq!(
+                Quote::parse(&QUOTE[..len]).err(),
+                Some(DcapError::MalformedQuote),
+                "truncated to {}",
+                len
+            );
+        }
+        let mut long = Vec::from(QUOTE);
+        long.push(0);
+        assert_eq!(Quote::parse(&long).err(), Some(DcapError::MalformedQuote));
+        // The PCK CA has no SGX extension.
+        assert_eq!(PckInfo::parse(PCK_CA, PCK_CA).err(), Some(DcapError::MalformedCertificate));
+    }
+
+    #[test]
+    fn verify_quotes() {
+        use sgx_ql_qv_result_t::*;
+        let verified = verify(QUOTE, collateral(), &policy(ROOT, NOW)).unwrap();
+        assert_eq!(verified.status(), SGX_QL_QV_RESULT_SW_HARDENING_NEEDED);
+        assert_eq!(verified.advisory_ids(), ["INTEL-SA-00615"]);
+        assert_eq!(verified.report_body().isv_prod_id, 7);
+
+        let with = |f: &dyn Fn(&mut Collateral)| {
+            let mut collateral = collateral();
+            f(&mut collateral);
+            verify(QUOTE, collateral, &policy(ROOT, NOW)).err()
+        };
+        let pck_crl_revoked = include_bytes!("../testdata/dcap/pck_crl_revoked.der");
+        assert_eq!(
+            with(&|c| c.pck_crl = Vec::from(&pck_crl_revoked[..])),
+            Some(DcapError::Revoked)
+        );
+        let other = include_bytes!("../testdata/dcap/tcb_info_other.json");
+        assert_eq!(
+            with(&|c| c.tcb_info = Vec::from(&other[..])),
+            Some(DcapError::CollateralMismatch)
+        );
+        assert_eq!(
+            with(&|c| c.tcb_info = Vec::from(&br#"{"tcbInfo":{}}"#[..])),
+            Some(DcapError::MalformedCollateral)
+        );
+        let tampered = |c: &mut Collateral| {
+            let at = c.tcb_info.windows(8).position(|w| w == b"SWHarden").unwrap();
+            c.tcb_info[at] = b'S' ^ 0x20;
+        };
+        assert_eq!(with(&tampered), Some(DcapError::BadSignature));
+
+        // The collateral is valid for 2026 only.
+        for now in [1_760_000_000, 1_800_000_000] {
+            assert_eq!(
+                verify(QUOTE, collateral(), &policy(ROOT, now)).err(),
+                Some(DcapError::CollateralExpired)
+            );
+        }
+        let untrusted = verify(QUOTE, collateral(), &policy(PCK_CA, NOW)).err();
+        assert!(matches!(untrusted, Some(DcapError::UntrustedCertificate(_))));
+
+        // The report, the QE report under the PCK signature, and the attestation key bound by
+        // the QE report data.
+        for at in [400, 432 + 4 + 128 + 64, 432 + 4 + 64] {
+            let mut quote = Vec::from(QUOTE);
+            quote[at] ^= 1;
+            assert_eq!(
+                verify(&quote, collateral(), &policy(ROOT, NOW)).err(),
+                Some(DcapError::BadSignature)
+            );
+        }
+    }
+
+    #[test]
+    fn pins_collateral_signers() {
+        let invalid = Some(DcapError::UntrustedCertificate(PkiError::InvalidIssuer));
+        let with = |f: &dyn Fn(&mut Collateral)| {
+            let mut collateral = collateral();
+            f(&mut collateral);
+            verify(QUOTE, collateral, &policy(ROOT, NOW)).err()
+        };
+
+        // TCB info signed under the root CA, but by the PCK CA.
+        let tcb_info = include_bytes!("../testdata/dcap/tcb_info_pck_ca.json");
+        let by_pck_ca = |c: &mut Collateral| {
+            c.tcb_info = Vec::from(&tcb_info[..]);
+            c.tcb_info_issuer_chain = c.pck_crl_issuer_chain.clone();
+        };
+        assert_eq!(with(&by_pck_ca), invalid);
+
+        // The PCK CRL issued by the TCB signing key, by the root CA, and by the key of the PCK
+        // CA in a certificate which isn't a CA or can't sign CRLs.
+        let by_root = |c: &mut Collateral| {
+            let begin = b"-----BEGIN CERTIFICATE-----";
+            let at = c.pck_crl_issuer_chain.windows(begin.len()).rposition(|w| w == begin);
+            c.pck_crl_issuer_chain.drain(..at.unwrap());
+            c.pck_crl = c.root_ca_crl.clone();
+        };
+        assert_eq!(with(&by_root), invalid);
+        let chains: [&[u8]; 3] = [
+            include_bytes!("../testdata/dcap/tcb_chain.pem"),
+            include_bytes!("../testdata/dcap/crl_chain_not_ca.pem"),
+            include_bytes!("../testdata/dcap/crl_chain_no_crl_sign.pem"),
+        ];
+        for chain in chains {
+            assert_eq!(with(&|c| c.pck_crl_issuer_chain = Vec::from(chain)), invalid);
+        }
+    }
+}
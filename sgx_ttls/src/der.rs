@@ -0,0 +1,204 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! The subset of DER (X.690) that certificates are made of.
+//!
+//! Encoding nests by building the contents of a constructed value before its header, since DER
+//! lengths come first. Decoding borrows from the input and only accepts the distinguished form:
+//! single-byte tags, definite minimal lengths, and minimal integers and booleans.
+
+use alloc::vec::Vec;
+
+pub(crate) const BOOLEAN: u8 = 0x01;
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const BIT_STRING: u8 = 0x03;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const NULL: u8 = 0x05;
+pub(crate) const OID: u8 = 0x06;
+pub(crate) const UTF8_STRING: u8 = 0x0c;
+pub(crate) const PRINTABLE_STRING: u8 = 0x13;
+pub(crate) const IA5_STRING: u8 = 0x16;
+pub(crate) const UTC_TIME: u8 = 0x17;
+pub(crate) const GENERALIZED_TIME: u8 = 0x18;
+pub(crate) const SEQUENCE: u8 = 0x30;
+pub(crate) const SET: u8 = 0x31;
+
+/// Returns the tag of the context-specific constructed value `[n]`.
+pub(crate) const fn explicit(n: u8) -> u8 {
+    0xa0 | n
+}
+
+/// Returns the tag of the context-specific primitive value `[n]`.
+pub(crate) const fn implicit(n: u8) -> u8 {
+    0x80 | n
+}
+
+/// Input which isn't DER, or not of the expected shape.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) struct DerError;
+
+pub(crate) type DerResult<T> = Result<T, DerError>;
+
+/// Appends the value `tag` with contents `value` to `out`.
+pub(crate) fn write(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    let len = value.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = (len as u64).to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (8 - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(value);
+}
+
+/// Appends the constructed value `tag` whose contents `contents` writes to `out`.
+pub(crate) fn write_nested<F: FnOnce(&mut Vec<u8>)>(out: &mut Vec<u8>, tag: u8, contents: F) {
+    let mut value = Vec::new();
+    contents(&mut value);
+    write(out, tag, &value);
+}
+
+/// Appends the INTEGER of the unsigned big-endian `bytes` to `out`.
+pub(crate) fn write_uint(out: &mut Vec<u8>, bytes: &[u8]) {
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    let bytes = &bytes[skip..];
+    let mut value = Vec::with_capacity(bytes.len() + 1);
+    if bytes.first().map(|b| b & 0x80 != 0).unwrap_or(true) {
+        value.push(0);
+    }
+    value.extend_from_slice(bytes);
+    write(out, INTEGER, &value);
+}
+
+/// Appends the BIT STRING of the whole bytes `bytes` to `out`.
+pub(crate) fn write_bits(out: &mut Vec<u8>, bytes: &[u8]) {
+    let mut value = Vec::with_capacity(bytes.len() + 1);
+    value.push(0);
+    value.extend_from_slice(bytes);
+    write(out, BIT_STRING, &value);
+}
+
+/// Returns the magnitude of the non-negative INTEGER contents `value`, without its leading zero.
+pub(crate) fn read_uint(value: &[u8]) -> DerResult<&[u8]> {
+    match value {
+        [] => Err(DerError),
+        [0] => Ok(&[]),
+        [0, next, ..] if next & 0x80 == 0 => Err(DerError),
+        [0, rest @ ..] => Ok(rest),
+        [first, ..] if first & 0x80 != 0 => Err(DerError),
+        _ => Ok(value),
+    }
+}
+
+/// Returns the BOOLEAN of contents `value`.
+pub(crate) fn read_bool(value: &[u8]) -> DerResult<bool> {
+    match value {
+        [0x00] => Ok(false),
+        [0xff] => Ok(true),
+        _ => Err(DerError),
+    }
+}
+
+/// Returns the bytes of the BIT STRING contents `value`, which must have no unused bits.
+pub(crate) fn read_bits(value: &[u8]) -> DerResult<&[u8]> {
+    match value.split_first() {
+        Some((0, bytes)) => Ok(bytes),
+        _ => Err(DerError),
+    }
+}
+
+/// Reads the values of a DER input in order.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub(crate) fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Reads the next value, returning its tag, its whole encoding and its contents.
+    pub(crate) fn read_raw(&mut self) -> DerResult<(u8, &'a [u8], &'a [u8])> {
+        let data = self.data;
+        let (&tag, rest) = data.split_first().ok_or(DerError)?;
+        if tag & 0x1f == 0x1f {
+            return Err(DerError);
+        }
+        let (&first, rest) = rest.split_first().ok_or(DerError)?;
+        let (len, rest) = if first < 0x80 {
+            (first as usize, rest)
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 || rest.len() < count || rest[0] == 0 {
+                return Err(DerError);
+            }
+            let len = rest[..count].iter().fold(0_usize, |len, &b| len << 8 | b as usize);
+            if len < 0x80 {
+                return Err(DerError);
+            }
+            (len, &rest[count..])
+        };
+        if rest.len() < len {
+            return Err(DerError);
+        }
+        let header = data.len() - rest.len();
+        self.data = &rest[len..];
+        Ok((tag, &data[..header + len], &rest[..len]))
+    }
+
+    /// Reads the next value, which must be tagged `tag`, returning its contents.
+    pub(crate) fn read(&mut self, tag: u8) -> DerResult<&'a [u8]> {
+        match self.read_raw()? {
+            (t, _, value) if t == tag => Ok(value),
+            _ => Err(DerError),
+        }
+    }
+
+    /// Reads the next value if it's tagged `tag`.
+    pub(crate) fn read_optional(&mut self, tag: u8) -> DerResult<Option<&'a [u8]>> {
+        if self.peek_tag() == Some(tag) {
+            self.read(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reads the next value, which must be the constructed `tag`, returning a reader of it.
+    pub(crate) fn read_nested(&mut self, tag: u8) -> DerResult<Reader<'a>> {
+        self.read(tag).map(Reader::new)
+    }
+
+    /// Fails unless all the input was read.
+    pub(crate) fn finish(&self) -> DerResult<()> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(DerError)
+        }
+    }
+}
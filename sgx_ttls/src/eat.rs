@@ -0,0 +1,379 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Entity Attestation Tokens (RFC 9711) minted from verified quotes.
+//!
+//! A verifier which checked the quote of an enclave vouches for it to downstream services
+//! with a short-lived signed token, so that they authorize the requests of the enclave with a
+//! signature check rather than by verifying quotes and collateral themselves:
+//!
+//! ```ignore
+//! // In the verifier.
+//! let verified = verify_quote(&bundle, &policy)?;
+//! let issuer = TokenIssuer::new(key, "https://verifier.example", 600).key_id("2026-10");
+//! let token = issuer.jwt(&verified, Some("payments"), Some(&nonce), clock.now()?)?;
+//!
+//! // In a downstream service.
+//! let token = verify_jwt(&token, &verifier_key, &Validation::new(now).audience("payments"))?;
+//! if token.enclave.mr_enclave != EXPECTED { ... }
+//! ```
+//!
+//! Tokens are JWTs signed as [`jose`](crate::jose) does, or CWTs (RFC 8392) in a COSE_Sign1
+//! message of [`cose`](crate::cose), with the claims:
+//!
+//! | JWT claim            | CWT label            | value                                        |
+//! |----------------------|----------------------|----------------------------------------------|
+//! | `"iss"`              | 1                    | the issuer                                   |
+//! | `"aud"`              | 3                    | the audience, if any                         |
+//! | `"exp"`              | 4                    | the expiry                                   |
+//! | `"iat"`              | 6                    | when the token was issued                    |
+//! | `"eat_nonce"`        | 10                   | the nonce of the relying party, if any       |
+//! | `"sgx_mrenclave"`    | `"sgx_mrenclave"`    | the measurement of the enclave               |
+//! | `"sgx_mrsigner"`     | `"sgx_mrsigner"`     | the hash of the key of its signer            |
+//! | `"sgx_isvprodid"`    | `"sgx_isvprodid"`    | its product ID                               |
+//! | `"sgx_isvsvn"`       | `"sgx_isvsvn"`       | its security version                         |
+//! | `"sgx_debug"`        | `"sgx_debug"`        | whether it's a debug enclave                 |
+//! | `"sgx_report_data"`  | `"sgx_report_data"`  | its report data                              |
+//! | `"sgx_tcb_status"`   | `"sgx_tcb_status"`   | the TCB status of the PCS, e.g. `"UpToDate"` |
+//! | `"sgx_advisory_ids"` | `"sgx_advisory_ids"` | the advisories affecting the TCB             |
+//!
+//! Byte strings are lowercase hex in JWTs and bytes in CWTs, whose map is in the deterministic
+//! encoding of RFC 8949. A token expires after the lifetime of its issuer, and no later than
+//! the collateral the quote was verified with.
+
+use crate::cbor;
+use crate::cose::{self, CoseError};
+use crate::dcap::{self, VerifiedQuote};
+use crate::jose::{self, Claims, JoseError, Validation};
+use crate::json;
+use crate::pki::{PublicKey, SigningKey};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
+use core::fmt::Write;
+use sgx_types::*;
+
+const ISS_LABEL: u64 = 1;
+const AUD_LABEL: u64 = 3;
+const EXP_LABEL: u64 = 4;
+const IAT_LABEL: u64 = 6;
+const NONCE_LABEL: u64 = 10;
+
+/// An error issuing or verifying a token.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TokenError {
+    /// Signing or verifying the JWT failed, or its claims, or those of a CWT, aren't valid.
+    Jose(JoseError),
+    /// Signing or verifying the CWT failed.
+    Cose(CoseError),
+    /// The token lacks a claim of an enclave, or has one of the wrong type.
+    Malformed,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Jose(error) => error.fmt(f),
+            TokenError::Cose(error) => error.fmt(f),
+            TokenError::Malformed => f.write_str("malformed attestation token"),
+        }
+    }
+}
+
+impl From<JoseError> for TokenError {
+    fn from(error: JoseError) -> TokenError {
+        TokenError::Jose(error)
+    }
+}
+
+impl From<CoseError> for TokenError {
+    fn from(error: CoseError) -> TokenError {
+        TokenError::Cose(error)
+    }
+}
+
+pub type TokenResult<T> = Result<T, TokenError>;
+
+/// What a token says of the attested enclave.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnclaveClaims {
+    pub mr_enclave: [u8; 32],
+    pub mr_signer: [u8; 32],
+    pub isv_prod_id: u16,
+    pub isv_svn: u16,
+    pub debug: bool,
+    pub report_data: [u8; 64],
+    /// The status the quote verified with.
+    pub tcb_status: sgx_ql_qv_result_t,
+    pub advisory_ids: Vec<String>,
+}
+
+impl EnclaveClaims {
+    /// Returns the claims of the enclave of `verified`.
+    pub fn from_quote(verified: &VerifiedQuote) -> EnclaveClaims {
+        let body = verified.report_body();
+        EnclaveClaims {
+            mr_enclave: body.mr_enclave.m,
+            mr_signer: body.mr_signer.m,
+            isv_prod_id: body.isv_prod_id,
+            isv_svn: body.isv_svn,
+            debug: body.attributes.flags & SGX_FLAGS_DEBUG != 0,
+            report_data: body.report_data.d,
+            tcb_status: verified.status(),
+            advisory_ids: Vec::from(verified.advisory_ids()),
+        }
+    }
+
+    fn status_name(&self) -> &'static str {
+        // Quotes only verify with the statuses of the PCS.
+        dcap::tcb_status_name(self.tcb_status).unwrap_or("Unknown")
+    }
+}
+
+/// A verified token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttestationToken {
+    pub issuer: String,
+    /// When the token was issued, in seconds since the Unix epoch.
+    pub issued_at: time_t,
+    pub expires_at: time_t,
+    pub nonce: Option<Vec<u8>>,
+    pub enclave: EnclaveClaims,
+}
+
+/// Mints tokens of verified quotes, signed by the key of the verifier.
+pub struct TokenIssuer<K> {
+    key: K,
+    issuer: String,
+    key_id: Option<String>,
+    lifetime: time_t,
+}
+
+impl<K: SigningKey> TokenIssuer<K> {
+    /// Creates an issuer named `issuer`, signing with `key` tokens which expire `lifetime`
+    /// seconds after they're issued.
+    pub fn new(key: K, issuer: &str, lifetime: time_t) -> TokenIssuer<K> {
+        TokenIssuer { key, issuer: String::from(issuer), key_id: None, lifetime }
+    }
+
+    /// Names the key in the header of tokens, for services to pick the key to verify with.
+    pub fn key_id(mut self, key_id: &str) -> TokenIssuer<K> {
+        self.key_id = Some(String::from(key_id));
+        self
+    }
+
+    /// Returns the JWT of the enclave of `verified`, for `audience` and answering `nonce` if
+    /// given, issued at the trusted time `now`.
+    ///
+    /// Fails with [`JoseError::Expired`] if the collateral of the quote has expired by `now`.
+    pub fn jwt(
+        &self,
+        verified: &VerifiedQuote,
+        audience: Option<&str>,
+        nonce: Option<&[u8]>,
+        now: time_t,
+    ) -> TokenResult<String> {
+        let expires = self.expiry(verified, now)?;
+        let enclave = EnclaveClaims::from_quote(verified);
+        let mut claims = Claims::new().issuer(&self.issuer);
+        if let Some(audience) = audience {
+            claims = claims.audience(audience);
+        }
+        claims = claims.issued_at(now).expires_at(expires);
+        if let Some(nonce) = nonce {
+            claims = claims.claim("eat_nonce", json::Value::from(hex(nonce)));
+        }
+        let advisories = enclave.advisory_ids.iter().map(|id| json::Value::from(id.as_str()));
+        let claims = claims
+            .claim("sgx_mrenclave", json::Value::from(hex(&enclave.mr_enclave)))
+            .claim("sgx_mrsigner", json::Value::from(hex(&enclave.mr_signer)))
+            .claim("sgx_isvprodid", json::Value::Integer(enclave.isv_prod_id.into()))
+            .claim("sgx_isvsvn", json::Value::Integer(enclave.isv_svn.into()))
+            .claim("sgx_debug", json::Value::Bool(enclave.debug))
+            .claim("sgx_report_data", json::Value::from(hex(&enclave.report_data)))
+            .claim("sgx_tcb_status", json::Value::from(enclave.status_name()))
+            .claim("sgx_advisory_ids", json::Value::Array(advisories.collect()));
+        Ok(jose::sign_token(&claims, self.key_id.as_deref(), &self.key)?)
+    }
+
+    /// Returns the CWT of the enclave of `verified`, as [`jwt`] does.
+    ///
+    /// [`jwt`]: TokenIssuer::jwt
+    pub fn cwt(
+        &self,
+        verified: &VerifiedQuote,
+        audience: Option<&str>,
+        nonce: Option<&[u8]>,
+        now: time_t,
+    ) -> TokenResult<Vec<u8>> {
+        use cbor::Value;
+
+        let expires = self.expiry(verified, now)?;
+        let enclave = EnclaveClaims::from_quote(verified);
+        let mut entries = vec![
+            (Value::Unsigned(ISS_LABEL), Value::from(self.issuer.as_str())),
+            (Value::Unsigned(EXP_LABEL), Value::from(expires)),
+            (Value::Unsigned(IAT_LABEL), Value::from(now)),
+        ];
+        if let Some(audience) = audience {
+            entries.push((Value::Unsigned(AUD_LABEL), Value::from(audience)));
+        }
+        if let Some(nonce) = nonce {
+            entries.push((Value::Unsigned(NONCE_LABEL), Value::from(nonce)));
+        }
+        let advisories = enclave.advisory_ids.iter().map(|id| Value::from(id.as_str()));
+        entries.extend([
+            (Value::from("sgx_mrenclave"), Value::from(&enclave.mr_enclave[..])),
+            (Value::from("sgx_mrsigner"), Value::from(&enclave.mr_signer[..])),
+            (Value::from("sgx_isvprodid"), Value::Unsigned(enclave.isv_prod_id.into())),
+            (Value::from("sgx_isvsvn"), Value::Unsigned(enclave.isv_svn.into())),
+            (Value::from("sgx_debug"), Value::Bool(enclave.debug)),
+            (Value::from("sgx_report_data"), Value::from(&enclave.report_data[..])),
+            (Value::from("sgx_tcb_status"), Value::from(enclave.status_name())),
+            (Value::from("sgx_advisory_ids"), Value::Array(advisories.collect())),
+        ]);
+        entries.sort_by_cached_key(|(key, _)| key.encode());
+        let payload = Value::Map(entries).encode();
+        let key_id = self.key_id.as_ref().map(String::as_bytes);
+        Ok(cose::sign1(&payload, &[], key_id, &self.key)?)
+    }
+
+    fn expiry(&self, verified: &VerifiedQuote, now: time_t) -> TokenResult<time_t> {
+        let expires = now.saturating_add(self.lifetime).min(verified.expires());
+        if expires <= now {
+            return Err(JoseError::Expired.into());
+        }
+        Ok(expires)
+    }
+}
+
+/// Verifies the JWT `token` of an enclave with the key of its issuer, and validates its claims
+/// as [`jose::verify_token`] does.
+pub fn verify_jwt(
+    token: &str,
+    key: &PublicKey,
+    validation: &Validation,
+) -> TokenResult<AttestationToken> {
+    use json::Value;
+
+    let claims = jose::verify_token(token, key, validation)?;
+    let text = |name: &str| claims.get(name).and_then(Value::as_str).ok_or(TokenError::Malformed);
+    let bytes = |name: &str| dcap::hex_decode(text(name)?).ok_or(TokenError::Malformed);
+    let integer =
+        |name: &str| claims.get(name).and_then(Value::as_i64).ok_or(TokenError::Malformed);
+    let advisory_ids = claims
+        .get("sgx_advisory_ids")
+        .and_then(Value::as_array)
+        .ok_or(TokenError::Malformed)?
+        .iter()
+        .map(|id| id.as_str().map(String::from).ok_or(TokenError::Malformed))
+        .collect::<TokenResult<_>>()?;
+    let nonce = match claims.get("eat_nonce") {
+        Some(_) => Some(bytes("eat_nonce")?),
+        None => None,
+    };
+    let enclave = EnclaveClaims {
+        mr_enclave: array(&bytes("sgx_mrenclave")?)?,
+        mr_signer: array(&bytes("sgx_mrsigner")?)?,
+        isv_prod_id: small(integer("sgx_isvprodid")?)?,
+        isv_svn: small(integer("sgx_isvsvn")?)?,
+        debug: claims.get("sgx_debug").and_then(Value::as_bool).ok_or(TokenError::Malformed)?,
+        report_data: array(&bytes("sgx_report_data")?)?,
+        tcb_status: dcap::tcb_status(text("sgx_tcb_status")?).ok_or(TokenError::Malformed)?,
+        advisory_ids,
+    };
+    Ok(AttestationToken {
+        issuer: String::from(text("iss")?),
+        issued_at: integer("iat")?,
+        expires_at: integer("exp")?,
+        nonce,
+        enclave,
+    })
+}
+
+/// Verifies the CWT `message` of an enclave with the key of its issuer, and validates its
+/// claims as [`verify_jwt`] does, failing with the [`JoseError`] of the claim found invalid.
+pub fn verify_cwt(
+    message: &[u8],
+    key: &PublicKey,
+    validation: &Validation,
+) -> TokenResult<AttestationToken> {
+    use cbor::Value;
+
+    let payload = cose::verify_sign1(message, &[], key)?;
+    let map = Value::decode(&payload).map_err(|_| TokenError::Malformed)?;
+    let get = |key: Value| map.get(&key).ok_or(TokenError::Malformed);
+    let text = |key: Value| get(key)?.as_text().ok_or(TokenError::Malformed);
+    let bytes = |key: Value| get(key)?.as_bytes().ok_or(TokenError::Malformed);
+    let integer = |key: Value| get(key)?.as_i64().ok_or(TokenError::Malformed);
+
+    // The registered claims are validated by the rules of JWTs.
+    let issuer = text(Value::Unsigned(ISS_LABEL))?;
+    let issued_at = integer(Value::Unsigned(IAT_LABEL))?;
+    let expires_at = integer(Value::Unsigned(EXP_LABEL))?;
+    let mut claims = Claims::new().issuer(issuer).issued_at(issued_at).expires_at(expires_at);
+    if map.get(&Value::Unsigned(AUD_LABEL)).is_some() {
+        claims = claims.audience(text(Value::Unsigned(AUD_LABEL))?);
+    }
+    validation.check(&claims)?;
+
+    let advisory_ids = get(Value::from("sgx_advisory_ids"))?
+        .as_array()
+        .ok_or(TokenError::Malformed)?
+        .iter()
+        .map(|id| id.as_text().map(String::from).ok_or(TokenError::Malformed))
+        .collect::<TokenResult<_>>()?;
+    let nonce = match map.get(&Value::Unsigned(NONCE_LABEL)) {
+        Some(_) => Some(Vec::from(bytes(Value::Unsigned(NONCE_LABEL))?)),
+        None => None,
+    };
+    let debug = match get(Value::from("sgx_debug"))? {
+        Value::Bool(debug) => *debug,
+        _ => return Err(TokenError::Malformed),
+    };
+    let status = text(Value::from("sgx_tcb_status"))?;
+    let enclave = EnclaveClaims {
+        mr_enclave: array(bytes(Value::from("sgx_mrenclave"))?)?,
+        mr_signer: array(bytes(Value::from("sgx_mrsigner"))?)?,
+        isv_prod_id: small(integer(Value::from("sgx_isvprodid"))?)?,
+        isv_svn: small(integer(Value::from("sgx_isvsvn"))?)?,
+        debug,
+        report_data: array(bytes(Value::from("sgx_report_data"))?)?,
+        tcb_status: dcap::tcb_status(status).ok_or(TokenError::Malformed)?,
+        advisory_ids,
+    };
+    Ok(AttestationToken { issuer: String::from(issuer), issued_at, expires_at, nonce, enclave })
+}
+
+fn array<const N: usize>(bytes: &[u8]) -> TokenResult<[u8; N]> {
+    bytes.try_into().map_err(|_| TokenError::Malformed)
+}
+
+fn small(integer: i64) -> TokenResult<u16> {
+    integer.try_into().map_err(|_| TokenError::Malformed)
+}
+
+/// Returns the lowercase hex of `bytes`.
+fn hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        // Writing to a string doesn't fail.
+        let _ = write!(text, "{:02x}", byte);
+    }
+    text
+}
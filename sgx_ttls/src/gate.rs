@@ -0,0 +1,237 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Ecalls gated on the attestation of the peer of their session.
+//!
+//! Ecalls serving a peer, such as the requests of an attested channel, must only run while the
+//! peer is attested, by a verification which still holds and of an enclave the policy trusts.
+//! An [`AttestationGate`] records the attested peer of each session once its evidence
+//! verified, and [`attested_ecall!`](crate::attested_ecall) refuses the ecalls of a session
+//! without one:
+//!
+//! ```ignore
+//! static GATE: SgxMutex<Option<AttestationGate>> = SgxMutex::new(None);
+//!
+//! #[no_mangle]
+//! pub extern "C" fn ecall_transfer(session: u64, request: *const u8, len: usize) -> sgx_status_t {
+//!     let gate = GATE.lock().unwrap();
+//!     let now = match clock.now() { ... };
+//!     attested_ecall!(gate.as_ref().unwrap(), session, now, |peer| {
+//!         transfer(peer, request, len)
+//!     })
+//! }
+//! ```
+//!
+//! The gate checks the policy when a peer is admitted, and at each call that the attestation
+//! hasn't expired by the trusted time given: the expiry of the collateral of a quote, or of a
+//! token. Sessions are numbered by the application, e.g. by the handle of their TLS session,
+//! and should be revoked when they end so that the number can't be reused by another peer
+//! while the attestation lasts. The gate isn't synchronized; enclaves whose ecalls run on
+//! several threads keep it behind a mutex.
+
+use crate::dcap::VerifiedQuote;
+use crate::eat::{AttestationToken, EnclaveClaims};
+use crate::policy::{Policy, PolicyError};
+use alloc::vec::Vec;
+use core::fmt;
+use sgx_types::*;
+
+/// The number of sessions a gate holds by default.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// An error admitting a peer, or gating an ecall.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GateError {
+    /// The session has no attested peer.
+    UnknownSession,
+    /// The attestation of the peer has expired.
+    Expired,
+    /// The policy doesn't trust the peer.
+    Policy(PolicyError),
+    /// The gate holds as many sessions as its capacity, none of them expired.
+    Full,
+}
+
+impl GateError {
+    /// Returns the status an ecall fails with: `SGX_ERROR_NO_PRIVILEGE` for a peer which isn't
+    /// attested, and `SGX_ERROR_OUT_OF_MEMORY` for a full gate.
+    pub fn status(&self) -> sgx_status_t {
+        match self {
+            GateError::Full => sgx_status_t::SGX_ERROR_OUT_OF_MEMORY,
+            _ => sgx_status_t::SGX_ERROR_NO_PRIVILEGE,
+        }
+    }
+}
+
+impl fmt::Display for GateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GateError::UnknownSession => f.write_str("session has no attested peer"),
+            GateError::Expired => f.write_str("attestation of the peer expired"),
+            GateError::Policy(error) => error.fmt(f),
+            GateError::Full => f.write_str("too many attested sessions"),
+        }
+    }
+}
+
+impl From<PolicyError> for GateError {
+    fn from(error: PolicyError) -> GateError {
+        GateError::Policy(error)
+    }
+}
+
+pub type GateResult<T> = Result<T, GateError>;
+
+struct Session {
+    id: u64,
+    peer: EnclaveClaims,
+    expires: time_t,
+}
+
+/// The attested peers of sessions.
+pub struct AttestationGate {
+    policy: Policy,
+    capacity: usize,
+    sessions: Vec<Session>,
+}
+
+impl AttestationGate {
+    /// Creates a gate admitting the peers `policy` trusts.
+    pub fn new(policy: Policy) -> AttestationGate {
+        AttestationGate { policy, capacity: DEFAULT_CAPACITY, sessions: Vec::new() }
+    }
+
+    /// Sets how many sessions the gate holds, [`DEFAULT_CAPACITY`] by default.
+    pub fn capacity(mut self, capacity: usize) -> AttestationGate {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Admits `peer` as the peer of `session` until `expires`, replacing any peer the session
+    /// had, if the policy trusts it, as [`Policy::check_status`] and [`Policy::check_enclave`]
+    /// do.
+    ///
+    /// Expired sessions are dropped to make room; fails with [`GateError::Full`] if there are
+    /// none.
+    pub fn admit(
+        &mut self,
+        session: u64,
+        peer: EnclaveClaims,
+        expires: time_t,
+        now: time_t,
+    ) -> GateResult<()> {
+        if now >= expires {
+            return Err(GateError::Expired);
+        }
+        self.policy.check_status(peer.tcb_status, Some(&peer.advisory_ids))?;
+        let mut body = sgx_report_body_t::default();
+        body.mr_enclave.m = peer.mr_enclave;
+        body.mr_signer.m = peer.mr_signer;
+        body.isv_prod_id = peer.isv_prod_id;
+        body.isv_svn = peer.isv_svn;
+        if peer.debug {
+            body.attributes.flags |= SGX_FLAGS_DEBUG;
+        }
+        self.policy.check_enclave(&body)?;
+
+        self.sessions.retain(|admitted| admitted.id != session);
+        if self.sessions.len() >= self.capacity {
+            self.purge(now);
+            if self.sessions.len() >= self.capacity {
+                return Err(GateError::Full);
+            }
+        }
+        self.sessions.push(Session { id: session, peer, expires });
+        Ok(())
+    }
+
+    /// Admits the enclave of `verified` until its collateral expires.
+    pub fn admit_quote(
+        &mut self,
+        session: u64,
+        verified: &VerifiedQuote,
+        now: time_t,
+    ) -> GateResult<()> {
+        self.admit(session, EnclaveClaims::from_quote(verified), verified.expires(), now)
+    }
+
+    /// Admits the enclave of `token` until the token expires.
+    pub fn admit_token(
+        &mut self,
+        session: u64,
+        token: &AttestationToken,
+        now: time_t,
+    ) -> GateResult<()> {
+        self.admit(session, token.enclave.clone(), token.expires_at, now)
+    }
+
+    /// Returns the attested peer of `session` if its attestation still holds at `now`.
+    pub fn check(&self, session: u64, now: time_t) -> GateResult<&EnclaveClaims> {
+        let admitted = self
+            .sessions
+            .iter()
+            .find(|admitted| admitted.id == session)
+            .ok_or(GateError::UnknownSession)?;
+        if now >= admitted.expires {
+            return Err(GateError::Expired);
+        }
+        Ok(&admitted.peer)
+    }
+
+    /// Forgets the peer of `session`, e.g. once the session ended.
+    pub fn revoke(&mut self, session: u64) {
+        self.sessions.retain(|admitted| admitted.id != session);
+    }
+
+    /// Drops the sessions whose attestation expired by `now`.
+    pub fn purge(&mut self, now: time_t) {
+        self.sessions.retain(|admitted| now < admitted.expires);
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+/// Runs `$body` with the attested peer of `$session` bound to `$peer`, if the
+/// [`AttestationGate`](crate::gate::AttestationGate) `$gate` has one at the trusted time `$now`,
+/// and otherwise returns the [`status`](crate::gate::GateError::status) of the error from the
+/// ecall.
+///
+/// ```ignore
+/// attested_ecall!(gate, session, now, |peer| {
+///     if peer.isv_svn < 3 { return sgx_status_t::SGX_ERROR_NO_PRIVILEGE; }
+///     handle(peer)
+/// })
+/// ```
+#[macro_export]
+macro_rules! attested_ecall {
+    ($gate:expr, $session:expr, $now:expr, |$peer:ident| $body:block) => {
+        match $gate.check($session, $now) {
+            Ok($peer) => $body,
+            Err(error) => return $crate::gate::GateError::status(&error),
+        }
+    };
+}
@@ -0,0 +1,446 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! A small HTTP/1.1 client.
+//!
+//! Requests are written to, and responses read from, any [`Transport`], typically an attested
+//! TLS stream, so that enclaves can fetch collateral and call REST services. Responses are read
+//! whole, with fixed-length, chunked and close-delimited bodies, and bounded by [`Limits`] so
+//! that a hostile server can't exhaust enclave memory.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A byte stream carrying HTTP, such as a TLS session.
+pub trait Transport {
+    type Error;
+
+    /// Reads into `buf`, returning the number of bytes read, or 0 at the end of the stream.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Writes all of `buf`.
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// An error sending a request or reading its response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HttpError<E> {
+    /// The transport failed.
+    Transport(E),
+    /// A header name or value, or the request target, contains forbidden characters.
+    InvalidHeader,
+    /// The status line and headers, or the trailers, are larger than allowed.
+    HeadersTooLarge,
+    /// There are more headers than allowed.
+    TooManyHeaders,
+    /// The body is larger than allowed.
+    BodyTooLarge,
+    /// The response isn't valid HTTP/1.1.
+    Malformed(&'static str),
+    /// The stream ended in the middle of the response.
+    UnexpectedEof,
+}
+
+impl<E: fmt::Display> fmt::Display for HttpError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            HttpError::Transport(ref e) => write!(f, "transport error: {}", e),
+            HttpError::InvalidHeader => f.write_str("invalid header"),
+            HttpError::HeadersTooLarge => f.write_str("headers too large"),
+            HttpError::TooManyHeaders => f.write_str("too many headers"),
+            HttpError::BodyTooLarge => f.write_str("body too large"),
+            HttpError::Malformed(what) => write!(f, "malformed response: {}", what),
+            HttpError::UnexpectedEof => f.write_str("unexpected end of response"),
+        }
+    }
+}
+
+pub type HttpResult<T, E> = Result<T, HttpError<E>>;
+
+/// The request methods.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// Bounds on the size of a response.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// The maximum size of the status line and headers together, and of the trailers.
+    pub max_header_bytes: usize,
+    /// The maximum number of headers, and of trailers.
+    pub max_headers: usize,
+    /// The maximum size of the body.
+    pub max_body_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits { max_header_bytes: 16 * 1024, max_headers: 64, max_body_bytes: 16 * 1024 * 1024 }
+    }
+}
+
+/// Returns whether `s` can be written in a request line or header without splitting it.
+fn is_field_safe(s: &str) -> bool {
+    !s.bytes().any(|b| b == b'\r' || b == b'\n' || b == 0)
+}
+
+/// Returns whether `name` is a valid header name token.
+fn is_token(name: &str) -> bool {
+    !name.is_empty()
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// An HTTP/1.1 request.
+#[derive(Clone, Debug)]
+pub struct Request {
+    method: Method,
+    host: String,
+    target: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Creates a request of `target` (e.g. `"/sgx/certification/v4/tcb?fmspc=00906ED50000"`) on
+    /// `host`, which is sent as the `Host` header.
+    pub fn new<E>(method: Method, host: &str, target: &str) -> HttpResult<Request, E> {
+        if !is_field_safe(host) || !is_field_safe(target) || target.contains(' ') {
+            return Err(HttpError::InvalidHeader);
+        }
+        Ok(Request {
+            method,
+            host: host.to_string(),
+            target: target.to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        })
+    }
+
+    /// Creates a `GET` request.
+    pub fn get<E>(host: &str, target: &str) -> HttpResult<Request, E> {
+        Request::new(Method::Get, host, target)
+    }
+
+    /// Creates a `POST` request with `body`.
+    pub fn post<E>(host: &str, target: &str, body: Vec<u8>) -> HttpResult<Request, E> {
+        Request::new(Method::Post, host, target).map(|request| request.body(body))
+    }
+
+    /// Adds a header.
+    ///
+    /// `Host`, `Content-Length` and `Transfer-Encoding` are set by the client.
+    pub fn header<E>(mut self, name: &str, value: &str) -> HttpResult<Request, E> {
+        if !is_token(name) || !is_field_safe(value) {
+            return Err(HttpError::InvalidHeader);
+        }
+        self.headers.push((name.to_string(), value.to_string()));
+        Ok(self)
+    }
+
+    /// Sets the body.
+    pub fn body(mut self, body: Vec<u8>) -> Request {
+        self.body = body;
+        self
+    }
+
+    /// Returns the method.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// Serializes the request.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut head = String::with_capacity(128);
+        head.push_str(self.method.as_str());
+        head.push(' ');
+        head.push_str(&self.target);
+        head.push_str(" HTTP/1.1\r\nHost: ");
+        head.push_str(&self.host);
+        head.push_str("\r\n");
+        for (name, value) in &self.headers {
+            let managed = ["host", "content-length", "transfer-encoding"];
+            if managed.iter().any(|managed| name.eq_ignore_ascii_case(managed)) {
+                continue;
+            }
+            head.push_str(name);
+            head.push_str(": ");
+            head.push_str(value);
+            head.push_str("\r\n");
+        }
+        if !self.body.is_empty() || matches!(self.method, Method::Post | Method::Put) {
+            head.push_str("Content-Length: ");
+            head.push_str(&self.body.len().to_string());
+            head.push_str("\r\n");
+        }
+        head.push_str("\r\n");
+
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+/// An HTTP/1.1 response.
+#[derive(Clone, Debug)]
+pub struct Response {
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns the status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the reason phrase.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Returns the headers, and trailers of a chunked body, in order.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Returns the value of the first header named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the body.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Returns the body, consuming the response.
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+}
+
+/// Buffers reads from a transport.
+pub(crate) struct Reader<'a, T: Transport> {
+    pub(crate) transport: &'a mut T,
+    pub(crate) buf: Vec<u8>,
+    pub(crate) pos: usize,
+}
+
+impl<'a, T: Transport> Reader<'a, T> {
+    /// Reads more data into the buffer, returning `false` at the end of the stream.
+    fn fill(&mut self) -> HttpResult<bool, T::Error> {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        let len = self.buf.len();
+        self.buf.resize(len + 4096, 0);
+        let result = self.transport.read(&mut self.buf[len..]);
+        let read = *result.as_ref().unwrap_or(&0);
+        self.buf.truncate(len + read);
+        result.map(|read| read > 0).map_err(HttpError::Transport)
+    }
+
+    /// Reads a line ending in CRLF, without the line ending, charging its length to `budget`.
+    pub(crate) fn read_line(&mut self, budget: &mut usize) -> HttpResult<String, T::Error> {
+        loop {
+            if let Some(end) = self.buf[self.pos..].windows(2).position(|w| w == b"\r\n") {
+                if end + 2 > *budget {
+                    return Err(HttpError::HeadersTooLarge);
+                }
+                *budget -= end + 2;
+                let line = &self.buf[self.pos..self.pos + end];
+                let line = core::str::from_utf8(line)
+                    .map_err(|_| HttpError::Malformed("line is not UTF-8"))?
+                    .to_string();
+                self.pos += end + 2;
+                return Ok(line);
+            }
+            if self.buf.len() - self.pos > *budget {
+                return Err(HttpError::HeadersTooLarge);
+            }
+            if !self.fill()? {
+                return Err(HttpError::UnexpectedEof);
+            }
+        }
+    }
+
+    /// Appends exactly `len` bytes to `out`.
+    fn read_exact(&mut self, mut len: usize, out: &mut Vec<u8>) -> HttpResult<(), T::Error> {
+        while len > 0 {
+            if self.pos == self.buf.len() && !self.fill()? {
+                return Err(HttpError::UnexpectedEof);
+            }
+            let available = core::cmp::min(len, self.buf.len() - self.pos);
+            out.extend_from_slice(&self.buf[self.pos..self.pos + available]);
+            self.pos += available;
+            len -= available;
+        }
+        Ok(())
+    }
+
+    /// Appends everything up to the end of the stream to `out`, up to `limit` bytes in total.
+    fn read_to_end(&mut self, out: &mut Vec<u8>, limit: usize) -> HttpResult<(), T::Error> {
+        loop {
+            if out.len() + (self.buf.len() - self.pos) > limit {
+                return Err(HttpError::BodyTooLarge);
+            }
+            out.extend_from_slice(&self.buf[self.pos..]);
+            self.pos = self.buf.len();
+            if !self.fill()? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reads header lines up to an empty line into `headers`.
+    pub(crate) fn read_headers(
+        &mut self,
+        headers: &mut Vec<(String, String)>,
+        limits: &Limits,
+        budget: &mut usize,
+    ) -> HttpResult<(), T::Error> {
+        let max = headers.len() + limits.max_headers;
+        loop {
+            let line = self.read_line(budget)?;
+            if line.is_empty() {
+                return Ok(());
+            }
+            if headers.len() == max {
+                return Err(HttpError::TooManyHeaders);
+            }
+            let (name, value) =
+                line.split_once(':').ok_or(HttpError::Malformed("header without a colon"))?;
+            if !is_token(name) {
+                return Err(HttpError::Malformed("invalid header name"));
+            }
+            headers.push((name.to_string(), value.trim().to_string()));
+        }
+    }
+}
+
+/// Sends `request` over `transport` and reads its response, within `limits`.
+///
+/// Interim `1xx` responses are skipped. The transport can be reused for another request unless
+/// the response has a close-delimited body or a `Connection: close` header.
+pub fn send<T: Transport>(
+    transport: &mut T,
+    request: &Request,
+    limits: &Limits,
+) -> HttpResult<Response, T::Error> {
+    transport.write_all(&request.to_bytes()).map_err(HttpError::Transport)?;
+
+    let mut reader = Reader { transport, buf: Vec::new(), pos: 0 };
+    let (status, reason, headers) = loop {
+        let mut budget = limits.max_header_bytes;
+        let line = reader.read_line(&mut budget)?;
+        let mut parts = line.splitn(3, ' ');
+        let version = parts.next().unwrap_or("");
+        if !version.starts_with("HTTP/1.") {
+            return Err(HttpError::Malformed("not an HTTP/1.x status line"));
+        }
+        let status = parts
+            .next()
+            .filter(|code| code.len() == 3)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or(HttpError::Malformed("invalid status code"))?;
+        let reason = parts.next().unwrap_or("").to_string();
+
+        let mut headers = Vec::new();
+        reader.read_headers(&mut headers, limits, &mut budget)?;
+        if !(100..200).contains(&status) || status == 101 {
+            break (status, reason, headers);
+        }
+    };
+
+    let mut response = Response { status, reason, headers, body: Vec::new() };
+    if request.method() == Method::Head || status == 204 || status == 304 || status == 101 {
+        return Ok(response);
+    }
+
+    // Only a final `chunked` coding delimits the body by chunks.
+    let chunked = match response.header("transfer-encoding") {
+        Some(codings) => {
+            codings.rsplit(',').next().unwrap_or("").trim().eq_ignore_ascii_case("chunked")
+        }
+        None => false,
+    };
+    if chunked {
+        let mut budget = limits.max_header_bytes;
+        loop {
+            let line = reader.read_line(&mut budget)?;
+            let size = line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| HttpError::Malformed("invalid chunk size"))?;
+            if size == 0 {
+                let mut trailers = Vec::new();
+                reader.read_headers(&mut trailers, limits, &mut budget)?;
+                response.headers.extend(trailers);
+                break;
+            }
+            if size > limits.max_body_bytes - response.body.len() {
+                return Err(HttpError::BodyTooLarge);
+            }
+            reader.read_exact(size, &mut response.body)?;
+            let mut crlf = Vec::with_capacity(2);
+            reader.read_exact(2, &mut crlf)?;
+            if crlf != b"\r\n" {
+                return Err(HttpError::Malformed("chunk not followed by CRLF"));
+            }
+            // Chunk size lines are charged per chunk, not for the whole body.
+            budget = limits.max_header_bytes;
+        }
+    } else if let Some(len) = response.header("content-length") {
+        let len = len
+            .parse::<usize>()
+            .map_err(|_| HttpError::Malformed("invalid Content-Length"))?;
+        if len > limits.max_body_bytes {
+            return Err(HttpError::BodyTooLarge);
+        }
+        let mut body = Vec::with_capacity(len);
+        reader.read_exact(len, &mut body)?;
+        response.body = body;
+    } else {
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body, limits.max_body_bytes)?;
+        response.body = body;
+    }
+    Ok(response)
+}
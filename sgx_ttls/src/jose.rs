@@ -0,0 +1,393 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! JSON Web Signatures (RFC 7515) and JSON Web Tokens (RFC 7519) over ES256 and EdDSA, and
+//! verified over RS256 too.
+//!
+//! Tokens are signed with the [`SigningKey`]s of the [`pki`](crate::pki) module, so that an
+//! enclave can mint short-lived capability tokens, e.g. for wallet sessions, without the key
+//! ever leaving it, and verified with a [`PublicKey`], such as the RSA key of an external
+//! attestation service. Only the compact serialization is supported, and a token is only
+//! accepted with the algorithm of the key it's verified with, whatever its header names:
+//! `none`, like headers with critical extensions, is rejected.
+//!
+//! [`verify_token`] checks the expiry and audience of a token against a [`Validation`], whose
+//! time has to come from a trusted source: the host controls the enclave's view of time
+//! otherwise.
+
+use crate::json::Value;
+use crate::pki::{sign_raw, verify_raw, verify_rsa_sha256, PkiError, PublicKey, SigningKey};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use sgx_types::*;
+
+/// An error signing or verifying a token.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum JoseError {
+    /// Signing or verifying failed.
+    Crypto(sgx_status_t),
+    /// The token isn't a compact JWS, or its header or claims aren't JSON objects.
+    Malformed,
+    /// The key isn't for ES256, EdDSA or, to verify, RS256, or the header names another
+    /// algorithm than the key's, or critical extensions.
+    UnsupportedAlgorithm,
+    /// The signature doesn't verify.
+    BadSignature,
+    /// The token has no expiry.
+    MissingExpiry,
+    /// The token has expired.
+    Expired,
+    /// The token isn't valid yet.
+    NotYetValid,
+    /// The token isn't intended for the expected audience.
+    InvalidAudience,
+    /// The token wasn't issued by the expected issuer.
+    InvalidIssuer,
+}
+
+impl fmt::Display for JoseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            JoseError::Crypto(status) => write!(f, "cryptographic operation failed: {}", status),
+            JoseError::Malformed => f.write_str("malformed token"),
+            JoseError::UnsupportedAlgorithm => f.write_str("unsupported algorithm"),
+            JoseError::BadSignature => f.write_str("signature verification failed"),
+            JoseError::MissingExpiry => f.write_str("token has no expiry"),
+            JoseError::Expired => f.write_str("token expired"),
+            JoseError::NotYetValid => f.write_str("token not valid yet"),
+            JoseError::InvalidAudience => f.write_str("token not intended for audience"),
+            JoseError::InvalidIssuer => f.write_str("token not issued by expected issuer"),
+        }
+    }
+}
+
+impl From<PkiError> for JoseError {
+    fn from(error: PkiError) -> JoseError {
+        match error {
+            PkiError::Crypto(status) => JoseError::Crypto(status),
+            _ => JoseError::UnsupportedAlgorithm,
+        }
+    }
+}
+
+pub type JoseResult<T> = Result<T, JoseError>;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Appends the unpadded base64url encoding of `data` to `out`.
+pub(crate) fn base64url(out: &mut String, data: &[u8]) {
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0_u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+}
+
+/// Decodes unpadded base64url, rejecting non-zero trailing bits so that each encoding is the
+/// only one of its data.
+pub(crate) fn base64url_decode(text: &str) -> JoseResult<Vec<u8>> {
+    let mut data = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return Err(JoseError::Malformed);
+        }
+        let mut bits = 0_u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'-' => 62,
+                b'_' => 63,
+                _ => return Err(JoseError::Malformed),
+            };
+            bits |= (value as u32) << (18 - 6 * i);
+        }
+        let len = chunk.len() - 1;
+        if bits & (0xff_ffff >> (8 * len)) != 0 {
+            return Err(JoseError::Malformed);
+        }
+        for i in 0..len {
+            data.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(data)
+}
+
+/// Returns the `alg` of signatures by `key`.
+fn algorithm(key: &PublicKey) -> JoseResult<&'static str> {
+    match key {
+        PublicKey::P256(_) => Ok("ES256"),
+        PublicKey::Ed25519(_) => Ok("EdDSA"),
+        PublicKey::Rsa { .. } => Ok("RS256"),
+    }
+}
+
+fn sign_with_type(
+    typ: Option<&str>,
+    payload: &[u8],
+    key_id: Option<&str>,
+    key: &dyn SigningKey,
+) -> JoseResult<String> {
+    let public = key.public_key();
+    if let PublicKey::Rsa { .. } = public {
+        return Err(JoseError::UnsupportedAlgorithm);
+    }
+    let mut header = vec![(String::from("alg"), Value::from(algorithm(&public)?))];
+    if let Some(typ) = typ {
+        header.push((String::from("typ"), Value::from(typ)));
+    }
+    if let Some(key_id) = key_id {
+        header.push((String::from("kid"), Value::from(key_id)));
+    }
+    let mut token = String::new();
+    base64url(&mut token, Value::Object(header).to_string().as_bytes());
+    token.push('.');
+    base64url(&mut token, payload);
+
+    let signature = sign_raw(key, token.as_bytes())?;
+    token.push('.');
+    base64url(&mut token, &signature);
+    Ok(token)
+}
+
+/// Returns the compact JWS of `payload` signed by `key`, with a header naming the key `key_id`
+/// if given.
+pub fn sign(payload: &[u8], key_id: Option<&str>, key: &dyn SigningKey) -> JoseResult<String> {
+    sign_with_type(None, payload, key_id, key)
+}
+
+/// Splits the compact JWS `token`, returning its signing input and decoded parts.
+fn split(token: &str) -> JoseResult<(&str, Value, Vec<u8>, Vec<u8>)> {
+    let mut parts = token.splitn(3, '.');
+    let (header, payload, signature) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(header), Some(payload), Some(signature)) => (header, payload, signature),
+        _ => return Err(JoseError::Malformed),
+    };
+    let input = &token[..header.len() + 1 + payload.len()];
+    let header = Value::parse(&base64url_decode(header)?).map_err(|_| JoseError::Malformed)?;
+    if header.as_object().is_none() {
+        return Err(JoseError::Malformed);
+    }
+    Ok((input, header, base64url_decode(payload)?, base64url_decode(signature)?))
+}
+
+/// Verifies the compact JWS `token` with `key`, returning its payload.
+pub fn verify(token: &str, key: &PublicKey) -> JoseResult<Vec<u8>> {
+    let (input, header, payload, signature) = split(token)?;
+    if header.get("alg").and_then(Value::as_str) != Some(algorithm(key)?)
+        || header.get("crit").is_some()
+    {
+        return Err(JoseError::UnsupportedAlgorithm);
+    }
+    let valid = match key {
+        PublicKey::Rsa { .. } => verify_rsa_sha256(key, input.as_bytes(), &signature)?,
+        _ => verify_raw(key, input.as_bytes(), &signature)?,
+    };
+    if !valid {
+        return Err(JoseError::BadSignature);
+    }
+    Ok(payload)
+}
+
+/// Returns the `kid` header of `token`, without verifying it, to pick the key to verify it
+/// with.
+pub fn key_id(token: &str) -> JoseResult<Option<String>> {
+    let (_, header, _, _) = split(token)?;
+    match header.get("kid") {
+        Some(Value::String(key_id)) => Ok(Some(key_id.clone())),
+        Some(_) => Err(JoseError::Malformed),
+        None => Ok(None),
+    }
+}
+
+/// The claims of a token, a JSON object with its members in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Claims {
+    members: Vec<(String, Value)>,
+}
+
+impl Claims {
+    pub fn new() -> Claims {
+        Claims::default()
+    }
+
+    pub fn issuer(self, issuer: &str) -> Claims {
+        self.claim("iss", Value::from(issuer))
+    }
+
+    pub fn subject(self, subject: &str) -> Claims {
+        self.claim("sub", Value::from(subject))
+    }
+
+    /// Adds the audience `audience`, the claim being a string as long as there's only one.
+    pub fn audience(self, audience: &str) -> Claims {
+        let audiences = match self.get("aud") {
+            Some(Value::String(first)) => vec![Value::from(first.as_str()), Value::from(audience)],
+            Some(Value::Array(audiences)) => {
+                let mut audiences = audiences.clone();
+                audiences.push(Value::from(audience));
+                audiences
+            }
+            _ => return self.claim("aud", Value::from(audience)),
+        };
+        self.claim("aud", Value::Array(audiences))
+    }
+
+    /// Sets the expiry, in seconds since the Unix epoch.
+    pub fn expires_at(self, time: time_t) -> Claims {
+        self.claim("exp", Value::Integer(time))
+    }
+
+    pub fn not_before(self, time: time_t) -> Claims {
+        self.claim("nbf", Value::Integer(time))
+    }
+
+    pub fn issued_at(self, time: time_t) -> Claims {
+        self.claim("iat", Value::Integer(time))
+    }
+
+    /// Sets the unique identifier of the token, which lets its use be recorded.
+    pub fn token_id(self, id: &str) -> Claims {
+        self.claim("jti", Value::from(id))
+    }
+
+    /// Sets the claim `name`, replacing any claim of the same name.
+    pub fn claim(mut self, name: &str, value: Value) -> Claims {
+        match self.members.iter_mut().find(|(n, _)| n == name) {
+            Some(member) => member.1 = value,
+            None => self.members.push((String::from(name), value)),
+        }
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.members.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    pub fn members(&self) -> &[(String, Value)] {
+        &self.members
+    }
+
+    /// Returns the NumericDate claim `name`, with any fraction of a second dropped.
+    fn time(&self, name: &str) -> JoseResult<Option<time_t>> {
+        match self.get(name) {
+            None => Ok(None),
+            Some(Value::Integer(time)) => Ok(Some(*time)),
+            Some(Value::Float(time)) if time.abs() < 1e18 => Ok(Some(*time as time_t)),
+            Some(_) => Err(JoseError::Malformed),
+        }
+    }
+}
+
+/// The requirements on the claims of a token besides its signature.
+#[derive(Clone, Debug)]
+pub struct Validation {
+    now: time_t,
+    leeway: time_t,
+    audience: Option<String>,
+    issuer: Option<String>,
+}
+
+impl Validation {
+    /// Returns the validation of tokens at `now`, in seconds since the Unix epoch, which have
+    /// no audience and any issuer.
+    pub fn new(now: time_t) -> Validation {
+        Validation { now, leeway: 0, audience: None, issuer: None }
+    }
+
+    /// Requires the token to be intended for `audience`.
+    pub fn audience(mut self, audience: &str) -> Validation {
+        self.audience = Some(String::from(audience));
+        self
+    }
+
+    /// Requires the token to be issued by `issuer`.
+    pub fn issuer(mut self, issuer: &str) -> Validation {
+        self.issuer = Some(String::from(issuer));
+        self
+    }
+
+    /// Allows `leeway` seconds of clock skew between the issuer and the enclave.
+    pub fn leeway(mut self, leeway: time_t) -> Validation {
+        self.leeway = leeway;
+        self
+    }
+
+    pub(crate) fn check(&self, claims: &Claims) -> JoseResult<()> {
+        let expiry = claims.time("exp")?.ok_or(JoseError::MissingExpiry)?;
+        if self.now >= expiry.saturating_add(self.leeway) {
+            return Err(JoseError::Expired);
+        }
+        if let Some(not_before) = claims.time("nbf")? {
+            if self.now.saturating_add(self.leeway) < not_before {
+                return Err(JoseError::NotYetValid);
+            }
+        }
+        // A token with an audience is only valid for it, so tokens for one relying party
+        // can't be replayed to another.
+        let audience = claims.get("aud");
+        let intended = match (&self.audience, audience) {
+            (None, None) => true,
+            (Some(expected), Some(Value::String(audience))) => audience == expected,
+            (Some(expected), Some(Value::Array(audiences))) => {
+                audiences.iter().any(|audience| audience.as_str() == Some(expected))
+            }
+            _ => false,
+        };
+        if !intended {
+            return Err(JoseError::InvalidAudience);
+        }
+        if let Some(expected) = &self.issuer {
+            if claims.get("iss").and_then(Value::as_str) != Some(expected) {
+                return Err(JoseError::InvalidIssuer);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the JWT of `claims` signed by `key`, with a header naming the key `key_id` if
+/// given.
+pub fn sign_token(
+    claims: &Claims,
+    key_id: Option<&str>,
+    key: &dyn SigningKey,
+) -> JoseResult<String> {
+    let payload = Value::Object(claims.members.clone()).to_string();
+    sign_with_type(Some("JWT"), payload.as_bytes(), key_id, key)
+}
+
+/// Verifies the JWT `token` with `key` and validates its claims, returning them.
+///
+/// The token must have an expiry, and be used before it and not before its `nbf`, give or take
+/// the leeway. A token with an audience is only accepted if it includes the expected one, and
+/// one without only if no audience is expected.
+pub fn verify_token(token: &str, key: &PublicKey, validation: &Validation) -> JoseResult<Claims> {
+    let payload = verify(token, key)?;
+    let members = match Value::parse(&payload) {
+        Ok(Value::Object(members)) => members,
+        _ => return Err(JoseError::Malformed),
+    };
+    let claims = Claims { members };
+    validation.check(&claims)?;
+    Ok(claims)
+}
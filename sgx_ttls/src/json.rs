@@ -0,0 +1,472 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! JSON (RFC 8259) values, for the tokens and the services enclaves exchange them with.
+//!
+//! Parsing is strict, as it is for input crossing the enclave boundary: no trailing commas or
+//! data, no duplicate object keys, lone surrogates or nesting deeper than [`MAX_DEPTH`].
+//! Objects keep their members in order, so that a value serializes back as it was built.
+//! Integers are kept exactly within `i64`, and other numbers as `f64`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::Write;
+
+/// The deepest nesting of arrays and objects parsing accepts.
+pub const MAX_DEPTH: usize = 64;
+
+/// A JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    /// A number with a fraction or an exponent, or out of the range of `i64`.
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// An object, with its members in order.
+    Object(Vec<(String, Value)>),
+}
+
+/// Input which isn't a JSON value, with the offset of the first byte found wrong.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct JsonError {
+    pub offset: usize,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid JSON at byte {}", self.offset)
+    }
+}
+
+pub type JsonResult<T> = Result<T, JsonError>;
+
+impl Value {
+    /// Parses `text`, which must hold exactly one value, surrounded by whitespace only.
+    pub fn parse(text: &[u8]) -> JsonResult<Value> {
+        let mut parser = Parser { text, pos: 0 };
+        let value = parser.value(0)?;
+        parser.whitespace();
+        if parser.pos != text.len() {
+            return Err(parser.error());
+        }
+        Ok(value)
+    }
+
+    /// Returns the member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the number, integers converted to `f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(n) => Some(*n as f64),
+            Value::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Value {
+        Value::Integer(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(String::from(s))
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Serializes compactly, without whitespace. Non-finite floats, which JSON can't represent,
+/// are written as `null`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Float(x) if x.is_finite() => write!(f, "{:?}", x),
+            Value::Float(_) => f.write_str("null"),
+            Value::String(s) => write_string(f, s),
+            Value::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self) -> JsonError {
+        JsonError { offset: self.pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &[u8]) -> JsonResult<()> {
+        if self.text[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> JsonResult<Value> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'n') => self.expect(b"null").map(|_| Value::Null),
+            Some(b't') => self.expect(b"true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect(b"false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b'[') if depth < MAX_DEPTH => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(values));
+                        }
+                        _ => return Err(self.error()),
+                    }
+                }
+            }
+            Some(b'{') if depth < MAX_DEPTH => {
+                self.pos += 1;
+                let mut members: Vec<(String, Value)> = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.whitespace();
+                    let start = self.pos;
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error());
+                    }
+                    let key = self.string()?;
+                    if members.iter().any(|(k, _)| *k == key) {
+                        return Err(JsonError { offset: start });
+                    }
+                    self.whitespace();
+                    self.expect(b":")?;
+                    let value = self.value(depth + 1)?;
+                    members.push((key, value));
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(members));
+                        }
+                        _ => return Err(self.error()),
+                    }
+                }
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn number(&mut self) -> JsonResult<Value> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let int_start = self.pos;
+        let int_len = self.digits();
+        if int_len == 0 || (int_len > 1 && self.text[int_start] == b'0') {
+            return Err(JsonError { offset: int_start });
+        }
+        let mut integer = true;
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            integer = false;
+            if self.digits() == 0 {
+                return Err(self.error());
+            }
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            integer = false;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error());
+            }
+        }
+        // Only ASCII digits and signs were consumed.
+        let text = core::str::from_utf8(&self.text[start..self.pos]).unwrap();
+        if integer {
+            if let Ok(n) = text.parse::<i64>() {
+                return Ok(Value::Integer(n));
+            }
+        }
+        match text.parse::<f64>() {
+            Ok(x) if x.is_finite() => Ok(Value::Float(x)),
+            _ => Err(JsonError { offset: start }),
+        }
+    }
+
+    fn hex4(&mut self) -> JsonResult<u32> {
+        let digits = self.text.get(self.pos..self.pos + 4).ok_or_else(|| self.error())?;
+        let mut value = 0;
+        for &d in digits {
+            let digit = match d {
+                b'0'..=b'9' => d - b'0',
+                b'a'..=b'f' => d - b'a' + 10,
+                b'A'..=b'F' => d - b'A' + 10,
+                _ => return Err(self.error()),
+            };
+            value = value << 4 | digit as u32;
+        }
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> JsonResult<String> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let b = self.peek().ok_or_else(|| self.error())?;
+            match b {
+                b'"' => {
+                    self.pos += 1;
+                    break;
+                }
+                b'\\' => {
+                    self.pos += 1;
+                    let escape = self.peek().ok_or_else(|| self.error())?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let escape_start = self.pos - 2;
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code) {
+                                self.expect(b"\\u")?;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(JsonError { offset: escape_start });
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(code).ok_or(JsonError { offset: escape_start })?
+                        }
+                        _ => return Err(JsonError { offset: self.pos - 1 }),
+                    };
+                    let mut buf = [0_u8; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                0x00..=0x1f => return Err(self.error()),
+                _ => {
+                    bytes.push(b);
+                    self.pos += 1;
+                }
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| JsonError { offset: start })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn parse() {
+        let value = Value::parse(br#" {"a": [1, -2.5e3, "\ud83d\ude00\n", true, null], "b": {}} "#)
+            .unwrap();
+        let a = value.get("a").and_then(Value::as_array).unwrap();
+        assert_eq!(a[0].as_i64(), Some(1));
+        assert_eq!(a[1].as_f64(), Some(-2500.0));
+        assert_eq!(a[2].as_str(), Some("\u{1f600}\n"));
+        assert_eq!(a[3].as_bool(), Some(true));
+        assert_eq!(a[4], Value::Null);
+        assert_eq!(value.get("b").and_then(Value::as_object), Some(&[][..]));
+        assert_eq!(Value::parse(value.to_string().as_bytes()), Ok(value));
+        assert_eq!(Value::parse(b"9223372036854775808"), Ok(Value::Float(9223372036854775808.0)));
+    }
+
+    #[test]
+    fn rejections() {
+        let cases: [(&[u8], usize); 14] = [
+            (b"", 0),
+            (b" ", 1),
+            (b"1 2", 2),
+            (b"[1,]", 3),
+            (b"[1 2]", 3),
+            (br#"{"a":1,"a":2}"#, 7),
+            (br#"{a:1}"#, 1),
+            (b"01", 0),
+            (b"-", 1),
+            (b"1.", 2),
+            (b"1e999", 0),
+            (b"nul", 0),
+            (b"\"a\x01\"", 2),
+            (b"\"\xff\"", 0),
+        ];
+        for (text, offset) in cases {
+            assert_eq!(Value::parse(text), Err(JsonError { offset }), "{:?}", text);
+        }
+        // Lone surrogates and unknown escapes.
+        assert_eq!(Value::parse(br#""\ud800""#), Err(JsonError { offset: 7 }));
+        assert_eq!(Value::parse(br#""\ud800A""#), Err(JsonError { offset: 7 }));
+        assert_eq!(Value::parse(br#""\ud800\u0041""#), Err(JsonError { offset: 1 }));
+        assert_eq!(Value::parse(br#""\udc00""#), Err(JsonError { offset: 1 }));
+        assert_eq!(Value::parse(br#""\x""#), Err(JsonError { offset: 2 }));
+    }
+
+    #[test]
+    fn depth() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Value::parse(nested(MAX_DEPTH).as_bytes()).is_ok());
+        assert_eq!(
+            Value::parse(nested(MAX_DEPTH + 1).as_bytes()),
+            Err(JsonError { offset: MAX_DEPTH })
+        );
+    }
+}
@@ -0,0 +1,70 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! # Trusted TLS Library
+//!
+//! The library provides attested TLS (RA-TLS) on top of the Intel sgx_ttls library: certificates
+//! carrying a DCAP quote over their key, and the verification of such certificates presented by a
+//! peer, along with session ticket keys letting clients resume sessions without a new handshake
+//! and attestation, and a small HTTP/1.1 client and WebSocket server to use over attested
+//! channels. The [`pki`] module issues and validates the X.509 certificates of other
+//! channels, and of enclaves enrolled into a certificate authority, and [`jose`] and [`cose`]
+//! sign and verify tokens and messages with the same keys, in JSON and CBOR. The [`dcap`]
+//! module produces DCAP quotes of the enclave bundled with the collateral to verify them, and
+//! verifies such bundles of other enclaves without leaving the enclave. A [`policy::Policy`]
+//! decides which attested enclaves are trusted, in both quotes and RA-TLS channels, and the
+//! [`cache`] module keeps evidence and its verification for a while, so as not to quote and
+//! verify on every connection. The [`pccs`] module fetches the collateral itself, from the PCS
+//! or a PCCS over pinned TLS, and keeps it sealed. A [`report_data::ReportDataBuilder`] binds
+//! the claims of the application, such as a key or a nonce, to the report data of quotes.
+//! The [`tpm`] module co-signs evidence with a TPM 2.0 quote of the host, and verifies both.
+//! A verifier mints [`eat`] tokens of the enclaves it attested, for other services to trust.
+//! The [`maa`] module has Microsoft Azure Attestation verify quotes instead, where it's
+//! mandated. A [`gate::AttestationGate`] keeps the attested peers of sessions, and
+//! [`attested_ecall!`] refuses the ecalls of sessions without one.
+//!
+
+#![no_std]
+#![cfg_attr(target_env = "sgx", feature(rustc_private))]
+#![allow(non_camel_case_types)]
+
+#[macro_use]
+extern crate alloc;
+
+extern crate sgx_tcrypto;
+extern crate sgx_tse;
+extern crate sgx_types;
+
+pub mod cache;
+pub mod cbor;
+pub mod cose;
+pub mod dcap;
+mod der;
+pub mod eat;
+pub mod gate;
+pub mod http;
+pub mod jose;
+pub mod json;
+pub mod maa;
+pub mod pccs;
+pub mod pki;
+pub mod policy;
+pub mod ra_tls;
+pub mod report_data;
+pub mod ticket;
+pub mod tpm;
+pub mod websocket;
@@ -0,0 +1,356 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Verification of quotes by Microsoft Azure Attestation.
+//!
+//! Deployments which mandate MAA rather than verifying quotes locally have an MAA instance
+//! verify the quote, along with the runtime data its report data binds, and trust the token
+//! it returns. A [`MaaClient`] submits quotes to the `SgxEnclave` attestation API of the
+//! instance and verifies the token with the signing keys of the instance, which it pins:
+//!
+//! ```ignore
+//! let mut maa = MaaClient::new("contoso.eus.attest.azure.net", clock, connect)
+//!     .signing_key(MAA_KEY_ID, maa_key);
+//! let attested = maa.attest(bundle.quote_bytes(), Some(&runtime_data))?;
+//! attested.check(&policy)?;
+//! ```
+//!
+//! A token received from elsewhere is verified with a [`MaaVerifier`] alone. The claims of the
+//! token are mapped into [`EnclaveClaims`], as [`eat`](crate::eat) tokens are:
+//!
+//! | MAA claim                | field                                                        |
+//! |--------------------------|--------------------------------------------------------------|
+//! | `x-ms-sgx-mrenclave`     | [`mr_enclave`](EnclaveClaims::mr_enclave)                    |
+//! | `x-ms-sgx-mrsigner`      | [`mr_signer`](EnclaveClaims::mr_signer)                      |
+//! | `x-ms-sgx-product-id`    | [`isv_prod_id`](EnclaveClaims::isv_prod_id)                  |
+//! | `x-ms-sgx-svn`           | [`isv_svn`](EnclaveClaims::isv_svn)                          |
+//! | `x-ms-sgx-is-debuggable` | [`debug`](EnclaveClaims::debug)                              |
+//! | `x-ms-sgx-report-data`   | [`report_data`](EnclaveClaims::report_data)                  |
+//! | `x-ms-sgx-ehd`           | [`runtime_data`](MaaAttestation::runtime_data)               |
+//!
+//! MAA doesn't report the TCB status of the platform, which the attestation policy of the
+//! instance decides on: the status is `SGX_QL_QV_RESULT_UNSPECIFIED`, with no advisories, and
+//! [`MaaAttestation::check`] only checks the enclave against a [`Policy`].
+//!
+//! The token is what authenticates the response, so the TLS session `connect` opens only
+//! needs to be one to the instance as far as the confidentiality of the request goes.
+
+use crate::dcap;
+use crate::eat::EnclaveClaims;
+use crate::http::{self, Limits, Request, Transport};
+use crate::jose::{self, base64url, base64url_decode, JoseError, Validation};
+use crate::json::Value;
+use crate::pki::PublicKey;
+use crate::policy::{Policy, PolicyResult};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
+use sgx_tcrypto::hash::{Hash, Sha256};
+use sgx_types::*;
+
+/// The version of the attestation API of MAA requests are made with.
+pub const API_VERSION: &str = "2022-08-01";
+
+/// An error attesting with MAA or verifying its tokens.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MaaError {
+    /// The instance couldn't be reached, or refused the request: `SGX_ERROR_NETWORK_FAILURE`
+    /// if the exchange failed, and otherwise the status of the response as the
+    /// [`pccs`](crate::pccs) client maps it; MAA answers 400 for a quote it doesn't trust.
+    Service(sgx_status_t),
+    /// The response isn't a token, or the token lacks the claims of an SGX enclave.
+    Malformed,
+    /// The token is signed by a key which isn't pinned.
+    UnknownKey,
+    /// The token doesn't verify, or isn't valid at the time of the verification.
+    Token(JoseError),
+    /// The token isn't for the runtime data sent, or its report data doesn't bind it.
+    RuntimeDataMismatch,
+}
+
+impl fmt::Display for MaaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaaError::Service(status) => write!(f, "attestation service failed: {}", status),
+            MaaError::Malformed => f.write_str("malformed attestation token"),
+            MaaError::UnknownKey => f.write_str("token signed by an unknown key"),
+            MaaError::Token(error) => error.fmt(f),
+            MaaError::RuntimeDataMismatch => f.write_str("token not for the runtime data"),
+        }
+    }
+}
+
+impl From<JoseError> for MaaError {
+    fn from(error: JoseError) -> MaaError {
+        MaaError::Token(error)
+    }
+}
+
+pub type MaaResult<T> = Result<T, MaaError>;
+
+/// What a verified MAA token attests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MaaAttestation {
+    pub issuer: String,
+    /// When the token was issued, in seconds since the Unix epoch.
+    pub issued_at: time_t,
+    pub expires_at: time_t,
+    pub enclave: EnclaveClaims,
+    /// The runtime data the report data binds, if the quote was submitted with some.
+    pub runtime_data: Option<Vec<u8>>,
+}
+
+impl MaaAttestation {
+    /// Checks that `policy` trusts the enclave, as [`Policy::check_enclave`] does.
+    pub fn check(&self, policy: &Policy) -> PolicyResult<()> {
+        let mut body = sgx_report_body_t::default();
+        body.mr_enclave.m = self.enclave.mr_enclave;
+        body.mr_signer.m = self.enclave.mr_signer;
+        body.isv_prod_id = self.enclave.isv_prod_id;
+        body.isv_svn = self.enclave.isv_svn;
+        body.attributes.flags = SGX_FLAGS_INITTED;
+        if self.enclave.debug {
+            body.attributes.flags |= SGX_FLAGS_DEBUG;
+        }
+        body.report_data.d = self.enclave.report_data;
+        policy.check_enclave(&body)
+    }
+}
+
+/// Verifies the tokens of an MAA instance with its pinned signing keys.
+#[derive(Clone, Debug)]
+pub struct MaaVerifier {
+    issuer: String,
+    keys: Vec<(String, PublicKey)>,
+    leeway: time_t,
+}
+
+impl MaaVerifier {
+    /// Creates a verifier of the tokens issued by `issuer`, the URL of the instance, e.g.
+    /// `https://contoso.eus.attest.azure.net`, trusting no key yet.
+    pub fn new(issuer: &str) -> MaaVerifier {
+        MaaVerifier { issuer: issuer.to_string(), keys: Vec::new(), leeway: 0 }
+    }
+
+    /// Trusts the signing key `key`, named `key_id` in the header of tokens, replacing any key
+    /// of that name.
+    ///
+    /// The keys are those of the certificates the instance publishes at `/certs`, which it
+    /// rotates: pin the next ones before the instance moves to them.
+    pub fn signing_key(mut self, key_id: &str, key: PublicKey) -> MaaVerifier {
+        self.keys.retain(|(pinned, _)| pinned != key_id);
+        self.keys.push((key_id.to_string(), key));
+        self
+    }
+
+    /// Allows `leeway` seconds of clock skew between the instance and the enclave.
+    pub fn leeway(mut self, leeway: time_t) -> MaaVerifier {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Verifies `token` at the trusted time `now`, and returns what it attests.
+    pub fn verify(&self, token: &str, now: time_t) -> MaaResult<MaaAttestation> {
+        let key_id = jose::key_id(token)?.ok_or(MaaError::UnknownKey)?;
+        let (_, key) =
+            self.keys.iter().find(|(pinned, _)| *pinned == key_id).ok_or(MaaError::UnknownKey)?;
+        let validation = Validation::new(now).issuer(&self.issuer).leeway(self.leeway);
+        let claims = jose::verify_token(token, key, &validation)?;
+
+        let get = |name: &str| claims.get(name).ok_or(MaaError::Malformed);
+        let text = |name: &str| get(name)?.as_str().ok_or(MaaError::Malformed);
+        let hex = |name: &str| dcap::hex_decode(text(name)?).ok_or(MaaError::Malformed);
+        let integer = |name: &str| {
+            let integer = get(name)?.as_i64().ok_or(MaaError::Malformed)?;
+            integer.try_into().map_err(|_| MaaError::Malformed)
+        };
+        if text("x-ms-attestation-type")? != "sgx" {
+            return Err(MaaError::Malformed);
+        }
+        let runtime_data = match claims.get("x-ms-sgx-ehd") {
+            Some(_) => Some(base64url_decode(text("x-ms-sgx-ehd")?)?),
+            None => None,
+        };
+        let enclave = EnclaveClaims {
+            mr_enclave: array(&hex("x-ms-sgx-mrenclave")?)?,
+            mr_signer: array(&hex("x-ms-sgx-mrsigner")?)?,
+            isv_prod_id: integer("x-ms-sgx-product-id")?,
+            isv_svn: integer("x-ms-sgx-svn")?,
+            debug: get("x-ms-sgx-is-debuggable")?.as_bool().ok_or(MaaError::Malformed)?,
+            report_data: array(&hex("x-ms-sgx-report-data")?)?,
+            tcb_status: sgx_ql_qv_result_t::SGX_QL_QV_RESULT_UNSPECIFIED,
+            advisory_ids: Vec::new(),
+        };
+        let time = |name: &str| get(name)?.as_i64().ok_or(MaaError::Malformed);
+        Ok(MaaAttestation {
+            issuer: self.issuer.clone(),
+            issued_at: time("iat")?,
+            expires_at: time("exp")?,
+            enclave,
+            runtime_data,
+        })
+    }
+}
+
+/// A client of the attestation API of an MAA instance.
+///
+/// The TLS stack is the application's: `connect` opens a session to the instance, which is
+/// kept alive across requests, and reopened once the instance closes it or a request fails.
+/// `clock` returns the trusted time, in seconds since the Unix epoch.
+pub struct MaaClient<T, F, K>
+where
+    T: Transport,
+    F: FnMut() -> SgxResult<T>,
+    K: FnMut() -> SgxResult<time_t>,
+{
+    host: String,
+    connect: F,
+    clock: K,
+    verifier: MaaVerifier,
+    authorization: Option<String>,
+    limits: Limits,
+    session: Option<T>,
+}
+
+impl<T, F, K> MaaClient<T, F, K>
+where
+    T: Transport,
+    F: FnMut() -> SgxResult<T>,
+    K: FnMut() -> SgxResult<time_t>,
+{
+    /// A client of the instance at `host`, whose tokens are issued by `https://` and `host`.
+    pub fn new(host: &str, clock: K, connect: F) -> Self {
+        MaaClient {
+            host: host.to_string(),
+            connect,
+            clock,
+            verifier: MaaVerifier::new(&format!("https://{}", host)),
+            authorization: None,
+            limits: Limits { max_body_bytes: 64 * 1024, ..Limits::default() },
+            session: None,
+        }
+    }
+
+    /// Trusts the signing key `key` of the instance, as [`MaaVerifier::signing_key`] does.
+    pub fn signing_key(mut self, key_id: &str, key: PublicKey) -> Self {
+        self.verifier = self.verifier.signing_key(key_id, key);
+        self
+    }
+
+    /// Sends the Azure AD access token `token` along with requests, for instances whose
+    /// attestation API requires authentication.
+    pub fn authorization(mut self, token: &str) -> Self {
+        self.authorization = Some(format!("Bearer {}", token));
+        self
+    }
+
+    /// Sets the bounds on the responses of the instance.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the verifier of the tokens of the instance.
+    pub fn verifier(&self) -> &MaaVerifier {
+        &self.verifier
+    }
+
+    /// Has the instance attest the SGX `quote`, whose report data starts with the SHA-256 of
+    /// `runtime_data` if given, and returns what its token attests.
+    pub fn attest(
+        &mut self,
+        quote: &[u8],
+        runtime_data: Option<&[u8]>,
+    ) -> MaaResult<MaaAttestation> {
+        let mut encoded = String::new();
+        base64url(&mut encoded, quote);
+        let mut members = vec![(String::from("quote"), Value::from(encoded))];
+        if let Some(data) = runtime_data {
+            let mut encoded = String::new();
+            base64url(&mut encoded, data);
+            let data = vec![
+                (String::from("data"), Value::from(encoded)),
+                (String::from("dataType"), Value::from("Binary")),
+            ];
+            members.push((String::from("runtimeData"), Value::Object(data)));
+        }
+        let body = Value::Object(members).to_string().into_bytes();
+        let response =
+            self.post(&format!("/attest/SgxEnclave?api-version={}", API_VERSION), body)?;
+
+        let token = match Value::parse(response.body()) {
+            Ok(value) => value.get("token").and_then(Value::as_str).map(String::from),
+            Err(_) => None,
+        };
+        let now = (self.clock)().map_err(MaaError::Service)?;
+        let attested = self.verifier.verify(&token.ok_or(MaaError::Malformed)?, now)?;
+
+        if let Some(data) = runtime_data {
+            let mut digest = [0_u8; 32];
+            Sha256::digest_into(data, &mut digest);
+            if attested.runtime_data.as_deref() != Some(data)
+                || attested.enclave.report_data[..32] != digest
+            {
+                return Err(MaaError::RuntimeDataMismatch);
+            }
+        }
+        Ok(attested)
+    }
+
+    /// Posts the JSON `body` to `target`, and returns the response if it succeeded.
+    fn post(&mut self, target: &str, body: Vec<u8>) -> MaaResult<http::Response> {
+        let invalid = |_| MaaError::Service(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        let mut request = Request::post::<()>(&self.host, target, body)
+            .and_then(|request| request.header("Content-Type", "application/json"))
+            .map_err(invalid)?;
+        if let Some(ref authorization) = self.authorization {
+            request = request.header::<()>("Authorization", authorization).map_err(invalid)?;
+        }
+
+        let limits = self.limits;
+        if self.session.is_none() {
+            self.session = Some((self.connect)().map_err(MaaError::Service)?);
+        }
+        let session =
+            self.session.as_mut().ok_or(MaaError::Service(sgx_status_t::SGX_ERROR_UNEXPECTED))?;
+        let response = match http::send(session, &request, &limits) {
+            Ok(response) => response,
+            Err(_) => {
+                self.session = None;
+                return Err(MaaError::Service(sgx_status_t::SGX_ERROR_NETWORK_FAILURE));
+            }
+        };
+        let close = response.header("connection");
+        if matches!(close, Some(value) if value.eq_ignore_ascii_case("close")) {
+            self.session = None;
+        }
+
+        let status = match response.status() {
+            200 => return Ok(response),
+            401 | 403 => sgx_status_t::SGX_ERROR_NO_PRIVILEGE,
+            404 => sgx_status_t::SGX_ERROR_INVALID_PARAMETER,
+            429 | 503 => sgx_status_t::SGX_ERROR_SERVICE_UNAVAILABLE,
+            _ => sgx_status_t::SGX_ERROR_UNEXPECTED,
+        };
+        Err(MaaError::Service(status))
+    }
+}
+
+fn array<const N: usize>(bytes: &[u8]) -> MaaResult<[u8; N]> {
+    bytes.try_into().map_err(|_| MaaError::Malformed)
+}
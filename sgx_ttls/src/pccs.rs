@@ -0,0 +1,532 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! A client of the Intel PCS, or of a PCCS, fetching collateral from inside the enclave.
+//!
+//! A [`PccsClient`] fetches the collateral of platforms, and the PCK certificates of platforms
+//! whose quotes don't carry them, over TLS to the PCS or to the PCCS of the deployment, and is
+//! the [`CollateralSource`] of a [`QuoteBuilder`](crate::dcap::QuoteBuilder). The host only
+//! relays the bytes of the TLS session: the client authenticates the server by the SHA-256 of
+//! the public key of its certificate, which the enclave pins, and the collateral is signed by
+//! Intel anyway, so a host can at most withhold it.
+//!
+//! Collateral is cached in memory and, sealed, in a [`CollateralStore`], such as files of the
+//! host, to survive restarts of the enclave. Cached collateral is refetched once it's
+//! `max_age` old, to pick up the TCB recoveries Intel publishes before the next update
+//! collateral announces, and never used past that next update; if refetching fails, collateral
+//! before its next update is used still. A host can replay older sealed collateral, but none
+//! past its next update.
+//!
+//! The client speaks version 4 of the API, under `/sgx/certification/v4`:
+//!
+//! | request                                     | response                                    |
+//! |---------------------------------------------|---------------------------------------------|
+//! | `GET /pckcrl?ca={ca}&encoding=der`          | the PCK CRL, its issuer chain in the        |
+//! |                                             | `SGX-PCK-CRL-Issuer-Chain` header           |
+//! | `GET /tcb?fmspc={fmspc}`                    | the TCB info, its issuer chain in           |
+//! |                                             | `TCB-Info-Issuer-Chain`                     |
+//! | `GET /qe/identity`                          | the QE identity, its issuer chain in        |
+//! |                                             | `SGX-Enclave-Identity-Issuer-Chain`         |
+//! | `GET /rootcacrl`                            | the CRL of the root CA, which only a PCCS   |
+//! |                                             | serves                                      |
+//! | `GET /pckcert?encrypted_ppid={ppid}`        | the PEM PCK certificate, its issuer chain   |
+//! | `&cpusvn={cpusvn}&pcesvn={pcesvn}`          | in `SGX-PCK-Certificate-Issuer-Chain`       |
+//! | `&pceid={pceid}`                            |                                             |
+//!
+//! with the FMSPC and the fields of the platform in uppercase hex, the PCE SVN little endian.
+//! Their statuses map to:
+//!
+//! | status     | error                             |
+//! |------------|-----------------------------------|
+//! | 200        | success                           |
+//! | 401, 403   | `SGX_ERROR_NO_PRIVILEGE`          |
+//! | 404        | `SGX_ERROR_INVALID_PARAMETER`     |
+//! | 429, 503   | `SGX_ERROR_SERVICE_UNAVAILABLE`   |
+//! | others     | `SGX_ERROR_UNEXPECTED`            |
+//!
+//! as [`DcapError::Collateral`]. A server not presenting a pinned key fails with
+//! `SGX_ERROR_INVALID_SIGNATURE`, and a failing session with `SGX_ERROR_NETWORK_FAILURE`.
+//!
+//! The cache of the collateral of an FMSPC is stored as `pccs-{fmspc}-{ca}`, the FMSPC in
+//! lowercase hex and the CA as [`PckCa::as_str`] names it, sealed to the signer of the enclave.
+//! The sealed data is the CBOR map:
+//!
+//! | key            | value                                                               |
+//! |----------------|---------------------------------------------------------------------|
+//! | `"version"`    | [`STORED_VERSION`]                                                  |
+//! | `"fmspc"`      | the FMSPC, as bytes                                                 |
+//! | `"ca"`         | the CA, as text                                                     |
+//! | `"fetched"`    | when the collateral was fetched, in seconds since the Unix epoch    |
+//! | `"collateral"` | the collateral, as in an evidence bundle                            |
+
+use crate::cbor::Value;
+use crate::dcap::PckInfo;
+use crate::dcap::{pem_certificates, Collateral, CollateralSource, DcapError, DcapResult, PckCa};
+use crate::http::{self, Limits, Request, Response, Transport};
+use crate::pki::Certificate;
+use crate::ticket::{seal_bytes, unseal_bytes};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+use sgx_tcrypto::hash::{Hash, Sha256};
+use sgx_types::*;
+
+/// The version of the collateral stored.
+pub const STORED_VERSION: u64 = 1;
+
+/// How long collateral is used before it's refetched, by default: a day.
+pub const DEFAULT_MAX_AGE: time_t = 24 * 3600;
+
+const API_PREFIX: &str = "/sgx/certification/v4";
+
+/// The version of `sgx_ql_qve_collateral_t` of the collateral of version 4 of the API.
+const COLLATERAL_VERSION: u32 = 3;
+
+/// Where sealed collateral is kept between restarts of the enclave.
+pub trait CollateralStore {
+    /// Returns the bytes last stored as `name`, if any.
+    fn load(&mut self, name: &str) -> SgxResult<Option<Vec<u8>>>;
+
+    /// Stores `sealed` as `name`, replacing what was stored.
+    fn store(&mut self, name: &str, sealed: &[u8]) -> SgxError;
+}
+
+/// No store, caching collateral in memory only.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoStore;
+
+impl CollateralStore for NoStore {
+    fn load(&mut self, _name: &str) -> SgxResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    fn store(&mut self, _name: &str, _sealed: &[u8]) -> SgxError {
+        Ok(())
+    }
+}
+
+impl<S: CollateralStore + ?Sized> CollateralStore for &mut S {
+    fn load(&mut self, name: &str) -> SgxResult<Option<Vec<u8>>> {
+        (**self).load(name)
+    }
+
+    fn store(&mut self, name: &str, sealed: &[u8]) -> SgxError {
+        (**self).store(name, sealed)
+    }
+}
+
+/// The platform a PCK certificate is requested for, as the PCE reports it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlatformId {
+    /// The PPID of the platform encrypted to the PCS, which a PCCS which already has the
+    /// certificates of the platform doesn't need.
+    pub encrypted_ppid: Vec<u8>,
+    pub cpu_svn: [u8; 16],
+    pub pce_svn: u16,
+    pub pce_id: [u8; 2],
+}
+
+/// A PCK certificate, with the chain of its issuer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PckCertificate {
+    /// The DER certificate.
+    pub certificate: Vec<u8>,
+    /// The DER certificates of the issuer then of the root CA.
+    pub issuer_chain: Vec<Vec<u8>>,
+}
+
+impl PckCertificate {
+    /// Returns what the certificate says of the platform.
+    pub fn info(&self) -> DcapResult<PckInfo> {
+        let issuer = self.issuer_chain.first().ok_or(DcapError::MalformedCertificate)?;
+        PckInfo::parse(&self.certificate, issuer)
+    }
+}
+
+struct Cached {
+    fmspc: [u8; 6],
+    ca: PckCa,
+    fetched: time_t,
+    next_update: time_t,
+    collateral: Collateral,
+}
+
+/// A client of the PCS or a PCCS.
+///
+/// The TLS stack is the application's: `connect` opens a session to the server and returns it
+/// with the DER certificate of the server, whose key is checked against the pins before any
+/// request is sent. Sessions are kept alive across requests, and reopened once the server
+/// closes them or a request fails. `clock` returns the trusted time, in seconds since the Unix
+/// epoch.
+///
+/// ```ignore
+/// let pccs = PccsClient::new("pccs.internal:8081", PCCS_KEY_SHA256, clock, connect)
+///     .store(HostFiles);
+/// let bundle = QuoteBuilder::new(pccs).report_data(report_data).build()?;
+/// ```
+pub struct PccsClient<T, F, K, S = NoStore>
+where
+    T: Transport,
+    F: FnMut() -> SgxResult<(T, Vec<u8>)>,
+    K: FnMut() -> SgxResult<time_t>,
+    S: CollateralStore,
+{
+    host: String,
+    pins: Vec<[u8; 32]>,
+    connect: F,
+    clock: K,
+    store: S,
+    api_key: Option<String>,
+    root_ca_crl: Option<Vec<u8>>,
+    max_age: time_t,
+    limits: Limits,
+    session: Option<T>,
+    cached: Vec<Cached>,
+}
+
+impl<T, F, K> PccsClient<T, F, K, NoStore>
+where
+    T: Transport,
+    F: FnMut() -> SgxResult<(T, Vec<u8>)>,
+    K: FnMut() -> SgxResult<time_t>,
+{
+    /// A client of the server at `host`, whose certificate has the public key with the SHA-256
+    /// `key_sha256`, of its DER `SubjectPublicKeyInfo`.
+    pub fn new(host: &str, key_sha256: [u8; 32], clock: K, connect: F) -> Self {
+        PccsClient {
+            host: host.to_string(),
+            pins: vec![key_sha256],
+            connect,
+            clock,
+            store: NoStore,
+            api_key: None,
+            root_ca_crl: None,
+            max_age: DEFAULT_MAX_AGE,
+            limits: Limits { max_body_bytes: 1024 * 1024, ..Limits::default() },
+            session: None,
+            cached: Vec::new(),
+        }
+    }
+}
+
+impl<T, F, K, S> PccsClient<T, F, K, S>
+where
+    T: Transport,
+    F: FnMut() -> SgxResult<(T, Vec<u8>)>,
+    K: FnMut() -> SgxResult<time_t>,
+    S: CollateralStore,
+{
+    /// Keeps sealed collateral in `store`.
+    pub fn store<S2: CollateralStore>(self, store: S2) -> PccsClient<T, F, K, S2> {
+        PccsClient {
+            host: self.host,
+            pins: self.pins,
+            connect: self.connect,
+            clock: self.clock,
+            store,
+            api_key: self.api_key,
+            root_ca_crl: self.root_ca_crl,
+            max_age: self.max_age,
+            limits: self.limits,
+            session: self.session,
+            cached: self.cached,
+        }
+    }
+
+    /// Also accepts a server key with the SHA-256 `key_sha256`, e.g. the next key of a server
+    /// about to rotate it.
+    pub fn pin(mut self, key_sha256: [u8; 32]) -> Self {
+        self.pins.push(key_sha256);
+        self
+    }
+
+    /// Sends the subscription key `api_key` of the PCS along with requests, in the
+    /// `Ocp-Apim-Subscription-Key` header.
+    pub fn api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    /// Uses the CRL `crl` of the root CA, DER or hex-encoded DER, rather than fetching it, for
+    /// the PCS, which doesn't serve it under the API.
+    pub fn root_ca_crl(mut self, crl: Vec<u8>) -> Self {
+        self.root_ca_crl = Some(crl);
+        self
+    }
+
+    /// Sets how long collateral is used before it's refetched, [`DEFAULT_MAX_AGE`] by default.
+    pub fn max_age(mut self, max_age: time_t) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Sets the bounds on the responses of the server.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Fetches the collateral of the platforms of `fmspc`, whose PCK certificates `ca` issues,
+    /// even if it's cached, and caches it.
+    pub fn refresh(&mut self, fmspc: &[u8; 6], ca: PckCa) -> DcapResult<Collateral> {
+        let now = (self.clock)().map_err(DcapError::Collateral)?;
+        let collateral = self.fetch(fmspc, ca)?;
+        let next_update = collateral.next_update()?;
+        if now >= next_update {
+            return Err(DcapError::CollateralExpired);
+        }
+
+        let name = store_name(fmspc, ca);
+        let value = Value::Map(vec![
+            (Value::from("version"), Value::Unsigned(STORED_VERSION)),
+            (Value::from("fmspc"), Value::Bytes(Vec::from(&fmspc[..]))),
+            (Value::from("ca"), Value::from(ca.as_str())),
+            (Value::from("fetched"), Value::Unsigned(now as u64)),
+            (Value::from("collateral"), collateral.to_value()),
+        ]);
+        // The collateral is fetched whether it's stored or not; a failing store only costs the
+        // next restart a fetch.
+        if let Ok(sealed) = seal_bytes(SGX_KEYPOLICY_MRSIGNER, &value.encode()) {
+            let _ = self.store.store(&name, &sealed);
+        }
+
+        self.cached.retain(|cached| cached.fmspc != *fmspc || cached.ca != ca);
+        let cached = Cached { fmspc: *fmspc, ca, fetched: now, next_update, collateral };
+        self.cached.push(cached);
+        Ok(self.cached[self.cached.len() - 1].collateral.clone())
+    }
+
+    /// Fetches the PCK certificate of `platform`.
+    pub fn pck_certificate(&mut self, platform: &PlatformId) -> DcapResult<PckCertificate> {
+        let mut target = String::from("/pckcert?encrypted_ppid=");
+        target.push_str(&hex(&platform.encrypted_ppid));
+        target.push_str("&cpusvn=");
+        target.push_str(&hex(&platform.cpu_svn));
+        target.push_str("&pcesvn=");
+        target.push_str(&hex(&platform.pce_svn.to_le_bytes()));
+        target.push_str("&pceid=");
+        target.push_str(&hex(&platform.pce_id));
+        let response = self.get(&target)?;
+
+        let issuer_chain = issuer_chain(&response, &["SGX-PCK-Certificate-Issuer-Chain"])?;
+        let mut certificate = pem_certificates(response.body())?;
+        if certificate.len() != 1 {
+            return Err(DcapError::MalformedCertificate);
+        }
+        let certificate = PckCertificate { certificate: certificate.remove(0), issuer_chain };
+        certificate.info()?;
+        Ok(certificate)
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Returns the collateral of `fmspc` and `ca` of the memory cache, or of the store, with
+    /// when it was fetched.
+    fn cached(&mut self, fmspc: &[u8; 6], ca: PckCa) -> Option<(time_t, time_t, Collateral)> {
+        let found = |cached: &Cached| cached.fmspc == *fmspc && cached.ca == ca;
+        if !self.cached.iter().any(found) {
+            let sealed = self.store.load(&store_name(fmspc, ca)).ok()??;
+            let cached = read_cached(&sealed, fmspc, ca)?;
+            self.cached.push(cached);
+        }
+        self.cached
+            .iter()
+            .find(|cached| found(cached))
+            .map(|cached| (cached.fetched, cached.next_update, cached.collateral.clone()))
+    }
+
+    fn fetch(&mut self, fmspc: &[u8; 6], ca: PckCa) -> DcapResult<Collateral> {
+        let target = format!("/pckcrl?ca={}&encoding=der", ca.as_str());
+        let response = self.get(&target)?;
+        let pck_crl_issuer_chain = pem_header(&response, &["SGX-PCK-CRL-Issuer-Chain"])?;
+        let pck_crl = response.into_body();
+
+        let response = self.get(&format!("/tcb?fmspc={}", hex(fmspc)))?;
+        let names = ["TCB-Info-Issuer-Chain", "SGX-TCB-Info-Issuer-Chain"];
+        let tcb_info_issuer_chain = pem_header(&response, &names)?;
+        let tcb_info = response.into_body();
+
+        let response = self.get("/qe/identity")?;
+        let names = ["SGX-Enclave-Identity-Issuer-Chain"];
+        let qe_identity_issuer_chain = pem_header(&response, &names)?;
+        let qe_identity = response.into_body();
+
+        let root_ca_crl = match self.root_ca_crl {
+            Some(ref crl) => crl.clone(),
+            None => self.get("/rootcacrl")?.into_body(),
+        };
+        Ok(Collateral {
+            version: COLLATERAL_VERSION,
+            tee_type: 0,
+            pck_crl_issuer_chain,
+            root_ca_crl,
+            pck_crl,
+            tcb_info_issuer_chain,
+            tcb_info,
+            qe_identity_issuer_chain,
+            qe_identity,
+        })
+    }
+
+    fn session(&mut self) -> SgxResult<&mut T> {
+        if self.session.is_none() {
+            let (session, certificate) = (self.connect)()?;
+            let key = Certificate::parse(&certificate)
+                .and_then(|certificate| certificate.public_key())
+                .map_err(|_| sgx_status_t::SGX_ERROR_INVALID_SIGNATURE)?;
+            let mut digest = [0_u8; 32];
+            Sha256::digest_into(&key.to_der(), &mut digest);
+            if !self.pins.contains(&digest) {
+                return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+            }
+            self.session = Some(session);
+        }
+        self.session.as_mut().ok_or(sgx_status_t::SGX_ERROR_UNEXPECTED)
+    }
+
+    /// Gets `target` under the prefix of the API, and returns the response if it succeeded.
+    fn get(&mut self, target: &str) -> DcapResult<Response> {
+        let target = format!("{}{}", API_PREFIX, target);
+        let mut request = Request::get::<()>(&self.host, &target)
+            .map_err(|_| DcapError::Collateral(sgx_status_t::SGX_ERROR_INVALID_PARAMETER))?;
+        if let Some(ref api_key) = self.api_key {
+            request = request
+                .header::<()>("Ocp-Apim-Subscription-Key", api_key)
+                .map_err(|_| DcapError::Collateral(sgx_status_t::SGX_ERROR_INVALID_PARAMETER))?;
+        }
+
+        let limits = self.limits;
+        let session = self.session().map_err(DcapError::Collateral)?;
+        let response = match http::send(session, &request, &limits) {
+            Ok(response) => response,
+            Err(_) => {
+                self.session = None;
+                return Err(DcapError::Collateral(sgx_status_t::SGX_ERROR_NETWORK_FAILURE));
+            }
+        };
+        let close = response.header("connection");
+        if matches!(close, Some(value) if value.eq_ignore_ascii_case("close")) {
+            self.session = None;
+        }
+
+        let status = match response.status() {
+            200 => return Ok(response),
+            401 | 403 => sgx_status_t::SGX_ERROR_NO_PRIVILEGE,
+            404 => sgx_status_t::SGX_ERROR_INVALID_PARAMETER,
+            429 | 503 => sgx_status_t::SGX_ERROR_SERVICE_UNAVAILABLE,
+            _ => sgx_status_t::SGX_ERROR_UNEXPECTED,
+        };
+        Err(DcapError::Collateral(status))
+    }
+}
+
+impl<T, F, K, S> CollateralSource for PccsClient<T, F, K, S>
+where
+    T: Transport,
+    F: FnMut() -> SgxResult<(T, Vec<u8>)>,
+    K: FnMut() -> SgxResult<time_t>,
+    S: CollateralStore,
+{
+    /// Returns the cached collateral of `fmspc` and `ca` if it's less than `max_age` old, and
+    /// otherwise fetches it, falling back to the cached collateral before its next update if
+    /// fetching fails.
+    fn collateral(&mut self, fmspc: &[u8; 6], ca: PckCa) -> DcapResult<Collateral> {
+        let now = (self.clock)().map_err(DcapError::Collateral)?;
+        let cached = self.cached(fmspc, ca).filter(|&(_, next_update, _)| now < next_update);
+        if let Some((fetched, _, ref collateral)) = cached {
+            if now >= fetched && now - fetched < self.max_age {
+                return Ok(collateral.clone());
+            }
+        }
+        match self.refresh(fmspc, ca) {
+            Ok(collateral) => Ok(collateral),
+            Err(error) => cached.map(|(_, _, collateral)| collateral).ok_or(error),
+        }
+    }
+}
+
+/// Reads collateral stored by [`PccsClient::refresh`], if it's that of `fmspc` and `ca`.
+fn read_cached(sealed: &[u8], fmspc: &[u8; 6], ca: PckCa) -> Option<Cached> {
+    let value = Value::decode(&unseal_bytes(sealed).ok()?).ok()?;
+    if value.get(&Value::from("version")) != Some(&Value::Unsigned(STORED_VERSION)) {
+        return None;
+    }
+    let stored_fmspc = value.get(&Value::from("fmspc")).and_then(Value::as_bytes)?;
+    let stored_ca = value.get(&Value::from("ca")).and_then(Value::as_text)?;
+    if stored_fmspc != fmspc || stored_ca != ca.as_str() {
+        return None;
+    }
+    let fetched = match value.get(&Value::from("fetched")) {
+        Some(&Value::Unsigned(fetched)) if fetched <= time_t::MAX as u64 => fetched as time_t,
+        _ => return None,
+    };
+    let collateral = Collateral::from_value(value.get(&Value::from("collateral"))?).ok()?;
+    let next_update = collateral.next_update().ok()?;
+    Some(Cached { fmspc: *fmspc, ca, fetched, next_update, collateral })
+}
+
+fn store_name(fmspc: &[u8; 6], ca: PckCa) -> String {
+    format!("pccs-{}-{}", hex(fmspc).to_ascii_lowercase(), ca.as_str())
+}
+
+/// Returns `bytes` in uppercase hex, as the PCS writes FMSPCs.
+fn hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        // Writing to a string doesn't fail.
+        let _ = write!(text, "{:02X}", byte);
+    }
+    text
+}
+
+/// Returns the PEM issuer chain of the first header of `names` in `response`, which the PCS
+/// percent-encodes.
+fn pem_header(response: &Response, names: &[&str]) -> DcapResult<Vec<u8>> {
+    let malformed = DcapError::Collateral(sgx_status_t::SGX_ERROR_UNEXPECTED);
+    let value = names.iter().find_map(|name| response.header(name)).ok_or(malformed)?;
+    let pem = percent_decode(value).ok_or(malformed)?;
+    if pem_certificates(&pem)?.is_empty() {
+        return Err(malformed);
+    }
+    Ok(pem)
+}
+
+/// Returns the DER certificates of the issuer chain of the first header of `names`.
+fn issuer_chain(response: &Response, names: &[&str]) -> DcapResult<Vec<Vec<u8>>> {
+    pem_certificates(&pem_header(response, names)?)
+}
+
+/// Decodes the percent-encoding of a URL component.
+fn percent_decode(text: &str) -> Option<Vec<u8>> {
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    let text = text.as_bytes();
+    let mut decoded = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        if text[i] == b'%' {
+            let pair = text.get(i + 1..i + 3)?;
+            decoded.push(digit(pair[0])? << 4 | digit(pair[1])?);
+            i += 3;
+        } else {
+            decoded.push(text[i]);
+            i += 1;
+        }
+    }
+    Some(decoded)
+}
@@ -0,0 +1,1831 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! X.509 certificates (RFC 5280) and PKCS #10 certificate requests (RFC 2986).
+//!
+//! [`CertificateBuilder`] issues certificates for keys held in the enclave, self-signed or
+//! signed by a certificate authority whose key the enclave holds, and requests for a corporate
+//! CA to sign; they carry subject alternative names, key usages, basic constraints, and custom
+//! extensions such as the quote of RA-TLS. Keys sign through [`SigningKey`], which the P-256
+//! keys of the Intel library ([`EcdsaP256Key`]) and the Ed25519 keys of sgx_tcrypto implement.
+//!
+//! [`Certificate`] parses a certificate without copying it, and [`verify_chain`] validates the
+//! chain a peer presents against trust anchors: the signatures, in ECDSA over P-256, Ed25519 or
+//! RSA PKCS #1 v1.5 as CAs commonly sign, validity at a trusted time, basic constraints and
+//! path lengths, critical extensions, and the name and usages of the leaf. Names are compared
+//! byte for byte rather than after the normalization of RFC 5280, which CAs issuing consistent
+//! names don't need. Name constraints aren't supported, so chains marking them critical, as
+//! they should, are rejected. [`Crl`] parses revocation lists, which the caller fetches and
+//! checks the chain against.
+
+use crate::der::{self, DerError, Reader};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
+use core::ops::BitOr;
+use core::ptr;
+use sgx_tcrypto::ed25519::{Ed25519PrivateKey, Ed25519PublicKey, Ed25519Signature};
+use sgx_tcrypto::hash::{Hash, Sha256};
+use sgx_tcrypto::rng::fill_random;
+use sgx_tcrypto::rsa::{RsaHash, RsaPublicKey};
+use sgx_tcrypto::SgxEccHandle;
+use sgx_types::*;
+
+/// OID 2.5.4.3 of the common name attribute, as the DER contents OIDs are given as here.
+pub const COMMON_NAME_OID: &[u8] = &[0x55, 0x04, 0x03];
+/// OID 2.5.4.6 of the country attribute.
+pub const COUNTRY_OID: &[u8] = &[0x55, 0x04, 0x06];
+/// OID 2.5.4.10 of the organization attribute.
+pub const ORGANIZATION_OID: &[u8] = &[0x55, 0x04, 0x0a];
+/// OID 2.5.4.11 of the organizational unit attribute.
+pub const ORGANIZATIONAL_UNIT_OID: &[u8] = &[0x55, 0x04, 0x0b];
+/// OID 1.3.6.1.5.5.7.3.1 of the extended key usage of TLS servers.
+pub const SERVER_AUTH_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01];
+/// OID 1.3.6.1.5.5.7.3.2 of the extended key usage of TLS clients.
+pub const CLIENT_AUTH_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x02];
+
+const SUBJECT_KEY_ID_OID: &[u8] = &[0x55, 0x1d, 0x0e];
+const KEY_USAGE_OID: &[u8] = &[0x55, 0x1d, 0x0f];
+const SUBJECT_ALT_NAME_OID: &[u8] = &[0x55, 0x1d, 0x11];
+const BASIC_CONSTRAINTS_OID: &[u8] = &[0x55, 0x1d, 0x13];
+const AUTHORITY_KEY_ID_OID: &[u8] = &[0x55, 0x1d, 0x23];
+const EXTENDED_KEY_USAGE_OID: &[u8] = &[0x55, 0x1d, 0x25];
+const ANY_EXTENDED_KEY_USAGE_OID: &[u8] = &[0x55, 0x1d, 0x25, 0x00];
+const EXTENSION_REQUEST_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e];
+
+const EC_PUBLIC_KEY_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const PRIME256V1_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const ED25519_OID: &[u8] = &[0x2b, 0x65, 0x70];
+const RSA_ENCRYPTION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const ECDSA_SHA256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const RSA_SHA256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const RSA_SHA384_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+
+/// The most certificates a chain may have, the leaf included.
+pub const MAX_CHAIN_LENGTH: usize = 8;
+
+/// The last second of the year 9999, the latest time certificates can encode.
+const MAX_TIME: time_t = 253_402_300_799;
+/// The first second of the year 0, the earliest.
+const MIN_TIME: time_t = -62_167_219_200;
+
+/// An error issuing, parsing or validating certificates.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PkiError {
+    /// Generating a key or signing failed.
+    Crypto(sgx_status_t),
+    /// A certificate or key isn't well-formed DER of what X.509 allows.
+    Malformed,
+    /// A certificate or request can't be issued as the builder describes it.
+    InvalidParameter,
+    /// The key or signature algorithm isn't supported.
+    UnsupportedAlgorithm,
+    /// A signature doesn't verify.
+    BadSignature,
+    /// A certificate of the chain has expired.
+    Expired,
+    /// A certificate of the chain isn't valid yet.
+    NotYetValid,
+    /// The chain doesn't lead to a trust anchor.
+    UnknownIssuer,
+    /// A certificate was issued by one that isn't a CA, or one whose path length it exceeds.
+    InvalidIssuer,
+    /// The chain has more than [`MAX_CHAIN_LENGTH`] certificates.
+    ChainTooLong,
+    /// A certificate has a critical extension which isn't understood.
+    UnknownCriticalExtension,
+    /// The leaf isn't valid for the name the peer was reached by.
+    NameMismatch,
+    /// The key usages of the leaf don't allow the intended use.
+    KeyUsage,
+}
+
+impl fmt::Display for PkiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PkiError::Crypto(status) => write!(f, "cryptographic operation failed: {}", status),
+            PkiError::Malformed => f.write_str("malformed certificate"),
+            PkiError::InvalidParameter => f.write_str("invalid certificate parameter"),
+            PkiError::UnsupportedAlgorithm => f.write_str("unsupported algorithm"),
+            PkiError::BadSignature => f.write_str("signature verification failed"),
+            PkiError::Expired => f.write_str("certificate expired"),
+            PkiError::NotYetValid => f.write_str("certificate not valid yet"),
+            PkiError::UnknownIssuer => f.write_str("certificate issued by unknown authority"),
+            PkiError::InvalidIssuer => f.write_str("certificate issued by invalid authority"),
+            PkiError::ChainTooLong => f.write_str("certificate chain too long"),
+            PkiError::UnknownCriticalExtension => f.write_str("unknown critical extension"),
+            PkiError::NameMismatch => f.write_str("certificate not valid for name"),
+            PkiError::KeyUsage => f.write_str("certificate not valid for usage"),
+        }
+    }
+}
+
+impl From<DerError> for PkiError {
+    fn from(_: DerError) -> PkiError {
+        PkiError::Malformed
+    }
+}
+
+pub type PkiResult<T> = Result<T, PkiError>;
+
+/// Overwrites `buf` with zeroes, in a way the compiler won't remove.
+fn zeroize(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
+/// Returns the DER contents of the OID of `arcs`, e.g. `&[1, 3, 6, 1, 4, 1, ...]` for an
+/// extension of a private enterprise number, failing with `InvalidParameter` unless it's valid.
+pub fn oid(arcs: &[u64]) -> PkiResult<Vec<u8>> {
+    let first = match arcs {
+        [a, b, ..] if *a < 2 && *b < 40 => a * 40 + b,
+        [2, b, ..] => b.checked_add(80).ok_or(PkiError::InvalidParameter)?,
+        _ => return Err(PkiError::InvalidParameter),
+    };
+    let mut encoded = Vec::new();
+    for mut arc in core::iter::once(first).chain(arcs[2..].iter().copied()) {
+        let mut bytes = [0_u8; 10];
+        let mut i = bytes.len();
+        loop {
+            i -= 1;
+            bytes[i] = (arc & 0x7f) as u8 | if i + 1 < bytes.len() { 0x80 } else { 0 };
+            arc >>= 7;
+            if arc == 0 {
+                break;
+            }
+        }
+        encoded.extend_from_slice(&bytes[i..]);
+    }
+    Ok(encoded)
+}
+
+/// Returns the number of days from 1970-01-01 to the given date of the proleptic Gregorian
+/// calendar.
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the year, month and day `days` after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Appends `time`, between `MIN_TIME` and `MAX_TIME`, as a UTCTime up to 2049 and as a
+/// GeneralizedTime otherwise, as RFC 5280 requires.
+fn write_time(out: &mut Vec<u8>, time: time_t) {
+    let (year, month, day) = civil_from_days(time.div_euclid(86_400));
+    let seconds = time.rem_euclid(86_400);
+    let utc = (1950..2050).contains(&year);
+    let mut digits = Vec::with_capacity(15);
+    let mut push = |value: i64, width: u32| {
+        for i in (0..width).rev() {
+            digits.push(b'0' + (value / 10_i64.pow(i) % 10) as u8);
+        }
+    };
+    if utc {
+        push(year % 100, 2);
+    } else {
+        push(year, 4);
+    }
+    for value in [month, day, seconds / 3600, seconds / 60 % 60, seconds % 60] {
+        push(value, 2);
+    }
+    digits.push(b'Z');
+    der::write(out, if utc { der::UTC_TIME } else { der::GENERALIZED_TIME }, &digits);
+}
+
+/// Returns the number written in ASCII `digits`.
+fn read_digits(digits: &[u8]) -> PkiResult<i64> {
+    digits.iter().try_fold(0, |value, &digit| match digit {
+        b'0'..=b'9' => Ok(value * 10 + (digit - b'0') as i64),
+        _ => Err(PkiError::Malformed),
+    })
+}
+
+/// Reads a UTCTime or GeneralizedTime, in the `Z` forms RFC 5280 allows.
+fn read_time(reader: &mut Reader<'_>) -> PkiResult<time_t> {
+    let (year, rest) = match reader.read_raw()? {
+        (der::UTC_TIME, _, value) if value.len() == 13 => {
+            let year = read_digits(&value[..2])?;
+            (if year >= 50 { 1900 + year } else { 2000 + year }, &value[2..])
+        }
+        (der::GENERALIZED_TIME, _, value) if value.len() == 15 => {
+            (read_digits(&value[..4])?, &value[4..])
+        }
+        _ => return Err(PkiError::Malformed),
+    };
+    if rest[10] != b'Z' {
+        return Err(PkiError::Malformed);
+    }
+    let month = read_digits(&rest[0..2])?;
+    let day = read_digits(&rest[2..4])?;
+    let hour = read_digits(&rest[4..6])?;
+    let minute = read_digits(&rest[6..8])?;
+    let second = read_digits(&rest[8..10])?;
+    if !(1..=12).contains(&month) || hour > 23 || minute > 59 || second > 59 {
+        return Err(PkiError::Malformed);
+    }
+    let days = days_from_civil(year, month, day);
+    let next_month = if month == 12 {
+        days_from_civil(year + 1, 1, 1)
+    } else {
+        days_from_civil(year, month + 1, 1)
+    };
+    if day < 1 || days >= next_month {
+        return Err(PkiError::Malformed);
+    }
+    Ok(days * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Converts a DER `Ecdsa-Sig-Value` to the little-endian form of the Intel library.
+fn ecdsa_from_der(signature: &[u8]) -> PkiResult<sgx_ec256_signature_t> {
+    fn words(value: &[u8]) -> PkiResult<[u32; 8]> {
+        let value = der::read_uint(value)?;
+        if value.len() > 32 {
+            return Err(PkiError::Malformed);
+        }
+        let mut bytes = [0_u8; 32];
+        bytes[32 - value.len()..].copy_from_slice(value);
+        let mut words = [0_u32; 8];
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = u32::from_be_bytes(bytes[28 - 4 * i..32 - 4 * i].try_into().unwrap());
+        }
+        Ok(words)
+    }
+
+    let mut reader = Reader::new(signature);
+    let mut value = reader.read_nested(der::SEQUENCE)?;
+    reader.finish()?;
+    let x = words(value.read(der::INTEGER)?)?;
+    let y = words(value.read(der::INTEGER)?)?;
+    value.finish()?;
+    Ok(sgx_ec256_signature_t { x, y })
+}
+
+/// Converts a signature of the Intel library to a DER `Ecdsa-Sig-Value`.
+fn ecdsa_to_der(signature: &sgx_ec256_signature_t) -> Vec<u8> {
+    fn bytes(words: &[u32; 8]) -> [u8; 32] {
+        let mut bytes = [0_u8; 32];
+        for (i, word) in words.iter().enumerate() {
+            bytes[28 - 4 * i..32 - 4 * i].copy_from_slice(&word.to_be_bytes());
+        }
+        bytes
+    }
+
+    let mut der = Vec::with_capacity(72);
+    der::write_nested(&mut der, der::SEQUENCE, |value| {
+        der::write_uint(value, &bytes(&signature.x));
+        der::write_uint(value, &bytes(&signature.y));
+    });
+    der
+}
+
+/// Signs `message` with `key`, returning the signature in the fixed-length form of JOSE and
+/// COSE, the 32-byte big-endian r and s for ECDSA.
+pub(crate) fn sign_raw(key: &dyn SigningKey, message: &[u8]) -> PkiResult<Vec<u8>> {
+    let signature = key.sign(message)?;
+    if let PublicKey::P256(_) = key.public_key() {
+        let signature = ecdsa_from_der(&signature)?;
+        let mut raw = Vec::with_capacity(64);
+        for words in [&signature.x, &signature.y] {
+            for word in words.iter().rev() {
+                raw.extend_from_slice(&word.to_be_bytes());
+            }
+        }
+        return Ok(raw);
+    }
+    Ok(signature)
+}
+
+/// Verifies `signature` over `message` in the form [`sign_raw`] returns.
+pub(crate) fn verify_raw(key: &PublicKey, message: &[u8], signature: &[u8]) -> PkiResult<bool> {
+    match key {
+        PublicKey::P256(public) => {
+            if signature.len() != 64 {
+                return Ok(false);
+            }
+            let mut ecdsa = sgx_ec256_signature_t::default();
+            let halves = signature.chunks(32);
+            for (words, half) in [&mut ecdsa.x, &mut ecdsa.y].into_iter().zip(halves) {
+                for (word, bytes) in words.iter_mut().rev().zip(half.chunks(4)) {
+                    *word = u32::from_be_bytes(bytes.try_into().unwrap());
+                }
+            }
+            let ecc = SgxEccHandle::new();
+            ecc.open().map_err(PkiError::Crypto)?;
+            ecc.ecdsa_verify_slice(message, public, &ecdsa).map_err(PkiError::Crypto)
+        }
+        PublicKey::Ed25519(public) => match signature.try_into() {
+            Ok(signature) => Ok(public.verify(message, &Ed25519Signature(signature))),
+            Err(_) => Ok(false),
+        },
+        PublicKey::Rsa { .. } => Err(PkiError::UnsupportedAlgorithm),
+    }
+}
+
+/// Verifies an RSASSA-PKCS1-v1_5 `signature` over the SHA-256 of `message`.
+pub(crate) fn verify_rsa_sha256(
+    key: &PublicKey,
+    message: &[u8],
+    signature: &[u8],
+) -> PkiResult<bool> {
+    let (modulus, exponent) = match key {
+        PublicKey::Rsa { modulus, exponent } => (modulus, *exponent),
+        _ => return Err(PkiError::UnsupportedAlgorithm),
+    };
+    match modulus.len() {
+        256 => verify_rsa::<32>(modulus, exponent, RsaHash::Sha256, message, signature),
+        384 => verify_rsa::<48>(modulus, exponent, RsaHash::Sha256, message, signature),
+        512 => verify_rsa::<64>(modulus, exponent, RsaHash::Sha256, message, signature),
+        _ => Err(PkiError::UnsupportedAlgorithm),
+    }
+}
+
+/// The public key of a certificate.
+#[derive(Clone)]
+pub enum PublicKey {
+    /// A P-256 key, in the little-endian form of the Intel library.
+    P256(sgx_ec256_public_t),
+    Ed25519(Ed25519PublicKey),
+    /// An RSA key of 2048, 3072 or 4096 bits, with its big-endian modulus, which only verifies
+    /// signatures.
+    Rsa { modulus: Vec<u8>, exponent: u32 },
+}
+
+impl PublicKey {
+    /// Parses a DER `SubjectPublicKeyInfo`.
+    pub fn from_der(der: &[u8]) -> PkiResult<PublicKey> {
+        let mut reader = Reader::new(der);
+        let mut info = reader.read_nested(der::SEQUENCE)?;
+        reader.finish()?;
+        let mut algorithm = info.read_nested(der::SEQUENCE)?;
+        let key = der::read_bits(info.read(der::BIT_STRING)?)?;
+        info.finish()?;
+
+        let oid = algorithm.read(der::OID)?;
+        if oid == EC_PUBLIC_KEY_OID {
+            if algorithm.read_optional(der::OID)? != Some(PRIME256V1_OID) {
+                return Err(PkiError::UnsupportedAlgorithm);
+            }
+            algorithm.finish()?;
+            // Only uncompressed points, which is what certificates carry.
+            if key.first() != Some(&0x04) {
+                return Err(PkiError::UnsupportedAlgorithm);
+            }
+            if key.len() != 65 {
+                return Err(PkiError::Malformed);
+            }
+            let mut public = sgx_ec256_public_t::default();
+            public.gx.copy_from_slice(&key[1..33]);
+            public.gy.copy_from_slice(&key[33..]);
+            public.gx.reverse();
+            public.gy.reverse();
+            Ok(PublicKey::P256(public))
+        } else if oid == ED25519_OID {
+            algorithm.finish()?;
+            let key = key.try_into().map_err(|_| PkiError::Malformed)?;
+            let key = Ed25519PublicKey::from_bytes(key).map_err(|_| PkiError::Malformed)?;
+            Ok(PublicKey::Ed25519(key))
+        } else if oid == RSA_ENCRYPTION_OID {
+            if !algorithm.read(der::NULL)?.is_empty() {
+                return Err(PkiError::Malformed);
+            }
+            algorithm.finish()?;
+            let mut reader = Reader::new(key);
+            let mut key = reader.read_nested(der::SEQUENCE)?;
+            reader.finish()?;
+            let modulus = der::read_uint(key.read(der::INTEGER)?)?;
+            let exponent = der::read_uint(key.read(der::INTEGER)?)?;
+            key.finish()?;
+            if exponent.len() > 4 {
+                return Err(PkiError::UnsupportedAlgorithm);
+            }
+            let exponent = exponent.iter().fold(0_u32, |e, &b| e << 8 | b as u32);
+            Ok(PublicKey::Rsa { modulus: Vec::from(modulus), exponent })
+        } else {
+            Err(PkiError::UnsupportedAlgorithm)
+        }
+    }
+
+    /// Returns the DER `SubjectPublicKeyInfo`.
+    pub fn to_der(&self) -> Vec<u8> {
+        let mut der = Vec::new();
+        der::write_nested(&mut der, der::SEQUENCE, |info| {
+            der::write_nested(info, der::SEQUENCE, |algorithm| match self {
+                PublicKey::P256(_) => {
+                    der::write(algorithm, der::OID, EC_PUBLIC_KEY_OID);
+                    der::write(algorithm, der::OID, PRIME256V1_OID);
+                }
+                PublicKey::Ed25519(_) => der::write(algorithm, der::OID, ED25519_OID),
+                PublicKey::Rsa { .. } => {
+                    der::write(algorithm, der::OID, RSA_ENCRYPTION_OID);
+                    der::write(algorithm, der::NULL, &[]);
+                }
+            });
+            der::write_bits(info, &self.key_bits());
+        });
+        der
+    }
+
+    /// Returns the contents of the subject public key bit string.
+    fn key_bits(&self) -> Vec<u8> {
+        match self {
+            PublicKey::P256(public) => {
+                let (mut x, mut y) = (public.gx, public.gy);
+                x.reverse();
+                y.reverse();
+                let mut key = Vec::with_capacity(65);
+                key.push(0x04);
+                key.extend_from_slice(&x);
+                key.extend_from_slice(&y);
+                key
+            }
+            PublicKey::Ed25519(public) => Vec::from(&public.to_bytes()[..]),
+            PublicKey::Rsa { modulus, exponent } => {
+                let mut key = Vec::new();
+                der::write_nested(&mut key, der::SEQUENCE, |key| {
+                    der::write_uint(key, modulus);
+                    der::write_uint(key, &exponent.to_be_bytes());
+                });
+                key
+            }
+        }
+    }
+
+    /// Returns the key identifier of RFC 7093, the first 160 bits of the SHA-256 of the key.
+    fn key_id(&self) -> [u8; 20] {
+        let mut id = [0_u8; 20];
+        Sha256::digest_into(&self.key_bits(), &mut id);
+        id
+    }
+
+    /// Returns the DER `AlgorithmIdentifier` of signatures by the key.
+    fn signature_algorithm(&self) -> PkiResult<Vec<u8>> {
+        let oid = match self {
+            PublicKey::P256(_) => ECDSA_SHA256_OID,
+            PublicKey::Ed25519(_) => ED25519_OID,
+            PublicKey::Rsa { .. } => return Err(PkiError::UnsupportedAlgorithm),
+        };
+        let mut der = Vec::with_capacity(oid.len() + 4);
+        der::write_nested(&mut der, der::SEQUENCE, |algorithm| {
+            der::write(algorithm, der::OID, oid);
+        });
+        Ok(der)
+    }
+
+    /// Verifies `signature` over `message`, made with the algorithm of the DER contents
+    /// `algorithm` of an `AlgorithmIdentifier`.
+    fn verify(&self, algorithm: &[u8], message: &[u8], signature: &[u8]) -> PkiResult<()> {
+        let mut reader = Reader::new(algorithm);
+        let oid = reader.read(der::OID)?;
+        let valid = if oid == ECDSA_SHA256_OID {
+            reader.finish()?;
+            let public = match self {
+                PublicKey::P256(public) => public,
+                _ => return Err(PkiError::BadSignature),
+            };
+            let signature = ecdsa_from_der(signature).map_err(|_| PkiError::BadSignature)?;
+            let ecc = SgxEccHandle::new();
+            ecc.open().map_err(PkiError::Crypto)?;
+            ecc.ecdsa_verify_slice(message, public, &signature).map_err(PkiError::Crypto)?
+        } else if oid == ED25519_OID {
+            reader.finish()?;
+            let public = match self {
+                PublicKey::Ed25519(public) => public,
+                _ => return Err(PkiError::BadSignature),
+            };
+            let signature = signature.try_into().map_err(|_| PkiError::BadSignature)?;
+            public.verify(message, &Ed25519Signature(signature))
+        } else if oid == RSA_SHA256_OID || oid == RSA_SHA384_OID {
+            // The parameters are NULL, which some encoders omit.
+            if !matches!(reader.read_optional(der::NULL)?, None | Some([])) {
+                return Err(PkiError::Malformed);
+            }
+            reader.finish()?;
+            let (modulus, exponent) = match self {
+                PublicKey::Rsa { modulus, exponent } => (modulus.as_slice(), *exponent),
+                _ => return Err(PkiError::BadSignature),
+            };
+            let hash = if oid == RSA_SHA256_OID { RsaHash::Sha256 } else { RsaHash::Sha384 };
+            match modulus.len() {
+                256 => verify_rsa::<32>(modulus, exponent, hash, message, signature)?,
+                384 => verify_rsa::<48>(modulus, exponent, hash, message, signature)?,
+                512 => verify_rsa::<64>(modulus, exponent, hash, message, signature)?,
+                _ => return Err(PkiError::UnsupportedAlgorithm),
+            }
+        } else {
+            return Err(PkiError::UnsupportedAlgorithm);
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(PkiError::BadSignature)
+        }
+    }
+}
+
+fn verify_rsa<const L: usize>(
+    modulus: &[u8],
+    exponent: u32,
+    hash: RsaHash,
+    message: &[u8],
+    signature: &[u8],
+) -> PkiResult<bool> {
+    let key = RsaPublicKey::<L>::new(modulus, exponent)
+        .map_err(|_| PkiError::UnsupportedAlgorithm)?;
+    Ok(key.verify_pkcs1v15(hash, message, signature))
+}
+
+impl PartialEq for PublicKey {
+    fn eq(&self, other: &PublicKey) -> bool {
+        match (self, other) {
+            (PublicKey::P256(a), PublicKey::P256(b)) => a.gx == b.gx && a.gy == b.gy,
+            (PublicKey::Ed25519(a), PublicKey::Ed25519(b)) => a == b,
+            (
+                PublicKey::Rsa { modulus: n, exponent: e },
+                PublicKey::Rsa { modulus: m, exponent: f },
+            ) => n == m && e == f,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for PublicKey {}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublicKey::P256(_) => f.write_str("PublicKey::P256(..)"),
+            PublicKey::Ed25519(key) => f.debug_tuple("PublicKey::Ed25519").field(key).finish(),
+            PublicKey::Rsa { modulus, exponent } => f
+                .debug_struct("PublicKey::Rsa")
+                .field("bits", &(8 * modulus.len()))
+                .field("exponent", exponent)
+                .finish(),
+        }
+    }
+}
+
+/// A private key certificates and requests are signed with.
+pub trait SigningKey {
+    fn public_key(&self) -> PublicKey;
+
+    /// Signs `message`, returning the signature as X.509 encodes it: a DER `Ecdsa-Sig-Value`
+    /// for P-256 keys, the 64 bytes of RFC 8032 for Ed25519 keys.
+    fn sign(&self, message: &[u8]) -> PkiResult<Vec<u8>>;
+}
+
+impl SigningKey for Ed25519PrivateKey {
+    fn public_key(&self) -> PublicKey {
+        PublicKey::Ed25519(Ed25519PrivateKey::public_key(self))
+    }
+
+    fn sign(&self, message: &[u8]) -> PkiResult<Vec<u8>> {
+        Ok(Vec::from(&Ed25519PrivateKey::sign(self, message).to_bytes()[..]))
+    }
+}
+
+/// A P-256 key pair of the Intel library, signing with ECDSA over SHA-256.
+///
+/// The private key is zeroized on drop.
+pub struct EcdsaP256Key {
+    private: sgx_ec256_private_t,
+    public: sgx_ec256_public_t,
+}
+
+impl EcdsaP256Key {
+    /// Generates a key pair inside the enclave.
+    pub fn generate() -> PkiResult<EcdsaP256Key> {
+        let ecc = SgxEccHandle::new();
+        ecc.open().map_err(PkiError::Crypto)?;
+        let (private, public) = ecc.create_key_pair().map_err(PkiError::Crypto)?;
+        Ok(EcdsaP256Key { private, public })
+    }
+
+    /// Wraps a key pair the enclave already holds, e.g. one it unsealed.
+    pub fn from_key_pair(private: &sgx_ec256_private_t, public: &sgx_ec256_public_t) -> Self {
+        EcdsaP256Key { private: *private, public: *public }
+    }
+}
+
+impl SigningKey for EcdsaP256Key {
+    fn public_key(&self) -> PublicKey {
+        PublicKey::P256(self.public)
+    }
+
+    fn sign(&self, message: &[u8]) -> PkiResult<Vec<u8>> {
+        let ecc = SgxEccHandle::new();
+        ecc.open().map_err(PkiError::Crypto)?;
+        let signature = ecc.ecdsa_sign_slice(message, &self.private).map_err(PkiError::Crypto)?;
+        Ok(ecdsa_to_der(&signature))
+    }
+}
+
+impl Drop for EcdsaP256Key {
+    fn drop(&mut self) {
+        zeroize(&mut self.private.r);
+    }
+}
+
+impl fmt::Debug for EcdsaP256Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EcdsaP256Key").finish_non_exhaustive()
+    }
+}
+
+/// A distinguished name, built with one attribute per relative distinguished name, in order.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Name {
+    rdns: Vec<u8>,
+}
+
+impl Name {
+    pub fn new() -> Name {
+        Name::default()
+    }
+
+    pub fn common_name(self, value: &str) -> Name {
+        self.attribute(COMMON_NAME_OID, value)
+    }
+
+    /// Adds the country, a two-letter code such as `"US"`.
+    pub fn country(self, value: &str) -> Name {
+        self.push(COUNTRY_OID, der::PRINTABLE_STRING, value)
+    }
+
+    pub fn organization(self, value: &str) -> Name {
+        self.attribute(ORGANIZATION_OID, value)
+    }
+
+    pub fn organizational_unit(self, value: &str) -> Name {
+        self.attribute(ORGANIZATIONAL_UNIT_OID, value)
+    }
+
+    /// Adds the attribute of OID `oid`, e.g. one returned by [`oid`], as a UTF8String.
+    pub fn attribute(self, oid: &[u8], value: &str) -> Name {
+        self.push(oid, der::UTF8_STRING, value)
+    }
+
+    fn push(mut self, oid: &[u8], tag: u8, value: &str) -> Name {
+        der::write_nested(&mut self.rdns, der::SET, |rdn| {
+            der::write_nested(rdn, der::SEQUENCE, |attribute| {
+                der::write(attribute, der::OID, oid);
+                der::write(attribute, tag, value.as_bytes());
+            });
+        });
+        self
+    }
+
+    /// Returns the DER `Name`.
+    pub fn to_der(&self) -> Vec<u8> {
+        let mut der = Vec::with_capacity(self.rdns.len() + 4);
+        der::write(&mut der, der::SEQUENCE, &self.rdns);
+        der
+    }
+}
+
+/// A subject alternative name of a certificate.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SubjectAltName {
+    Dns(String),
+    Email(String),
+    Uri(String),
+    /// An IPv4 or IPv6 address, as its 4 or 16 bytes.
+    Ip(Vec<u8>),
+}
+
+impl SubjectAltName {
+    /// Appends the DER `GeneralName`, failing unless the name is ASCII or the address is 4 or
+    /// 16 bytes long.
+    fn write(&self, out: &mut Vec<u8>) -> PkiResult<()> {
+        let (tag, value) = match self {
+            SubjectAltName::Email(name) => (der::implicit(1), name.as_bytes()),
+            SubjectAltName::Dns(name) => (der::implicit(2), name.as_bytes()),
+            SubjectAltName::Uri(name) => (der::implicit(6), name.as_bytes()),
+            SubjectAltName::Ip(address) => {
+                if address.len() != 4 && address.len() != 16 {
+                    return Err(PkiError::InvalidParameter);
+                }
+                (der::implicit(7), address.as_slice())
+            }
+        };
+        if tag != der::implicit(7) && !value.is_ascii() {
+            return Err(PkiError::InvalidParameter);
+        }
+        der::write(out, tag, value);
+        Ok(())
+    }
+
+    /// Reads a DER `GeneralName`, or skips it if it's of another kind than these.
+    fn read(reader: &mut Reader<'_>) -> PkiResult<Option<SubjectAltName>> {
+        let (tag, _, value) = reader.read_raw()?;
+        let text = || match core::str::from_utf8(value) {
+            Ok(text) if text.is_ascii() => Ok(String::from(text)),
+            _ => Err(PkiError::Malformed),
+        };
+        Ok(match tag {
+            0x81 => Some(SubjectAltName::Email(text()?)),
+            0x82 => Some(SubjectAltName::Dns(text()?)),
+            0x86 => Some(SubjectAltName::Uri(text()?)),
+            0x87 if value.len() == 4 || value.len() == 16 => {
+                Some(SubjectAltName::Ip(Vec::from(value)))
+            }
+            0x87 => return Err(PkiError::Malformed),
+            _ => None,
+        })
+    }
+}
+
+/// The key usages a certificate allows, which combine with `|`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct KeyUsage(u16);
+
+impl KeyUsage {
+    pub const DIGITAL_SIGNATURE: KeyUsage = KeyUsage(1 << 0);
+    pub const NON_REPUDIATION: KeyUsage = KeyUsage(1 << 1);
+    pub const KEY_ENCIPHERMENT: KeyUsage = KeyUsage(1 << 2);
+    pub const DATA_ENCIPHERMENT: KeyUsage = KeyUsage(1 << 3);
+    pub const KEY_AGREEMENT: KeyUsage = KeyUsage(1 << 4);
+    pub const KEY_CERT_SIGN: KeyUsage = KeyUsage(1 << 5);
+    pub const CRL_SIGN: KeyUsage = KeyUsage(1 << 6);
+    pub const ENCIPHER_ONLY: KeyUsage = KeyUsage(1 << 7);
+    pub const DECIPHER_ONLY: KeyUsage = KeyUsage(1 << 8);
+
+    /// Returns whether all the usages of `other` are allowed.
+    pub fn contains(self, other: KeyUsage) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the contents of the DER BIT STRING, whose first bit is the first usage.
+    fn to_bits(self) -> Vec<u8> {
+        let bytes = self.0.reverse_bits().to_be_bytes();
+        let len = bytes.iter().rposition(|b| *b != 0).map(|i| i + 1).unwrap_or(0);
+        let unused = if len == 0 { 0 } else { bytes[len - 1].trailing_zeros() as u8 };
+        let mut bits = vec![unused];
+        bits.extend_from_slice(&bytes[..len]);
+        bits
+    }
+
+    fn from_bits(bits: &[u8]) -> PkiResult<KeyUsage> {
+        let (unused, bytes) = match bits {
+            [unused, bytes @ ..] if *unused < 8 && bytes.len() <= 2 => (*unused, bytes),
+            _ => return Err(PkiError::Malformed),
+        };
+        let last = bytes.last().copied().unwrap_or(0);
+        if (bytes.is_empty() && unused != 0) || last & ((1_u16 << unused) - 1) as u8 != 0 {
+            return Err(PkiError::Malformed);
+        }
+        let mut value = [0_u8; 2];
+        value[..bytes.len()].copy_from_slice(bytes);
+        Ok(KeyUsage(u16::from_be_bytes(value).reverse_bits()))
+    }
+}
+
+impl BitOr for KeyUsage {
+    type Output = KeyUsage;
+
+    fn bitor(self, other: KeyUsage) -> KeyUsage {
+        KeyUsage(self.0 | other.0)
+    }
+}
+
+/// An extension of a parsed certificate.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Extension<'a> {
+    /// The DER contents of the OID.
+    pub oid: &'a [u8],
+    pub critical: bool,
+    /// The DER value, the contents of the `extnValue` OCTET STRING.
+    pub value: &'a [u8],
+}
+
+/// Appends a DER `Extension`.
+fn write_extension(out: &mut Vec<u8>, oid: &[u8], critical: bool, value: &[u8]) {
+    der::write_nested(out, der::SEQUENCE, |extension| {
+        der::write(extension, der::OID, oid);
+        if critical {
+            der::write(extension, der::BOOLEAN, &[0xff]);
+        }
+        der::write(extension, der::OCTET_STRING, value);
+    });
+}
+
+/// The contents of a certificate or certificate request to issue.
+///
+/// Certificates are X.509 v3, with a subject key identifier, and an authority key identifier
+/// when they're issued by another certificate.
+#[derive(Clone, Debug)]
+pub struct CertificateBuilder {
+    subject: Name,
+    serial: Option<Vec<u8>>,
+    not_before: time_t,
+    not_after: time_t,
+    alt_names: Vec<SubjectAltName>,
+    key_usage: Option<KeyUsage>,
+    extended_key_usage: Vec<Vec<u8>>,
+    ca: Option<Option<u8>>,
+    extensions: Vec<(Vec<u8>, bool, Vec<u8>)>,
+}
+
+impl CertificateBuilder {
+    /// Starts a certificate of `subject`, valid from `not_before` to `not_after` included, in
+    /// seconds since the Unix epoch.
+    pub fn new(subject: Name, not_before: time_t, not_after: time_t) -> CertificateBuilder {
+        CertificateBuilder {
+            subject,
+            serial: None,
+            not_before,
+            not_after,
+            alt_names: Vec::new(),
+            key_usage: None,
+            extended_key_usage: Vec::new(),
+            ca: None,
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Sets the serial number, a positive big-endian integer of at most 20 bytes. Random 127-bit
+    /// serials are drawn otherwise.
+    pub fn serial(mut self, serial: &[u8]) -> CertificateBuilder {
+        self.serial = Some(Vec::from(serial));
+        self
+    }
+
+    pub fn alt_name(mut self, name: SubjectAltName) -> CertificateBuilder {
+        self.alt_names.push(name);
+        self
+    }
+
+    /// Restricts the key to `usage`, in a critical extension.
+    pub fn key_usage(mut self, usage: KeyUsage) -> CertificateBuilder {
+        self.key_usage = Some(usage);
+        self
+    }
+
+    /// Allows the key the extended usage `oid`, such as [`SERVER_AUTH_OID`], restricting it to
+    /// the extended usages added.
+    pub fn extended_key_usage(mut self, oid: &[u8]) -> CertificateBuilder {
+        self.extended_key_usage.push(Vec::from(oid));
+        self
+    }
+
+    /// Makes the certificate a CA's, allowed `path_len` levels of intermediate CAs below it, or
+    /// any number if `None`.
+    pub fn ca(mut self, path_len: Option<u8>) -> CertificateBuilder {
+        self.ca = Some(path_len);
+        self
+    }
+
+    /// Adds the extension `oid` with the DER `value`, e.g. evidence about the enclave for the
+    /// verifier. Critical extensions are rejected by verifiers which don't understand them.
+    pub fn extension(mut self, oid: &[u8], critical: bool, value: &[u8]) -> CertificateBuilder {
+        self.extensions.push((Vec::from(oid), critical, Vec::from(value)));
+        self
+    }
+
+    /// Returns the DER certificate of `key`, signed by itself.
+    pub fn self_signed(&self, key: &dyn SigningKey) -> PkiResult<Vec<u8>> {
+        let public = key.public_key();
+        self.sign(&public, &self.subject.to_der(), None, key)
+    }
+
+    /// Returns the DER certificate of `subject_key`, issued by the holder of `issuer_key` and
+    /// of the certificate `issuer`.
+    pub fn issue(
+        &self,
+        subject_key: &PublicKey,
+        issuer: &Certificate<'_>,
+        issuer_key: &dyn SigningKey,
+    ) -> PkiResult<Vec<u8>> {
+        if issuer_key.public_key() != issuer.public_key()? {
+            return Err(PkiError::InvalidParameter);
+        }
+        let authority_key_id = match issuer.extension(SUBJECT_KEY_ID_OID) {
+            Some(extension) => {
+                let mut reader = Reader::new(extension.value);
+                let id = reader.read(der::OCTET_STRING)?;
+                reader.finish()?;
+                Vec::from(id)
+            }
+            None => Vec::from(&issuer.public_key()?.key_id()[..]),
+        };
+        self.sign(subject_key, issuer.subject(), Some(&authority_key_id), issuer_key)
+    }
+
+    /// Returns a DER PKCS #10 request for a certificate of `key`, with the subject and
+    /// extensions of the builder. The serial and validity are left for the CA to choose.
+    pub fn request(&self, key: &dyn SigningKey) -> PkiResult<Vec<u8>> {
+        let public = key.public_key();
+        let algorithm = public.signature_algorithm()?;
+        let extensions = self.extensions(None, None)?;
+        let mut info = Vec::new();
+        der::write_nested(&mut info, der::SEQUENCE, |info| {
+            der::write_uint(info, &[0]);
+            info.extend_from_slice(&self.subject.to_der());
+            info.extend_from_slice(&public.to_der());
+            der::write_nested(info, der::explicit(0), |attributes| {
+                // Leaves out an empty SEQUENCE, which is two bytes.
+                if extensions.len() > 2 {
+                    der::write_nested(attributes, der::SEQUENCE, |attribute| {
+                        der::write(attribute, der::OID, EXTENSION_REQUEST_OID);
+                        der::write(attribute, der::SET, &extensions);
+                    });
+                }
+            });
+        });
+        signed(&info, &algorithm, key)
+    }
+
+    /// Returns the DER `Extensions` of a certificate or request.
+    fn extensions(
+        &self,
+        subject_key_id: Option<&[u8]>,
+        authority_key_id: Option<&[u8]>,
+    ) -> PkiResult<Vec<u8>> {
+        let mut oids: Vec<&[u8]> = Vec::new();
+        let mut list = Vec::new();
+        if let Some(path_len) = self.ca {
+            let mut value = Vec::new();
+            der::write_nested(&mut value, der::SEQUENCE, |constraints| {
+                der::write(constraints, der::BOOLEAN, &[0xff]);
+                if let Some(path_len) = path_len {
+                    der::write_uint(constraints, &[path_len]);
+                }
+            });
+            write_extension(&mut list, BASIC_CONSTRAINTS_OID, true, &value);
+            oids.push(BASIC_CONSTRAINTS_OID);
+        }
+        if let Some(usage) = self.key_usage {
+            let mut value = Vec::new();
+            der::write(&mut value, der::BIT_STRING, &usage.to_bits());
+            write_extension(&mut list, KEY_USAGE_OID, true, &value);
+            oids.push(KEY_USAGE_OID);
+        }
+        if !self.extended_key_usage.is_empty() {
+            let mut value = Vec::new();
+            der::write_nested(&mut value, der::SEQUENCE, |usages| {
+                for oid in &self.extended_key_usage {
+                    der::write(usages, der::OID, oid);
+                }
+            });
+            write_extension(&mut list, EXTENDED_KEY_USAGE_OID, false, &value);
+            oids.push(EXTENDED_KEY_USAGE_OID);
+        }
+        if !self.alt_names.is_empty() {
+            let mut names = Vec::new();
+            for name in &self.alt_names {
+                name.write(&mut names)?;
+            }
+            let mut value = Vec::new();
+            der::write(&mut value, der::SEQUENCE, &names);
+            // With an empty subject, the alternative names are the only names, and critical.
+            write_extension(&mut list, SUBJECT_ALT_NAME_OID, self.subject.rdns.is_empty(), &value);
+            oids.push(SUBJECT_ALT_NAME_OID);
+        }
+        if let Some(id) = subject_key_id {
+            let mut value = Vec::new();
+            der::write(&mut value, der::OCTET_STRING, id);
+            write_extension(&mut list, SUBJECT_KEY_ID_OID, false, &value);
+            oids.push(SUBJECT_KEY_ID_OID);
+        }
+        if let Some(id) = authority_key_id {
+            let mut value = Vec::new();
+            der::write_nested(&mut value, der::SEQUENCE, |authority| {
+                der::write(authority, der::implicit(0), id);
+            });
+            write_extension(&mut list, AUTHORITY_KEY_ID_OID, false, &value);
+            oids.push(AUTHORITY_KEY_ID_OID);
+        }
+        for (oid, critical, value) in &self.extensions {
+            if oid.is_empty() || oids.contains(&oid.as_slice()) {
+                return Err(PkiError::InvalidParameter);
+            }
+            write_extension(&mut list, oid, *critical, value);
+            oids.push(oid);
+        }
+        let mut extensions = Vec::with_capacity(list.len() + 4);
+        der::write(&mut extensions, der::SEQUENCE, &list);
+        Ok(extensions)
+    }
+
+    fn sign(
+        &self,
+        subject_key: &PublicKey,
+        issuer: &[u8],
+        authority_key_id: Option<&[u8]>,
+        key: &dyn SigningKey,
+    ) -> PkiResult<Vec<u8>> {
+        let algorithm = key.public_key().signature_algorithm()?;
+        let serial = match &self.serial {
+            Some(serial) => serial.clone(),
+            None => {
+                let mut serial = vec![0_u8; 16];
+                fill_random(&mut serial).map_err(PkiError::Crypto)?;
+                serial[0] &= 0x7f;
+                serial
+            }
+        };
+        let significant = serial.iter().skip_while(|b| **b == 0).count();
+        if significant == 0 || significant > 20 {
+            return Err(PkiError::InvalidParameter);
+        }
+        if self.not_before > self.not_after
+            || self.not_before < MIN_TIME
+            || self.not_after > MAX_TIME
+        {
+            return Err(PkiError::InvalidParameter);
+        }
+        let extensions = self.extensions(Some(&subject_key.key_id()), authority_key_id)?;
+
+        let mut tbs = Vec::new();
+        der::write_nested(&mut tbs, der::SEQUENCE, |tbs| {
+            der::write_nested(tbs, der::explicit(0), |version| der::write_uint(version, &[2]));
+            der::write_uint(tbs, &serial);
+            tbs.extend_from_slice(&algorithm);
+            tbs.extend_from_slice(issuer);
+            der::write_nested(tbs, der::SEQUENCE, |validity| {
+                write_time(validity, self.not_before);
+                write_time(validity, self.not_after);
+            });
+            tbs.extend_from_slice(&self.subject.to_der());
+            tbs.extend_from_slice(&subject_key.to_der());
+            der::write(tbs, der::explicit(3), &extensions);
+        });
+        signed(&tbs, &algorithm, key)
+    }
+}
+
+/// Returns the DER of the signed structure of `data`, a certificate or a request.
+fn signed(data: &[u8], algorithm: &[u8], key: &dyn SigningKey) -> PkiResult<Vec<u8>> {
+    let signature = key.sign(data)?;
+    let mut der = Vec::with_capacity(data.len() + algorithm.len() + signature.len() + 8);
+    der::write_nested(&mut der, der::SEQUENCE, |signed| {
+        signed.extend_from_slice(data);
+        signed.extend_from_slice(algorithm);
+        der::write_bits(signed, &signature);
+    });
+    Ok(der)
+}
+
+/// A parsed certificate, borrowing from its DER encoding.
+///
+/// Parsing checks the structure of the certificate and of the extensions it understands, not
+/// the signature, which [`verify_chain`] or [`Certificate::verify_signature`] does.
+#[derive(Clone, Debug)]
+pub struct Certificate<'a> {
+    der: &'a [u8],
+    tbs: &'a [u8],
+    serial: &'a [u8],
+    signature_algorithm: &'a [u8],
+    issuer: &'a [u8],
+    subject: &'a [u8],
+    not_before: time_t,
+    not_after: time_t,
+    public_key: &'a [u8],
+    extensions: Vec<Extension<'a>>,
+    signature: &'a [u8],
+    key_usage: Option<KeyUsage>,
+    extended_key_usage: Vec<&'a [u8]>,
+    alt_names: Vec<SubjectAltName>,
+    ca: bool,
+    path_len: Option<u8>,
+}
+
+impl<'a> Certificate<'a> {
+    /// Parses the DER certificate `der`.
+    pub fn parse(der: &'a [u8]) -> PkiResult<Certificate<'a>> {
+        let mut reader = Reader::new(der);
+        let mut signed = reader.read_nested(der::SEQUENCE)?;
+        reader.finish()?;
+        let (tag, tbs, contents) = signed.read_raw()?;
+        if tag != der::SEQUENCE {
+            return Err(PkiError::Malformed);
+        }
+        let signature_algorithm = signed.read(der::SEQUENCE)?;
+        let signature = der::read_bits(signed.read(der::BIT_STRING)?)?;
+        signed.finish()?;
+
+        let mut reader = Reader::new(contents);
+        // Version 1 is the default, which DER leaves out.
+        let version = match reader.read_optional(der::explicit(0))? {
+            Some(version) => {
+                let mut version = Reader::new(version);
+                let number = der::read_uint(version.read(der::INTEGER)?)?;
+                version.finish()?;
+                match number {
+                    [1] => 2,
+                    [2] => 3,
+                    _ => return Err(PkiError::Malformed),
+                }
+            }
+            None => 1,
+        };
+        let serial = der::read_uint(reader.read(der::INTEGER)?)?;
+        if reader.read(der::SEQUENCE)? != signature_algorithm {
+            return Err(PkiError::Malformed);
+        }
+        let issuer = match reader.read_raw()? {
+            (der::SEQUENCE, issuer, _) => issuer,
+            _ => return Err(PkiError::Malformed),
+        };
+        let mut validity = reader.read_nested(der::SEQUENCE)?;
+        let not_before = read_time(&mut validity)?;
+        let not_after = read_time(&mut validity)?;
+        validity.finish()?;
+        let subject = match reader.read_raw()? {
+            (der::SEQUENCE, subject, _) => subject,
+            _ => return Err(PkiError::Malformed),
+        };
+        let public_key = match reader.read_raw()? {
+            (der::SEQUENCE, public_key, _) => public_key,
+            _ => return Err(PkiError::Malformed),
+        };
+        if version >= 2 {
+            reader.read_optional(der::implicit(1))?;
+            reader.read_optional(der::implicit(2))?;
+        }
+        let mut extensions = Vec::new();
+        if version == 3 {
+            if let Some(list) = reader.read_optional(der::explicit(3))? {
+                let mut outer = Reader::new(list);
+                let mut list = outer.read_nested(der::SEQUENCE)?;
+                outer.finish()?;
+                if list.is_empty() {
+                    return Err(PkiError::Malformed);
+                }
+                while !list.is_empty() {
+                    let mut extension = list.read_nested(der::SEQUENCE)?;
+                    let oid = extension.read(der::OID)?;
+                    // FALSE is the default, which DER leaves out.
+                    let critical = match extension.read_optional(der::BOOLEAN)? {
+                        Some(critical) if der::read_bool(critical)? => true,
+                        Some(_) => return Err(PkiError::Malformed),
+                        None => false,
+                    };
+                    let value = extension.read(der::OCTET_STRING)?;
+                    extension.finish()?;
+                    if extensions.iter().any(|e: &Extension<'_>| e.oid == oid) {
+                        return Err(PkiError::Malformed);
+                    }
+                    extensions.push(Extension { oid, critical, value });
+                }
+            }
+        }
+        reader.finish()?;
+
+        let mut certificate = Certificate {
+            der,
+            tbs,
+            serial,
+            signature_algorithm,
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            public_key,
+            extensions,
+            signature,
+            key_usage: None,
+            extended_key_usage: Vec::new(),
+            alt_names: Vec::new(),
+            ca: false,
+            path_len: None,
+        };
+        certificate.parse_extensions()?;
+        Ok(certificate)
+    }
+
+    /// Parses the extensions the validation relies on.
+    fn parse_extensions(&mut self) -> PkiResult<()> {
+        for extension in self.extensions.iter() {
+            let mut reader = Reader::new(extension.value);
+            if extension.oid == KEY_USAGE_OID {
+                self.key_usage = Some(KeyUsage::from_bits(reader.read(der::BIT_STRING)?)?);
+            } else if extension.oid == EXTENDED_KEY_USAGE_OID {
+                let mut usages = reader.read_nested(der::SEQUENCE)?;
+                while !usages.is_empty() {
+                    self.extended_key_usage.push(usages.read(der::OID)?);
+                }
+                if self.extended_key_usage.is_empty() {
+                    return Err(PkiError::Malformed);
+                }
+            } else if extension.oid == SUBJECT_ALT_NAME_OID {
+                let mut names = reader.read_nested(der::SEQUENCE)?;
+                if names.is_empty() {
+                    return Err(PkiError::Malformed);
+                }
+                while !names.is_empty() {
+                    if let Some(name) = SubjectAltName::read(&mut names)? {
+                        self.alt_names.push(name);
+                    }
+                }
+            } else if extension.oid == BASIC_CONSTRAINTS_OID {
+                let mut constraints = reader.read_nested(der::SEQUENCE)?;
+                self.ca = match constraints.read_optional(der::BOOLEAN)? {
+                    Some(ca) if der::read_bool(ca)? => true,
+                    Some(_) => return Err(PkiError::Malformed),
+                    None => false,
+                };
+                if let Some(path_len) = constraints.read_optional(der::INTEGER)? {
+                    // Longer paths than a chain may be are as good as unlimited.
+                    let path_len = der::read_uint(path_len)?;
+                    self.path_len = match path_len {
+                        [] => Some(0),
+                        [n] => Some(*n),
+                        _ => Some(u8::MAX),
+                    };
+                }
+                constraints.finish()?;
+            } else {
+                continue;
+            }
+            reader.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the DER encoding.
+    pub fn der(&self) -> &'a [u8] {
+        self.der
+    }
+
+    /// Returns the big-endian serial number.
+    pub fn serial(&self) -> &'a [u8] {
+        self.serial
+    }
+
+    /// Returns the DER `Name` of the issuer.
+    pub fn issuer(&self) -> &'a [u8] {
+        self.issuer
+    }
+
+    /// Returns the DER `Name` of the subject.
+    pub fn subject(&self) -> &'a [u8] {
+        self.subject
+    }
+
+    /// Returns the first attribute `oid` of the subject, e.g. [`COMMON_NAME_OID`], if it's a
+    /// string.
+    pub fn subject_attribute(&self, oid: &[u8]) -> Option<&'a str> {
+        let mut name = Reader::new(self.subject).read_nested(der::SEQUENCE).ok()?;
+        while !name.is_empty() {
+            let mut rdn = name.read_nested(der::SET).ok()?;
+            while !rdn.is_empty() {
+                let mut attribute = rdn.read_nested(der::SEQUENCE).ok()?;
+                if attribute.read(der::OID).ok()? != oid {
+                    continue;
+                }
+                return match attribute.read_raw().ok()? {
+                    (der::UTF8_STRING | der::PRINTABLE_STRING | der::IA5_STRING, _, value) => {
+                        core::str::from_utf8(value).ok()
+                    }
+                    _ => None,
+                };
+            }
+        }
+        None
+    }
+
+    /// Returns the first second the certificate is valid at, since the Unix epoch.
+    pub fn not_before(&self) -> time_t {
+        self.not_before
+    }
+
+    /// Returns the last second the certificate is valid at, since the Unix epoch.
+    pub fn not_after(&self) -> time_t {
+        self.not_after
+    }
+
+    /// Returns the public key, failing with `UnsupportedAlgorithm` if it's of another kind.
+    pub fn public_key(&self) -> PkiResult<PublicKey> {
+        PublicKey::from_der(self.public_key)
+    }
+
+    pub fn extensions(&self) -> &[Extension<'a>] {
+        &self.extensions
+    }
+
+    /// Returns the extension `oid`.
+    pub fn extension(&self, oid: &[u8]) -> Option<Extension<'a>> {
+        self.extensions.iter().find(|extension| extension.oid == oid).copied()
+    }
+
+    /// Returns the key usages, or `None` if the key may be used for anything.
+    pub fn key_usage(&self) -> Option<KeyUsage> {
+        self.key_usage
+    }
+
+    /// Returns the OIDs of the extended key usages, which are empty if the key may be used for
+    /// anything.
+    pub fn extended_key_usage(&self) -> &[&'a [u8]] {
+        &self.extended_key_usage
+    }
+
+    pub fn alt_names(&self) -> &[SubjectAltName] {
+        &self.alt_names
+    }
+
+    /// Returns whether the certificate is a CA's.
+    pub fn is_ca(&self) -> bool {
+        self.ca
+    }
+
+    /// Returns the number of intermediate CAs allowed below a CA, or `None` if unlimited.
+    pub fn path_len(&self) -> Option<u8> {
+        self.path_len
+    }
+
+    /// Verifies the signature of the certificate by `issuer`.
+    pub fn verify_signature(&self, issuer: &PublicKey) -> PkiResult<()> {
+        issuer.verify(self.signature_algorithm, self.tbs, self.signature)
+    }
+}
+
+/// A parsed certificate revocation list (RFC 5280, section 5), borrowing its DER encoding.
+///
+/// Delta CRLs and the extensions scoping a CRL aren't supported, so CRLs with critical
+/// extensions, or entries with critical extensions, are rejected.
+#[derive(Clone, Debug)]
+pub struct Crl<'a> {
+    tbs: &'a [u8],
+    signature_algorithm: &'a [u8],
+    signature: &'a [u8],
+    issuer: &'a [u8],
+    this_update: time_t,
+    next_update: Option<time_t>,
+    revoked: Vec<&'a [u8]>,
+}
+
+impl<'a> Crl<'a> {
+    /// Parses the DER CRL `der`.
+    pub fn parse(der: &'a [u8]) -> PkiResult<Crl<'a>> {
+        let mut reader = Reader::new(der);
+        let mut signed = reader.read_nested(der::SEQUENCE)?;
+        reader.finish()?;
+        let (tag, tbs, contents) = signed.read_raw()?;
+        if tag != der::SEQUENCE {
+            return Err(PkiError::Malformed);
+        }
+        let signature_algorithm = signed.read(der::SEQUENCE)?;
+        let signature = der::read_bits(signed.read(der::BIT_STRING)?)?;
+        signed.finish()?;
+
+        let mut reader = Reader::new(contents);
+        // Version 1 is the default, which DER leaves out.
+        let v2 = match reader.read_optional(der::INTEGER)? {
+            Some(version) if der::read_uint(version)? == [1] => true,
+            Some(_) => return Err(PkiError::Malformed),
+            None => false,
+        };
+        if reader.read(der::SEQUENCE)? != signature_algorithm {
+            return Err(PkiError::Malformed);
+        }
+        let issuer = match reader.read_raw()? {
+            (der::SEQUENCE, issuer, _) => issuer,
+            _ => return Err(PkiError::Malformed),
+        };
+        let this_update = read_time(&mut reader)?;
+        let next_update = match reader.peek_tag() {
+            Some(der::UTC_TIME | der::GENERALIZED_TIME) => Some(read_time(&mut reader)?),
+            _ => None,
+        };
+        let mut revoked = Vec::new();
+        if let Some(entries) = reader.read_optional(der::SEQUENCE)? {
+            let mut entries = Reader::new(entries);
+            while !entries.is_empty() {
+                let mut entry = entries.read_nested(der::SEQUENCE)?;
+                revoked.push(der::read_uint(entry.read(der::INTEGER)?)?);
+                read_time(&mut entry)?;
+                if let Some(extensions) = entry.read_optional(der::SEQUENCE)? {
+                    if !v2 {
+                        return Err(PkiError::Malformed);
+                    }
+                    check_crl_extensions(extensions)?;
+                }
+                entry.finish()?;
+            }
+        }
+        if let Some(list) = reader.read_optional(der::explicit(0))? {
+            if !v2 {
+                return Err(PkiError::Malformed);
+            }
+            let mut outer = Reader::new(list);
+            check_crl_extensions(outer.read(der::SEQUENCE)?)?;
+            outer.finish()?;
+        }
+        reader.finish()?;
+
+        Ok(Crl { tbs, signature_algorithm, signature, issuer, this_update, next_update, revoked })
+    }
+
+    /// Returns the DER `Name` of the issuer.
+    pub fn issuer(&self) -> &'a [u8] {
+        self.issuer
+    }
+
+    /// Returns the second the CRL was issued at, since the Unix epoch.
+    pub fn this_update(&self) -> time_t {
+        self.this_update
+    }
+
+    /// Returns the second the next CRL is issued by, if the CRL says.
+    pub fn next_update(&self) -> Option<time_t> {
+        self.next_update
+    }
+
+    /// Returns whether the certificate of big-endian serial number `serial`, as
+    /// [`Certificate::serial`] returns it, is revoked.
+    pub fn is_revoked(&self, serial: &[u8]) -> bool {
+        self.revoked.contains(&serial)
+    }
+
+    /// Verifies the signature of the CRL by `issuer`.
+    pub fn verify_signature(&self, issuer: &PublicKey) -> PkiResult<()> {
+        issuer.verify(self.signature_algorithm, self.tbs, self.signature)
+    }
+
+    /// Checks that the CA `issuer` issued the CRL, and that it's current at `now`: issued
+    /// before it, and not past its next update.
+    pub fn verify(&self, issuer: &Certificate<'_>, now: time_t) -> PkiResult<()> {
+        if self.issuer != issuer.subject {
+            return Err(PkiError::UnknownIssuer);
+        }
+        if matches!(issuer.key_usage, Some(usage) if !usage.contains(KeyUsage::CRL_SIGN)) {
+            return Err(PkiError::InvalidIssuer);
+        }
+        self.verify_signature(&issuer.public_key()?)?;
+        if now < self.this_update {
+            return Err(PkiError::NotYetValid);
+        }
+        if matches!(self.next_update, Some(next_update) if now > next_update) {
+            return Err(PkiError::Expired);
+        }
+        Ok(())
+    }
+}
+
+/// Checks that the DER contents `list` of the extensions of a CRL or of one of its entries
+/// are well-formed and none of them is critical.
+fn check_crl_extensions(list: &[u8]) -> PkiResult<()> {
+    let mut list = Reader::new(list);
+    if list.is_empty() {
+        return Err(PkiError::Malformed);
+    }
+    while !list.is_empty() {
+        let mut extension = list.read_nested(der::SEQUENCE)?;
+        extension.read(der::OID)?;
+        match extension.read_optional(der::BOOLEAN)? {
+            Some(critical) if der::read_bool(critical)? => {
+                return Err(PkiError::UnknownCriticalExtension)
+            }
+            Some(_) => return Err(PkiError::Malformed),
+            None => {}
+        }
+        extension.read(der::OCTET_STRING)?;
+        extension.finish()?;
+    }
+    Ok(())
+}
+
+/// The requirements on a chain of certificates besides its validity.
+#[derive(Clone, Debug)]
+pub struct ChainPolicy {
+    now: time_t,
+    dns_name: Option<String>,
+    key_usage: KeyUsage,
+    extended_key_usage: Option<Vec<u8>>,
+    critical_extensions: Vec<Vec<u8>>,
+}
+
+impl ChainPolicy {
+    /// Returns a policy validating chains at `now`, in seconds since the Unix epoch. Take it
+    /// from a trusted time source: the host controls the enclave's view of time otherwise.
+    pub fn new(now: time_t) -> ChainPolicy {
+        ChainPolicy {
+            now,
+            dns_name: None,
+            key_usage: KeyUsage::default(),
+            extended_key_usage: None,
+            critical_extensions: Vec::new(),
+        }
+    }
+
+    /// Requires the leaf to be valid for the DNS name `name`, through its subject alternative
+    /// names, the leftmost label of which may be a wildcard.
+    pub fn dns_name(mut self, name: &str) -> ChainPolicy {
+        self.dns_name = Some(String::from(name));
+        self
+    }
+
+    /// Requires the key usages `usage` of the leaf, if it restricts them.
+    pub fn key_usage(mut self, usage: KeyUsage) -> ChainPolicy {
+        self.key_usage = usage;
+        self
+    }
+
+    /// Requires the extended key usage `oid`, such as [`SERVER_AUTH_OID`], of the leaf, if it
+    /// restricts them.
+    pub fn extended_key_usage(mut self, oid: &[u8]) -> ChainPolicy {
+        self.extended_key_usage = Some(Vec::from(oid));
+        self
+    }
+
+    /// Accepts certificates with the critical extension `oid`, which the caller checks itself.
+    pub fn allow_critical_extension(mut self, oid: &[u8]) -> ChainPolicy {
+        self.critical_extensions.push(Vec::from(oid));
+        self
+    }
+
+    /// Checks the validity period and the critical extensions of `certificate`.
+    fn check(&self, certificate: &Certificate<'_>) -> PkiResult<()> {
+        if self.now < certificate.not_before {
+            return Err(PkiError::NotYetValid);
+        }
+        if self.now > certificate.not_after {
+            return Err(PkiError::Expired);
+        }
+        const UNDERSTOOD: [&[u8]; 4] =
+            [KEY_USAGE_OID, EXTENDED_KEY_USAGE_OID, SUBJECT_ALT_NAME_OID, BASIC_CONSTRAINTS_OID];
+        for extension in certificate.extensions.iter().filter(|extension| extension.critical) {
+            if !UNDERSTOOD.contains(&extension.oid)
+                && !self.critical_extensions.iter().any(|oid| oid == extension.oid)
+            {
+                return Err(PkiError::UnknownCriticalExtension);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the name and usages of the leaf.
+    fn check_leaf(&self, leaf: &Certificate<'_>) -> PkiResult<()> {
+        if let Some(name) = &self.dns_name {
+            let matches = |alt_name: &SubjectAltName| match alt_name {
+                SubjectAltName::Dns(pattern) => dns_matches(pattern, name),
+                _ => false,
+            };
+            if !leaf.alt_names.iter().any(matches) {
+                return Err(PkiError::NameMismatch);
+            }
+        }
+        if matches!(leaf.key_usage, Some(usage) if !usage.contains(self.key_usage)) {
+            return Err(PkiError::KeyUsage);
+        }
+        if let Some(required) = &self.extended_key_usage {
+            let usages = &leaf.extended_key_usage;
+            if !usages.is_empty()
+                && !usages.iter().any(|oid| *oid == required || *oid == ANY_EXTENDED_KEY_USAGE_OID)
+            {
+                return Err(PkiError::KeyUsage);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether the DNS name `pattern` of a certificate matches `name`, ignoring case.
+///
+/// A wildcard leftmost label of `pattern` matches any one label, but not below a top-level
+/// domain.
+fn dns_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => match name.split_once('.') {
+            Some((label, rest)) => {
+                !label.is_empty() && suffix.contains('.') && rest.eq_ignore_ascii_case(suffix)
+            }
+            None => false,
+        },
+        None => pattern.eq_ignore_ascii_case(name),
+    }
+}
+
+/// Checks that `issuer`, a CA with `depth` intermediate CAs below it, issued `certificate`.
+fn check_issuer(
+    certificate: &Certificate<'_>,
+    issuer: &Certificate<'_>,
+    depth: usize,
+) -> PkiResult<()> {
+    if certificate.issuer != issuer.subject {
+        return Err(PkiError::UnknownIssuer);
+    }
+    if !issuer.ca
+        || matches!(issuer.key_usage, Some(usage) if !usage.contains(KeyUsage::KEY_CERT_SIGN))
+        || matches!(issuer.path_len, Some(path_len) if depth > path_len as usize)
+    {
+        return Err(PkiError::InvalidIssuer);
+    }
+    certificate.verify_signature(&issuer.public_key()?)
+}
+
+/// Validates the DER certificate chain of a peer against `policy`, returning its leaf.
+///
+/// `chain` starts with the leaf, each certificate being issued by the next. The last one must
+/// either be one of the DER `anchors`, or be issued by one of them, which must be a CA. Anchors
+/// are trusted as they are, without checking their validity period, which lets a self-signed
+/// peer certificate be pinned as its own anchor.
+pub fn verify_chain<'a>(
+    chain: &[&'a [u8]],
+    anchors: &[&[u8]],
+    policy: &ChainPolicy,
+) -> PkiResult<Certificate<'a>> {
+    if chain.is_empty() {
+        return Err(PkiError::Malformed);
+    }
+    if chain.len() > MAX_CHAIN_LENGTH {
+        return Err(PkiError::ChainTooLong);
+    }
+    let mut certificates = Vec::with_capacity(chain.len());
+    for der in chain {
+        certificates.push(Certificate::parse(der)?);
+    }
+    for (i, certificate) in certificates.iter().enumerate() {
+        policy.check(certificate)?;
+        if i > 0 {
+            check_issuer(&certificates[i - 1], certificate, i - 1)?;
+        }
+    }
+
+    let last = &certificates[certificates.len() - 1];
+    if !anchors.contains(&last.der) {
+        let mut issued = Err(PkiError::UnknownIssuer);
+        for anchor in anchors {
+            let anchor = Certificate::parse(anchor)?;
+            if anchor.subject == last.issuer {
+                issued = check_issuer(last, &anchor, certificates.len() - 1);
+                if issued.is_ok() {
+                    break;
+                }
+            }
+        }
+        issued?;
+    }
+
+    let leaf = certificates.swap_remove(0);
+    policy.check_leaf(&leaf)?;
+    Ok(leaf)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // The certificates in testdata/pki were issued by OpenSSL, valid from 2026-01-01 for ten
+    // years: an RSA root, an ECDSA intermediate, and a leaf for *.example.com.
+    const ROOT: &[u8] = include_bytes!("../testdata/pki/root.der");
+    const INTER: &[u8] = include_bytes!("../testdata/pki/inter.der");
+    const LEAF: &[u8] = include_bytes!("../testdata/pki/leaf.der");
+    const LEAF_CRIT: &[u8] = include_bytes!("../testdata/pki/leaf_crit.der");
+    // A CRL of the DCAP root CA, revoking serial number 99, issued on 2026-01-01 for a year.
+    const DCAP_ROOT: &[u8] = include_bytes!("../testdata/dcap/root.der");
+    const DCAP_ROOT_CRL: &[u8] = include_bytes!("../testdata/dcap/root_crl.der");
+    const NOW: time_t = 1_767_225_600 + 86400;
+
+    /// Signs the certificate of `builder` with another extension of `value`, then renames that
+    /// extension `oid`, which the builder won't issue twice.
+    pub(crate) fn with_duplicate_extension(
+        builder: CertificateBuilder,
+        oid: &[u8],
+        value: &[u8],
+    ) -> Vec<u8> {
+        let mut placeholder = vec![der::OID, oid.len() as u8, 0x2a];
+        placeholder.resize(oid.len() + 2, 0x03);
+        let key = EcdsaP256Key::generate().unwrap();
+        let mut cert =
+            builder.extension(&placeholder[2..], false, value).self_signed(&key).unwrap();
+        assert!(Certificate::parse(&cert).is_ok());
+        let at = cert.windows(placeholder.len()).position(|w| w == &placeholder[..]).unwrap();
+        cert[at + 2..at + placeholder.len()].copy_from_slice(oid);
+        cert
+    }
+
+    #[test]
+    fn parse_foreign() {
+        let root = Certificate::parse(ROOT).unwrap();
+        assert!(root.is_ca());
+        assert_eq!(root.path_len(), Some(1));
+        assert_eq!(root.subject_attribute(COMMON_NAME_OID), Some("Root"));
+        assert_eq!(root.subject_attribute(ORGANIZATION_OID), Some("Corp"));
+        assert_eq!(root.not_before(), 1_767_225_600);
+        root.verify_signature(&root.public_key().unwrap()).unwrap();
+        let leaf = Certificate::parse(LEAF).unwrap();
+        assert_eq!(
+            leaf.alt_names(),
+            &[SubjectAltName::Dns("*.example.com".into()), SubjectAltName::Ip(vec![10, 0, 0, 1])]
+        );
+        assert_eq!(leaf.key_usage(), Some(KeyUsage::DIGITAL_SIGNATURE));
+        assert_eq!(leaf.extended_key_usage(), &[SERVER_AUTH_OID]);
+    }
+
+    #[test]
+    fn parse_rejections() {
+        for len in 0..LEAF.len() {
+            assert!(Certificate::parse(&LEAF[..len]).is_err(), "truncated to {}", len);
+        }
+        let mut long = Vec::from(LEAF);
+        long.push(0);
+        assert_eq!(Certificate::parse(&long).unwrap_err(), PkiError::Malformed);
+        let builder = CertificateBuilder::new(Name::new().common_name("dup"), NOW, NOW + 1000);
+        let quote_oid = oid(&[1, 2, 840, 113741, 1337, 6]).unwrap();
+        let value = [der::OCTET_STRING, 1, 0];
+        let cert = with_duplicate_extension(
+            builder.clone().extension(&quote_oid, false, &value),
+            &quote_oid,
+            &value,
+        );
+        assert_eq!(Certificate::parse(&cert).unwrap_err(), PkiError::Malformed);
+        let value = [der::BIT_STRING, 2, 7, 0x80];
+        let cert = with_duplicate_extension(
+            builder.key_usage(KeyUsage::DIGITAL_SIGNATURE),
+            KEY_USAGE_OID,
+            &value,
+        );
+        assert_eq!(Certificate::parse(&cert).unwrap_err(), PkiError::Malformed);
+    }
+
+    #[test]
+    fn chains() {
+        let policy =
+            ChainPolicy::new(NOW).dns_name("svc.EXAMPLE.com").extended_key_usage(SERVER_AUTH_OID);
+        assert_eq!(verify_chain(&[LEAF, INTER], &[ROOT], &policy).unwrap().der(), LEAF);
+        assert_eq!(verify_chain(&[LEAF], &[ROOT], &policy).unwrap_err(), PkiError::UnknownIssuer);
+        assert_eq!(
+            verify_chain(&[LEAF, INTER], &[LEAF_CRIT], &policy).unwrap_err(),
+            PkiError::UnknownIssuer
+        );
+        let other = ChainPolicy::new(NOW).dns_name("example.com");
+        assert_eq!(
+            verify_chain(&[LEAF, INTER], &[ROOT], &other).unwrap_err(),
+            PkiError::NameMismatch
+        );
+        let later = ChainPolicy::new(NOW + 3651 * 86400);
+        assert_eq!(verify_chain(&[LEAF, INTER], &[ROOT], &later).unwrap_err(), PkiError::Expired);
+        let critical = verify_chain(&[LEAF_CRIT, INTER], &[ROOT], &ChainPolicy::new(NOW));
+        assert_eq!(critical.unwrap_err(), PkiError::UnknownCriticalExtension);
+        let mut tampered = Vec::from(LEAF);
+        let n = tampered.len();
+        tampered[n - 5] ^= 1;
+        let tampered = verify_chain(&[&tampered, INTER], &[ROOT], &ChainPolicy::new(NOW));
+        assert_eq!(tampered.unwrap_err(), PkiError::BadSignature);
+    }
+
+    #[test]
+    fn crl() {
+        let crl = Crl::parse(DCAP_ROOT_CRL).unwrap();
+        assert_eq!(crl.this_update(), 1_767_225_600);
+        assert_eq!(crl.next_update(), Some(1_798_761_600));
+        assert!(crl.is_revoked(&[99]));
+        assert!(!crl.is_revoked(&[98]));
+        let issuer = Certificate::parse(DCAP_ROOT).unwrap();
+        crl.verify(&issuer, NOW).unwrap();
+        assert_eq!(crl.verify(&issuer, 1_767_225_599).unwrap_err(), PkiError::NotYetValid);
+        assert_eq!(crl.verify(&issuer, 1_798_761_601).unwrap_err(), PkiError::Expired);
+        assert_eq!(
+            crl.verify(&Certificate::parse(ROOT).unwrap(), NOW).unwrap_err(),
+            PkiError::UnknownIssuer
+        );
+
+        for len in 0..DCAP_ROOT_CRL.len() {
+            assert!(Crl::parse(&DCAP_ROOT_CRL[..len]).is_err(), "truncated to {}", len);
+        }
+        let mut long = Vec::from(DCAP_ROOT_CRL);
+        long.push(0);
+        assert_eq!(Crl::parse(&long).unwrap_err(), PkiError::Malformed);
+        assert_eq!(Crl::parse(LEAF).unwrap_err(), PkiError::Malformed);
+        let mut tampered = Vec::from(DCAP_ROOT_CRL);
+        let n = tampered.len();
+        tampered[n - 5] ^= 1;
+        assert_eq!(
+            Crl::parse(&tampered).unwrap().verify(&issuer, NOW).unwrap_err(),
+            PkiError::BadSignature
+        );
+    }
+}
@@ -0,0 +1,320 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Attestation policies: which attested enclaves, on which platforms, are trusted.
+//!
+//! A [`Policy`] allows enclaves by `MRENCLAVE` or `MRSIGNER`, with a product ID and a minimum
+//! security version, rejects debug enclaves unless told otherwise, and accepts platforms with
+//! the TCB statuses it lists and, optionally, only with the Intel security advisories it
+//! allows. The same policy checks quotes verified in the enclave by [`dcap::verify_quote`] and
+//! the peers of RA-TLS channels, as a [`RaTlsPolicy`].
+//!
+//! Policies are built in code, or parsed from a JSON document shipped along with the
+//! enclave, such as:
+//!
+//! ```json
+//! {
+//!   "mr_signers": ["c30446b4be9baf0f69728423ea613ef81a63e72acf7439fa0549001fd5482835"],
+//!   "isv_prod_id": 1,
+//!   "min_isv_svn": 3,
+//!   "tcb_statuses": ["UpToDate", "SWHardeningNeeded"],
+//!   "advisory_ids": ["INTEL-SA-00334", "INTEL-SA-00615"]
+//! }
+//! ```
+//!
+//! | member           | value                                                               |
+//! |------------------|---------------------------------------------------------------------|
+//! | `"mr_enclaves"`  | the trusted `MRENCLAVE`s, in hex                                    |
+//! | `"mr_signers"`   | the trusted `MRSIGNER`s, in hex                                     |
+//! | `"isv_prod_id"`  | the required product ID, if any                                     |
+//! | `"min_isv_svn"`  | the minimum ISV SVN, 0 if absent                                    |
+//! | `"tcb_statuses"` | the accepted TCB statuses, by their names in the TCB info of the    |
+//! |                  | PCS, `["UpToDate"]` if absent                                       |
+//! | `"allow_debug"`  | whether debug enclaves are trusted, `false` if absent               |
+//! | `"advisory_ids"` | the advisories platforms may be affected by; any if absent          |
+//!
+//! Every member is optional, and others are rejected, so that a misspelled restriction can't
+//! go unnoticed. A policy without any measurement trusts nobody.
+//!
+//! [`dcap::verify_quote`]: crate::dcap::verify_quote
+//! [`RaTlsPolicy`]: crate::ra_tls::RaTlsPolicy
+
+use crate::dcap::{self, VerifiedQuote};
+use crate::json::Value;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
+use core::fmt::Write;
+use sgx_types::*;
+
+/// An evidence which a [`Policy`] doesn't trust, or a policy document it can't be parsed from.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PolicyError {
+    /// The policy document isn't well-formed.
+    Malformed,
+    /// The quote verified with a status the policy doesn't accept.
+    TcbStatus(sgx_ql_qv_result_t),
+    /// The platform is affected by an advisory the policy doesn't allow, or isn't up to date
+    /// and its advisories are unknown.
+    Advisory,
+    /// The enclave doesn't have a trusted measurement, product ID or security version.
+    UntrustedEnclave,
+    /// The enclave is a debug one, which the policy doesn't trust.
+    DebugEnclave,
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PolicyError::Malformed => f.write_str("malformed policy document"),
+            PolicyError::TcbStatus(status) => write!(f, "quote status not accepted: {}", status),
+            PolicyError::Advisory => f.write_str("platform affected by a disallowed advisory"),
+            PolicyError::UntrustedEnclave => f.write_str("attested enclave not trusted by policy"),
+            PolicyError::DebugEnclave => f.write_str("debug enclave not trusted by policy"),
+        }
+    }
+}
+
+pub type PolicyResult<T> = Result<T, PolicyError>;
+
+/// The enclaves and platforms an attesting peer may be.
+#[derive(Clone)]
+pub struct Policy {
+    mr_enclaves: Vec<sgx_measurement_t>,
+    mr_signers: Vec<sgx_measurement_t>,
+    isv_prod_id: Option<sgx_prod_id_t>,
+    min_isv_svn: sgx_isv_svn_t,
+    accepted: Vec<sgx_ql_qv_result_t>,
+    allow_debug: bool,
+    advisory_ids: Option<Vec<String>>,
+}
+
+impl Policy {
+    /// Creates a policy accepting only up-to-date platforms and no enclave yet.
+    pub fn new() -> Policy {
+        Policy {
+            mr_enclaves: Vec::new(),
+            mr_signers: Vec::new(),
+            isv_prod_id: None,
+            min_isv_svn: 0,
+            accepted: vec![sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OK],
+            allow_debug: false,
+            advisory_ids: None,
+        }
+    }
+
+    /// Trusts the enclave with measurement `mr_enclave`.
+    pub fn mr_enclave(mut self, mr_enclave: sgx_measurement_t) -> Policy {
+        self.mr_enclaves.push(mr_enclave);
+        self
+    }
+
+    /// Trusts the enclaves signed by `mr_signer`.
+    pub fn mr_signer(mut self, mr_signer: sgx_measurement_t) -> Policy {
+        self.mr_signers.push(mr_signer);
+        self
+    }
+
+    /// Requires the product id `isv_prod_id`.
+    pub fn isv_prod_id(mut self, isv_prod_id: sgx_prod_id_t) -> Policy {
+        self.isv_prod_id = Some(isv_prod_id);
+        self
+    }
+
+    /// Requires a security version of at least `min_isv_svn`.
+    pub fn min_isv_svn(mut self, min_isv_svn: sgx_isv_svn_t) -> Policy {
+        self.min_isv_svn = min_isv_svn;
+        self
+    }
+
+    /// Also accepts quotes verifying with `status`, e.g. `SGX_QL_QV_RESULT_OUT_OF_DATE` while a
+    /// TCB recovery is rolled out.
+    pub fn accept_status(mut self, status: sgx_ql_qv_result_t) -> Policy {
+        if !self.accepted.contains(&status) {
+            self.accepted.push(status);
+        }
+        self
+    }
+
+    /// Trusts debug enclaves, whose memory the host can read. Only for development.
+    pub fn allow_debug(mut self, allow_debug: bool) -> Policy {
+        self.allow_debug = allow_debug;
+        self
+    }
+
+    /// Allows platforms affected by the advisory `id`, such as `INTEL-SA-00615`, and from then
+    /// on only by the advisories allowed.
+    ///
+    /// The advisories of platforms are only known from quotes verified by
+    /// [`dcap::verify_quote`]; other evidence, such as that of RA-TLS peers, is then only
+    /// trusted from up-to-date platforms.
+    ///
+    /// [`dcap::verify_quote`]: crate::dcap::verify_quote
+    pub fn allow_advisory(mut self, id: &str) -> Policy {
+        let ids = self.advisory_ids.get_or_insert_with(Vec::new);
+        if !ids.iter().any(|allowed| allowed == id) {
+            ids.push(String::from(id));
+        }
+        self
+    }
+
+    /// Parses the JSON policy document `text`.
+    pub fn from_json(text: &[u8]) -> PolicyResult<Policy> {
+        let malformed = PolicyError::Malformed;
+        let document = Value::parse(text).map_err(|_| malformed)?;
+        let members = document.as_object().ok_or(malformed)?;
+        let strings = |value: &Value| -> PolicyResult<Vec<String>> {
+            let values = value.as_array().ok_or(malformed)?;
+            values.iter().map(|v| v.as_str().map(String::from).ok_or(malformed)).collect()
+        };
+        let measurements = |value: &Value| -> PolicyResult<Vec<sgx_measurement_t>> {
+            let mut measurements = Vec::new();
+            for text in strings(value)? {
+                let m = dcap::hex_decode(&text).and_then(|m| m.try_into().ok()).ok_or(malformed)?;
+                measurements.push(sgx_measurement_t { m });
+            }
+            Ok(measurements)
+        };
+        let uint = |value: &Value| match value.as_i64() {
+            Some(n) if (0..=u16::MAX as i64).contains(&n) => Ok(n as u16),
+            _ => Err(malformed),
+        };
+
+        let mut policy = Policy::new();
+        for (key, value) in members {
+            match key.as_str() {
+                "mr_enclaves" => policy.mr_enclaves = measurements(value)?,
+                "mr_signers" => policy.mr_signers = measurements(value)?,
+                "isv_prod_id" => policy.isv_prod_id = Some(uint(value)?),
+                "min_isv_svn" => policy.min_isv_svn = uint(value)?,
+                "tcb_statuses" => {
+                    let names = strings(value)?;
+                    let statuses = names.iter().map(|name| dcap::tcb_status(name));
+                    policy.accepted = statuses.collect::<Option<_>>().ok_or(malformed)?;
+                }
+                "allow_debug" => policy.allow_debug = value.as_bool().ok_or(malformed)?,
+                "advisory_ids" => policy.advisory_ids = Some(strings(value)?),
+                _ => return Err(malformed),
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Returns the JSON policy document of the policy, which [`from_json`] parses back.
+    ///
+    /// [`from_json`]: Policy::from_json
+    pub fn to_json(&self) -> Value {
+        let measurements = |measurements: &[sgx_measurement_t]| {
+            Value::Array(measurements.iter().map(|m| Value::from(hex(&m.m))).collect())
+        };
+        let strings = |strings: &[String]| {
+            Value::Array(strings.iter().map(|s| Value::from(s.as_str())).collect())
+        };
+        let mut members = vec![
+            (String::from("mr_enclaves"), measurements(&self.mr_enclaves)),
+            (String::from("mr_signers"), measurements(&self.mr_signers)),
+        ];
+        if let Some(isv_prod_id) = self.isv_prod_id {
+            members.push((String::from("isv_prod_id"), Value::from(isv_prod_id as i64)));
+        }
+        members.push((String::from("min_isv_svn"), Value::from(self.min_isv_svn as i64)));
+        // Every status a quote verifies with has a name.
+        let names = self.accepted.iter().filter_map(|status| dcap::tcb_status_name(*status));
+        let names = names.map(Value::from).collect();
+        members.push((String::from("tcb_statuses"), Value::Array(names)));
+        members.push((String::from("allow_debug"), Value::from(self.allow_debug)));
+        if let Some(ids) = &self.advisory_ids {
+            members.push((String::from("advisory_ids"), strings(ids)));
+        }
+        Value::Object(members)
+    }
+
+    /// Checks that the policy accepts a platform whose quote verified with `status`, and which
+    /// is affected by the advisories `advisory_ids`, if they're known.
+    pub fn check_status(
+        &self,
+        status: sgx_ql_qv_result_t,
+        advisory_ids: Option<&[String]>,
+    ) -> PolicyResult<()> {
+        if !self.accepted.contains(&status) {
+            return Err(PolicyError::TcbStatus(status));
+        }
+        if let Some(allowed) = &self.advisory_ids {
+            match advisory_ids {
+                Some(ids) if ids.iter().all(|id| allowed.contains(id)) => {}
+                None if status == sgx_ql_qv_result_t::SGX_QL_QV_RESULT_OK => {}
+                _ => return Err(PolicyError::Advisory),
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the policy trusts the enclave of report body `body`.
+    pub fn check_enclave(&self, body: &sgx_report_body_t) -> PolicyResult<()> {
+        let measured = self.mr_enclaves.iter().any(|m| m.m == body.mr_enclave.m)
+            || self.mr_signers.iter().any(|m| m.m == body.mr_signer.m);
+        if !measured
+            || self.isv_prod_id.into_iter().any(|id| id != body.isv_prod_id)
+            || body.isv_svn < self.min_isv_svn
+        {
+            return Err(PolicyError::UntrustedEnclave);
+        }
+        if !self.allow_debug && body.attributes.flags & SGX_FLAGS_DEBUG != 0 {
+            return Err(PolicyError::DebugEnclave);
+        }
+        Ok(())
+    }
+
+    /// Checks both the platform and the enclave of `quote`.
+    pub fn check_quote(&self, quote: &VerifiedQuote) -> PolicyResult<()> {
+        self.check_status(quote.status(), Some(quote.advisory_ids()))?;
+        self.check_enclave(quote.report_body())
+    }
+}
+
+impl fmt::Debug for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mr_enclaves: Vec<_> = self.mr_enclaves.iter().map(|m| m.m).collect();
+        let mr_signers: Vec<_> = self.mr_signers.iter().map(|m| m.m).collect();
+        f.debug_struct("Policy")
+            .field("mr_enclaves", &mr_enclaves)
+            .field("mr_signers", &mr_signers)
+            .field("isv_prod_id", &self.isv_prod_id)
+            .field("min_isv_svn", &self.min_isv_svn)
+            .field("accepted", &self.accepted)
+            .field("allow_debug", &self.allow_debug)
+            .field("advisory_ids", &self.advisory_ids)
+            .finish()
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy::new()
+    }
+}
+
+/// Returns the lowercase hex of `bytes`.
+fn hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        // Writing to a string doesn't fail.
+        let _ = write!(text, "{:02x}", byte);
+    }
+    text
+}
@@ -0,0 +1,422 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Attested TLS channels.
+//!
+//! An RA-TLS certificate is a self-signed X.509 certificate for a key generated inside the
+//! enclave, with an extension carrying a DCAP quote whose report data is the hash of that key.
+//! A peer verifying the quote and the binding learns which enclave holds the private key, so a
+//! TLS session authenticated with the certificate is a session with that enclave.
+//!
+//! [`RaTlsAcceptor`] and [`RaTlsConnector`] bundle an identity and the policy a peer has to
+//! satisfy. The TLS protocol itself is left to the TLS library of the enclave: it is given the
+//! certificate and private key of the identity, and calls the `verify_*` method from its
+//! certificate verification hook.
+
+use crate::pki::{Certificate, PkiError};
+use crate::policy::Policy;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::ptr;
+use core::slice;
+use sgx_tcrypto::SgxEccHandle;
+use sgx_types::*;
+
+/// OID 1.2.840.113741.1337.6 of the X.509 extension carrying the quote, as written by
+/// `tee_get_certificate_with_evidence`.
+const QUOTE_EXTENSION_OID: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf8, 0x4d, 0x8a, 0x39, 0x06];
+
+/// DER of an `ECPrivateKey` of prime256v1 up to the private key.
+const EC_PRIVATE_KEY_HEADER: [u8; 7] = [0x30, 0x77, 0x02, 0x01, 0x01, 0x04, 0x20];
+
+/// DER of an `ECPrivateKey` of prime256v1 from the curve OID up to the public point.
+const EC_PRIVATE_KEY_CURVE: [u8; 18] = [
+    0xa0, 0x0a, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0xa1, 0x44, 0x03, 0x42,
+    0x00, 0x04,
+];
+
+/// DER of a `SubjectPublicKeyInfo` of prime256v1 up to the public point.
+const EC_PUBLIC_KEY_HEADER: [u8; 27] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00, 0x04,
+];
+
+/// An error establishing or verifying an attested channel.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RaTlsError {
+    /// Generating the key of the identity failed.
+    Crypto(sgx_status_t),
+    /// Creating or checking the quote failed.
+    Quote(sgx_quote3_error_t),
+    /// The subject name contains a NUL byte.
+    InvalidSubject,
+    /// The certificate isn't well-formed.
+    Certificate(PkiError),
+    /// The certificate has no quote extension, or it is malformed.
+    MissingEvidence,
+    /// The quote verified with a status the policy doesn't accept.
+    QuoteStatus(sgx_ql_qv_result_t),
+    /// The attested enclave doesn't satisfy the policy.
+    UntrustedEnclave,
+}
+
+impl fmt::Display for RaTlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RaTlsError::Crypto(status) => write!(f, "key generation failed: {}", status),
+            RaTlsError::Quote(error) => write!(f, "quote operation failed: {}", error),
+            RaTlsError::InvalidSubject => f.write_str("subject name contains a NUL byte"),
+            RaTlsError::Certificate(error) => write!(f, "malformed certificate: {}", error),
+            RaTlsError::MissingEvidence => f.write_str("certificate carries no valid quote"),
+            RaTlsError::QuoteStatus(status) => write!(f, "quote status not accepted: {}", status),
+            RaTlsError::UntrustedEnclave => f.write_str("attested enclave not trusted by policy"),
+        }
+    }
+}
+
+impl From<PkiError> for RaTlsError {
+    fn from(error: PkiError) -> RaTlsError {
+        RaTlsError::Certificate(error)
+    }
+}
+
+pub type RaTlsResult<T> = Result<T, RaTlsError>;
+
+/// Overwrites `buf` with zeroes, in a way the compiler won't remove.
+fn zeroize(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
+/// Returns the padded base64 encoding of `data`.
+pub(crate) fn base64(data: &[u8]) -> Vec<u8> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = Vec::with_capacity(data.chunks(3).len() * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0_u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize]);
+            } else {
+                encoded.push(b'=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Returns `label`ed PEM of `der`, NUL-terminated as the sgx_ttls parser requires.
+fn pem(label: &str, der: &[u8]) -> Vec<u8> {
+    let mut encoded = base64(der);
+    let mut pem = Vec::with_capacity(encoded.len() + encoded.len() / 64 + 2 * label.len() + 36);
+    pem.extend_from_slice(b"-----BEGIN ");
+    pem.extend_from_slice(label.as_bytes());
+    pem.extend_from_slice(b"-----\n");
+    for line in encoded.chunks(64) {
+        pem.extend_from_slice(line);
+        pem.push(b'\n');
+    }
+    pem.extend_from_slice(b"-----END ");
+    pem.extend_from_slice(label.as_bytes());
+    pem.extend_from_slice(b"-----\n\0");
+    zeroize(&mut encoded);
+    pem
+}
+
+/// A certificate attesting the enclave, along with its private key.
+///
+/// The key is generated inside the enclave and never leaves it, except through the TLS library
+/// it's handed to. It's zeroized on drop.
+pub struct RaTlsIdentity {
+    certificate: Vec<u8>,
+    private_key: Vec<u8>,
+}
+
+impl RaTlsIdentity {
+    /// Generates an ephemeral P-256 key and a certificate for it, named `subject` (e.g.
+    /// `"CN=wallet-enclave,O=Example,C=US"`), with a quote of the enclave over the key hash.
+    ///
+    /// Quoting goes through the `sgx_tls_*_ocall`s of `sgx_ttls.edl`, which the enclave has to
+    /// import.
+    pub fn generate(subject: &str) -> RaTlsResult<RaTlsIdentity> {
+        if subject.as_bytes().contains(&0) {
+            return Err(RaTlsError::InvalidSubject);
+        }
+        let mut subject = Vec::from(subject.as_bytes());
+        subject.push(0);
+
+        let ecc = SgxEccHandle::new();
+        ecc.open().map_err(RaTlsError::Crypto)?;
+        let (mut private, public) = ecc.create_key_pair().map_err(RaTlsError::Crypto)?;
+
+        // The SGX key types are little-endian, DER is big-endian.
+        let mut d = private.r;
+        zeroize(&mut private.r);
+        d.reverse();
+        let (mut x, mut y) = (public.gx, public.gy);
+        x.reverse();
+        y.reverse();
+
+        let len = EC_PRIVATE_KEY_HEADER.len() + 32 + EC_PRIVATE_KEY_CURVE.len() + 64;
+        let mut der = Vec::with_capacity(len);
+        der.extend_from_slice(&EC_PRIVATE_KEY_HEADER);
+        der.extend_from_slice(&d);
+        der.extend_from_slice(&EC_PRIVATE_KEY_CURVE);
+        der.extend_from_slice(&x);
+        der.extend_from_slice(&y);
+        zeroize(&mut d);
+        let private_key = pem("EC PRIVATE KEY", &der);
+        zeroize(&mut der);
+
+        let mut der = Vec::with_capacity(EC_PUBLIC_KEY_HEADER.len() + 64);
+        der.extend_from_slice(&EC_PUBLIC_KEY_HEADER);
+        der.extend_from_slice(&x);
+        der.extend_from_slice(&y);
+        let public_key = pem("PUBLIC KEY", &der);
+
+        // Owns the key from here, so that it's zeroized on every path.
+        let mut identity = RaTlsIdentity { certificate: Vec::new(), private_key };
+        let mut cert: *mut u8 = ptr::null_mut();
+        let mut cert_size: usize = 0;
+        let ret = unsafe {
+            tee_get_certificate_with_evidence(
+                subject.as_ptr(),
+                identity.private_key.as_ptr(),
+                identity.private_key.len(),
+                public_key.as_ptr(),
+                public_key.len(),
+                &mut cert,
+                &mut cert_size,
+            )
+        };
+        if ret != sgx_quote3_error_t::SGX_QL_SUCCESS {
+            return Err(RaTlsError::Quote(ret));
+        }
+        identity.certificate = Vec::from(unsafe { slice::from_raw_parts(cert, cert_size) });
+        unsafe { tee_free_certificate(cert) };
+        Ok(identity)
+    }
+
+    /// Returns the DER encoded certificate.
+    pub fn certificate(&self) -> &[u8] {
+        &self.certificate
+    }
+
+    /// Returns the PEM encoded private key, in SEC1 `EC PRIVATE KEY` form.
+    pub fn private_key_pem(&self) -> &str {
+        let pem = &self.private_key[..self.private_key.len() - 1];
+        // Only ever holds base64 and the PEM armor.
+        unsafe { core::str::from_utf8_unchecked(pem) }
+    }
+}
+
+impl Drop for RaTlsIdentity {
+    fn drop(&mut self) {
+        zeroize(&mut self.private_key);
+    }
+}
+
+impl fmt::Debug for RaTlsIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RaTlsIdentity")
+            .field("certificate", &self.certificate.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The enclaves a peer of an attested channel may be.
+///
+/// A peer is trusted if its quote verifies with a status the [`Policy`] accepts, and the
+/// policy trusts its enclave. The advisories of the platforms of peers aren't known, so a
+/// policy allowing only some advisories only trusts up-to-date peers.
+pub type RaTlsPolicy = Policy;
+
+/// What the certificate of a trusted peer attests.
+#[derive(Clone, Copy)]
+pub struct PeerEvidence {
+    status: sgx_ql_qv_result_t,
+    report_body: sgx_report_body_t,
+}
+
+impl PeerEvidence {
+    /// Returns the verification status of the quote.
+    pub fn status(&self) -> sgx_ql_qv_result_t {
+        self.status
+    }
+
+    /// Returns the report body of the peer enclave.
+    pub fn report_body(&self) -> &sgx_report_body_t {
+        &self.report_body
+    }
+}
+
+impl fmt::Debug for PeerEvidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerEvidence")
+            .field("status", &self.status)
+            .field("mr_enclave", &self.report_body.mr_enclave.m)
+            .field("mr_signer", &self.report_body.mr_signer.m)
+            .field("isv_prod_id", &self.report_body.isv_prod_id)
+            .field("isv_svn", &self.report_body.isv_svn)
+            .finish()
+    }
+}
+
+/// Returns the quote in the extension of the DER certificate `cert`.
+///
+/// The certificate is parsed rather than scanned for the OID, so that the quote is the one in
+/// the extension `tee_verify_certificate_with_evidence` verified, and not bytes planted
+/// elsewhere in the certificate. Parsing rejects certificates carrying the extension twice.
+fn quote_extension(cert: &[u8]) -> RaTlsResult<&[u8]> {
+    let cert = Certificate::parse(cert)?;
+    let extension = cert.extension(&QUOTE_EXTENSION_OID).ok_or(RaTlsError::MissingEvidence)?;
+    Ok(extension.value)
+}
+
+/// Verifies the RA-TLS certificate `cert` of a peer against `policy`.
+///
+/// The quote is verified, along with its binding to the key of the certificate, using the
+/// collateral fetched by the host, with `now` (seconds since the Unix epoch) as the date the
+/// collateral has to be valid at. Take `now` from a trusted time source: the host controls the
+/// enclave's view of time otherwise.
+pub fn verify_certificate(
+    cert: &[u8],
+    now: time_t,
+    policy: &RaTlsPolicy,
+) -> RaTlsResult<PeerEvidence> {
+    let mut status = sgx_ql_qv_result_t::SGX_QL_QV_RESULT_UNSPECIFIED;
+    let ret = unsafe {
+        tee_verify_certificate_with_evidence(
+            cert.as_ptr(),
+            cert.len(),
+            now,
+            &mut status,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    if ret != sgx_quote3_error_t::SGX_QL_SUCCESS {
+        return Err(RaTlsError::Quote(ret));
+    }
+    policy.check_status(status, None).map_err(|_| RaTlsError::QuoteStatus(status))?;
+
+    let quote = quote_extension(cert)?;
+    if quote.len() < mem::size_of::<sgx_quote3_t>() {
+        return Err(RaTlsError::MissingEvidence);
+    }
+    let quote = unsafe { ptr::read_unaligned(quote.as_ptr() as *const sgx_quote3_t) };
+    let report_body = quote.report_body;
+    policy.check_enclave(&report_body).map_err(|_| RaTlsError::UntrustedEnclave)?;
+    Ok(PeerEvidence { status, report_body })
+}
+
+/// The server side of attested channels.
+#[derive(Debug)]
+pub struct RaTlsAcceptor {
+    identity: RaTlsIdentity,
+    client_policy: Option<RaTlsPolicy>,
+}
+
+impl RaTlsAcceptor {
+    /// Generates the identity of the server, named `subject`.
+    ///
+    /// With a `client_policy`, clients have to present an RA-TLS certificate satisfying it, for
+    /// mutually attested sessions.
+    pub fn new(subject: &str, client_policy: Option<RaTlsPolicy>) -> RaTlsResult<RaTlsAcceptor> {
+        Ok(RaTlsAcceptor { identity: RaTlsIdentity::generate(subject)?, client_policy })
+    }
+
+    /// Returns the identity the server presents.
+    pub fn identity(&self) -> &RaTlsIdentity {
+        &self.identity
+    }
+
+    /// Returns whether clients have to present a certificate.
+    pub fn requires_client_certificate(&self) -> bool {
+        self.client_policy.is_some()
+    }
+
+    /// Verifies the certificate presented by a client.
+    ///
+    /// Returns `None` if no client certificate is required.
+    pub fn verify_client(&self, cert: &[u8], now: time_t) -> RaTlsResult<Option<PeerEvidence>> {
+        self.client_policy.as_ref().map(|policy| verify_certificate(cert, now, policy)).transpose()
+    }
+}
+
+/// The client side of attested channels.
+#[derive(Debug)]
+pub struct RaTlsConnector {
+    identity: Option<RaTlsIdentity>,
+    server_policy: RaTlsPolicy,
+}
+
+impl RaTlsConnector {
+    /// Creates a client trusting the servers satisfying `server_policy`.
+    pub fn new(server_policy: RaTlsPolicy) -> RaTlsConnector {
+        RaTlsConnector { identity: None, server_policy }
+    }
+
+    /// Creates a client which also attests itself to the server, with an identity named
+    /// `subject`.
+    pub fn with_identity(subject: &str, server_policy: RaTlsPolicy) -> RaTlsResult<RaTlsConnector> {
+        Ok(RaTlsConnector { identity: Some(RaTlsIdentity::generate(subject)?), server_policy })
+    }
+
+    /// Returns the identity the client presents, if any.
+    pub fn identity(&self) -> Option<&RaTlsIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// Verifies the certificate presented by the server.
+    pub fn verify_server(&self, cert: &[u8], now: time_t) -> RaTlsResult<PeerEvidence> {
+        verify_certificate(cert, now, &self.server_policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::tests::with_duplicate_extension;
+    use crate::pki::{CertificateBuilder, EcdsaP256Key, Name};
+
+    #[test]
+    fn quote_extensions() {
+        let builder = CertificateBuilder::new(Name::new().common_name("enclave"), 0, 1000);
+        let key = EcdsaP256Key::generate().unwrap();
+        let cert = builder
+            .clone()
+            .extension(&QUOTE_EXTENSION_OID, false, b"quote")
+            .self_signed(&key)
+            .unwrap();
+        assert_eq!(quote_extension(&cert), Ok(&b"quote"[..]));
+        let cert = builder.self_signed(&key).unwrap();
+        assert_eq!(quote_extension(&cert), Err(RaTlsError::MissingEvidence));
+        assert_eq!(quote_extension(&cert[1..]), Err(RaTlsError::Certificate(PkiError::Malformed)));
+        // A second quote extension, which verifying the first wouldn't cover.
+        let builder = CertificateBuilder::new(Name::new().common_name("enclave"), 0, 1000)
+            .extension(&QUOTE_EXTENSION_OID, false, b"quote");
+        let cert = with_duplicate_extension(builder, &QUOTE_EXTENSION_OID, b"other");
+        assert_eq!(quote_extension(&cert), Err(RaTlsError::Certificate(PkiError::Malformed)));
+    }
+}
@@ -0,0 +1,269 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! The report data of quotes, binding the claims of the application.
+//!
+//! The 64 bytes of report data are all a quote says of the application, so enclaves hash into
+//! them what a verifier needs to trust along with the enclave: the key of a channel, the nonce
+//! of a challenge, the policy the enclave enforces, or any other claim. A
+//! [`ReportDataBuilder`] encodes the claims canonically, so that the enclave and the verifier,
+//! each with its own code, hash the same bytes:
+//!
+//! ```ignore
+//! // In the enclave.
+//! let claims = ReportDataBuilder::new().public_key(&key.to_der()).nonce(&nonce).build();
+//! let bundle = QuoteBuilder::new(pccs).report_data(claims.report_data()).build()?;
+//! send(&bundle.to_bytes(), &claims.to_bytes());
+//!
+//! // In the verifier.
+//! let verified = verify_quote(&bundle, &policy)?;
+//! let claims = ReportDataClaims::verify(&claims_bytes, &verified.report_body().report_data)?;
+//! if claims.nonce() != Some(&nonce[..]) { ... }
+//! ```
+//!
+//! The claims are the CBOR map, in the deterministic encoding of RFC 8949, its keys sorted
+//! by their encodings:
+//!
+//! | key            | value                                                               |
+//! |----------------|---------------------------------------------------------------------|
+//! | `"version"`    | [`CLAIMS_VERSION`]                                                  |
+//! | `"public_key"` | the public key, as bytes, if any                                    |
+//! | `"nonce"`      | the nonce, as bytes, if any                                         |
+//! | `"policy"`     | the SHA-256 of the policy, as bytes, if any                         |
+//! | `"claims"`     | a map of the other claims, by name, as bytes, if any                |
+//!
+//! and the report data is its SHA-512. The policy of a [`Policy`] is hashed as its compact JSON
+//! document.
+
+use crate::cbor::Value;
+use crate::policy::Policy;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use sgx_tcrypto::hash::{Hash, Sha256, Sha512};
+use sgx_types::*;
+
+/// The version of the claims written by [`ReportDataClaims::to_bytes`].
+pub const CLAIMS_VERSION: u64 = 1;
+
+/// An error verifying claims against report data.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ReportDataError {
+    /// The claims aren't canonical CBOR of the known version.
+    Malformed,
+    /// The claims don't hash to the report data.
+    Mismatch,
+}
+
+impl fmt::Display for ReportDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ReportDataError::Malformed => f.write_str("malformed report data claims"),
+            ReportDataError::Mismatch => f.write_str("claims don't match the report data"),
+        }
+    }
+}
+
+pub type ReportDataResult<T> = Result<T, ReportDataError>;
+
+/// Builds the claims an enclave binds to its report data.
+#[derive(Clone, Debug, Default)]
+pub struct ReportDataBuilder {
+    claims: ReportDataClaims,
+}
+
+impl ReportDataBuilder {
+    pub fn new() -> ReportDataBuilder {
+        ReportDataBuilder::default()
+    }
+
+    /// Binds the public key `key`, such as the DER `SubjectPublicKeyInfo` of
+    /// [`PublicKey::to_der`](crate::pki::PublicKey::to_der).
+    pub fn public_key(mut self, key: &[u8]) -> ReportDataBuilder {
+        self.claims.public_key = Some(Vec::from(key));
+        self
+    }
+
+    /// Binds the nonce of the challenge of a verifier.
+    pub fn nonce(mut self, nonce: &[u8]) -> ReportDataBuilder {
+        self.claims.nonce = Some(Vec::from(nonce));
+        self
+    }
+
+    /// Binds `policy`, the policy the enclave enforces on its own peers.
+    pub fn policy(self, policy: &Policy) -> ReportDataBuilder {
+        self.policy_hash(policy_hash(policy))
+    }
+
+    /// Binds the SHA-256 `hash` of a policy.
+    pub fn policy_hash(mut self, hash: [u8; 32]) -> ReportDataBuilder {
+        self.claims.policy_hash = Some(hash);
+        self
+    }
+
+    /// Binds the claim `name`, replacing any claim of the same name.
+    pub fn claim(mut self, name: &str, value: &[u8]) -> ReportDataBuilder {
+        self.claims.claims.retain(|(claim, _)| claim != name);
+        self.claims.claims.push((name.to_string(), Vec::from(value)));
+        self
+    }
+
+    pub fn build(self) -> ReportDataClaims {
+        self.claims
+    }
+}
+
+/// The claims bound to report data.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReportDataClaims {
+    public_key: Option<Vec<u8>>,
+    nonce: Option<Vec<u8>>,
+    policy_hash: Option<[u8; 32]>,
+    claims: Vec<(String, Vec<u8>)>,
+}
+
+impl ReportDataClaims {
+    /// Parses the claims `bytes` written by [`to_bytes`], and checks that they're the claims of
+    /// `report_data`.
+    ///
+    /// [`to_bytes`]: ReportDataClaims::to_bytes
+    pub fn verify(
+        bytes: &[u8],
+        report_data: &sgx_report_data_t,
+    ) -> ReportDataResult<ReportDataClaims> {
+        let malformed = ReportDataError::Malformed;
+        let value = Value::decode(bytes).map_err(|_| malformed)?;
+        let entries = value.as_map().ok_or(malformed)?;
+        let member = |name: &str| value.get(&Value::from(name));
+        let known = ["version", "public_key", "nonce", "policy", "claims"];
+        if entries.iter().any(|(key, _)| !known.iter().any(|name| *key == Value::from(*name))) {
+            return Err(malformed);
+        }
+        if member("version") != Some(&Value::Unsigned(CLAIMS_VERSION)) {
+            return Err(malformed);
+        }
+        let bytes_member = |name: &str| match member(name) {
+            None => Ok(None),
+            Some(Value::Bytes(bytes)) => Ok(Some(bytes.clone())),
+            Some(_) => Err(malformed),
+        };
+
+        let mut claims = ReportDataClaims {
+            public_key: bytes_member("public_key")?,
+            nonce: bytes_member("nonce")?,
+            policy_hash: None,
+            claims: Vec::new(),
+        };
+        if let Some(hash) = bytes_member("policy")? {
+            let hash: [u8; 32] = hash.as_slice().try_into().map_err(|_| malformed)?;
+            claims.policy_hash = Some(hash);
+        }
+        if let Some(map) = member("claims") {
+            for (name, value) in map.as_map().ok_or(malformed)? {
+                let name = name.as_text().ok_or(malformed)?;
+                let value = value.as_bytes().ok_or(malformed)?;
+                claims.claims.push((name.to_string(), Vec::from(value)));
+            }
+        }
+
+        // Only the canonical encoding is accepted, so that the claims have a single one.
+        let encoded = claims.to_bytes();
+        if encoded != bytes {
+            return Err(malformed);
+        }
+        if report_data_of(&encoded).d != report_data.d {
+            return Err(ReportDataError::Mismatch);
+        }
+        Ok(claims)
+    }
+
+    pub fn public_key(&self) -> Option<&[u8]> {
+        self.public_key.as_deref()
+    }
+
+    pub fn nonce(&self) -> Option<&[u8]> {
+        self.nonce.as_deref()
+    }
+
+    pub fn policy_hash(&self) -> Option<&[u8; 32]> {
+        self.policy_hash.as_ref()
+    }
+
+    /// Returns whether the claims bind `policy`.
+    pub fn binds_policy(&self, policy: &Policy) -> bool {
+        self.policy_hash == Some(policy_hash(policy))
+    }
+
+    /// Returns the value of the claim `name`.
+    pub fn claim(&self, name: &str) -> Option<&[u8]> {
+        self.claims.iter().find(|(claim, _)| claim == name).map(|(_, value)| value.as_slice())
+    }
+
+    /// Returns the other claims, by name.
+    pub fn claims(&self) -> &[(String, Vec<u8>)] {
+        &self.claims
+    }
+
+    /// Returns the canonical CBOR of the claims, which the verifier needs along with the quote.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut entries = vec![(Value::from("version"), Value::Unsigned(CLAIMS_VERSION))];
+        if let Some(ref key) = self.public_key {
+            entries.push((Value::from("public_key"), Value::Bytes(key.clone())));
+        }
+        if let Some(ref nonce) = self.nonce {
+            entries.push((Value::from("nonce"), Value::Bytes(nonce.clone())));
+        }
+        if let Some(ref hash) = self.policy_hash {
+            entries.push((Value::from("policy"), Value::Bytes(Vec::from(&hash[..]))));
+        }
+        if !self.claims.is_empty() {
+            let claims = self
+                .claims
+                .iter()
+                .map(|(name, value)| (Value::from(name.as_str()), Value::Bytes(value.clone())))
+                .collect();
+            entries.push((Value::from("claims"), canonical_map(claims)));
+        }
+        canonical_map(entries).encode()
+    }
+
+    /// Returns the report data binding the claims.
+    pub fn report_data(&self) -> sgx_report_data_t {
+        report_data_of(&self.to_bytes())
+    }
+}
+
+/// Returns the map of `entries` with its keys in the order of their encodings, as the
+/// deterministic encoding sorts them.
+fn canonical_map(entries: Vec<(Value, Value)>) -> Value {
+    let mut entries: Vec<(Vec<u8>, (Value, Value))> =
+        entries.into_iter().map(|entry| (entry.0.encode(), entry)).collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Value::Map(entries.into_iter().map(|(_, entry)| entry).collect())
+}
+
+fn report_data_of(claims: &[u8]) -> sgx_report_data_t {
+    let mut report_data = sgx_report_data_t::default();
+    Sha512::digest_into(claims, &mut report_data.d);
+    report_data
+}
+
+fn policy_hash(policy: &Policy) -> [u8; 32] {
+    let mut hash = [0_u8; 32];
+    Sha256::digest_into(policy.to_json().to_string().as_bytes(), &mut hash);
+    hash
+}
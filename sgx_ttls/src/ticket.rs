@@ -0,0 +1,287 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! Session ticket keys.
+//!
+//! A session ticket is the state of a TLS session, encrypted by the server under a key only it
+//! knows and handed to the client, which presents it when reconnecting to resume the session
+//! without a full handshake, and, for RA-TLS, without a new quote and its verification.
+//!
+//! [`TicketKeys`] encrypts and decrypts tickets with AES-128-GCM, rotating to a fresh key every
+//! rotation interval while still accepting the tickets of the previous key for one more interval,
+//! and is sealed to persist across enclave restarts. The TLS library of the enclave calls
+//! `encrypt` and `decrypt` from its ticket hooks.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::ptr;
+use sgx_tcrypto::{rsgx_rijndael128GCM_decrypt, rsgx_rijndael128GCM_encrypt};
+use sgx_tse::{rsgx_get_key, rsgx_self_report};
+use sgx_types::*;
+
+const KEY_NAME_SIZE: usize = 16;
+const IV_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+
+/// The size of a sealed set of keys before its keys, holding the key id, CPU SVN, ISV SVN and key
+/// policy of the seal key, and the IV.
+const SEALED_HEADER_SIZE: usize = 32 + 16 + 2 + 2 + IV_SIZE;
+
+/// The size of a key when sealed: name, key and creation time.
+const SEALED_KEY_SIZE: usize = KEY_NAME_SIZE + 16 + 8;
+
+fn read_rand(buf: &mut [u8]) -> SgxError {
+    match unsafe { sgx_read_rand(buf.as_mut_ptr(), buf.len()) } {
+        sgx_status_t::SGX_SUCCESS => Ok(()),
+        ret => Err(ret),
+    }
+}
+
+fn zeroize(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+}
+
+/// A ticket key, named by random bytes prefixing the tickets it encrypts.
+struct TicketKey {
+    name: [u8; KEY_NAME_SIZE],
+    key: sgx_aes_gcm_128bit_key_t,
+    created: u64,
+}
+
+impl TicketKey {
+    fn generate(now: u64) -> SgxResult<TicketKey> {
+        let mut key = TicketKey { name: [0; KEY_NAME_SIZE], key: [0; 16], created: now };
+        read_rand(&mut key.name)?;
+        read_rand(&mut key.key)?;
+        Ok(key)
+    }
+}
+
+impl Drop for TicketKey {
+    fn drop(&mut self) {
+        zeroize(&mut self.key);
+    }
+}
+
+/// The keys encrypting session tickets, newest last.
+pub struct TicketKeys {
+    keys: Vec<TicketKey>,
+    rotation_interval: u64,
+}
+
+impl TicketKeys {
+    /// Creates a fresh key, rotated every `rotation_interval` seconds.
+    ///
+    /// Tickets stay valid for up to two intervals, so the interval should be half the ticket
+    /// lifetime the TLS library advertises. `now` is the current time in seconds.
+    pub fn new(rotation_interval: u64, now: u64) -> SgxResult<TicketKeys> {
+        if rotation_interval == 0 {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        Ok(TicketKeys { keys: vec![TicketKey::generate(now)?], rotation_interval })
+    }
+
+    /// Returns the rotation interval, in seconds.
+    pub fn rotation_interval(&self) -> u64 {
+        self.rotation_interval
+    }
+
+    /// Rotates to a fresh key if the current one is at least an interval old, and forgets the
+    /// keys more than two intervals old. Returns whether a key was created.
+    ///
+    /// A persisted set of keys has to be sealed again after a rotation.
+    pub fn rotate(&mut self, now: u64) -> SgxResult<bool> {
+        let interval = self.rotation_interval;
+        // `keys` is never empty, a rotation only adds a key.
+        let due = now.saturating_sub(self.keys[self.keys.len() - 1].created) >= interval;
+        if due {
+            self.keys.push(TicketKey::generate(now)?);
+        }
+        // Keys are in creation order.
+        let expiry = interval.saturating_mul(2);
+        while self.keys.len() > 1 && now.saturating_sub(self.keys[0].created) >= expiry {
+            self.keys.remove(0);
+        }
+        Ok(due)
+    }
+
+    /// Encrypts the session state `state` into a ticket under the current key.
+    pub fn encrypt(&self, state: &[u8]) -> SgxResult<Vec<u8>> {
+        let key = &self.keys[self.keys.len() - 1];
+        let mut ticket = vec![0_u8; KEY_NAME_SIZE + IV_SIZE + state.len() + TAG_SIZE];
+        let (name, rest) = ticket.split_at_mut(KEY_NAME_SIZE);
+        let (iv, rest) = rest.split_at_mut(IV_SIZE);
+        let (ciphertext, tag) = rest.split_at_mut(state.len());
+
+        name.copy_from_slice(&key.name);
+        read_rand(iv)?;
+        let mut mac = sgx_aes_gcm_128bit_tag_t::default();
+        rsgx_rijndael128GCM_encrypt(&key.key, state, iv, name, ciphertext, &mut mac)?;
+        tag.copy_from_slice(&mac);
+        Ok(ticket)
+    }
+
+    /// Decrypts `ticket` into the session state it holds.
+    ///
+    /// Returns `None` if the ticket was encrypted by a key which has been rotated out, or has
+    /// been tampered with; the TLS library should then fall back to a full handshake.
+    pub fn decrypt(&self, ticket: &[u8]) -> Option<Vec<u8>> {
+        if ticket.len() < KEY_NAME_SIZE + IV_SIZE + TAG_SIZE {
+            return None;
+        }
+        let (name, rest) = ticket.split_at(KEY_NAME_SIZE);
+        let (iv, rest) = rest.split_at(IV_SIZE);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_SIZE);
+
+        let key = self.keys.iter().find(|key| key.name[..] == *name)?;
+        let mut mac = sgx_aes_gcm_128bit_tag_t::default();
+        mac.copy_from_slice(tag);
+        let mut state = vec![0_u8; ciphertext.len()];
+        rsgx_rijndael128GCM_decrypt(&key.key, ciphertext, iv, name, &mac, &mut state).ok()?;
+        Some(state)
+    }
+
+    /// Seals the keys, for `unseal` to restore after a restart of the enclave.
+    ///
+    /// `key_policy` is `SGX_KEYPOLICY_MRENCLAVE` for only this enclave to unseal the keys, or
+    /// `SGX_KEYPOLICY_MRSIGNER` for later versions of it as well.
+    pub fn seal(&self, key_policy: u16) -> SgxResult<Vec<u8>> {
+        let mut plaintext = Vec::with_capacity(8 + self.keys.len() * SEALED_KEY_SIZE);
+        plaintext.extend_from_slice(&self.rotation_interval.to_le_bytes());
+        for key in &self.keys {
+            plaintext.extend_from_slice(&key.name);
+            plaintext.extend_from_slice(&key.key);
+            plaintext.extend_from_slice(&key.created.to_le_bytes());
+        }
+        let result = seal_bytes(key_policy, &plaintext);
+        zeroize(&mut plaintext);
+        result
+    }
+
+    /// Restores keys sealed by `seal`.
+    pub fn unseal(sealed: &[u8]) -> SgxResult<TicketKeys> {
+        if sealed.len() < SEALED_HEADER_SIZE + 8 + SEALED_KEY_SIZE + TAG_SIZE {
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut plaintext = unseal_bytes(sealed)?;
+
+        let mut interval = [0_u8; 8];
+        interval.copy_from_slice(&plaintext[..8]);
+        let chunks = plaintext[8..].chunks_exact(SEALED_KEY_SIZE);
+        if !chunks.remainder().is_empty() {
+            zeroize(&mut plaintext);
+            return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+        }
+        let mut keys = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            let mut key = TicketKey { name: [0; KEY_NAME_SIZE], key: [0; 16], created: 0 };
+            key.name.copy_from_slice(&chunk[..KEY_NAME_SIZE]);
+            key.key.copy_from_slice(&chunk[KEY_NAME_SIZE..KEY_NAME_SIZE + 16]);
+            let mut created = [0_u8; 8];
+            created.copy_from_slice(&chunk[KEY_NAME_SIZE + 16..]);
+            key.created = u64::from_le_bytes(created);
+            keys.push(key);
+        }
+        zeroize(&mut plaintext);
+        Ok(TicketKeys { keys, rotation_interval: u64::from_le_bytes(interval) })
+    }
+}
+
+/// Seals `plaintext` under a seal key of `key_policy` with a random key ID, prefixing it with
+/// the key ID, CPU SVN, ISV SVN and key policy of the key, authenticated, and the IV.
+pub(crate) fn seal_bytes(key_policy: u16, plaintext: &[u8]) -> SgxResult<Vec<u8>> {
+    let report = rsgx_self_report();
+    let mut request = seal_key_request(key_policy, report.body.cpu_svn, report.body.isv_svn);
+    read_rand(&mut request.key_id.id)?;
+
+    let mut sealed = vec![0_u8; SEALED_HEADER_SIZE + plaintext.len() + TAG_SIZE];
+    sealed[..32].copy_from_slice(&request.key_id.id);
+    sealed[32..48].copy_from_slice(&request.cpu_svn.svn);
+    sealed[48..50].copy_from_slice(&request.isv_svn.to_le_bytes());
+    sealed[50..52].copy_from_slice(&key_policy.to_le_bytes());
+    let (header, rest) = sealed.split_at_mut(SEALED_HEADER_SIZE);
+    let (ciphertext, tag) = rest.split_at_mut(plaintext.len());
+    read_rand(&mut header[52..])?;
+
+    let mut seal_key = rsgx_get_key(&request)?;
+    let mut mac = sgx_aes_gcm_128bit_tag_t::default();
+    let iv = &header[52..];
+    let aad = &header[..52];
+    let result = rsgx_rijndael128GCM_encrypt(&seal_key, plaintext, iv, aad, ciphertext, &mut mac);
+    zeroize(&mut seal_key);
+    tag.copy_from_slice(&mac);
+    result.map(|_| sealed)
+}
+
+/// Unseals bytes sealed by [`seal_bytes`].
+pub(crate) fn unseal_bytes(sealed: &[u8]) -> SgxResult<Vec<u8>> {
+    if sealed.len() < SEALED_HEADER_SIZE + TAG_SIZE {
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+    let (header, rest) = sealed.split_at(SEALED_HEADER_SIZE);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_SIZE);
+
+    let mut cpu_svn = sgx_cpu_svn_t::default();
+    cpu_svn.svn.copy_from_slice(&header[32..48]);
+    let isv_svn = u16::from_le_bytes([header[48], header[49]]);
+    let key_policy = u16::from_le_bytes([header[50], header[51]]);
+    let mut request = seal_key_request(key_policy, cpu_svn, isv_svn);
+    request.key_id.id.copy_from_slice(&header[..32]);
+
+    let mut mac = sgx_aes_gcm_128bit_tag_t::default();
+    mac.copy_from_slice(tag);
+    let mut plaintext = vec![0_u8; ciphertext.len()];
+    let mut seal_key = rsgx_get_key(&request)?;
+    let result = rsgx_rijndael128GCM_decrypt(
+        &seal_key,
+        ciphertext,
+        &header[52..],
+        &header[..52],
+        &mac,
+        &mut plaintext,
+    );
+    zeroize(&mut seal_key);
+    if let Err(e) = result {
+        zeroize(&mut plaintext);
+        return Err(e);
+    }
+    Ok(plaintext)
+}
+
+fn seal_key_request(key_policy: u16, cpu_svn: sgx_cpu_svn_t, isv_svn: u16) -> sgx_key_request_t {
+    let mut request: sgx_key_request_t = unsafe { mem::zeroed() };
+    request.key_name = SGX_KEYSELECT_SEAL;
+    request.key_policy = key_policy;
+    request.isv_svn = isv_svn;
+    request.cpu_svn = cpu_svn;
+    request.attribute_mask.flags = TSEAL_DEFAULT_FLAGSMASK;
+    request.attribute_mask.xfrm = 0;
+    request.misc_mask = TSEAL_DEFAULT_MISCMASK;
+    request
+}
+
+impl fmt::Debug for TicketKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TicketKeys")
+            .field("keys", &self.keys.len())
+            .field("rotation_interval", &self.rotation_interval)
+            .finish()
+    }
+}
@@ -0,0 +1,301 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+
+//! TPM 2.0 quotes co-signing evidence bundles.
+//!
+//! A platform whose host measures its boot into a TPM, or a confidential VM with a vTPM, can
+//! vouch for the host the enclave runs on along with the enclave: the host has its TPM quote
+//! the PCRs with the [`binding`] of the bundle as qualifying data, and the enclave adds the
+//! quote to the bundle through a [`HostAttester`] of its own, e.g. over an ocall:
+//!
+//! ```ignore
+//! // In the enclave.
+//! let mut bundle = QuoteBuilder::new(pccs).report_data(claims.report_data()).build()?;
+//! bundle.co_sign(&mut host_tpm)?;
+//!
+//! // In the verifier.
+//! let tpm = TpmQuoteVerifier::new(attestation_key).pcr(0, firmware).pcr(7, secure_boot);
+//! let verified = verify_evidence(&bundle, &policy, &[&tpm])?;
+//! ```
+//!
+//! The evidence is the CBOR map of:
+//!
+//! | key           | value                                                                |
+//! |---------------|----------------------------------------------------------------------|
+//! | `"attest"`    | the `TPMS_ATTEST` the TPM signed, as bytes                           |
+//! | `"signature"` | the `TPMT_SIGNATURE` of the attestation key over it, as bytes        |
+//! | `"pcrs"`      | a map of the values of the quoted PCRs, as bytes, by index           |
+//!
+//! Only quotes of the SHA-256 bank, signed with ECDSA P-256 or RSASSA-PKCS1-v1_5 over SHA-256,
+//! are verified. The attestation key is the one of the TPM of the host, which the verifier
+//! trusts by enrolling it, e.g. from its certificate; [`TpmQuoteVerifier`] doesn't check its
+//! credentials up to the endorsement key.
+//!
+//! [`binding`]: crate::dcap::EvidenceBundle::binding
+//! [`HostAttester`]: crate::dcap::HostAttester
+
+use crate::cbor::Value;
+use crate::dcap::{DcapError, DcapResult, HostVerifier};
+use crate::pki::{self, PublicKey};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use sgx_tcrypto::hash::{Hash, Sha256};
+use sgx_types::*;
+
+/// The kind of the evidence of TPM 2.0 quotes.
+pub const TPM2: &str = "tpm2";
+
+/// `TPM_GENERATED_VALUE`, the magic of structures the TPM signs.
+const TPM_GENERATED: u32 = 0xff54_4347;
+/// `TPM_ST_ATTEST_QUOTE`.
+const ST_ATTEST_QUOTE: u16 = 0x8018;
+const ALG_RSASSA: u16 = 0x0014;
+const ALG_ECDSA: u16 = 0x0018;
+const ALG_SHA256: u16 = 0x000b;
+/// The PCRs of a TPM 2.0 PC client.
+const PCR_COUNT: u8 = 24;
+
+/// A quote of the PCRs of a TPM, with their values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TpmQuote {
+    /// The `TPMS_ATTEST` structure, as the TPM returned it.
+    pub attest: Vec<u8>,
+    /// The `TPMT_SIGNATURE` structure over `attest`.
+    pub signature: Vec<u8>,
+    /// The values of the quoted PCRs of the SHA-256 bank, by index.
+    pub pcrs: Vec<(u8, [u8; 32])>,
+}
+
+impl TpmQuote {
+    /// Encodes the quote as the evidence of a [`TPM2`] attester.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut pcrs = self.pcrs.clone();
+        pcrs.sort_by_key(|&(index, _)| index);
+        let pcrs = pcrs
+            .into_iter()
+            .map(|(index, value)| (Value::Unsigned(index.into()), Value::Bytes(Vec::from(value))))
+            .collect();
+        Value::Map(vec![
+            (Value::from("pcrs"), Value::Map(pcrs)),
+            (Value::from("attest"), Value::Bytes(self.attest.clone())),
+            (Value::from("signature"), Value::Bytes(self.signature.clone())),
+        ])
+        .encode()
+    }
+
+    /// Decodes the evidence of a [`TPM2`] attester.
+    pub fn from_bytes(bytes: &[u8]) -> DcapResult<TpmQuote> {
+        let untrusted = DcapError::UntrustedHost;
+        let value = Value::decode(bytes).map_err(|_| untrusted)?;
+        let field = |name: &str| value.get(&Value::from(name)).ok_or(untrusted);
+        let attest = Vec::from(field("attest")?.as_bytes().ok_or(untrusted)?);
+        let signature = Vec::from(field("signature")?.as_bytes().ok_or(untrusted)?);
+        let mut pcrs = Vec::new();
+        for (index, value) in field("pcrs")?.as_map().ok_or(untrusted)? {
+            let index = match index {
+                Value::Unsigned(index) if *index < PCR_COUNT.into() => *index as u8,
+                _ => return Err(untrusted),
+            };
+            let value = value.as_bytes().and_then(|value| value.try_into().ok());
+            pcrs.push((index, value.ok_or(untrusted)?));
+        }
+        Ok(TpmQuote { attest, signature, pcrs })
+    }
+}
+
+/// Verifies the [`TPM2`] evidence of bundles, quoted by a trusted attestation key.
+#[derive(Clone, Debug)]
+pub struct TpmQuoteVerifier {
+    key: PublicKey,
+    pcrs: Vec<(u8, [u8; 32])>,
+}
+
+impl TpmQuoteVerifier {
+    /// Creates a verifier of quotes signed by the attestation key `key`, a P-256 or RSA key,
+    /// expecting no PCR values yet.
+    pub fn new(key: PublicKey) -> TpmQuoteVerifier {
+        TpmQuoteVerifier { key, pcrs: Vec::new() }
+    }
+
+    /// Expects the PCR `index` of the SHA-256 bank to be quoted with `value`, replacing any
+    /// value expected of it.
+    pub fn pcr(mut self, index: u8, value: [u8; 32]) -> TpmQuoteVerifier {
+        self.pcrs.retain(|&(pcr, _)| pcr != index);
+        self.pcrs.push((index, value));
+        self
+    }
+
+    /// Verifies `quote`: the signature of the attestation key over it, that it's bound to
+    /// `binding`, that the values of the PCRs are the ones it quoted, and that they're the
+    /// ones expected.
+    pub fn verify_quote(&self, quote: &TpmQuote, binding: &[u8; 32]) -> DcapResult<()> {
+        let untrusted = DcapError::UntrustedHost;
+        if !verify_signature(&self.key, &quote.attest, &quote.signature)? {
+            return Err(untrusted);
+        }
+        let attest = Attest::parse(&quote.attest)?;
+        if attest.extra_data != binding {
+            return Err(untrusted);
+        }
+
+        let mut pcrs = quote.pcrs.clone();
+        pcrs.sort_by_key(|&(index, _)| index);
+        let indices: Vec<u8> = pcrs.iter().map(|&(index, _)| index).collect();
+        if indices != attest.selected || indices.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(untrusted);
+        }
+        let mut digest = Sha256::new();
+        for (_, value) in &pcrs {
+            digest.update(value);
+        }
+        let mut expected = [0_u8; 32];
+        digest.finalize_into(&mut expected);
+        if attest.pcr_digest != expected {
+            return Err(untrusted);
+        }
+
+        for expected in &self.pcrs {
+            if !pcrs.contains(expected) {
+                return Err(untrusted);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HostVerifier for TpmQuoteVerifier {
+    fn kind(&self) -> &str {
+        TPM2
+    }
+
+    fn verify(&self, evidence: &[u8], binding: &[u8; 32], _now: time_t) -> DcapResult<()> {
+        self.verify_quote(&TpmQuote::from_bytes(evidence)?, binding)
+    }
+}
+
+/// The fields of a `TPMS_ATTEST` of a quote which are checked.
+struct Attest<'a> {
+    extra_data: &'a [u8],
+    /// The indices of the PCRs selected, in ascending order.
+    selected: Vec<u8>,
+    pcr_digest: &'a [u8],
+}
+
+impl<'a> Attest<'a> {
+    fn parse(bytes: &'a [u8]) -> DcapResult<Attest<'a>> {
+        let mut reader = TpmReader(bytes);
+        if reader.u32()? != TPM_GENERATED || reader.u16()? != ST_ATTEST_QUOTE {
+            return Err(DcapError::UntrustedHost);
+        }
+        reader.sized()?; // qualifiedSigner
+        let extra_data = reader.sized()?;
+        reader.take(17)?; // clockInfo
+        reader.take(8)?; // firmwareVersion
+
+        let banks = reader.u32()?;
+        if banks != 1 || reader.u16()? != ALG_SHA256 {
+            return Err(DcapError::UntrustedHost);
+        }
+        let size = reader.u8()?;
+        let select = reader.take(size.into())?;
+        let mut selected = Vec::new();
+        for (byte, bits) in select.iter().enumerate() {
+            for bit in 0..8 {
+                if bits & (1 << bit) != 0 {
+                    let index = 8 * byte + bit;
+                    selected.push(u8::try_from(index).map_err(|_| DcapError::UntrustedHost)?);
+                }
+            }
+        }
+        let pcr_digest = reader.sized()?;
+        reader.finish()?;
+        Ok(Attest { extra_data, selected, pcr_digest })
+    }
+}
+
+/// Verifies a `TPMT_SIGNATURE` of `key` over `message`.
+fn verify_signature(key: &PublicKey, message: &[u8], signature: &[u8]) -> DcapResult<bool> {
+    let mut reader = TpmReader(signature);
+    let algorithm = reader.u16()?;
+    if reader.u16()? != ALG_SHA256 {
+        return Err(DcapError::UntrustedHost);
+    }
+    let valid = match (algorithm, key) {
+        (ALG_ECDSA, PublicKey::P256(_)) => {
+            let mut raw = [0_u8; 64];
+            for half in raw.chunks_mut(32) {
+                let mut integer = reader.sized()?;
+                while integer.len() > 32 && integer[0] == 0 {
+                    integer = &integer[1..];
+                }
+                if integer.len() > 32 {
+                    return Err(DcapError::UntrustedHost);
+                }
+                half[32 - integer.len()..].copy_from_slice(integer);
+            }
+            reader.finish()?;
+            pki::verify_raw(key, message, &raw)
+        }
+        (ALG_RSASSA, PublicKey::Rsa { .. }) => {
+            let signature = reader.sized()?;
+            reader.finish()?;
+            pki::verify_rsa_sha256(key, message, signature)
+        }
+        _ => return Err(DcapError::UntrustedHost),
+    };
+    valid.map_err(|_| DcapError::UntrustedHost)
+}
+
+/// Reads the big-endian structures of the TPM.
+struct TpmReader<'a>(&'a [u8]);
+
+impl<'a> TpmReader<'a> {
+    fn take(&mut self, len: usize) -> DcapResult<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(DcapError::UntrustedHost);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> DcapResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> DcapResult<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> DcapResult<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Reads a `TPM2B` structure: its size, on 16 bits, and its bytes.
+    fn sized(&mut self) -> DcapResult<&'a [u8]> {
+        let len = self.u16()?;
+        self.take(len.into())
+    }
+
+    fn finish(&self) -> DcapResult<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(DcapError::UntrustedHost)
+        }
+    }
+}
@@ -0,0 +1,485 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License..
+//! A WebSocket server.
+//!
+//! [`accept`] completes the RFC 6455 opening handshake on a [`Transport`], typically an
+//! attested TLS stream, and returns a [`WebSocket`] exchanging messages over it, so that browser
+//! clients keep an end-to-end confidential channel into the enclave. Fragmented messages are
+//! reassembled, pings are answered, and message sizes are bounded.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use sgx_tcrypto::rsgx_sha1_slice;
+
+use crate::http::{HttpError, Limits, Reader, Transport};
+use crate::ra_tls::base64;
+
+/// The GUID appended to the client key to derive `Sec-WebSocket-Accept`.
+const ACCEPT_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The default limit on the size of a received message, 16 MiB.
+pub const DEFAULT_MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/// Close code for a normal closure.
+pub const CLOSE_NORMAL: u16 = 1000;
+/// Close code for a protocol error.
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+/// Close code for a text message which isn't UTF-8.
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+/// Close code for a message over the size limit.
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+/// An error accepting a WebSocket or exchanging messages over it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebSocketError<E> {
+    /// The transport failed.
+    Transport(E),
+    /// The opening handshake isn't a valid WebSocket upgrade request.
+    Handshake(&'static str),
+    /// The peer broke the framing protocol.
+    Protocol(&'static str),
+    /// A received message is larger than allowed.
+    MessageTooLarge,
+    /// The connection has been closed.
+    Closed,
+}
+
+impl<E: fmt::Display> fmt::Display for WebSocketError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebSocketError::Transport(e) => write!(f, "transport error: {}", e),
+            WebSocketError::Handshake(msg) => write!(f, "invalid handshake: {}", msg),
+            WebSocketError::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            WebSocketError::MessageTooLarge => f.write_str("message is too large"),
+            WebSocketError::Closed => f.write_str("connection is closed"),
+        }
+    }
+}
+
+impl<E> From<HttpError<E>> for WebSocketError<E> {
+    fn from(e: HttpError<E>) -> WebSocketError<E> {
+        match e {
+            HttpError::Transport(e) => WebSocketError::Transport(e),
+            HttpError::HeadersTooLarge | HttpError::TooManyHeaders => {
+                WebSocketError::Handshake("request headers are too large")
+            }
+            HttpError::UnexpectedEof => WebSocketError::Handshake("stream ended in the request"),
+            HttpError::Malformed(msg) => WebSocketError::Handshake(msg),
+            HttpError::InvalidHeader | HttpError::BodyTooLarge => {
+                WebSocketError::Handshake("invalid request")
+            }
+        }
+    }
+}
+
+/// The result type of WebSocket operations.
+pub type WebSocketResult<T, E> = Result<T, WebSocketError<E>>;
+
+/// A WebSocket message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// A text message.
+    Text(String),
+    /// A binary message.
+    Binary(Vec<u8>),
+    /// A pong, answering a ping sent with [`WebSocket::send`].
+    Pong(Vec<u8>),
+    /// A ping; received pings are answered before being returned.
+    Ping(Vec<u8>),
+    /// A close frame, with its close code and reason if any.
+    Close(Option<(u16, String)>),
+}
+
+/// The upgrade request a WebSocket was accepted with.
+#[derive(Clone, Debug)]
+pub struct UpgradeRequest {
+    target: String,
+    headers: Vec<(String, String)>,
+}
+
+impl UpgradeRequest {
+    /// Returns the request target, e.g. `/wallet?session=1`.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Returns the request headers, in order.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Returns the value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    /// Returns whether the comma-separated header `name` includes `token`, ignoring case.
+    fn has_token(&self, name: &str, token: &str) -> bool {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .flat_map(|(_, v)| v.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+}
+
+/// Reads a WebSocket upgrade request from `transport` within `limits`, and accepts it.
+///
+/// Requests which aren't valid upgrades are answered with `400 Bad Request`, or
+/// `426 Upgrade Required` for unsupported protocol versions, and fail with a
+/// [`WebSocketError::Handshake`] error.
+pub fn accept<'a, T: Transport>(
+    transport: &'a mut T,
+    limits: &Limits,
+) -> WebSocketResult<WebSocket<'a, T>, T::Error> {
+    let mut reader = Reader { transport, buf: Vec::new(), pos: 0 };
+    let mut budget = limits.max_header_bytes;
+    let line = reader.read_line(&mut budget)?;
+    let mut parts = line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next())
+    {
+        (Some(method), Some(target), Some(version), None) => (method, target, version),
+        _ => return Err(reject(reader.transport, "invalid request line", false)),
+    };
+    if method != "GET" || version != "HTTP/1.1" {
+        return Err(reject(reader.transport, "not an HTTP/1.1 GET request", false));
+    }
+
+    let mut headers = Vec::new();
+    reader.read_headers(&mut headers, limits, &mut budget)?;
+    let request = UpgradeRequest { target: target.to_string(), headers };
+
+    if !request.has_token("upgrade", "websocket") || !request.has_token("connection", "upgrade") {
+        return Err(reject(reader.transport, "not a WebSocket upgrade", false));
+    }
+    if request.header("sec-websocket-version") != Some("13") {
+        return Err(reject(reader.transport, "unsupported WebSocket version", true));
+    }
+    let key = match request.header("sec-websocket-key") {
+        // The key is 16 bytes in base64.
+        Some(key) if key.len() == 24 && key.ends_with("==") => key,
+        _ => return Err(reject(reader.transport, "invalid Sec-WebSocket-Key", false)),
+    };
+    let mut digest_input = Vec::with_capacity(key.len() + ACCEPT_GUID.len());
+    digest_input.extend_from_slice(key.as_bytes());
+    digest_input.extend_from_slice(ACCEPT_GUID);
+    let digest = rsgx_sha1_slice(&digest_input)
+        .map_err(|_| WebSocketError::Handshake("failed to hash Sec-WebSocket-Key"))?;
+
+    let mut response = Vec::with_capacity(160);
+    response.extend_from_slice(
+        b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n",
+    );
+    response.extend_from_slice(b"Sec-WebSocket-Accept: ");
+    response.extend_from_slice(&base64(&digest));
+    response.extend_from_slice(b"\r\n\r\n");
+    reader.transport.write_all(&response).map_err(WebSocketError::Transport)?;
+
+    // The client may send frames right behind its request.
+    let pending = reader.buf.split_off(reader.pos);
+    Ok(WebSocket {
+        transport: reader.transport,
+        request,
+        pending,
+        pos: 0,
+        fragments: None,
+        max_message_len: DEFAULT_MAX_MESSAGE_LEN,
+        close_sent: false,
+        closed: false,
+    })
+}
+
+/// Answers a failed upgrade, returning the handshake error.
+fn reject<T: Transport>(
+    transport: &mut T,
+    msg: &'static str,
+    bad_version: bool,
+) -> WebSocketError<T::Error> {
+    let response: &[u8] = if bad_version {
+        b"HTTP/1.1 426 Upgrade Required\r\nSec-WebSocket-Version: 13\r\nConnection: close\r\n\
+          Content-Length: 0\r\n\r\n"
+    } else {
+        b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+    };
+    // The handshake failed anyway; the answer is only a courtesy.
+    let _ = transport.write_all(response);
+    WebSocketError::Handshake(msg)
+}
+
+/// The server end of a WebSocket.
+pub struct WebSocket<'a, T: Transport> {
+    transport: &'a mut T,
+    request: UpgradeRequest,
+    pending: Vec<u8>,
+    pos: usize,
+    fragments: Option<(u8, Vec<u8>)>,
+    max_message_len: usize,
+    close_sent: bool,
+    closed: bool,
+}
+
+impl<'a, T: Transport> WebSocket<'a, T> {
+    /// Returns the request the WebSocket was accepted with.
+    pub fn request(&self) -> &UpgradeRequest {
+        &self.request
+    }
+
+    /// Limits received messages, reassembled from their fragments, to `max_message_len` bytes.
+    pub fn set_max_message_len(&mut self, max_message_len: usize) {
+        self.max_message_len = max_message_len;
+    }
+
+    /// Reads the next message.
+    ///
+    /// Pings are answered with a pong before being returned. A close frame is answered with a
+    /// close frame, unless one was sent already, and returned; reading further fails with
+    /// [`WebSocketError::Closed`]. Protocol errors close the connection with the matching code.
+    pub fn read_message(&mut self) -> WebSocketResult<Message, T::Error> {
+        if self.closed {
+            return Err(WebSocketError::Closed);
+        }
+        match self.read_message_inner() {
+            Err(e) => {
+                let code = match e {
+                    WebSocketError::Protocol("text message is not UTF-8") => CLOSE_INVALID_DATA,
+                    WebSocketError::Protocol(_) => CLOSE_PROTOCOL_ERROR,
+                    WebSocketError::MessageTooLarge => CLOSE_TOO_BIG,
+                    _ => return Err(e),
+                };
+                let _ = self.close(code, "");
+                self.closed = true;
+                Err(e)
+            }
+            message => message,
+        }
+    }
+
+    fn read_message_inner(&mut self) -> WebSocketResult<Message, T::Error> {
+        loop {
+            let (fin, opcode, payload) = self.read_frame()?;
+            match opcode {
+                OP_PING => {
+                    if !self.close_sent {
+                        self.write_frame(OP_PONG, &payload)?;
+                    }
+                    return Ok(Message::Ping(payload));
+                }
+                OP_PONG => return Ok(Message::Pong(payload)),
+                OP_CLOSE => {
+                    let close = match payload.len() {
+                        0 => None,
+                        1 => return Err(WebSocketError::Protocol("truncated close code")),
+                        _ => {
+                            let code = u16::from_be_bytes([payload[0], payload[1]]);
+                            let reason = String::from_utf8(payload[2..].to_vec()).map_err(|_| {
+                                WebSocketError::Protocol("close reason is not UTF-8")
+                            })?;
+                            Some((code, reason))
+                        }
+                    };
+                    if !self.close_sent {
+                        let code = close.as_ref().map_or(CLOSE_NORMAL, |(code, _)| *code);
+                        self.close(code, "")?;
+                    }
+                    self.closed = true;
+                    return Ok(Message::Close(close));
+                }
+                OP_TEXT | OP_BINARY => {
+                    if self.fragments.is_some() {
+                        return Err(WebSocketError::Protocol("new message within a fragmented one"));
+                    }
+                    if fin {
+                        return message(opcode, payload);
+                    }
+                    self.fragments = Some((opcode, payload));
+                }
+                OP_CONTINUATION => {
+                    let (first, mut data) = self
+                        .fragments
+                        .take()
+                        .ok_or(WebSocketError::Protocol("continuation without a message"))?;
+                    if payload.len() > self.max_message_len - data.len() {
+                        return Err(WebSocketError::MessageTooLarge);
+                    }
+                    data.extend_from_slice(&payload);
+                    if fin {
+                        return message(first, data);
+                    }
+                    self.fragments = Some((first, data));
+                }
+                _ => return Err(WebSocketError::Protocol("unknown opcode")),
+            }
+        }
+    }
+
+    /// Sends `message`, unfragmented.
+    pub fn send(&mut self, message: &Message) -> WebSocketResult<(), T::Error> {
+        if self.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+        match message {
+            Message::Text(text) => self.write_frame(OP_TEXT, text.as_bytes()),
+            Message::Binary(data) => self.write_frame(OP_BINARY, data),
+            Message::Ping(data) => self.write_control(OP_PING, data),
+            Message::Pong(data) => self.write_control(OP_PONG, data),
+            Message::Close(None) => {
+                self.write_frame(OP_CLOSE, &[])?;
+                self.close_sent = true;
+                Ok(())
+            }
+            Message::Close(Some((code, reason))) => self.close(*code, reason),
+        }
+    }
+
+    /// Starts the closing handshake with `code` and `reason`; the peer's close frame is then
+    /// returned by [`read_message`](WebSocket::read_message).
+    pub fn close(&mut self, code: u16, reason: &str) -> WebSocketResult<(), T::Error> {
+        if self.close_sent {
+            return Ok(());
+        }
+        let mut payload = Vec::with_capacity(2 + reason.len());
+        payload.extend_from_slice(&code.to_be_bytes());
+        payload.extend_from_slice(reason.as_bytes());
+        self.write_control(OP_CLOSE, &payload)?;
+        self.close_sent = true;
+        Ok(())
+    }
+
+    /// Control frames can't be fragmented, so their payload is at most 125 bytes.
+    fn write_control(&mut self, opcode: u8, payload: &[u8]) -> WebSocketResult<(), T::Error> {
+        if payload.len() > 125 {
+            return Err(WebSocketError::Protocol("control frame payload is too long"));
+        }
+        self.write_frame(opcode, payload)
+    }
+
+    /// Writes a final frame; frames from the server are not masked.
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> WebSocketResult<(), T::Error> {
+        let mut frame = Vec::with_capacity(10 + payload.len());
+        frame.push(0x80 | opcode);
+        match payload.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len @ 126..=0xffff => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        self.transport.write_all(&frame).map_err(WebSocketError::Transport)
+    }
+
+    /// Reads a frame, returning its FIN bit, opcode and unmasked payload.
+    fn read_frame(&mut self) -> WebSocketResult<(bool, u8, Vec<u8>), T::Error> {
+        let mut header = [0_u8; 2];
+        self.read_exact(&mut header)?;
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0f;
+        if header[0] & 0x70 != 0 {
+            return Err(WebSocketError::Protocol("reserved bits set without an extension"));
+        }
+        if header[1] & 0x80 == 0 {
+            return Err(WebSocketError::Protocol("client frame is not masked"));
+        }
+
+        let len = match header[1] & 0x7f {
+            126 => {
+                let mut len = [0_u8; 2];
+                self.read_exact(&mut len)?;
+                u16::from_be_bytes(len) as u64
+            }
+            127 => {
+                let mut len = [0_u8; 8];
+                self.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => len as u64,
+        };
+        if opcode & 0x8 != 0 && (!fin || len > 125) {
+            return Err(WebSocketError::Protocol("fragmented or long control frame"));
+        }
+        if len > self.max_message_len as u64 {
+            return Err(WebSocketError::MessageTooLarge);
+        }
+
+        let mut mask = [0_u8; 4];
+        self.read_exact(&mut mask)?;
+        let mut payload = vec![0; len as usize];
+        self.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok((fin, opcode, payload))
+    }
+
+    /// Fills `buf`, first with the data read along with the handshake.
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> WebSocketResult<(), T::Error> {
+        if self.pos < self.pending.len() {
+            let n = core::cmp::min(buf.len(), self.pending.len() - self.pos);
+            buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+            self.pos += n;
+            if self.pos == self.pending.len() {
+                self.pending = Vec::new();
+                self.pos = 0;
+            }
+            buf = &mut buf[n..];
+        }
+        while !buf.is_empty() {
+            match self.transport.read(buf).map_err(WebSocketError::Transport)? {
+                0 => {
+                    self.closed = true;
+                    return Err(WebSocketError::Protocol("stream ended without a close frame"));
+                }
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds a data message of type `opcode`.
+fn message<E>(opcode: u8, payload: Vec<u8>) -> WebSocketResult<Message, E> {
+    if opcode == OP_TEXT {
+        String::from_utf8(payload)
+            .map(Message::Text)
+            .map_err(|_| WebSocketError::Protocol("text message is not UTF-8"))
+    } else {
+        Ok(Message::Binary(payload))
+    }
+}
+
+impl<T: Transport> fmt::Debug for WebSocket<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("request", &self.request)
+            .field("max_message_len", &self.max_message_len)
+            .field("close_sent", &self.close_sent)
+            .field("closed", &self.closed)
+            .finish_non_exhaustive()
+    }
+}
@@ -118,6 +118,8 @@ impl_enum! {
         SGX_ERROR_WASM_REGISTER_ERROR           = 0x0F00_F005,   /* sgxwasm register error */
         SGX_ERROR_FAAS_BUFFER_TOO_SHORT         = 0x0F00_E001,   /* faas output buffer not long enough */
         SGX_ERROR_FAAS_INTERNAL_ERROR           = 0x0F00_E002,   /* faas exec internal error */
+        SGX_ERROR_ECALL_PANICKED                = 0x0F00_D001,   /* the ecall panicked and the panic was caught */
+        SGX_ERROR_ENCLAVE_POISONED              = 0x0F00_D002,   /* the enclave panicked too often and rejects ecalls */
     }
 }
 
@@ -212,6 +214,8 @@ impl sgx_status_t {
             sgx_status_t::SGX_ERROR_WASM_REGISTER_ERROR => "sgxwasm register error.",
             sgx_status_t::SGX_ERROR_FAAS_BUFFER_TOO_SHORT => "faas output buffer too short.",
             sgx_status_t::SGX_ERROR_FAAS_INTERNAL_ERROR => "faas exec internal error.",
+            sgx_status_t::SGX_ERROR_ECALL_PANICKED => "The ecall panicked.",
+            sgx_status_t::SGX_ERROR_ENCLAVE_POISONED => "The enclave is poisoned by repeated panics.",
         }
     }
 
@@ -319,6 +323,8 @@ impl sgx_status_t {
             sgx_status_t::SGX_ERROR_WASM_REGISTER_ERROR => "SGX_ERROR_WASM_REGISTER_ERROR",
             sgx_status_t::SGX_ERROR_FAAS_BUFFER_TOO_SHORT => "SGX_ERROR_FAAS_BUFFER_TOO_SHORT",
             sgx_status_t::SGX_ERROR_FAAS_INTERNAL_ERROR => "SGX_ERROR_FAAS_INTERNAL_ERROR",
+            sgx_status_t::SGX_ERROR_ECALL_PANICKED => "SGX_ERROR_ECALL_PANICKED",
+            sgx_status_t::SGX_ERROR_ENCLAVE_POISONED => "SGX_ERROR_ENCLAVE_POISONED",
         }
     }
 }
@@ -1907,3 +1907,32 @@ pub const SGX_PROT_READ: uint32_t = 0x1; /* page can be read */
 pub const SGX_PROT_WRITE: uint32_t = 0x2; /* page can be written */
 pub const SGX_PROT_EXEC: uint32_t = 0x4; /* page can be executed */
 pub const SGX_PROT_NONE: uint32_t = 0x0; /* page can not be accessed */
+
+//
+// panic information of an enclave, filled in by sgx_tstd
+//
+pub const SGX_PANIC_MESSAGE_SIZE: size_t = 256;
+pub const SGX_PANIC_FILE_SIZE: size_t = 128;
+pub const SGX_PANIC_MAX_FRAMES: size_t = 32;
+
+impl_copy_clone! {
+    pub struct sgx_panic_info_t {
+        pub code: uint32_t,
+        pub line: uint32_t,
+        pub column: uint32_t,
+        pub message_len: uint32_t,
+        pub message: [uint8_t; SGX_PANIC_MESSAGE_SIZE],
+        pub file_len: uint32_t,
+        pub file: [uint8_t; SGX_PANIC_FILE_SIZE],
+        pub frame_count: uint32_t,
+        pub frames: [uint64_t; SGX_PANIC_MAX_FRAMES],
+    }
+}
+
+impl_struct_default! {
+    sgx_panic_info_t; //1000
+}
+
+impl_struct_ContiguousMemory! {
+    sgx_panic_info_t;
+}
@@ -182,6 +182,21 @@ pub extern "C" fn u_pwritev64_ocall(
     ret
 }
 
+#[no_mangle]
+pub extern "C" fn u_flock_ocall(error: *mut c_int, fd: c_int, operation: c_int) -> c_int {
+    let mut errno = 0;
+    let ret = unsafe { libc::flock(fd, operation) };
+    if ret < 0 {
+        errno = Error::last_os_error().raw_os_error().unwrap_or(0);
+    }
+    if !error.is_null() {
+        unsafe {
+            *error = errno;
+        }
+    }
+    ret
+}
+
 #[no_mangle]
 pub extern "C" fn u_fcntl_arg0_ocall(error: *mut c_int, fd: c_int, cmd: c_int) -> c_int {
     let mut errno = 0;
@@ -252,6 +267,24 @@ pub extern "C" fn u_ioctl_arg1_ocall(
     ret
 }
 
+#[no_mangle]
+pub extern "C" fn u_ficlone_ocall(error: *mut c_int, fd: c_int, src_fd: c_int) -> c_int {
+    // _IOW(0x94, 9, int) from linux/fs.h
+    const FICLONE: c_ulong = 0x4004_9409;
+
+    let mut errno = 0;
+    let ret = unsafe { libc::ioctl(fd, FICLONE, src_fd) };
+    if ret < 0 {
+        errno = Error::last_os_error().raw_os_error().unwrap_or(0);
+    }
+    if !error.is_null() {
+        unsafe {
+            *error = errno;
+        }
+    }
+    ret
+}
+
 #[no_mangle]
 pub extern "C" fn u_close_ocall(error: *mut c_int, fd: c_int) -> c_int {
     let mut errno = 0;
@@ -266,3 +299,18 @@ pub extern "C" fn u_close_ocall(error: *mut c_int, fd: c_int) -> c_int {
     }
     ret
 }
+
+#[no_mangle]
+pub extern "C" fn u_isatty_ocall(error: *mut c_int, fd: c_int) -> c_int {
+    let mut errno = 0;
+    let ret = unsafe { libc::isatty(fd) };
+    if ret == 0 {
+        errno = Error::last_os_error().raw_os_error().unwrap_or(0);
+    }
+    if !error.is_null() {
+        unsafe {
+            *error = errno;
+        }
+    }
+    ret
+}
@@ -244,6 +244,27 @@ pub extern "C" fn u_ftruncate64_ocall(error: *mut c_int, fd: c_int, length: off6
     ret
 }
 
+#[no_mangle]
+pub extern "C" fn u_fallocate64_ocall(
+    error: *mut c_int,
+    fd: c_int,
+    mode: c_int,
+    offset: off64_t,
+    len: off64_t,
+) -> c_int {
+    let mut errno = 0;
+    let ret = unsafe { libc::fallocate64(fd, mode, offset, len) };
+    if ret < 0 {
+        errno = Error::last_os_error().raw_os_error().unwrap_or(0);
+    }
+    if !error.is_null() {
+        unsafe {
+            *error = errno;
+        }
+    }
+    ret
+}
+
 #[no_mangle]
 pub extern "C" fn u_truncate_ocall(error: *mut c_int, path: *const c_char, length: off_t) -> c_int {
     let mut errno = 0;
@@ -20,6 +20,8 @@
 #include <sys/types.h>
 #include <sys/ioctl.h>
 #include <sys/uio.h>
+#include <sys/file.h>
+#include <linux/fs.h>
 #include <errno.h>
 #include <unistd.h>
 #include <fcntl.h>
@@ -105,6 +107,15 @@ int u_fcntl_arg0_ocall(int *error, int fd, int cmd)
     return ret;
 }
 
+int u_flock_ocall(int *error, int fd, int operation)
+{
+    int ret = flock(fd, operation);
+    if (error) {
+        *error = ret == -1 ? errno : 0;
+    }
+    return ret;
+}
+
 int u_fcntl_arg1_ocall(int *error, int fd, int cmd, int arg)
 {
     int ret = fcntl(fd, cmd, arg);
@@ -132,6 +143,15 @@ int u_ioctl_arg1_ocall(int *error, int fd, int request, int *arg)
     return ret;
 }
 
+int u_ficlone_ocall(int *error, int fd, int src_fd)
+{
+    int ret = ioctl(fd, FICLONE, src_fd);
+    if (error) {
+        *error = ret == -1 ? errno : 0;
+    }
+    return ret;
+}
+
 int u_close_ocall(int *error, int fd)
 {
     int ret = close(fd);
@@ -139,4 +159,13 @@ int u_close_ocall(int *error, int fd)
         *error = ret == -1 ? errno : 0;
     }
     return ret;
+}
+
+int u_isatty_ocall(int *error, int fd)
+{
+    int ret = isatty(fd);
+    if (error) {
+        *error = ret == 0 ? errno : 0;
+    }
+    return ret;
 }
\ No newline at end of file
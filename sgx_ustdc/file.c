@@ -146,6 +146,15 @@ int u_ftruncate64_ocall(int *error, int fd, off64_t length)
     return ret;
 }
 
+int u_fallocate64_ocall(int *error, int fd, int mode, off64_t offset, off64_t len)
+{
+    int ret = fallocate64(fd, mode, offset, len);
+    if (error) {
+        *error = ret == -1 ? errno : 0;
+    }
+    return ret;
+}
+
 int u_truncate_ocall(int *error, const char *path, off_t length)
 {
     int ret = truncate(path, length);